@@ -32,12 +32,15 @@
 //! These config files can be changed to your preferences.
 use std::collections::HashMap;
 use std::path::Path;
-use std::str::FromStr;
 
-use ethereum_types::{Address, Secret, U256};
+use anyhow::Context;
+use ethereum_types::{Address, U256};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt::sp_core::sr25519::{Pair as Sr25519Pair, Public};
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt::sp_core::Pair;
+use zeroize::Zeroizing;
 
 /// The default port the relayer will listen on. Defaults to 9955.
 const fn default_port() -> u16 {
@@ -47,14 +50,102 @@ const fn default_port() -> u16 {
 const fn enable_leaves_watcher_default() -> bool {
     true
 }
-/// The maximum events per step is set to `100` by default.
-const fn max_events_per_step_default() -> u64 {
-    100
-}
 /// The print progress interval is set to `7_000` by default.
 const fn print_progress_interval_default() -> u64 {
     7_000
 }
+/// The leaves snapshot export interval is set to `3_600_000` (1 hour) by default.
+const fn ipfs_export_interval_default() -> u64 {
+    3_600_000
+}
+/// Signed proposals are kept for `604_800` seconds (7 days) by default.
+const fn proposals_max_age_default() -> u64 {
+    604_800
+}
+/// The stale proposals are collected every `600_000` milliseconds (10 minutes) by default.
+const fn proposals_gc_interval_default() -> u64 {
+    600_000
+}
+/// The circuit breaker of an events watcher opens after `5` consecutive failures by default.
+const fn failure_threshold_default() -> u32 {
+    5
+}
+/// A degraded events watcher is retried every `300_000` milliseconds (5 minutes) by default.
+const fn degraded_retry_interval_default() -> u64 {
+    300_000
+}
+/// The retries of a failing events watcher are at most `60_000` milliseconds apart by default.
+const fn max_backoff_interval_default() -> u64 {
+    60_000
+}
+/// The gas cost of a withdraw is increased by 20% by default, when computing the dynamic fee.
+const fn gas_price_margin_default() -> f64 {
+    1.2
+}
+/// The fee of a withdraw should cover its gas cost by default, without any margin.
+const fn gas_cost_margin_default() -> f64 {
+    1.0
+}
+/// The heartbeat is sent every `60_000` milliseconds (1 minute) by default.
+const fn heartbeat_interval_default() -> u64 {
+    60_000
+}
+/// The roots are checked every `600` seconds (10 minutes) by default.
+const fn root_checkpoints_interval_default() -> u64 {
+    10 * 60
+}
+/// The events watchers could lag `50` blocks behind the chain by default.
+const fn heartbeat_max_lag_default() -> u64 {
+    50
+}
+/// The command streams buffer up to `50` updates by default.
+const fn command_stream_capacity_default() -> usize {
+    50
+}
+/// Only `1` relay command per chain estimates and submits its transaction at a time by default.
+const fn max_concurrent_relays_default() -> usize {
+    1
+}
+/// A transaction dropped from the mempool is resubmitted up to `3` times by default.
+const fn max_resubmissions_default() -> u32 {
+    3
+}
+/// A connection has up to `2` relay jobs in flight by default.
+const fn max_relay_jobs_default() -> usize {
+    2
+}
+/// A connection has up to `16` commands in flight by default.
+const fn max_concurrent_commands_default() -> usize {
+    16
+}
+/// A connection watches up to `8` commitments at once by default.
+const fn max_commitment_watches_default() -> usize {
+    8
+}
+/// A commitment is watched for up to `1` hour by default.
+const fn commitment_watch_timeout_default() -> u64 {
+    60 * 60
+}
+/// The relay stats are kept for `90` days by default.
+const fn relay_stats_retention_default() -> u64 {
+    90 * 24 * 60 * 60
+}
+/// The leader lease is named `webb-relayer:leader` by default.
+fn ha_lease_key_default() -> String {
+    String::from("webb-relayer:leader")
+}
+/// The shared queues are prefixed by `webb-relayer:queue` by default.
+fn ha_queue_prefix_default() -> String {
+    String::from("webb-relayer:queue")
+}
+/// The leader lease expires `15_000` milliseconds (15 seconds) after its last renewal by default.
+const fn ha_lease_ttl_default() -> u64 {
+    15_000
+}
+/// The store caches up to `1024` entries of each kind by default.
+const fn cache_capacity_default() -> usize {
+    1024
+}
 /// WebbRelayerConfig is the configuration for the webb relayer.
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -72,12 +163,104 @@ pub struct WebbRelayerConfig {
     /// Substrate based networks and the configuration.
     ///
     /// a map between chain name and its configuration.
+    #[cfg(feature = "substrate")]
     #[serde(default)]
     pub substrate: HashMap<String, SubstrateConfig>,
     /// For Experimental Options
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+    /// Periodic export of the leaves snapshots to IPFS.
+    ///
+    /// Optional, and disabled by default.
+    #[serde(default, skip_serializing)]
+    pub ipfs: Option<IpfsConfig>,
+    /// Extra relayer identities (tenants), each with its own wallets, fee policy
+    /// and allowed contracts.
+    ///
+    /// a map between the identity name and its configuration.
+    #[serde(default)]
+    pub identities: HashMap<String, RelayerIdentityConfig>,
+    /// Admin API configuration.
+    ///
+    /// Optional, the admin API is disabled if not configured.
+    #[serde(default, skip_serializing)]
+    pub admin: Option<AdminConfig>,
+    /// Controls how long the signed proposals are waiting for execution
+    /// before they get garbage collected.
+    #[serde(default, skip_serializing)]
+    pub proposals: ProposalsConfig,
+    /// Periodic heartbeats to an external monitor (like healthchecks.io), only sent
+    /// while the relayer is healthy.
+    ///
+    /// Optional, and disabled by default.
+    #[serde(default, skip_serializing)]
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Periodic checkpoints of the roots of the cached leaves against the on-chain ones,
+    /// alerting when they diverge for more than one interval.
+    ///
+    /// Optional, and disabled by default.
+    #[serde(default, skip_serializing)]
+    pub root_checkpoints: Option<RootCheckpointsConfig>,
+    /// The checks of every chain, before serving any traffic.
+    #[serde(default, skip_serializing)]
+    pub preflight: PreflightConfig,
+    /// The capacities of the internal channels.
+    #[serde(default, skip_serializing)]
+    pub channels: ChannelsConfig,
+    /// The limits of every client connection.
+    #[serde(default, skip_serializing)]
+    pub connections: ConnectionsConfig,
+    /// The configuration of the tokio runtime.
+    #[serde(default, skip_serializing)]
+    pub runtime: RuntimeConfig,
+    /// The configuration of the store.
+    #[serde(default, skip_serializing)]
+    pub store: StoreConfig,
+    /// The active/standby high-availability mode, where only the elected leader among
+    /// the instances of a deployment broadcasts transactions.
+    ///
+    /// Optional, and disabled by default.
+    #[serde(default, skip_serializing)]
+    pub ha: Option<HaConfig>,
+    /// The API keys of the clients, with their daily quotas.
+    #[serde(default, skip_serializing)]
+    pub api_keys: ApiKeysConfig,
+    /// The canonical names of the EVM chains, by their lowercased aliases and chain ids,
+    /// indexed once the configuration is loaded, see [`WebbRelayerConfig::canonical_evm_chain`].
+    #[serde(skip)]
+    pub evm_chain_names: HashMap<String, String>,
 }
+
+impl WebbRelayerConfig {
+    /// Resolves the name, an alias, or the chain id of an EVM chain to its canonical name,
+    /// the (lowercased) name it is configured with.
+    ///
+    /// The chains are only known by their canonical name internally, and their history is
+    /// stored by their chain id, so a chain renamed with its old name kept as an alias
+    /// orphans nothing.
+    pub fn canonical_evm_chain(&self, chain: &str) -> Option<&str> {
+        let chain = chain.to_lowercase();
+        if let Some((name, _)) = self.evm.get_key_value(&chain) {
+            return Some(name);
+        }
+        self.evm_chain_names.get(&chain).map(String::as_str)
+    }
+    /// Resolves the name or the chain id of a Substrate node to the (lowercased) name it
+    /// is configured with, the chain id being the `chain-id` of the node.
+    #[cfg(feature = "substrate")]
+    pub fn canonical_substrate_chain(&self, chain: &str) -> Option<&str> {
+        let chain = chain.to_lowercase();
+        if let Some((name, _)) = self.substrate.get_key_value(&chain) {
+            return Some(name);
+        }
+        let chain_id = chain.parse::<u32>().ok()?;
+        self.substrate
+            .iter()
+            .find(|(_, node)| node.chain_id == Some(chain_id))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
 /// EvmChainConfig is the configuration for the EVM based networks.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -100,6 +283,12 @@ pub struct EvmChainConfig {
     /// chain specific id.
     #[serde(rename(serialize = "chainId"))]
     pub chain_id: u64,
+    /// The other names of this chain (like `eth-goerli` for `goerli`), case-insensitive.
+    ///
+    /// They are accepted wherever the chain is named: in the commands, the linked anchors
+    /// and the identities, along with its chain id.
+    #[serde(skip_serializing, default)]
+    pub aliases: Vec<String>,
     /// The Private Key of this account on this network
     /// the format is more dynamic here:
     /// 1. if it starts with '0x' then this would be raw (64 bytes) hex encoded
@@ -127,8 +316,89 @@ pub struct EvmChainConfig {
     /// TxQueue configuration
     #[serde(skip_serializing, default)]
     pub tx_queue: TxQueueConfig,
+    /// Optionally, a fee policy that raises the withdraw fee percentage of the contracts
+    /// on this chain when the gas price goes up.
+    #[serde(rename(serialize = "dynamicFee"))]
+    pub dynamic_fee: Option<DynamicFeeConfig>,
+    /// The gas cost of the withdraws on this chain, used for estimating their fees.
+    #[serde(rename(serialize = "gasModels"), default)]
+    pub gas_models: GasModelsConfig,
+    /// The fee policies of the withdraws on this chain, for every contract type.
+    #[serde(rename(serialize = "feePolicies"), default)]
+    pub fee_policies: FeePoliciesConfig,
+    /// Optionally, a guard that rejects the withdraws with a fee that does not cover their gas cost.
+    #[serde(skip_serializing)]
+    pub profitability_guard: Option<ProfitabilityGuardConfig>,
+    /// Optionally, a rate limit of the requests sent to the `http-endpoint`, shared by
+    /// all the watchers and the relay handlers of the chains using that endpoint.
+    #[serde(skip_serializing)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// The kind of rollup this chain is, if it is one, for estimating the gas of its transactions.
+    ///
+    /// Defaults to the preset of the known rollups, by their `chain-id`.
+    #[serde(skip_serializing, default)]
+    pub rollup: Option<RollupKind>,
+    /// The profile of this chain, which sets the defaults of its events watchers.
+    #[serde(skip_serializing, default)]
+    pub profile: ChainProfile,
+    /// Send legacy transactions only, priced with `eth_gasPrice`, for the chains that
+    /// reject the typed transactions or lack `eth_feeHistory`.
+    #[serde(skip_serializing, default)]
+    pub legacy_tx: bool,
+    /// How many relay commands could estimate and submit their transaction on this chain
+    /// at the same time, the others wait for their turn.
+    ///
+    /// The relayer's account signs all of them, so sending them together races for its nonce.
+    #[serde(skip_serializing, default = "max_concurrent_relays_default")]
+    pub max_concurrent_relays: usize,
+    /// How many times a transaction dropped from the mempool is repriced and resubmitted,
+    /// before giving up on it.
+    #[serde(skip_serializing, default = "max_resubmissions_default")]
+    pub max_resubmissions: u32,
+}
+
+impl EvmChainConfig {
+    /// Returns the kind of rollup of this chain, the configured one or its preset.
+    pub fn rollup_kind(&self) -> Option<RollupKind> {
+        self.rollup
+            .or_else(|| RollupKind::from_chain_id(self.chain_id))
+    }
+}
+/// RollupKind is the kind of a rollup (L2) chain, which prices its gas differently than
+/// the mainnet-like chains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RollupKind {
+    /// An Arbitrum chain, its gas estimations include the L1 calldata cost.
+    Arbitrum,
+    /// An Optimism chain, the L1 data fee is charged on top of the L2 gas.
+    Optimism,
+}
+
+impl RollupKind {
+    /// Returns the kind of rollup of the known rollup chains.
+    pub fn from_chain_id(chain_id: u64) -> Option<Self> {
+        match chain_id {
+            // Arbitrum One, Nova, Rinkeby and Goerli.
+            42161 | 42170 | 421611 | 421613 => Some(Self::Arbitrum),
+            // Optimism, Kovan and Goerli.
+            10 | 69 | 420 => Some(Self::Optimism),
+            _ => None,
+        }
+    }
+}
+/// RateLimitConfig is the configuration of the rate limit of an RPC endpoint.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RateLimitConfig {
+    /// The number of requests sent every second, on average.
+    pub requests_per_second: u32,
+    /// The number of requests that could be sent at once, defaults to `requests-per-second`.
+    #[serde(default)]
+    pub burst: Option<u32>,
 }
 /// SubstrateConfig is the configuration for the Substrate based networks.
+#[cfg(feature = "substrate")]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SubstrateConfig {
@@ -182,10 +452,49 @@ pub struct SubstrateConfig {
     pub beneficiary: Option<Public>,
     /// Which Substrate Runtime to use?
     pub runtime: SubstrateRuntime,
+    /// The chain id of this node, as in its typed chain id.
+    ///
+    /// Optional, the relays over this node are only counted in the relay stats when it
+    /// is set.
+    #[serde(default)]
+    pub chain_id: Option<u32>,
     /// Supported pallets over this substrate node.
     #[serde(default)]
     pub pallets: Vec<Pallet>,
 }
+/// RelayerIdentityConfig is the configuration for a single relayer identity.
+///
+/// Incoming relay commands could select an identity by its name, and then the
+/// transaction will be signed by the identity's wallet on that chain, and checked
+/// against the identity's fee policy and allowed contracts.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RelayerIdentityConfig {
+    /// The accounts of this identity on the EVM based networks.
+    ///
+    /// a map between chain name and the account on it.
+    #[serde(default)]
+    pub evm: HashMap<String, IdentityEvmAccountConfig>,
+    /// Overrides the withdraw fee percentage of the contracts for this identity.
+    #[serde(rename(serialize = "withdrawFeePercentage"))]
+    pub withdraw_fee_percentage: Option<f64>,
+    /// The contracts this identity is allowed to relay transactions for.
+    ///
+    /// if empty, all the configured contracts are allowed.
+    #[serde(rename(serialize = "allowedContracts"), default)]
+    pub allowed_contracts: Vec<Address>,
+}
+/// IdentityEvmAccountConfig is the account of a relayer identity on an EVM based network.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IdentityEvmAccountConfig {
+    /// The Private Key of this identity's account on this network,
+    /// it uses the same format as [`EvmChainConfig::private_key`].
+    #[serde(skip_serializing)]
+    pub private_key: PrivateKey,
+    /// Optionally, a user can specify an account to receive rewards for relaying
+    pub beneficiary: Option<Address>,
+}
 /// ExperimentalConfig is the configuration for the Experimental Options.
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -195,6 +504,287 @@ pub struct ExperimentalConfig {
     pub smart_anchor_updates: bool,
     pub smart_anchor_updates_retries: u32,
 }
+/// IpfsConfig is the configuration for exporting the leaves snapshots to IPFS.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IpfsConfig {
+    /// Boolean indicating the snapshots exporter is enabled or not.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The IPFS HTTP API Endpoint used for adding and pinning the snapshots.
+    ///
+    /// Example: http://127.0.0.1:5001
+    pub api_endpoint: url::Url,
+    /// How often (in milliseconds) a new snapshot of each contract's leaves
+    /// should be exported.
+    #[serde(default = "ipfs_export_interval_default")]
+    pub export_interval: u64,
+}
+/// AdminConfig is the configuration for the Admin API.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AdminConfig {
+    /// The token used to authenticate the admin requests, sent in the
+    /// `Authorization` header as `Bearer <token>`.
+    pub token: String,
+}
+/// ProposalsConfig is the configuration for the expiry of the signed proposals.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProposalsConfig {
+    /// Maximum number of seconds a signed proposal could wait for execution,
+    /// after that it gets removed and its queued transaction gets cancelled.
+    #[serde(default = "proposals_max_age_default")]
+    pub max_age: u64,
+    /// How often (in milliseconds) the stale proposals should be collected.
+    #[serde(default = "proposals_gc_interval_default")]
+    pub gc_interval: u64,
+}
+
+impl Default for ProposalsConfig {
+    fn default() -> Self {
+        Self {
+            max_age: proposals_max_age_default(),
+            gc_interval: proposals_gc_interval_default(),
+        }
+    }
+}
+/// HeartbeatConfig is the configuration for reporting heartbeats to an external monitor.
+///
+/// A heartbeat is only sent if all the events watchers are within the lag bounds and
+/// all the wallets are funded, so that silent failures show up as missed heartbeats.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HeartbeatConfig {
+    /// The URL that gets pinged (with a GET request) on every heartbeat.
+    ///
+    /// Example: https://hc-ping.com/<uuid>
+    pub url: url::Url,
+    /// How often (in milliseconds) the heartbeat should be sent.
+    #[serde(default = "heartbeat_interval_default")]
+    pub interval: u64,
+    /// Maximum number of blocks an events watcher could lag behind its chain.
+    #[serde(default = "heartbeat_max_lag_default")]
+    pub max_lag: u64,
+    /// The wallets with a balance (in wei, or in the smallest unit of the token of a
+    /// Substrate node) at or below this one are considered unfunded.
+    #[serde(default)]
+    pub min_balance: U256,
+}
+/// RootCheckpointsConfig is the configuration of the periodic checkpoints of the roots.
+///
+/// Every interval, the root computed from the cached leaves of every anchor and tornado
+/// contract is recorded along with the on-chain one. A divergence lasting for more than
+/// one interval (corrupted leaves, or missed events) is alerted.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RootCheckpointsConfig {
+    /// How often (in seconds) the roots are checked.
+    #[serde(default = "root_checkpoints_interval_default")]
+    pub interval: u64,
+    /// The URL that gets the divergence alerts (with a POST request), if any.
+    #[serde(default)]
+    pub webhook: Option<url::Url>,
+}
+/// PreflightConfig is the configuration of the startup checks of every enabled EVM chain
+/// and Substrate node.
+///
+/// The checks make sure the RPC endpoint is reachable, it reports the configured chain id,
+/// the configured contracts have code at their address and got deployed before the chain head,
+/// and the wallet balance is at least the floor. The Substrate nodes are checked for their
+/// endpoint and the free balance of the relayer's account.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreflightConfig {
+    /// Boolean indicating the checks are skipped or not.
+    #[serde(default)]
+    pub skip: bool,
+    /// What to do with the chains that fail the checks.
+    #[serde(default)]
+    pub on_failure: PreflightFailureAction,
+    /// The wallets with a balance (in wei, or in the smallest unit of a Substrate node)
+    /// below this one fail the checks.
+    #[serde(default)]
+    pub min_balance: U256,
+}
+
+/// Enumerates what the relayer does with the chains that fail the startup checks.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum PreflightFailureAction {
+    /// Report the failures, and start the relayer with all the chains.
+    #[serde(rename = "warn")]
+    #[default]
+    Warn,
+    /// Refuse to start the relayer.
+    #[serde(rename = "abort")]
+    Abort,
+    /// Start the relayer with the failed chains disabled.
+    #[serde(rename = "disable-chain")]
+    DisableChain,
+}
+/// HaConfig is the configuration of the active/standby high-availability mode.
+///
+/// The instances of a deployment compete for a lease in a shared Redis server. The one
+/// holding it is the leader, which relays the transactions, while the standbys keep
+/// their caches warm (their events watchers keep running) and take over once the lease
+/// of the leader expires.
+///
+/// The transaction queues live in the same Redis server, shared by all the instances,
+/// so the transactions queued by a failed leader are sent by the next one.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HaConfig {
+    /// The URL of the Redis server holding the lease.
+    ///
+    /// Example: redis://127.0.0.1:6379
+    pub redis_url: url::Url,
+    /// The key of the lease, shared by all the instances of a deployment.
+    #[serde(default = "ha_lease_key_default")]
+    pub lease_key: String,
+    /// How long (in milliseconds) the lease is held without being renewed, the leader
+    /// renews it every third of it.
+    #[serde(default = "ha_lease_ttl_default")]
+    pub lease_ttl: u64,
+    /// The id of this instance in the lease, a random one by default.
+    #[serde(default)]
+    pub instance_id: Option<String>,
+    /// The prefix of the keys of the shared transaction queues, shared by all the
+    /// instances of a deployment.
+    #[serde(default = "ha_queue_prefix_default")]
+    pub queue_prefix: String,
+}
+/// ChannelsConfig is the configuration of the capacities of the internal channels.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChannelsConfig {
+    /// The number of updates buffered for a client, before the relay handlers wait
+    /// for the client to catch up.
+    #[serde(default = "command_stream_capacity_default")]
+    pub command_stream_capacity: usize,
+}
+
+impl Default for ChannelsConfig {
+    fn default() -> Self {
+        Self {
+            command_stream_capacity: command_stream_capacity_default(),
+        }
+    }
+}
+/// ApiKeysConfig is the configuration of the API keys of the clients, so the operators
+/// could offer tiered access to their relayer.
+///
+/// A client sends its key in the `X-Api-Key` header (or in the `apiKey` query parameter
+/// of the websocket, for the browsers), its relay commands and the leaves it gets served
+/// are then counted against the daily quotas of that key.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiKeysConfig {
+    /// Whether the clients without an API key are refused, they are served without
+    /// any quota otherwise.
+    #[serde(default)]
+    pub required: bool,
+    /// The API keys, by the name they are counted under.
+    #[serde(default)]
+    pub keys: HashMap<String, ApiKeyConfig>,
+    /// The names of the API keys, by the keccak256 hash of their key, see
+    /// [`ApiKeysConfig::index_keys`].
+    #[serde(skip)]
+    pub names_by_hash: HashMap<[u8; 32], String>,
+}
+
+impl ApiKeysConfig {
+    /// Indexes the names of the API keys by the hash of their key, so the key of a client
+    /// is looked up without comparing it with every configured key.
+    pub fn index_keys(&mut self) {
+        self.names_by_hash = self
+            .keys
+            .iter()
+            .map(|(name, c)| {
+                (crate::utils::keccak256(c.key.as_bytes()), name.clone())
+            })
+            .collect();
+    }
+}
+/// ApiKeyConfig is an API key of a client, with its daily quotas.
+///
+/// The quotas are reset at midnight (UTC), the missing ones are unlimited.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiKeyConfig {
+    /// The secret key sent by the client.
+    pub key: String,
+    /// The number of relay commands accepted per day.
+    pub relays_per_day: Option<u64>,
+    /// The size of the leaves served per day, in bytes (32 per leaf, whatever the
+    /// encoding of the response).
+    pub leaves_bytes_per_day: Option<u64>,
+}
+/// ConnectionsConfig is the configuration of the limits of the client connections.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConnectionsConfig {
+    /// The number of commands (of any kind) a connection could have in flight, the
+    /// next ones are read once one of them is done.
+    #[serde(default = "max_concurrent_commands_default")]
+    pub max_concurrent_commands: usize,
+    /// The number of relay jobs a connection could have in flight, the extra ones are
+    /// rejected until one of them is done.
+    #[serde(default = "max_relay_jobs_default")]
+    pub max_relay_jobs: usize,
+    /// The number of commitments a connection could watch at once, the extra ones are
+    /// rejected until one of them is inserted or expired.
+    #[serde(default = "max_commitment_watches_default")]
+    pub max_commitment_watches: usize,
+    /// How long a commitment is watched for (in seconds), before the connection is
+    /// told it is not watched anymore.
+    #[serde(default = "commitment_watch_timeout_default")]
+    pub commitment_watch_timeout: u64,
+}
+
+impl Default for ConnectionsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_commands: max_concurrent_commands_default(),
+            max_relay_jobs: max_relay_jobs_default(),
+            max_commitment_watches: max_commitment_watches_default(),
+            commitment_watch_timeout: commitment_watch_timeout_default(),
+        }
+    }
+}
+/// RuntimeConfig is the configuration of the tokio runtime the relayer runs on.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RuntimeConfig {
+    /// The number of worker threads, defaults to the number of CPU cores.
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    /// The maximum number of threads for the blocking operations, defaults to 512.
+    #[serde(default)]
+    pub max_blocking_threads: Option<usize>,
+}
+/// StoreConfig is the configuration of the store of the relayer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StoreConfig {
+    /// The number of entries (per contract) of each kind kept in the read cache,
+    /// the leaves and the last block numbers; `0` disables the cache.
+    #[serde(default = "cache_capacity_default")]
+    pub cache_capacity: usize,
+    /// How long the relay stats buckets are kept, in seconds.
+    #[serde(default = "relay_stats_retention_default")]
+    pub relay_stats_retention: u64,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            cache_capacity: cache_capacity_default(),
+            relay_stats_retention: relay_stats_retention_default(),
+        }
+    }
+}
 /// TxQueueConfig is the configuration for the TxQueue.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -211,6 +801,85 @@ impl Default for TxQueueConfig {
         }
     }
 }
+/// DynamicFeeConfig is the configuration of the fee policy that tracks the gas price.
+///
+/// The fee percentage is the one that covers the gas cost of a withdraw (`withdraw-gaslimit`
+/// at the current gas price) for the contract's denomination, but never lower than the
+/// configured `withdraw-fee-percentage`.
+/// **Note**: the gas cost is compared to the denomination as is, so this only makes sense
+/// for contracts of the native token of the chain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DynamicFeeConfig {
+    /// The gas cost is multiplied by this margin, to cover the gas price changes between
+    /// the fee quote and the relay transaction.
+    #[serde(
+        rename(serialize = "gasPriceMargin"),
+        default = "gas_price_margin_default"
+    )]
+    pub gas_price_margin: f64,
+    /// The fee percentage never goes above this one, however high the gas price is.
+    #[serde(rename(serialize = "maxFeePercentage"))]
+    pub max_fee_percentage: f64,
+}
+/// FeePoliciesConfig is the fee policy of a withdraw, for every contract type.
+///
+/// The withdraws differ hugely in gas, so does the fee operators expect for them.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeePoliciesConfig {
+    /// The fee policy of the withdraws on the Tornado contracts.
+    #[serde(rename(serialize = "tornadoWithdraw"), default)]
+    pub tornado_withdraw: FeePolicyConfig,
+    /// The fee policy of the withdraws on the Anchor contracts.
+    #[serde(rename(serialize = "anchorWithdraw"), default)]
+    pub anchor_withdraw: FeePolicyConfig,
+}
+/// FeePolicyConfig is the fee policy of one type of withdraw.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeePolicyConfig {
+    /// The withdraw fee percentage of the contracts of that type, for the contracts
+    /// that do not set their own.
+    #[serde(rename(serialize = "feePercentage"))]
+    pub fee_percentage: Option<f64>,
+    /// Overrides the `dynamic-fee` of the chain for that type of withdraw.
+    #[serde(rename(serialize = "dynamicFee"))]
+    pub dynamic_fee: Option<DynamicFeeConfig>,
+}
+/// ProfitabilityGuardConfig is the configuration of the guard that checks the fee of a withdraw
+/// against its gas cost, before it gets sent to the chain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfitabilityGuardConfig {
+    /// The fee should cover the estimated gas cost multiplied by this margin.
+    #[serde(default = "gas_cost_margin_default")]
+    pub gas_cost_margin: f64,
+    /// Relay the unprofitable withdraws anyway, they are only logged.
+    #[serde(default)]
+    pub altruistic: bool,
+}
+/// GasModelsConfig is the gas cost of a withdraw, for every contract type.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GasModelsConfig {
+    /// The gas used by a withdraw on a Tornado contract.
+    #[serde(rename(serialize = "tornadoWithdraw"))]
+    pub tornado_withdraw: U256,
+    /// The gas used by a withdraw on an Anchor contract.
+    #[serde(rename(serialize = "anchorWithdraw"))]
+    pub anchor_withdraw: U256,
+}
+
+impl Default for GasModelsConfig {
+    fn default() -> Self {
+        Self {
+            tornado_withdraw: U256::from(350_000),
+            anchor_withdraw: U256::from(500_000),
+        }
+    }
+}
+
 /// EventsWatchConfig is the configuration for the events watch.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -218,29 +887,223 @@ pub struct EventsWatcherConfig {
     #[serde(default = "enable_leaves_watcher_default")]
     /// if it is enabled for this chain or not.
     pub enabled: bool,
-    /// Polling interval in milliseconds
-    #[serde(rename(serialize = "pollingInterval"))]
-    pub polling_interval: u64,
-    /// The maximum number of events to fetch in one request.
-    #[serde(skip_serializing, default = "max_events_per_step_default")]
-    pub max_events_per_step: u64,
+    /// Polling interval in milliseconds, defaults to the one of the chain profile.
+    #[serde(rename(serialize = "pollingInterval"), default)]
+    pub polling_interval: Option<u64>,
+    /// The maximum number of blocks to fetch the events of in one request,
+    /// defaults to the one of the chain profile.
+    #[serde(skip_serializing, default)]
+    pub max_events_per_step: Option<u64>,
+    /// The number of blocks on top of a block before its events get handled,
+    /// defaults to the one of the chain profile.
+    #[serde(skip_serializing, default)]
+    pub confirmations: Option<u64>,
+    /// The maximum random delay added to every cooldown, in milliseconds, so the watchers
+    /// sharing a polling interval do not poll their RPC endpoint all at once.
+    #[serde(skip_serializing, default)]
+    pub polling_jitter: u64,
+    /// The circuit breaker of the events watcher, which slows down the retries of a
+    /// watcher that keeps failing.
+    #[serde(skip_serializing, default)]
+    pub circuit_breaker: CircuitBreakerConfig,
     /// print sync progress frequency in milliseconds
     /// if it is zero, means no progress will be printed.
     #[serde(skip_serializing, default = "print_progress_interval_default")]
     pub print_progress_interval: u64,
 }
 
+impl EventsWatcherConfig {
+    /// Fills the settings that are not configured with the defaults of that chain profile.
+    pub fn apply_profile(&mut self, profile: ChainProfile) {
+        self.polling_interval
+            .get_or_insert(profile.polling_interval());
+        self.max_events_per_step
+            .get_or_insert(profile.max_events_per_step());
+        self.confirmations.get_or_insert(profile.confirmations());
+    }
+
+    /// Moves the settings filled from the `old` profile to the defaults of the `new` one, for
+    /// a chain whose profile changes once the configuration is loaded.
+    ///
+    /// The settings that differ from the defaults of the `old` profile are configured, they
+    /// are kept.
+    pub fn change_profile(&mut self, old: ChainProfile, new: ChainProfile) {
+        if self.polling_interval == Some(old.polling_interval()) {
+            self.polling_interval = Some(new.polling_interval());
+        }
+        if self.max_events_per_step == Some(old.max_events_per_step()) {
+            self.max_events_per_step = Some(new.max_events_per_step());
+        }
+        if self.confirmations == Some(old.confirmations()) {
+            self.confirmations = Some(new.confirmations());
+        }
+    }
+
+    /// The polling interval in milliseconds, see [`ChainProfile::polling_interval`].
+    pub fn polling_interval(&self) -> u64 {
+        self.polling_interval
+            .unwrap_or_else(|| ChainProfile::default().polling_interval())
+    }
+
+    /// The block step of the events watcher, see [`ChainProfile::max_events_per_step`].
+    pub fn max_events_per_step(&self) -> u64 {
+        self.max_events_per_step
+            .unwrap_or_else(|| ChainProfile::default().max_events_per_step())
+    }
+
+    /// The required confirmations of the events, see [`ChainProfile::confirmations`].
+    pub fn confirmations(&self) -> u64 {
+        self.confirmations
+            .unwrap_or_else(|| ChainProfile::default().confirmations())
+    }
+}
+
+/// CircuitBreakerConfig is the configuration of the circuit breaker of an events watcher.
+///
+/// The transient failures are retried with an exponential backoff, up to `max-backoff-interval`
+/// apart. After `failure-threshold` consecutive failures, the watcher is marked as degraded and
+/// only retried every `retry-interval`, until it makes progress again. The permanent failures
+/// (decoding failures, misconfigurations) stop the watcher instead.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failures that open the circuit breaker.
+    #[serde(default = "failure_threshold_default")]
+    pub failure_threshold: u32,
+    /// How often a degraded watcher is retried, in milliseconds.
+    #[serde(default = "degraded_retry_interval_default")]
+    pub retry_interval: u64,
+    /// The ceiling of the exponential backoff between the retries of the transient failures,
+    /// before the breaker opens, in milliseconds.
+    #[serde(default = "max_backoff_interval_default")]
+    pub max_backoff_interval: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: failure_threshold_default(),
+            retry_interval: degraded_retry_interval_default(),
+            max_backoff_interval: max_backoff_interval_default(),
+        }
+    }
+}
+
+/// ChainProfile tunes the defaults of the events watchers of a chain for its block time.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChainProfile {
+    /// For the mainnet-like chains, with a block every ~15 seconds.
+    #[default]
+    Standard,
+    /// For the chains producing a block every ~2 seconds (like Polygon), which reorg more often.
+    HighThroughput,
+    /// For the PoA and development chains (like Ganache), where a mined block is final.
+    InstantFinality,
+}
+
+impl ChainProfile {
+    /// The default polling interval of the events watchers, in milliseconds.
+    pub const fn polling_interval(&self) -> u64 {
+        match self {
+            Self::Standard => 7_000,
+            Self::HighThroughput | Self::InstantFinality => 1_000,
+        }
+    }
+
+    /// The default number of blocks the events watchers fetch the events of at once.
+    pub const fn max_events_per_step(&self) -> u64 {
+        match self {
+            Self::Standard => 100,
+            Self::HighThroughput | Self::InstantFinality => 1_000,
+        }
+    }
+
+    /// The default number of confirmations of the events, before the events watchers handle them.
+    pub const fn confirmations(&self) -> u64 {
+        match self {
+            // a reorg under the handled blocks rolls the leaves back, but it is better
+            // not to serve the leaves of the blocks likely to be reorged at all.
+            Self::Standard => 12,
+            Self::HighThroughput => 128,
+            Self::InstantFinality => 0,
+        }
+    }
+
+    /// How often a sent transaction is polled for its receipt, in milliseconds.
+    pub const fn tx_polling_interval(&self) -> u64 {
+        match self {
+            Self::Standard | Self::HighThroughput => 1_000,
+            Self::InstantFinality => 100,
+        }
+    }
+}
+
 /// AnchorWithdrawConfig is the configuration for the Anchor Withdraw.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct AnchorWithdrawConfig {
     /// The fee percentage that your account will receive when you relay a transaction
-    /// over this chain.
-    #[serde(rename(serialize = "withdrawFeePercentage"))]
-    pub withdraw_fee_percentage: f64,
+    /// to this contract.
+    ///
+    /// Defaults to the `fee-percentage` of the fee policy of the chain for this contract type.
+    #[serde(rename(serialize = "withdrawFeePercentage"), default)]
+    pub withdraw_fee_percentage: Option<f64>,
+    /// The account receiving the fees of the transactions relayed to this contract.
+    ///
+    /// Defaults to the `beneficiary` of the chain.
+    #[serde(default)]
+    pub beneficiary: Option<Address>,
     /// A hex value of the gaslimit when doing a withdraw relay transaction on this chain.
-    #[serde(skip_serializing)]
-    pub withdraw_gaslimit: U256,
+    ///
+    /// if set, it overrides the gas model of the contract type, see [`GasModelsConfig`].
+    #[serde(skip_serializing, alias = "withdraw-gas-limit")]
+    pub withdraw_gaslimit: Option<U256>,
+    /// A hex value of the gas limit of the withdraw transactions relayed to this contract,
+    /// instead of the estimation of the node, for the chains mis-estimating the proof
+    /// verification.
+    ///
+    /// Not set by default, the withdraw transactions are estimated by the node.
+    #[serde(skip_serializing, default)]
+    pub fixed_gas_limit: Option<U256>,
+    /// The address of a Chainlink-compatible price feed of the contract's token in the
+    /// native token of this chain, for the contracts of other tokens.
+    ///
+    /// Used by the profitability guard for converting the fee at the current price, the
+    /// fee is assumed to be in the native token if not set.
+    #[serde(skip_serializing, default)]
+    pub price_feed: Option<Address>,
+}
+
+impl AnchorWithdrawConfig {
+    /// Fills the settings this contract does not override from its chain, and
+    /// the fee policy of the chain for this contract type.
+    ///
+    /// Returns an error if neither the contract nor the policy sets the fee percentage.
+    pub fn apply_chain_defaults(
+        &mut self,
+        beneficiary: Option<Address>,
+        policy: &FeePolicyConfig,
+    ) -> anyhow::Result<()> {
+        self.beneficiary = self.beneficiary.or(beneficiary);
+        self.withdraw_fee_percentage =
+            self.withdraw_fee_percentage.or(policy.fee_percentage);
+        anyhow::ensure!(
+            self.withdraw_fee_percentage.is_some(),
+            "no `withdraw-fee-percentage`, set it on the contract \
+            or in the `fee-policies` of the chain"
+        );
+        Ok(())
+    }
+
+    /// The fee percentage of the withdraws relayed to this contract.
+    ///
+    /// It is always set once the config is loaded, see [`Self::apply_chain_defaults`].
+    pub fn withdraw_fee_percentage(&self) -> f64 {
+        self.withdraw_fee_percentage.unwrap_or_default()
+    }
 }
 
 /// LinkedAnchorConfig is the configuration for the linked anchor.
@@ -250,8 +1113,13 @@ pub struct LinkedAnchorConfig {
     /// The Chain name where this anchor belongs to.
     /// and it is case-insensitive.
     pub chain: String,
-    /// The Anchor Contract Address.
+    /// The Anchor Contract Address, unused for the anchors of a Substrate node.
+    #[serde(default)]
     pub address: Address,
+    /// The tree id of the anchor, for the anchors of the anchor handler pallet of a
+    /// Substrate node, the `chain` is then the name of that node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree_id: Option<u32>,
 }
 
 /// Enumerates the supported contract configurations.
@@ -264,12 +1132,26 @@ pub enum Contract {
     GovernanceBravoDelegate(GovernanceBravoDelegateContractConfig),
 }
 
+impl Contract {
+    /// Returns the common configuration of that contract.
+    pub fn common(&self) -> &CommonContractConfig {
+        match self {
+            Contract::Tornado(c) => &c.common,
+            Contract::Anchor(c) => &c.common,
+            Contract::SignatureBridge(c) => &c.common,
+            Contract::GovernanceBravoDelegate(c) => &c.common,
+        }
+    }
+}
+
 /// Enumerates the supported pallets configurations.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "pallet")]
 pub enum Pallet {
     DKGProposals(DKGProposalsPalletConfig),
     DKGProposalHandler(DKGProposalHandlerPalletConfig),
+    /// The bridge pallet of the Webb Protocol runtime.
+    Bridge(BridgePalletConfig),
 }
 
 /// Enumerates the supported Substrate runtimes.
@@ -289,6 +1171,41 @@ pub struct CommonContractConfig {
     /// the block number where this contract got deployed at.
     #[serde(rename(serialize = "deployedAt"))]
     pub deployed_at: u64,
+    /// Where the events watcher of this contract starts syncing from, the first time.
+    #[serde(skip_serializing, default)]
+    pub sync_from: SyncFrom,
+    /// Whether to clear everything cached about this contract on startup, so it gets
+    /// re-indexed from the `deployed-at` block.
+    ///
+    /// It is only cleared on the first startup with this flag set.
+    #[serde(skip_serializing, default)]
+    pub force_resync: bool,
+}
+
+impl CommonContractConfig {
+    /// Where the events watcher of this contract starts syncing from, a forced resync
+    /// always starts from the `deployed-at` block.
+    pub fn sync_from(&self) -> SyncFrom {
+        if self.force_resync {
+            SyncFrom::DeployedAt
+        } else {
+            self.sync_from
+        }
+    }
+}
+
+/// SyncFrom is where the events watcher of a contract starts syncing from, when
+/// it has no progress saved yet.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncFrom {
+    /// From the `deployed-at` block, so all the historical events get handled.
+    #[default]
+    DeployedAt,
+    /// From the chain head, so only the new events get handled.
+    Latest,
 }
 
 /// TornadoContractConfig represents the configuration for the Tornado contract.
@@ -305,6 +1222,10 @@ pub struct TornadoContractConfig {
     /// Anchor withdraw configuration.
     #[serde(flatten)]
     pub withdraw_config: AnchorWithdrawConfig,
+    /// The verifying key of the withdraw circuit of this contract, the withdraw proofs are
+    /// verified locally against it before being relayed, with the `zk-verifier` feature.
+    #[serde(skip_serializing, default)]
+    pub verifying_key: Option<std::path::PathBuf>,
 }
 
 /// AnchorContractOverDKGConfig represents the configuration for the Anchor contract over DKG.
@@ -327,6 +1248,32 @@ pub struct AnchorContractConfig {
     /// A List of linked Anchor Contracts (on other chains) to this contract.
     #[serde(rename(serialize = "linkedAnchors"), default)]
     pub linked_anchors: Vec<LinkedAnchorConfig>,
+    /// The encoding of the anchor update proposals expected by the handler of this contract.
+    #[serde(rename(serialize = "proposalEncoding"), default)]
+    pub proposal_encoding: AnchorUpdateProposalEncoding,
+    /// Whether the relayer should propose to register the resource of this contract on the
+    /// Signature Bridge of its chain, if it is not registered yet.
+    #[serde(rename(serialize = "registerResource"), default)]
+    pub register_resource: bool,
+    /// The verifying key of the withdraw circuit of this contract, the withdraw proofs are
+    /// verified locally against it before being relayed, with the `zk-verifier` feature.
+    #[serde(skip_serializing, default)]
+    pub verifying_key: Option<std::path::PathBuf>,
+}
+
+/// Enumerates the supported encodings of the anchor update proposals, which
+/// depends on the version of the `AnchorHandler` of the target anchor.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum AnchorUpdateProposalEncoding {
+    /// Handlers that use plain chain ids, with the source chain id encoded as an `uint256`.
+    #[serde(rename = "legacy")]
+    Legacy,
+    /// Handlers that use typed chain ids, as defined in `webb-proposals`.
+    #[serde(rename = "v1")]
+    #[default]
+    V1,
 }
 
 /// GovernanceBravoDelegateContractConfig represents the configuration for the GovernanceBravoDelegate contract.
@@ -366,6 +1313,15 @@ pub struct DKGProposalHandlerPalletConfig {
     pub events_watcher: EventsWatcherConfig,
 }
 
+/// BridgePalletConfig represents the configuration for the Substrate bridge pallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BridgePalletConfig {
+    /// Controls the events watcher
+    #[serde(rename(serialize = "eventsWatcher"))]
+    pub events_watcher: EventsWatcherConfig,
+}
+
 /// Enumerates the supported different signing backends configurations.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -375,6 +1331,10 @@ pub enum ProposalSigningBackendConfig {
     DkgNode(DkgNodeProposalSigningBackendConfig),
     /// Uses the Private Key of the current Governor to sign proposals.
     Mocked(MockedProposalSigningBackendConfig),
+    /// Votes for the proposals on the bridge pallet of a Webb Protocol node, which
+    /// executes them once enough relayers voted for them.
+    #[serde(rename = "SubstrateBridge")]
+    SubstrateBridge(SubstrateBridgeProposalSigningBackendConfig),
 }
 
 /// DKGNodeSigningBackendConfig represents the configuration for the DKGNode signing backend.
@@ -387,6 +1347,18 @@ pub struct DkgNodeProposalSigningBackendConfig {
     pub node: String,
 }
 
+/// SubstrateBridgeProposalSigningBackendConfig represents the configuration for the
+/// Substrate Bridge signing backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SubstrateBridgeProposalSigningBackendConfig {
+    /// The name of the Webb Protocol node running the bridge pallet, its account votes
+    /// for the proposals.
+    ///
+    /// Must be defined in the config.
+    pub node: String,
+}
+
 /// MockedSigningBackendConfig represents the configuration for the Mocked signing backend.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -397,20 +1369,43 @@ pub struct MockedProposalSigningBackendConfig {
 }
 
 /// PrivateKey represents a private key.
+///
+/// The key bytes are wiped from memory when it is dropped, and it is never printed
+/// by `Debug` nor serialized (it does not implement `Serialize` on purpose).
 #[derive(Clone)]
-pub struct PrivateKey(Secret);
+pub struct PrivateKey(Zeroizing<[u8; 32]>);
 
-impl std::fmt::Debug for PrivateKey {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("PrivateKey").finish()
+impl PrivateKey {
+    /// The raw bytes of the private key.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
     }
-}
 
-impl std::ops::Deref for PrivateKey {
-    type Target = Secret;
+    /// Creates a private key from its raw bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(Zeroizing::new(bytes))
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// Parses a hex encoded private key (including the 0x prefix).
+    fn from_hex(value: &str) -> Result<Self, String> {
+        let hex = value
+            .strip_prefix("0x")
+            .ok_or_else(|| String::from("missing the 0x prefix"))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        hex::decode_to_slice(hex, &mut key[..]).map_err(|e| {
+            format!(
+                "{}\n got {} chars but expected a 66 chars string (including the 0x prefix)",
+                e,
+                value.len()
+            )
+        })?;
+        Ok(Self(key))
+    }
+}
+
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PrivateKey").finish()
     }
 }
 
@@ -421,7 +1416,7 @@ impl<'de> Deserialize<'de> for PrivateKey {
     {
         struct PrivateKeyVistor;
         impl<'de> serde::de::Visitor<'de> for PrivateKeyVistor {
-            type Value = Secret;
+            type Value = PrivateKey;
 
             fn expecting(
                 &self,
@@ -438,26 +1433,21 @@ impl<'de> Deserialize<'de> for PrivateKey {
             {
                 if value.starts_with("0x") {
                     // hex value
-                    let maybe_hex = Secret::from_str(value);
-                    match maybe_hex {
-                        Ok(val) => Ok(val),
-                        Err(e) => Err(serde::de::Error::custom(format!("{}\n got {} but expected a 66 string (including the 0x prefix)", e, value.len()))),
-                    }
+                    PrivateKey::from_hex(value)
+                        .map_err(serde::de::Error::custom)
                 } else if value.starts_with('$') {
                     // env
                     let var = value.strip_prefix('$').unwrap_or(value);
                     tracing::trace!("Reading {} from env", var);
-                    let val = std::env::var(var).map_err(|e| {
-                        serde::de::Error::custom(format!(
-                            "error while loading this env {}: {}",
-                            var, e,
-                        ))
-                    })?;
-                    let maybe_hex = Secret::from_str(&val);
-                    match maybe_hex {
-                        Ok(val) => Ok(val),
-                        Err(e) => Err(serde::de::Error::custom(format!("{}\n expected a 66 chars string (including the 0x prefix) but found {} char", e,  val.len()))),
-                    }
+                    let val = std::env::var(var).map(Zeroizing::new).map_err(
+                        |e| {
+                            serde::de::Error::custom(format!(
+                                "error while loading this env {}: {}",
+                                var, e,
+                            ))
+                        },
+                    )?;
+                    PrivateKey::from_hex(&val).map_err(serde::de::Error::custom)
                 } else if value.starts_with('>') {
                     todo!("Implement command execution to extract the private key")
                 } else {
@@ -466,26 +1456,33 @@ impl<'de> Deserialize<'de> for PrivateKey {
             }
         }
 
-        let secret = deserializer.deserialize_str(PrivateKeyVistor)?;
-        Ok(Self(secret))
+        deserializer.deserialize_str(PrivateKeyVistor)
     }
 }
 
+/// Suri is the secret of a Substrate account.
+///
+/// The underlying key pair wipes its secret key from memory when it is dropped,
+/// and it is never printed by `Debug` nor serialized.
+#[cfg(feature = "substrate")]
 #[derive(Clone)]
 pub struct Suri(Sr25519Pair);
 
+#[cfg(feature = "substrate")]
 impl std::fmt::Debug for Suri {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("SubstratePrivateKey").finish()
     }
 }
 
+#[cfg(feature = "substrate")]
 impl From<Suri> for Sr25519Pair {
     fn from(suri: Suri) -> Self {
         suri.0
     }
 }
 
+#[cfg(feature = "substrate")]
 impl std::ops::Deref for Suri {
     type Target = Sr25519Pair;
 
@@ -494,6 +1491,7 @@ impl std::ops::Deref for Suri {
     }
 }
 
+#[cfg(feature = "substrate")]
 impl<'de> Deserialize<'de> for Suri {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -520,12 +1518,14 @@ impl<'de> Deserialize<'de> for Suri {
                     // env
                     let var = value.strip_prefix('$').unwrap_or(value);
                     tracing::trace!("Reading {} from env", var);
-                    let val = std::env::var(var).map_err(|e| {
-                        serde::de::Error::custom(format!(
-                            "error while loading this env {}: {}",
-                            var, e,
-                        ))
-                    })?;
+                    let val = std::env::var(var).map(Zeroizing::new).map_err(
+                        |e| {
+                            serde::de::Error::custom(format!(
+                                "error while loading this env {}: {}",
+                                var, e,
+                            ))
+                        },
+                    )?;
                     let maybe_pair =
                         Sr25519Pair::from_string_with_seed(&val, None);
                     match maybe_pair {
@@ -632,6 +1632,56 @@ pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<WebbRelayerConfig> {
     }
 }
 
+/// Indexes the canonical names of the EVM chains by their lowercased aliases and chain ids.
+///
+/// An alias naming another chain, or two chains, is rejected. A chain id shared by two chains
+/// is ambiguous, so it is not indexed, they could still be named by their names.
+fn evm_chain_names(
+    evm: &HashMap<String, EvmChainConfig>,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut names = HashMap::new();
+    for (name, chain) in evm {
+        for alias in &chain.aliases {
+            let alias = alias.to_lowercase();
+            anyhow::ensure!(
+                alias == *name || !evm.contains_key(&alias),
+                "the alias {} of the chain {} is the name of another chain",
+                alias,
+                name
+            );
+            if let Some(other) = names.insert(alias.clone(), name.clone()) {
+                anyhow::ensure!(
+                    other == *name,
+                    "the alias {} names both the chains {} and {}",
+                    alias,
+                    other,
+                    name
+                );
+            }
+        }
+    }
+    let mut chain_ids = HashMap::<u64, Vec<&String>>::new();
+    for (name, chain) in evm {
+        chain_ids.entry(chain.chain_id).or_default().push(name);
+    }
+    for (chain_id, chains) in chain_ids {
+        match chains.as_slice() {
+            [name] => {
+                names
+                    .entry(chain_id.to_string())
+                    .or_insert_with(|| (*name).clone());
+            }
+            _ => tracing::warn!(
+                "!!WARNING!!: the chains {:?} share the chain id {}, \
+                they could only be named by their names.",
+                chains,
+                chain_id
+            ),
+        }
+    }
+    Ok(names)
+}
+
 /// The postloading_process exists to validate configuration and standardize
 /// the format of the configuration
 fn postloading_process(
@@ -639,6 +1689,38 @@ fn postloading_process(
 ) -> anyhow::Result<WebbRelayerConfig> {
     tracing::trace!("Checking configration sanity ...");
     tracing::trace!("postloaded config: {:?}", config);
+    // a limit of zero would not bound the commands of a connection at all.
+    anyhow::ensure!(
+        config.connections.max_concurrent_commands > 0,
+        "`max-concurrent-commands` of the `[connections]` must be at least 1"
+    );
+    // the runtime panics without threads.
+    anyhow::ensure!(
+        config.runtime.worker_threads != Some(0),
+        "`worker-threads` of the `[runtime]` must be at least 1"
+    );
+    anyhow::ensure!(
+        config.runtime.max_blocking_threads != Some(0),
+        "`max-blocking-threads` of the `[runtime]` must be at least 1"
+    );
+    // the channels panic with a zero capacity.
+    anyhow::ensure!(
+        config.channels.command_stream_capacity > 0,
+        "`command-stream-capacity` of the `[channels]` must be at least 1"
+    );
+    // the checkpoints would never wait for the contracts to move on.
+    if let Some(ref root_checkpoints) = config.root_checkpoints {
+        anyhow::ensure!(
+            root_checkpoints.interval > 0,
+            "`interval` of the `[root-checkpoints]` must be at least 1"
+        );
+    }
+    // the API keys are looked up by their hash, so a key could only have one name.
+    config.api_keys.index_keys();
+    anyhow::ensure!(
+        config.api_keys.names_by_hash.len() == config.api_keys.keys.len(),
+        "every `key` of the `[api-keys]` must be unique"
+    );
     // make all chain names lower case
     // 1. drain everything, and take enabled chains.
     let old_evm = config
@@ -647,17 +1729,84 @@ fn postloading_process(
         .filter(|(_, chain)| chain.enabled)
         .collect::<HashMap<_, _>>();
     // 2. insert them again, as lowercased.
-    for (k, v) in old_evm {
+    for (k, mut v) in old_evm {
+        // fill the settings of the events watchers from the chain profile.
+        let profile = v.profile;
+        let mut contracts = std::mem::take(&mut v.contracts);
+        for contract in contracts.iter_mut() {
+            let (withdraw_config, policy) = match contract {
+                Contract::Tornado(c) => {
+                    c.events_watcher.apply_profile(profile);
+                    (&mut c.withdraw_config, &v.fee_policies.tornado_withdraw)
+                }
+                Contract::Anchor(c) => {
+                    c.events_watcher.apply_profile(profile);
+                    (&mut c.withdraw_config, &v.fee_policies.anchor_withdraw)
+                }
+                Contract::SignatureBridge(c) => {
+                    c.events_watcher.apply_profile(profile);
+                    continue;
+                }
+                Contract::GovernanceBravoDelegate(_) => continue,
+            };
+            // and the withdraw settings the contract does not override from the chain.
+            withdraw_config
+                .apply_chain_defaults(v.beneficiary, policy)
+                .with_context(|| {
+                    format!(
+                        "contract {} on chain {}",
+                        contract.common().address,
+                        k
+                    )
+                })?;
+        }
+        v.contracts = contracts;
         config.evm.insert(k.to_lowercase(), v);
     }
     // do the same for substrate
-    let old_substrate = config
-        .substrate
-        .drain()
-        .filter(|(_, chain)| chain.enabled)
-        .collect::<HashMap<_, _>>();
-    for (k, v) in old_substrate {
-        config.substrate.insert(k.to_lowercase(), v);
+    #[cfg(feature = "substrate")]
+    {
+        let old_substrate = config
+            .substrate
+            .drain()
+            .filter(|(_, chain)| chain.enabled)
+            .collect::<HashMap<_, _>>();
+        for (k, v) in old_substrate {
+            config.substrate.insert(k.to_lowercase(), v);
+        }
+    }
+    // index the aliases and the chain ids of the chains, so they resolve to their names.
+    config.evm_chain_names = evm_chain_names(&config.evm)?;
+    // the linked anchors name their chains canonically.
+    let evm_chain_names = config.evm_chain_names.clone();
+    let canonical = |chain: &str| {
+        let chain = chain.to_lowercase();
+        evm_chain_names.get(&chain).cloned().unwrap_or(chain)
+    };
+    for chain_config in config.evm.values_mut() {
+        for contract in chain_config.contracts.iter_mut() {
+            if let Contract::Anchor(c) = contract {
+                for linked_anchor in c.linked_anchors.iter_mut() {
+                    linked_anchor.chain = canonical(&linked_anchor.chain);
+                }
+            }
+        }
+    }
+    // and for the chains of every identity.
+    for (identity_name, identity) in config.identities.iter_mut() {
+        let old_accounts = identity.evm.drain().collect::<Vec<_>>();
+        for (k, v) in old_accounts {
+            let chain = canonical(&k);
+            if !config.evm.contains_key(&chain) {
+                tracing::warn!(
+                    "!!WARNING!!: chain {} is not defined in the config, \
+                    but it is used by the identity {}.",
+                    chain,
+                    identity_name,
+                );
+            }
+            identity.evm.insert(chain, v);
+        }
     }
     // check that all required chains are already present in the config.
     for (chain_name, chain_config) in &config.evm {
@@ -668,6 +1817,20 @@ fn postloading_process(
         for anchor in anchors {
             for linked_anchor in &anchor.linked_anchors {
                 let chain = linked_anchor.chain.to_lowercase();
+                // the anchors of a Substrate node are resolved by its chain id.
+                #[cfg(feature = "substrate")]
+                if let Some(node) = config.substrate.get(&chain) {
+                    if node.chain_id.is_none()
+                        || linked_anchor.tree_id.is_none()
+                    {
+                        tracing::warn!(
+                            "!!WARNING!!: the anchor linked on the Substrate node {} to the Anchor Contract ({}) needs both the `chain-id` of the node and its `tree-id`.",
+                            chain,
+                            anchor.common.address,
+                        );
+                    }
+                    continue;
+                }
                 let chain_defined = config.evm.contains_key(&chain);
                 if !chain_defined {
                     tracing::warn!("!!WARNING!!: chain {} is not defined in the config.
@@ -683,3 +1846,167 @@ fn postloading_process(
     }
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_key_is_never_printed() {
+        let hex = format!("0x{}", "ab".repeat(32));
+        let key = PrivateKey::from_hex(&hex).unwrap();
+        assert_eq!(key.as_bytes(), &[0xab; 32][..]);
+        assert!(!format!("{:?}", key).contains("ab"));
+        assert!(PrivateKey::from_hex("0xabcd").is_err());
+    }
+
+    #[test]
+    fn chain_profile_fills_the_watcher_defaults() {
+        let mut config: EventsWatcherConfig =
+            serde_json::from_str(r#"{ "polling-interval": 3000 }"#).unwrap();
+        config.apply_profile(ChainProfile::HighThroughput);
+        assert_eq!(config.polling_interval(), 3_000);
+        assert_eq!(config.max_events_per_step(), 1_000);
+        assert_eq!(config.confirmations(), 128);
+    }
+
+    #[test]
+    fn watchers_follow_a_changed_profile() {
+        let mut config: EventsWatcherConfig =
+            serde_json::from_str(r#"{ "max-events-per-step": 500 }"#).unwrap();
+        config.apply_profile(ChainProfile::Standard);
+        config.change_profile(
+            ChainProfile::Standard,
+            ChainProfile::InstantFinality,
+        );
+        assert_eq!(config.polling_interval(), 1_000);
+        assert_eq!(config.confirmations(), 0);
+        // the configured settings are kept.
+        assert_eq!(config.max_events_per_step(), 500);
+    }
+
+    fn evm_chain(chain_id: u64, aliases: &[&str]) -> EvmChainConfig {
+        serde_json::from_value(serde_json::json!({
+            "http-endpoint": "http://localhost:8545",
+            "ws-endpoint": "ws://localhost:8545",
+            "chain-id": chain_id,
+            "private-key": format!("0x{}", "ab".repeat(32)),
+            "aliases": aliases,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn chains_resolve_by_their_aliases_and_chain_ids() {
+        let mut config = WebbRelayerConfig::default();
+        config
+            .evm
+            .insert("goerli".into(), evm_chain(5, &["Eth-Goerli"]));
+        config.evm.insert("hermes".into(), evm_chain(5001, &[]));
+        config.evm_chain_names = evm_chain_names(&config.evm).unwrap();
+        assert_eq!(config.canonical_evm_chain("Goerli"), Some("goerli"));
+        assert_eq!(config.canonical_evm_chain("eth-goerli"), Some("goerli"));
+        assert_eq!(config.canonical_evm_chain("5"), Some("goerli"));
+        assert_eq!(config.canonical_evm_chain("5001"), Some("hermes"));
+        assert_eq!(config.canonical_evm_chain("ropsten"), None);
+        // an alias could not name another chain.
+        config
+            .evm
+            .insert("athena".into(), evm_chain(5002, &["hermes"]));
+        assert!(evm_chain_names(&config.evm).is_err());
+    }
+
+    #[test]
+    fn contracts_fall_back_to_the_withdraw_settings_of_their_chain() {
+        let beneficiary = Some(Address::repeat_byte(1));
+        let policy = FeePolicyConfig {
+            fee_percentage: Some(0.05),
+            dynamic_fee: None,
+        };
+        let mut config: AnchorWithdrawConfig =
+            serde_json::from_str(r#"{ "withdraw-fee-percentage": 0.01 }"#)
+                .unwrap();
+        config.apply_chain_defaults(beneficiary, &policy).unwrap();
+        assert_eq!(config.withdraw_fee_percentage(), 0.01);
+        assert_eq!(config.beneficiary, Some(Address::repeat_byte(1)));
+        let json = r#"{ "beneficiary": "0x0000000000000000000000000000000000000002" }"#;
+        let mut config: AnchorWithdrawConfig =
+            serde_json::from_str(json).unwrap();
+        config.apply_chain_defaults(beneficiary, &policy).unwrap();
+        assert_eq!(config.withdraw_fee_percentage(), 0.05);
+        assert_eq!(config.beneficiary, Some(Address::repeat_byte(2)));
+        // without a fee percentage on the contract nor on the chain.
+        let mut config: AnchorWithdrawConfig =
+            serde_json::from_str("{}").unwrap();
+        let unset = FeePolicyConfig::default();
+        assert!(config.apply_chain_defaults(beneficiary, &unset).is_err());
+    }
+
+    #[test]
+    fn withdraws_are_estimated_unless_a_fixed_gas_limit_is_set() {
+        let json = r#"{ "withdraw-gaslimit": "0x350000" }"#;
+        let config: AnchorWithdrawConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.withdraw_gaslimit, Some(U256::from(0x350000)));
+        assert_eq!(config.fixed_gas_limit, None);
+        let json = r#"{ "withdraw-gaslimit": "0x350000", "fixed-gas-limit": "0x5B8D80" }"#;
+        let config: AnchorWithdrawConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.fixed_gas_limit, Some(U256::from(0x5B8D80)));
+    }
+
+    #[test]
+    fn contracts_sync_from_their_deployment_by_default() {
+        let json = r#"{ "address": "0x0000000000000000000000000000000000000001", "deployed-at": 10 }"#;
+        let config: CommonContractConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.sync_from, SyncFrom::DeployedAt);
+        let json = r#"{ "address": "0x0000000000000000000000000000000000000001", "deployed-at": 10, "sync-from": "latest" }"#;
+        let config: CommonContractConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.sync_from, SyncFrom::Latest);
+        assert!(!config.force_resync);
+    }
+
+    #[test]
+    fn commands_of_a_connection_are_always_bounded() {
+        let mut config = WebbRelayerConfig::default();
+        config.connections.max_concurrent_commands = 0;
+        assert!(postloading_process(config).is_err());
+        assert!(postloading_process(WebbRelayerConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn runtimes_have_threads() {
+        let mut config = WebbRelayerConfig::default();
+        config.runtime.worker_threads = Some(0);
+        assert!(postloading_process(config).is_err());
+        let mut config = WebbRelayerConfig::default();
+        config.runtime.max_blocking_threads = Some(0);
+        assert!(postloading_process(config).is_err());
+        let mut config = WebbRelayerConfig::default();
+        config.runtime.worker_threads = Some(1);
+        assert!(postloading_process(config).is_ok());
+    }
+
+    #[test]
+    fn command_streams_have_a_capacity() {
+        let mut config = WebbRelayerConfig::default();
+        config.channels.command_stream_capacity = 0;
+        assert!(postloading_process(config).is_err());
+    }
+
+    #[test]
+    fn root_checkpoints_have_an_interval() {
+        let mut config = WebbRelayerConfig::default();
+        config.root_checkpoints = Some(RootCheckpointsConfig {
+            interval: 0,
+            webhook: None,
+        });
+        assert!(postloading_process(config).is_err());
+    }
+
+    #[test]
+    fn forced_resyncs_start_from_the_deployment() {
+        let json = r#"{ "address": "0x0000000000000000000000000000000000000001", "deployed-at": 10, "sync-from": "latest", "force-resync": true }"#;
+        let config: CommonContractConfig = serde_json::from_str(json).unwrap();
+        assert!(config.force_resync);
+        assert_eq!(config.sync_from(), SyncFrom::DeployedAt);
+    }
+}
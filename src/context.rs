@@ -18,17 +18,40 @@
 //! # Relayer Context Module 🕸️
 //!
 //! A module for managing the context of the relayer.
-use std::convert::TryFrom;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
-use tokio::sync::broadcast;
+use ethereum_types::{Address, H256, U256};
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::{
+    broadcast, OwnedRwLockReadGuard, OwnedSemaphorePermit, Semaphore,
+};
+#[cfg(feature = "evm")]
 use webb::evm::ethers::core::k256::SecretKey;
+#[cfg(feature = "evm")]
 use webb::evm::ethers::prelude::*;
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt;
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt::sp_core::sr25519::Pair as Sr25519Pair;
 
 use crate::config;
+use crate::proposals::{evm_typed_chain_id, TypedChainId};
+#[cfg(feature = "evm")]
+use crate::rate_limit::{HttpProvider, RateLimitedHttp, RateLimiter};
+use crate::store::BridgeKey;
+#[cfg(feature = "evm")]
+use crate::store::KeyRotationStore;
+#[cfg(feature = "zk-verifier")]
+use crate::tx_relay::evm::verifier::VerifyingKeys;
+use crate::tx_relay::session::RelaySessions;
+
+/// How long connecting to a Substrate node is retried, before giving up.
+#[cfg(feature = "substrate")]
+pub const SUBSTRATE_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 /// RelayerContext contains Relayer's configuration and shutdown signal.
 #[derive(Clone)]
 pub struct RelayerContext {
@@ -42,25 +65,310 @@ pub struct RelayerContext {
     /// the broadcast::Sender. Each active connection receives it, reaches a
     /// safe terminal state, and completes the task.
     notify_shutdown: broadcast::Sender<()>,
+    /// When the relayer started.
+    started_at: SystemTime,
+    /// The typed chain ids of the chains that are paused by the admin, the EVM chains
+    /// and the Substrate nodes.
+    ///
+    /// While a chain is paused, the relayer does not accept relay commands
+    /// and does not broadcast transactions from the queue for that chain.
+    paused_chains: Arc<RwLock<HashSet<TypedChainId>>>,
+    /// The Signature Bridges that are paused on-chain, as last seen from their events.
+    ///
+    /// While a bridge is paused, its commands are held in its queue, and the transactions
+    /// sent to it are held in the transaction queue of its chain.
+    paused_bridges: Arc<RwLock<HashSet<BridgeKey>>>,
+    /// Whether this instance is the leader of its deployment, in the high-availability mode.
+    ///
+    /// A standby does not accept relay commands and does not broadcast transactions,
+    /// it is always the leader outside of the high-availability mode.
+    leader: Arc<AtomicBool>,
+    /// The sessions of the running relay jobs, that clients can resume.
+    sessions: RelaySessions,
+    /// The rate limiters of the RPC endpoints, by their URL.
+    ///
+    /// Every provider of the same endpoint shares its limiter, so all the watchers
+    /// together stay under the limit of the endpoint.
+    #[cfg(feature = "evm")]
+    rate_limiters: Arc<Mutex<HashMap<url::Url, Arc<RateLimiter>>>>,
+    /// The semaphores bounding the relay commands in their submission phase, by chain id.
+    relay_permits: Arc<Mutex<HashMap<u64, Arc<Semaphore>>>>,
+    /// The withdraws being relayed, by chain id, contract address and nullifier hash.
+    queued_withdrawals: Arc<Mutex<HashSet<(u64, Address, H256)>>>,
+    /// The signing keys rotated by the admin, replacing the revoked ones.
+    #[cfg(feature = "evm")]
+    key_rotations: KeyRotations,
+    /// The locks of the transactions in flight on a chain, by chain name.
+    ///
+    /// The relays and the tx queue hold a read guard while their transaction is in flight,
+    /// a key rotation takes the write guard, so it waits for them to be done.
+    signing_locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::RwLock<()>>>>>,
+    /// The verifying keys of the withdraw circuits, loaded the first time they are needed.
+    #[cfg(feature = "zk-verifier")]
+    verifying_keys: VerifyingKeys,
 }
 
 impl RelayerContext {
     /// Creates a new RelayerContext.
     pub fn new(config: config::WebbRelayerConfig) -> Self {
         let (notify_shutdown, _) = broadcast::channel(2);
+        let sessions =
+            RelaySessions::new(config.channels.command_stream_capacity);
+        let leader = Arc::new(AtomicBool::new(config.ha.is_none()));
         Self {
             config,
             notify_shutdown,
+            started_at: SystemTime::now(),
+            paused_chains: Default::default(),
+            paused_bridges: Default::default(),
+            leader,
+            sessions,
+            #[cfg(feature = "evm")]
+            rate_limiters: Default::default(),
+            relay_permits: Default::default(),
+            queued_withdrawals: Default::default(),
+            #[cfg(feature = "evm")]
+            key_rotations: Default::default(),
+            signing_locks: Default::default(),
+            #[cfg(feature = "zk-verifier")]
+            verifying_keys: Default::default(),
         }
     }
     /// Returns a broadcast receiver handle for the shutdown signal.
     pub fn shutdown_signal(&self) -> Shutdown {
         Shutdown::new(self.notify_shutdown.subscribe())
     }
+    /// Returns when the relayer started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+    /// Returns the verifying keys of the withdraw circuits.
+    #[cfg(feature = "zk-verifier")]
+    pub fn verifying_keys(&self) -> &VerifyingKeys {
+        &self.verifying_keys
+    }
+    /// Returns the sessions of the running relay jobs.
+    pub fn sessions(&self) -> &RelaySessions {
+        &self.sessions
+    }
     /// Sends a shutdown signal to all subscribed tasks/connections.
     pub fn shutdown(&self) {
         let _ = self.notify_shutdown.send(());
     }
+    /// Pauses the chain with the given typed chain id.
+    ///
+    /// Returns `false` if the chain was already paused.
+    pub fn pause_chain(&self, chain_id: TypedChainId) -> bool {
+        self.paused_chains.write().insert(chain_id)
+    }
+    /// Resumes the chain with the given typed chain id.
+    ///
+    /// Returns `false` if the chain was not paused.
+    pub fn resume_chain(&self, chain_id: TypedChainId) -> bool {
+        self.paused_chains.write().remove(&chain_id)
+    }
+    /// Returns true if the chain with the given typed chain id is paused.
+    pub fn is_chain_paused(&self, chain_id: TypedChainId) -> bool {
+        self.paused_chains.read().contains(&chain_id)
+    }
+    /// Returns true if the EVM chain with the given chain id is paused.
+    pub fn is_evm_chain_paused(&self, chain_id: U256) -> bool {
+        evm_typed_chain_id(chain_id)
+            .map_or(false, |chain_id| self.is_chain_paused(chain_id))
+    }
+    /// Returns true if that Substrate node is paused, only the nodes with a
+    /// configured `chain-id` could be.
+    pub fn is_substrate_node_paused(&self, node_name: &str) -> bool {
+        self.config
+            .substrate
+            .get(node_name)
+            .and_then(|node| node.chain_id)
+            .map_or(false, |chain_id| {
+                self.is_chain_paused(TypedChainId::Substrate(chain_id))
+            })
+    }
+    /// Marks that Signature Bridge as paused or unpaused.
+    ///
+    /// Returns `false` if the bridge was already in that state.
+    pub fn set_bridge_paused(&self, key: BridgeKey, paused: bool) -> bool {
+        let mut bridges = self.paused_bridges.write();
+        if paused {
+            bridges.insert(key)
+        } else {
+            bridges.remove(&key)
+        }
+    }
+    /// Returns true if that Signature Bridge is paused, as last seen from its events.
+    pub fn is_bridge_paused(&self, key: BridgeKey) -> bool {
+        self.paused_bridges.read().contains(&key)
+    }
+    /// Returns true if this instance is the leader of its deployment, see [`config::HaConfig`].
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+    /// Promotes this instance to the leader of its deployment, or demotes it to a standby.
+    ///
+    /// Returns whether it was the leader.
+    pub fn set_leader(&self, leader: bool) -> bool {
+        self.leader.swap(leader, Ordering::SeqCst)
+    }
+    /// Waits for a turn to estimate and submit a relay transaction on that EVM chain.
+    ///
+    /// At most `max-concurrent-relays` permits of a chain are held at the same time, the
+    /// others are queued in the order they asked. The permit should be dropped once the
+    /// transaction is submitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The configuration of the chain.
+    pub async fn evm_relay_permit(
+        &self,
+        chain: &config::EvmChainConfig,
+    ) -> OwnedSemaphorePermit {
+        let semaphore = self
+            .relay_permits
+            .lock()
+            .entry(chain.chain_id)
+            .or_insert_with(|| {
+                Arc::new(Semaphore::new(chain.max_concurrent_relays.max(1)))
+            })
+            .clone();
+        if semaphore.available_permits() == 0 {
+            tracing::debug!(
+                chain_id = chain.chain_id,
+                "Waiting for the running relays on the chain to be submitted"
+            );
+        }
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("relay semaphores are never closed")
+    }
+    /// Holds the signing key of that EVM chain while a transaction signed with it is in flight.
+    ///
+    /// A key rotation waits for all the guards of the chain to be dropped. The guards asked
+    /// for once a rotation is waiting are only given after it, so they get the new key.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_name` - A string representing the chain name.
+    pub async fn evm_signing_guard(
+        &self,
+        chain_name: &str,
+    ) -> OwnedRwLockReadGuard<()> {
+        self.signing_lock(chain_name).read_owned().await
+    }
+    /// Replaces the signing key of that EVM chain, once the transactions in flight signed
+    /// with the current one are done, see [`Self::evm_signing_guard`].
+    ///
+    /// The old key is revoked on that chain, wherever it is used: by the chain itself, by
+    /// the identities, or for signing the proposals. The rotation is recorded in the store,
+    /// so it outlives a restart of the relayer.
+    ///
+    /// Returns the addresses of the old key and of the new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the rotation is recorded in.
+    /// * `chain_name` - A string representing the chain name.
+    /// * `key` - The new private key of the chain.
+    #[cfg(feature = "evm")]
+    pub async fn rotate_evm_key<S: KeyRotationStore>(
+        &self,
+        store: &S,
+        chain_name: &str,
+        key: config::PrivateKey,
+    ) -> Result<(Address, Address), KeyRotationError> {
+        let chain_id = self
+            .config
+            .evm
+            .get(chain_name)
+            .context(format!("Chain {} not configured or enabled", chain_name))?
+            .chain_id;
+        let new_address = key_address(&key)
+            .map_err(|e| KeyRotationError::InvalidKey(e.to_string()))?;
+        let lock = self.signing_lock(chain_name);
+        let _drained = lock.write().await;
+        if self.key_rotations.is_revoked(chain_id, new_address) {
+            return Err(KeyRotationError::Revoked(new_address));
+        }
+        let old_address = self.evm_wallet(chain_name).await?.address();
+        if old_address == new_address {
+            return Ok((old_address, new_address));
+        }
+        store.insert_key_rotation(chain_id, old_address, &key)?;
+        self.key_rotations.insert(chain_id, old_address, key);
+        Ok((old_address, new_address))
+    }
+    /// Restores the signing keys rotated by the admin, before the relayer got restarted.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the rotations are recorded in.
+    #[cfg(feature = "evm")]
+    pub fn restore_key_rotations<S: KeyRotationStore>(
+        &self,
+        store: &S,
+    ) -> anyhow::Result<()> {
+        for (chain_id, revoked, key) in store.key_rotations()? {
+            self.key_rotations.insert(chain_id, revoked, key);
+        }
+        Ok(())
+    }
+    /// Returns the signing keys rotated by the admin, shared with this context.
+    #[cfg(feature = "evm")]
+    pub fn key_rotations(&self) -> KeyRotations {
+        self.key_rotations.clone()
+    }
+    /// Returns the signing key of that EVM chain, the configured one unless it is revoked.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_name` - A string representing the chain name.
+    #[cfg(feature = "evm")]
+    pub fn evm_key(
+        &self,
+        chain_name: &str,
+    ) -> anyhow::Result<config::PrivateKey> {
+        let chain_config = self.config.evm.get(chain_name).context(format!(
+            "Chain {} not configured or enabled",
+            chain_name
+        ))?;
+        self.key_rotations
+            .resolve(chain_config.chain_id, &chain_config.private_key)
+    }
+    fn signing_lock(&self, chain_name: &str) -> Arc<tokio::sync::RwLock<()>> {
+        self.signing_locks
+            .lock()
+            .entry(chain_name.to_owned())
+            .or_default()
+            .clone()
+    }
+    /// Claims the withdraw spending that nullifier on that contract, for relaying it.
+    ///
+    /// Returns `None` if that withdraw is already being relayed, otherwise the claim is
+    /// held until the returned guard is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_id` - The chain id of the chain.
+    /// * `contract` - The address of the contract.
+    /// * `nullifier_hash` - The nullifier hash spent by the withdraw.
+    pub fn claim_withdrawal(
+        &self,
+        chain_id: u64,
+        contract: Address,
+        nullifier_hash: H256,
+    ) -> Option<QueuedWithdrawal> {
+        let key = (chain_id, contract, nullifier_hash);
+        if !self.queued_withdrawals.lock().insert(key) {
+            return None;
+        }
+        Some(QueuedWithdrawal {
+            queued: self.queued_withdrawals.clone(),
+            key,
+        })
+    }
     /// Returns a new `EthereumProvider` for the relayer.
     ///
     /// # Arguments
@@ -73,16 +381,30 @@ impl RelayerContext {
     /// let chain_name = "mainnet".to_string();
     /// let provider = ctx.evm_provider(chain_name).await?;
     /// ```
+    #[cfg(feature = "evm")]
     pub async fn evm_provider(
         &self,
         chain_name: &str,
-    ) -> anyhow::Result<Provider<Http>> {
+    ) -> anyhow::Result<HttpProvider> {
         let chain_config = self.config.evm.get(chain_name).context(format!(
             "Chain {} not configured or enabled",
             chain_name
         ))?;
-        let provider = Provider::try_from(chain_config.http_endpoint.as_str())?
-            .interval(Duration::from_millis(5u64));
+        let endpoint = &chain_config.http_endpoint;
+        let limiter = chain_config.rate_limit.as_ref().map(|c| {
+            let burst = c.burst.unwrap_or(c.requests_per_second);
+            self.rate_limiters
+                .lock()
+                .entry(endpoint.clone())
+                .or_insert_with(|| {
+                    Arc::new(RateLimiter::new(c.requests_per_second, burst))
+                })
+                .clone()
+        });
+        let transport =
+            RateLimitedHttp::new(Http::new(endpoint.clone()), limiter);
+        let provider =
+            Provider::new(transport).interval(Duration::from_millis(5u64));
         Ok(provider)
     }
     /// Sets up and returns an EVM wallet for the relayer.
@@ -97,6 +419,7 @@ impl RelayerContext {
     /// let chain_name = "mainnet".to_string();
     /// let wallet = self.ctx.evm_wallet(&self.chain_name).await?;
     /// ```
+    #[cfg(feature = "evm")]
     pub async fn evm_wallet(
         &self,
         chain_name: &str,
@@ -105,13 +428,56 @@ impl RelayerContext {
             "Chain {} not configured or enabled",
             chain_name
         ))?;
-        let key = SecretKey::from_bytes(chain_config.private_key.as_bytes())?;
+        // the key rotated by the admin replaces the configured one.
+        let key = self.evm_key(chain_name)?;
+        let key = SecretKey::from_bytes(key.as_bytes())?;
         let chain_id = chain_config.chain_id;
         let wallet = LocalWallet::from(key).with_chain_id(chain_id);
         Ok(wallet)
     }
+    /// Sets up and returns the EVM wallet of a relayer identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - A string representing the identity name.
+    /// * `chain_name` - A string representing the chain name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let wallet = ctx.evm_identity_wallet("my-dapp", "mainnet").await?;
+    /// ```
+    #[cfg(feature = "evm")]
+    pub async fn evm_identity_wallet(
+        &self,
+        identity: &str,
+        chain_name: &str,
+    ) -> anyhow::Result<LocalWallet> {
+        let chain_config = self.config.evm.get(chain_name).context(format!(
+            "Chain {} not configured or enabled",
+            chain_name
+        ))?;
+        let account = self
+            .config
+            .identities
+            .get(identity)
+            .context(format!("Identity {} not configured", identity))?
+            .evm
+            .get(chain_name)
+            .context(format!(
+                "Identity {} has no account on chain {}",
+                identity, chain_name
+            ))?;
+        let chain_id = chain_config.chain_id;
+        let key = self.key_rotations.resolve(chain_id, &account.private_key)?;
+        let key = SecretKey::from_bytes(key.as_bytes())?;
+        let wallet = LocalWallet::from(key).with_chain_id(chain_id);
+        Ok(wallet)
+    }
     /// Sets up and returns a Substrate client for the relayer.
     ///
+    /// Connecting is retried with an exponential backoff, for up to [`SUBSTRATE_CONNECT_TIMEOUT`].
+    ///
     /// # Arguments
     ///
     /// * `node_name` - A string representing the node name.
@@ -122,6 +488,7 @@ impl RelayerContext {
     /// let node_name = "dkg_node".to_string();
     /// let client = ctx.substrate_provider::<subxt::DefaultConfig>(node_name).await?;
     /// ```
+    #[cfg(feature = "substrate")]
     pub async fn substrate_provider<C: subxt::Config>(
         &self,
         node_name: &str,
@@ -131,14 +498,33 @@ impl RelayerContext {
             .substrate
             .get(node_name)
             .context(format!("Node {} not configured or enabled", node_name))?;
-        let client = subxt::ClientBuilder::new()
-            .set_url(node_config.ws_endpoint.as_str())
-            .build()
-            .await
-            .context(format!(
-                "connecting to {} using {}",
-                node_name, node_config.ws_endpoint
-            ))?;
+        // the node could be restarting, or its websocket could have dropped, so
+        // keep trying for a while before giving up.
+        let backoff = backoff::ExponentialBackoff {
+            max_elapsed_time: Some(SUBSTRATE_CONNECT_TIMEOUT),
+            ..Default::default()
+        };
+        let task = || async {
+            subxt::ClientBuilder::new()
+                .set_url(node_config.ws_endpoint.as_str())
+                .build()
+                .await
+                .map_err(|e| {
+                    tracing::warn!(
+                        "Failed to connect to {}, retrying: {}",
+                        node_name,
+                        e
+                    );
+                    backoff::Error::transient(e)
+                })
+        };
+        let client =
+            backoff::future::retry(backoff, task)
+                .await
+                .context(format!(
+                    "connecting to {} using {}",
+                    node_name, node_config.ws_endpoint
+                ))?;
         Ok(client)
     }
     /// Sets up and returns a Substrate wallet for the relayer.
@@ -153,6 +539,7 @@ impl RelayerContext {
     /// let node_name = "dkg_node".to_string();
     /// let pair = ctx.substrate_wallet(node_name).await?;
     /// ```
+    #[cfg(feature = "substrate")]
     pub async fn substrate_wallet(
         &self,
         node_name: &str,
@@ -167,6 +554,19 @@ impl RelayerContext {
     }
 }
 
+/// The claim of a withdraw being relayed, released when dropped.
+#[derive(Debug)]
+pub struct QueuedWithdrawal {
+    queued: Arc<Mutex<HashSet<(u64, Address, H256)>>>,
+    key: (u64, Address, H256),
+}
+
+impl Drop for QueuedWithdrawal {
+    fn drop(&mut self) {
+        self.queued.lock().remove(&self.key);
+    }
+}
+
 /// Listens for the server shutdown signal.
 ///
 /// Shutdown is signalled using a `broadcast::Receiver`. Only a single value is
@@ -209,3 +609,235 @@ impl Shutdown {
         self.shutdown = true;
     }
 }
+
+/// The reasons a rotation of a signing key gets refused.
+#[cfg(feature = "evm")]
+#[derive(Debug, thiserror::Error)]
+pub enum KeyRotationError {
+    /// The new key is not a valid private key.
+    #[error("invalid private key: {0}")]
+    InvalidKey(String),
+    /// The new key was revoked on that chain by an earlier rotation.
+    #[error("the key of {0:?} is revoked on this chain")]
+    Revoked(Address),
+    /// The rotation could not be recorded.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// The signing keys rotated by the admin, by chain id and the address of the revoked key.
+///
+/// A rotation revokes the old key on its chain wherever it is used, the revoked keys are
+/// never rotated in again.
+#[cfg(feature = "evm")]
+#[derive(Clone, Default)]
+pub struct KeyRotations(
+    Arc<RwLock<HashMap<(u64, Address), config::PrivateKey>>>,
+);
+
+#[cfg(feature = "evm")]
+impl KeyRotations {
+    /// Returns the key replacing that key on that chain, following the rotations,
+    /// or that key if it is not revoked.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_id` - The chain id of the chain.
+    /// * `key` - The configured key.
+    pub fn resolve(
+        &self,
+        chain_id: u64,
+        key: &config::PrivateKey,
+    ) -> anyhow::Result<config::PrivateKey> {
+        let rotations = self.0.read();
+        let mut key = key.clone();
+        for _ in 0..=rotations.len() {
+            match rotations.get(&(chain_id, key_address(&key)?)) {
+                Some(v) => key = v.clone(),
+                None => return Ok(key),
+            }
+        }
+        anyhow::bail!("the key rotations of chain {} are looping", chain_id)
+    }
+
+    fn is_revoked(&self, chain_id: u64, address: Address) -> bool {
+        self.0.read().contains_key(&(chain_id, address))
+    }
+
+    fn insert(&self, chain_id: u64, revoked: Address, key: config::PrivateKey) {
+        self.0.write().insert((chain_id, revoked), key);
+    }
+}
+
+#[cfg(feature = "evm")]
+fn key_address(key: &config::PrivateKey) -> anyhow::Result<Address> {
+    let key = SecretKey::from_bytes(key.as_bytes())?;
+    Ok(LocalWallet::from(key).address())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "evm")]
+    use crate::store::sled::SledStore;
+
+    #[tokio::test]
+    async fn relays_beyond_the_limit_wait_for_their_turn() {
+        let json = format!(
+            r#"{{
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 5001,
+                "private-key": "0x{}",
+                "max-concurrent-relays": 1
+            }}"#,
+            "ab".repeat(32)
+        );
+        let chain: config::EvmChainConfig =
+            serde_json::from_str(&json).unwrap();
+        let ctx = RelayerContext::new(Default::default());
+        let permit = ctx.evm_relay_permit(&chain).await;
+        let waiting = Duration::from_millis(50);
+        let queued =
+            tokio::time::timeout(waiting, ctx.evm_relay_permit(&chain)).await;
+        assert!(queued.is_err());
+        drop(permit);
+        let next =
+            tokio::time::timeout(waiting, ctx.evm_relay_permit(&chain)).await;
+        assert!(next.is_ok());
+    }
+
+    #[test]
+    fn bridges_are_paused_until_unpaused() {
+        let ctx = RelayerContext::new(Default::default());
+        let key = BridgeKey::new(
+            Address::random(),
+            crate::proposals::TypedChainId::Evm(1),
+        );
+        assert!(!ctx.is_bridge_paused(key));
+        assert!(ctx.set_bridge_paused(key, true));
+        assert!(!ctx.set_bridge_paused(key, true));
+        assert!(ctx.is_bridge_paused(key));
+        // every clone of the context shares the paused bridges.
+        assert!(ctx.clone().is_bridge_paused(key));
+        assert!(ctx.set_bridge_paused(key, false));
+        assert!(!ctx.set_bridge_paused(key, false));
+        assert!(!ctx.is_bridge_paused(key));
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test]
+    async fn a_key_rotation_waits_for_the_txs_in_flight() {
+        let json = format!(
+            r#"{{
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 5001,
+                "private-key": "0x{}"
+            }}"#,
+            "ab".repeat(32)
+        );
+        let mut config = config::WebbRelayerConfig::default();
+        config
+            .evm
+            .insert("hermes".into(), serde_json::from_str(&json).unwrap());
+        let ctx = RelayerContext::new(config);
+        let old_address = ctx.evm_wallet("hermes").await.unwrap().address();
+        let key: config::PrivateKey =
+            serde_json::from_str(&format!(r#""0x{}""#, "cd".repeat(32)))
+                .unwrap();
+
+        let store = SledStore::temporary().unwrap();
+
+        let in_flight = ctx.evm_signing_guard("hermes").await;
+        let waiting = Duration::from_millis(50);
+        let rotation = ctx.rotate_evm_key(&store, "hermes", key.clone());
+        tokio::pin!(rotation);
+        assert!(tokio::time::timeout(waiting, &mut rotation).await.is_err());
+        assert_eq!(
+            ctx.evm_wallet("hermes").await.unwrap().address(),
+            old_address
+        );
+        drop(in_flight);
+        let (old, new) = rotation.await.unwrap();
+        assert_eq!(old, old_address);
+        assert_ne!(new, old_address);
+        assert_eq!(ctx.evm_wallet("hermes").await.unwrap().address(), new);
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test]
+    async fn a_revoked_key_stays_revoked() {
+        let json = format!(
+            r#"{{
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 5001,
+                "private-key": "0x{}"
+            }}"#,
+            "ab".repeat(32)
+        );
+        let mut config = config::WebbRelayerConfig::default();
+        config
+            .evm
+            .insert("hermes".into(), serde_json::from_str(&json).unwrap());
+        let revoked = config.evm["hermes"].private_key.clone();
+        let key = config::PrivateKey::from_bytes([0xcd; 32]);
+        let store = SledStore::temporary().unwrap();
+        let ctx = RelayerContext::new(config.clone());
+        let (_, new) = ctx.rotate_evm_key(&store, "hermes", key).await.unwrap();
+        // the revoked key is not rotated in again.
+        let rotation = ctx.rotate_evm_key(&store, "hermes", revoked).await;
+        assert!(matches!(rotation, Err(KeyRotationError::Revoked(_))));
+        // every use of the revoked key on that chain gets the new one.
+        let account = key_address(
+            &ctx.key_rotations()
+                .resolve(5001, &config.evm["hermes"].private_key)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(account, new);
+        // once restarted, the rotated key is still used.
+        let ctx = RelayerContext::new(config);
+        ctx.restore_key_rotations(&store).unwrap();
+        assert_eq!(ctx.evm_wallet("hermes").await.unwrap().address(), new);
+    }
+
+    #[test]
+    fn standbys_do_not_lead_until_elected() {
+        let ctx = RelayerContext::new(Default::default());
+        assert!(ctx.is_leader());
+
+        let ha = serde_json::from_str(
+            r#"{ "redis-url": "redis://127.0.0.1:6379" }"#,
+        )
+        .unwrap();
+        let config = config::WebbRelayerConfig {
+            ha: Some(ha),
+            ..Default::default()
+        };
+        let ctx = RelayerContext::new(config);
+        assert!(!ctx.is_leader());
+        assert!(!ctx.set_leader(true));
+        assert!(ctx.is_leader());
+    }
+
+    #[test]
+    fn a_withdrawal_is_claimed_once_at_a_time() {
+        let ctx = RelayerContext::new(Default::default());
+        let contract = Address::random();
+        let nullifier_hash = H256::random();
+        let claim = ctx.claim_withdrawal(5001, contract, nullifier_hash);
+        assert!(claim.is_some());
+        assert!(ctx
+            .claim_withdrawal(5001, contract, nullifier_hash)
+            .is_none());
+        assert!(ctx
+            .claim_withdrawal(5002, contract, nullifier_hash)
+            .is_some());
+        drop(claim);
+        assert!(ctx
+            .claim_withdrawal(5001, contract, nullifier_hash)
+            .is_some());
+    }
+}
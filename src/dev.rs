@@ -0,0 +1,278 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fmt::{self, Display};
+
+use ethereum_types::U256;
+use webb::evm::ethers::providers::{JsonRpcClient, Middleware};
+use webb::evm::ethers::signers::Signer;
+
+use crate::config::{ChainProfile, Contract, WebbRelayerConfig};
+use crate::context::RelayerContext;
+
+/// The balance the relayer's accounts get funded with on the local nodes, 100 of the native token.
+pub const DEV_BALANCE: u128 = 100_000_000_000_000_000_000;
+
+/// The local development nodes the relayer could detect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevNode {
+    /// A Foundry Anvil node.
+    Anvil,
+    /// A Hardhat Network node.
+    Hardhat,
+    /// A Ganache (or the older TestRPC) node.
+    Ganache,
+}
+
+impl DevNode {
+    /// Detects the local node from its `web3_clientVersion`, if it is one.
+    pub fn from_client_version(version: &str) -> Option<Self> {
+        let version = version.to_lowercase();
+        if version.starts_with("anvil") {
+            Some(Self::Anvil)
+        } else if version.starts_with("hardhatnetwork") {
+            Some(Self::Hardhat)
+        } else if version.contains("ganache") || version.contains("testrpc") {
+            Some(Self::Ganache)
+        } else {
+            None
+        }
+    }
+
+    /// The RPC method of the node that sets the balance of an account.
+    fn set_balance_method(&self) -> &'static str {
+        match self {
+            Self::Anvil => "anvil_setBalance",
+            Self::Hardhat => "hardhat_setBalance",
+            Self::Ganache => "evm_setAccountBalance",
+        }
+    }
+}
+
+impl Display for DevNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Anvil => write!(f, "Anvil"),
+            Self::Hardhat => write!(f, "Hardhat"),
+            Self::Ganache => write!(f, "Ganache"),
+        }
+    }
+}
+
+/// Detects the enabled EVM chains that are running on a local development node.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+pub async fn detect(
+    ctx: &RelayerContext,
+) -> anyhow::Result<Vec<(String, DevNode)>> {
+    let mut found = Vec::new();
+    let chains = ctx.config.evm.iter().filter(|(_, c)| c.enabled);
+    for (chain_name, _) in chains {
+        let provider = ctx.evm_provider(chain_name).await?;
+        match provider.client_version().await {
+            Ok(version) => match DevNode::from_client_version(&version) {
+                Some(node) => found.push((chain_name.clone(), node)),
+                None => tracing::debug!(
+                    "Chain {} is not a local node ({})",
+                    chain_name,
+                    version
+                ),
+            },
+            Err(e) => tracing::warn!(
+                "Failed to detect the node of chain {}: {}",
+                chain_name,
+                e
+            ),
+        }
+    }
+    Ok(found)
+}
+
+/// Relaxes the configuration of the chains running on a local node: their blocks are final once
+/// mined, and their gas is free, so the events are handled without confirmations and the
+/// fee policies are disabled.
+pub fn relax(config: &mut WebbRelayerConfig, chains: &[(String, DevNode)]) {
+    for (chain_name, node) in chains {
+        let chain = match config.evm.get_mut(chain_name) {
+            Some(v) => v,
+            None => continue,
+        };
+        // the watchers got their settings from the profile of the chain when the
+        // configuration got loaded, they follow the new one.
+        let old_profile = std::mem::replace(
+            &mut chain.profile,
+            ChainProfile::InstantFinality,
+        );
+        chain.dynamic_fee = None;
+        chain.fee_policies.tornado_withdraw.dynamic_fee = None;
+        chain.fee_policies.anchor_withdraw.dynamic_fee = None;
+        chain.profitability_guard = None;
+        // the older Ganache nodes reject the typed transactions.
+        if *node == DevNode::Ganache {
+            chain.legacy_tx = true;
+        }
+        for contract in chain.contracts.iter_mut() {
+            let events_watcher = match contract {
+                Contract::Tornado(c) => &mut c.events_watcher,
+                Contract::Anchor(c) => &mut c.events_watcher,
+                Contract::SignatureBridge(c) => &mut c.events_watcher,
+                Contract::GovernanceBravoDelegate(_) => continue,
+            };
+            events_watcher.change_profile(old_profile, chain.profile);
+            events_watcher.confirmations = Some(0);
+        }
+    }
+}
+
+/// Funds the relayer's account on that local node, with [`DEV_BALANCE`].
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_name` - The name of the chain
+/// * `node` - The local node of the chain
+pub async fn fund(
+    ctx: &RelayerContext,
+    chain_name: &str,
+    node: DevNode,
+) -> anyhow::Result<()> {
+    let provider = ctx.evm_provider(chain_name).await?;
+    let wallet = ctx.evm_wallet(chain_name).await?;
+    let balance = format!("{:#x}", U256::from(DEV_BALANCE));
+    JsonRpcClient::request::<_, serde_json::Value>(
+        provider.as_ref(),
+        node.set_balance_method(),
+        (wallet.address(), balance),
+    )
+    .await?;
+    tracing::info!(
+        "Funded the relayer's account {:?} on chain {}",
+        wallet.address(),
+        chain_name
+    );
+    Ok(())
+}
+
+/// Returns example commands, ready to be pasted, for trying the relayer on the local chains.
+pub fn example_commands(
+    config: &WebbRelayerConfig,
+    chains: &[(String, DevNode)],
+) -> Vec<String> {
+    let api = format!("http://localhost:{}/api/v1", config.port);
+    let mut commands = vec![
+        format!("curl {}/info", api),
+        format!(
+            "echo '{{\"ping\": []}}' | websocat ws://localhost:{}/ws",
+            config.port
+        ),
+    ];
+    let chains = chains
+        .iter()
+        .filter_map(|(chain_name, _)| config.evm.get(chain_name));
+    for chain in chains {
+        for contract in &chain.contracts {
+            let address = match contract {
+                Contract::Tornado(c) => c.common.address,
+                Contract::Anchor(c) => c.common.address,
+                _ => continue,
+            };
+            commands.push(format!(
+                "curl {}/leaves/{:#x}/{:?}",
+                api, chain.chain_id, address
+            ));
+            commands.push(format!(
+                "curl {}/fee/{:#x}/{:?}",
+                api, chain.chain_id, address
+            ));
+        }
+    }
+    commands
+}
+
+/// Sets up the dev mode: detects the local nodes, relaxes their configuration, funds the
+/// relayer's accounts on them if asked to, and prints example commands.
+///
+/// Returns the context with the relaxed configuration.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext that holds the configuration
+/// * `fund_accounts` - Whether to fund the relayer's accounts on the local nodes
+pub async fn setup(
+    ctx: RelayerContext,
+    fund_accounts: bool,
+) -> anyhow::Result<RelayerContext> {
+    let chains = detect(&ctx).await?;
+    if chains.is_empty() {
+        tracing::warn!(
+            "Dev mode: no local Anvil, Hardhat or Ganache node found"
+        );
+        return Ok(ctx);
+    }
+    for (chain_name, node) in &chains {
+        tracing::info!("Dev mode: chain {} runs on {}", chain_name, node);
+    }
+    let mut config = ctx.config.clone();
+    relax(&mut config, &chains);
+    let ctx = RelayerContext::new(config);
+    if fund_accounts {
+        for (chain_name, node) in &chains {
+            if let Err(e) = fund(&ctx, chain_name, *node).await {
+                tracing::warn!(
+                    "Failed to fund the relayer's account on chain {}: {}",
+                    chain_name,
+                    e
+                );
+            }
+        }
+    }
+    tracing::info!("Dev mode: try the relayer with");
+    for command in example_commands(&ctx.config, &chains) {
+        tracing::info!("  {}", command);
+    }
+    Ok(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_nodes_are_detected() {
+        assert_eq!(
+            DevNode::from_client_version("anvil/v0.1.0"),
+            Some(DevNode::Anvil)
+        );
+        assert_eq!(
+            DevNode::from_client_version(
+                "HardhatNetwork/2.9.3/ethereumjs-vm/4.0.0"
+            ),
+            Some(DevNode::Hardhat)
+        );
+        assert_eq!(
+            DevNode::from_client_version(
+                "Ganache/v7.0.4/EthereumJS TestRPC/v7.0.4/ethereum-js"
+            ),
+            Some(DevNode::Ganache)
+        );
+        assert_eq!(
+            DevNode::from_client_version(
+                "Geth/v1.10.17-stable/linux-amd64/go1.18"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "evm")]
+use ethereum_types::U256;
+use futures::Future;
+use serde::Serialize;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::providers::Middleware;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::signers::Signer;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::types::TransactionRequest;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt;
+
+use crate::context::RelayerContext;
+
+/// How long a single check could take, before it is considered failed.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The result of a single check against an endpoint.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    /// Whether the check passed or not.
+    pub ok: bool,
+    /// How long the check took, in milliseconds.
+    pub latency_ms: u64,
+    /// The value returned by the endpoint, if the check passed.
+    pub value: Option<String>,
+    /// Why the check failed, if it did.
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn failed(latency: Duration, error: impl Display) -> Self {
+        Self {
+            ok: false,
+            latency_ms: latency.as_millis() as u64,
+            value: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Runs the check, with a timeout, and measures its latency.
+async fn check<F, T, E>(fut: F) -> CheckResult
+where
+    F: Future<Output = Result<T, E>>,
+    T: Display,
+    E: Display,
+{
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, fut).await;
+    let latency = started.elapsed();
+    match result {
+        Ok(Ok(value)) => CheckResult {
+            ok: true,
+            latency_ms: latency.as_millis() as u64,
+            value: Some(value.to_string()),
+            error: None,
+        },
+        Ok(Err(e)) => CheckResult::failed(latency, e),
+        Err(_) => CheckResult::failed(latency, "timed out"),
+    }
+}
+
+/// The diagnostics of the endpoint of an EVM chain.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmChainDiagnostics {
+    /// The name of the chain.
+    pub chain: String,
+    /// Fetching the latest block number.
+    pub latest_block: CheckResult,
+    /// Fetching the chain id, it should match the configured one.
+    pub chain_id: CheckResult,
+    /// A dry-run gas estimation of an empty transfer from the relayer's wallet.
+    pub estimate_gas: CheckResult,
+}
+
+/// The diagnostics of the endpoint of a Substrate node.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubstrateNodeDiagnostics {
+    /// The name of the node.
+    pub node: String,
+    /// Fetching the hash of the latest block.
+    pub latest_block: CheckResult,
+}
+
+/// The diagnostics of all the configured endpoints.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    /// Whether all the checks passed or not.
+    pub healthy: bool,
+    /// The diagnostics of the EVM chains.
+    pub evm: Vec<EvmChainDiagnostics>,
+    /// The diagnostics of the Substrate nodes.
+    pub substrate: Vec<SubstrateNodeDiagnostics>,
+}
+
+/// Diagnoses the endpoint of that EVM chain.
+///
+/// A chain whose client could not be set up fails all its checks, with the reason.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_name` - The name of the chain
+#[cfg(feature = "evm")]
+pub async fn diagnose_evm_chain(
+    ctx: &RelayerContext,
+    chain_name: &str,
+) -> EvmChainDiagnostics {
+    let setup = async {
+        let chain = ctx.config.evm.get(chain_name).ok_or_else(|| {
+            anyhow::anyhow!("Chain {} not configured", chain_name)
+        })?;
+        let provider = ctx.evm_provider(chain_name).await?;
+        let wallet = ctx.evm_wallet(chain_name).await?;
+        Result::<_, anyhow::Error>::Ok((chain, provider, wallet))
+    };
+    let (chain, provider, wallet) = match setup.await {
+        Ok(v) => v,
+        Err(e) => {
+            let failed = CheckResult::failed(Duration::ZERO, e);
+            return EvmChainDiagnostics {
+                chain: chain_name.to_owned(),
+                latest_block: failed.clone(),
+                chain_id: failed.clone(),
+                estimate_gas: failed,
+            };
+        }
+    };
+    let latest_block = check(provider.get_block_number()).await;
+    let expected_chain_id = U256::from(chain.chain_id);
+    let chain_id = check(async {
+        let chain_id = provider.get_chainid().await?;
+        if chain_id != expected_chain_id {
+            anyhow::bail!(
+                "expected chain id {}, got {}",
+                expected_chain_id,
+                chain_id
+            );
+        }
+        Ok(chain_id)
+    })
+    .await;
+    let transfer = TransactionRequest::new()
+        .from(wallet.address())
+        .to(wallet.address())
+        .value(0u64);
+    let estimate_gas = check(provider.estimate_gas(&transfer.into())).await;
+    EvmChainDiagnostics {
+        chain: chain_name.to_owned(),
+        latest_block,
+        chain_id,
+        estimate_gas,
+    }
+}
+
+/// Diagnoses the endpoint of that Substrate node.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `node_name` - The name of the node
+#[cfg(feature = "substrate")]
+pub async fn diagnose_substrate_node(
+    ctx: &RelayerContext,
+    node_name: &str,
+) -> SubstrateNodeDiagnostics {
+    let latest_block = check(async {
+        let client = ctx
+            .substrate_provider::<subxt::DefaultConfig>(node_name)
+            .await?;
+        let hash = client.rpc().block_hash(None).await?;
+        hash.map(|v| format!("{:?}", v))
+            .ok_or_else(|| anyhow::anyhow!("no latest block"))
+    })
+    .await;
+    SubstrateNodeDiagnostics {
+        node: node_name.to_owned(),
+        latest_block,
+    }
+}
+
+/// Diagnoses the endpoints of all the enabled chains and nodes.
+///
+/// Every chain gets its own results, a failing chain does not hide the others. The
+/// chains of a stack the relayer is built without are not diagnosed.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+pub async fn diagnose(ctx: &RelayerContext) -> Diagnostics {
+    #[cfg(feature = "evm")]
+    let evm = {
+        let evm_chains = ctx.config.evm.iter().filter(|(_, c)| c.enabled);
+        futures::future::join_all(
+            evm_chains.map(|(name, _)| diagnose_evm_chain(ctx, name)),
+        )
+        .await
+    };
+    #[cfg(not(feature = "evm"))]
+    let evm: Vec<EvmChainDiagnostics> = Vec::new();
+    #[cfg(feature = "substrate")]
+    let substrate = {
+        let nodes = ctx.config.substrate.iter().filter(|(_, c)| c.enabled);
+        futures::future::join_all(
+            nodes.map(|(name, _)| diagnose_substrate_node(ctx, name)),
+        )
+        .await
+    };
+    #[cfg(not(feature = "substrate"))]
+    let substrate: Vec<SubstrateNodeDiagnostics> = Vec::new();
+    let healthy = evm
+        .iter()
+        .all(|c| c.latest_block.ok && c.chain_id.ok && c.estimate_gas.ok)
+        && substrate.iter().all(|c| c.latest_block.ok);
+    Diagnostics {
+        healthy,
+        evm,
+        substrate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checks_report_the_failures() {
+        let passed = check(async { Ok::<_, String>(42) }).await;
+        assert!(passed.ok);
+        assert_eq!(passed.value.as_deref(), Some("42"));
+        let failed = check(async { Err::<u64, _>("connection refused") }).await;
+        assert!(!failed.ok);
+        assert_eq!(failed.error.as_deref(), Some("connection refused"));
+    }
+}
@@ -13,24 +13,153 @@
 // limitations under the License.
 //
 #![warn(missing_docs)]
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ops;
 use std::sync::Arc;
 use std::time::Duration;
 
 use ethereum_types::H256;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use webb::evm::contract::protocol_solidity::fixed_deposit_anchor::{
+    DepositFilter, EdgeAdditionFilter, EdgeUpdateFilter,
+};
 use webb::evm::contract::protocol_solidity::{
     FixedDepositAnchorContract, FixedDepositAnchorContractEvents,
+    SignatureBridgeContract,
 };
 use webb::evm::ethers::prelude::{Contract, LogMeta, Middleware};
-use webb::evm::ethers::providers;
 use webb::evm::ethers::types;
 
 use crate::config;
 use crate::events_watcher::proposal_signing_backend::ProposalSigningBackend;
-use crate::store::sled::SledStore;
-use crate::store::LeafCacheStore;
+use crate::proposals;
+use crate::store::cache::RelayerStore;
+use crate::store::{LeafCacheStore, NeighborRootStore, ProposalNonceStore};
+
+type HttpProvider = crate::rate_limit::HttpProvider;
+
+/// The resource of an anchor on the Signature Bridge of its chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnchorResource {
+    /// The address of the anchor.
+    pub anchor: types::Address,
+    /// The chain id of the chain of the anchor.
+    pub chain_id: u32,
+    /// The resource id, derived from the address and the chain id of the anchor.
+    pub resource_id: proposals::ResourceId,
+    /// The handler of the anchor.
+    pub handler: types::Address,
+    /// The handler of the resource on the bridge, zero if it is not registered yet.
+    pub registered_handler: types::Address,
+}
+
+/// The resources of the anchors resolved when their watchers started, by chain id and address.
+static ANCHOR_RESOURCES: Lazy<
+    RwLock<HashMap<(u32, types::Address), AnchorResource>>,
+> = Lazy::new(Default::default);
+
+/// Returns the resource of that anchor, if its watcher resolved it already.
+pub fn cached_anchor_resource(
+    chain_id: u32,
+    anchor: types::Address,
+) -> Option<AnchorResource> {
+    ANCHOR_RESOURCES.read().get(&(chain_id, anchor)).copied()
+}
+
+/// Returns the resource id of that anchor, the cached one, or derived from its address and
+/// chain id if its watcher did not resolve it (its chain could be watched by another relayer).
+pub fn anchor_resource_id(
+    chain_id: u32,
+    anchor: types::Address,
+) -> proposals::ResourceId {
+    cached_anchor_resource(chain_id, anchor)
+        .map(|v| v.resource_id)
+        .unwrap_or_else(|| proposals::evm_resource_id(anchor, chain_id))
+}
+
+/// Returns the resource id of the linked anchor, an anchor contract of an EVM chain, or a
+/// tree of the anchor handler pallet of a Substrate node.
+///
+/// Returns `None` if the chain of the linked anchor is not configured, or if the chain id
+/// of its Substrate node, or its tree id, is missing.
+fn linked_anchor_resource_id(
+    config: &config::WebbRelayerConfig,
+    chain_name: &str,
+    linked_anchor: &config::LinkedAnchorConfig,
+) -> Option<proposals::ResourceId> {
+    if let Some(chain) = config.evm.get(chain_name) {
+        let chain_id = u32::try_from(chain.chain_id).ok()?;
+        return Some(anchor_resource_id(chain_id, linked_anchor.address));
+    }
+    let node = config.substrate.get(chain_name)?;
+    Some(proposals::substrate_resource_id(
+        linked_anchor.tree_id?,
+        node.chain_id?,
+    ))
+}
 
-type HttpProvider = providers::Provider<providers::Http>;
+/// Resolves the resource of the anchor on the Signature Bridge of its chain, and caches it.
+///
+/// The resource id is derived from the address and the chain id of the anchor, its handler
+/// is queried from the anchor, and the handler of the resource from the bridge, if there is
+/// one configured, so they never have to be duplicated in the configuration.
+///
+/// # Arguments
+///
+/// * `client` - The client of the chain of the anchor
+/// * `wrapper` - The anchor contract
+/// * `bridge` - The address of the Signature Bridge on the chain of the anchor, if any
+pub async fn resolve_anchor_resource<M>(
+    client: Arc<M>,
+    wrapper: &AnchorContractWrapper<M>,
+    bridge: Option<types::Address>,
+) -> anyhow::Result<AnchorResource>
+where
+    M: Middleware + 'static,
+{
+    let chain_id = client.get_chainid().await.map_err(anyhow::Error::msg)?;
+    let chain_id =
+        proposals::evm_typed_chain_id(chain_id)?.underlying_chain_id();
+    let anchor = wrapper.contract.address();
+    let resource_id = proposals::evm_resource_id(anchor, chain_id);
+    let handler = wrapper.contract.handler().call().await?;
+    let registered_handler = match bridge {
+        Some(bridge) => {
+            SignatureBridgeContract::new(bridge, client)
+                .resource_id_to_handler_address(resource_id.into_bytes())
+                .call()
+                .await?
+        }
+        None => types::Address::zero(),
+    };
+    if !registered_handler.is_zero() && registered_handler != handler {
+        tracing::warn!(
+            %anchor,
+            %handler,
+            %registered_handler,
+            "The resource of the anchor is registered on the bridge with another handler",
+        );
+    }
+    let resource = AnchorResource {
+        anchor,
+        chain_id,
+        resource_id,
+        handler,
+        registered_handler,
+    };
+    ANCHOR_RESOURCES
+        .write()
+        .insert((chain_id, anchor), resource);
+    tracing::debug!(
+        %anchor,
+        %handler,
+        resource_id = ?hex::encode(resource_id.into_bytes()),
+        "Resolved the resource of the anchor",
+    );
+    Ok(resource)
+}
 /// Represents an Anchor Contract Watcher which will use a configured signing backend for signing proposals.
 pub struct AnchorWatcher<B> {
     proposal_signing_backend: B,
@@ -38,7 +167,7 @@ pub struct AnchorWatcher<B> {
 
 impl<B> AnchorWatcher<B>
 where
-    B: ProposalSigningBackend<webb_proposals::AnchorUpdateProposal>,
+    B: ProposalSigningBackend<super::VersionedAnchorUpdateProposal>,
 {
     pub fn new(proposal_signing_backend: B) -> Self {
         Self {
@@ -47,6 +176,75 @@ where
     }
 }
 
+impl<B> AnchorWatcher<B>
+where
+    B: ProposalSigningBackend<super::VersionedAnchorUpdateProposal>
+        + ProposalSigningBackend<proposals::ResourceIdUpdateProposal>,
+{
+    /// Proposes to register the resource of the anchor on the Signature Bridge of its chain,
+    /// unless it is registered already.
+    ///
+    /// The proposal is signed by the signing backend, then executed with
+    /// `adminSetResourceWithSignature` by the watcher of the bridge.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client of the chain of the anchor
+    /// * `resource` - The resource of the anchor, see [`resolve_anchor_resource`]
+    /// * `bridge` - The address of the Signature Bridge on the chain of the anchor
+    pub async fn register_resource<M>(
+        &self,
+        client: Arc<M>,
+        resource: &AnchorResource,
+        bridge: types::Address,
+    ) -> anyhow::Result<()>
+    where
+        M: Middleware + 'static,
+    {
+        let (anchor, chain_id) = (resource.anchor, resource.chain_id);
+        if !resource.registered_handler.is_zero() {
+            tracing::debug!(
+                %anchor,
+                handler = %resource.registered_handler,
+                "The resource of the anchor is already registered on the bridge",
+            );
+            return Ok(());
+        }
+        let handler = resource.handler;
+        let bridge = SignatureBridgeContract::new(bridge, client);
+        let nonce = bridge.proposal_nonce().call().await?.as_u32() + 1;
+        let proposal = proposals::resource_id_update_proposal(
+            bridge.address(),
+            chain_id,
+            nonce,
+            anchor,
+            handler,
+        );
+        let backend = &self.proposal_signing_backend;
+        let can_handle = ProposalSigningBackend::<
+            proposals::ResourceIdUpdateProposal,
+        >::can_handle_proposal(backend, &proposal)
+        .await?;
+        if !can_handle {
+            tracing::warn!(
+                %anchor,
+                "The signing backend can not sign the resource registration of the anchor, skipping",
+            );
+            return Ok(());
+        }
+        tracing::debug!(
+            %anchor,
+            %handler,
+            nonce,
+            "Proposing to register the resource of the anchor on the bridge",
+        );
+        ProposalSigningBackend::<proposals::ResourceIdUpdateProposal>::handle_proposal(
+            backend, &proposal,
+        )
+        .await
+    }
+}
+
 /// AnchorContractWrapper contains FixedDepositAnchorContract contract along with configurations for Anchor contract, and Relayer.
 #[derive(Clone, Debug)]
 pub struct AnchorContractWrapper<M>
@@ -55,6 +253,9 @@ where
 {
     config: config::AnchorContractConfig,
     webb_config: config::WebbRelayerConfig,
+    /// The chain id of the anchor, as configured, so the events are handled without
+    /// asking the chain for it.
+    chain_id: types::U256,
     contract: FixedDepositAnchorContract<M>,
 }
 
@@ -66,6 +267,7 @@ where
     pub fn new(
         config: config::AnchorContractConfig,
         webb_config: config::WebbRelayerConfig,
+        chain_id: types::U256,
         client: Arc<M>,
     ) -> Self {
         Self {
@@ -75,6 +277,7 @@ where
             ),
             config,
             webb_config,
+            chain_id,
         }
     }
 }
@@ -98,12 +301,28 @@ where
         self.config.common.deployed_at.into()
     }
 
+    fn sync_from(&self) -> config::SyncFrom {
+        self.config.common.sync_from()
+    }
+
     fn polling_interval(&self) -> Duration {
-        Duration::from_millis(self.config.events_watcher.polling_interval)
+        Duration::from_millis(self.config.events_watcher.polling_interval())
     }
 
     fn max_events_per_step(&self) -> types::U64 {
-        self.config.events_watcher.max_events_per_step.into()
+        self.config.events_watcher.max_events_per_step().into()
+    }
+
+    fn confirmations(&self) -> types::U64 {
+        self.config.events_watcher.confirmations().into()
+    }
+
+    fn polling_jitter(&self) -> Duration {
+        Duration::from_millis(self.config.events_watcher.polling_jitter)
+    }
+
+    fn circuit_breaker(&self) -> config::CircuitBreakerConfig {
+        self.config.events_watcher.circuit_breaker
     }
 
     fn print_progress_interval(&self) -> Duration {
@@ -115,13 +334,172 @@ where
 
 /// An Anchor Leaves Watcher that watches for Deposit events and save the leaves to the store.
 /// It serves as a cache for leaves that could be used by dApp for proof generation.
+#[derive(Debug)]
+pub struct AnchorLeavesWatcher {
+    handlers: super::EventHandlers<
+        AnchorContractWrapper<HttpProvider>,
+        RelayerStore,
+        FixedDepositAnchorContractEvents,
+    >,
+}
+
+impl Default for AnchorLeavesWatcher {
+    fn default() -> Self {
+        use FixedDepositAnchorContractEvents::*;
+        let handlers = super::EventHandlers::new()
+            .register(
+                "Deposit",
+                |e| match e {
+                    DepositFilter(v) => Some(v),
+                    _ => None,
+                },
+                AnchorDepositHandler,
+            )
+            .register(
+                "EdgeAddition",
+                |e| match e {
+                    EdgeAdditionFilter(v) => Some(v),
+                    _ => None,
+                },
+                EdgeAdditionHandler,
+            )
+            .register(
+                "EdgeUpdate",
+                |e| match e {
+                    EdgeUpdateFilter(v) => Some(v),
+                    _ => None,
+                },
+                EdgeUpdateHandler,
+            );
+        Self { handlers }
+    }
+}
+
+/// Saves the leaf of an anchor deposit to the store.
 #[derive(Copy, Clone, Debug, Default)]
-pub struct AnchorLeavesWatcher;
+pub struct AnchorDepositHandler;
+
+#[async_trait::async_trait]
+impl
+    super::EventHandler<
+        AnchorContractWrapper<HttpProvider>,
+        RelayerStore,
+        DepositFilter,
+    > for AnchorDepositHandler
+{
+    async fn handle_event(
+        &self,
+        store: Arc<RelayerStore>,
+        wrapper: &AnchorContractWrapper<HttpProvider>,
+        deposit: &DepositFilter,
+        log: &LogMeta,
+    ) -> anyhow::Result<()> {
+        let value = (deposit.leaf_index, H256::from_slice(&deposit.commitment));
+        let chain_id = wrapper.chain_id;
+        super::insert_leaves_or_roll_back(
+            store.as_ref(),
+            (chain_id, wrapper.contract.address()).into(),
+            &[value],
+        )?;
+        store.insert_leaves_block_numbers(
+            (chain_id, wrapper.contract.address()),
+            &[(value.0, log.block_number)],
+        )?;
+        store.insert_last_deposit_block_number(
+            (chain_id, wrapper.contract.address()),
+            log.block_number,
+        )?;
+        tracing::trace!(
+            %log.block_number,
+            "detected block number",
+        );
+        tracing::event!(
+            target: crate::probe::TARGET,
+            tracing::Level::DEBUG,
+            kind = %crate::probe::Kind::LeavesStore,
+            leaf_index = %value.0,
+            leaf = %value.1,
+            chain_id = %chain_id,
+            block_number = %log.block_number
+        );
+        Ok(())
+    }
+}
+
+/// Logs the edges added to an anchor, and records the roots of its new neighbors.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EdgeAdditionHandler;
+
+#[async_trait::async_trait]
+impl
+    super::EventHandler<
+        AnchorContractWrapper<HttpProvider>,
+        RelayerStore,
+        EdgeAdditionFilter,
+    > for EdgeAdditionHandler
+{
+    async fn handle_event(
+        &self,
+        store: Arc<RelayerStore>,
+        wrapper: &AnchorContractWrapper<HttpProvider>,
+        v: &EdgeAdditionFilter,
+        _log: &LogMeta,
+    ) -> anyhow::Result<()> {
+        tracing::debug!(
+            "Edge Added of chain {} at index {} with root 0x{}",
+            v.chain_id,
+            v.latest_leaf_index,
+            hex::encode(v.merkle_root)
+        );
+        let chain_id = wrapper.chain_id;
+        store.insert_neighbor_root(
+            (chain_id, wrapper.contract.address()),
+            v.chain_id,
+            H256::from(v.merkle_root),
+        )?;
+        Ok(())
+    }
+}
+
+/// Logs the edges updated in an anchor, and records the new roots of its neighbors.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EdgeUpdateHandler;
+
+#[async_trait::async_trait]
+impl
+    super::EventHandler<
+        AnchorContractWrapper<HttpProvider>,
+        RelayerStore,
+        EdgeUpdateFilter,
+    > for EdgeUpdateHandler
+{
+    async fn handle_event(
+        &self,
+        store: Arc<RelayerStore>,
+        wrapper: &AnchorContractWrapper<HttpProvider>,
+        v: &EdgeUpdateFilter,
+        _log: &LogMeta,
+    ) -> anyhow::Result<()> {
+        tracing::debug!(
+            "Edge Updated of chain {} at index {} with root 0x{}",
+            v.chain_id,
+            v.latest_leaf_index,
+            hex::encode(v.merkle_root)
+        );
+        let chain_id = wrapper.chain_id;
+        store.insert_neighbor_root(
+            (chain_id, wrapper.contract.address()),
+            v.chain_id,
+            H256::from(v.merkle_root),
+        )?;
+        Ok(())
+    }
+}
 
 #[async_trait::async_trait]
 impl<B> super::EventWatcher for AnchorWatcher<B>
 where
-    B: ProposalSigningBackend<webb_proposals::AnchorUpdateProposal>
+    B: ProposalSigningBackend<super::VersionedAnchorUpdateProposal>
         + Send
         + Sync,
 {
@@ -132,12 +510,12 @@ where
 
     type Events = FixedDepositAnchorContractEvents;
 
-    type Store = SledStore;
+    type Store = RelayerStore;
 
     #[tracing::instrument(skip_all)]
     async fn handle_event(
         &self,
-        _store: Arc<Self::Store>,
+        store: Arc<Self::Store>,
         wrapper: &Self::Contract,
         (event, _): (Self::Events, LogMeta),
     ) -> anyhow::Result<()> {
@@ -155,34 +533,46 @@ where
         let src_chain_id = client.get_chainid().await?;
         let root = wrapper.contract.get_last_root().call().await?;
         let leaf_index = event_data.leaf_index;
-        let function_signature = [68, 52, 123, 169];
         let nonce = event_data.leaf_index;
         for linked_anchor in &wrapper.config.linked_anchors {
-            let dest_chain = linked_anchor.chain.to_lowercase();
-            let maybe_chain = wrapper.webb_config.evm.get(&dest_chain);
-            let dest_chain = match maybe_chain {
-                Some(chain) => chain,
+            let dest_chain_name = linked_anchor.chain.to_lowercase();
+            let resource_id = match linked_anchor_resource_id(
+                &wrapper.webb_config,
+                &dest_chain_name,
+                linked_anchor,
+            ) {
+                Some(v) => v,
                 None => continue,
             };
-            let target_system =
-                webb_proposals::TargetSystem::new_contract_address(
-                    linked_anchor.address.to_fixed_bytes(),
-                );
-            let typed_chain_id =
-                webb_proposals::TypedChainId::Evm(dest_chain.chain_id as _);
-            let resource_id =
-                webb_proposals::ResourceId::new(target_system, typed_chain_id);
-            let header = webb_proposals::ProposalHeader::new(
+            let header = proposals::ProposalHeader::new(
                 resource_id,
-                function_signature.into(),
+                proposals::ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
                 nonce.into(),
             );
+            let src_chain = proposals::evm_typed_chain_id(src_chain_id)?;
+            // do not create proposals that are older than what got executed already,
+            // which could happen after restarts, when old deposits are watched again.
+            let last_nonce =
+                store.get_last_executed_nonce(resource_id, src_chain)?;
+            if matches!(last_nonce, Some(last) if nonce <= last) {
+                tracing::debug!(
+                    nonce,
+                    last_nonce = ?last_nonce,
+                    "Skipping anchor update proposal since a newer one is already executed",
+                );
+                continue;
+            }
             let proposal = webb_proposals::AnchorUpdateProposal::new(
-                header,
-                webb_proposals::TypedChainId::Evm(src_chain_id.as_u32()),
-                leaf_index,
-                root,
+                header, src_chain, leaf_index, root,
             );
+            // encode the proposal as expected by the handler of the linked anchor.
+            let encoding = linked_anchor_encoding(
+                &wrapper.webb_config,
+                &dest_chain_name,
+                linked_anchor.address,
+            );
+            let proposal =
+                super::VersionedAnchorUpdateProposal::new(proposal, encoding);
             let can_sign_proposal = self
                 .proposal_signing_backend
                 .can_handle_proposal(&proposal)
@@ -211,7 +601,15 @@ impl super::EventWatcher for AnchorLeavesWatcher {
 
     type Events = FixedDepositAnchorContractEvents;
 
-    type Store = SledStore;
+    type Store = RelayerStore;
+
+    fn event_kinds(&self) -> Vec<&'static str> {
+        self.handlers.kinds()
+    }
+
+    fn event_kind(&self, event: &Self::Events) -> Option<&'static str> {
+        self.handlers.kind_of(event)
+    }
 
     #[tracing::instrument(skip_all)]
     async fn handle_event(
@@ -219,57 +617,94 @@ impl super::EventWatcher for AnchorLeavesWatcher {
         store: Arc<Self::Store>,
         wrapper: &Self::Contract,
         (event, log): (Self::Events, LogMeta),
+    ) -> anyhow::Result<()> {
+        let handled =
+            self.handlers.dispatch(store, wrapper, &event, &log).await?;
+        if !handled {
+            tracing::trace!("Unhandled event {:?}", event);
+        }
+        Ok(())
+    }
+
+    /// Inserts all the leaves found in the page in one store operation,
+    /// instead of one by one, which speeds up the initial sync a lot.
+    ///
+    /// The cursors are only moved past the page once its leaves are inserted.
+    #[tracing::instrument(skip_all)]
+    async fn handle_events(
+        &self,
+        store: Arc<Self::Store>,
+        wrapper: &Self::Contract,
+        events: Vec<(Self::Events, LogMeta)>,
+        _cursors: &super::EventCursors<'_, Self::Store>,
     ) -> anyhow::Result<()> {
         use FixedDepositAnchorContractEvents::*;
-        match event {
-            DepositFilter(deposit) => {
-                let commitment = deposit.commitment;
-                let leaf_index = deposit.leaf_index;
-                let value = (leaf_index, H256::from_slice(&commitment));
-                let chain_id = wrapper.contract.client().get_chainid().await?;
-                store.insert_leaves(
-                    (chain_id, wrapper.contract.address()),
-                    &[value],
-                )?;
-                store.insert_last_deposit_block_number(
-                    (chain_id, wrapper.contract.address()),
-                    log.block_number,
-                )?;
-                tracing::trace!(
-                    %log.block_number,
-                    "detected block number",
-                );
-                tracing::event!(
-                    target: crate::probe::TARGET,
-                    tracing::Level::DEBUG,
-                    kind = %crate::probe::Kind::LeavesStore,
-                    leaf_index = %value.0,
-                    leaf = %value.1,
-                    chain_id = %chain_id,
-                    block_number = %log.block_number
-                );
-            }
-            EdgeAdditionFilter(v) => {
-                tracing::debug!(
-                    "Edge Added of chain {} at index {} with root 0x{}",
-                    v.chain_id,
-                    v.latest_leaf_index,
-                    hex::encode(v.merkle_root)
-                );
-            }
-            EdgeUpdateFilter(v) => {
-                tracing::debug!(
-                    "Edge Updated of chain {} at index {} with root 0x{}",
-                    v.chain_id,
-                    v.latest_leaf_index,
-                    hex::encode(v.merkle_root)
-                );
-            }
-            _ => {
-                tracing::trace!("Unhandled event {:?}", event);
+        let chain_id = wrapper.chain_id;
+        let mut leaves = Vec::new();
+        let mut block_numbers = Vec::new();
+        let mut last_deposit_block_number = None;
+        for (event, log) in events {
+            match event {
+                DepositFilter(deposit) => {
+                    let value = (
+                        deposit.leaf_index,
+                        H256::from_slice(&deposit.commitment),
+                    );
+                    tracing::event!(
+                        target: crate::probe::TARGET,
+                        tracing::Level::DEBUG,
+                        kind = %crate::probe::Kind::LeavesStore,
+                        leaf_index = %value.0,
+                        leaf = %value.1,
+                        chain_id = %chain_id,
+                        block_number = %log.block_number
+                    );
+                    leaves.push(value);
+                    block_numbers.push((value.0, log.block_number));
+                    last_deposit_block_number = Some(log.block_number);
+                }
+                event => {
+                    self.handle_event(store.clone(), wrapper, (event, log))
+                        .await?;
+                }
             }
-        };
-
+        }
+        if let Some(block_number) = last_deposit_block_number {
+            let key = (chain_id, wrapper.contract.address());
+            super::insert_leaves_or_roll_back(
+                store.as_ref(),
+                key.into(),
+                &leaves,
+            )?;
+            store.insert_leaves_block_numbers(key, &block_numbers)?;
+            store.insert_last_deposit_block_number(key, block_number)?;
+            tracing::trace!(
+                count = leaves.len(),
+                %block_number,
+                "inserted leaves",
+            );
+        }
         Ok(())
     }
 }
+
+/// Returns the proposal encoding of the linked anchor, as configured on its chain,
+/// or the default encoding if the linked anchor is not configured.
+fn linked_anchor_encoding(
+    webb_config: &config::WebbRelayerConfig,
+    chain_name: &str,
+    address: types::Address,
+) -> config::AnchorUpdateProposalEncoding {
+    webb_config
+        .evm
+        .get(chain_name)
+        .and_then(|chain| {
+            chain.contracts.iter().find_map(|contract| match contract {
+                config::Contract::Anchor(c) if c.common.address == address => {
+                    Some(c.proposal_encoding)
+                }
+                _ => None,
+            })
+        })
+        .unwrap_or_default()
+}
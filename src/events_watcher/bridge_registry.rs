@@ -0,0 +1,347 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::proposals::TypedChainId;
+use crate::store::sled::SledQueueKey;
+use crate::store::{BridgeCommand, BridgeKey, BridgeRegistryStore, QueueStore};
+
+/// A handle to a registered Signature Bridge, used for sending commands to it.
+pub struct BridgeHandle<S> {
+    key: BridgeKey,
+    store: Arc<S>,
+}
+
+impl<S> BridgeHandle<S>
+where
+    S: QueueStore<BridgeCommand, Key = SledQueueKey>,
+{
+    /// The key of the bridge this handle points to.
+    pub fn key(&self) -> BridgeKey {
+        self.key
+    }
+
+    /// Sends a command to the bridge, it will be handled by the bridge watcher
+    /// in the background.
+    ///
+    /// if the bridge watcher is offline (restarting for example), the command is kept in the
+    /// queue and it will be handled once the bridge watcher is back.
+    pub fn send(&self, cmd: BridgeCommand) -> anyhow::Result<()> {
+        self.store
+            .enqueue_item(SledQueueKey::from_bridge_key(self.key), cmd)
+    }
+}
+
+/// The status of a registered bridge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeStatus {
+    /// Whether the bridge watcher is running or not.
+    pub online: bool,
+    /// The last command handled by the bridge watcher, since the relayer started.
+    pub last_command: Option<HandledCommand>,
+}
+
+/// A command that got handled by a bridge watcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HandledCommand {
+    /// The name of the command.
+    #[schema(value_type = String)]
+    pub command: &'static str,
+    /// Unix timestamp (in seconds) of when the command got handled.
+    pub handled_at: u64,
+}
+
+/// The BridgeRegistry keeps track of the Signature Bridges that are watched by the relayer,
+/// and routes the commands sent from other event watchers or services to them.
+///
+/// Bridge watchers register themselves when they start, and go offline when they stop.
+/// Registrations are persisted in the store, so commands sent to a bridge that is offline
+/// (or not started yet after a restart) are queued and replayed once its watcher is back.
+/// The registrations of the bridges removed from the config are pruned at startup, see
+/// [`BridgeRegistry::prune`].
+pub struct BridgeRegistry<S> {
+    store: Arc<S>,
+    bridges: Arc<RwLock<BTreeMap<BridgeKey, BridgeStatus>>>,
+}
+
+impl<S> Clone for BridgeRegistry<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            bridges: self.bridges.clone(),
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for BridgeRegistry<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BridgeRegistry")
+            .field("bridges", &*self.bridges.read())
+            .finish()
+    }
+}
+
+impl<S> BridgeRegistry<S>
+where
+    S: QueueStore<BridgeCommand, Key = SledQueueKey> + BridgeRegistryStore,
+{
+    /// Creates a new BridgeRegistry, with all the bridges that got registered before
+    /// loaded from the store, as offline.
+    pub fn new(store: Arc<S>) -> anyhow::Result<Self> {
+        let bridges = store
+            .registered_bridges()?
+            .into_iter()
+            .map(|key| (key, BridgeStatus::default()))
+            .collect();
+        Ok(Self {
+            store,
+            bridges: Arc::new(RwLock::new(bridges)),
+        })
+    }
+
+    /// Registers a bridge as online, so that its commands get handled.
+    ///
+    /// Returns `false` if the bridge was already online.
+    pub fn register(&self, key: BridgeKey) -> anyhow::Result<bool> {
+        tracing::debug!(%key, "Registering bridge");
+        self.store.register_bridge(key)?;
+        let mut bridges = self.bridges.write();
+        let status = bridges.entry(key).or_default();
+        let was_online = status.online;
+        status.online = true;
+        Ok(!was_online)
+    }
+
+    /// Marks the bridge as offline, commands sent to it will be queued
+    /// until it gets registered again.
+    ///
+    /// Returns `false` if the bridge was not online.
+    pub fn unregister(&self, key: BridgeKey) -> bool {
+        tracing::debug!(%key, "Bridge is going offline");
+        match self.bridges.write().get_mut(&key) {
+            Some(status) if status.online => {
+                status.online = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the offline bridges that are not `configured` anymore, along with the
+    /// commands queued for them, which no bridge watcher would ever handle.
+    ///
+    /// Returns the keys of the forgotten bridges.
+    pub fn prune(
+        &self,
+        configured: &[BridgeKey],
+    ) -> anyhow::Result<Vec<BridgeKey>> {
+        let mut bridges = self.bridges.write();
+        let stale = bridges
+            .iter()
+            .filter(|(key, status)| !status.online && !configured.contains(key))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in &stale {
+            self.store.unregister_bridge(*key)?;
+            let queue = SledQueueKey::from_bridge_key(*key);
+            while let Some(cmd) = QueueStore::<BridgeCommand>::dequeue_item(
+                self.store.as_ref(),
+                queue,
+            )? {
+                tracing::warn!(%key, command = cmd.name(), "Dropping the command of a forgotten bridge");
+            }
+            bridges.remove(key);
+        }
+        Ok(stale)
+    }
+
+    /// Records that the bridge watcher handled a command with that name.
+    pub fn record_handled(&self, key: BridgeKey, command: &'static str) {
+        let handled_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        if let Some(status) = self.bridges.write().get_mut(&key) {
+            status.last_command = Some(HandledCommand {
+                command,
+                handled_at,
+            });
+        }
+    }
+
+    /// Returns all the known bridges along with their status.
+    pub fn bridges(&self) -> Vec<(BridgeKey, BridgeStatus)> {
+        self.bridges
+            .read()
+            .iter()
+            .map(|(key, status)| (*key, *status))
+            .collect()
+    }
+
+    /// Returns the number of commands waiting in the queue of that bridge.
+    pub fn backlog(&self, key: BridgeKey) -> anyhow::Result<usize> {
+        QueueStore::<BridgeCommand>::count_items(
+            self.store.as_ref(),
+            SledQueueKey::from_bridge_key(key),
+        )
+    }
+
+    /// Returns a handle to the bridge with that exact key, if it is known
+    /// (even if it is offline).
+    pub fn lookup(&self, key: BridgeKey) -> Option<BridgeHandle<S>> {
+        if self.bridges.read().contains_key(&key) {
+            Some(self.handle(key))
+        } else {
+            None
+        }
+    }
+
+    /// Returns handles to all the known bridges on that chain.
+    pub fn lookup_by_chain(
+        &self,
+        chain_id: TypedChainId,
+    ) -> Vec<BridgeHandle<S>> {
+        self.bridges
+            .read()
+            .keys()
+            .filter(|key| key.chain_id == chain_id)
+            .map(|key| self.handle(*key))
+            .collect()
+    }
+
+    /// Sends the command to every known bridge on that chain.
+    ///
+    /// Returns the number of bridges the command got sent to.
+    pub fn broadcast(
+        &self,
+        chain_id: TypedChainId,
+        cmd: BridgeCommand,
+    ) -> anyhow::Result<usize> {
+        let bridges = self.lookup_by_chain(chain_id);
+        for bridge in &bridges {
+            tracing::debug!(bridge_key = %bridge.key(), "Sending command");
+            bridge.send(cmd.clone())?;
+        }
+        Ok(bridges.len())
+    }
+
+    fn handle(&self, key: BridgeKey) -> BridgeHandle<S> {
+        BridgeHandle {
+            key,
+            store: self.store.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sled::SledStore;
+    use ethereum_types as types;
+
+    #[test]
+    fn broadcast_only_reaches_bridges_on_that_chain() {
+        let store = Arc::new(SledStore::temporary().unwrap());
+        let registry = BridgeRegistry::new(store.clone()).unwrap();
+        let a = BridgeKey::new(types::Address::random(), TypedChainId::Evm(1));
+        let b = BridgeKey::new(types::Address::random(), TypedChainId::Evm(1));
+        let c = BridgeKey::new(types::Address::random(), TypedChainId::Evm(2));
+        for key in [a, b, c] {
+            assert!(registry.register(key).unwrap());
+        }
+        assert_eq!(registry.lookup_by_chain(TypedChainId::Evm(1)).len(), 2);
+        let cmd = BridgeCommand::ExecuteProposalWithSignature {
+            data: vec![1, 2, 3],
+            signature: vec![4, 5, 6],
+        };
+        assert_eq!(
+            registry
+                .broadcast(TypedChainId::Evm(1), cmd.clone())
+                .unwrap(),
+            2
+        );
+        for key in [a, b] {
+            let item: Option<BridgeCommand> = store
+                .dequeue_item(SledQueueKey::from_bridge_key(key))
+                .unwrap();
+            assert_eq!(item, Some(cmd.clone()));
+        }
+        let item: Option<BridgeCommand> = store
+            .dequeue_item(SledQueueKey::from_bridge_key(c))
+            .unwrap();
+        assert_eq!(item, None);
+    }
+
+    #[test]
+    fn commands_are_buffered_while_bridge_is_offline() {
+        let store = Arc::new(SledStore::temporary().unwrap());
+        let key =
+            BridgeKey::new(types::Address::random(), TypedChainId::Evm(1));
+        let registry = BridgeRegistry::new(store.clone()).unwrap();
+        registry.register(key).unwrap();
+        // the bridge watcher goes offline
+        assert!(registry.unregister(key));
+        assert!(!registry.unregister(key));
+        // and the relayer restarts, before the bridge watcher is started again.
+        let registry = BridgeRegistry::new(store.clone()).unwrap();
+        let cmd = BridgeCommand::ExecuteProposalWithSignature {
+            data: vec![1, 2, 3],
+            signature: vec![4, 5, 6],
+        };
+        registry.lookup(key).unwrap().send(cmd.clone()).unwrap();
+        // once the bridge is back, the command is still there.
+        assert!(registry.register(key).unwrap());
+        assert_eq!(registry.backlog(key).unwrap(), 1);
+        let item: Option<BridgeCommand> = store
+            .dequeue_item(SledQueueKey::from_bridge_key(key))
+            .unwrap();
+        assert_eq!(item, Some(cmd));
+    }
+
+    #[test]
+    fn bridges_not_configured_anymore_are_forgotten() {
+        let store = Arc::new(SledStore::temporary().unwrap());
+        let kept =
+            BridgeKey::new(types::Address::random(), TypedChainId::Evm(1));
+        let removed =
+            BridgeKey::new(types::Address::random(), TypedChainId::Evm(1));
+        let registry = BridgeRegistry::new(store.clone()).unwrap();
+        registry.register(kept).unwrap();
+        registry.register(removed).unwrap();
+        let cmd = BridgeCommand::ExecuteProposalWithSignature {
+            data: vec![1, 2, 3],
+            signature: vec![4, 5, 6],
+        };
+        registry.lookup(removed).unwrap().send(cmd).unwrap();
+        // the online bridges are kept, even if not configured.
+        assert!(registry.prune(&[kept]).unwrap().is_empty());
+        // after a restart, the removed bridge is forgotten.
+        let registry = BridgeRegistry::new(store.clone()).unwrap();
+        assert_eq!(registry.prune(&[kept]).unwrap(), vec![removed]);
+        assert!(registry.lookup(removed).is_none());
+        assert_eq!(registry.backlog(removed).unwrap(), 0);
+        let registry = BridgeRegistry::new(store).unwrap();
+        assert!(registry.lookup(kept).is_some());
+        assert!(registry.lookup(removed).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use backoff::backoff::Backoff;
+use ethereum_types::{H160, U256};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::config::CircuitBreakerConfig;
+use crate::store::HistoryStoreKey;
+
+/// The health of an events watcher that is not running fine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatcherHealth {
+    /// The watcher keeps failing, its circuit breaker is open.
+    Degraded,
+    /// The watcher stopped, after a permanent failure.
+    Stopped,
+}
+
+/// The health of the events watchers that are not running fine, by their contract and tag.
+static HEALTH: Lazy<
+    RwLock<HashMap<(HistoryStoreKey, &'static str), WatcherHealth>>,
+> = Lazy::new(Default::default);
+
+/// Sets the health of an events watcher, `None` if it runs fine.
+pub fn set_watcher_health(
+    key: HistoryStoreKey,
+    tag: &'static str,
+    health: Option<WatcherHealth>,
+) {
+    match health {
+        Some(v) => HEALTH.write().insert((key, tag), v),
+        None => HEALTH.write().remove(&(key, tag)),
+    };
+}
+
+/// Returns the contracts of that EVM chain with an events watcher in that health.
+fn evm_contracts_with(chain_id: U256, health: WatcherHealth) -> Vec<H160> {
+    let mut contracts: Vec<_> = HEALTH
+        .read()
+        .iter()
+        .filter(|(_, h)| **h == health)
+        .filter_map(|((key, _), _)| match key {
+            HistoryStoreKey::Evm {
+                chain_id: c,
+                address,
+            } if *c == chain_id => Some(*address),
+            _ => None,
+        })
+        .collect();
+    contracts.sort();
+    contracts.dedup();
+    contracts
+}
+
+/// Returns the contracts of that EVM chain whose events watcher is degraded.
+pub fn degraded_evm_contracts(chain_id: U256) -> Vec<H160> {
+    evm_contracts_with(chain_id, WatcherHealth::Degraded)
+}
+
+/// Returns the contracts of that EVM chain whose events watcher stopped.
+pub fn stopped_evm_contracts(chain_id: U256) -> Vec<H160> {
+    evm_contracts_with(chain_id, WatcherHealth::Stopped)
+}
+
+/// A circuit breaker over the retries of an events watcher.
+///
+/// It retries with the exponential `backoff` (up to `max_backoff_interval` apart), until the watcher failed
+/// `failure_threshold` times in a row, then the breaker opens: the watcher is marked
+/// as degraded and retried every `retry_interval`. The watcher records its progress
+/// with the [`CircuitBreakerHandle`], which closes the breaker.
+pub struct CircuitBreaker {
+    backoff: backoff::ExponentialBackoff,
+    failures: Arc<AtomicU32>,
+    config: CircuitBreakerConfig,
+    key: HistoryStoreKey,
+    tag: &'static str,
+}
+
+/// The handle of a [`CircuitBreaker`], used by the watcher for recording its progress.
+#[derive(Clone)]
+pub struct CircuitBreakerHandle {
+    failures: Arc<AtomicU32>,
+    config: CircuitBreakerConfig,
+    key: HistoryStoreKey,
+    tag: &'static str,
+}
+
+impl CircuitBreaker {
+    /// Creates the circuit breaker of the events watcher of that contract, and its handle.
+    pub fn new(
+        config: CircuitBreakerConfig,
+        key: HistoryStoreKey,
+        tag: &'static str,
+    ) -> (Self, CircuitBreakerHandle) {
+        let failures = Arc::new(AtomicU32::new(0));
+        let handle = CircuitBreakerHandle {
+            failures: failures.clone(),
+            config,
+            key: key.clone(),
+            tag,
+        };
+        let breaker = Self {
+            backoff: backoff::ExponentialBackoff {
+                max_elapsed_time: None,
+                max_interval: Duration::from_millis(
+                    config.max_backoff_interval,
+                ),
+                ..Default::default()
+            },
+            failures,
+            config,
+            key,
+            tag,
+        };
+        (breaker, handle)
+    }
+}
+
+impl Backoff for CircuitBreaker {
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures == 1 {
+            // the watcher made progress since its last failure.
+            self.backoff.reset();
+        }
+        let threshold = self.config.failure_threshold.max(1);
+        if failures < threshold {
+            return self.backoff.next_backoff();
+        }
+        if failures == threshold {
+            tracing::warn!(
+                tag = %self.tag,
+                "The events watcher failed {} times in a row, it is degraded \
+                and retried every {}ms",
+                failures,
+                self.config.retry_interval,
+            );
+            set_watcher_health(
+                self.key.clone(),
+                self.tag,
+                Some(WatcherHealth::Degraded),
+            );
+        }
+        Some(Duration::from_millis(self.config.retry_interval))
+    }
+}
+
+impl CircuitBreakerHandle {
+    /// Records that the watcher made progress, closing the circuit breaker if it was open.
+    pub fn record_success(&self) {
+        let failures = self.failures.swap(0, Ordering::SeqCst);
+        if failures >= self.config.failure_threshold.max(1) {
+            tracing::info!(
+                tag = %self.tag,
+                "The events watcher recovered, after {} failures",
+                failures
+            );
+            set_watcher_health(self.key.clone(), self.tag, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let chain_id = U256::from(31339);
+        let key = HistoryStoreKey::from((chain_id, H160::random()));
+        let config = CircuitBreakerConfig {
+            failure_threshold: 3,
+            retry_interval: 60_000,
+            max_backoff_interval: 1_000,
+        };
+        let (mut breaker, handle) =
+            CircuitBreaker::new(config, key, "Anchor Watcher");
+        let slow = Some(Duration::from_millis(60_000));
+        assert_ne!(breaker.next_backoff(), slow);
+        assert_ne!(breaker.next_backoff(), slow);
+        assert!(degraded_evm_contracts(chain_id).is_empty());
+        assert_eq!(breaker.next_backoff(), slow);
+        assert_eq!(breaker.next_backoff(), slow);
+        assert_eq!(degraded_evm_contracts(chain_id).len(), 1);
+        // progress closes the breaker.
+        handle.record_success();
+        assert!(degraded_evm_contracts(chain_id).is_empty());
+        assert_ne!(breaker.next_backoff(), slow);
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fmt;
+use std::sync::Arc;
+
+use webb::evm::ethers::contract::LogMeta;
+
+/// A handler of one event type `E`, emitted by the contract `C` of a watcher
+/// that uses the store `S`.
+#[async_trait::async_trait]
+pub trait EventHandler<C, S, E>: Send + Sync
+where
+    C: Sync,
+    S: Send + Sync,
+    E: Sync,
+{
+    /// Handles one event, found in the `log`.
+    async fn handle_event(
+        &self,
+        store: Arc<S>,
+        contract: &C,
+        event: &E,
+        log: &LogMeta,
+    ) -> anyhow::Result<()>;
+}
+
+/// Selects the event type `E` out of the events enum `Ev` of a contract.
+pub type SelectEvent<Ev, E> = fn(&Ev) -> Option<&E>;
+
+/// A registered handler, with the event type it handles erased.
+#[async_trait::async_trait]
+trait ErasedHandler<C, S, Ev>: Send + Sync {
+    /// The kind of events of the handler.
+    fn kind(&self) -> &'static str;
+
+    /// Whether the event is of the type of the handler.
+    fn selects(&self, event: &Ev) -> bool;
+
+    /// Handles the event if it is of the type of the handler, returns whether it did.
+    async fn handle(
+        &self,
+        store: Arc<S>,
+        contract: &C,
+        event: &Ev,
+        log: &LogMeta,
+    ) -> anyhow::Result<bool>;
+}
+
+struct Registered<Ev, E, H> {
+    kind: &'static str,
+    select: SelectEvent<Ev, E>,
+    handler: H,
+}
+
+#[async_trait::async_trait]
+impl<C, S, Ev, E, H> ErasedHandler<C, S, Ev> for Registered<Ev, E, H>
+where
+    C: Sync + 'static,
+    S: Send + Sync + 'static,
+    Ev: Sync + 'static,
+    E: Sync + 'static,
+    H: EventHandler<C, S, E>,
+{
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn selects(&self, event: &Ev) -> bool {
+        (self.select)(event).is_some()
+    }
+
+    async fn handle(
+        &self,
+        store: Arc<S>,
+        contract: &C,
+        event: &Ev,
+        log: &LogMeta,
+    ) -> anyhow::Result<bool> {
+        match (self.select)(event) {
+            Some(event) => {
+                self.handler
+                    .handle_event(store, contract, event, log)
+                    .await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// A registry of the handlers of the event types of a contract.
+///
+/// The handlers are registered per event type, many handlers could be registered
+/// for the same event type, they run in the order they got registered.
+///
+/// Every event type is registered under a kind, like `Deposit`, the watchers keep a cursor
+/// per kind of events, so a newly registered kind is backfilled from the contract deployment.
+/// The handlers of the same event type share the kind it got first registered under.
+pub struct EventHandlers<C, S, Ev> {
+    handlers: Vec<Box<dyn ErasedHandler<C, S, Ev>>>,
+}
+
+impl<C, S, Ev> EventHandlers<C, S, Ev>
+where
+    C: Sync + 'static,
+    S: Send + Sync + 'static,
+    Ev: Sync + 'static,
+{
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Registers a handler of the event type selected by `select`.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of the events, which names their cursor
+    /// * `select` - Selects the event type out of the events enum
+    /// * `handler` - The handler of the event type
+    pub fn register<E, H>(
+        mut self,
+        kind: &'static str,
+        select: SelectEvent<Ev, E>,
+        handler: H,
+    ) -> Self
+    where
+        E: Sync + 'static,
+        H: EventHandler<C, S, E> + 'static,
+    {
+        self.handlers.push(Box::new(Registered {
+            kind,
+            select,
+            handler,
+        }));
+        self
+    }
+
+    /// The kinds of events with a registered handler.
+    pub fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds: Vec<_> =
+            self.handlers.iter().map(|h| h.kind()).collect();
+        kinds.sort_unstable();
+        kinds.dedup();
+        kinds
+    }
+
+    /// The kind of that event, `None` if there is no handler registered for it.
+    pub fn kind_of(&self, event: &Ev) -> Option<&'static str> {
+        self.handlers
+            .iter()
+            .find(|h| h.selects(event))
+            .map(|h| h.kind())
+    }
+
+    /// Runs all the handlers registered for the type of that event.
+    ///
+    /// Returns false if there is no handler registered for it.
+    pub async fn dispatch(
+        &self,
+        store: Arc<S>,
+        contract: &C,
+        event: &Ev,
+        log: &LogMeta,
+    ) -> anyhow::Result<bool> {
+        let mut handled = false;
+        for handler in &self.handlers {
+            handled |=
+                handler.handle(store.clone(), contract, event, log).await?;
+        }
+        Ok(handled)
+    }
+}
+
+impl<C, S, Ev> Default for EventHandlers<C, S, Ev>
+where
+    C: Sync + 'static,
+    S: Send + Sync + 'static,
+    Ev: Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, S, Ev> fmt::Debug for EventHandlers<C, S, Ev> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventHandlers")
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    enum Events {
+        Deposit(u32),
+        Withdrawal,
+    }
+
+    struct Record(&'static str);
+
+    #[async_trait::async_trait]
+    impl EventHandler<(), Mutex<Vec<String>>, u32> for Record {
+        async fn handle_event(
+            &self,
+            store: Arc<Mutex<Vec<String>>>,
+            _contract: &(),
+            event: &u32,
+            _log: &LogMeta,
+        ) -> anyhow::Result<()> {
+            store.lock().push(format!("{} {}", self.0, event));
+            Ok(())
+        }
+    }
+
+    fn deposit(e: &Events) -> Option<&u32> {
+        match e {
+            Events::Deposit(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn handlers_run_for_their_event_type() {
+        let handlers = EventHandlers::new()
+            .register("Deposit", deposit, Record("first"))
+            .register("Deposit", deposit, Record("second"));
+        assert_eq!(handlers.kinds(), vec!["Deposit"]);
+        assert_eq!(handlers.kind_of(&Events::Deposit(1)), Some("Deposit"));
+        assert_eq!(handlers.kind_of(&Events::Withdrawal), None);
+        let store = Arc::new(Mutex::new(Vec::new()));
+        let log = LogMeta {
+            address: Default::default(),
+            block_number: Default::default(),
+            block_hash: Default::default(),
+            transaction_hash: Default::default(),
+            transaction_index: Default::default(),
+            log_index: Default::default(),
+        };
+        let handled = handlers
+            .dispatch(store.clone(), &(), &Events::Deposit(7), &log)
+            .await
+            .unwrap();
+        assert!(handled);
+        assert_eq!(*store.lock(), vec!["first 7", "second 7"]);
+        let handled = handlers
+            .dispatch(store.clone(), &(), &Events::Withdrawal, &log)
+            .await
+            .unwrap();
+        assert!(!handled);
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Scripted fixtures for testing the events watchers without any network.
+//!
+//! A [`ScriptedChain`] is a [`Middleware`] serving a scripted head and logs, a
+//! [`ScriptedStore`] is an in-memory store failing its scripted writes, and a
+//! [`RecordingWatcher`] records the deposits it handles, so [`EventWatcher::run`]
+//! (the range stepping, the backoff and the confirmations) can be driven deterministically.
+use std::fmt::Debug;
+use std::ops;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use webb::evm::contract::tornado::{
+    DepositFilter, TornadoContract, TornadoContractEvents,
+};
+use webb::evm::ethers::abi::{self, Token};
+use webb::evm::ethers::contract::{Contract, EthEvent, LogMeta};
+use webb::evm::ethers::providers::{
+    FromErr, Middleware, MockProvider, Provider, ProviderError,
+};
+use webb::evm::ethers::types::{
+    Address, BlockNumber, Filter, FilterBlockOption, Log, ValueOrArray, H256,
+    U256, U64,
+};
+
+use super::{EventWatcher, WatchableContract};
+use crate::config::{CircuitBreakerConfig, SyncFrom};
+use crate::store::mem::InMemoryStore;
+use crate::store::{HistoryStore, HistoryStoreKey};
+
+/// The errors of a [`ScriptedChain`].
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptedError {
+    /// A failure scripted with [`ScriptedChain::fail_next_get_logs`].
+    #[error("scripted failure")]
+    Scripted,
+    /// A call the chain has no script for.
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+}
+
+impl FromErr<ProviderError> for ScriptedError {
+    fn from(src: ProviderError) -> Self {
+        Self::Provider(src)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChainState {
+    head: U64,
+    logs: Vec<Log>,
+    get_logs_failures: usize,
+    requested_ranges: Vec<(U64, U64)>,
+}
+
+/// A chain serving a scripted head and logs.
+///
+/// Only the chain id, the block number and the logs are scripted, the other calls
+/// go to an empty [`MockProvider`] and fail.
+#[derive(Debug, Clone)]
+pub struct ScriptedChain {
+    chain_id: U256,
+    state: Arc<Mutex<ChainState>>,
+    inner: Provider<MockProvider>,
+}
+
+impl ScriptedChain {
+    /// Creates a chain with that chain id, at the genesis block.
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            state: Default::default(),
+            inner: Provider::mocked().0,
+        }
+    }
+
+    /// Moves the head of the chain to that block.
+    pub fn set_head(&self, block_number: u64) {
+        self.state.lock().head = block_number.into();
+    }
+
+    /// Adds a log to the chain.
+    pub fn push_log(&self, log: Log) {
+        self.state.lock().logs.push(log);
+    }
+
+    /// Drops the logs of the blocks from `block_number`, as a reorg replacing them would.
+    pub fn reorg(&self, block_number: u64) {
+        self.state.lock().logs.retain(|log| {
+            log.block_number.unwrap_or_default() < U64::from(block_number)
+        });
+    }
+
+    /// Fails the next `count` requests for logs.
+    pub fn fail_next_get_logs(&self, count: usize) {
+        self.state.lock().get_logs_failures = count;
+    }
+
+    /// The block ranges of the successful requests for logs, in order.
+    pub fn requested_ranges(&self) -> Vec<(u64, u64)> {
+        self.state
+            .lock()
+            .requested_ranges
+            .iter()
+            .map(|(from, to)| (from.as_u64(), to.as_u64()))
+            .collect()
+    }
+}
+
+fn block_of(block: Option<BlockNumber>, head: U64) -> U64 {
+    match block {
+        Some(BlockNumber::Number(n)) => n,
+        Some(BlockNumber::Earliest) => U64::zero(),
+        _ => head,
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for ScriptedChain {
+    type Error = ScriptedError;
+    type Provider = MockProvider;
+    type Inner = Provider<MockProvider>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        Ok(self.chain_id)
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        Ok(self.state.lock().head)
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        let mut state = self.state.lock();
+        if state.get_logs_failures > 0 {
+            state.get_logs_failures -= 1;
+            return Err(ScriptedError::Scripted);
+        }
+        let (from, to) = match filter.block_option {
+            FilterBlockOption::Range {
+                from_block,
+                to_block,
+            } => (
+                block_of(from_block, state.head),
+                block_of(to_block, state.head),
+            ),
+            FilterBlockOption::AtBlockHash(_) => (state.head, state.head),
+        };
+        state.requested_ranges.push((from, to));
+        let logs = state
+            .logs
+            .iter()
+            .filter(|log| {
+                let block = log.block_number.unwrap_or_default();
+                block >= from && block <= to
+            })
+            .filter(|log| match &filter.address {
+                Some(ValueOrArray::Value(address)) => log.address == *address,
+                Some(ValueOrArray::Array(addresses)) => {
+                    addresses.contains(&log.address)
+                }
+                None => true,
+            })
+            .cloned()
+            .collect();
+        Ok(logs)
+    }
+}
+
+/// A `Deposit` log of a tornado contract, at that block.
+pub fn deposit_log(
+    address: Address,
+    block_number: u64,
+    leaf_index: u32,
+) -> Log {
+    let data = abi::encode(&[
+        Token::Uint(leaf_index.into()),
+        Token::Uint(U256::zero()),
+    ]);
+    Log {
+        address,
+        topics: vec![DepositFilter::signature(), H256::random()],
+        data: data.into(),
+        block_hash: Some(H256::random()),
+        block_number: Some(block_number.into()),
+        transaction_hash: Some(H256::random()),
+        transaction_index: Some(U64::zero()),
+        log_index: Some(U256::zero()),
+        ..Default::default()
+    }
+}
+
+/// An in-memory store, failing its scripted writes.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedStore {
+    inner: InMemoryStore,
+    write_failures: Arc<Mutex<usize>>,
+}
+
+impl ScriptedStore {
+    /// Fails the next `count` writes of the last block numbers.
+    pub fn fail_next_writes(&self, count: usize) {
+        *self.write_failures.lock() = count;
+    }
+
+    fn check_write(&self) -> anyhow::Result<()> {
+        let mut failures = self.write_failures.lock();
+        if *failures > 0 {
+            *failures -= 1;
+            anyhow::bail!("scripted write failure");
+        }
+        Ok(())
+    }
+}
+
+impl HistoryStore for ScriptedStore {
+    fn set_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_number: U64,
+    ) -> anyhow::Result<U64> {
+        self.check_write()?;
+        self.inner.set_last_block_number(key, block_number)
+    }
+
+    fn get_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        default_block_number: U64,
+    ) -> anyhow::Result<U64> {
+        self.inner.get_last_block_number(key, default_block_number)
+    }
+
+    fn set_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+        block_number: U64,
+    ) -> anyhow::Result<Option<U64>> {
+        self.check_write()?;
+        self.inner
+            .set_last_event_block_number(key, event, block_number)
+    }
+
+    fn get_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+    ) -> anyhow::Result<Option<U64>> {
+        self.inner.get_last_event_block_number(key, event)
+    }
+
+    fn set_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: U64,
+    ) -> anyhow::Result<()> {
+        self.check_write()?;
+        self.inner.set_synced_from(key, from)
+    }
+
+    fn get_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<U64>> {
+        self.inner.get_synced_from(key)
+    }
+}
+
+/// A tornado contract on a [`ScriptedChain`], polled every second.
+#[derive(Debug, Clone)]
+pub struct ScriptedContract {
+    contract: TornadoContract<ScriptedChain>,
+    /// The block the contract got deployed at.
+    pub deployed_at: U64,
+    /// How many blocks are read at once.
+    pub step: U64,
+    /// How many blocks should be on top of a block, before its events get handled.
+    pub confirmations: U64,
+}
+
+impl ScriptedContract {
+    /// Creates a contract at that address, deployed at the genesis block.
+    pub fn new(address: Address, client: Arc<ScriptedChain>) -> Self {
+        Self {
+            contract: TornadoContract::new(address, client),
+            deployed_at: U64::zero(),
+            step: 100.into(),
+            confirmations: U64::zero(),
+        }
+    }
+}
+
+impl ops::Deref for ScriptedContract {
+    type Target = Contract<ScriptedChain>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.contract
+    }
+}
+
+impl WatchableContract for ScriptedContract {
+    fn deployed_at(&self) -> U64 {
+        self.deployed_at
+    }
+
+    fn sync_from(&self) -> SyncFrom {
+        SyncFrom::DeployedAt
+    }
+
+    fn polling_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn polling_jitter(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn max_events_per_step(&self) -> U64 {
+        self.step
+    }
+
+    fn confirmations(&self) -> U64 {
+        self.confirmations
+    }
+
+    fn print_progress_interval(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn circuit_breaker(&self) -> CircuitBreakerConfig {
+        CircuitBreakerConfig::default()
+    }
+}
+
+/// A watcher recording the block and the leaf index of the deposits it handles.
+#[derive(Debug, Default)]
+pub struct RecordingWatcher {
+    handled: Mutex<Vec<(U64, u32)>>,
+    fail_once_at: Mutex<Option<U64>>,
+}
+
+impl RecordingWatcher {
+    /// Fails the first deposit handled at that block, before recording it.
+    pub fn fail_once_at(&self, block_number: u64) {
+        *self.fail_once_at.lock() = Some(block_number.into());
+    }
+
+    /// The handled deposits, in order.
+    pub fn handled(&self) -> Vec<(u64, u32)> {
+        self.handled
+            .lock()
+            .iter()
+            .map(|(block, leaf_index)| (block.as_u64(), *leaf_index))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl EventWatcher for RecordingWatcher {
+    const TAG: &'static str = "Recording Watcher";
+
+    type Middleware = ScriptedChain;
+
+    type Contract = ScriptedContract;
+
+    type Events = TornadoContractEvents;
+
+    type Store = ScriptedStore;
+
+    async fn handle_event(
+        &self,
+        _store: Arc<Self::Store>,
+        _contract: &Self::Contract,
+        (event, log): (Self::Events, LogMeta),
+    ) -> anyhow::Result<()> {
+        if let TornadoContractEvents::DepositFilter(deposit) = event {
+            let mut fail_once_at = self.fail_once_at.lock();
+            if *fail_once_at == Some(log.block_number) {
+                *fail_once_at = None;
+                anyhow::bail!("scripted failure");
+            }
+            drop(fail_once_at);
+            self.handled
+                .lock()
+                .push((log.block_number, deposit.leaf_index));
+        }
+        Ok(())
+    }
+}
@@ -24,74 +24,362 @@
 //! of an event watcher polls for blocks. Implementations of the event watcher trait define an
 //! action to take when the specified event is found in a block at the `handle_event` api.
 use std::cmp;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use ethereum_types::{U256, U64};
+use ethereum_types::{H256, U256, U64};
 use futures::prelude::*;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rand::Rng;
 
-use webb::{
-    evm::ethers::{
-        contract,
-        providers::{self, Middleware},
-        types,
-        types::transaction,
-    },
-    substrate::{
-        scale,
-        subxt::{
-            self,
-            sp_core::{storage::StorageKey, twox_128},
-            sp_runtime::traits::Header,
-        },
-    },
+#[cfg(feature = "evm")]
+use webb::evm::ethers::{
+    contract,
+    providers::{self, Middleware},
+    types,
+    types::transaction,
+};
+#[cfg(feature = "substrate")]
+use webb::substrate::{
+    scale,
+    subxt::{self, sp_runtime::traits::Header},
 };
 
+use crate::config::{CircuitBreakerConfig, SyncFrom};
+use crate::metrics::{self, Labels, Metric};
 use crate::store::sled::SledQueueKey;
 use crate::store::{
-    BridgeCommand, BridgeKey, HistoryStore, ProposalStore, QueueStore,
+    BridgeCommand, BridgeKey, BridgeRegistryStore, HistoryStore,
+    HistoryStoreKey, LeafCacheStore, ProposalStore, QueueStore, SignedProposal,
 };
 use crate::utils;
 
 /// A module for listening on tornado events.
+#[cfg(feature = "evm")]
 mod tornado_leaves_watcher;
 #[doc(hidden)]
+#[cfg(feature = "evm")]
 pub use tornado_leaves_watcher::*;
 /// A module for listening on anchor events over the DKG.
+#[cfg(feature = "evm")]
 mod anchor_watcher;
 #[doc(hidden)]
+#[cfg(feature = "evm")]
 pub use anchor_watcher::*;
+/// A module for encoding the proposals for the different handler versions.
+#[cfg(feature = "evm")]
+mod proposal_encoding;
+#[doc(hidden)]
+#[cfg(feature = "evm")]
+pub use proposal_encoding::*;
 /// A module for listening on proposal events.
+#[cfg(all(feature = "evm", feature = "substrate"))]
 mod proposal_handler_watcher;
 #[doc(hidden)]
+#[cfg(all(feature = "evm", feature = "substrate"))]
 pub use proposal_handler_watcher::*;
 
 /// A module for listening on Signature Bridge commands and events.
+#[cfg(feature = "evm")]
 mod signature_bridge_watcher;
 #[doc(hidden)]
+#[cfg(feature = "evm")]
 pub use signature_bridge_watcher::*;
 
+/// A module for listening on the proposal events of the Substrate bridge pallet.
+#[cfg(feature = "substrate")]
+mod substrate_bridge_watcher;
+#[doc(hidden)]
+#[cfg(feature = "substrate")]
+pub use substrate_bridge_watcher::*;
+
+/// A module for the circuit breakers of the events watchers.
+mod circuit_breaker;
+#[doc(hidden)]
+pub use circuit_breaker::*;
+
+/// A module for registering the handlers of the individual event types.
+#[cfg(feature = "evm")]
+mod event_handlers;
 #[doc(hidden)]
+#[cfg(feature = "evm")]
+pub use event_handlers::*;
+
+/// A module for routing commands to the registered Signature Bridges.
+mod bridge_registry;
+#[doc(hidden)]
+pub use bridge_registry::*;
+
+/// A module for the scripted chains and stores the events watchers are tested against.
+#[cfg(all(test, feature = "evm"))]
+mod mock;
+
+#[doc(hidden)]
+#[cfg(feature = "evm")]
 pub mod proposal_signing_backend;
 
 /// A watchable contract is a contract used in the [EventWatcher]
 pub trait WatchableContract: Send + Sync {
     /// The block number where this contract is deployed.
-    fn deployed_at(&self) -> types::U64;
+    fn deployed_at(&self) -> U64;
+
+    /// Where to start syncing from, when there is no progress saved yet.
+    fn sync_from(&self) -> SyncFrom;
 
     /// How often this contract should be polled for events.
     fn polling_interval(&self) -> Duration;
 
+    /// The maximum random delay added to the polling interval.
+    fn polling_jitter(&self) -> Duration;
+
     /// How many events to fetch at one request.
-    fn max_events_per_step(&self) -> types::U64;
+    fn max_events_per_step(&self) -> U64;
+
+    /// How many blocks should be on top of a block, before its events get handled.
+    fn confirmations(&self) -> U64;
 
     /// The frequency of printing the sync progress.
     fn print_progress_interval(&self) -> Duration;
+
+    /// The circuit breaker of the events watcher of this contract.
+    fn circuit_breaker(&self) -> CircuitBreakerConfig;
+}
+
+/// Since when the running events watchers are caught up with the chain head, by their
+/// contract and tag, `None` while they are not.
+static SYNC_STATUS: Lazy<
+    RwLock<HashMap<(HistoryStoreKey, &'static str), Option<Instant>>>,
+> = Lazy::new(Default::default);
+
+/// How many times the events watchers got started since the relayer started, by their
+/// contract and tag.
+static WATCHER_STARTS: Lazy<
+    RwLock<HashMap<(HistoryStoreKey, &'static str), u64>>,
+> = Lazy::new(Default::default);
+
+fn set_synced(key: HistoryStoreKey, tag: &'static str, synced: bool) {
+    let mut status = SYNC_STATUS.write();
+    let since = status.entry((key, tag)).or_default();
+    if !synced {
+        *since = None;
+    } else if since.is_none() {
+        *since = Some(Instant::now());
+    }
+}
+
+fn record_watcher_start(key: HistoryStoreKey, tag: &'static str) {
+    *WATCHER_STARTS.write().entry((key, tag)).or_default() += 1;
+}
+
+/// Returns true if all the events watchers of that EVM chain caught up with the chain head.
+///
+/// A chain without any running events watcher has nothing to sync, so it is always synced.
+pub fn is_evm_chain_synced(chain_id: U256) -> bool {
+    SYNC_STATUS
+        .read()
+        .iter()
+        .all(|((key, _), since)| match key {
+            HistoryStoreKey::Evm { chain_id: c, .. } if *c == chain_id => {
+                since.is_some()
+            }
+            _ => true,
+        })
+}
+
+/// Returns how long all the events watchers of that EVM chain are caught up with the chain
+/// head, without any of them falling behind since, `None` if one of them is not synced.
+///
+/// A chain without any running events watcher has nothing to sync, so it is synced for
+/// the whole `uptime` of the relayer.
+pub fn evm_chain_synced_for(
+    chain_id: U256,
+    uptime: Duration,
+) -> Option<Duration> {
+    let status = SYNC_STATUS.read();
+    let mut synced_for = uptime;
+    for ((key, _), since) in status.iter() {
+        match key {
+            HistoryStoreKey::Evm { chain_id: c, .. } if *c == chain_id => {
+                synced_for = cmp::min(synced_for, (*since)?.elapsed());
+            }
+            _ => {}
+        }
+    }
+    Some(synced_for)
+}
+
+/// Returns how many times the events watchers of that EVM chain got restarted, after
+/// failing, since the relayer started.
+pub fn evm_watcher_restarts(chain_id: U256) -> u64 {
+    WATCHER_STARTS
+        .read()
+        .iter()
+        .filter_map(|((key, _), starts)| match key {
+            HistoryStoreKey::Evm { chain_id: c, .. } if *c == chain_id => {
+                Some(starts.saturating_sub(1))
+            }
+            _ => None,
+        })
+        .sum()
+}
+
+/// Returns true if all the events watchers of that contract caught up with the chain head.
+///
+/// A contract without any running events watcher is never synced.
+pub fn is_contract_synced(key: HistoryStoreKey) -> bool {
+    let status = SYNC_STATUS.read();
+    let mut watchers = status.iter().filter(|((k, _), _)| *k == key).peekable();
+    watchers.peek().is_some() && watchers.all(|(_, since)| since.is_some())
+}
+
+/// Adds a random delay, up to `jitter`, to the polling `interval`.
+fn with_jitter(interval: Duration, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return interval;
+    }
+    let jitter_ms = jitter.as_millis() as u64;
+    interval
+        + Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms))
+}
+
+/// The name of the cursor of one kind of events of a watcher, the watchers of the same
+/// contract could handle the same kinds.
+fn event_cursor_name(tag: &str, kind: &str) -> String {
+    format!("{}/{}", tag, kind)
+}
+
+/// Creates the missing cursors of the kinds of events of a watcher.
+///
+/// The new kinds of a watcher that already has cursors are backfilled from the
+/// contract deployment. Without any cursor yet, the watcher either never ran or ran
+/// before having cursors per kind, so they start from the cursor of the contract.
+fn init_event_cursors<S: HistoryStore>(
+    store: &S,
+    key: HistoryStoreKey,
+    tag: &str,
+    kinds: &[&'static str],
+    deployed_at: U64,
+) -> anyhow::Result<()> {
+    let mut missing = Vec::new();
+    for kind in kinds {
+        let name = event_cursor_name(tag, kind);
+        if store
+            .get_last_event_block_number(key.clone(), &name)?
+            .is_none()
+        {
+            missing.push((kind, name));
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let start = if missing.len() == kinds.len() {
+        store.get_last_block_number(key.clone(), deployed_at)?
+    } else {
+        deployed_at
+    };
+    for (kind, name) in missing {
+        tracing::info!(%tag, "Handling the {} events from #{}", kind, start);
+        store.set_last_event_block_number(key.clone(), &name, start)?;
+    }
+    Ok(())
+}
+
+/// The cursors of the events of a watcher, the one of the contract and the ones of the
+/// kinds of events, see [`EventWatcher::event_kind`].
+pub struct EventCursors<'a, S> {
+    store: &'a S,
+    key: HistoryStoreKey,
+    tag: &'static str,
+    contract_block: U64,
+    kinds: HashMap<&'static str, U64>,
+}
+
+impl<'a, S: HistoryStore> EventCursors<'a, S> {
+    /// Moves the cursors forward to that block, once all of its events got handled.
+    ///
+    /// A cursor never moves back, the kinds being backfilled are behind the contract.
+    pub fn advance(&self, block_number: U64) -> anyhow::Result<()> {
+        self.store.set_last_block_number(
+            self.key.clone(),
+            cmp::max(self.contract_block, block_number),
+        )?;
+        for (kind, cursor) in &self.kinds {
+            self.store.set_last_event_block_number(
+                self.key.clone(),
+                &event_cursor_name(self.tag, kind),
+                cmp::max(*cursor, block_number),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A misconfiguration found while handling the events, like a missing bridge or signer.
+///
+/// Retrying would fail the same way, so an events watcher that hits it stops, instead of
+/// retrying forever.
+#[derive(Debug, thiserror::Error)]
+#[error("misconfiguration: {0}")]
+pub struct Misconfiguration(pub String);
+
+/// Caches the leaves of a contract, rolling its cache back from the conflicting leaf on a
+/// [`LeafConflict`](crate::store::LeafConflict): the chain got reorganized under the handled
+/// blocks, so they get handled again once the watcher retries.
+///
+/// The conflict is still returned, so the watcher retries from the rolled back blocks.
+pub fn insert_leaves_or_roll_back<S: LeafCacheStore>(
+    store: &S,
+    key: HistoryStoreKey,
+    leaves: &[(u32, H256)],
+) -> anyhow::Result<()> {
+    let result = store.insert_leaves(key.clone(), leaves);
+    if let Err(e) = &result {
+        if let Some(conflict) = e.downcast_ref::<crate::store::LeafConflict>() {
+            tracing::warn!(
+                "Rolling the leaves of {} back from #{}",
+                key,
+                conflict.index
+            );
+            store.roll_back_leaves(key, conflict.index)?;
+        }
+    }
+    result
+}
+
+/// Classifies an error of the events handlers: a [`Misconfiguration`] is permanent,
+/// anything else is retried, including a [`LeafConflict`](crate::store::LeafConflict)
+/// once the leaves got rolled back, see [`insert_leaves_or_roll_back`].
+fn classify_handler_error(e: anyhow::Error) -> backoff::Error<anyhow::Error> {
+    if e.is::<Misconfiguration>() {
+        backoff::Error::permanent(e)
+    } else {
+        backoff::Error::transient(e)
+    }
+}
+
+/// Classifies an error of a contract call: the RPC failures (timeouts, rate limits) are
+/// retried, while the decoding and ABI failures are permanent, as the contract does not
+/// match the relayer's bindings.
+#[cfg(feature = "evm")]
+fn classify_contract_error<M: providers::Middleware + 'static>(
+    e: contract::ContractError<M>,
+) -> backoff::Error<anyhow::Error> {
+    match e {
+        contract::ContractError::MiddlewareError(_)
+        | contract::ContractError::ProviderError(_) => {
+            backoff::Error::transient(anyhow::Error::from(e))
+        }
+        _ => backoff::Error::permanent(anyhow::Error::from(e)),
+    }
 }
 
 /// A trait for watching events from a watchable contract.
 /// EventWatcher trait exists for deployments that are smart-contract / EVM based
+#[cfg(feature = "evm")]
 #[async_trait::async_trait]
 pub trait EventWatcher {
     const TAG: &'static str;
@@ -108,6 +396,56 @@ pub trait EventWatcher {
         (event, log): (Self::Events, contract::LogMeta),
     ) -> anyhow::Result<()>;
 
+    /// The kinds of events that have their own cursor, see [`EventWatcher::event_kind`].
+    fn event_kinds(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// The kind of that event, if it has its own cursor.
+    ///
+    /// The events with a cursor are handled from the last block handled for their kind,
+    /// so a kind added to an existing watcher is backfilled from the contract deployment,
+    /// without handling the other events again. The events without a kind (the default)
+    /// use the cursor of the whole contract.
+    fn event_kind(&self, _event: &Self::Events) -> Option<&'static str> {
+        None
+    }
+
+    /// Handles all the events found in one page of logs, in order.
+    ///
+    /// By default, it calls [`EventWatcher::handle_event`] for every event, and moves
+    /// the `cursors` forward as soon as all the events of a block got handled, so if
+    /// this returns an error only the events of the block that failed are handled
+    /// again. The cursors only record blocks, so handling the same event twice should
+    /// be harmless.
+    ///
+    /// Watchers could override it to handle the whole page at once (for example, to
+    /// write everything to the store in one operation), the cursors are moved past
+    /// the page once this returns, and the whole page is handled again on an error.
+    async fn handle_events(
+        &self,
+        store: Arc<Self::Store>,
+        contract: &Self::Contract,
+        events: Vec<(Self::Events, contract::LogMeta)>,
+        cursors: &EventCursors<'_, Self::Store>,
+    ) -> anyhow::Result<()> {
+        let mut handled_block = None;
+        for (event, log) in events {
+            let block_number = log.block_number;
+            // the events of the previous blocks are all handled now.
+            if let Some(handled) = handled_block {
+                if block_number > handled {
+                    cursors.advance(handled)?;
+                }
+            }
+            self.handle_event(store.clone(), contract, (event, log))
+                .await?;
+            handled_block = Some(block_number);
+            tracing::trace!("event handled successfully. at #{}", block_number);
+        }
+        Ok(())
+    }
+
     /// Returns a task that should be running in the background
     /// that will watch events
     #[tracing::instrument(
@@ -124,87 +462,157 @@ pub trait EventWatcher {
         store: Arc<Self::Store>,
         contract: Self::Contract,
     ) -> anyhow::Result<()> {
-        let backoff = backoff::ExponentialBackoff {
-            max_elapsed_time: None,
-            ..Default::default()
-        };
+        let chain_id =
+            client.get_chainid().map_err(anyhow::Error::from).await?;
+        let key = HistoryStoreKey::from((chain_id, contract.address()));
+        // a contract that keeps failing is retried slowly, not affecting the others.
+        let (backoff, breaker) = CircuitBreaker::new(
+            contract.circuit_breaker(),
+            key.clone(),
+            Self::TAG,
+        );
         let task = || async {
             let step = contract.max_events_per_step();
             // saves the last time we printed sync progress.
             let mut instant = std::time::Instant::now();
-            let chain_id =
-                client.get_chainid().map_err(anyhow::Error::from).await?;
+            record_watcher_start(key.clone(), Self::TAG);
+            set_synced(key.clone(), Self::TAG, false);
+            // without any saved progress, skip the historical events if asked to.
+            let unset = types::U64::MAX;
+            let saved = store
+                .get_last_block_number((chain_id, contract.address()), unset)?;
+            if contract.sync_from() == SyncFrom::Latest && saved == unset {
+                let head = client
+                    .get_block_number()
+                    .map_err(anyhow::Error::from)
+                    .await?
+                    .saturating_sub(contract.confirmations());
+                // recorded first, so the history is never taken as complete.
+                store.set_synced_from(
+                    (chain_id, contract.address()),
+                    head + 1,
+                )?;
+                store.set_last_block_number(
+                    (chain_id, contract.address()),
+                    head,
+                )?;
+                tracing::info!(
+                    "Skipping the historical events, syncing from #{}",
+                    head
+                );
+            }
+            let kinds = self.event_kinds();
+            init_event_cursors(
+                &*store,
+                key.clone(),
+                Self::TAG,
+                &kinds,
+                contract.deployed_at(),
+            )?;
             // now we start polling for new events.
             loop {
-                let block = store.get_last_block_number(
+                let contract_block = store.get_last_block_number(
                     (chain_id, contract.address()),
                     contract.deployed_at(),
                 )?;
-                let current_block_number = client
+                let mut cursors = HashMap::new();
+                for kind in &kinds {
+                    let cursor = store
+                        .get_last_event_block_number(
+                            key.clone(),
+                            &event_cursor_name(Self::TAG, kind),
+                        )?
+                        .unwrap_or(contract_block);
+                    cursors.insert(*kind, cursor);
+                }
+                // the kinds being backfilled are behind the contract.
+                let block =
+                    cursors.values().copied().fold(contract_block, cmp::min);
+                let latest_block_number = client
                     .get_block_number()
                     .map_err(anyhow::Error::from)
                     .await?;
+                // the blocks without enough confirmations could get reorged,
+                // so we stay behind them.
+                let current_block_number = latest_block_number
+                    .saturating_sub(contract.confirmations());
                 tracing::trace!(
                     "Latest block number: #{}",
                     current_block_number
                 );
                 let dest_block = cmp::min(block + step, current_block_number);
-                // check if we are now on the latest block.
+                // check if we are now on the latest (confirmed) block.
                 let should_cooldown = dest_block == current_block_number;
+                set_synced(key.clone(), Self::TAG, should_cooldown);
                 tracing::trace!("Reading from #{} to #{}", block, dest_block);
-                // Only handle events from found blocks if they are new
-                if dest_block != block {
+                // Only handle events from found blocks if they are new,
+                // the store could be ahead of the confirmed blocks.
+                if dest_block > block {
                     let events_filter = contract
                         .event_with_filter::<Self::Events>(Default::default())
                         .from_block(block + 1)
                         .to_block(dest_block);
                     let found_events = events_filter
                         .query_with_meta()
-                        .map_err(anyhow::Error::from)
+                        .map_err(classify_contract_error)
                         .await?;
 
                     tracing::trace!("Found #{} events", found_events.len());
+                    // skip the events already handled for their kind.
+                    let found_events: Vec<_> = found_events
+                        .into_iter()
+                        .filter(|(event, log)| {
+                            let cursor = self
+                                .event_kind(event)
+                                .and_then(|kind| cursors.get(kind))
+                                .copied()
+                                .unwrap_or(contract_block);
+                            log.block_number > cursor
+                        })
+                        .collect();
 
-                    for (event, log) in found_events {
-                        let result = self
-                            .handle_event(
-                                store.clone(),
-                                &contract,
-                                (event, log.clone()),
-                            )
-                            .await;
-                        match result {
-                            Ok(_) => {
-                                store.set_last_block_number(
-                                    (chain_id, contract.address()),
-                                    log.block_number,
-                                )?;
-                                tracing::trace!(
-                                    "event handled successfully. at #{}",
-                                    log.block_number
-                                );
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    "Error while handling event: {}",
-                                    e
-                                );
-                                tracing::warn!("Restarting event watcher ...");
-                                // this a transient error, so we will retry again.
-                                return Err(backoff::Error::transient(e));
-                            }
-                        }
+                    let events_count = found_events.len();
+                    let event_cursors = EventCursors {
+                        store: &*store,
+                        key: key.clone(),
+                        tag: Self::TAG,
+                        contract_block,
+                        kinds: cursors,
+                    };
+                    let result = self
+                        .handle_events(
+                            store.clone(),
+                            &contract,
+                            found_events,
+                            &event_cursors,
+                        )
+                        .await;
+                    if let Err(e) = result {
+                        tracing::error!("Error while handling events: {}", e);
+                        tracing::warn!("Restarting event watcher ...");
+                        // only the misconfigurations are not retried.
+                        return Err(classify_handler_error(e));
                     }
+                    metrics::add(
+                        Metric::EventsHandled,
+                        Labels::new(
+                            chain_id,
+                            format!("{:?}", contract.address()),
+                            Self::TAG,
+                        ),
+                        events_count as f64,
+                    );
                     // move forward.
-                    store.set_last_block_number(
-                        (chain_id, contract.address()),
-                        dest_block,
-                    )?;
+                    event_cursors.advance(dest_block)?;
                     tracing::trace!("Last saved block number: #{}", dest_block);
                 }
+                breaker.record_success();
                 tracing::trace!("Polled from #{} to #{}", block, dest_block);
                 if should_cooldown {
-                    let duration = contract.polling_interval();
+                    let duration = with_jitter(
+                        contract.polling_interval(),
+                        contract.polling_jitter(),
+                    );
                     tracing::trace!(
                         "Cooldown a bit for {}ms",
                         duration.as_millis()
@@ -243,20 +651,33 @@ pub trait EventWatcher {
                 }
             }
         };
-        backoff::future::retry(backoff, task).await?;
-        Ok(())
+        let result = backoff::future::retry(backoff, task).await;
+        if let Err(e) = &result {
+            tracing::error!(
+                "The events watcher stopped, after a permanent error: {}",
+                e
+            );
+            set_watcher_health(key, Self::TAG, Some(WatcherHealth::Stopped));
+        }
+        result
     }
 }
 
+/// How often a paused bridge is checked again, before handling its commands.
+#[cfg(feature = "evm")]
+const PAUSED_BRIDGE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 /// A Bridge Watcher is a trait for Bridge contracts that not specific for watching events from that contract,
 /// instead it watches for commands sent from other event watchers or services, it helps decouple the event watchers
 /// from the actual action that should be taken depending on the event.
+#[cfg(feature = "evm")]
 #[async_trait::async_trait]
 pub trait BridgeWatcher: EventWatcher
 where
-    Self::Store: ProposalStore<Proposal = ()>
+    Self::Store: ProposalStore<Proposal = SignedProposal>
         + QueueStore<transaction::eip2718::TypedTransaction, Key = SledQueueKey>
-        + QueueStore<BridgeCommand, Key = SledQueueKey>,
+        + QueueStore<BridgeCommand, Key = SledQueueKey>
+        + BridgeRegistryStore,
 {
     async fn handle_cmd(
         &self,
@@ -265,6 +686,12 @@ where
         cmd: BridgeCommand,
     ) -> anyhow::Result<()>;
 
+    /// Whether the bridge is paused, its commands are then held in the queue
+    /// until it gets unpaused, instead of sending transactions that would revert.
+    fn is_paused(&self, _key: BridgeKey) -> bool {
+        false
+    }
+
     /// Returns a task that should be running in the background
     /// that will watch for all commands
     #[tracing::instrument(
@@ -280,6 +707,7 @@ where
         client: Arc<Self::Middleware>,
         store: Arc<Self::Store>,
         contract: Self::Contract,
+        bridge_registry: BridgeRegistry<Self::Store>,
     ) -> anyhow::Result<()> {
         let backoff = backoff::ExponentialBackoff {
             max_elapsed_time: None,
@@ -289,13 +717,29 @@ where
             let my_address = contract.address();
             let my_chain_id =
                 client.get_chainid().map_err(anyhow::Error::from).await?;
-            let bridge_key = BridgeKey::new(my_address, my_chain_id);
+            let my_typed_chain_id =
+                crate::proposals::evm_typed_chain_id(my_chain_id)
+                    .map_err(backoff::Error::permanent)?;
+            let bridge_key = BridgeKey::new(my_address, my_typed_chain_id);
             let key = SledQueueKey::from_bridge_key(bridge_key);
-            while let Some(command) = store.dequeue_item(key)? {
+            loop {
+                if self.is_paused(bridge_key) {
+                    tokio::time::sleep(PAUSED_BRIDGE_POLL_INTERVAL).await;
+                    continue;
+                }
+                let command = match QueueStore::<BridgeCommand>::dequeue_item(
+                    &store, key,
+                )? {
+                    Some(command) => command,
+                    None => break,
+                };
+                let command_name = command.name();
                 let result =
                     self.handle_cmd(store.clone(), &contract, command).await;
                 match result {
                     Ok(_) => {
+                        bridge_registry
+                            .record_handled(bridge_key, command_name);
                         tokio::time::sleep(Duration::from_millis(500)).await;
                         continue;
                     }
@@ -318,17 +762,26 @@ where
 }
 
 /// Type alias for Substrate block number.
+#[cfg(feature = "substrate")]
 pub type BlockNumberOf<T> =
     <<T as SubstrateEventWatcher>::RuntimeConfig as subxt::Config>::BlockNumber;
 /// Represents a Substrate event watcher.
+#[cfg(feature = "substrate")]
 #[async_trait::async_trait]
 pub trait SubstrateEventWatcher {
     const TAG: &'static str;
     type RuntimeConfig: subxt::Config + Send + Sync + 'static;
     type Api: From<subxt::Client<Self::RuntimeConfig>> + Send + Sync;
-    type Event: subxt::Event + Send + Sync;
+    type Event: utils::DecodeEvent + Send + Sync;
     type Store: HistoryStore;
 
+    /// The id of the tree (or the pallet) this watcher is following,
+    /// it is used along with the chain id to key the history of the watcher.
+    ///
+    /// Every watcher of the same chain must use a different id, the watchers following
+    /// a whole pallet use the ones in [`crate::store::pallet_tree_ids`].
+    fn tree_id(&self) -> u32;
+
     async fn handle_event(
         &self,
         store: Arc<Self::Store>,
@@ -341,14 +794,13 @@ pub trait SubstrateEventWatcher {
     #[tracing::instrument(
         skip_all,
         fields(
-            node = %node_name,
             chain_id = %chain_id,
+            tree_id = %self.tree_id(),
             tag = %Self::TAG
         )
     )]
     async fn run(
         &self,
-        node_name: String,
         chain_id: U256,
         client: subxt::Client<Self::RuntimeConfig>,
         store: Arc<Self::Store>,
@@ -357,23 +809,6 @@ pub trait SubstrateEventWatcher {
             max_elapsed_time: None,
             ..Default::default()
         };
-        // The storage Key, where all events are stored.
-        struct SystemEvents(StorageKey);
-
-        impl Default for SystemEvents {
-            fn default() -> Self {
-                let mut storage_key = twox_128(b"System").to_vec();
-                storage_key.extend(twox_128(b"Events").to_vec());
-                Self(StorageKey(storage_key))
-            }
-        }
-
-        impl From<SystemEvents> for StorageKey {
-            fn from(key: SystemEvents) -> Self {
-                key.0
-            }
-        }
-
         let task = || async {
             let mut instant = std::time::Instant::now();
             let step = U64::from(50u64);
@@ -381,12 +816,22 @@ pub trait SubstrateEventWatcher {
             let api: Arc<Self::Api> = Arc::new(client_api.to_runtime_api());
             let rpc = client.rpc();
             let decoder = client.events_decoder();
-            let keys = vec![StorageKey::from(SystemEvents::default())];
+            let keys = vec![utils::system_events_key()];
+            let spec_version = utils::spec_version(&client).await?;
             loop {
+                // the events can't be decoded with a stale metadata, so stop here and let
+                // the caller re-create the client.
+                match utils::ensure_spec_version(&client, spec_version).await {
+                    Err(e) if e.is::<utils::RuntimeUpgraded>() => {
+                        tracing::warn!("{}, stopping the watcher", e);
+                        return Err(backoff::Error::permanent(e));
+                    }
+                    result => result?,
+                }
                 // now we start polling for new events.
                 // get the latest seen block number.
                 let block = store.get_last_block_number(
-                    (node_name.clone(), chain_id),
+                    (chain_id, self.tree_id()),
                     1u64.into(),
                 )?;
                 let latest_head =
@@ -489,9 +934,17 @@ pub trait SubstrateEventWatcher {
                                 let current_block_number =
                                     U64::from(current_block_number);
                                 store.set_last_block_number(
-                                    (node_name.clone(), chain_id),
+                                    (chain_id, self.tree_id()),
                                     current_block_number,
                                 )?;
+                                metrics::inc(
+                                    Metric::EventsHandled,
+                                    Labels::new(
+                                        chain_id,
+                                        self.tree_id(),
+                                        Self::TAG,
+                                    ),
+                                );
                                 tracing::trace!(
                                     "event handled successfully. at #{}",
                                     current_block_number
@@ -510,7 +963,7 @@ pub trait SubstrateEventWatcher {
                     }
                     // move forward.
                     store.set_last_block_number(
-                        (node_name.clone(), chain_id),
+                        (chain_id, self.tree_id()),
                         dest_block,
                     )?;
                     tracing::trace!("Last saved block number: #{}", dest_block);
@@ -553,16 +1006,259 @@ pub trait SubstrateEventWatcher {
 mod tests {
     use std::sync::Arc;
 
+    #[cfg(feature = "substrate")]
     use webb::substrate::dkg_runtime;
+    #[cfg(feature = "substrate")]
     use webb::substrate::dkg_runtime::api::system;
 
     use crate::store::sled::SledStore;
 
     use super::*;
 
+    #[test]
+    fn chains_are_synced_once_every_watcher_is() {
+        let chain_id = U256::from(31337);
+        let anchor = HistoryStoreKey::from((
+            chain_id,
+            ethereum_types::Address::random(),
+        ));
+        let bridge = HistoryStoreKey::from((
+            chain_id,
+            ethereum_types::Address::random(),
+        ));
+        assert!(is_evm_chain_synced(chain_id));
+        set_synced(anchor.clone(), "Anchor Watcher", true);
+        set_synced(bridge, "Bridge Watcher", false);
+        assert!(!is_evm_chain_synced(chain_id));
+        set_synced(anchor, "Anchor Watcher", false);
+        assert!(!is_evm_chain_synced(chain_id));
+        // other chains are not affected.
+        assert!(is_evm_chain_synced(U256::from(31338)));
+    }
+
+    #[test]
+    fn chains_are_synced_for_as_long_as_their_latest_synced_watcher() {
+        let chain_id = U256::from(31339);
+        let uptime = Duration::from_secs(60);
+        let anchor = HistoryStoreKey::from((
+            chain_id,
+            ethereum_types::Address::random(),
+        ));
+        let bridge = HistoryStoreKey::from((
+            chain_id,
+            ethereum_types::Address::random(),
+        ));
+        assert_eq!(evm_chain_synced_for(chain_id, uptime), Some(uptime));
+        set_synced(anchor.clone(), "Anchor Watcher", true);
+        set_synced(bridge.clone(), "Bridge Watcher", false);
+        assert_eq!(evm_chain_synced_for(chain_id, uptime), None);
+        set_synced(bridge.clone(), "Bridge Watcher", true);
+        std::thread::sleep(Duration::from_millis(20));
+        // staying synced keeps the time it got synced.
+        set_synced(bridge, "Bridge Watcher", true);
+        let synced_for = evm_chain_synced_for(chain_id, uptime).unwrap();
+        assert!(synced_for >= Duration::from_millis(20));
+        assert!(synced_for < uptime);
+
+        record_watcher_start(anchor.clone(), "Anchor Watcher");
+        assert_eq!(evm_watcher_restarts(chain_id), 0);
+        record_watcher_start(anchor.clone(), "Anchor Watcher");
+        record_watcher_start(anchor, "Anchor Watcher");
+        assert_eq!(evm_watcher_restarts(chain_id), 2);
+    }
+
+    #[test]
+    fn cooldowns_get_a_bounded_jitter() {
+        let interval = Duration::from_millis(1_000);
+        assert_eq!(with_jitter(interval, Duration::ZERO), interval);
+        let jitter = Duration::from_millis(500);
+        for _ in 0..100 {
+            let cooldown = with_jitter(interval, jitter);
+            assert!(cooldown >= interval && cooldown <= interval + jitter);
+        }
+    }
+
+    #[test]
+    fn new_event_kinds_are_backfilled_from_the_deployment() {
+        let store = crate::store::mem::InMemoryStore::default();
+        let key = HistoryStoreKey::from((
+            U256::from(5),
+            ethereum_types::H160::zero(),
+        ));
+        let tag = "Anchor Watcher For Leaves";
+        let deployed_at = U64::from(10);
+        // a watcher that ran before having cursors per kind.
+        store
+            .set_last_block_number(key.clone(), 500.into())
+            .unwrap();
+        init_event_cursors(&store, key.clone(), tag, &["Deposit"], deployed_at)
+            .unwrap();
+        let cursor = |kind| {
+            store
+                .get_last_event_block_number(
+                    key.clone(),
+                    &event_cursor_name(tag, kind),
+                )
+                .unwrap()
+        };
+        assert_eq!(cursor("Deposit"), Some(500.into()));
+        // then a new kind of events gets a handler.
+        init_event_cursors(
+            &store,
+            key.clone(),
+            tag,
+            &["Deposit", "EdgeAddition"],
+            deployed_at,
+        )
+        .unwrap();
+        assert_eq!(cursor("Deposit"), Some(500.into()));
+        assert_eq!(cursor("EdgeAddition"), Some(deployed_at));
+    }
+
+    #[test]
+    fn only_misconfigurations_stop_the_watchers() {
+        let e = anyhow::Error::from(Misconfiguration("no signer".into()))
+            .context("while handling the event");
+        assert!(matches!(
+            classify_handler_error(e),
+            backoff::Error::Permanent(_)
+        ));
+        let e = anyhow::anyhow!("connection reset");
+        assert!(matches!(
+            classify_handler_error(e),
+            backoff::Error::Transient { .. }
+        ));
+    }
+
+    /// Runs the watcher against the scripted chain for 30 seconds, in virtual time.
+    ///
+    /// Returns the result of the watcher, if it stopped before.
+    #[cfg(feature = "evm")]
+    async fn run_for_a_while(
+        watcher: &mock::RecordingWatcher,
+        chain: &Arc<mock::ScriptedChain>,
+        store: &Arc<mock::ScriptedStore>,
+        contract: &mock::ScriptedContract,
+    ) -> Option<anyhow::Result<()>> {
+        let run = watcher.run(chain.clone(), store.clone(), contract.clone());
+        tokio::time::timeout(Duration::from_secs(30), run)
+            .await
+            .ok()
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test(start_paused = true)]
+    async fn watchers_read_the_blocks_in_steps() {
+        let chain = Arc::new(mock::ScriptedChain::new(5));
+        let store = Arc::new(mock::ScriptedStore::default());
+        let address = ethereum_types::Address::random();
+        let mut contract = mock::ScriptedContract::new(address, chain.clone());
+        contract.step = 10.into();
+        chain.set_head(35);
+        for (block, leaf_index) in [(5, 0), (15, 1), (33, 2)] {
+            chain.push_log(mock::deposit_log(address, block, leaf_index));
+        }
+        let watcher = mock::RecordingWatcher::default();
+        let stopped =
+            run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert!(stopped.is_none());
+        assert_eq!(
+            chain.requested_ranges(),
+            vec![(1, 10), (11, 20), (21, 30), (31, 35)]
+        );
+        assert_eq!(watcher.handled(), vec![(5, 0), (15, 1), (33, 2)]);
+        let last_block = store
+            .get_last_block_number((U256::from(5), address), U64::zero())
+            .unwrap();
+        assert_eq!(last_block, U64::from(35));
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test(start_paused = true)]
+    async fn watchers_stay_behind_the_unconfirmed_blocks() {
+        let chain = Arc::new(mock::ScriptedChain::new(5));
+        let store = Arc::new(mock::ScriptedStore::default());
+        let address = ethereum_types::Address::random();
+        let mut contract = mock::ScriptedContract::new(address, chain.clone());
+        contract.confirmations = 5.into();
+        chain.set_head(20);
+        chain.push_log(mock::deposit_log(address, 12, 0));
+        chain.push_log(mock::deposit_log(address, 18, 1));
+        let watcher = mock::RecordingWatcher::default();
+        run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert_eq!(watcher.handled(), vec![(12, 0)]);
+        // the unconfirmed deposit gets reorged into another block.
+        chain.reorg(16);
+        chain.push_log(mock::deposit_log(address, 17, 1));
+        chain.set_head(25);
+        run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert_eq!(watcher.handled(), vec![(12, 0), (17, 1)]);
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test(start_paused = true)]
+    async fn watchers_back_off_and_retry_the_failed_requests() {
+        let chain = Arc::new(mock::ScriptedChain::new(5));
+        let store = Arc::new(mock::ScriptedStore::default());
+        let address = ethereum_types::Address::random();
+        let contract = mock::ScriptedContract::new(address, chain.clone());
+        chain.set_head(10);
+        chain.push_log(mock::deposit_log(address, 5, 0));
+        chain.fail_next_get_logs(3);
+        let watcher = mock::RecordingWatcher::default();
+        let stopped =
+            run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert!(stopped.is_none());
+        assert_eq!(chain.requested_ranges(), vec![(1, 10)]);
+        assert_eq!(watcher.handled(), vec![(5, 0)]);
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test(start_paused = true)]
+    async fn pages_are_handled_again_after_a_failed_write() {
+        let chain = Arc::new(mock::ScriptedChain::new(5));
+        let store = Arc::new(mock::ScriptedStore::default());
+        let address = ethereum_types::Address::random();
+        let contract = mock::ScriptedContract::new(address, chain.clone());
+        chain.set_head(10);
+        chain.push_log(mock::deposit_log(address, 5, 0));
+        store.fail_next_writes(1);
+        let watcher = mock::RecordingWatcher::default();
+        run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert_eq!(watcher.handled(), vec![(5, 0), (5, 0)]);
+        let last_block = store
+            .get_last_block_number((U256::from(5), address), U64::zero())
+            .unwrap();
+        assert_eq!(last_block, U64::from(10));
+    }
+
+    #[cfg(feature = "evm")]
+    #[tokio::test(start_paused = true)]
+    async fn only_the_failed_block_is_handled_again() {
+        let chain = Arc::new(mock::ScriptedChain::new(5));
+        let store = Arc::new(mock::ScriptedStore::default());
+        let address = ethereum_types::Address::random();
+        let contract = mock::ScriptedContract::new(address, chain.clone());
+        chain.set_head(10);
+        chain.push_log(mock::deposit_log(address, 4, 0));
+        chain.push_log(mock::deposit_log(address, 7, 1));
+        let watcher = mock::RecordingWatcher::default();
+        watcher.fail_once_at(7);
+        run_for_a_while(&watcher, &chain, &store, &contract).await;
+        assert_eq!(watcher.handled(), vec![(4, 0), (7, 1)]);
+        let last_block = store
+            .get_last_block_number((U256::from(5), address), U64::zero())
+            .unwrap();
+        assert_eq!(last_block, U64::from(10));
+    }
+
+    #[cfg(feature = "substrate")]
     #[derive(Debug, Clone, Default)]
-    struct RemarkedEventWatcher;
+    struct RemarkedEventWatcher {
+        tree_id: u32,
+    }
 
+    #[cfg(feature = "substrate")]
     #[async_trait::async_trait]
     impl SubstrateEventWatcher for RemarkedEventWatcher {
         const TAG: &'static str = "Remarked Event Watcher";
@@ -578,6 +1274,10 @@ mod tests {
 
         type Store = SledStore;
 
+        fn tree_id(&self) -> u32 {
+            self.tree_id
+        }
+
         async fn handle_event(
             &self,
             _store: Arc<Self::Store>,
@@ -593,6 +1293,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "substrate")]
     fn setup_logger() -> anyhow::Result<()> {
         let log_level = tracing::Level::TRACE;
         let env_filter = tracing_subscriber::EnvFilter::from_default_env()
@@ -608,16 +1309,16 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "substrate")]
     #[tokio::test]
     #[ignore = "need to be run manually"]
     async fn substrate_event_watcher_should_work() -> anyhow::Result<()> {
         setup_logger()?;
-        let node_name = String::from("test-node");
         let chain_id = U256::from(5u32);
         let store = Arc::new(SledStore::temporary()?);
         let client = subxt::ClientBuilder::new().build().await?;
         let watcher = RemarkedEventWatcher::default();
-        watcher.run(node_name, chain_id, client, store).await?;
+        watcher.run(chain_id, client, store).await?;
         Ok(())
     }
 }
@@ -0,0 +1,234 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::ops;
+
+use webb_proposals::AnchorUpdateProposal;
+
+use crate::proposals::{
+    FunctionSignature, Nonce, ProposalHeader, ResourceId, TargetSystem,
+    TypedChainId,
+};
+
+pub use crate::config::AnchorUpdateProposalEncoding;
+
+/// An anchor update proposal, along with the encoding expected by the handler
+/// of its target anchor.
+///
+/// Signing backends should always use [`VersionedAnchorUpdateProposal::encode`] for
+/// getting the proposal bytes, so anchors with different handler versions could
+/// coexist on the same relayer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionedAnchorUpdateProposal {
+    proposal: AnchorUpdateProposal,
+    encoding: AnchorUpdateProposalEncoding,
+}
+
+impl VersionedAnchorUpdateProposal {
+    /// Creates a new versioned anchor update proposal.
+    pub fn new(
+        proposal: AnchorUpdateProposal,
+        encoding: AnchorUpdateProposalEncoding,
+    ) -> Self {
+        Self { proposal, encoding }
+    }
+
+    /// The encoding of this proposal.
+    pub fn encoding(&self) -> AnchorUpdateProposalEncoding {
+        self.encoding
+    }
+
+    /// Returns the resource id of the target anchor, as expected by its handler.
+    pub fn encoded_resource_id(&self) -> [u8; ResourceId::LENGTH] {
+        let resource_id = self.proposal.header().resource_id();
+        match self.encoding {
+            AnchorUpdateProposalEncoding::V1 => resource_id.into_bytes(),
+            AnchorUpdateProposalEncoding::Legacy => {
+                legacy_resource_id(resource_id)
+            }
+        }
+    }
+
+    /// Encodes the proposal, as expected by the handler of the target anchor.
+    ///
+    /// The legacy encoding is:
+    /// ```text
+    /// ┌───────────────┬─────────────┬──────────┬───────────────┬─────────────┬──────────┐
+    /// │ ResourceId 32B│ FuncSig 4B  │ Nonce 4B │ SrcChainId 32B│ LeafIdx 4B  │ Root 32B │
+    /// └───────────────┴─────────────┴──────────┴───────────────┴─────────────┴──────────┘
+    /// ```
+    /// where the chain ids are plain (untyped) chain ids.
+    pub fn encode(&self) -> Vec<u8> {
+        match self.encoding {
+            AnchorUpdateProposalEncoding::V1 => {
+                self.proposal.to_bytes().to_vec()
+            }
+            AnchorUpdateProposalEncoding::Legacy => {
+                let header = self.proposal.header();
+                let mut bytes = Vec::with_capacity(LEGACY_LENGTH);
+                bytes.extend_from_slice(&self.encoded_resource_id());
+                bytes
+                    .extend_from_slice(&header.function_signature().to_bytes());
+                bytes.extend_from_slice(&header.nonce().to_bytes());
+                let mut src_chain_id = [0u8; 32];
+                src_chain_id[28..].copy_from_slice(
+                    &self
+                        .proposal
+                        .src_chain()
+                        .underlying_chain_id()
+                        .to_be_bytes(),
+                );
+                bytes.extend_from_slice(&src_chain_id);
+                bytes.extend_from_slice(
+                    &self.proposal.latest_leaf_index().to_be_bytes(),
+                );
+                bytes.extend_from_slice(self.proposal.merkle_root());
+                bytes
+            }
+        }
+    }
+
+    /// Decodes a proposal that got encoded using the given encoding.
+    ///
+    /// Returns an error if the bytes are not a valid proposal for that encoding.
+    pub fn decode(
+        bytes: &[u8],
+        encoding: AnchorUpdateProposalEncoding,
+    ) -> anyhow::Result<Self> {
+        let proposal = match encoding {
+            AnchorUpdateProposalEncoding::V1 => {
+                anyhow::ensure!(
+                    bytes.len() == AnchorUpdateProposal::LENGTH,
+                    "invalid anchor update proposal length: {}",
+                    bytes.len()
+                );
+                let mut buf = [0u8; AnchorUpdateProposal::LENGTH];
+                buf.copy_from_slice(bytes);
+                AnchorUpdateProposal::from(buf)
+            }
+            AnchorUpdateProposalEncoding::Legacy => {
+                anyhow::ensure!(
+                    bytes.len() == LEGACY_LENGTH,
+                    "invalid legacy anchor update proposal length: {}",
+                    bytes.len()
+                );
+                let mut target_system = [0u8; TargetSystem::LENGTH];
+                target_system.copy_from_slice(&bytes[0..26]);
+                let mut u32_buf = [0u8; 4];
+                u32_buf.copy_from_slice(&bytes[28..32]);
+                let chain_id = u32::from_be_bytes(u32_buf);
+                let resource_id = ResourceId::new(
+                    TargetSystem::from(target_system),
+                    TypedChainId::Evm(chain_id),
+                );
+                let mut function_signature = [0u8; 4];
+                function_signature.copy_from_slice(&bytes[32..36]);
+                let mut nonce = [0u8; 4];
+                nonce.copy_from_slice(&bytes[36..40]);
+                let header = ProposalHeader::new(
+                    resource_id,
+                    FunctionSignature::from(function_signature),
+                    Nonce::from(nonce),
+                );
+                anyhow::ensure!(
+                    bytes[40..68].iter().all(|b| *b == 0),
+                    "legacy source chain id does not fit in 32 bits"
+                );
+                u32_buf.copy_from_slice(&bytes[68..72]);
+                let src_chain = TypedChainId::Evm(u32::from_be_bytes(u32_buf));
+                u32_buf.copy_from_slice(&bytes[72..76]);
+                let leaf_index = u32::from_be_bytes(u32_buf);
+                let mut merkle_root = [0u8; 32];
+                merkle_root.copy_from_slice(&bytes[76..108]);
+                AnchorUpdateProposal::new(
+                    header,
+                    src_chain,
+                    leaf_index,
+                    merkle_root,
+                )
+            }
+        };
+        Ok(Self { proposal, encoding })
+    }
+}
+
+impl ops::Deref for VersionedAnchorUpdateProposal {
+    type Target = AnchorUpdateProposal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.proposal
+    }
+}
+
+/// Length of the legacy anchor update proposal in bytes.
+const LEGACY_LENGTH: usize = 32 + 4 + 4 + 32 + 4 + 32;
+
+/// Legacy handlers identify resources by the target system followed by the
+/// plain chain id, without the chain type.
+fn legacy_resource_id(resource_id: ResourceId) -> [u8; ResourceId::LENGTH] {
+    let mut bytes = resource_id.into_bytes();
+    let chain_id = resource_id.typed_chain_id().underlying_chain_id();
+    bytes[TargetSystem::LENGTH..].copy_from_slice(&[0u8; 6]);
+    bytes[28..].copy_from_slice(&chain_id.to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webb::evm::ethers::types;
+
+    fn proposal() -> AnchorUpdateProposal {
+        let target_system =
+            TargetSystem::new_contract_address(types::Address::random());
+        let resource_id = ResourceId::new(target_system, TypedChainId::Evm(4));
+        let header = ProposalHeader::new(
+            resource_id,
+            FunctionSignature::from([68, 52, 123, 169]),
+            Nonce::new(12),
+        );
+        AnchorUpdateProposal::new(header, TypedChainId::Evm(5), 12, [7u8; 32])
+    }
+
+    #[test]
+    fn encodings_roundtrip() {
+        let proposal = proposal();
+        for encoding in [
+            AnchorUpdateProposalEncoding::V1,
+            AnchorUpdateProposalEncoding::Legacy,
+        ] {
+            let versioned =
+                VersionedAnchorUpdateProposal::new(proposal, encoding);
+            let bytes = versioned.encode();
+            let decoded =
+                VersionedAnchorUpdateProposal::decode(&bytes, encoding)
+                    .unwrap();
+            assert_eq!(decoded, versioned);
+        }
+        let v1 = VersionedAnchorUpdateProposal::new(
+            proposal,
+            AnchorUpdateProposalEncoding::V1,
+        );
+        assert_eq!(v1.encode(), proposal.to_bytes().to_vec());
+        let legacy = VersionedAnchorUpdateProposal::new(
+            proposal,
+            AnchorUpdateProposalEncoding::Legacy,
+        );
+        let bytes = legacy.encode();
+        assert_eq!(bytes.len(), LEGACY_LENGTH);
+        // plain chain ids, no chain type.
+        assert_eq!(&bytes[26..32], &[0, 0, 0, 0, 0, 4]);
+        assert_eq!(&bytes[68..72], &5u32.to_be_bytes());
+    }
+}
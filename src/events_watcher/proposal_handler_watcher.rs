@@ -18,22 +18,21 @@ use webb::substrate::dkg_runtime::api::dkg_proposal_handler;
 use webb::substrate::dkg_runtime::api::runtime_types::webb_proposals::header::TypedChainId;
 use webb::substrate::{dkg_runtime, subxt};
 
-use crate::config::{self, Contract};
-use crate::store::sled::{SledQueueKey, SledStore};
-use crate::store::{BridgeCommand, BridgeKey, QueueStore};
+use crate::store::cache::RelayerStore;
+use crate::store::{pallet_tree_ids, BridgeCommand};
 
-use super::{BlockNumberOf, SubstrateEventWatcher};
+use super::{BlockNumberOf, BridgeRegistry, SubstrateEventWatcher};
 
 /// A ProposalHandler watcher for the DKG Substrate runtime.
 /// It watches for the `ProposalSigned` event and sends the proposal to the signature bridge.
 #[derive(Clone, Debug)]
 pub struct ProposalHandlerWatcher {
-    webb_config: config::WebbRelayerConfig,
+    bridge_registry: BridgeRegistry<RelayerStore>,
 }
 
 impl ProposalHandlerWatcher {
-    pub fn new(webb_config: config::WebbRelayerConfig) -> Self {
-        Self { webb_config }
+    pub fn new(bridge_registry: BridgeRegistry<RelayerStore>) -> Self {
+        Self { bridge_registry }
     }
 }
 
@@ -50,11 +49,15 @@ impl SubstrateEventWatcher for ProposalHandlerWatcher {
 
     type Event = dkg_proposal_handler::events::ProposalSigned;
 
-    type Store = SledStore;
+    type Store = RelayerStore;
+
+    fn tree_id(&self) -> u32 {
+        pallet_tree_ids::DKG_PROPOSAL_HANDLER
+    }
 
     async fn handle_event(
         &self,
-        store: Arc<Self::Store>,
+        _store: Arc<Self::Store>,
         _api: Arc<Self::Api>,
         (event, block_number): (Self::Event, BlockNumberOf<Self>),
     ) -> anyhow::Result<()> {
@@ -67,28 +70,14 @@ impl SubstrateEventWatcher for ProposalHandlerWatcher {
             ?event.target_chain,
             ?block_number,
         );
-        let maybe_bridge_key = match event.target_chain {
+        let maybe_chain_id = match event.target_chain {
             TypedChainId::None => {
                 tracing::warn!(
                     "Received `ProposalSigned` Event with no chain id"
                 );
                 None
             }
-            TypedChainId::Evm(id) => self
-                .webb_config
-                .evm
-                .values()
-                .find(|c| c.chain_id == u64::from(id))
-                .and_then(|c| {
-                    c.contracts.iter().find(|contract| {
-                        matches!(contract, Contract::SignatureBridge(_))
-                    })
-                })
-                .and_then(|contract| match contract {
-                    Contract::SignatureBridge(bridge) => Some(bridge),
-                    _ => None,
-                })
-                .map(|config| BridgeKey::new(config.common.address, id.into())),
+            TypedChainId::Evm(id) => Some(id),
             TypedChainId::Substrate(_) => {
                 tracing::warn!(
                     "Unhandled `ProposalSigned` Event with substrate chain id"
@@ -120,39 +109,38 @@ impl SubstrateEventWatcher for ProposalHandlerWatcher {
                 None
             }
         };
-        tracing::debug!(?maybe_bridge_key, "Sending Proposal to the bridge");
-        // now we just signal the bridge with the proposal.
-        let bridge_key = match maybe_bridge_key {
-            Some(bridge_key) => bridge_key,
-            None => {
-                tracing::warn!(
-                    ?event.target_chain,
-                    "No bridge configured for that chain, skipping",
-                );
-                return Ok(());
-            }
+        let chain_id = match maybe_chain_id {
+            Some(chain_id) => chain_id,
+            None => return Ok(()),
         };
         tracing::debug!(
-            %bridge_key,
+            chain_id,
             proposal = ?event,
-            "Signaling Signature Bridge to execute proposal",
+            "Signaling Signature Bridges to execute proposal",
         );
         tracing::event!(
             target: crate::probe::TARGET,
             tracing::Level::DEBUG,
             kind = %crate::probe::Kind::SigningBackend,
             backend = "DKG",
-            signal_bridge = %bridge_key,
+            signal_bridges_on = chain_id,
             data = ?hex::encode(&event.data),
             signature = ?hex::encode(&event.signature),
         );
-        store.enqueue_item(
-            SledQueueKey::from_bridge_key(bridge_key),
+        // now we just signal every bridge on that chain with the proposal.
+        let sent_to = self.bridge_registry.broadcast(
+            crate::proposals::TypedChainId::Evm(chain_id),
             BridgeCommand::ExecuteProposalWithSignature {
-                data: event.data.clone(),
+                data: event.data,
                 signature: event.signature,
             },
         )?;
+        if sent_to == 0 {
+            tracing::warn!(
+                chain_id,
+                "No bridge registered for that chain, skipping",
+            );
+        }
         Ok(())
     }
 }
@@ -1,20 +1,35 @@
+use std::sync::Arc;
+
 use futures::StreamExt;
 use webb::substrate::dkg_runtime::api::runtime_types::webb_proposals::header::{TypedChainId, ResourceId, Nonce};
 use webb::substrate::subxt::sp_core::sr25519::Pair as Sr25519Pair;
 use webb::substrate::{dkg_runtime, subxt};
-use webb_proposals::AnchorUpdateProposal;
+use crate::context::RelayerContext;
+use crate::events_watcher::VersionedAnchorUpdateProposal;
+use crate::proposals::ResourceIdUpdateProposal;
+use crate::utils;
 
 type DkgConfig = subxt::DefaultConfig;
 type DkgRuntimeApi =
     dkg_runtime::api::RuntimeApi<DkgConfig, subxt::DefaultExtra<DkgConfig>>;
 
+/// The runtime API of the DKG node, along with the spec version of the runtime its
+/// metadata got fetched at.
+struct DkgConnection<R, C: subxt::Config> {
+    client: subxt::Client<C>,
+    api: Arc<R>,
+    spec_version: u32,
+}
+
 /// A ProposalSigningBackend that uses the DKG System for Signing Proposals.
 pub struct DkgProposalSigningBackend<R, C>
 where
     R: From<subxt::Client<C>>,
     C: subxt::Config,
 {
-    api: R,
+    ctx: RelayerContext,
+    node_name: String,
+    connection: tokio::sync::Mutex<DkgConnection<R, C>>,
     pair: subxt::PairSigner<C, subxt::DefaultExtra<C>, Sr25519Pair>,
 }
 
@@ -23,28 +38,63 @@ where
     R: From<subxt::Client<C>>,
     C: subxt::Config,
 {
-    pub fn new(
+    pub async fn new(
+        ctx: RelayerContext,
+        node_name: String,
         client: subxt::Client<C>,
         pair: subxt::PairSigner<C, subxt::DefaultExtra<C>, Sr25519Pair>,
-    ) -> Self {
-        Self {
-            api: client.to_runtime_api(),
+    ) -> anyhow::Result<Self> {
+        let spec_version = utils::spec_version(&client).await?;
+        let connection = DkgConnection {
+            api: Arc::new(client.clone().to_runtime_api()),
+            client,
+            spec_version,
+        };
+        Ok(Self {
+            ctx,
+            node_name,
+            connection: tokio::sync::Mutex::new(connection),
             pair,
+        })
+    }
+
+    /// Returns the runtime API of the DKG node.
+    ///
+    /// If the runtime of the node got upgraded, the API is re-created with a fresh client,
+    /// so the extrinsics are never built with stale call indices.
+    async fn api(&self) -> anyhow::Result<Arc<R>> {
+        let mut connection = self.connection.lock().await;
+        let current = utils::spec_version(&connection.client).await?;
+        if current != connection.spec_version {
+            tracing::warn!(
+                node = %self.node_name,
+                from = connection.spec_version,
+                to = current,
+                "runtime upgraded, refreshing the metadata"
+            );
+            let client =
+                self.ctx.substrate_provider::<C>(&self.node_name).await?;
+            let spec_version = utils::spec_version(&client).await?;
+            *connection = DkgConnection {
+                api: Arc::new(client.clone().to_runtime_api()),
+                client,
+                spec_version,
+            };
         }
+        Ok(connection.api.clone())
     }
 }
 
 #[async_trait::async_trait]
-impl super::ProposalSigningBackend<AnchorUpdateProposal>
+impl super::ProposalSigningBackend<VersionedAnchorUpdateProposal>
     for DkgProposalSigningBackend<DkgRuntimeApi, DkgConfig>
 {
     async fn can_handle_proposal(
         &self,
-        proposal: &AnchorUpdateProposal,
+        proposal: &VersionedAnchorUpdateProposal,
     ) -> anyhow::Result<bool> {
-        let header = proposal.header();
-        let resource_id = header.resource_id();
-        let storage_api = self.api.storage().dkg_proposals();
+        let api = self.api().await?;
+        let storage_api = api.storage().dkg_proposals();
         let src_chain_id =
             webb_proposals_typed_chain_converter(proposal.src_chain());
         let maybe_whitelisted =
@@ -55,11 +105,11 @@ impl super::ProposalSigningBackend<AnchorUpdateProposal>
         }
 
         let maybe_resource_id = storage_api
-            .resources(ResourceId(resource_id.into_bytes()), None)
+            .resources(ResourceId(proposal.encoded_resource_id()), None)
             .await?;
         if maybe_resource_id.is_none() {
             tracing::warn!(
-                resource_id = %hex::encode(&resource_id.into_bytes()),
+                resource_id = %hex::encode(&proposal.encoded_resource_id()),
                 "resource id doesn't exist!",
             );
             return Ok(false);
@@ -70,34 +120,108 @@ impl super::ProposalSigningBackend<AnchorUpdateProposal>
 
     async fn handle_proposal(
         &self,
-        proposal: &AnchorUpdateProposal,
+        proposal: &VersionedAnchorUpdateProposal,
     ) -> anyhow::Result<()> {
         self.handle_anchor_update_proposal(proposal).await
     }
 }
 
+#[async_trait::async_trait]
+impl super::ProposalSigningBackend<ResourceIdUpdateProposal>
+    for DkgProposalSigningBackend<DkgRuntimeApi, DkgConfig>
+{
+    async fn can_handle_proposal(
+        &self,
+        proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<bool> {
+        let api = self.api().await?;
+        let storage_api = api.storage().dkg_proposals();
+        // the proposal is addressed to the bridge, on the chain of the new resource.
+        let target_chain_id = webb_proposals_typed_chain_converter(
+            proposal.header().resource_id().typed_chain_id(),
+        );
+        let maybe_whitelisted = storage_api
+            .chain_nonces(target_chain_id.clone(), None)
+            .await?;
+        if maybe_whitelisted.is_none() {
+            tracing::warn!(?target_chain_id, "chain is not whitelisted");
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    async fn handle_proposal(
+        &self,
+        proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<()> {
+        let header = proposal.header();
+        let target_chain_id = webb_proposals_typed_chain_converter(
+            header.resource_id().typed_chain_id(),
+        );
+        tracing::debug!(
+            nonce = %header.nonce().to_u32(),
+            resource_id = %hex::encode(header.resource_id().into_bytes()),
+            new_resource_id = %hex::encode(proposal.new_resource_id().into_bytes()),
+            "sending resource id update proposal to DKG runtime"
+        );
+        self.submit_proposal(
+            header.nonce().to_u32(),
+            target_chain_id,
+            header.resource_id().into_bytes(),
+            proposal.to_bytes().to_vec(),
+        )
+        .await
+    }
+}
+
 impl DkgProposalSigningBackend<DkgRuntimeApi, DkgConfig> {
     async fn handle_anchor_update_proposal(
         &self,
-        proposal: &AnchorUpdateProposal,
+        proposal: &VersionedAnchorUpdateProposal,
     ) -> anyhow::Result<()> {
-        let tx_api = self.api.tx().dkg_proposals();
         let leaf_index = proposal.latest_leaf_index();
-        let resource_id = proposal.header().resource_id();
+        let resource_id = proposal.encoded_resource_id();
+        let proposal_bytes = proposal.encode();
         let src_chain_id =
             webb_proposals_typed_chain_converter(proposal.src_chain());
         tracing::debug!(
             %leaf_index,
-            resource_id = %hex::encode(&resource_id.into_bytes()),
+            resource_id = %hex::encode(resource_id),
             src_chain_id = ?src_chain_id,
-            proposal = %hex::encode(&proposal.to_bytes()),
+            encoding = ?proposal.encoding(),
+            proposal = %hex::encode(&proposal_bytes),
             "sending proposal to DKG runtime"
         );
+        self.submit_proposal(
+            leaf_index,
+            src_chain_id,
+            resource_id,
+            proposal_bytes,
+        )
+        .await
+    }
+
+    /// Submits the proposal to the DKG runtime for signing, and waits for the
+    /// extrinsic to be finalized.
+    async fn submit_proposal(
+        &self,
+        nonce: u32,
+        src_chain_id: TypedChainId,
+        resource_id: [u8; 32],
+        proposal_bytes: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        // the proposals are only submitted by the leader, the standbys see the same ones.
+        if !self.ctx.is_leader() {
+            tracing::debug!(%nonce, "Standby relayer, leaving the proposal to the leader");
+            return Ok(());
+        }
+        let api = self.api().await?;
+        let tx_api = api.tx().dkg_proposals();
         let xt = tx_api.acknowledge_proposal(
-            Nonce(leaf_index),
+            Nonce(nonce),
             src_chain_id,
-            ResourceId(resource_id.into_bytes()),
-            proposal.to_bytes().into(),
+            ResourceId(resource_id),
+            proposal_bytes,
         );
         // TODO: here we should have a substrate based tx queue in the background
         // where just send the raw xt bytes and let it handle the work for us.
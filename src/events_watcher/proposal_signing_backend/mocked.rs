@@ -1,14 +1,15 @@
 use crate::config::PrivateKey;
+use crate::context::KeyRotations;
+use crate::events_watcher::VersionedAnchorUpdateProposal;
+use crate::events_watcher::{BridgeRegistry, Misconfiguration};
 use crate::store::sled::SledQueueKey;
-use crate::store::{BridgeCommand, BridgeKey, QueueStore};
+use crate::store::{BridgeCommand, BridgeKey, BridgeRegistryStore, QueueStore};
 use std::collections::HashMap;
-use std::sync::Arc;
 use typed_builder::TypedBuilder;
 use webb::evm::ethers::core::k256::SecretKey;
 use webb::evm::ethers::prelude::*;
 use webb::evm::ethers::utils::keccak256;
-use webb_proposals::AnchorUpdateProposal;
-use webb_proposals::TypedChainId;
+use webb_proposals::{ResourceIdUpdateProposal, TypedChainId};
 
 #[derive(Debug, Clone)]
 pub struct SignatureBridgeMetadata {
@@ -26,8 +27,11 @@ where
     /// A map between chain id and its signature bridge contract address.
     #[builder(setter(into))]
     signature_bridges: HashMap<TypedChainId, SignatureBridgeMetadata>,
-    /// Something that implements the QueueStore trait.
-    store: Arc<S>,
+    /// The registry used for routing the signed proposals to the signature bridges.
+    bridge_registry: BridgeRegistry<S>,
+    /// The keys rotated by the admin, replacing the revoked governor keys.
+    #[builder(default)]
+    key_rotations: KeyRotations,
 }
 
 impl<S> MockedProposalSigningBackend<S>
@@ -42,7 +46,11 @@ where
             .get(&chain_id)
             .cloned()
             .ok_or_else(|| {
-                anyhow::anyhow!("no bridge for chain id {:?}", chain_id)
+                Misconfiguration(format!(
+                    "no bridge for chain id {:?}",
+                    chain_id
+                ))
+                .into()
             })
     }
     /// get the signer of the target chain that will be used to sign proposals.
@@ -51,22 +59,29 @@ where
         chain_id: TypedChainId,
     ) -> anyhow::Result<LocalWallet> {
         let metadata = self.bridge_metadata(chain_id)?;
-        let key = SecretKey::from_bytes(metadata.private_key.as_bytes())?;
-        let signer = LocalWallet::from(key)
-            .with_chain_id(metadata.chain_id.underlying_chain_id());
+        let chain_id = metadata.chain_id.underlying_chain_id();
+        let key = self
+            .key_rotations
+            .resolve(u64::from(chain_id), &metadata.private_key)?;
+        let key = SecretKey::from_bytes(key.as_bytes())?;
+        let signer = LocalWallet::from(key).with_chain_id(chain_id);
         Ok(signer)
     }
 }
 
 #[async_trait::async_trait]
-impl<S> super::ProposalSigningBackend<AnchorUpdateProposal>
+impl<S> super::ProposalSigningBackend<VersionedAnchorUpdateProposal>
     for MockedProposalSigningBackend<S>
 where
-    S: QueueStore<BridgeCommand, Key = SledQueueKey> + Send + Sync + 'static,
+    S: QueueStore<BridgeCommand, Key = SledQueueKey>
+        + BridgeRegistryStore
+        + Send
+        + Sync
+        + 'static,
 {
     async fn can_handle_proposal(
         &self,
-        proposal: &AnchorUpdateProposal,
+        proposal: &VersionedAnchorUpdateProposal,
     ) -> anyhow::Result<bool> {
         let dest_chain_id = proposal.header().resource_id().typed_chain_id();
         let known_bridge = self.signature_bridges.contains_key(&dest_chain_id);
@@ -75,24 +90,65 @@ where
 
     async fn handle_proposal(
         &self,
-        proposal: &AnchorUpdateProposal,
+        proposal: &VersionedAnchorUpdateProposal,
+    ) -> anyhow::Result<()> {
+        let dest_chain_id = proposal.header().resource_id().typed_chain_id();
+        tracing::debug!(?proposal, "Signing anchor update proposal");
+        self.sign_and_send(dest_chain_id, proposal.encode())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> super::ProposalSigningBackend<ResourceIdUpdateProposal>
+    for MockedProposalSigningBackend<S>
+where
+    S: QueueStore<BridgeCommand, Key = SledQueueKey>
+        + BridgeRegistryStore
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn can_handle_proposal(
+        &self,
+        proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<bool> {
+        let dest_chain_id = proposal.header().resource_id().typed_chain_id();
+        let known_bridge = self.signature_bridges.contains_key(&dest_chain_id);
+        Ok(known_bridge)
+    }
+
+    async fn handle_proposal(
+        &self,
+        proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<()> {
+        let dest_chain_id = proposal.header().resource_id().typed_chain_id();
+        tracing::debug!(?proposal, "Signing resource id update proposal");
+        self.sign_and_send(dest_chain_id, proposal.to_bytes().to_vec())
+    }
+}
+
+impl<S> MockedProposalSigningBackend<S>
+where
+    S: QueueStore<BridgeCommand, Key = SledQueueKey> + BridgeRegistryStore,
+{
+    /// Signs the proposal with the governor key of the bridge of the destination chain,
+    /// and signals that bridge to execute it.
+    fn sign_and_send(
+        &self,
+        dest_chain_id: TypedChainId,
+        proposal_bytes: Vec<u8>,
     ) -> anyhow::Result<()> {
         // the way this one works is that we get the hash of the proposal bytes,
         // the we use the hash to be signed by the signer.
         // Read more here: https://bit.ly/3rqNYTU
-        let dest_chain_id = proposal.header().resource_id().typed_chain_id();
         let bridge_metadata = self.bridge_metadata(dest_chain_id)?;
         let signer = self.signer(dest_chain_id)?;
-        let proposal_bytes = proposal.to_bytes();
         let hash = keccak256(&proposal_bytes);
         let signature = signer.sign_hash(H256::from(hash), false);
-        let bridge_key = BridgeKey::new(
-            bridge_metadata.address,
-            bridge_metadata.chain_id.underlying_chain_id().into(),
-        );
+        let bridge_key =
+            BridgeKey::new(bridge_metadata.address, bridge_metadata.chain_id);
         tracing::debug!(
             %bridge_key,
-            ?proposal,
             "Signaling Signature Bridge to execute proposal",
         );
         let signature_bytes = signature.to_vec();
@@ -106,13 +162,20 @@ where
             signature = ?hex::encode(&signature_bytes),
         );
         // now all we have to do is to send the data and the signature to the signature bridge.
-        self.store.enqueue_item(
-            SledQueueKey::from_bridge_key(bridge_key),
-            BridgeCommand::ExecuteProposalWithSignature {
-                data: proposal_bytes.to_vec(),
-                signature: signature_bytes,
-            },
-        )?;
+        let bridge = match self.bridge_registry.lookup(bridge_key) {
+            Some(bridge) => bridge,
+            None => {
+                tracing::warn!(
+                    %bridge_key,
+                    "Signature Bridge is not registered, skipping",
+                );
+                return Ok(());
+            }
+        };
+        bridge.send(BridgeCommand::ExecuteProposalWithSignature {
+            data: proposal_bytes,
+            signature: signature_bytes,
+        })?;
         Ok(())
     }
 }
@@ -1,23 +1,32 @@
 #[doc(hidden)]
+#[cfg(feature = "substrate")]
 mod dkg;
 #[doc(hidden)]
 mod mocked;
+#[doc(hidden)]
+#[cfg(feature = "substrate")]
+mod substrate_bridge;
 
 /// A module that Implements the DKG Proposal Signing Backend.
+#[cfg(feature = "substrate")]
 pub use dkg::*;
 /// A module that Implements the Mocked Proposal Signing Backend.
 pub use mocked::*;
+/// A module that Implements the Substrate Bridge Proposal Signing Backend.
+#[cfg(feature = "substrate")]
+pub use substrate_bridge::*;
 
 /// A Proposal Signing Backend is responsible for signing proposal `P` where `P` is anything really depending on the
 /// requirement of the user of this backend.
 ///
 /// For example, an Anchor Event Watcher that watches for `Deposit` events might need to sign an `AnchorUpdateProposal` and to do so, it will
-/// require a `ProposalSigningBackend<AnchorUpdateProposal>` to do so.
+/// require a `ProposalSigningBackend<VersionedAnchorUpdateProposal>` to do so.
 ///
-/// As of now, we have two implementations of this trait:
+/// As of now, we have three implementations of this trait:
 ///
 /// - `DkgSigningBackend`: This is using the `DKG` protocol to sign the proposal.
 /// - `MockedSigningBackend`: This is using the Governor's `PrivateKey` to sign the proposal directly.
+/// - `SubstrateBridgeProposalSigningBackend`: This is voting for the proposal on the bridge pallet of a Webb Protocol node.
 #[async_trait::async_trait]
 pub trait ProposalSigningBackend<P>
 where
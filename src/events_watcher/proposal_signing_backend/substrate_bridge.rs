@@ -0,0 +1,173 @@
+use webb::substrate::protocol_substrate_runtime::api::runtime_types::{
+    pallet_anchor_handler,
+    pallet_linkable_tree::types::EdgeMetadata,
+    webb_standalone_runtime::{Call, Element},
+};
+use webb::substrate::subxt::sp_core::sr25519::Pair as Sr25519Pair;
+use webb::substrate::{protocol_substrate_runtime, subxt};
+
+use crate::context::RelayerContext;
+use crate::events_watcher::VersionedAnchorUpdateProposal;
+use crate::proposals::ResourceIdUpdateProposal;
+
+type WebbProtocolConfig = subxt::DefaultConfig;
+type WebbProtocolRuntimeApi = protocol_substrate_runtime::api::RuntimeApi<
+    WebbProtocolConfig,
+    subxt::DefaultExtra<WebbProtocolConfig>,
+>;
+
+/// A ProposalSigningBackend that votes for the proposals on the bridge pallet of a
+/// Webb Protocol node.
+///
+/// The proposals are not signed, the relayer acknowledges them with its account, and
+/// once enough relayers did, the bridge pallet executes the call of the proposal, which
+/// updates the edge of the anchor on the anchor handler pallet. The lifecycle of the
+/// proposal is then followed by the `SubstrateBridgeWatcher`.
+pub struct SubstrateBridgeProposalSigningBackend {
+    ctx: RelayerContext,
+    node_name: String,
+    client: subxt::Client<WebbProtocolConfig>,
+    pair: subxt::PairSigner<
+        WebbProtocolConfig,
+        subxt::DefaultExtra<WebbProtocolConfig>,
+        Sr25519Pair,
+    >,
+}
+
+impl SubstrateBridgeProposalSigningBackend {
+    pub fn new(
+        ctx: RelayerContext,
+        node_name: String,
+        client: subxt::Client<WebbProtocolConfig>,
+        pair: subxt::PairSigner<
+            WebbProtocolConfig,
+            subxt::DefaultExtra<WebbProtocolConfig>,
+            Sr25519Pair,
+        >,
+    ) -> Self {
+        Self {
+            ctx,
+            node_name,
+            client,
+            pair,
+        }
+    }
+
+    fn api(&self) -> WebbProtocolRuntimeApi {
+        self.client.clone().to_runtime_api()
+    }
+}
+
+#[async_trait::async_trait]
+impl super::ProposalSigningBackend<VersionedAnchorUpdateProposal>
+    for SubstrateBridgeProposalSigningBackend
+{
+    async fn can_handle_proposal(
+        &self,
+        proposal: &VersionedAnchorUpdateProposal,
+    ) -> anyhow::Result<bool> {
+        let api = self.api();
+        let storage_api = api.storage().bridge();
+        let src_chain_id = proposal.src_chain().chain_id();
+        let maybe_whitelisted =
+            storage_api.chain_nonces(src_chain_id, None).await?;
+        if maybe_whitelisted.is_none() {
+            tracing::warn!(
+                node = %self.node_name,
+                src_chain_id,
+                "chain is not whitelisted on the bridge"
+            );
+            return Ok(false);
+        }
+        let resource_id = proposal.header().resource_id().into_bytes();
+        let maybe_resource_id =
+            storage_api.resources(resource_id, None).await?;
+        if maybe_resource_id.is_none() {
+            tracing::warn!(
+                node = %self.node_name,
+                resource_id = %hex::encode(&resource_id),
+                "resource id doesn't exist on the bridge!",
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    async fn handle_proposal(
+        &self,
+        proposal: &VersionedAnchorUpdateProposal,
+    ) -> anyhow::Result<()> {
+        let header = proposal.header();
+        let nonce = u64::from(header.nonce().to_u32());
+        let src_chain_id = proposal.src_chain().chain_id();
+        let resource_id = header.resource_id().into_bytes();
+        // the bridge pallet executes this call once the proposal is approved.
+        let call = Call::AnchorHandler(
+            pallet_anchor_handler::pallet::Call::execute_anchor_update_proposal {
+                r_id: resource_id,
+                anchor_metadata: EdgeMetadata {
+                    src_chain_id,
+                    root: Element(*proposal.merkle_root()),
+                    latest_leaf_index: proposal.latest_leaf_index(),
+                },
+            },
+        );
+        // the votes are only sent by the leader, the standbys see the same proposals.
+        if !self.ctx.is_leader() {
+            tracing::debug!(
+                %nonce,
+                "Standby relayer, leaving the vote to the leader"
+            );
+            return Ok(());
+        }
+        tracing::debug!(
+            node = %self.node_name,
+            %nonce,
+            src_chain_id,
+            resource_id = %hex::encode(resource_id),
+            leaf_index = proposal.latest_leaf_index(),
+            "voting for the proposal on the bridge pallet"
+        );
+        let api = self.api();
+        let xt = api.tx().bridge().acknowledge_proposal(
+            nonce,
+            src_chain_id,
+            resource_id,
+            Box::new(call),
+        );
+        let events = xt
+            .sign_and_submit_then_watch(&self.pair)
+            .await?
+            .wait_for_finalized_success()
+            .await?;
+        tracing::debug!(?events, %nonce, "vote finalized");
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl super::ProposalSigningBackend<ResourceIdUpdateProposal>
+    for SubstrateBridgeProposalSigningBackend
+{
+    async fn can_handle_proposal(
+        &self,
+        proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<bool> {
+        // the resources of the bridge pallet are set by its admin, not by proposals.
+        tracing::warn!(
+            node = %self.node_name,
+            new_resource_id = %hex::encode(proposal.new_resource_id().into_bytes()),
+            "resource id update proposals are not voted on the bridge pallet"
+        );
+        Ok(false)
+    }
+
+    async fn handle_proposal(
+        &self,
+        _proposal: &ResourceIdUpdateProposal,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!(
+            "resource id update proposals are not voted on the bridge pallet"
+        )
+    }
+}
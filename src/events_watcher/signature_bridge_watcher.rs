@@ -12,25 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
+use std::convert::{TryFrom, TryInto};
 use std::ops;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use webb::evm::contract::protocol_solidity::{
     SignatureBridgeContract, SignatureBridgeContractEvents,
 };
 use webb::evm::ethers::core::types::transaction::eip2718::TypedTransaction;
 use webb::evm::ethers::prelude::*;
-use webb::evm::ethers::providers;
 use webb::evm::ethers::types;
 use webb::evm::ethers::utils;
 
 use crate::config;
-use crate::events_watcher::{BridgeWatcher, EventWatcher};
-use crate::store::sled::{SledQueueKey, SledStore};
-use crate::store::{BridgeCommand, QueueStore};
+use crate::context::RelayerContext;
+use crate::events_watcher::{
+    AnchorUpdateProposalEncoding, BridgeWatcher, EventWatcher,
+    VersionedAnchorUpdateProposal,
+};
+use crate::metrics::{self, Labels, Metric};
+use crate::proposals;
+use crate::store::cache::RelayerStore;
+use crate::store::sled::SledQueueKey;
+use crate::store::{
+    audit, AuditEntry, AuditEvent, BridgeCommand, BridgeKey, GovernorStore,
+    ProposalNonceStore, ProposalStatus, ProposalStore, QueueKey, QueueStore,
+    SignedProposal,
+};
 
-type HttpProvider = providers::Provider<providers::Http>;
+type HttpProvider = crate::rate_limit::HttpProvider;
 
 /// A Wrapper around the `SignatureBridgeContract` contract.
 #[derive(Clone, Debug)]
@@ -52,6 +63,14 @@ impl<M: Middleware> SignatureBridgeContractWrapper<M> {
             config,
         }
     }
+
+    /// Returns whether the bridge is paused right now, as the bridge reports it.
+    pub async fn is_paused(&self) -> anyhow::Result<bool>
+    where
+        M: 'static,
+    {
+        Ok(self.contract.paused().call().await?)
+    }
 }
 
 impl<M: Middleware> ops::Deref for SignatureBridgeContractWrapper<M> {
@@ -69,12 +88,28 @@ impl<M: Middleware> super::WatchableContract
         self.config.common.deployed_at.into()
     }
 
+    fn sync_from(&self) -> config::SyncFrom {
+        self.config.common.sync_from()
+    }
+
     fn polling_interval(&self) -> Duration {
-        Duration::from_millis(self.config.events_watcher.polling_interval)
+        Duration::from_millis(self.config.events_watcher.polling_interval())
     }
 
     fn max_events_per_step(&self) -> types::U64 {
-        self.config.events_watcher.max_events_per_step.into()
+        self.config.events_watcher.max_events_per_step().into()
+    }
+
+    fn confirmations(&self) -> types::U64 {
+        self.config.events_watcher.confirmations().into()
+    }
+
+    fn polling_jitter(&self) -> Duration {
+        Duration::from_millis(self.config.events_watcher.polling_jitter)
+    }
+
+    fn circuit_breaker(&self) -> config::CircuitBreakerConfig {
+        self.config.events_watcher.circuit_breaker
     }
 
     fn print_progress_interval(&self) -> Duration {
@@ -85,8 +120,24 @@ impl<M: Middleware> super::WatchableContract
 }
 
 /// A SignatureBridge contract events & commands watcher.
-#[derive(Copy, Clone, Debug, Default)]
-pub struct SignatureBridgeContractWatcher;
+#[derive(Clone)]
+pub struct SignatureBridgeContractWatcher {
+    /// The context of the relayer, where the paused bridges are kept.
+    ctx: RelayerContext,
+}
+
+impl SignatureBridgeContractWatcher {
+    /// Creates the watcher, keeping the paused bridges in that context.
+    pub fn new(ctx: RelayerContext) -> Self {
+        Self { ctx }
+    }
+}
+
+impl std::fmt::Debug for SignatureBridgeContractWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignatureBridgeContractWatcher").finish()
+    }
+}
 
 #[async_trait::async_trait]
 impl EventWatcher for SignatureBridgeContractWatcher {
@@ -98,7 +149,7 @@ impl EventWatcher for SignatureBridgeContractWatcher {
 
     type Events = SignatureBridgeContractEvents;
 
-    type Store = SledStore;
+    type Store = RelayerStore;
 
     #[tracing::instrument(
         skip_all,
@@ -106,17 +157,75 @@ impl EventWatcher for SignatureBridgeContractWatcher {
     )]
     async fn handle_event(
         &self,
-        _store: Arc<Self::Store>,
-        _wrapper: &Self::Contract,
+        store: Arc<Self::Store>,
+        wrapper: &Self::Contract,
         e: (Self::Events, LogMeta),
     ) -> anyhow::Result<()> {
-        tracing::debug!("Got Event {:?}", e.0);
+        use SignatureBridgeContractEvents::*;
+        let (event, log) = e;
+        match event {
+            GovernanceOwnershipTransferredFilter(v) => {
+                let chain_id = wrapper.contract.get_chain_id().call().await?;
+                let key = (chain_id, wrapper.contract.address());
+                let changed = store.set_governor(
+                    key,
+                    v.new_owner,
+                    log.block_number.as_u64(),
+                )?;
+                if !changed {
+                    return Ok(());
+                }
+                tracing::info!(
+                    previous_governor = %v.previous_owner,
+                    new_governor = %v.new_owner,
+                    "The governor of the bridge changed",
+                );
+                let rejected = reject_proposals_of_other_governors(
+                    store.as_ref(),
+                    chain_id,
+                    v.new_owner,
+                )?;
+                if rejected > 0 {
+                    tracing::warn!(
+                        rejected,
+                        "Rejected the pending proposals that are not signed by the new governor",
+                    );
+                }
+            }
+            PausedFilter(_) | UnpausedFilter(_) => {
+                let paused = matches!(event, PausedFilter(_));
+                let chain_id = wrapper.contract.get_chain_id().call().await?;
+                let key = BridgeKey::new(
+                    wrapper.contract.address(),
+                    proposals::evm_typed_chain_id(chain_id)?,
+                );
+                if !self.ctx.set_bridge_paused(key, paused) {
+                    return Ok(());
+                }
+                if paused {
+                    tracing::warn!(
+                        %log.block_number,
+                        "The bridge got paused, its commands are held until it gets unpaused",
+                    );
+                } else {
+                    tracing::info!(
+                        %log.block_number,
+                        "The bridge got unpaused, resuming its commands",
+                    );
+                }
+            }
+            e => tracing::debug!("Got Event {:?}", e),
+        }
         Ok(())
     }
 }
 
 #[async_trait::async_trait]
 impl BridgeWatcher for SignatureBridgeContractWatcher {
+    fn is_paused(&self, key: BridgeKey) -> bool {
+        self.ctx.is_bridge_paused(key)
+    }
+
     #[tracing::instrument(skip_all)]
     async fn handle_cmd(
         &self,
@@ -173,15 +282,96 @@ where
             return Ok(());
         }
 
+        // decode the proposal header, and make sure that this proposal is
+        // targeting this bridge's chain and a resource it knows about.
+        let header = match proposals::decode_header(&data) {
+            Ok(header) => header,
+            Err(e) => {
+                tracing::warn!(
+                    data = ?hex::encode(&data),
+                    "Skipping execution of this proposal since its header is invalid: {}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+        let (resource_id, origin_chain) = proposal_target(&header, &data);
+        let expected_chain_id = proposals::evm_typed_chain_id(chain_id)?;
+        if resource_id.typed_chain_id() != expected_chain_id {
+            tracing::warn!(
+                data_hash = ?hex::encode(data_hash),
+                target = ?resource_id.typed_chain_id(),
+                expected = ?expected_chain_id,
+                "Skipping execution of this proposal since it is targeting another chain",
+            );
+            return Ok(());
+        }
+        // refuse the proposals that got replayed, or that are older than
+        // what we already executed for that resource.
+        let nonce = header.nonce().to_u32();
+        let last_nonce =
+            store.get_last_executed_nonce(resource_id, origin_chain)?;
+        if matches!(last_nonce, Some(last) if nonce <= last) {
+            tracing::warn!(
+                data_hash = ?hex::encode(data_hash),
+                nonce,
+                last_nonce = ?last_nonce,
+                "Skipping execution of this proposal since a newer one is already executed",
+            );
+            return Ok(());
+        }
+        // the resource registrations are executed by the bridge itself, the other
+        // proposals by the handler of their resource.
+        let resource_update = match decode_resource_id_update(&header, &data) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!(
+                    data_hash = ?hex::encode(data_hash),
+                    "Skipping execution of this proposal since it is an invalid resource id update: {}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+        let handler = match &resource_update {
+            Some(update) => {
+                let handler = types::Address::from(update.handler_address());
+                let registered = contract
+                    .resource_id_to_handler_address(
+                        update.new_resource_id().into_bytes(),
+                    )
+                    .call()
+                    .await?;
+                if registered == handler {
+                    tracing::debug!(
+                        data_hash = ?hex::encode(data_hash),
+                        "Skipping execution of this proposal since its resource is already registered",
+                    );
+                    return Ok(());
+                }
+                handler
+            }
+            None => {
+                contract
+                    .resource_id_to_handler_address(
+                        header.resource_id().into_bytes(),
+                    )
+                    .call()
+                    .await?
+            }
+        };
+        if handler.is_zero() {
+            tracing::warn!(
+                data_hash = ?hex::encode(data_hash),
+                resource_id = ?hex::encode(header.resource_id().into_bytes()),
+                "Skipping execution of this proposal since its resource is not registered on the bridge",
+            );
+            return Ok(());
+        }
+
         // now we need to check if the signature is valid.
-        let (data_clone, signature_clone) = (data.clone(), signature.clone());
-        let is_signature_valid = contract
-            .is_signature_from_governor(
-                data_clone.into(),
-                signature_clone.into(),
-            )
-            .call()
-            .await?;
+        let governor = governor_of(store.as_ref(), contract, chain_id).await?;
+        let is_signature_valid = is_signed_by(&data, &signature, governor);
 
         let data_hex = hex::encode(&data);
         let signature_hex = hex::encode(&signature);
@@ -203,11 +393,46 @@ where
             data = ?data_hex,
             signature = ?signature_hex,
             data_hash = ?hex::encode(data_hash),
+            nonce = %nonce,
+            handler = %handler,
         );
         // I guess now we are ready to enqueue the transaction.
-        let call = contract
-            .execute_proposal_with_signature(data.into(), signature.into());
+        let call = match resource_update {
+            Some(update) => contract.admin_set_resource_with_signature(
+                header.resource_id().into_bytes(),
+                header.function_signature().to_bytes(),
+                nonce,
+                update.new_resource_id().into_bytes(),
+                handler,
+                types::Address::from(update.execution_address()),
+                signature.clone().into(),
+            ),
+            None => contract.execute_proposal_with_signature(
+                data.clone().into(),
+                signature.clone().into(),
+            ),
+        };
         QueueStore::<TypedTransaction>::enqueue_item(&store, tx_key, call.tx)?;
+        audit(
+            store.as_ref(),
+            AuditEntry::new(AuditEvent::TxQueued {
+                chain_id,
+                item_key: tx_key.item_key().map(hex::encode),
+            }),
+        );
+        // keep track of the proposal, so it could be collected if it never gets executed.
+        let enqueued_at =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        store.insert_proposal(SignedProposal {
+            chain_id,
+            data,
+            signature,
+            enqueued_at,
+        })?;
+        store.set_proposal_status(
+            &data_hash,
+            ProposalStatus::Queued { at: enqueued_at },
+        )?;
         tracing::debug!(
             data_hash = ?hex::encode(data_hash),
             "Enqueued the proposal for execution in the tx queue",
@@ -216,6 +441,335 @@ where
     }
 }
 
+/// Records the outcome of a transaction sent by the tx queue, if it executes one of
+/// the signed proposals of that chain: the proposal is removed, and marked as executed
+/// (recording its nonce) if the transaction succeeded, or as failed otherwise.
+///
+/// The proposal is looked up by the resource id and the nonce in the `calldata`,
+/// then by its signature, since the same nonce could be signed for several origin
+/// chains. The transactions of the failed proposals are not kept in the queue, so
+/// they could be signed and queued again.
+pub fn record_proposal_execution<S>(
+    store: &S,
+    chain_id: types::U256,
+    calldata: &[u8],
+    succeeded: bool,
+) -> anyhow::Result<()>
+where
+    S: ProposalStore<Proposal = SignedProposal>
+        + ProposalNonceStore
+        + QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let (resource_id, nonce, signature) = match decode_execution_call(calldata)
+    {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    for proposal in store.proposals_by_nonce(resource_id, nonce)? {
+        if proposal.chain_id != chain_id || proposal.signature != signature {
+            continue;
+        }
+        let data_hash = proposal.data_hash();
+        let status = if succeeded {
+            let header = proposals::decode_header(&proposal.data)?;
+            let (resource_id, origin_chain) =
+                proposal_target(&header, &proposal.data);
+            store.set_last_executed_nonce(resource_id, origin_chain, nonce)?;
+            ProposalStatus::Executed { at: now }
+        } else {
+            cancel_proposal_tx(store, &proposal)?;
+            tracing::warn!(
+                data_hash = ?hex::encode(data_hash),
+                nonce,
+                "The transaction executing the proposal reverted",
+            );
+            ProposalStatus::Failed { at: now }
+        };
+        store.set_proposal_status(&data_hash, status)?;
+        store.remove_proposal(&data_hash)?;
+    }
+    Ok(())
+}
+
+/// Removes the signed proposals that did not get executed within `max_age`,
+/// and cancels their queued transactions, counting them in [`Metric::ProposalsExpired`].
+///
+/// The statuses of the executed, failed, expired or rejected proposals are kept
+/// for `max_age` too.
+///
+/// Returns the number of expired proposals.
+pub fn remove_stale_proposals<S>(
+    store: &S,
+    max_age: Duration,
+) -> anyhow::Result<usize>
+where
+    S: ProposalStore<Proposal = SignedProposal>
+        + ProposalNonceStore
+        + QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut expired = 0;
+    for proposal in store.proposals()? {
+        let age = now.saturating_sub(proposal.enqueued_at);
+        if age < max_age.as_secs() {
+            continue;
+        }
+        let data_hash = proposal.data_hash();
+        cancel_proposal_tx(store, &proposal)?;
+        store.set_proposal_status(
+            &data_hash,
+            ProposalStatus::Expired { at: now },
+        )?;
+        store.remove_proposal(&data_hash)?;
+        expired += 1;
+        metrics::inc(
+            Metric::ProposalsExpired,
+            Labels::new(proposal.chain_id, "", crate::probe::Kind::ProposalsGc),
+        );
+        tracing::warn!(
+            data_hash = ?hex::encode(data_hash),
+            chain_id = %proposal.chain_id,
+            age,
+            "Removed stale proposal and cancelled its queued transaction",
+        );
+    }
+    for (data_hash, status) in store.proposal_statuses()? {
+        let finished = !matches!(status, ProposalStatus::Queued { .. });
+        if finished && now.saturating_sub(status.at()) >= max_age.as_secs() {
+            store.remove_proposal_status(&data_hash)?;
+        }
+    }
+    Ok(expired)
+}
+
+/// Rejects the signed proposals of that chain that are not signed by its new
+/// governor, and cancels their queued transactions.
+///
+/// Returns the number of rejected proposals.
+pub fn reject_proposals_of_other_governors<S>(
+    store: &S,
+    chain_id: types::U256,
+    governor: types::Address,
+) -> anyhow::Result<usize>
+where
+    S: ProposalStore<Proposal = SignedProposal>
+        + ProposalNonceStore
+        + QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut rejected = 0;
+    for proposal in store.proposals()? {
+        if proposal.chain_id != chain_id
+            || is_signed_by(&proposal.data, &proposal.signature, governor)
+        {
+            continue;
+        }
+        let data_hash = proposal.data_hash();
+        cancel_proposal_tx(store, &proposal)?;
+        store.set_proposal_status(
+            &data_hash,
+            ProposalStatus::Rejected { at: now },
+        )?;
+        store.remove_proposal(&data_hash)?;
+        rejected += 1;
+        tracing::debug!(
+            data_hash = ?hex::encode(data_hash),
+            %governor,
+            "Rejected proposal and cancelled its queued transaction",
+        );
+    }
+    Ok(rejected)
+}
+
+/// Removes the signed proposals addressed to that resource, cancels their queued
+/// transactions, and forgets their statuses, as if they never got signed.
+///
+/// Returns the number of removed proposals.
+pub fn remove_proposals_of_resource<S>(
+    store: &S,
+    resource_id: proposals::ResourceId,
+) -> anyhow::Result<usize>
+where
+    S: ProposalStore<Proposal = SignedProposal>
+        + ProposalNonceStore
+        + QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let mut removed = 0;
+    for proposal in store.proposals()? {
+        match proposals::decode_header(&proposal.data) {
+            Ok(header) if header.resource_id() == resource_id => {}
+            _ => continue,
+        }
+        let data_hash = proposal.data_hash();
+        cancel_proposal_tx(store, &proposal)?;
+        store.remove_proposal(&data_hash)?;
+        store.remove_proposal_status(&data_hash)?;
+        removed += 1;
+        tracing::debug!(
+            data_hash = ?hex::encode(data_hash),
+            "Removed proposal and cancelled its queued transaction",
+        );
+    }
+    Ok(removed)
+}
+
+/// Cancels the queued transaction of the proposal, and forgets its key, so the
+/// proposal could be queued again.
+fn cancel_proposal_tx<S>(
+    store: &S,
+    proposal: &SignedProposal,
+) -> anyhow::Result<()>
+where
+    S: QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let tx_key = SledQueueKey::from_evm_with_custom_key(
+        proposal.chain_id,
+        make_execute_proposal_key(proposal.data_hash()),
+    );
+    if store.has_item(tx_key)? {
+        store.remove_item(tx_key)?;
+    }
+    Ok(())
+}
+
+/// Returns the governor of the bridge, as it is stored, or as the bridge reports it
+/// the first time, which then gets stored.
+async fn governor_of<S, M>(
+    store: &S,
+    contract: &SignatureBridgeContract<M>,
+    chain_id: types::U256,
+) -> anyhow::Result<types::Address>
+where
+    S: GovernorStore,
+    M: Middleware + 'static,
+{
+    let key = (chain_id, contract.address());
+    if let Some(governor) = store.get_governor(key)? {
+        return Ok(governor);
+    }
+    // the block number is read first, so a transfer that happens in between
+    // is seen as the newer one.
+    let block_number = contract.client().get_block_number().await?;
+    let governor = contract.governor().call().await?;
+    store.set_governor(key, governor, block_number.as_u64())?;
+    Ok(governor)
+}
+
+/// Whether the signature over the proposal data is from that governor, the same way
+/// the bridge verifies it: by recovering the signer of the keccak256 hash of the data.
+fn is_signed_by(
+    data: &[u8],
+    signature: &[u8],
+    governor: types::Address,
+) -> bool {
+    let hash = types::H256::from(utils::keccak256(data));
+    types::Signature::try_from(signature)
+        .and_then(|signature| signature.recover(hash))
+        .ok()
+        == Some(governor)
+}
+
+/// Returns the resource id targeted by the proposal, using typed chain ids whatever
+/// the encoding of the proposal is, along with the chain where it originated from.
+///
+/// Only the anchor update proposals carry their origin chain, other proposals
+/// are tracked per resource id only, using [`proposals::TypedChainId::None`].
+fn proposal_target(
+    header: &proposals::ProposalHeader,
+    data: &[u8],
+) -> (proposals::ResourceId, proposals::TypedChainId) {
+    let encodings = [
+        AnchorUpdateProposalEncoding::V1,
+        AnchorUpdateProposalEncoding::Legacy,
+    ];
+    encodings
+        .iter()
+        .find_map(|e| VersionedAnchorUpdateProposal::decode(data, *e).ok())
+        .map(|p| (p.header().resource_id(), p.src_chain()))
+        .unwrap_or((header.resource_id(), proposals::TypedChainId::None))
+}
+
+/// Decodes the resource id update proposal in `data`, if its header calls
+/// `adminSetResourceWithSignature` on the bridge.
+///
+/// Returns an error if the data does not have the length of a resource id update proposal.
+fn decode_resource_id_update(
+    header: &proposals::ProposalHeader,
+    data: &[u8],
+) -> anyhow::Result<Option<proposals::ResourceIdUpdateProposal>> {
+    const LENGTH: usize = proposals::ResourceIdUpdateProposal::LENGTH;
+    let function_signature = header.function_signature().to_bytes();
+    if function_signature != proposals::SET_RESOURCE_FUNCTION_SIGNATURE {
+        return Ok(None);
+    }
+    let bytes: [u8; LENGTH] = data.try_into().map_err(|_| {
+        anyhow::anyhow!("got {} bytes, expected {}", data.len(), LENGTH)
+    })?;
+    Ok(Some(proposals::ResourceIdUpdateProposal::from(bytes)))
+}
+
+/// The signature of the `executeProposalWithSignature` function of the bridge.
+const EXECUTE_PROPOSAL_SIGNATURE: &str =
+    "executeProposalWithSignature(bytes,bytes)";
+/// The signature of the `adminSetResourceWithSignature` function of the bridge.
+const SET_RESOURCE_SIGNATURE: &str = "adminSetResourceWithSignature(bytes32,bytes4,uint32,bytes32,address,address,bytes)";
+
+/// Decodes the resource id, the nonce and the signature of the proposal executed by
+/// the `calldata` of a transaction sent to the bridge.
+///
+/// Returns `None` if the calldata does not execute a proposal.
+fn decode_execution_call(
+    calldata: &[u8],
+) -> Option<(proposals::ResourceId, u32, Vec<u8>)> {
+    use webb::evm::ethers::abi::{self, ParamType, Token};
+    if calldata.len() < 4 {
+        return None;
+    }
+    let (selector, args) = calldata.split_at(4);
+    if selector == utils::id(EXECUTE_PROPOSAL_SIGNATURE) {
+        let tokens =
+            abi::decode(&[ParamType::Bytes, ParamType::Bytes], args).ok()?;
+        match tokens.as_slice() {
+            [Token::Bytes(data), Token::Bytes(signature)] => {
+                let header = proposals::decode_header(data).ok()?;
+                Some((
+                    header.resource_id(),
+                    header.nonce().to_u32(),
+                    signature.clone(),
+                ))
+            }
+            _ => None,
+        }
+    } else if selector == utils::id(SET_RESOURCE_SIGNATURE) {
+        let params = [
+            ParamType::FixedBytes(32),
+            ParamType::FixedBytes(4),
+            ParamType::Uint(32),
+            ParamType::FixedBytes(32),
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Bytes,
+        ];
+        let tokens = abi::decode(&params, args).ok()?;
+        match tokens.as_slice() {
+            [Token::FixedBytes(resource_id), _, Token::Uint(nonce), _, _, _, Token::Bytes(signature)] =>
+            {
+                let resource_id: [u8; 32] =
+                    resource_id.as_slice().try_into().ok()?;
+                Some((
+                    proposals::ResourceId::from(resource_id),
+                    u32::try_from(*nonce).ok()?,
+                    signature.clone(),
+                ))
+            }
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
 fn make_execute_proposal_key(data_hash: [u8; 32]) -> [u8; 64] {
     let mut result = [0u8; 64];
     let prefix = b"execute_proposal_with_signature_";
@@ -223,3 +777,354 @@ fn make_execute_proposal_key(data_hash: [u8; 32]) -> [u8; 64] {
     result[32..64].copy_from_slice(&data_hash);
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sled::SledStore;
+
+    #[test]
+    fn resource_id_updates_are_told_apart() {
+        let proposal = proposals::resource_id_update_proposal(
+            types::Address::random(),
+            5,
+            1,
+            types::Address::random(),
+            types::Address::random(),
+        );
+        let data = proposal.to_bytes();
+        let header = proposals::decode_header(&data).unwrap();
+        let decoded = decode_resource_id_update(&header, &data).unwrap();
+        assert_eq!(decoded, Some(proposal));
+        assert!(decode_resource_id_update(&header, &data[..80]).is_err());
+        let other = proposals::ProposalHeader::new(
+            header.resource_id(),
+            proposals::ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
+            header.nonce(),
+        );
+        assert_eq!(decode_resource_id_update(&other, &data).unwrap(), None);
+    }
+
+    #[test]
+    fn governor_rotation_rejects_proposals_of_the_old_governor() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = SledStore::open(tmp.path()).unwrap();
+        let chain_id = types::U256::from(5);
+        let bridge = (chain_id, types::Address::random());
+        let old_governor: LocalWallet =
+            "0000000000000000000000000000000000000000000000000000000000000001"
+                .parse()
+                .unwrap();
+        let new_governor: LocalWallet =
+            "0000000000000000000000000000000000000000000000000000000000000002"
+                .parse()
+                .unwrap();
+        assert!(store
+            .set_governor(bridge, old_governor.address(), 10)
+            .unwrap());
+        assert!(store
+            .set_governor(bridge, new_governor.address(), 20)
+            .unwrap());
+        // replaying an older transfer does not roll the governor back.
+        assert!(!store
+            .set_governor(bridge, old_governor.address(), 10)
+            .unwrap());
+        assert_eq!(
+            store.get_governor(bridge).unwrap(),
+            Some(new_governor.address())
+        );
+        let make_proposal = |data: u8, signer: &LocalWallet| {
+            let data = vec![data; 64];
+            let hash = types::H256::from(utils::keccak256(&data));
+            let proposal = SignedProposal {
+                chain_id,
+                data,
+                signature: signer.sign_hash(hash, false).to_vec(),
+                enqueued_at: 0,
+            };
+            let tx_key = SledQueueKey::from_evm_with_custom_key(
+                chain_id,
+                make_execute_proposal_key(proposal.data_hash()),
+            );
+            QueueStore::<TypedTransaction>::enqueue_item(
+                &store,
+                tx_key,
+                TypedTransaction::default(),
+            )
+            .unwrap();
+            store.insert_proposal(proposal.clone()).unwrap();
+            proposal
+        };
+        let outdated = make_proposal(1, &old_governor);
+        let valid = make_proposal(2, &new_governor);
+
+        let rejected = reject_proposals_of_other_governors(
+            &store,
+            chain_id,
+            new_governor.address(),
+        )
+        .unwrap();
+        assert_eq!(rejected, 1);
+        assert_eq!(store.proposals().unwrap(), vec![valid]);
+        assert!(matches!(
+            store.proposal_status(&outdated.data_hash()).unwrap(),
+            Some(ProposalStatus::Rejected { .. })
+        ));
+    }
+
+    #[test]
+    fn removes_the_proposals_of_a_resource() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = SledStore::open(tmp.path()).unwrap();
+        let chain_id = types::U256::from(5);
+        let anchor = proposals::evm_resource_id(types::Address::random(), 5);
+        let other = proposals::evm_resource_id(types::Address::random(), 5);
+        let make_proposal = |resource_id: proposals::ResourceId| {
+            let header = proposals::ProposalHeader::new(
+                resource_id,
+                proposals::ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
+                1.into(),
+            );
+            let data = webb_proposals::AnchorUpdateProposal::new(
+                header,
+                proposals::TypedChainId::Evm(4),
+                0,
+                [0u8; 32],
+            )
+            .to_bytes()
+            .to_vec();
+            let proposal = SignedProposal {
+                chain_id,
+                data,
+                signature: vec![],
+                enqueued_at: 0,
+            };
+            let tx_key = SledQueueKey::from_evm_with_custom_key(
+                chain_id,
+                make_execute_proposal_key(proposal.data_hash()),
+            );
+            QueueStore::<TypedTransaction>::enqueue_item(
+                &store,
+                tx_key,
+                TypedTransaction::default(),
+            )
+            .unwrap();
+            store.insert_proposal(proposal.clone()).unwrap();
+            store
+                .set_proposal_status(
+                    &proposal.data_hash(),
+                    ProposalStatus::Queued { at: 0 },
+                )
+                .unwrap();
+            proposal
+        };
+        let removed = make_proposal(anchor);
+        let kept = make_proposal(other);
+
+        assert_eq!(remove_proposals_of_resource(&store, anchor).unwrap(), 1);
+        assert_eq!(store.proposals().unwrap(), vec![kept]);
+        assert_eq!(store.proposal_status(&removed.data_hash()).unwrap(), None);
+        let tx_key = SledQueueKey::from_evm_with_custom_key(
+            chain_id,
+            make_execute_proposal_key(removed.data_hash()),
+        );
+        assert!(
+            !QueueStore::<TypedTransaction>::has_item(&store, tx_key).unwrap()
+        );
+    }
+
+    #[test]
+    fn removes_stale_proposals() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = SledStore::open(tmp.path()).unwrap();
+        let chain_id = types::U256::from(5);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let make_proposal = |data: u8, enqueued_at: u64| {
+            let proposal = SignedProposal {
+                chain_id,
+                data: vec![data; 64],
+                signature: vec![0; 65],
+                enqueued_at,
+            };
+            let tx_key = SledQueueKey::from_evm_with_custom_key(
+                chain_id,
+                make_execute_proposal_key(proposal.data_hash()),
+            );
+            QueueStore::<TypedTransaction>::enqueue_item(
+                &store,
+                tx_key,
+                TypedTransaction::default(),
+            )
+            .unwrap();
+            store.insert_proposal(proposal.clone()).unwrap();
+            proposal
+        };
+        let stale = make_proposal(1, now - 100);
+        let fresh = make_proposal(2, now);
+        let executed = make_proposal(3, now - 100);
+        record_proposal_execution(
+            &store,
+            chain_id,
+            &execution_calldata(&executed),
+            true,
+        )
+        .unwrap();
+        let forgotten = [9u8; 32];
+        store
+            .set_proposal_status(
+                &forgotten,
+                ProposalStatus::Executed { at: now - 100 },
+            )
+            .unwrap();
+
+        let expired =
+            remove_stale_proposals(&store, Duration::from_secs(50)).unwrap();
+        assert_eq!(expired, 1);
+        assert!(
+            metrics::render().contains("webb_relayer_proposals_expired_total")
+        );
+        assert_eq!(store.proposals().unwrap(), vec![fresh]);
+        let status = |p: &SignedProposal| {
+            store.proposal_status(&p.data_hash()).unwrap().unwrap()
+        };
+        assert!(matches!(status(&stale), ProposalStatus::Expired { .. }));
+        assert!(matches!(status(&executed), ProposalStatus::Executed { .. }));
+        assert_eq!(store.proposal_status(&forgotten).unwrap(), None);
+        let stale_tx_key = SledQueueKey::from_evm_with_custom_key(
+            chain_id,
+            make_execute_proposal_key(stale.data_hash()),
+        );
+        assert!(!QueueStore::<TypedTransaction>::has_item(
+            &store,
+            stale_tx_key
+        )
+        .unwrap());
+    }
+
+    fn execution_calldata(proposal: &SignedProposal) -> Vec<u8> {
+        use webb::evm::ethers::abi::{self, Token};
+        let mut calldata = utils::id(EXECUTE_PROPOSAL_SIGNATURE).to_vec();
+        calldata.extend(abi::encode(&[
+            Token::Bytes(proposal.data.clone()),
+            Token::Bytes(proposal.signature.clone()),
+        ]));
+        calldata
+    }
+
+    #[test]
+    fn proposals_are_executed_once_their_tx_succeeded() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = SledStore::open(tmp.path()).unwrap();
+        let chain_id = types::U256::from(5);
+        let resource_id =
+            proposals::evm_resource_id(types::Address::random(), 5);
+        let make_proposal = |src_chain_id: u32, signature: u8| {
+            let header = proposals::ProposalHeader::new(
+                resource_id,
+                proposals::ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
+                7.into(),
+            );
+            let data = webb_proposals::AnchorUpdateProposal::new(
+                header,
+                proposals::TypedChainId::Evm(src_chain_id),
+                7,
+                [0u8; 32],
+            )
+            .to_bytes()
+            .to_vec();
+            let proposal = SignedProposal {
+                chain_id,
+                data,
+                signature: vec![signature; 65],
+                enqueued_at: 0,
+            };
+            let tx_key = SledQueueKey::from_evm_with_custom_key(
+                chain_id,
+                make_execute_proposal_key(proposal.data_hash()),
+            );
+            QueueStore::<TypedTransaction>::enqueue_item(
+                &store,
+                tx_key,
+                TypedTransaction::default(),
+            )
+            .unwrap();
+            store.insert_proposal(proposal.clone()).unwrap();
+            proposal
+        };
+        // the same nonce, from two origin chains.
+        let executed = make_proposal(4, 1);
+        let failed = make_proposal(3, 2);
+        assert_eq!(store.proposals_by_nonce(resource_id, 7).unwrap().len(), 2);
+        // leaving the queue is not enough.
+        let _: Option<TypedTransaction> = store
+            .dequeue_item(SledQueueKey::from_evm_chain_id(chain_id))
+            .unwrap();
+        assert_eq!(store.proposals().unwrap().len(), 2);
+
+        record_proposal_execution(
+            &store,
+            chain_id,
+            &execution_calldata(&executed),
+            true,
+        )
+        .unwrap();
+        record_proposal_execution(
+            &store,
+            chain_id,
+            &execution_calldata(&failed),
+            false,
+        )
+        .unwrap();
+        assert!(store.proposals().unwrap().is_empty());
+        assert!(store.proposals_by_nonce(resource_id, 7).unwrap().is_empty());
+        let status = |p: &SignedProposal| {
+            store.proposal_status(&p.data_hash()).unwrap().unwrap()
+        };
+        assert!(matches!(status(&executed), ProposalStatus::Executed { .. }));
+        assert!(matches!(status(&failed), ProposalStatus::Failed { .. }));
+        let nonce = |src_chain_id| {
+            store
+                .get_last_executed_nonce(
+                    resource_id,
+                    proposals::TypedChainId::Evm(src_chain_id),
+                )
+                .unwrap()
+        };
+        assert_eq!(nonce(4), Some(7));
+        assert_eq!(nonce(3), None);
+        // the failed proposal could be queued again.
+        let tx_key = SledQueueKey::from_evm_with_custom_key(
+            chain_id,
+            make_execute_proposal_key(failed.data_hash()),
+        );
+        assert!(
+            !QueueStore::<TypedTransaction>::has_item(&store, tx_key).unwrap()
+        );
+    }
+
+    #[test]
+    fn resource_id_update_calls_are_decoded() {
+        use webb::evm::ethers::abi::{self, Token};
+        let resource_id = [3u8; 32];
+        let mut calldata = utils::id(SET_RESOURCE_SIGNATURE).to_vec();
+        calldata.extend(abi::encode(&[
+            Token::FixedBytes(resource_id.to_vec()),
+            Token::FixedBytes(vec![0; 4]),
+            Token::Uint(9.into()),
+            Token::FixedBytes(vec![4; 32]),
+            Token::Address(types::Address::random()),
+            Token::Address(types::Address::random()),
+            Token::Bytes(vec![5; 65]),
+        ]));
+        let (decoded, nonce, signature) =
+            decode_execution_call(&calldata).unwrap();
+        assert_eq!(decoded, proposals::ResourceId::from(resource_id));
+        assert_eq!(nonce, 9);
+        assert_eq!(signature, vec![5; 65]);
+        assert!(decode_execution_call(&calldata[..40]).is_none());
+        assert!(decode_execution_call(&[1, 2, 3, 4]).is_none());
+    }
+}
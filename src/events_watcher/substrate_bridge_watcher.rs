@@ -0,0 +1,257 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::Arc;
+
+use ethereum_types::U256;
+use webb::substrate::protocol_substrate_runtime::api::bridge;
+use webb::substrate::{protocol_substrate_runtime, subxt};
+
+use crate::store::cache::RelayerStore;
+use crate::store::{
+    pallet_tree_ids, SubstrateProposal, SubstrateProposalStatus,
+    SubstrateProposalStore,
+};
+use crate::utils::DecodeEvent;
+
+use super::{BlockNumberOf, SubstrateEventWatcher};
+
+/// The events of the Substrate bridge pallet about the lifecycle of a proposal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeProposalEvent {
+    VoteFor(bridge::events::VoteFor),
+    VoteAgainst(bridge::events::VoteAgainst),
+    ProposalApproved(bridge::events::ProposalApproved),
+    ProposalRejected(bridge::events::ProposalRejected),
+    ProposalSucceeded(bridge::events::ProposalSucceeded),
+    ProposalFailed(bridge::events::ProposalFailed),
+}
+
+impl BridgeProposalEvent {
+    /// The source chain id and the deposit nonce of the proposal.
+    fn key(&self) -> (u64, u64) {
+        match self {
+            Self::VoteFor(e) => (e.chain_id, e.deposit_nonce),
+            Self::VoteAgainst(e) => (e.chain_id, e.deposit_nonce),
+            Self::ProposalApproved(e) => (e.chain_id, e.deposit_nonce),
+            Self::ProposalRejected(e) => (e.chain_id, e.deposit_nonce),
+            Self::ProposalSucceeded(e) => (e.chain_id, e.deposit_nonce),
+            Self::ProposalFailed(e) => (e.chain_id, e.deposit_nonce),
+        }
+    }
+}
+
+impl DecodeEvent for BridgeProposalEvent {
+    fn decode_event(raw: &subxt::RawEvent) -> Option<Self> {
+        use bridge::events::*;
+        VoteFor::decode_event(raw)
+            .map(Self::VoteFor)
+            .or_else(|| VoteAgainst::decode_event(raw).map(Self::VoteAgainst))
+            .or_else(|| {
+                ProposalApproved::decode_event(raw).map(Self::ProposalApproved)
+            })
+            .or_else(|| {
+                ProposalRejected::decode_event(raw).map(Self::ProposalRejected)
+            })
+            .or_else(|| {
+                ProposalSucceeded::decode_event(raw)
+                    .map(Self::ProposalSucceeded)
+            })
+            .or_else(|| {
+                ProposalFailed::decode_event(raw).map(Self::ProposalFailed)
+            })
+    }
+}
+
+/// Applies the event to the tracked proposal, starting to track it on its first event.
+fn apply_event(
+    proposal: Option<SubstrateProposal>,
+    event: &BridgeProposalEvent,
+    block_number: u64,
+) -> SubstrateProposal {
+    use BridgeProposalEvent::*;
+    let (src_chain_id, deposit_nonce) = event.key();
+    let mut proposal = proposal.unwrap_or(SubstrateProposal {
+        src_chain_id,
+        deposit_nonce,
+        status: SubstrateProposalStatus::Voting,
+        votes_for: 0,
+        votes_against: 0,
+        updated_at: block_number,
+    });
+    proposal.updated_at = block_number;
+    match event {
+        VoteFor(_) => proposal.votes_for += 1,
+        VoteAgainst(_) => proposal.votes_against += 1,
+        ProposalApproved(_) => {
+            proposal.status = SubstrateProposalStatus::Approved
+        }
+        ProposalRejected(_) => {
+            proposal.status = SubstrateProposalStatus::Rejected
+        }
+        ProposalSucceeded(_) => {
+            proposal.status = SubstrateProposalStatus::Succeeded
+        }
+        ProposalFailed(_) => proposal.status = SubstrateProposalStatus::Failed,
+    }
+    proposal
+}
+
+/// Whether the proposal reached a final status, no more events are coming for it.
+fn is_final(status: SubstrateProposalStatus) -> bool {
+    matches!(
+        status,
+        SubstrateProposalStatus::Succeeded
+            | SubstrateProposalStatus::Rejected
+            | SubstrateProposalStatus::Failed
+    )
+}
+
+/// A watcher for the proposals of the Substrate bridge pallet.
+///
+/// It follows the lifecycle events of the proposals, and keeps track of the ones that
+/// are still being voted on or executed, like the Signature Bridge watcher does for the
+/// EVM chains. The votes themselves are sent by the `SubstrateBridge` proposal signing
+/// backend.
+#[derive(Clone, Debug)]
+pub struct SubstrateBridgeWatcher {
+    chain_id: U256,
+}
+
+impl SubstrateBridgeWatcher {
+    /// Creates the watcher of the bridge pallet of the chain with that chain id.
+    pub fn new(chain_id: U256) -> Self {
+        Self { chain_id }
+    }
+}
+
+#[async_trait::async_trait]
+impl SubstrateEventWatcher for SubstrateBridgeWatcher {
+    const TAG: &'static str = "Substrate Bridge Watcher";
+
+    type RuntimeConfig = subxt::DefaultConfig;
+
+    type Api = protocol_substrate_runtime::api::RuntimeApi<
+        Self::RuntimeConfig,
+        subxt::DefaultExtra<Self::RuntimeConfig>,
+    >;
+
+    type Event = BridgeProposalEvent;
+
+    type Store = RelayerStore;
+
+    fn tree_id(&self) -> u32 {
+        pallet_tree_ids::BRIDGE
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn handle_event(
+        &self,
+        store: Arc<Self::Store>,
+        _api: Arc<Self::Api>,
+        (event, block_number): (Self::Event, BlockNumberOf<Self>),
+    ) -> anyhow::Result<()> {
+        let (src_chain_id, deposit_nonce) = event.key();
+        tracing::debug!(
+            src_chain_id,
+            deposit_nonce,
+            %block_number,
+            ?event,
+            "Bridge proposal event",
+        );
+        let proposal = store.get_substrate_proposal(
+            self.chain_id,
+            src_chain_id,
+            deposit_nonce,
+        )?;
+        let proposal = apply_event(proposal, &event, block_number.into());
+        if is_final(proposal.status) {
+            tracing::debug!(
+                src_chain_id,
+                deposit_nonce,
+                status = ?proposal.status,
+                votes_for = proposal.votes_for,
+                votes_against = proposal.votes_against,
+                "Proposal is final, no longer tracked"
+            );
+            store.remove_substrate_proposal(
+                self.chain_id,
+                src_chain_id,
+                deposit_nonce,
+            )?;
+        } else {
+            store.insert_substrate_proposal(self.chain_id, proposal)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bridge::events::{
+        ProposalApproved, ProposalRejected, ProposalSucceeded, VoteAgainst,
+        VoteFor,
+    };
+    use webb::substrate::subxt::sp_core::crypto::AccountId32;
+
+    #[test]
+    fn proposals_are_tracked_until_executed() {
+        let vote = BridgeProposalEvent::VoteFor(VoteFor {
+            chain_id: 5,
+            deposit_nonce: 1,
+            who: AccountId32::new([0; 32]),
+        });
+        let proposal = apply_event(None, &vote, 10);
+        assert_eq!(proposal.status, SubstrateProposalStatus::Voting);
+        assert_eq!(proposal.votes_for, 1);
+        assert!(!is_final(proposal.status));
+        let approved =
+            BridgeProposalEvent::ProposalApproved(ProposalApproved {
+                chain_id: 5,
+                deposit_nonce: 1,
+            });
+        let proposal = apply_event(Some(proposal), &approved, 11);
+        assert_eq!(proposal.status, SubstrateProposalStatus::Approved);
+        assert_eq!(proposal.updated_at, 11);
+        assert!(!is_final(proposal.status));
+        let succeeded =
+            BridgeProposalEvent::ProposalSucceeded(ProposalSucceeded {
+                chain_id: 5,
+                deposit_nonce: 1,
+            });
+        let proposal = apply_event(Some(proposal), &succeeded, 12);
+        assert_eq!(proposal.status, SubstrateProposalStatus::Succeeded);
+        assert!(is_final(proposal.status));
+    }
+
+    #[test]
+    fn rejected_proposals_are_final() {
+        let vote = BridgeProposalEvent::VoteAgainst(VoteAgainst {
+            chain_id: 5,
+            deposit_nonce: 2,
+            who: AccountId32::new([0; 32]),
+        });
+        let proposal = apply_event(None, &vote, 10);
+        assert_eq!(proposal.votes_against, 1);
+        let rejected =
+            BridgeProposalEvent::ProposalRejected(ProposalRejected {
+                chain_id: 5,
+                deposit_nonce: 2,
+            });
+        let proposal = apply_event(Some(proposal), &rejected, 11);
+        assert_eq!(proposal.status, SubstrateProposalStatus::Rejected);
+        assert!(is_final(proposal.status));
+    }
+}
@@ -16,19 +16,133 @@ use std::ops;
 use std::sync::Arc;
 use std::time::Duration;
 
+use webb::evm::contract::tornado::DepositFilter;
 use webb::evm::contract::tornado::TornadoContract;
 use webb::evm::contract::tornado::TornadoContractEvents;
+use webb::evm::contract::tornado::WithdrawalFilter;
 use webb::evm::ethers::contract::LogMeta;
 use webb::evm::ethers::prelude::*;
-use webb::evm::ethers::providers;
 use webb::evm::ethers::types;
 
 use crate::config;
-use crate::store::sled::SledStore;
-use crate::store::LeafCacheStore;
+use crate::store::cache::RelayerStore;
+use crate::store::{LeafCacheStore, NullifierStore, SpentNullifier};
+
+/// A Tornado Leaves Watcher that watches for Deposit events and save the leaves to the store,
+/// and for Withdrawal events and caches their spent nullifiers.
+#[derive(Debug)]
+pub struct TornadoLeavesWatcher {
+    handlers: super::EventHandlers<
+        TornadoContractWrapper<crate::rate_limit::HttpProvider>,
+        RelayerStore,
+        TornadoContractEvents,
+    >,
+}
+
+impl Default for TornadoLeavesWatcher {
+    fn default() -> Self {
+        let handlers = super::EventHandlers::new()
+            .register(
+                "Deposit",
+                |e| match e {
+                    TornadoContractEvents::DepositFilter(v) => Some(v),
+                    _ => None,
+                },
+                TornadoDepositHandler,
+            )
+            .register(
+                "Withdrawal",
+                |e| match e {
+                    TornadoContractEvents::WithdrawalFilter(v) => Some(v),
+                    _ => None,
+                },
+                TornadoWithdrawalHandler,
+            );
+        Self { handlers }
+    }
+}
+
+/// Saves the leaf of a tornado deposit to the store.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TornadoDepositHandler;
+
+#[async_trait::async_trait]
+impl
+    super::EventHandler<
+        TornadoContractWrapper<crate::rate_limit::HttpProvider>,
+        RelayerStore,
+        DepositFilter,
+    > for TornadoDepositHandler
+{
+    async fn handle_event(
+        &self,
+        store: Arc<RelayerStore>,
+        contract: &TornadoContractWrapper<crate::rate_limit::HttpProvider>,
+        deposit: &DepositFilter,
+        log: &LogMeta,
+    ) -> anyhow::Result<()> {
+        let value = (deposit.leaf_index, H256::from_slice(&deposit.commitment));
+        let chain_id = contract.client().get_chainid().await?;
+        super::insert_leaves_or_roll_back(
+            store.as_ref(),
+            (chain_id, contract.address()).into(),
+            &[value],
+        )?;
+        store.insert_leaves_block_numbers(
+            (chain_id, contract.address()),
+            &[(value.0, log.block_number)],
+        )?;
+        store.insert_last_deposit_block_number(
+            (chain_id, contract.address()),
+            log.block_number,
+        )?;
+        tracing::debug!("Saved Deposit Event ({}, {})", value.0, value.1);
+        Ok(())
+    }
+}
+
+/// Caches the nullifier spent by a tornado withdraw, so the relayer rejects its replays early.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TornadoWithdrawalHandler;
+
+#[async_trait::async_trait]
+impl
+    super::EventHandler<
+        TornadoContractWrapper<crate::rate_limit::HttpProvider>,
+        RelayerStore,
+        WithdrawalFilter,
+    > for TornadoWithdrawalHandler
+{
+    async fn handle_event(
+        &self,
+        store: Arc<RelayerStore>,
+        contract: &TornadoContractWrapper<crate::rate_limit::HttpProvider>,
+        withdrawal: &WithdrawalFilter,
+        log: &LogMeta,
+    ) -> anyhow::Result<()> {
+        let nullifier_hash = H256::from_slice(&withdrawal.nullifier_hash);
+        let chain_id = contract.client().get_chainid().await?;
+        // the nullifier got spent when its block got mined, not when it got cached.
+        let spent_at = contract
+            .client()
+            .get_block(log.block_hash)
+            .await?
+            .map(|b| b.timestamp.as_u64());
+        let spent = SpentNullifier {
+            tx_hash: Some(log.transaction_hash),
+            block_number: Some(log.block_number),
+            spent_at,
+        };
+        store.insert_spent_nullifier(
+            (chain_id, contract.address()),
+            nullifier_hash,
+            spent,
+        )?;
+        tracing::debug!("Saved Withdrawal Event ({})", nullifier_hash);
+        Ok(())
+    }
+}
 
-#[derive(Copy, Clone, Debug)]
-pub struct TornadoLeavesWatcher;
 /// Represents a Tornado leaves watcher.
 #[derive(Clone, Debug)]
 pub struct TornadoContractWrapper<M: Middleware> {
@@ -59,12 +173,28 @@ impl<M: Middleware> super::WatchableContract for TornadoContractWrapper<M> {
         self.config.common.deployed_at.into()
     }
 
+    fn sync_from(&self) -> config::SyncFrom {
+        self.config.common.sync_from()
+    }
+
     fn polling_interval(&self) -> Duration {
-        Duration::from_millis(self.config.events_watcher.polling_interval)
+        Duration::from_millis(self.config.events_watcher.polling_interval())
     }
 
     fn max_events_per_step(&self) -> types::U64 {
-        self.config.events_watcher.max_events_per_step.into()
+        self.config.events_watcher.max_events_per_step().into()
+    }
+
+    fn confirmations(&self) -> types::U64 {
+        self.config.events_watcher.confirmations().into()
+    }
+
+    fn polling_jitter(&self) -> Duration {
+        Duration::from_millis(self.config.events_watcher.polling_jitter)
+    }
+
+    fn circuit_breaker(&self) -> config::CircuitBreakerConfig {
+        self.config.events_watcher.circuit_breaker
     }
 
     fn print_progress_interval(&self) -> Duration {
@@ -78,13 +208,21 @@ impl<M: Middleware> super::WatchableContract for TornadoContractWrapper<M> {
 impl super::EventWatcher for TornadoLeavesWatcher {
     const TAG: &'static str = "Tornado Watcher For Leaves";
 
-    type Middleware = providers::Provider<providers::Http>;
+    type Middleware = crate::rate_limit::HttpProvider;
 
     type Contract = TornadoContractWrapper<Self::Middleware>;
 
     type Events = TornadoContractEvents;
 
-    type Store = SledStore;
+    type Store = RelayerStore;
+
+    fn event_kinds(&self) -> Vec<&'static str> {
+        self.handlers.kinds()
+    }
+
+    fn event_kind(&self, event: &Self::Events) -> Option<&'static str> {
+        self.handlers.kind_of(event)
+    }
 
     #[tracing::instrument(skip_all)]
     async fn handle_event(
@@ -93,30 +231,9 @@ impl super::EventWatcher for TornadoLeavesWatcher {
         contract: &Self::Contract,
         (event, log): (Self::Events, LogMeta),
     ) -> anyhow::Result<()> {
-        match event {
-            TornadoContractEvents::DepositFilter(deposit) => {
-                let commitment = deposit.commitment;
-                let leaf_index = deposit.leaf_index;
-                let value = (leaf_index, H256::from_slice(&commitment));
-                let chain_id = contract.client().get_chainid().await?;
-                store
-                    .insert_leaves((chain_id, contract.address()), &[value])?;
-                store.insert_last_deposit_block_number(
-                    (chain_id, contract.address()),
-                    log.block_number,
-                )?;
-
-                tracing::debug!(
-                    "Saved Deposit Event ({}, {})",
-                    value.0,
-                    value.1
-                );
-            }
-            TornadoContractEvents::WithdrawalFilter(_) => {
-                // we don't care for withdraw events for now
-            }
-        };
-
+        self.handlers
+            .dispatch(store, contract, &event, &log)
+            .await?;
         Ok(())
     }
 }
@@ -0,0 +1,257 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+
+use crate::config::HaConfig;
+use crate::context::RelayerContext;
+
+/// Takes the lease if it is free, or renews it if this instance holds it.
+///
+/// Returns `1` if this instance holds the lease, `0` otherwise.
+const ACQUIRE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 1
+end
+return 0
+"#;
+
+/// Frees the lease, only if this instance holds it.
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// The lease is renewed every third of its time to live.
+fn renew_interval(ttl: Duration) -> Duration {
+    ttl / 3
+}
+
+/// How long a call to the Redis server could take, before giving up.
+///
+/// A renewal that gives up, and the next one, both end before the lease expires, so the
+/// leader steps down before another instance could take over.
+fn call_timeout(ttl: Duration) -> Duration {
+    renew_interval(ttl) / 2
+}
+
+/// The LeaderElection elects the leader among the instances of a deployment, the one
+/// holding the lease in the shared Redis server, see [`HaConfig`].
+pub struct LeaderElection {
+    ctx: RelayerContext,
+    client: redis::Client,
+    /// The connection to the Redis server, opened on the first renewal, and opened
+    /// again after a failure.
+    conn: tokio::sync::Mutex<Option<MultiplexedConnection>>,
+    lease_key: String,
+    instance_id: String,
+    ttl: Duration,
+}
+
+impl LeaderElection {
+    /// Creates a new LeaderElection instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - RelayContext reference that holds the configuration
+    /// * `config` - The configuration of the high-availability mode
+    pub fn new(ctx: RelayerContext, config: &HaConfig) -> anyhow::Result<Self> {
+        let client = redis::Client::open(config.redis_url.as_str())?;
+        let instance_id = config
+            .instance_id
+            .clone()
+            .unwrap_or_else(|| hex::encode(rand::random::<[u8; 16]>()));
+        Ok(Self {
+            ctx,
+            client,
+            conn: Default::default(),
+            lease_key: config.lease_key.clone(),
+            instance_id,
+            ttl: Duration::from_millis(config.lease_ttl),
+        })
+    }
+    /// Competes for the lease, and keeps renewing it while this instance is the leader.
+    ///
+    /// The instance is demoted to a standby as soon as it fails to renew the lease, and
+    /// promoted back once it takes it again. It never resolves.
+    #[tracing::instrument(skip_all, fields(instance = %self.instance_id))]
+    pub async fn run(&self) {
+        let interval = renew_interval(self.ttl);
+        loop {
+            self.renew().await;
+            tokio::time::sleep(interval).await;
+        }
+    }
+    /// Takes or renews the lease, and updates the leader flag of the context.
+    ///
+    /// A failed or timed out renewal clears the flag, the lease could expire before the
+    /// next one succeeds.
+    async fn renew(&self) {
+        let leader = match self.try_acquire().await {
+            Ok(acquired) => acquired,
+            Err(e) => {
+                tracing::warn!("Failed to renew the leader lease: {}", e);
+                false
+            }
+        };
+        let was_leader = self.ctx.set_leader(leader);
+        if leader != was_leader {
+            if leader {
+                tracing::warn!("Elected as the leader, relaying transactions");
+            } else {
+                tracing::warn!(
+                    "Stepped down to a standby, not relaying transactions"
+                );
+            }
+            tracing::event!(
+                target: crate::probe::TARGET,
+                tracing::Level::DEBUG,
+                kind = %crate::probe::Kind::LeaderElection,
+                instance = %self.instance_id,
+                leader = %leader,
+            );
+        }
+    }
+    /// Frees the lease, if this instance holds it, so a standby takes over right away.
+    pub async fn release(&self) -> anyhow::Result<()> {
+        self.ctx.set_leader(false);
+        let script = redis::Script::new(RELEASE_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation.key(&self.lease_key).arg(&self.instance_id);
+        self.invoke(&invocation).await?;
+        Ok(())
+    }
+
+    async fn try_acquire(&self) -> anyhow::Result<bool> {
+        let ttl = u64::try_from(self.ttl.as_millis())?;
+        let script = redis::Script::new(ACQUIRE_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(&self.lease_key)
+            .arg(&self.instance_id)
+            .arg(ttl);
+        let held = self.invoke(&invocation).await?;
+        Ok(held == 1)
+    }
+
+    /// Runs the script over the kept connection, the connection is dropped if it fails
+    /// or times out, so the next call opens a new one.
+    async fn invoke(
+        &self,
+        invocation: &redis::ScriptInvocation<'_>,
+    ) -> anyhow::Result<i64> {
+        let timeout = call_timeout(self.ttl);
+        let call = async {
+            let mut conn = self.conn.lock().await;
+            let mut connected = match conn.take() {
+                Some(v) => v,
+                None => self.client.get_multiplexed_tokio_connection().await?,
+            };
+            let result = invocation.invoke_async(&mut connected).await?;
+            *conn = Some(connected);
+            Ok(result)
+        };
+        match tokio::time::timeout(timeout, call).await {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!(
+                "the Redis server did not answer within {:?}",
+                timeout
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_leader_steps_down_before_its_lease_expires() {
+        let ttl = Duration::from_secs(15);
+        // a renewal giving up, then the next one, both end before the lease expires.
+        assert!(renew_interval(ttl) + call_timeout(ttl) * 2 < ttl);
+    }
+
+    fn election(lease_key: &str) -> (RelayerContext, LeaderElection) {
+        let url = std::env::var("REDIS_URL")
+            .unwrap_or_else(|_| String::from("redis://127.0.0.1:6379"));
+        election_at(&url, lease_key)
+    }
+
+    fn election_at(
+        url: &str,
+        lease_key: &str,
+    ) -> (RelayerContext, LeaderElection) {
+        let config: HaConfig = serde_json::from_value(serde_json::json!({
+            "redis-url": url,
+            "lease-key": lease_key,
+            "lease-ttl": 3_000,
+        }))
+        .unwrap();
+        let ctx = RelayerContext::new(crate::config::WebbRelayerConfig {
+            ha: Some(config.clone()),
+            ..Default::default()
+        });
+        let election = LeaderElection::new(ctx.clone(), &config).unwrap();
+        (ctx, election)
+    }
+
+    #[tokio::test]
+    async fn the_leader_steps_down_once_the_redis_server_hangs() {
+        // accepts the connections, but never answers.
+        let server =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", server.local_addr().unwrap());
+        let (ctx, election) = election_at(&url, "webb-relayer-test:hang");
+        ctx.set_leader(true);
+        let started = tokio::time::Instant::now();
+        election.renew().await;
+        assert!(!ctx.is_leader());
+        assert!(started.elapsed() < renew_interval(election.ttl));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server"]
+    async fn only_one_instance_leads_at_a_time() {
+        let lease_key = format!(
+            "webb-relayer-test:{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        );
+        let (leader_ctx, leader) = election(&lease_key);
+        let (_, standby) = election(&lease_key);
+        assert!(leader.try_acquire().await.unwrap());
+        assert!(!standby.try_acquire().await.unwrap());
+        // renewing the lease keeps it.
+        assert!(leader.try_acquire().await.unwrap());
+        assert!(!standby.try_acquire().await.unwrap());
+        // the standby takes over once the leader steps down.
+        leader_ctx.set_leader(true);
+        leader.release().await.unwrap();
+        assert!(!leader_ctx.is_leader());
+        assert!(standby.try_acquire().await.unwrap());
+        assert!(!leader.try_acquire().await.unwrap());
+        // or once the lease of a failed leader expires.
+        drop(standby);
+        tokio::time::sleep(Duration::from_millis(3_500)).await;
+        assert!(leader.try_acquire().await.unwrap());
+    }
+}
@@ -0,0 +1,543 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! The proptest [`Arbitrary`] implementations of the commands and the responses.
+//!
+//! The hashes, the addresses and the amounts are drawn over their whole range, the strings
+//! are any unicode strings, so the generated messages cover what a client could send.
+use ethereum_types::{Address, H256, U256, U64};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::strategy::Union;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::types::Bytes;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::sp_core::crypto::AccountId32;
+
+use super::types::*;
+
+fn h256() -> impl Strategy<Value = H256> {
+    any::<[u8; 32]>().prop_map(H256::from)
+}
+
+fn address() -> impl Strategy<Value = Address> {
+    any::<[u8; 20]>().prop_map(Address::from)
+}
+
+fn u256() -> impl Strategy<Value = U256> {
+    any::<[u64; 4]>().prop_map(U256)
+}
+
+fn u64() -> impl Strategy<Value = U64> {
+    any::<u64>().prop_map(U64::from)
+}
+
+#[cfg(feature = "evm")]
+fn bytes() -> impl Strategy<Value = Bytes> {
+    vec(any::<u8>(), 0..256).prop_map(Bytes::from)
+}
+
+#[cfg(feature = "substrate")]
+fn account_id() -> impl Strategy<Value = AccountId32> {
+    any::<[u8; 32]>().prop_map(AccountId32::new)
+}
+
+impl Arbitrary for Command {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        #[allow(unused_mut)]
+        let mut commands = vec![
+            Just(Command::Ping()).boxed(),
+            (any::<String>(), h256())
+                .prop_map(|(chain, tx_hash)| Command::TxStatus {
+                    chain,
+                    tx_hash,
+                })
+                .boxed(),
+            any::<String>()
+                .prop_map(|token| Command::Resume { token })
+                .boxed(),
+            any::<String>()
+                .prop_map(|id| Command::CancelJob { id })
+                .boxed(),
+            (any::<String>(), address(), h256())
+                .prop_map(|(chain, contract, commitment)| {
+                    Command::WatchCommitment {
+                        chain,
+                        contract,
+                        commitment,
+                    }
+                })
+                .boxed(),
+        ];
+        #[cfg(feature = "evm")]
+        commands.push(any::<EvmCommand>().prop_map(Command::Evm).boxed());
+        #[cfg(feature = "substrate")]
+        commands.push(
+            any::<SubstrateCommand>()
+                .prop_map(Command::Substrate)
+                .boxed(),
+        );
+        Union::new(commands).boxed()
+    }
+}
+
+#[cfg(feature = "substrate")]
+impl Arbitrary for SubstrateCommand {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        any::<MixerRelayTransaction>()
+            .prop_map(SubstrateCommand::MixerRelayTx)
+            .boxed()
+    }
+}
+
+#[cfg(feature = "substrate")]
+impl Arbitrary for MixerRelayTransaction {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            (any::<String>(), any::<u32>(), vec(any::<u8>(), 0..256)),
+            (any::<[u8; 32]>(), any::<[u8; 32]>()),
+            (account_id(), account_id()),
+            (any::<u128>(), any::<u128>()),
+        )
+            .prop_map(
+                |(
+                    (chain, id, proof),
+                    (root, nullifier_hash),
+                    (recipient, relayer),
+                    (fee, refund),
+                )| MixerRelayTransaction {
+                    chain,
+                    id,
+                    proof,
+                    root,
+                    nullifier_hash,
+                    recipient,
+                    relayer,
+                    fee,
+                    refund,
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(feature = "evm")]
+impl Arbitrary for EvmCommand {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            any::<TornadoRelayTransaction>()
+                .prop_map(EvmCommand::TornadoRelayTx),
+            any::<AnchorRelayTransaction>().prop_map(EvmCommand::AnchorRelayTx),
+            (any::<String>(), address(), any::<u32>()).prop_map(
+                |(chain, contract, start)| EvmCommand::GetLeaves {
+                    chain,
+                    contract,
+                    start,
+                }
+            ),
+        ]
+        .boxed()
+    }
+}
+
+#[cfg(feature = "evm")]
+impl Arbitrary for TornadoRelayTransaction {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            (any::<String>(), address(), bytes()),
+            (h256(), h256()),
+            (address(), address()),
+            (u256(), u256()),
+            any::<Option<String>>(),
+        )
+            .prop_map(
+                |(
+                    (chain, contract, proof),
+                    (root, nullifier_hash),
+                    (recipient, relayer),
+                    (fee, refund),
+                    identity,
+                )| TornadoRelayTransaction {
+                    chain,
+                    contract,
+                    proof,
+                    root,
+                    nullifier_hash,
+                    recipient,
+                    relayer,
+                    fee,
+                    refund,
+                    identity,
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(feature = "evm")]
+impl Arbitrary for AnchorRelayTransaction {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            (any::<String>(), address(), bytes(), bytes()),
+            (h256(), h256(), h256()),
+            (address(), address()),
+            (u256(), u256()),
+            any::<Option<String>>(),
+        )
+            .prop_map(
+                |(
+                    (chain, contract, proof, roots),
+                    (refresh_commitment, nullifier_hash, ext_data_hash),
+                    (recipient, relayer),
+                    (fee, refund),
+                    identity,
+                )| AnchorRelayTransaction {
+                    chain,
+                    contract,
+                    proof,
+                    roots,
+                    refresh_commitment,
+                    nullifier_hash,
+                    ext_data_hash,
+                    recipient,
+                    relayer,
+                    fee,
+                    refund,
+                    identity,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for CommandResponse {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            any::<PongResponse>().prop_map(CommandResponse::Pong),
+            any::<NetworkStatus>().prop_map(CommandResponse::Network),
+            any::<WithdrawStatus>().prop_map(CommandResponse::Withdraw),
+            any::<TxStatus>().prop_map(CommandResponse::TxStatus),
+            any::<usize>()
+                .prop_map(|limit| CommandResponse::TooManyJobs { limit }),
+            any::<QuotaExceeded>().prop_map(CommandResponse::QuotaExceeded),
+            prop_oneof![
+                Just(MessageEncoding::Json),
+                Just(MessageEncoding::MessagePack)
+            ]
+            .prop_map(|encoding| CommandResponse::Handshake(
+                Handshake { encoding }
+            )),
+            any::<LeavesResponse>().prop_map(CommandResponse::Leaves),
+            any::<CommitmentStatus>().prop_map(CommandResponse::Commitment),
+            any::<MalformedCommand>().prop_map(CommandResponse::Malformed),
+            any::<ValidationError>().prop_map(CommandResponse::Invalid),
+            any::<String>().prop_map(CommandResponse::Error),
+            any::<String>().prop_map(CommandResponse::Unimplemented),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for QuotaExceeded {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            prop_oneof![Just(Quota::Relays), Just(Quota::LeavesBytes)],
+            any::<u64>(),
+            any::<u64>(),
+            any::<u64>(),
+        )
+            .prop_map(|(quota, limit, used, resets_at)| QuotaExceeded {
+                quota,
+                limit,
+                used,
+                resets_at,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for MalformedCommand {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<String>(),
+            any::<Option<String>>(),
+            any::<String>(),
+            vec(any::<String>(), 0..8),
+        )
+            .prop_map(|(field, expected, reason, supported_commands)| {
+                MalformedCommand {
+                    field,
+                    expected,
+                    reason,
+                    supported_commands,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for PongResponse {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<String>(),
+            any::<String>(),
+            vec(any::<ChainSyncStatus>(), 0..4),
+            vec(any::<String>(), 0..4),
+        )
+            .prop_map(|(version, commit, chains, substrate_nodes)| {
+                PongResponse {
+                    version,
+                    commit,
+                    chains,
+                    substrate_nodes,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ChainSyncStatus {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<String>(),
+            any::<u64>(),
+            any::<bool>(),
+            vec(address(), 0..4),
+            vec(address(), 0..4),
+        )
+            .prop_map(|(name, chain_id, synced, degraded, stopped)| {
+                ChainSyncStatus {
+                    name,
+                    chain_id,
+                    synced,
+                    degraded,
+                    stopped,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for LeavesResponse {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (any::<u32>(), vec(h256(), 0..16), u64())
+            .prop_map(|(start, leaves, last_queried_block)| LeavesResponse {
+                start,
+                leaves,
+                last_queried_block,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for NetworkStatus {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            Just(NetworkStatus::Connecting),
+            Just(NetworkStatus::Connected),
+            any::<String>().prop_map(|reason| NetworkStatus::Failed { reason }),
+            Just(NetworkStatus::Disconnected),
+            Just(NetworkStatus::UnsupportedContract),
+            Just(NetworkStatus::UnsupportedChain),
+            Just(NetworkStatus::Misconfigured),
+            Just(NetworkStatus::InvalidRelayerAddress),
+            Just(NetworkStatus::UnsupportedIdentity),
+            Just(NetworkStatus::Paused),
+            Just(NetworkStatus::Standby),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for WithdrawStatus {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            Just(WithdrawStatus::Sent),
+            h256().prop_map(|tx_hash| WithdrawStatus::Submitted { tx_hash }),
+            h256().prop_map(|tx_hash| WithdrawStatus::Finalized { tx_hash }),
+            Just(WithdrawStatus::Valid),
+            Just(WithdrawStatus::InvalidMerkleRoots),
+            Just(WithdrawStatus::InvalidProof),
+            Just(WithdrawStatus::DroppedFromMemPool),
+            Just(WithdrawStatus::NullifierAlreadySpent),
+            Just(WithdrawStatus::AlreadyQueued),
+            any::<u32>()
+                .prop_map(|attempt| WithdrawStatus::Resubmitting { attempt }),
+            Just(WithdrawStatus::Cancelled),
+            (u256(), u256()).prop_map(|(fee, minimum_fee)| {
+                WithdrawStatus::Unprofitable {
+                    code: UNPROFITABLE_CODE,
+                    fee,
+                    minimum_fee,
+                }
+            }),
+            (any::<i32>(), any::<String>()).prop_map(|(code, reason)| {
+                WithdrawStatus::Errored { code, reason }
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for TxStatus {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            h256().prop_map(|tx_hash| TxStatus::Unknown { tx_hash }),
+            h256().prop_map(|tx_hash| TxStatus::Pending { tx_hash }),
+            (h256(), u64(), any::<u64>(), any::<bool>()).prop_map(
+                |(tx_hash, block_number, confirmations, success)| {
+                    TxStatus::Mined {
+                        tx_hash,
+                        block_number,
+                        confirmations,
+                        success,
+                    }
+                }
+            ),
+            h256().prop_map(|tx_hash| TxStatus::Dropped { tx_hash }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for CommitmentStatus {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            h256().prop_map(|commitment| CommitmentStatus::Watching {
+                commitment
+            }),
+            (h256(), any::<u32>()).prop_map(|(commitment, leaf_index)| {
+                CommitmentStatus::Inserted {
+                    commitment,
+                    leaf_index,
+                }
+            }),
+            h256().prop_map(|commitment| CommitmentStatus::Expired {
+                commitment
+            }),
+            (h256(), any::<usize>()).prop_map(|(commitment, limit)| {
+                CommitmentStatus::TooManyWatches { commitment, limit }
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for ValidationError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (any::<String>(), any::<String>())
+            .prop_map(|(field, reason)| ValidationError { field, reason })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_relay::validation::validate_message;
+
+    proptest! {
+        #[test]
+        fn commands_round_trip(cmd in any::<Command>()) {
+            let json = serde_json::to_string(&cmd).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Command>(&json).unwrap(), cmd);
+        }
+
+        #[test]
+        fn commands_round_trip_in_message_pack(cmd in any::<Command>()) {
+            let bytes = rmp_serde::to_vec_named(&cmd).unwrap();
+            prop_assert_eq!(rmp_serde::from_slice::<Command>(&bytes).unwrap(), cmd);
+        }
+
+        #[test]
+        fn responses_round_trip(response in any::<CommandResponse>()) {
+            let json = serde_json::to_string(&response).unwrap();
+            prop_assert_eq!(
+                serde_json::from_str::<CommandResponse>(&json).unwrap(),
+                response
+            );
+        }
+
+        #[test]
+        fn any_message_is_parsed_without_panicking(message in any::<String>()) {
+            // the path of a websocket message, before it gets handled.
+            if validate_message(message.as_bytes()).is_ok() {
+                let _ = serde_json::from_str::<Command>(&message);
+            }
+        }
+
+        #[test]
+        fn any_mutated_command_is_parsed_without_panicking(
+            cmd in any::<Command>(),
+            index in any::<prop::sample::Index>(),
+            byte in any::<u8>(),
+        ) {
+            let mut json = serde_json::to_vec(&cmd).unwrap();
+            let i = index.index(json.len());
+            json[i] = byte;
+            let _ = serde_json::from_slice::<Command>(&json);
+        }
+    }
+}
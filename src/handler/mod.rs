@@ -0,0 +1,3577 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+#![allow(clippy::large_enum_variant)]
+#![warn(missing_docs)]
+use std::collections::HashSet;
+use std::convert::{Infallible, TryFrom};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use ethereum_types::{Address, H256, U256, U64};
+use futures::prelude::*;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+use utoipa::ToSchema;
+use warp::ws::Message;
+use warp::Reply;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::{
+    contract::ContractError,
+    core::k256::SecretKey,
+    providers::Middleware,
+    signers::{LocalWallet, Signer},
+};
+
+#[cfg(feature = "evm")]
+use crate::context::KeyRotationError;
+use crate::context::RelayerContext;
+use crate::events_watcher::{
+    degraded_evm_contracts, evm_chain_synced_for, evm_watcher_restarts,
+    is_evm_chain_synced, stopped_evm_contracts, BridgeRegistry, BridgeStatus,
+};
+use crate::metrics::{self, Labels, Metric};
+use crate::proposals::{evm_typed_chain_id, TypedChainId};
+use crate::quota::{self, ApiKey, QuotaError, QuotaExceeded};
+use crate::store::cache::RelayerStore;
+use crate::store::{
+    audit, verify_audit_chain, AccountingStore, ApiUsage, ApiUsageStore,
+    AuditEntry, AuditEvent, AuditLogStore, AuditRecord, EarningsStore,
+    HistoryStore, LeafCacheStore, LeafWatchStore, LeavesIter,
+    LeavesSnapshotStore, MissingLeafBlocks, NullifierStore, ProposalStore,
+    RelayStatsStore, RelayedTxStore, StatsResolution,
+};
+#[cfg(feature = "evm")]
+use crate::tx_relay::evm::anchor::handle_anchor_relay_tx;
+#[cfg(feature = "evm")]
+use crate::tx_relay::evm::tornado::handle_tornado_relay_tx;
+use crate::tx_relay::session::{Cancellation, SESSION_TTL};
+#[cfg(feature = "substrate")]
+use crate::tx_relay::substrate::mixer::handle_substrate_mixer_relay_tx;
+use crate::tx_relay::validation::{
+    resolve_chain, validate_command, validate_message, ValidationError,
+};
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::{self, sp_core::Pair};
+
+/// A module for generating arbitrary commands and responses, for fuzzing them.
+#[cfg(any(test, feature = "arbitrary"))]
+mod arbitrary;
+/// A module for the commands and the responses of the relayer, as they are sent over the wire.
+pub mod types;
+#[doc(hidden)]
+pub use types::*;
+
+/// Generates a new id for a request, the id is attached to all the logs of the request
+/// and echoed in its responses, so a failure reported by a user could be found in the logs.
+pub fn new_request_id() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 8]>())
+}
+
+/// A command response, along with the id of the request it answers.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaggedResponse<'a> {
+    /// The response.
+    #[serde(flatten)]
+    pub response: &'a CommandResponse,
+    /// The id of the request, see [`new_request_id`].
+    pub request_id: &'a str,
+    /// The token of the session of the relay job the response is an update of, the
+    /// client resumes the session with it if its connection drops.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<&'a str>,
+}
+
+impl<'a> TaggedResponse<'a> {
+    /// Tags the response with the id of its request.
+    pub fn new(response: &'a CommandResponse, request_id: &'a str) -> Self {
+        Self {
+            response,
+            request_id,
+            session_token: None,
+        }
+    }
+
+    /// Tags the response with the token of the session of its relay job too.
+    pub fn with_session_token(mut self, token: &'a str) -> Self {
+        self.session_token = Some(token);
+        self
+    }
+
+    /// The response as a websocket message, in that encoding.
+    pub fn to_message(
+        &self,
+        encoding: MessageEncoding,
+    ) -> anyhow::Result<Message> {
+        encoding.encode(self)
+    }
+}
+
+impl MessageEncoding {
+    /// Encodes a value as a websocket message, a text message for JSON and a binary one
+    /// for MessagePack.
+    pub fn encode<T: Serialize>(self, value: &T) -> anyhow::Result<Message> {
+        match self {
+            Self::Json => Ok(Message::text(serde_json::to_string(value)?)),
+            Self::MessagePack => {
+                Ok(Message::binary(rmp_serde::to_vec_named(value)?))
+            }
+        }
+    }
+
+    /// Decodes a value from the payload of a websocket message.
+    pub fn decode<T: DeserializeOwned>(
+        self,
+        payload: &[u8],
+    ) -> anyhow::Result<T> {
+        match self {
+            Self::Json => Ok(serde_json::from_slice(payload)?),
+            Self::MessagePack => Ok(rmp_serde::from_slice(payload)?),
+        }
+    }
+
+    /// Decodes a command from the payload of a websocket message, the errors name the
+    /// offending field of the command.
+    pub fn decode_command(
+        self,
+        payload: &[u8],
+    ) -> Result<Command, MalformedCommand> {
+        match self {
+            Self::Json => {
+                let mut de = serde_json::Deserializer::from_slice(payload);
+                let cmd =
+                    serde_path_to_error::deserialize(&mut de).map_err(|e| {
+                        malformed_command(
+                            e.path().to_string(),
+                            e.inner().to_string(),
+                        )
+                    })?;
+                // like `serde_json::from_slice`, only whitespaces could follow the command.
+                de.end().map_err(|e| {
+                    malformed_command(String::from("."), e.to_string())
+                })?;
+                Ok(cmd)
+            }
+            Self::MessagePack => {
+                let mut de = rmp_serde::Deserializer::from_read_ref(payload);
+                serde_path_to_error::deserialize(&mut de).map_err(|e| {
+                    malformed_command(
+                        e.path().to_string(),
+                        e.inner().to_string(),
+                    )
+                })
+            }
+        }
+    }
+
+    /// The payload of a websocket message, if it is of the kind of this encoding; the
+    /// other messages (pings, and text or binary messages in the other encoding) are ignored.
+    pub fn payload(self, msg: &Message) -> Option<&[u8]> {
+        match self {
+            Self::Json if msg.is_text() => Some(msg.as_bytes()),
+            Self::MessagePack if msg.is_binary() => Some(msg.as_bytes()),
+            _ => None,
+        }
+    }
+}
+
+/// Describes a command that could not be deserialized at `path` (`.` for the command).
+///
+/// The missing fields are reported at the path of the field itself, rather than the path
+/// of the object missing it, and what the field should be is taken out of the serde error.
+fn malformed_command(path: String, reason: String) -> MalformedCommand {
+    let missing = reason
+        .strip_prefix("missing field `")
+        .and_then(|v| v.split('`').next());
+    let field = match missing {
+        Some(name) if path == "." => name.to_owned(),
+        Some(name) => format!("{}.{}", path, name),
+        None => path,
+    };
+    // serde_json reports the position of the error after the message.
+    let expected = reason
+        .split_once(", expected ")
+        .map(|(_, v)| v.split(" at line ").next().unwrap_or(v).to_owned());
+    MalformedCommand {
+        field,
+        expected,
+        reason,
+        supported_commands: supported_commands()
+            .iter()
+            .map(|v| String::from(*v))
+            .collect(),
+    }
+}
+
+/// The commands supported by this relayer, by their path, as they are sent over the wire.
+pub fn supported_commands() -> &'static [&'static str] {
+    &[
+        #[cfg(feature = "evm")]
+        "evm.tornadoRelayTx",
+        #[cfg(feature = "evm")]
+        "evm.anchorRelayTx",
+        #[cfg(feature = "evm")]
+        "evm.getLeaves",
+        #[cfg(feature = "substrate")]
+        "substrate.mixerRelayTx",
+        "ping",
+        "txStatus",
+        "resume",
+        "cancelJob",
+        "watchCommitment",
+    ]
+}
+
+/// The first message of a connection, when it is a [`Handshake`].
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum HandshakeMessage {
+    Handshake(Handshake),
+}
+
+/// The stream the relay handlers send their updates (the command responses) to.
+///
+/// It is bounded: sending to a full stream waits for the client to catch up, and the updates
+/// sent after the client is gone are dropped. Both are reported in the metrics.
+#[derive(Debug, Clone)]
+pub struct CommandStream {
+    tx: mpsc::Sender<CommandResponse>,
+    kind: &'static str,
+    cancellation: Cancellation,
+}
+
+impl CommandStream {
+    /// Creates a stream of that capacity, returning it along with its receiving end.
+    ///
+    /// The `kind` is the label of its metrics.
+    pub fn channel(
+        capacity: usize,
+        kind: &'static str,
+    ) -> (Self, mpsc::Receiver<CommandResponse>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let cancellation = Cancellation::default();
+        (
+            Self {
+                tx,
+                kind,
+                cancellation,
+            },
+            rx,
+        )
+    }
+
+    /// Whether the relay job sending to this stream got cancelled by its client.
+    pub fn cancellation(&self) -> &Cancellation {
+        &self.cancellation
+    }
+
+    /// Sends an update, waiting for a free slot if the stream is full.
+    ///
+    /// Returns the update back if the client is gone.
+    pub async fn send(
+        &self,
+        value: CommandResponse,
+    ) -> Result<(), mpsc::error::SendError<CommandResponse>> {
+        use mpsc::error::TrySendError;
+        let value = match self.tx.try_send(value) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(value)) => {
+                tracing::warn!(
+                    kind = self.kind,
+                    "Command stream is full, waiting for the client to catch up"
+                );
+                metrics::inc(
+                    Metric::ChannelBlocked,
+                    Labels::component(self.kind),
+                );
+                value
+            }
+            Err(TrySendError::Closed(value)) => value,
+        };
+        let result = self.tx.send(value).await;
+        if result.is_err() {
+            tracing::debug!(
+                kind = self.kind,
+                "Dropped an update, the client is gone"
+            );
+            metrics::inc(Metric::ChannelDropped, Labels::component(self.kind));
+        }
+        result
+    }
+}
+
+/// Sets up a websocket connection.
+///
+/// The commands of the connection are handled concurrently, their responses are tagged
+/// with their request id and sent over the connection one at a time. Only
+/// `connections.max-relay-jobs` relay jobs of the connection are in flight at once, and
+/// only `connections.max-commitment-watches` of its commitments are watched at once,
+/// out of `connections.max-concurrent-commands` commands in flight.
+///
+/// The messages are in JSON, unless the first one is a [`Handshake`] negotiating
+/// another encoding.
+///
+/// Returns `Ok(())` on success
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `client_ip` - The IP address of the client, recorded in the audit log
+/// * `api_key` - The API key of the client, if any, see [`quota::authenticate`]
+/// * `stream` - Websocket stream
+///
+/// # Examples
+///
+/// ```
+/// let _ = handler::accept_connection(ctx.as_ref(), store, client_ip, api_key, socket).await;
+/// ```
+pub async fn accept_connection(
+    ctx: &RelayerContext,
+    store: Arc<RelayerStore>,
+    client_ip: Option<IpAddr>,
+    api_key: Option<ApiKey>,
+    stream: warp::ws::WebSocket,
+) -> anyhow::Result<()> {
+    let (mut ws_tx, mut rx) = stream.split();
+    let mut encoding = MessageEncoding::Json;
+    // the first message is either a handshake, or already a command.
+    let first = rx.try_next().await?;
+    let handshake = first
+        .as_ref()
+        .and_then(|msg| msg.to_str().ok())
+        .and_then(|text| serde_json::from_str::<HandshakeMessage>(text).ok());
+    let first = match handshake {
+        Some(HandshakeMessage::Handshake(handshake)) => {
+            tracing::debug!(encoding = ?handshake.encoding, "Negotiated the encoding");
+            encoding = handshake.encoding;
+            let value = CommandResponse::Handshake(handshake);
+            let value = TaggedResponse::new(&value, &new_request_id())
+                .to_message(encoding)?;
+            ws_tx.send(value).await?;
+            None
+        }
+        None => first,
+    };
+    let rx = stream::iter(first.map(Ok)).chain(rx);
+    let (tx, responses) = futures::channel::mpsc::channel(
+        ctx.config.channels.command_stream_capacity,
+    );
+    let (closed_tx, closed) = tokio::sync::watch::channel(());
+    let connection = Connection {
+        client_ip,
+        api_key,
+        encoding,
+        jobs: ConnectionJobs::new(ctx.config.connections.max_relay_jobs),
+        watches: ConnectionJobs::new(
+            ctx.config.connections.max_commitment_watches,
+        ),
+        closed,
+    };
+    let max_commands = ctx.config.connections.max_concurrent_commands;
+    let writer = responses.map(Ok).forward(ws_tx);
+    // Wait for client to send over commands (such as relay transaction requests), until
+    // the connection is closed; the relay jobs in flight keep running in the background,
+    // the other commands are dropped.
+    let reader = async {
+        // the writer is done once the commands in flight drop their clones.
+        let tx = tx;
+        let closing = stream::once(async move { drop(closed_tx) })
+            .filter_map(|()| future::ready(None));
+        rx.take_while(|msg| future::ready(msg.is_ok()))
+            .filter_map(|msg| future::ready(msg.ok()))
+            .chain(closing)
+            .for_each_concurrent(max_commands, |msg| {
+                let mut tx = tx.clone();
+                let store = store.clone();
+                let connection = &connection;
+                async move {
+                    let payload = match connection.encoding.payload(&msg) {
+                        Some(payload) => payload,
+                        None => return,
+                    };
+                    let result = handle_message(
+                        ctx, store, connection, payload, &mut tx,
+                    )
+                    .await;
+                    if let Err(e) = result {
+                        tracing::warn!("Failed to handle a command: {}", e);
+                    }
+                }
+            })
+            .await;
+    };
+    let (_, written) = future::join(reader, writer).await;
+    written?;
+    Ok(())
+}
+
+/// The state of a client connection, shared by its commands.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    /// The IP address of the client, recorded in the audit log.
+    pub client_ip: Option<IpAddr>,
+    /// The API key of the client, its relay jobs and leaves are counted against its quotas.
+    pub api_key: Option<ApiKey>,
+    /// The encoding of the messages, see [`Handshake`].
+    pub encoding: MessageEncoding,
+    /// The relay jobs in flight of the connection.
+    pub jobs: ConnectionJobs,
+    /// The commitments watched by the connection, see [`handle_watch_commitment`].
+    pub watches: ConnectionJobs,
+    /// Changes once the client closed the connection, its sender is dropped.
+    closed: tokio::sync::watch::Receiver<()>,
+}
+
+impl Connection {
+    /// Returns once the client closed the connection.
+    pub async fn closed(&self) {
+        let mut closed = self.closed.clone();
+        while closed.changed().await.is_ok() {}
+    }
+}
+
+/// The jobs in flight of a connection (its relay jobs, or its watched commitments),
+/// bounded so a single client can't hold the relay permits of a chain, or the
+/// subscriptions of the store, with its jobs.
+#[derive(Debug, Clone)]
+pub struct ConnectionJobs {
+    permits: Arc<tokio::sync::Semaphore>,
+    limit: usize,
+}
+
+impl ConnectionJobs {
+    /// Allows up to `limit` jobs in flight.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            permits: Arc::new(tokio::sync::Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// Takes the permit of a new job, held until the job is done.
+    ///
+    /// Returns `None` if the connection has `limit` jobs in flight already.
+    pub fn try_start(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        self.permits.clone().try_acquire_owned().ok()
+    }
+
+    /// The number of jobs a connection could have in flight.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+/// Sets up a websocket channels for message sending.
+///
+/// Every command gets a new request id, attached to its logs (and the logs of its relay job)
+/// and echoed in the `requestId` field of its responses.
+///
+/// Returns `Ok(())` on success
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `connection` - The state of the connection the message got sent over
+/// * `v` - The payload of the message to be handled, in the encoding of the connection.
+/// * `tx` - A mutable Trait implementation of the `warp::ws::Sender` trait
+///
+/// # Examples
+///
+/// ```
+/// let _ = handle_message(ctx, store, &connection, payload, &mut tx).await?;;
+/// ```
+pub async fn handle_message<TX>(
+    ctx: &RelayerContext,
+    store: Arc<RelayerStore>,
+    connection: &Connection,
+    v: &[u8],
+    tx: &mut TX,
+) -> anyhow::Result<()>
+where
+    TX: Sink<Message> + Unpin,
+    TX::Error: Error + Send + Sync + 'static,
+{
+    let request_id = new_request_id();
+    let span = tracing::info_span!("command", request_id = %request_id);
+    handle_command(ctx, store, connection, v, tx, request_id)
+        .instrument(span)
+        .await
+}
+
+async fn handle_command<TX>(
+    ctx: &RelayerContext,
+    store: Arc<RelayerStore>,
+    connection: &Connection,
+    v: &[u8],
+    tx: &mut TX,
+    request_id: String,
+) -> anyhow::Result<()>
+where
+    TX: Sink<Message> + Unpin,
+    TX::Error: Error + Send + Sync + 'static,
+{
+    let client_ip = connection.client_ip;
+    let encoding = connection.encoding;
+    let jobs = &connection.jobs;
+    // reject the oversized and invalid commands before doing anything with them.
+    let mut parsed = match validate_message(v) {
+        Ok(()) => encoding.decode_command(v),
+        Err(e) => return send_invalid(tx, e, encoding, &request_id).await,
+    };
+    if let Ok(cmd) = &mut parsed {
+        // the chain could be sent as its chain id.
+        resolve_chain(&ctx.config, cmd);
+        if let Err(e) = validate_command(&ctx.config, cmd) {
+            return send_invalid(tx, e, encoding, &request_id).await;
+        }
+    }
+    match parsed {
+        Ok(Command::Resume { token }) => match ctx.sessions().get(&token) {
+            Some(session) => {
+                tracing::debug!(
+                    job_request_id = session.request_id(),
+                    "Resuming the session of a relay job"
+                );
+                audit(
+                    store.as_ref(),
+                    AuditEntry {
+                        client_ip,
+                        job_id: Some(token.clone()),
+                        event: AuditEvent::CommandAccepted {
+                            command: String::from("resume"),
+                            chain: None,
+                        },
+                    },
+                );
+                session.forward(tx, encoding).await?;
+                ctx.sessions().remove(session.token());
+            }
+            None => {
+                let error = CommandResponse::Error(format!(
+                    "Unknown or expired session: {}",
+                    token
+                ));
+                let value = TaggedResponse::new(&error, &request_id);
+                tx.send(value.to_message(encoding)?).await?
+            }
+        },
+        Ok(cmd) if cmd.is_relay_job() => {
+            // the job holds its permit until it is done, even if the connection drops.
+            let permit = match jobs.try_start() {
+                Some(permit) => permit,
+                None => {
+                    tracing::warn!(
+                        limit = jobs.limit(),
+                        "Too many relay jobs in flight on the connection"
+                    );
+                    let value = CommandResponse::TooManyJobs {
+                        limit: jobs.limit(),
+                    };
+                    let value = TaggedResponse::new(&value, &request_id);
+                    tx.send(value.to_message(encoding)?).await?;
+                    return Ok(());
+                }
+            };
+            let relay = ApiUsage {
+                relays: 1,
+                leaves_bytes: 0,
+            };
+            if let Err(e) = quota::consume(
+                store.as_ref(),
+                connection.api_key.as_ref(),
+                relay,
+            ) {
+                let value = quota_error_response(e);
+                let value = TaggedResponse::new(&value, &request_id);
+                tx.send(value.to_message(encoding)?).await?;
+                return Ok(());
+            }
+            // relay jobs run in the background, so the client can resume the session
+            // and get the remaining updates if the connection drops, the token of
+            // the session comes with every update.
+            let (session, stream) = ctx.sessions().start(request_id.clone());
+            let token = session.token().to_owned();
+            audit(
+                store.as_ref(),
+                AuditEntry {
+                    client_ip,
+                    job_id: Some(token.clone()),
+                    event: command_audit_event(&cmd),
+                },
+            );
+            let job_ctx = ctx.clone();
+            // the relay job keeps the span of its command.
+            let job = async move {
+                let sessions = job_ctx.sessions().clone();
+                handle_cmd(job_ctx, store, cmd, stream).await;
+                drop(permit);
+                tokio::time::sleep(SESSION_TTL).await;
+                sessions.remove(&token);
+            };
+            tokio::spawn(job.in_current_span());
+            session.forward(tx, encoding).await?;
+            ctx.sessions().remove(session.token());
+        }
+        Ok(cmd) => {
+            // a watched commitment holds its permit until it is inserted or expired.
+            let watches = &connection.watches;
+            let _watch = match &cmd {
+                Command::WatchCommitment { commitment, .. } => {
+                    match watches.try_start() {
+                        Some(permit) => Some(permit),
+                        None => {
+                            tracing::warn!(
+                                limit = watches.limit(),
+                                "Too many commitments watched by the connection"
+                            );
+                            let status = CommitmentStatus::TooManyWatches {
+                                commitment: *commitment,
+                                limit: watches.limit(),
+                            };
+                            let value = CommandResponse::Commitment(status);
+                            let value =
+                                TaggedResponse::new(&value, &request_id);
+                            tx.send(value.to_message(encoding)?).await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => None,
+            };
+            if !matches!(cmd, Command::Ping()) {
+                let job_id = match &cmd {
+                    Command::CancelJob { id } => Some(id.clone()),
+                    _ => None,
+                };
+                audit(
+                    store.as_ref(),
+                    AuditEntry {
+                        client_ip,
+                        job_id,
+                        event: command_audit_event(&cmd),
+                    },
+                );
+            }
+            // for every connection, we create a new channel, where we will use to send messages
+            // over it.
+            let (my_tx, my_rx) = CommandStream::channel(
+                ctx.config.channels.command_stream_capacity,
+                "connection",
+            );
+            let res_stream = ReceiverStream::new(my_rx);
+            let api_key = connection.api_key.as_ref();
+            let leaves_store = store.clone();
+            // the responses are sent as they come, a watched commitment is
+            // answered right away, and again once it is cached.
+            let forwarded = res_stream
+                .fuse()
+                .map(|v| charge_leaves(leaves_store.as_ref(), api_key, v))
+                .inspect(|v| tracing::trace!("Sending: {:?}", v))
+                .map(|v| {
+                    TaggedResponse::new(&v, &request_id)
+                        .to_message(encoding)
+                        .expect("bad value")
+                })
+                .map(Result::Ok)
+                .forward(tx);
+            let handled = future::join(
+                handle_cmd(ctx.clone(), store, cmd, my_tx),
+                forwarded,
+            );
+            // the command is dropped once the connection is closed, along with
+            // the subscription of its watched commitment.
+            tokio::select! {
+                (_, forwarded) = handled => forwarded?,
+                _ = connection.closed() => {
+                    tracing::debug!("The connection is closed, dropping the command");
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!(field = %e.field, "Got invalid payload: {}", e.reason);
+            let error = CommandResponse::Malformed(e);
+            let value = TaggedResponse::new(&error, &request_id);
+            tx.send(value.to_message(encoding)?).await?
+        }
+    };
+    Ok(())
+}
+
+/// The response of a command refused by the quotas.
+fn quota_error_response(e: QuotaError) -> CommandResponse {
+    match e {
+        QuotaError::Exceeded(e) => CommandResponse::QuotaExceeded(e),
+        e => CommandResponse::Error(e.to_string()),
+    }
+}
+
+/// Counts the leaves of a `Leaves` response against the quota of the API key, the
+/// response is replaced if it would go over it.
+fn charge_leaves<S: ApiUsageStore>(
+    store: &S,
+    api_key: Option<&ApiKey>,
+    response: CommandResponse,
+) -> CommandResponse {
+    let leaves = match &response {
+        CommandResponse::Leaves(v) => v.leaves.len(),
+        _ => return response,
+    };
+    match charge_leaves_bytes(store, api_key, leaves) {
+        Ok(()) => response,
+        Err(e) => quota_error_response(e),
+    }
+}
+
+/// The audit log event of an accepted command.
+fn command_audit_event(cmd: &Command) -> AuditEvent {
+    let (command, chain) = match cmd {
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::TornadoRelayTx(cmd)) => {
+            ("tornadoRelayTx", Some(&cmd.chain))
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::AnchorRelayTx(cmd)) => {
+            ("anchorRelayTx", Some(&cmd.chain))
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::GetLeaves { chain, .. }) => {
+            ("getLeaves", Some(chain))
+        }
+        #[cfg(feature = "substrate")]
+        Command::Substrate(SubstrateCommand::MixerRelayTx(cmd)) => {
+            ("mixerRelayTx", Some(&cmd.chain))
+        }
+        Command::TxStatus { chain, .. } => ("txStatus", Some(chain)),
+        Command::Resume { .. } => ("resume", None),
+        Command::CancelJob { .. } => ("cancelJob", None),
+        Command::WatchCommitment { chain, .. } => {
+            ("watchCommitment", Some(chain))
+        }
+        Command::Ping() => ("ping", None),
+    };
+    AuditEvent::CommandAccepted {
+        command: command.to_owned(),
+        chain: chain.cloned(),
+    }
+}
+
+async fn send_invalid<TX>(
+    tx: &mut TX,
+    e: ValidationError,
+    encoding: MessageEncoding,
+    request_id: &str,
+) -> anyhow::Result<()>
+where
+    TX: Sink<Message> + Unpin,
+    TX::Error: Error + Send + Sync + 'static,
+{
+    tracing::warn!(field = %e.field, "Got invalid command: {}", e.reason);
+    let value = CommandResponse::Invalid(e);
+    tx.send(TaggedResponse::new(&value, request_id).to_message(encoding)?)
+        .await?;
+    Ok(())
+}
+
+/// Representation for IP address response
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IpInformationResponse {
+    ip: String,
+}
+/// Representation for an error response
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    error: String,
+}
+/// Representation for a request refused by the quotas of its API key
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaErrorResponse {
+    error: String,
+    /// The exhausted quota, if the request would go over one.
+    quota_exceeded: Option<QuotaExceeded>,
+}
+/// The reply to a request refused by the quotas, `401 Unauthorized` for a missing or
+/// unknown API key, `429 Too Many Requests` for an exhausted quota.
+pub fn quota_error_reply(e: QuotaError) -> warp::reply::Response {
+    let error = e.to_string();
+    let (status, quota_exceeded) = match e {
+        QuotaError::MissingApiKey | QuotaError::UnknownApiKey => {
+            (warp::http::StatusCode::UNAUTHORIZED, None)
+        }
+        QuotaError::Exceeded(v) => {
+            (warp::http::StatusCode::TOO_MANY_REQUESTS, Some(v))
+        }
+        QuotaError::Store(_) => {
+            (warp::http::StatusCode::INTERNAL_SERVER_ERROR, None)
+        }
+    };
+    let reply = warp::reply::json(&QuotaErrorResponse {
+        error,
+        quota_exceeded,
+    });
+    warp::reply::with_status(reply, status).into_response()
+}
+/// Counts the served leaves against the leaves quota of the API key.
+fn charge_leaves_bytes<S: ApiUsageStore>(
+    store: &S,
+    api_key: Option<&ApiKey>,
+    leaves: usize,
+) -> Result<(), QuotaError> {
+    let usage = ApiUsage {
+        relays: 0,
+        leaves_bytes: leaves as u64 * quota::LEAF_BYTES,
+    };
+    quota::consume(store, api_key, usage)
+}
+/// Representation for the usage of an API key today
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUsageResponse {
+    /// The name the usage of the key is counted under.
+    api_key: String,
+    /// The usage of the key today.
+    usage: ApiUsage,
+    /// The relay commands accepted per day, unlimited if missing.
+    relays_per_day: Option<u64>,
+    /// The size of the leaves served per day, in bytes, unlimited if missing.
+    leaves_bytes_per_day: Option<u64>,
+    /// Unix timestamp (in seconds) of when the usage is reset.
+    resets_at: u64,
+}
+/// The query of the websocket connections.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsQuery {
+    /// The API key of the client, for the browsers that could not send the `X-Api-Key`
+    /// header with the websocket handshake.
+    pub api_key: Option<String>,
+}
+/// Representation for the cached leaves response
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesCacheResponse {
+    /// The cached leaves, in the order of their index.
+    #[schema(value_type = Vec<String>)]
+    leaves: Vec<H256>,
+    /// The indices of the leaves, only for the leaves of a block range, which do not start
+    /// at the first leaf.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leaf_indices: Option<Vec<u32>>,
+    /// The last block the leaves got cached up to.
+    #[schema(value_type = String)]
+    last_queried_block: U64,
+}
+/// Representation for the leaf index response
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LeafIndexResponse {
+    leaf_index: u32,
+}
+/// Representation for the withdrawal lookup response
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalResponse {
+    /// Whether the nullifier hash is known to be spent on that contract.
+    spent: bool,
+    /// The hash of the withdraw transaction, if known.
+    #[schema(value_type = Option<String>)]
+    tx_hash: Option<H256>,
+    /// The block the withdraw transaction got included in, if known.
+    #[schema(value_type = Option<String>)]
+    block_number: Option<U64>,
+    /// Unix timestamp (in seconds) of the block the withdraw transaction got included in,
+    /// if known.
+    spent_at: Option<u64>,
+    /// Unix timestamp (in seconds) of when this relayer sent the withdraw transaction,
+    /// if it relayed it.
+    relayed_at: Option<u64>,
+}
+/// Representation for a registered signature bridge
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeInfo {
+    chain_id: u32,
+    typed_chain_id: u64,
+    #[schema(value_type = String)]
+    address: Address,
+    #[serde(flatten)]
+    status: BridgeStatus,
+    /// The number of commands waiting in the queue of the bridge.
+    backlog: Option<usize>,
+    /// Whether the bridge is paused, its commands are then held in its queue.
+    paused: bool,
+}
+/// The uptime of the relayer, and how long it kept up with every chain
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UptimeResponse {
+    /// When the relayer started, as a unix timestamp.
+    started_at: u64,
+    /// How long the relayer is running, in seconds.
+    uptime: u64,
+    /// The sync state of the enabled EVM chains, by their name.
+    chains: Vec<ChainUptime>,
+}
+/// How long the relayer kept up with an EVM chain
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainUptime {
+    name: String,
+    chain_id: u64,
+    /// Whether the events watchers of the chain caught up with the chain head.
+    synced: bool,
+    /// How long (in seconds) the events watchers of the chain are caught up with the
+    /// chain head, without falling behind since, if they are.
+    synced_for: Option<u64>,
+    /// How many times the events watchers of the chain got restarted since the relayer
+    /// started, after failing.
+    watcher_restarts: u64,
+}
+/// Handles the `ip` address response
+///
+/// Returns a Result with the `IpInformationResponse` on success
+///
+/// # Arguments
+///
+/// * `ip` - Option containing the IP address
+///
+/// # Examples
+///
+/// ```
+/// let _ = handler::handle_ip_info
+/// ```
+#[utoipa::path(
+    get,
+    path = "/api/v1/ip",
+    responses((status = 200, description = "The IP address of the caller", body = IpInformationResponse)),
+)]
+pub async fn handle_ip_info(
+    ip: Option<IpAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&IpInformationResponse {
+        ip: ip.unwrap().to_string(),
+    }))
+}
+/// Handles the socket address response
+///
+/// Returns a Result with the `IpInformationResponse` on success
+///
+/// # Arguments
+///
+/// * `ip` - Option containing the socket address
+///
+/// # Examples
+///
+/// ```
+/// let _ = handler::handle_ip_info
+/// ```
+pub async fn handle_socket_info(
+    ip: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&IpInformationResponse {
+        ip: ip.unwrap().ip().to_string(),
+    }))
+}
+/// Handles relayer configuration requests
+///
+/// Returns a Result with the `RelayerConfigurationResponse` on success
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+#[utoipa::path(
+    get,
+    path = "/api/v1/info",
+    responses((status = 200, description = "The configuration of the relayer, without its secrets", body = Object)),
+)]
+pub async fn handle_relayer_info(
+    ctx: Arc<RelayerContext>,
+) -> Result<impl warp::Reply, Infallible> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RelayerInformationResponse {
+        #[serde(flatten)]
+        config: crate::config::WebbRelayerConfig,
+    }
+    // clone the original config, to update it with accounts.
+    let mut config = ctx.config.clone();
+    // the keys rotated by the admin replace the configured ones.
+    #[cfg(feature = "evm")]
+    for (chain_name, v) in config.evm.iter_mut() {
+        if let Ok(key) = ctx.evm_key(chain_name) {
+            v.private_key = key;
+        }
+    }
+
+    #[cfg(feature = "evm")]
+    let _ = config
+        .evm
+        .values_mut()
+        .filter(|v| v.beneficiary.is_none())
+        .try_for_each(|v| {
+            let key = SecretKey::from_bytes(v.private_key.as_bytes())?;
+            let wallet = LocalWallet::from(key);
+            v.beneficiary = Some(wallet.address());
+            // so do the contracts that do not override it.
+            for contract in v.contracts.iter_mut() {
+                let withdraw_config = match contract {
+                    crate::config::Contract::Tornado(c) => {
+                        &mut c.withdraw_config
+                    }
+                    crate::config::Contract::Anchor(c) => {
+                        &mut c.withdraw_config
+                    }
+                    _ => continue,
+                };
+                withdraw_config.beneficiary.get_or_insert(wallet.address());
+            }
+            Result::<_, anyhow::Error>::Ok(())
+        });
+    #[cfg(feature = "substrate")]
+    let _ = config
+        .substrate
+        .values_mut()
+        .filter(|v| v.beneficiary.is_none())
+        .try_for_each(|v| {
+            v.beneficiary = Some(v.suri.public());
+            Result::<_, anyhow::Error>::Ok(())
+        });
+    #[cfg(feature = "evm")]
+    let _ = config
+        .identities
+        .values_mut()
+        .flat_map(|v| v.evm.values_mut())
+        .filter(|v| v.beneficiary.is_none())
+        .try_for_each(|v| {
+            let key = SecretKey::from_bytes(v.private_key.as_bytes())?;
+            let wallet = LocalWallet::from(key);
+            v.beneficiary = Some(wallet.address());
+            Result::<_, anyhow::Error>::Ok(())
+        });
+    Ok(warp::reply::json(&RelayerInformationResponse { config }))
+}
+/// Handles leaf data requests
+///
+/// Returns a Result with the `LeafDataResponse` on success, along with an `ETag` header.
+/// If the client already has the latest leaves (its `If-None-Match` header matches the `ETag`),
+/// an empty `304 Not Modified` response is returned instead.
+///
+/// If the client accepts `application/x-ndjson`, the leaves are streamed as they are read
+/// from the store instead, see [`leaves_ndjson_stream`].
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `if_none_match` - The value of the `If-None-Match` header, if any
+/// * `accept` - The value of the `Accept` header, if any
+/// * `query` - The block range of the deposits of the leaves, if any
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `api_key` - The value of the `X-Api-Key` header, if any
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaves/{chain_id}/{contract}",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+        ("if-none-match" = Option<String>, Header, description = "The `ETag` of the leaves the client already has"),
+        ("x-api-key" = Option<String>, Header, description = "The API key of the client"),
+        ("fromBlock" = Option<u64>, Query, description = "Only the leaves deposited at or after this block"),
+        ("toBlock" = Option<u64>, Query, description = "Only the leaves deposited at or before this block"),
+    ),
+    responses(
+        (status = 200, description = "The cached leaves", body = LeavesCacheResponse, headers(("etag" = String))),
+        (status = 304, description = "The client already has the latest leaves"),
+        (status = 400, description = "The block range ends before it starts", body = ErrorResponse),
+        (status = 401, description = "The API key is missing or unknown", body = QuotaErrorResponse),
+        (status = 409, description = "The historical leaves are not cached, only the ranges after them are served, or some leaves have no recorded deposit block, then no range is served", body = ErrorResponse),
+        (status = 429, description = "The leaves quota of the API key is exhausted", body = QuotaErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_leaves_cache(
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+    if_none_match: Option<String>,
+    accept: Option<String>,
+    query: LeavesQuery,
+    ctx: Arc<RelayerContext>,
+    api_key: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    let api_key =
+        match quota::authenticate(&ctx.config.api_keys, api_key.as_deref()) {
+            Ok(v) => v,
+            Err(e) => return Ok(quota_error_reply(e)),
+        };
+    if let Some((from, to)) = query.block_range() {
+        if from > to {
+            let reply = warp::reply::json(&ErrorResponse {
+                error: format!(
+                    "The block range ends at {} before it starts at {}",
+                    to, from
+                ),
+            });
+            return Ok(warp::reply::with_status(
+                reply,
+                warp::http::StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    }
+    let complete =
+        store
+            .get_synced_from((chain_id, contract))
+            .map(|synced_from| {
+                check_leaves_complete(synced_from, query.block_range())
+            });
+    match complete {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let reply = warp::reply::json(&ErrorResponse { error });
+            return Ok(warp::reply::with_status(
+                reply,
+                warp::http::StatusCode::CONFLICT,
+            )
+            .into_response());
+        }
+        Err(e) => {
+            let reply = warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            });
+            return Ok(warp::reply::with_status(
+                reply,
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response());
+        }
+    }
+    let key = (chain_id, contract);
+    let read = read_leaves_cache(store.as_ref(), key, query.block_range());
+    let (leaves_count, last_queried_block, in_range) = match read {
+        Ok(v) => v,
+        Err(e) if e.is::<MissingLeafBlocks>() => {
+            return Ok(error_reply(
+                warp::http::StatusCode::CONFLICT,
+                e.to_string(),
+            ));
+        }
+        Err(e) => {
+            return Ok(error_reply(
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ));
+        }
+    };
+    // the leaves of a block range are a different resource than the full set.
+    let etag = match (&in_range, query.block_range()) {
+        (Some(leaves), Some(range)) => {
+            leaves_range_etag(leaves.len(), last_queried_block, range)
+        }
+        _ => leaves_cache_etag(leaves_count, last_queried_block),
+    };
+    if matches!(if_none_match, Some(v) if etag_matches(&v, &etag)) {
+        let reply = warp::reply::with_status(
+            warp::reply(),
+            warp::http::StatusCode::NOT_MODIFIED,
+        );
+        return Ok(
+            warp::reply::with_header(reply, "etag", etag).into_response()
+        );
+    }
+    let served = in_range.as_ref().map_or(leaves_count, Vec::len);
+    if let Err(e) =
+        charge_leaves_bytes(store.as_ref(), api_key.as_ref(), served)
+    {
+        return Ok(quota_error_reply(e));
+    }
+    if matches!(accept, Some(v) if v.contains(NDJSON)) {
+        let (leaves_count, leaves): (_, LeavesIter) = match in_range {
+            Some(leaves) => {
+                (leaves.len(), Box::new(leaves.into_iter().map(Ok)))
+            }
+            None => match store.iter_leaves_with_index(key) {
+                Ok(leaves) => (leaves_count, leaves),
+                Err(e) => {
+                    return Ok(error_reply(
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        e.to_string(),
+                    ));
+                }
+            },
+        };
+        let stream =
+            leaves_ndjson_stream(leaves_count, last_queried_block, leaves);
+        let reply =
+            warp::reply::Response::new(warp::hyper::Body::wrap_stream(stream));
+        let reply = warp::reply::with_header(reply, "content-type", NDJSON);
+        return Ok(
+            warp::reply::with_header(reply, "etag", etag).into_response()
+        );
+    }
+    let (leaves, leaf_indices) = match in_range {
+        Some(leaves) => {
+            let (indices, leaves) = leaves.into_iter().unzip();
+            (leaves, Some(indices))
+        }
+        None => match store.get_leaves(key) {
+            Ok(leaves) => (leaves, None),
+            Err(e) => {
+                return Ok(error_reply(
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    e.to_string(),
+                ));
+            }
+        },
+    };
+    let reply = warp::reply::json(&LeavesCacheResponse {
+        leaves,
+        leaf_indices,
+        last_queried_block,
+    });
+    Ok(warp::reply::with_header(reply, "etag", etag).into_response())
+}
+
+/// Reads the leaves count and the last deposit block of a contract, along with its leaves
+/// deposited within the block `range`, if any.
+fn read_leaves_cache(
+    store: &crate::store::cache::RelayerStore,
+    key: (U256, Address),
+    range: Option<(U64, U64)>,
+) -> anyhow::Result<(usize, U64, Option<Vec<(u32, H256)>>)> {
+    let leaves_count = store.get_leaves_count(key)?;
+    let last_deposit_block = store.get_last_deposit_block_number(key)?;
+    let in_range = range
+        .map(|(from, to)| store.get_leaves_in_block_range(key, from, to))
+        .transpose()?;
+    Ok((leaves_count, last_deposit_block, in_range))
+}
+
+/// A JSON [`ErrorResponse`] with that status.
+fn error_reply(
+    status: warp::http::StatusCode,
+    error: String,
+) -> warp::reply::Response {
+    let reply = warp::reply::json(&ErrorResponse { error });
+    warp::reply::with_status(reply, status).into_response()
+}
+
+/// Handles the requests for the cached leaves, in the compact binary encoding
+///
+/// Returns a Result with the leaves count, the last queried block and the leaves, see
+/// [`CompactLeaves`](crate::snapshot::CompactLeaves), along with an `ETag` header. It is
+/// several times smaller and faster to parse than the JSON leaves, and the wallets verify
+/// it against the on-chain root, like the JSON leaves. If the client already has the latest
+/// leaves, an empty `304 Not Modified` response is returned instead.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `if_none_match` - The value of the `If-None-Match` header, if any
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `api_key` - The value of the `X-Api-Key` header, if any
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaves/{chain_id}/{contract}/snapshot",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+        ("if-none-match" = Option<String>, Header, description = "The `ETag` of the leaves the client already has"),
+        ("x-api-key" = Option<String>, Header, description = "The API key of the client"),
+    ),
+    responses(
+        (status = 200, description = "The cached leaves, in the compact binary encoding", content_type = "application/octet-stream", headers(("etag" = String))),
+        (status = 304, description = "The client already has the latest leaves"),
+        (status = 401, description = "The API key is missing or unknown", body = QuotaErrorResponse),
+        (status = 409, description = "The historical leaves are not cached", body = ErrorResponse),
+        (status = 429, description = "The leaves quota of the API key is exhausted", body = QuotaErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_leaves_snapshot(
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+    if_none_match: Option<String>,
+    ctx: Arc<RelayerContext>,
+    api_key: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    let api_key =
+        match quota::authenticate(&ctx.config.api_keys, api_key.as_deref()) {
+            Ok(v) => v,
+            Err(e) => return Ok(quota_error_reply(e)),
+        };
+    let result = store.get_synced_from((chain_id, contract)).and_then(|v| {
+        if let Err(error) = check_leaves_complete(v, None) {
+            return Ok(Err(error));
+        }
+        let leaves = store.get_leaves((chain_id, contract))?;
+        let block_number =
+            store.get_last_deposit_block_number((chain_id, contract))?;
+        Ok(Ok(crate::snapshot::CompactLeaves {
+            block_number: block_number.as_u64(),
+            leaves,
+        }))
+    });
+    let compact = match result {
+        Ok(Ok(v)) => v,
+        Ok(Err(error)) => {
+            let reply = warp::reply::json(&ErrorResponse { error });
+            return Ok(warp::reply::with_status(
+                reply,
+                warp::http::StatusCode::CONFLICT,
+            )
+            .into_response());
+        }
+        Err(e) => {
+            let reply = warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            });
+            return Ok(warp::reply::with_status(
+                reply,
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response());
+        }
+    };
+    let etag = leaves_cache_etag(
+        compact.leaves.len(),
+        U64::from(compact.block_number),
+    );
+    if matches!(if_none_match, Some(v) if etag_matches(&v, &etag)) {
+        let reply = warp::reply::with_status(
+            warp::reply(),
+            warp::http::StatusCode::NOT_MODIFIED,
+        );
+        return Ok(
+            warp::reply::with_header(reply, "etag", etag).into_response()
+        );
+    }
+    let served = compact.leaves.len();
+    if let Err(e) =
+        charge_leaves_bytes(store.as_ref(), api_key.as_ref(), served)
+    {
+        return Ok(quota_error_reply(e));
+    }
+    let reply = warp::reply::with_header(
+        compact.to_bytes(),
+        "content-type",
+        "application/octet-stream",
+    );
+    Ok(warp::reply::with_header(reply, "etag", etag).into_response())
+}
+
+/// The block range of a leaves request, only the leaves deposited within it are returned.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesQuery {
+    /// The first block of the range, the start of the chain if missing.
+    pub from_block: Option<u64>,
+    /// The last block of the range, the latest block if missing.
+    pub to_block: Option<u64>,
+}
+
+impl LeavesQuery {
+    /// The requested block range, if any of its ends is set.
+    fn block_range(&self) -> Option<(U64, U64)> {
+        if self.from_block.is_none() && self.to_block.is_none() {
+            return None;
+        }
+        let from = self.from_block.unwrap_or_default();
+        let to = self.to_block.unwrap_or(u64::MAX);
+        Some((U64::from(from), U64::from(to)))
+    }
+}
+
+/// Checks that the cached leaves cover the requested block `range` (all of them if
+/// missing), when the history of the contract is only `synced_from` a block.
+///
+/// The leaves deposited before it are not cached, so serving them as the full set would
+/// break the proofs of the clients.
+fn check_leaves_complete(
+    synced_from: Option<U64>,
+    range: Option<(U64, U64)>,
+) -> Result<(), String> {
+    let synced_from = match synced_from {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    match range {
+        Some((from, _)) if from >= synced_from => Ok(()),
+        _ => Err(format!(
+            "The leaves deposited before block {} are not cached, only the block ranges from it are served",
+            synced_from
+        )),
+    }
+}
+
+/// The media type of the streamed leaves, one JSON document per line.
+const NDJSON: &str = "application/x-ndjson";
+
+/// The number of lines of a leaves stream read ahead of the client.
+const LEAVES_STREAM_BUFFER: usize = 1024;
+
+/// Streams the leaves as newline delimited JSON.
+///
+/// The first line holds the `leavesCount` and the `lastQueriedBlock`, then every line holds
+/// a leaf with its `index`, in the order of the store. The leaves are read on a blocking
+/// thread, as reading the store blocks, and the reading stops once the client is gone.
+fn leaves_ndjson_stream(
+    leaves_count: usize,
+    last_queried_block: U64,
+    leaves: LeavesIter,
+) -> impl Stream<Item = anyhow::Result<Vec<u8>>> + Send + 'static {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct LeavesHeader {
+        leaves_count: usize,
+        last_queried_block: U64,
+    }
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct IndexedLeaf {
+        index: u32,
+        leaf: H256,
+    }
+    fn line<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+    let header = line(&LeavesHeader {
+        leaves_count,
+        last_queried_block,
+    });
+    let leaves = leaves.map(|leaf| {
+        let (index, leaf) = leaf?;
+        line(&IndexedLeaf { index, leaf })
+    });
+    let (tx, rx) = tokio::sync::mpsc::channel(LEAVES_STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        for line in std::iter::once(header).chain(leaves) {
+            let failed = line.is_err();
+            if tx.blocking_send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// The `ETag` of the leaves of a contract, it changes whenever a new leaf is cached.
+fn leaves_cache_etag(leaves_count: usize, last_deposit_block: U64) -> String {
+    format!("\"{}-{}\"", leaves_count, last_deposit_block)
+}
+
+/// The `ETag` of the leaves of a contract deposited within a block `range`, it changes
+/// whenever a new leaf of the range is cached.
+fn leaves_range_etag(
+    leaves_count: usize,
+    last_deposit_block: U64,
+    (from, to): (U64, U64),
+) -> String {
+    format!(
+        "\"{}-{}-{}-{}\"",
+        leaves_count, last_deposit_block, from, to
+    )
+}
+
+/// Checks if the `If-None-Match` header value matches the `ETag`.
+///
+/// The header could be a list of (possibly weak) tags, or `*`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Checks if the `Accept-Encoding` header value accepts that content `encoding`.
+///
+/// The header is a list of encodings, with an optional quality, `q=0` refuses it.
+pub fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|v| {
+        let mut parts = v.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let refused = parts.any(|p| {
+            matches!(p.strip_prefix("q="), Some(q) if q.parse::<f32>() == Ok(0.0))
+        });
+        name.eq_ignore_ascii_case(encoding) && !refused
+    })
+}
+/// Handles the requests for the index of a leaf by its commitment
+///
+/// Returns a Result with the `LeafIndexResponse` on success, or a 404 if
+/// the commitment is not cached (yet) for that contract.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `commitment` - The commitment of the leaf
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaves/{chain_id}/{contract}/index/{commitment}",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+        ("commitment" = String, Path, description = "The commitment of the leaf"),
+    ),
+    responses(
+        (status = 200, description = "The index of the leaf", body = LeafIndexResponse),
+        (status = 404, description = "The leaf is not cached", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_leaf_index(
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+    commitment: H256,
+) -> Result<impl warp::Reply, Infallible> {
+    match store.get_leaf_index((chain_id, contract), commitment) {
+        Ok(Some(leaf_index)) => Ok(warp::reply::with_status(
+            warp::reply::json(&LeafIndexResponse { leaf_index }),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: String::from("leaf not found"),
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// Handles the requests for the withdraw that spent a nullifier hash
+///
+/// Looks the nullifier hash up in the cache of the spent nullifiers, and its transaction in
+/// the tx history, for the support requests about "missing" withdraws. A nullifier that is
+/// not known to be spent is answered with `spent: false`, the withdraw could still have
+/// been relayed by another relayer on the contracts whose withdraws are not watched.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `nullifier_hash` - The nullifier hash spent by the withdraw
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/{chain_id}/{contract}/{nullifier_hash}",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+        ("nullifier_hash" = String, Path, description = "The nullifier hash spent by the withdraw"),
+    ),
+    responses(
+        (status = 200, description = "The withdraw spending the nullifier hash", body = WithdrawalResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_withdrawal(
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+    nullifier_hash: H256,
+) -> Result<impl warp::Reply, Infallible> {
+    match withdrawal(store.as_ref(), chain_id, contract, nullifier_hash) {
+        Ok(v) => Ok(warp::reply::with_status(
+            warp::reply::json(&v),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+fn withdrawal<S: NullifierStore + RelayedTxStore>(
+    store: &S,
+    chain_id: U256,
+    contract: Address,
+    nullifier_hash: H256,
+) -> anyhow::Result<WithdrawalResponse> {
+    let spent = match store
+        .get_spent_nullifier((chain_id, contract), nullifier_hash)?
+    {
+        Some(v) => v,
+        None => {
+            return Ok(WithdrawalResponse {
+                spent: false,
+                tx_hash: None,
+                block_number: None,
+                spent_at: None,
+                relayed_at: None,
+            })
+        }
+    };
+    let relayed_at = match spent.tx_hash {
+        Some(tx_hash) => store
+            .get_relayed_tx(evm_typed_chain_id(chain_id)?, tx_hash)?
+            .map(|tx| tx.relayed_at),
+        None => None,
+    };
+    Ok(WithdrawalResponse {
+        spent: true,
+        tx_hash: spent.tx_hash,
+        block_number: spent.block_number,
+        spent_at: spent.spent_at,
+        relayed_at,
+    })
+}
+/// Handles requests for the latest IPFS snapshot of the leaves
+///
+/// Returns a Result with the `LeavesSnapshotInfo` on success, or a 404 if
+/// no snapshot got exported yet for that contract.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaves/{chain_id}/{contract}/ipfs",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+    ),
+    responses(
+        (status = 200, description = "The latest snapshot of the leaves", body = crate::store::LeavesSnapshotInfo),
+        (status = 404, description = "No snapshot got exported yet", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_leaves_snapshot_cid(
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+) -> Result<impl warp::Reply, Infallible> {
+    match store.get_latest_leaves_snapshot((chain_id, contract)) {
+        Ok(Some(info)) => Ok(warp::reply::with_status(
+            warp::reply::json(&info),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: String::from("no snapshot exported yet"),
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// Handles the requests for checking the cached leaves against the on-chain roots
+///
+/// Returns a Result with the `RootsReport` on success, or a 404 if the contract is
+/// not configured on the relayer (or does not have leaves). Requires the admin token,
+/// since every check calls the contract once per known root.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+#[cfg(feature = "evm")]
+#[utoipa::path(
+    get,
+    path = "/api/v1/verify/{chain_id}/{contract}",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+    ),
+    responses(
+        (status = 200, description = "The report of the check", body = crate::verify::RootsReport),
+        (status = 401, description = "The admin token is missing or wrong", body = ErrorResponse),
+        (status = 404, description = "The contract is not configured, or the admin API is not enabled", body = ErrorResponse),
+        (status = 500, description = "The check failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_verify_roots(
+    ctx: Arc<RelayerContext>,
+    store: Arc<crate::store::cache::RelayerStore>,
+    chain_id: U256,
+    contract: Address,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "verifyRoots",
+    ) {
+        return Ok(reply);
+    }
+    let found = crate::verify::find_contract(&ctx.config, chain_id, contract);
+    let (chain_name, contract) = match found {
+        Some(v) => v,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: String::from("contract not found"),
+                }),
+                warp::http::StatusCode::NOT_FOUND,
+            ))
+        }
+    };
+    match crate::verify::verify_roots(&ctx, &store, chain_name, contract).await
+    {
+        Ok(report) => Ok(warp::reply::with_status(
+            warp::reply::json(&report),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// The query of the fee quotes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeQuoteQuery {
+    /// The relayer identity the withdraw would be relayed with, the default one if missing.
+    pub identity: Option<String>,
+}
+/// Handles the fee quote requests
+///
+/// Returns a Result with the `FeeQuote` on success, or a 404 if the contract is
+/// not configured on the relayer (or does not support withdraws), or if the identity
+/// could not relay on it.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_id` - An U256 representing the chain id of the chain to query
+/// * `contract` - An address of the contract to query
+/// * `query` - The identity the withdraw would be relayed with
+/// * `api_key` - The `X-Api-Key` header of the request
+#[cfg(feature = "evm")]
+#[utoipa::path(
+    get,
+    path = "/api/v1/fee/{chain_id}/{contract}",
+    params(
+        ("chain_id" = String, Path, description = "The chain id, in hex"),
+        ("contract" = String, Path, description = "The address of the contract"),
+        ("identity" = Option<String>, Query, description = "The relayer identity the withdraw would be relayed with"),
+    ),
+    responses(
+        (status = 200, description = "The current fee of the contract", body = crate::tx_relay::evm::fees::FeeQuote),
+        (status = 401, description = "The API key is missing or unknown", body = QuotaErrorResponse),
+        (status = 404, description = "The contract or the identity is not configured", body = ErrorResponse),
+        (status = 500, description = "The quote failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_fee_quote(
+    ctx: Arc<RelayerContext>,
+    chain_id: U256,
+    contract: Address,
+    query: FeeQuoteQuery,
+    api_key: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    if let Err(e) =
+        quota::authenticate(&ctx.config.api_keys, api_key.as_deref())
+    {
+        return Ok(quota_error_reply(e));
+    }
+    let not_found = |error: &str| {
+        warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: String::from(error),
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        )
+        .into_response()
+    };
+    let found = crate::verify::find_contract(&ctx.config, chain_id, contract);
+    let (chain_name, contract_config) = match found {
+        Some(v) => v,
+        None => return Ok(not_found("contract not found")),
+    };
+    // the same checks as the relays, an identity that could not relay the withdraw
+    // is not quoted.
+    let identity = match query.identity.as_deref() {
+        Some(name) => match ctx.config.identities.get(name) {
+            Some(v)
+                if v.evm.contains_key(chain_name)
+                    && (v.allowed_contracts.is_empty()
+                        || v.allowed_contracts.contains(&contract)) =>
+            {
+                Some(v)
+            }
+            Some(_) => {
+                return Ok(not_found("identity not allowed for contract"))
+            }
+            None => return Ok(not_found("identity not found")),
+        },
+        None => None,
+    };
+    match crate::tx_relay::evm::fees::quote_fee(
+        &ctx,
+        chain_name,
+        contract_config,
+        identity,
+    )
+    .await
+    {
+        Ok(quote) => Ok(warp::reply::with_status(
+            warp::reply::json(&quote),
+            warp::http::StatusCode::OK,
+        )
+        .into_response()),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+    }
+}
+/// Handles the earnings requests
+///
+/// Returns a Result with the fees earned by the relayer, for every token on every chain.
+/// Requires the admin token.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+#[utoipa::path(
+    get,
+    path = "/api/v1/earnings",
+    responses(
+        (status = 200, description = "The fees earned by the relayer", body = Vec<crate::store::Earnings>),
+        (status = 401, description = "The admin token is missing or wrong", body = ErrorResponse),
+        (status = 404, description = "The admin API is not enabled", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_earnings(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "earnings",
+    ) {
+        return Ok(reply);
+    }
+    match store.earnings() {
+        Ok(earnings) => Ok(warp::reply::with_status(
+            warp::reply::json(&earnings),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// The time range of an accounting request, in Unix timestamps (in seconds).
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct AccountingQuery {
+    /// The start of the range, the start of the history if missing.
+    pub from: Option<u64>,
+    /// The end of the range, now if missing.
+    pub to: Option<u64>,
+}
+/// Handles the accounting requests
+///
+/// Returns a Result with the gas spent and the fees earned by the relayer, in daily buckets
+/// per chain and contract, for the days in the requested range. Requires the admin token.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `query` - The time range of the report
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounting",
+    params(
+        ("from" = Option<u64>, Query, description = "The start of the range, a Unix timestamp in seconds"),
+        ("to" = Option<u64>, Query, description = "The end of the range, a Unix timestamp in seconds, defaults to now"),
+    ),
+    responses(
+        (status = 200, description = "The daily accounting buckets", body = Vec<crate::store::AccountingEntry>),
+        (status = 400, description = "The range ends before it starts", body = ErrorResponse),
+        (status = 401, description = "The admin token is missing or wrong", body = ErrorResponse),
+        (status = 404, description = "The admin API is not enabled", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_accounting(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    query: AccountingQuery,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "accounting",
+    ) {
+        return Ok(reply);
+    }
+    let from = query.from.unwrap_or_default();
+    let to = query.to.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    });
+    if from > to {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: format!(
+                    "The range ends at {} before it starts at {}",
+                    to, from
+                ),
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    match store.accounting(from, to) {
+        Ok(entries) => Ok(warp::reply::with_status(
+            warp::reply::json(&entries),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// The resolution and the time range of a relay stats request, in Unix timestamps (in seconds).
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct RelayStatsQuery {
+    /// The length of the buckets, hourly by default.
+    #[serde(default)]
+    pub resolution: StatsResolution,
+    /// The start of the range, the start of the retained stats if missing.
+    pub from: Option<u64>,
+    /// The end of the range, now if missing.
+    pub to: Option<u64>,
+}
+/// Handles the relay stats requests
+///
+/// Returns a Result with the number of relays per chain and contract, in hourly or daily
+/// buckets, for the buckets in the requested range. Requires the admin token.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `query` - The resolution and the time range of the stats
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/relays",
+    params(
+        ("resolution" = Option<crate::store::StatsResolution>, Query, description = "The length of the buckets, `hourly` or `daily`, defaults to `hourly`"),
+        ("from" = Option<u64>, Query, description = "The start of the range, a Unix timestamp in seconds"),
+        ("to" = Option<u64>, Query, description = "The end of the range, a Unix timestamp in seconds, defaults to now"),
+    ),
+    responses(
+        (status = 200, description = "The relay stats buckets", body = Vec<crate::store::RelayStats>),
+        (status = 400, description = "The range ends before it starts", body = ErrorResponse),
+        (status = 401, description = "The admin token is missing or wrong", body = ErrorResponse),
+        (status = 404, description = "The admin API is not enabled", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_relay_stats(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    query: RelayStatsQuery,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "relayStats",
+    ) {
+        return Ok(reply);
+    }
+    let from = query.from.unwrap_or_default();
+    let to = query.to.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    });
+    if from > to {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: format!(
+                    "The range ends at {} before it starts at {}",
+                    to, from
+                ),
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    match store.relay_stats(query.resolution, from, to) {
+        Ok(stats) => Ok(warp::reply::with_status(
+            warp::reply::json(&stats),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// Handles the proposal status requests
+///
+/// Returns a Result with the status of the signed proposal with that data hash, on its way
+/// to the Signature Bridge.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `data_hash` - The keccak256 hash of the proposal data
+#[utoipa::path(
+    get,
+    path = "/api/v1/proposals/{data_hash}",
+    params(
+        ("data_hash" = String, Path, description = "The keccak256 hash of the proposal data"),
+    ),
+    responses(
+        (status = 200, description = "The status of the proposal", body = crate::store::ProposalStatus),
+        (status = 404, description = "The proposal is not known", body = ErrorResponse),
+        (status = 500, description = "The store failed", body = ErrorResponse),
+    ),
+)]
+pub async fn handle_proposal_status(
+    store: Arc<RelayerStore>,
+    data_hash: H256,
+) -> Result<impl warp::Reply, Infallible> {
+    match store.proposal_status(data_hash.as_bytes()) {
+        Ok(Some(status)) => Ok(warp::reply::with_status(
+            warp::reply::json(&status),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: String::from("proposal not found"),
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// Handles the metrics requests
+///
+/// Returns a Result with the metrics of the relayer, in the Prometheus text format.
+pub async fn handle_metrics() -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::with_header(
+        crate::metrics::render(),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+/// Handles the registered signature bridges requests
+///
+/// Returns a Result with a list of the registered bridges, along with their status
+/// and the number of commands waiting in their queues.
+///
+/// # Arguments
+///
+/// * `bridge_registry` - The registry used for routing commands to the signature bridges
+/// * `ctx` - RelayContext reference that holds the paused bridges
+#[utoipa::path(
+    get,
+    path = "/api/v1/bridges",
+    responses((status = 200, description = "The registered signature bridges", body = Vec<BridgeInfo>)),
+)]
+pub async fn handle_bridges_info(
+    bridge_registry: BridgeRegistry<crate::store::cache::RelayerStore>,
+    ctx: Arc<RelayerContext>,
+) -> Result<impl warp::Reply, Infallible> {
+    let bridges = bridge_registry
+        .bridges()
+        .into_iter()
+        .map(|(key, status)| BridgeInfo {
+            chain_id: key.chain_id.underlying_chain_id(),
+            typed_chain_id: key.chain_id.chain_id(),
+            address: key.address,
+            status,
+            backlog: bridge_registry.backlog(key).ok(),
+            paused: ctx.is_bridge_paused(key),
+        })
+        .collect::<Vec<_>>();
+    Ok(warp::reply::json(&bridges))
+}
+/// Handles the uptime requests
+///
+/// Returns a Result with when the relayer started, how long every enabled EVM chain is
+/// synced, and how many times its events watchers got restarted since then, so operators
+/// and relayer directories could assess the reliability of the relayer.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+#[utoipa::path(
+    get,
+    path = "/api/v1/uptime",
+    responses((status = 200, description = "The uptime of the relayer and the sync state of its chains", body = UptimeResponse)),
+)]
+pub async fn handle_uptime(
+    ctx: Arc<RelayerContext>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&uptime(&ctx)))
+}
+
+fn uptime(ctx: &RelayerContext) -> UptimeResponse {
+    let started_at = ctx.started_at();
+    let uptime = started_at.elapsed().unwrap_or_default();
+    let mut chains: Vec<_> = ctx
+        .config
+        .evm
+        .iter()
+        .filter(|(_, c)| c.enabled)
+        .map(|(name, c)| {
+            let synced_for =
+                evm_chain_synced_for(U256::from(c.chain_id), uptime);
+            ChainUptime {
+                name: name.clone(),
+                chain_id: c.chain_id,
+                synced: synced_for.is_some(),
+                synced_for: synced_for.map(|v| v.as_secs()),
+                watcher_restarts: evm_watcher_restarts(U256::from(c.chain_id)),
+            }
+        })
+        .collect();
+    chains.sort_by(|a, b| a.name.cmp(&b.name));
+    UptimeResponse {
+        started_at: started_at
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        uptime: uptime.as_secs(),
+        chains,
+    }
+}
+/// Handles the usage requests of the API keys
+///
+/// Returns a Result with the usage of the API key of the client today, along with its
+/// daily quotas, so the clients could throttle themselves before getting refused.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `api_key` - The value of the `X-Api-Key` header, if any
+#[utoipa::path(
+    get,
+    path = "/api/v1/usage",
+    params(("x-api-key" = String, Header, description = "The API key of the client")),
+    responses(
+        (status = 200, description = "The usage of the API key today", body = ApiUsageResponse),
+        (status = 401, description = "The API key is missing or unknown", body = QuotaErrorResponse),
+        (status = 500, description = "The store failed", body = QuotaErrorResponse),
+    ),
+)]
+pub async fn handle_api_usage(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    api_key: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    let api_key =
+        match quota::authenticate(&ctx.config.api_keys, api_key.as_deref()) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                return Ok(quota_error_reply(QuotaError::MissingApiKey))
+            }
+            Err(e) => return Ok(quota_error_reply(e)),
+        };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let day = quota::day(now);
+    let usage = match store.api_usage(&api_key.name, day) {
+        Ok(v) => v,
+        Err(e) => return Ok(quota_error_reply(QuotaError::Store(e))),
+    };
+    Ok(warp::reply::json(&ApiUsageResponse {
+        api_key: api_key.name,
+        usage,
+        relays_per_day: api_key.config.relays_per_day,
+        leaves_bytes_per_day: api_key.config.leaves_bytes_per_day,
+        resets_at: quota::resets_at(day),
+    })
+    .into_response())
+}
+/// Checks the `Authorization` header of an admin request against the configured admin token.
+///
+/// Returns an error reply if the request is not authorized, or if the admin API is not configured.
+/// The refused requests are recorded in the audit log, along with the `action` they asked for.
+fn check_admin_auth(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    authorization: Option<String>,
+    client_ip: Option<IpAddr>,
+    action: &str,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let admin = match ctx.config.admin {
+        Some(ref admin) => admin,
+        None => {
+            return Err(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: String::from("admin API is not enabled"),
+                }),
+                warp::http::StatusCode::NOT_FOUND,
+            ))
+        }
+    };
+    let token = authorization
+        .as_deref()
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // compared in constant time, so the token could not be guessed from the timings.
+    let authorized = token
+        .map(|v| bool::from(v.as_bytes().ct_eq(admin.token.as_bytes())))
+        .unwrap_or(false);
+    if !authorized {
+        tracing::warn!(?client_ip, action, "Unauthorized admin request");
+        audit(
+            store,
+            AuditEntry {
+                client_ip,
+                job_id: None,
+                event: AuditEvent::AdminAuthFailed {
+                    action: action.to_string(),
+                },
+            },
+        );
+        return Err(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: String::from("unauthorized"),
+            }),
+            warp::http::StatusCode::UNAUTHORIZED,
+        ));
+    }
+    Ok(())
+}
+/// Handles the admin requests for pausing a chain
+///
+/// While a chain is paused, relay commands for it are rejected and its transaction queue
+/// stops broadcasting, but the leaves caching keeps running.
+///
+/// # Arguments
+///
+/// * `chain_id` - The chain id of the chain to pause
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, for the audit log
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+pub async fn handle_admin_pause_chain(
+    chain_id: u64,
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    let client_ip = remote.map(|v| v.ip());
+    Ok(set_chain_paused(
+        &ctx,
+        &store,
+        chain_id,
+        authorization,
+        client_ip,
+        true,
+    ))
+}
+/// Handles the admin requests for resuming a paused chain
+///
+/// # Arguments
+///
+/// * `chain_id` - The chain id of the chain to resume
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, for the audit log
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+pub async fn handle_admin_resume_chain(
+    chain_id: u64,
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    let client_ip = remote.map(|v| v.ip());
+    Ok(set_chain_paused(
+        &ctx,
+        &store,
+        chain_id,
+        authorization,
+        client_ip,
+        false,
+    ))
+}
+
+fn set_chain_paused(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    chain_id: u64,
+    authorization: Option<String>,
+    client_ip: Option<IpAddr>,
+    paused: bool,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ChainPauseResponse {
+        chain_id: u64,
+        paused: bool,
+    }
+    let action = if paused { "pauseChain" } else { "resumeChain" };
+    if let Err(reply) =
+        check_admin_auth(ctx, store, authorization, client_ip, action)
+    {
+        return reply;
+    }
+    // the chain id is the one of an EVM chain, or the `chain-id` of a Substrate node.
+    let evm_chains = ctx
+        .config
+        .evm
+        .values()
+        .filter(|c| c.chain_id == chain_id)
+        .filter_map(|_| u32::try_from(chain_id).ok().map(TypedChainId::Evm));
+    let substrate_nodes = ctx
+        .config
+        .substrate
+        .values()
+        .filter_map(|c| c.chain_id)
+        .filter(|id| u64::from(*id) == chain_id)
+        .map(TypedChainId::Substrate);
+    let chains = evm_chains.chain(substrate_nodes).collect::<HashSet<_>>();
+    if chains.is_empty() {
+        return warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: format!("chain {} is not configured", chain_id),
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        );
+    }
+    for typed_chain_id in chains {
+        if paused {
+            ctx.pause_chain(typed_chain_id);
+        } else {
+            ctx.resume_chain(typed_chain_id);
+        }
+    }
+    audit(
+        store,
+        AuditEntry {
+            client_ip,
+            job_id: None,
+            event: AuditEvent::AdminAction {
+                action: action.to_string(),
+                chain_id: Some(chain_id),
+            },
+        },
+    );
+    tracing::warn!(
+        "Chain {} is {} by the admin",
+        chain_id,
+        if paused { "paused" } else { "resumed" }
+    );
+    warp::reply::with_status(
+        warp::reply::json(&ChainPauseResponse { chain_id, paused }),
+        warp::http::StatusCode::OK,
+    )
+}
+/// The body of the admin requests for rotating the signing key of a chain.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotateKeyRequest {
+    /// The chain, by its name or its chain id.
+    #[serde(deserialize_with = "types::chain_name_or_id")]
+    chain: String,
+    /// The new private key, in any of the formats of the `private-key` of the config.
+    private_key: crate::config::PrivateKey,
+}
+/// Handles the admin requests for rotating the signing key of an EVM chain
+///
+/// The new key is used once the transactions in flight signed with the old one are done,
+/// both by the tx queue and the relays of the chain, without restarting the relayer.
+/// Returns a Result with the addresses of the old key and of the new one.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, for the audit log
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+/// * `body` - The chain and its new private key
+#[cfg(feature = "evm")]
+pub async fn handle_admin_rotate_key(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+    body: RotateKeyRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RotateKeyResponse {
+        chain_id: u64,
+        old_address: Address,
+        new_address: Address,
+    }
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "rotateKey",
+    ) {
+        return Ok(reply);
+    }
+    let chain = ctx
+        .config
+        .canonical_evm_chain(&body.chain)
+        .and_then(|name| Some((name, ctx.config.evm.get(name)?)));
+    let (chain_name, chain) = match chain {
+        Some(v) => v,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: format!("chain {} is not configured", body.chain),
+                }),
+                warp::http::StatusCode::NOT_FOUND,
+            ))
+        }
+    };
+    // the new key has to pay for the transactions of the chain right away.
+    let new_address = match SecretKey::from_bytes(body.private_key.as_bytes()) {
+        Ok(key) => LocalWallet::from(key).address(),
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: format!("invalid private key: {}", e),
+                }),
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    let balance = match ctx.evm_provider(chain_name).await {
+        Ok(provider) => provider.get_balance(new_address, None).await,
+        Err(e) => {
+            tracing::error!("Failed to connect to chain {}: {}", chain_name, e);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: String::from("failed to connect to the chain"),
+                }),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    match balance {
+        Ok(v) if v.is_zero() => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: format!(
+                        "the account {:?} of the new key has no balance",
+                        new_address
+                    ),
+                }),
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!(
+                "Failed to fetch the balance of {:?}: {}",
+                new_address,
+                e
+            );
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: String::from(
+                        "failed to fetch the balance of the new key",
+                    ),
+                }),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    }
+    let rotation = ctx
+        .rotate_evm_key(store.as_ref(), chain_name, body.private_key)
+        .await;
+    let (old_address, new_address) = match rotation {
+        Ok(v) => v,
+        Err(
+            e
+            @ (KeyRotationError::InvalidKey(_) | KeyRotationError::Revoked(_)),
+        ) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: e.to_string(),
+                }),
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+        Err(KeyRotationError::Other(e)) => {
+            tracing::error!(
+                "Failed to rotate the key of {}: {}",
+                chain_name,
+                e
+            );
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: String::from("failed to record the key rotation"),
+                }),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    audit(
+        &store,
+        AuditEntry {
+            client_ip: remote.map(|v| v.ip()),
+            job_id: None,
+            event: AuditEvent::AdminAction {
+                action: String::from("rotateKey"),
+                chain_id: Some(chain.chain_id),
+            },
+        },
+    );
+    tracing::warn!(
+        %old_address,
+        %new_address,
+        "Signing key of chain {} is rotated by the admin",
+        chain.chain_id,
+    );
+    Ok(warp::reply::with_status(
+        warp::reply::json(&RotateKeyResponse {
+            chain_id: chain.chain_id,
+            old_address,
+            new_address,
+        }),
+        warp::http::StatusCode::OK,
+    ))
+}
+/// The most records returned by a single audit log request.
+const MAX_AUDIT_RECORDS: usize = 500;
+/// The page of an audit log request.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct AuditLogQuery {
+    /// The sequence number of the first record, the start of the log if missing.
+    pub from: Option<u64>,
+    /// The most records to return, and at most [`MAX_AUDIT_RECORDS`].
+    pub limit: Option<usize>,
+}
+/// Handles the admin requests for the audit log
+///
+/// Returns a Result with a page of the records of the audit log, along with the sequence
+/// number of the first record of the page that breaks the hash chain, if the log got
+/// tampered with, and the sequence number where the next page starts, if any.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `query` - The page of the log to return
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+pub async fn handle_admin_audit_log(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    query: AuditLogQuery,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct AuditLogResponse {
+        records: Vec<AuditRecord>,
+        first_broken_record: Option<u64>,
+        next_from: Option<u64>,
+    }
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "auditLog",
+    ) {
+        return Ok(reply);
+    }
+    let from = query.from.unwrap_or(1).max(1);
+    let limit = query
+        .limit
+        .unwrap_or(MAX_AUDIT_RECORDS)
+        .clamp(1, MAX_AUDIT_RECORDS);
+    let result = store.audit_records(from, limit).and_then(|records| {
+        // the page is chained to the last record of the previous page.
+        let prev = match from {
+            1 => None,
+            _ => store
+                .audit_records(from - 1, 1)?
+                .pop()
+                .filter(|v| v.seq == from - 1),
+        };
+        let first_broken_record = match prev {
+            // the previous record is missing.
+            None if from > 1 && !records.is_empty() => Some(from - 1),
+            _ => verify_audit_chain(prev.as_ref(), &records)?,
+        };
+        let next_from = match records.last() {
+            Some(last) if records.len() == limit => Some(last.seq + 1),
+            _ => None,
+        };
+        Ok(AuditLogResponse {
+            records,
+            first_broken_record,
+            next_from,
+        })
+    });
+    match result {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: e.to_string(),
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+/// Handles the admin requests for the self-diagnostics
+///
+/// Actively tests the endpoints of every enabled chain, and returns a Result with the
+/// latency and the failures of every check, to tell relayer bugs from RPC outages.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, for the audit log
+/// * `authorization` - The `Authorization` header of the request
+/// * `remote` - The address of the client, recorded in the audit log
+pub async fn handle_admin_diagnostics(
+    ctx: Arc<RelayerContext>,
+    store: Arc<RelayerStore>,
+    authorization: Option<String>,
+    remote: Option<SocketAddr>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Err(reply) = check_admin_auth(
+        &ctx,
+        &store,
+        authorization,
+        remote.map(|v| v.ip()),
+        "diagnostics",
+    ) {
+        return Ok(reply);
+    }
+    let diagnostics = crate::diagnostics::diagnose(&ctx).await;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&diagnostics),
+        warp::http::StatusCode::OK,
+    ))
+}
+impl Command {
+    /// Whether the command is a relay job, which runs in the background of a session.
+    fn is_relay_job(&self) -> bool {
+        match self {
+            #[cfg(feature = "evm")]
+            Command::Evm(EvmCommand::GetLeaves { .. }) => false,
+            #[cfg(feature = "evm")]
+            Command::Evm(_) => true,
+            #[cfg(feature = "substrate")]
+            Command::Substrate(_) => true,
+            _ => false,
+        }
+    }
+}
+impl PongResponse {
+    /// Builds the response from the configuration and the sync status of the events watchers.
+    pub fn new(ctx: &RelayerContext) -> Self {
+        let mut chains: Vec<_> = ctx
+            .config
+            .evm
+            .iter()
+            .filter(|(_, c)| c.enabled)
+            .map(|(name, c)| ChainSyncStatus {
+                name: name.clone(),
+                chain_id: c.chain_id,
+                synced: is_evm_chain_synced(U256::from(c.chain_id)),
+                degraded: degraded_evm_contracts(U256::from(c.chain_id)),
+                stopped: stopped_evm_contracts(U256::from(c.chain_id)),
+            })
+            .collect();
+        chains.sort_by(|a, b| a.name.cmp(&b.name));
+        #[cfg(feature = "substrate")]
+        let substrate_nodes = {
+            let mut nodes: Vec<_> = ctx
+                .config
+                .substrate
+                .iter()
+                .filter(|(_, c)| c.enabled)
+                .map(|(name, _)| name.clone())
+                .collect();
+            nodes.sort();
+            nodes
+        };
+        #[cfg(not(feature = "substrate"))]
+        let substrate_nodes = Vec::new();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            commit: env!("GIT_COMMIT").to_owned(),
+            chains,
+            substrate_nodes,
+        }
+    }
+}
+/// Handles the command prompts for EVM and Substrate chains
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `cmd` - The command to execute
+/// * `stream` - The stream to write the response to
+pub async fn handle_cmd(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    cmd: Command,
+    stream: CommandStream,
+) {
+    use CommandResponse::*;
+    match cmd {
+        #[cfg(feature = "substrate")]
+        Command::Substrate(sub) => {
+            handle_substrate(ctx, store, sub, stream).await
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(evm) => handle_evm(ctx, store, evm, stream).await,
+        Command::Ping() => {
+            let _ = stream.send(Pong(PongResponse::new(&ctx))).await;
+        }
+        Command::TxStatus { chain, tx_hash } => {
+            handle_tx_status(ctx, store, chain, tx_hash, stream).await
+        }
+        #[cfg(feature = "evm")]
+        Command::WatchCommitment {
+            chain,
+            contract,
+            commitment,
+        } => {
+            handle_watch_commitment(
+                ctx, store, chain, contract, commitment, stream,
+            )
+            .await
+        }
+        // the leaves are only cached for the EVM chains.
+        #[cfg(not(feature = "evm"))]
+        Command::WatchCommitment { .. } => {
+            let _ = stream.send(Network(NetworkStatus::UnsupportedChain)).await;
+        }
+        Command::CancelJob { id } => {
+            let response = if ctx.sessions().cancel(&id) {
+                Withdraw(WithdrawStatus::Cancelled)
+            } else {
+                Error(format!(
+                    "Unknown job {}, or its transaction got broadcast already",
+                    id
+                ))
+            };
+            let _ = stream.send(response).await;
+        }
+        Command::Resume { .. } => {
+            let _ = stream
+                .send(Error(
+                    "Sessions can only be resumed over a websocket connection"
+                        .to_string(),
+                ))
+                .await;
+        }
+    }
+}
+/// Handler for EVM commands
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `cmd` - The command to execute
+/// * `stream` - The stream to write the response to
+#[cfg(feature = "evm")]
+pub async fn handle_evm(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    cmd: EvmCommand,
+    stream: CommandStream,
+) {
+    match cmd {
+        EvmCommand::TornadoRelayTx(cmd) => {
+            handle_tornado_relay_tx(ctx, store, cmd, stream).await
+        }
+        EvmCommand::AnchorRelayTx(cmd) => {
+            handle_anchor_relay_tx(ctx, store, cmd, stream).await
+        }
+        EvmCommand::GetLeaves {
+            chain,
+            contract,
+            start,
+        } => {
+            handle_get_leaves(ctx, store, chain, contract, start, stream).await
+        }
+    }
+}
+/// Handler for the `GetLeaves` command
+///
+/// Answers with the same leaves as the leaves cache endpoint, starting from `start`.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain` - The name of the chain of the contract
+/// * `contract` - The contract to query the leaves of
+/// * `start` - The index of the first leaf to return
+/// * `stream` - The stream to write the response to
+#[cfg(feature = "evm")]
+pub async fn handle_get_leaves(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    chain: String,
+    contract: Address,
+    start: u32,
+    stream: CommandStream,
+) {
+    let requested_chain = chain.to_lowercase();
+    let chain_id = match ctx.config.evm.get(&requested_chain) {
+        Some(v) => U256::from(v.chain_id),
+        None => {
+            tracing::warn!("Unsupported Chain: {}", requested_chain);
+            let _ = stream
+                .send(CommandResponse::Network(NetworkStatus::UnsupportedChain))
+                .await;
+            return;
+        }
+    };
+    // reading the store blocks, so the leaves are read on a blocking thread.
+    let leaves = tokio::task::spawn_blocking(move || {
+        leaves_from(store.as_ref(), chain_id, contract, start)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|v| v);
+    let response = match leaves {
+        Ok(v) => CommandResponse::Leaves(v),
+        Err(e) => {
+            tracing::error!("Error while reading the leaves: {}", e);
+            CommandResponse::Error(e.to_string())
+        }
+    };
+    let _ = stream.send(response).await;
+}
+/// Reads the cached leaves of that contract, starting from the leaf at `start`.
+fn leaves_from<S: LeafCacheStore>(
+    store: &S,
+    chain_id: U256,
+    contract: Address,
+    start: u32,
+) -> anyhow::Result<LeavesResponse> {
+    let last_queried_block =
+        store.get_last_deposit_block_number((chain_id, contract))?;
+    let leaves = store
+        .iter_leaves_with_index((chain_id, contract))?
+        .filter(|v| !matches!(v, Ok((index, _)) if *index < start))
+        .map(|v| v.map(|(_, leaf)| leaf))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(LeavesResponse {
+        start,
+        leaves,
+        last_queried_block,
+    })
+}
+/// Handler for the `WatchCommitment` command
+///
+/// Answers with `watching` until the commitment is in the leaves cache, then with its
+/// leaf index as soon as it gets cached. The commitment is watched for up to the
+/// `commitment-watch-timeout` of the connections, or until the relayer shuts down.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain` - The name of the chain of the contract
+/// * `contract` - The contract the deposit got sent to
+/// * `commitment` - The commitment of the deposit
+/// * `stream` - The stream to write the responses to
+#[cfg(feature = "evm")]
+pub async fn handle_watch_commitment(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    chain: String,
+    contract: Address,
+    commitment: H256,
+    stream: CommandStream,
+) {
+    use CommitmentStatus::*;
+    let requested_chain = chain.to_lowercase();
+    let chain_config = match ctx.config.evm.get(&requested_chain) {
+        Some(v) => v,
+        None => {
+            tracing::warn!("Unsupported Chain: {}", requested_chain);
+            let _ = stream
+                .send(CommandResponse::Network(NetworkStatus::UnsupportedChain))
+                .await;
+            return;
+        }
+    };
+    // the leaves of the other contracts are never cached.
+    let configured = chain_config
+        .contracts
+        .iter()
+        .any(|c| c.common().address == contract);
+    if !configured {
+        tracing::warn!("Unsupported Contract: {:?}", contract);
+        let _ = stream
+            .send(CommandResponse::Network(NetworkStatus::UnsupportedContract))
+            .await;
+        return;
+    }
+    let chain_id = U256::from(chain_config.chain_id);
+    let mut watch = match store.watch_leaf((chain_id, contract), commitment) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Error while watching the commitment: {}", e);
+            let _ = stream.send(CommandResponse::Error(e.to_string())).await;
+            return;
+        }
+    };
+    // a cached commitment is answered right away.
+    match (&mut watch).now_or_never() {
+        Some(Some(leaf_index)) => {
+            let status = Inserted {
+                commitment,
+                leaf_index,
+            };
+            let _ = stream.send(CommandResponse::Commitment(status)).await;
+            return;
+        }
+        Some(None) => {
+            let status = Expired { commitment };
+            let _ = stream.send(CommandResponse::Commitment(status)).await;
+            return;
+        }
+        None => {}
+    }
+    let status = Watching { commitment };
+    if stream
+        .send(CommandResponse::Commitment(status))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    let timeout = std::time::Duration::from_secs(
+        ctx.config.connections.commitment_watch_timeout,
+    );
+    let mut shutdown = ctx.shutdown_signal();
+    let status = tokio::select! {
+        Some(leaf_index) = watch => Inserted { commitment, leaf_index },
+        _ = tokio::time::sleep(timeout) => Expired { commitment },
+        _ = shutdown.recv() => Expired { commitment },
+    };
+    tracing::debug!(?status, "Done watching the commitment");
+    let _ = stream.send(CommandResponse::Commitment(status)).await;
+}
+/// Handler for the status of relayed transactions
+///
+/// Only the transactions relayed by this relayer are looked up on the chain,
+/// the others are reported as `Unknown`. The chain is an EVM chain, or a Substrate
+/// node with a configured `chain-id`, whose extrinsics are recorded once finalized.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain` - The name of the chain the transaction got sent to
+/// * `tx_hash` - The hash of the transaction
+/// * `stream` - The stream to write the response to
+pub async fn handle_tx_status(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    chain: String,
+    tx_hash: H256,
+    stream: CommandStream,
+) {
+    let requested_chain = chain.to_lowercase();
+    let mut result = None;
+    #[cfg(feature = "evm")]
+    if let Some(chain_config) = ctx.config.evm.get(&requested_chain) {
+        let chain_id = U256::from(chain_config.chain_id);
+        result = Some(
+            relayed_tx_status(
+                &ctx,
+                &store,
+                &requested_chain,
+                chain_id,
+                tx_hash,
+            )
+            .await,
+        );
+    }
+    #[cfg(feature = "substrate")]
+    if let (None, Some(chain_id)) = (
+        &result,
+        ctx.config
+            .substrate
+            .get(&requested_chain)
+            .and_then(|node| node.chain_id),
+    ) {
+        result = Some(
+            relayed_extrinsic_status(
+                &ctx,
+                &store,
+                &requested_chain,
+                chain_id,
+                tx_hash,
+            )
+            .await,
+        );
+    }
+    let result = match result {
+        Some(v) => v,
+        None => {
+            tracing::warn!("Unsupported Chain: {}", requested_chain);
+            let _ = stream
+                .send(CommandResponse::Network(NetworkStatus::UnsupportedChain))
+                .await;
+            return;
+        }
+    };
+    let response = match result {
+        Ok(status) => CommandResponse::TxStatus(status),
+        Err(e) => {
+            tracing::error!("Error while querying the tx status: {}", e);
+            CommandResponse::Error(e.to_string())
+        }
+    };
+    let _ = stream.send(response).await;
+}
+/// Resolves the status of a relayed transaction from the tx history and the chain.
+#[cfg(feature = "evm")]
+async fn relayed_tx_status(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    chain_name: &str,
+    chain_id: U256,
+    tx_hash: H256,
+) -> anyhow::Result<TxStatus> {
+    let typed_chain_id = evm_typed_chain_id(chain_id)?;
+    if store.get_relayed_tx(typed_chain_id, tx_hash)?.is_none() {
+        return Ok(TxStatus::Unknown { tx_hash });
+    }
+    let provider = ctx.evm_provider(chain_name).await?;
+    if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+        let latest_block = provider.get_block_number().await?;
+        let block_number = receipt.block_number.unwrap_or(latest_block);
+        let confirmations =
+            latest_block.saturating_sub(block_number).as_u64() + 1;
+        return Ok(TxStatus::Mined {
+            tx_hash,
+            block_number,
+            confirmations,
+            success: receipt.status == Some(U64::one()),
+        });
+    }
+    match provider.get_transaction(tx_hash).await? {
+        Some(_) => Ok(TxStatus::Pending { tx_hash }),
+        None => Ok(TxStatus::Dropped { tx_hash }),
+    }
+}
+/// Resolves the status of a relayed extrinsic from the tx history, where it is recorded
+/// once finalized, and the confirmations from the best block of the node.
+#[cfg(feature = "substrate")]
+async fn relayed_extrinsic_status(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    node_name: &str,
+    chain_id: u32,
+    tx_hash: H256,
+) -> anyhow::Result<TxStatus> {
+    let typed_chain_id = TypedChainId::Substrate(chain_id);
+    let tx = match store.get_relayed_tx(typed_chain_id, tx_hash)? {
+        Some(v) => v,
+        None => return Ok(TxStatus::Unknown { tx_hash }),
+    };
+    let (block_number, success) = match (tx.block_number, tx.success) {
+        (Some(block_number), Some(success)) => (block_number, success),
+        // submitted, but not finalized yet.
+        _ => return Ok(TxStatus::Pending { tx_hash }),
+    };
+    let client = ctx
+        .substrate_provider::<subxt::DefaultConfig>(node_name)
+        .await?;
+    let best_block = client
+        .rpc()
+        .header(None)
+        .await?
+        .map(|header| u64::from(header.number))
+        .unwrap_or(block_number);
+    Ok(TxStatus::Mined {
+        tx_hash,
+        block_number: U64::from(block_number),
+        confirmations: best_block.saturating_sub(block_number) + 1,
+        success,
+    })
+}
+
+#[cfg(feature = "evm")]
+pub fn into_withdraw_error<M: Middleware>(
+    e: ContractError<M>,
+) -> WithdrawStatus {
+    // a poor man error parser
+    // WARNING: **don't try this at home**.
+    let msg = format!("{}", e);
+    // split the error into words, lazily.
+    let mut words = msg.split_whitespace();
+    let mut reason = "unknown".to_string();
+    let mut code = -1;
+
+    while let Some(current_word) = words.next() {
+        if current_word == "(code:" {
+            code = match words.next() {
+                Some(val) => {
+                    let mut v = val.to_string();
+                    v.pop(); // remove ","
+                    v.parse().unwrap_or(-1)
+                }
+                _ => -1, // unknown code
+            };
+        } else if current_word == "message:" {
+            // next we need to collect all words in between "message:"
+            // and "data:", that would be the error message.
+            let msg: Vec<_> =
+                words.clone().take_while(|v| *v != "data:").collect();
+            reason = msg.join(" ");
+            reason.pop(); // remove the "," at the end.
+        }
+    }
+
+    WithdrawStatus::Errored { reason, code }
+}
+/// Handler for Substrate commands
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `cmd` - The command to execute
+/// * `stream` - The stream to write the response to
+#[cfg(feature = "substrate")]
+pub async fn handle_substrate<'a>(
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+    cmd: SubstrateCommand,
+    stream: CommandStream,
+) {
+    match cmd {
+        SubstrateCommand::MixerRelayTx(cmd) => {
+            handle_substrate_mixer_relay_tx(ctx, store, cmd, stream).await;
+        }
+    }
+}
+
+/// Calculates the fee for a given transaction
+pub fn calculate_fee(fee_percent: f64, principle: U256) -> U256 {
+    let mill_fee = (fee_percent * 1_000_000.0) as u32;
+    let mill_u256: U256 = principle * (mill_fee);
+    let fee_u256: U256 = mill_u256 / (1_000_000);
+    fee_u256
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percent_fee() {
+        let submitted_value =
+            U256::from_dec_str("5000000000000000").ok().unwrap();
+        let expected_fee = U256::from_dec_str("250000000000000").ok().unwrap();
+        let withdraw_fee_percent_dec = 0.05f64;
+        let formatted_fee =
+            calculate_fee(withdraw_fee_percent_dec, submitted_value);
+
+        assert_eq!(expected_fee, formatted_fee);
+    }
+
+    #[test]
+    fn chains_without_watchers_are_synced_since_the_start() {
+        let json = format!(
+            r#"{{
+                "enabled": true,
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 31340,
+                "private-key": "0x{}"
+            }}"#,
+            "ab".repeat(32)
+        );
+        let mut config = crate::config::WebbRelayerConfig::default();
+        config
+            .evm
+            .insert("hermes".into(), serde_json::from_str(&json).unwrap());
+        let ctx = RelayerContext::new(config);
+        let response = uptime(&ctx);
+        assert_eq!(response.chains.len(), 1);
+        let chain = &response.chains[0];
+        assert_eq!(chain.chain_id, 31340);
+        assert!(chain.synced);
+        assert_eq!(chain.synced_for, Some(response.uptime));
+        assert_eq!(chain.watcher_restarts, 0);
+    }
+
+    #[test]
+    fn message_pack_messages_are_binary() {
+        let value = CommandResponse::Withdraw(WithdrawStatus::Sent);
+        let msg = TaggedResponse::new(&value, "request")
+            .with_session_token("token")
+            .to_message(MessageEncoding::MessagePack)
+            .unwrap();
+        assert!(MessageEncoding::Json.payload(&msg).is_none());
+        let payload = MessageEncoding::MessagePack.payload(&msg).unwrap();
+        let decoded: serde_json::Value =
+            MessageEncoding::MessagePack.decode(payload).unwrap();
+        assert_eq!(decoded["requestId"], "request");
+        assert_eq!(decoded["sessionToken"], "token");
+        assert_eq!(decoded["withdraw"], "sent");
+    }
+
+    #[test]
+    fn only_the_relay_job_updates_carry_a_session_token() {
+        let value = CommandResponse::Withdraw(WithdrawStatus::Sent);
+        let json =
+            |tagged: TaggedResponse| serde_json::to_value(&tagged).unwrap();
+        let untagged = json(TaggedResponse::new(&value, "request"));
+        assert!(untagged.get("sessionToken").is_none());
+        let tagged = json(
+            TaggedResponse::new(&value, "request").with_session_token("token"),
+        );
+        assert_eq!(tagged["sessionToken"], "token");
+    }
+
+    #[test]
+    fn malformed_commands_name_the_offending_field() {
+        let decode = |v: &str| {
+            MessageEncoding::Json
+                .decode_command(v.as_bytes())
+                .unwrap_err()
+        };
+        let e = decode(r#"{"resume": {}}"#);
+        assert_eq!(e.field, "resume.token");
+        let e = decode(r#"{"txStatus": {"chain": "goerli", "txHash": 5}}"#);
+        assert_eq!(e.field, "txStatus.txHash");
+        assert!(e.expected.is_some());
+        let e = decode(r#"{"unknown": {}}"#);
+        assert!(e.expected.unwrap().contains("`ping`"));
+        assert!(e.supported_commands.contains(&String::from("ping")));
+        let e = decode(r#"{"ping": []} trailing"#);
+        assert_eq!(e.field, ".");
+    }
+
+    #[test]
+    fn connection_jobs_are_bounded() {
+        let jobs = ConnectionJobs::new(2);
+        let first = jobs.try_start().unwrap();
+        let _second = jobs.try_start().unwrap();
+        assert!(jobs.try_start().is_none());
+        drop(first);
+        assert!(jobs.try_start().is_some());
+    }
+
+    #[tokio::test]
+    async fn commands_are_dropped_once_the_connection_is_closed() {
+        let (closed_tx, closed) = tokio::sync::watch::channel(());
+        let connection = Connection {
+            client_ip: None,
+            api_key: None,
+            encoding: MessageEncoding::Json,
+            jobs: ConnectionJobs::new(2),
+            watches: ConnectionJobs::new(1),
+            closed,
+        };
+        let watch = connection.watches.try_start().unwrap();
+        assert!(connection.watches.try_start().is_none());
+        let closing = connection.closed();
+        futures::pin_mut!(closing);
+        assert!((&mut closing).now_or_never().is_none());
+        drop(closed_tx);
+        assert!(closing.now_or_never().is_some());
+        drop(watch);
+        assert!(connection.watches.try_start().is_some());
+    }
+
+    #[test]
+    fn compression_follows_the_accepted_encodings() {
+        assert!(accepts_encoding("gzip, deflate, br", "br"));
+        assert!(accepts_encoding("GZIP;q=0.8", "gzip"));
+        assert!(!accepts_encoding("gzip;q=0, identity", "gzip"));
+        assert!(!accepts_encoding("deflate", "br"));
+    }
+
+    #[test]
+    fn partial_leaves_are_only_served_after_the_synced_block() {
+        assert_eq!(check_leaves_complete(None, None), Ok(()));
+        let synced_from = Some(U64::from(100));
+        assert!(check_leaves_complete(synced_from, None).is_err());
+        let range = |from: u64, to: u64| Some((U64::from(from), U64::from(to)));
+        assert!(check_leaves_complete(synced_from, range(99, 200)).is_err());
+        assert_eq!(check_leaves_complete(synced_from, range(100, 200)), Ok(()));
+        assert_eq!(check_leaves_complete(synced_from, range(150, 200)), Ok(()));
+    }
+
+    #[test]
+    fn leaves_cache_etag_matching() {
+        let etag = leaves_cache_etag(3, U64::from(42));
+        assert_eq!(etag, "\"3-42\"");
+        assert!(etag_matches("\"3-42\"", &etag));
+        assert!(etag_matches("W/\"3-42\"", &etag));
+        assert!(etag_matches("\"2-40\", \"3-42\"", &etag));
+        assert!(etag_matches("*", &etag));
+        assert!(!etag_matches("\"2-40\"", &etag));
+    }
+
+    #[test]
+    fn leaves_are_read_from_the_start_index() {
+        let store = crate::store::sled::SledStore::temporary().unwrap();
+        let key = (U256::from(4), Address::zero());
+        let leaves: Vec<_> = (0..4u32)
+            .map(|i| (i, H256::from_low_u64_be(i as _)))
+            .collect();
+        store.insert_leaves(key, &leaves).unwrap();
+        let response = leaves_from(&store, key.0, key.1, 2).unwrap();
+        assert_eq!(response.start, 2);
+        assert_eq!(
+            response.leaves,
+            vec![H256::from_low_u64_be(2), H256::from_low_u64_be(3)]
+        );
+    }
+
+    #[test]
+    fn withdrawals_are_looked_up_in_the_tx_history() {
+        let store = crate::store::sled::SledStore::temporary().unwrap();
+        let (chain_id, contract) = (U256::from(4), Address::zero());
+        let nullifier_hash = H256::random();
+        let response =
+            withdrawal(&store, chain_id, contract, nullifier_hash).unwrap();
+        assert!(!response.spent);
+        let tx_hash = H256::random();
+        let spent = crate::store::SpentNullifier {
+            tx_hash: Some(tx_hash),
+            block_number: Some(U64::from(42)),
+            spent_at: Some(1_650_000_100),
+        };
+        store
+            .insert_spent_nullifier((chain_id, contract), nullifier_hash, spent)
+            .unwrap();
+        let response =
+            withdrawal(&store, chain_id, contract, nullifier_hash).unwrap();
+        assert!(response.spent);
+        assert_eq!(response.tx_hash, Some(tx_hash));
+        assert_eq!(response.relayed_at, None);
+        // the withdraw got relayed by this relayer.
+        let tx = crate::store::RelayedTx {
+            chain_id: evm_typed_chain_id(chain_id).unwrap(),
+            tx_hash,
+            relayed_at: 1_650_000_000,
+            block_number: None,
+            success: None,
+        };
+        store.insert_relayed_tx(tx).unwrap();
+        let response =
+            withdrawal(&store, chain_id, contract, nullifier_hash).unwrap();
+        assert_eq!(response.relayed_at, Some(1_650_000_000));
+    }
+
+    #[test]
+    fn leaves_are_filtered_by_deposit_block() {
+        let store = crate::store::sled::SledStore::temporary().unwrap();
+        let key = (U256::from(4), Address::zero());
+        let leaves: Vec<_> = (0..4u32)
+            .map(|i| (i, H256::from_low_u64_be(i as _)))
+            .collect();
+        store.insert_leaves(key, &leaves).unwrap();
+        let blocks: Vec<_> =
+            (0..4u32).map(|i| (i, U64::from(10 + i * 10))).collect();
+        store.insert_leaves_block_numbers(key, &blocks).unwrap();
+        let query = LeavesQuery {
+            from_block: Some(20),
+            to_block: Some(30),
+        };
+        let (from, to) = query.block_range().unwrap();
+        let in_range = store.get_leaves_in_block_range(key, from, to).unwrap();
+        assert_eq!(in_range, leaves[1..3].to_vec());
+        let query = LeavesQuery {
+            from_block: Some(35),
+            to_block: None,
+        };
+        let (from, to) = query.block_range().unwrap();
+        let in_range = store.get_leaves_in_block_range(key, from, to).unwrap();
+        assert_eq!(in_range, leaves[3..].to_vec());
+        assert!(LeavesQuery::default().block_range().is_none());
+        // a leaf cached without its block, the ranges could miss it.
+        store.insert_leaves(key, &[(4, H256::random())]).unwrap();
+        let err = store.get_leaves_in_block_range(key, from, to).unwrap_err();
+        assert_eq!(err.downcast_ref::<MissingLeafBlocks>().unwrap().missing, 1);
+    }
+
+    #[test]
+    fn leaves_of_a_range_have_their_own_etag() {
+        let full = leaves_cache_etag(3, U64::from(42));
+        let range = (U64::from(10), U64::from(20));
+        let in_range = leaves_range_etag(3, U64::from(42), range);
+        assert_eq!(in_range, "\"3-42-10-20\"");
+        assert!(!etag_matches(&full, &in_range));
+        let other = leaves_range_etag(3, U64::from(42), (range.0, 21.into()));
+        assert!(!etag_matches(&in_range, &other));
+    }
+
+    #[tokio::test]
+    async fn leaves_are_streamed_line_by_line() {
+        let leaves: LeavesIter = Box::new(
+            vec![Ok((0, H256::zero())), Ok((1, H256::zero()))].into_iter(),
+        );
+        let lines: Vec<_> = leaves_ndjson_stream(2, U64::from(42), leaves)
+            .map(|v| String::from_utf8(v.unwrap()).unwrap())
+            .collect()
+            .await;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "{\"leavesCount\":2,\"lastQueriedBlock\":\"0x2a\"}\n"
+        );
+        assert!(lines[2].starts_with("{\"index\":1,\"leaf\":\"0x0000"));
+        assert!(lines.iter().all(|v| v.ends_with('\n')));
+    }
+
+    #[cfg(feature = "substrate")]
+    #[tokio::test]
+    async fn tx_status_of_substrate_nodes_is_looked_up() {
+        let mut config = crate::config::WebbRelayerConfig::default();
+        let node = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "http-endpoint": "http://localhost:9933",
+            "ws-endpoint": "ws://localhost:9944",
+            "runtime": "WebbProtocol",
+            "suri": "//Alice",
+            "chain-id": 1080,
+        }))
+        .unwrap();
+        config.substrate.insert("webb".into(), node);
+        let ctx = RelayerContext::new(config);
+        let store = Arc::new(RelayerStore::new(
+            crate::store::sled::SledStore::temporary().unwrap(),
+            16,
+        ));
+        let tx_status = |tx_hash: H256| {
+            let (stream, mut rx) = CommandStream::channel(1, "test");
+            let (ctx, store) = (ctx.clone(), store.clone());
+            async move {
+                handle_tx_status(ctx, store, "webb".into(), tx_hash, stream)
+                    .await;
+                rx.recv().await.unwrap()
+            }
+        };
+        let tx_hash = H256::random();
+        assert!(matches!(
+            tx_status(tx_hash).await,
+            CommandResponse::TxStatus(TxStatus::Unknown { .. })
+        ));
+        // submitted by this relayer, but not finalized yet.
+        let tx = crate::store::RelayedTx {
+            chain_id: TypedChainId::Substrate(1080),
+            tx_hash,
+            relayed_at: 1_650_000_000,
+            block_number: None,
+            success: None,
+        };
+        store.insert_relayed_tx(tx).unwrap();
+        assert!(matches!(
+            tx_status(tx_hash).await,
+            CommandResponse::TxStatus(TxStatus::Pending { .. })
+        ));
+    }
+}
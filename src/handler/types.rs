@@ -0,0 +1,722 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! The commands and the responses of the relayer, as they are sent over the wire.
+//!
+//! Both directions could be serialized and deserialized, so the clients (and the tests)
+//! could build and parse exactly what the relayer speaks. They are exported by the
+//! library of this crate, as `webb_relayer::types`.
+use ethereum_types::{Address, H256, U256, U64};
+use serde::{Deserialize, Deserializer, Serialize};
+#[cfg(feature = "evm")]
+use webb::evm::ethers::types::Bytes;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::sp_core::crypto::AccountId32;
+
+use derive_more::Display;
+use utoipa::ToSchema;
+
+/// Deserializes the `chain` of a command, either the name of a configured chain or its
+/// chain id, as a string (`"5"`) or a number (`5`).
+///
+/// The chain ids are resolved to the configured names by the relayer, once the command
+/// is parsed.
+pub(crate) fn chain_name_or_id<'de, D>(
+    deserializer: D,
+) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NameOrId {
+        Name(String),
+        Id(u64),
+    }
+    match NameOrId::deserialize(deserializer)? {
+        NameOrId::Name(v) => Ok(v),
+        NameOrId::Id(v) => Ok(v.to_string()),
+    }
+}
+
+/// Deserializes the bytes of a proof, a root or a nullifier hash, sent either as a
+/// `0x`-prefixed hex string, as an array of numbers, or as raw bytes (in the binary encodings).
+#[cfg(any(feature = "evm", feature = "substrate"))]
+struct ProofBytes;
+
+#[cfg(any(feature = "evm", feature = "substrate"))]
+impl<'de> serde::de::Visitor<'de> for ProofBytes {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a 0x-prefixed hex string, or an array of bytes")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+        let hex = v.strip_prefix("0x").ok_or_else(|| {
+            E::invalid_value(serde::de::Unexpected::Str(v), &self)
+        })?;
+        hex::decode(hex).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(
+        self,
+        v: Vec<u8>,
+    ) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Vec<u8>, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(b) = seq.next_element::<u8>()? {
+            bytes.push(b);
+        }
+        Ok(bytes)
+    }
+}
+
+/// (De)serializes the proofs as hex strings in the human readable encodings (JSON), and as
+/// raw bytes in the binary ones, see [`MessageEncoding`].
+///
+/// The proofs are accepted as arrays of numbers too, so the clients could send them either way.
+#[cfg(any(feature = "evm", feature = "substrate"))]
+mod hex_bytes {
+    use serde::{Deserializer, Serializer};
+
+    use super::ProofBytes;
+
+    pub fn serialize<S, T>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        if serializer.is_human_readable() {
+            let hex = format!("0x{}", hex::encode(bytes.as_ref()));
+            serializer.serialize_str(&hex)
+        } else {
+            serializer.serialize_bytes(bytes.as_ref())
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<Vec<u8>>,
+    {
+        deserializer.deserialize_any(ProofBytes).map(Into::into)
+    }
+}
+
+/// (De)serializes the 32 bytes long roots and nullifier hashes like [`hex_bytes`], their
+/// length is checked.
+#[cfg(any(feature = "evm", feature = "substrate"))]
+mod hex_array {
+    use std::convert::TryFrom;
+
+    use serde::{de, Deserializer, Serializer};
+
+    use super::ProofBytes;
+
+    pub fn serialize<S, T>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        super::hex_bytes::serialize(bytes, serializer)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<[u8; 32]>,
+    {
+        let bytes = deserializer.deserialize_any(ProofBytes)?;
+        <[u8; 32]>::try_from(bytes)
+            .map(Into::into)
+            .map_err(|v| de::Error::invalid_length(v.len(), &"32 bytes"))
+    }
+}
+
+/// Enumerates the encodings of the messages of a connection, see [`Handshake`].
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageEncoding {
+    /// JSON text messages.
+    #[default]
+    Json,
+    /// MessagePack binary messages, where the proofs are raw bytes instead of hex strings.
+    MessagePack,
+}
+
+/// Negotiates the encoding of the messages of a connection.
+///
+/// Sent as the first message of the connection, always in JSON:
+/// `{"handshake": {"encoding": "messagePack"}}`. The other messages, in both directions,
+/// use that encoding, starting with the answer to the handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Handshake {
+    /// The encoding of the other messages.
+    pub encoding: MessageEncoding,
+}
+
+/// Enumerates the supported commands for chain specific relayers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Command {
+    #[cfg(feature = "substrate")]
+    Substrate(SubstrateCommand),
+    #[cfg(feature = "evm")]
+    Evm(EvmCommand),
+    Ping(),
+    /// Queries the status of a transaction that got relayed before, for clients that
+    /// lost their connection before getting the final status of their withdrawal.
+    TxStatus {
+        /// one of the supported chains of this relayer, by name or chain id
+        #[serde(deserialize_with = "chain_name_or_id")]
+        chain: String,
+        /// The hash of the relayed transaction
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+    /// Re-attaches to a running relay job, using the token of its session, as sent
+    /// in the `sessionToken` of its updates.
+    Resume {
+        token: String,
+    },
+    /// Cancels a running relay job, using the token of its session, if its transaction
+    /// did not get broadcast yet.
+    CancelJob {
+        id: String,
+    },
+    /// Watches a deposit, the connection gets notified as soon as its commitment is in
+    /// the leaves cache, instead of polling the leaves after depositing.
+    WatchCommitment {
+        /// one of the supported chains of this relayer, by name or chain id
+        #[serde(deserialize_with = "chain_name_or_id")]
+        chain: String,
+        /// The contract the deposit got sent to
+        contract: Address,
+        /// The commitment of the deposit
+        commitment: H256,
+    },
+}
+/// Enumerates the supported commands for the substrate relayer
+#[cfg(feature = "substrate")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubstrateCommand {
+    MixerRelayTx(MixerRelayTransaction),
+}
+/// Contains data that is relayed to the Mixers
+#[cfg(feature = "substrate")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MixerRelayTransaction {
+    /// one of the supported chains of this relayer
+    #[serde(deserialize_with = "chain_name_or_id")]
+    pub chain: String,
+    /// The tree id of the mixer's underlying tree
+    pub id: u32,
+    /// The zero-knowledge proof bytes
+    #[serde(with = "hex_bytes")]
+    pub proof: Vec<u8>,
+    /// The target merkle root for the proof
+    #[serde(with = "hex_array")]
+    pub root: [u8; 32],
+    /// The nullifier_hash for the proof
+    #[serde(with = "hex_array")]
+    pub nullifier_hash: [u8; 32],
+    /// The recipient of the transaction
+    pub recipient: AccountId32,
+    /// The relayer of the transaction
+    pub relayer: AccountId32,
+    /// The relayer's fee for the transaction
+    pub fee: u128,
+    /// The refund for the transaction in native tokens
+    pub refund: u128,
+}
+/// Enumerates the supported EVM commands for relaying transactions
+#[cfg(feature = "evm")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EvmCommand {
+    TornadoRelayTx(TornadoRelayTransaction),
+    AnchorRelayTx(AnchorRelayTransaction),
+    /// Queries the cached leaves of a contract, for clients that already hold a
+    /// connection to the relayer, without a separate HTTP request.
+    GetLeaves {
+        /// one of the supported chains of this relayer, by name or chain id
+        #[serde(deserialize_with = "chain_name_or_id")]
+        chain: String,
+        /// The contract to query the leaves of
+        contract: Address,
+        /// The index of the first leaf to return, all the leaves by default
+        #[serde(default)]
+        start: u32,
+    },
+}
+/// Contains the data for tornado relay transactions
+#[cfg(feature = "evm")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TornadoRelayTransaction {
+    /// one of the supported chains of this relayer, by name or chain id
+    #[serde(deserialize_with = "chain_name_or_id")]
+    pub chain: String,
+    /// The target contract.
+    pub contract: Address,
+    /// Proof bytes
+    #[serde(with = "hex_bytes")]
+    pub proof: Bytes,
+    /// Args...
+    #[serde(with = "hex_array")]
+    pub root: H256,
+    #[serde(with = "hex_array")]
+    pub nullifier_hash: H256,
+    pub recipient: Address, // H160 ([u8; 20])
+    pub relayer: Address,   // H160 (should be this realyer account)
+    pub fee: U256,
+    pub refund: U256,
+    /// The relayer identity to use for relaying this transaction.
+    ///
+    /// if not set, the default account of the chain is used.
+    #[serde(default)]
+    pub identity: Option<String>,
+}
+/// Contains transaction data that is relayed to Anchors
+#[cfg(feature = "evm")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorRelayTransaction {
+    /// one of the supported chains of this relayer, by name or chain id
+    #[serde(deserialize_with = "chain_name_or_id")]
+    pub chain: String,
+    /// The target contract.
+    pub contract: Address,
+    /// Proof bytes
+    #[serde(with = "hex_bytes")]
+    pub proof: Bytes,
+    /// Args...
+    #[serde(with = "hex_bytes")]
+    pub roots: Bytes,
+    #[serde(with = "hex_array")]
+    pub refresh_commitment: H256,
+    #[serde(with = "hex_array")]
+    pub nullifier_hash: H256,
+    #[serde(with = "hex_array")]
+    pub ext_data_hash: H256,
+    pub recipient: Address, // H160 ([u8; 20])
+    pub relayer: Address,   // H160 (should be this realyer account)
+    pub fee: U256,
+    pub refund: U256,
+    /// The relayer identity to use for relaying this transaction.
+    ///
+    /// if not set, the default account of the chain is used.
+    #[serde(default)]
+    pub identity: Option<String>,
+}
+/// Enumerates the command responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandResponse {
+    Pong(PongResponse),
+    Network(NetworkStatus),
+    Withdraw(WithdrawStatus),
+    TxStatus(TxStatus),
+    /// Sent when the connection has `limit` relay jobs in flight already, the command
+    /// is not relayed.
+    TooManyJobs {
+        limit: usize,
+    },
+    /// Sent when the command would go over a daily quota of the API key of the
+    /// connection, the command is not handled.
+    QuotaExceeded(QuotaExceeded),
+    /// The answer to a [`Handshake`], the first message in the negotiated encoding.
+    Handshake(Handshake),
+    /// The answer to a `GetLeaves` command.
+    Leaves(LeavesResponse),
+    /// The updates of a `WatchCommitment` command.
+    Commitment(CommitmentStatus),
+    /// Sent when the command could not be deserialized.
+    Malformed(MalformedCommand),
+    /// Sent when a field of the command is invalid, before trying to relay it.
+    Invalid(ValidationError),
+    Error(String),
+    #[allow(unused)]
+    Unimplemented(String),
+}
+/// The daily quotas of an API key.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, Display,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Quota {
+    /// The number of the relay commands.
+    #[display(fmt = "relays")]
+    Relays,
+    /// The size of the served leaves, in bytes.
+    #[display(fmt = "leaves bytes")]
+    LeavesBytes,
+}
+
+/// Sent when a request would go over a daily quota of its API key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaExceeded {
+    /// The quota that would be exceeded.
+    pub quota: Quota,
+    /// The daily limit of the quota.
+    pub limit: u64,
+    /// How much of the quota is used today.
+    pub used: u64,
+    /// Unix timestamp (in seconds) of when the quota is reset.
+    pub resets_at: u64,
+}
+
+/// A field of a relay command that did not pass the validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationError {
+    /// The name of the field, as sent by the client.
+    pub field: String,
+    /// Why the field is invalid.
+    pub reason: String,
+}
+
+/// Why a command could not be deserialized, so the dApp developers do not have to guess
+/// it from the deserializer error alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MalformedCommand {
+    /// The path of the offending field in the command (like `evm.anchorRelayTx.fee`),
+    /// `.` for the command itself.
+    pub field: String,
+    /// What the field should be, when the deserializer says it.
+    pub expected: Option<String>,
+    /// The error of the deserializer.
+    pub reason: String,
+    /// The commands supported by this relayer, by their path (like `evm.anchorRelayTx`).
+    pub supported_commands: Vec<String>,
+}
+/// The response to a `Ping`, so clients could check they are talking to a healthy and
+/// compatible relayer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PongResponse {
+    /// The version of the relayer.
+    pub version: String,
+    /// The git commit the relayer got built from.
+    pub commit: String,
+    /// The enabled EVM chains, with their sync status.
+    pub chains: Vec<ChainSyncStatus>,
+    /// The names of the enabled Substrate nodes.
+    pub substrate_nodes: Vec<String>,
+}
+/// The cached leaves of a contract, starting from the requested index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesResponse {
+    /// The index of the first leaf.
+    pub start: u32,
+    /// The leaves, sorted by their index.
+    pub leaves: Vec<H256>,
+    /// The last block the leaves got queried at.
+    pub last_queried_block: U64,
+}
+/// Enumerates the status of a watched commitment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitmentStatus {
+    /// The commitment is not cached yet, the connection gets notified once it is.
+    Watching { commitment: H256 },
+    /// The commitment got cached, at that leaf index.
+    Inserted {
+        commitment: H256,
+        #[serde(rename = "leafIndex")]
+        leaf_index: u32,
+    },
+    /// The commitment did not get cached in time, it is not watched anymore.
+    Expired { commitment: H256 },
+    /// The connection watches `limit` commitments already, the commitment is not watched.
+    TooManyWatches { commitment: H256, limit: usize },
+}
+/// The sync status of an EVM chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainSyncStatus {
+    /// The name of the chain.
+    pub name: String,
+    /// The chain id of the chain.
+    pub chain_id: u64,
+    /// Whether the events watchers of the chain caught up with the chain head.
+    pub synced: bool,
+    /// The contracts whose events watcher keeps failing, see the `circuit-breaker` of the events watchers.
+    pub degraded: Vec<Address>,
+    /// The contracts whose events watcher stopped, after a permanent error (a decoding
+    /// failure or a misconfiguration), until the relayer is restarted.
+    pub stopped: Vec<Address>,
+}
+/// Enumerates the network status response of the relayer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NetworkStatus {
+    Connecting,
+    Connected,
+    Failed { reason: String },
+    Disconnected,
+    UnsupportedContract,
+    UnsupportedChain,
+    Misconfigured,
+    InvalidRelayerAddress,
+    UnsupportedIdentity,
+    Paused,
+    Standby,
+}
+/// The `code` of the [`WithdrawStatus::Unprofitable`] withdraws, the errored ones are `4`.
+pub const UNPROFITABLE_CODE: i32 = 5;
+/// Enumerates the withdraw status response of the relayer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WithdrawStatus {
+    Sent,
+    Submitted {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+    Finalized {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+    Valid,
+    InvalidMerkleRoots,
+    /// The proof got rejected by the local verifier, the transaction would revert.
+    InvalidProof,
+    DroppedFromMemPool,
+    /// The nullifier of the withdraw is already spent, the transaction would revert.
+    NullifierAlreadySpent,
+    /// A withdraw spending the same nullifier is already being relayed.
+    AlreadyQueued,
+    /// The transaction got dropped from the mempool, it is resubmitted with a fresh gas price.
+    Resubmitting {
+        attempt: u32,
+    },
+    /// The job got cancelled by its client, before its transaction got broadcast.
+    Cancelled,
+    /// The fee does not cover the gas cost of the withdraw.
+    Unprofitable {
+        /// Always [`UNPROFITABLE_CODE`], for the clients handling the errors by their code.
+        code: i32,
+        fee: U256,
+        #[serde(rename = "minimumFee")]
+        minimum_fee: U256,
+    },
+    Errored {
+        code: i32,
+        reason: String,
+    },
+}
+/// Enumerates the status of a relayed transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TxStatus {
+    /// The transaction did not get relayed by this relayer.
+    Unknown {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+    /// The transaction is still waiting in the mempool.
+    Pending {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+    /// The transaction got included in a block.
+    Mined {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+        #[serde(rename = "blockNumber")]
+        block_number: U64,
+        confirmations: u64,
+        /// Whether the transaction got executed successfully or reverted.
+        success: bool,
+    },
+    /// The transaction is neither mined nor in the mempool anymore.
+    Dropped {
+        #[serde(rename = "txHash")]
+        tx_hash: H256,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"txStatus": {"chain": "hermes", "txHash": "0x0000000000000000000000000000000000000000000000000000000000000001"}}"#,
+        )
+        .unwrap();
+        assert_eq!(round_trip(&cmd), cmd);
+        assert_eq!(round_trip(&Command::Ping()), Command::Ping());
+        // the chain could be sent as a chain id too.
+        let by_id: Command = serde_json::from_str(
+            r#"{"txStatus": {"chain": 5, "txHash": "0x0000000000000000000000000000000000000000000000000000000000000001"}}"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(by_id, Command::TxStatus { chain, .. } if chain == "5")
+        );
+        let cmd = Command::WatchCommitment {
+            chain: "hermes".into(),
+            contract: Address::random(),
+            commitment: H256::random(),
+        };
+        assert_eq!(round_trip(&cmd), cmd);
+        #[cfg(feature = "evm")]
+        {
+            let cmd = Command::Evm(EvmCommand::GetLeaves {
+                chain: "hermes".into(),
+                contract: Address::random(),
+                start: 3,
+            });
+            assert_eq!(round_trip(&cmd), cmd);
+        }
+    }
+
+    #[cfg(feature = "substrate")]
+    #[test]
+    fn mixer_commands_accept_a_chain_id() {
+        let json = |chain: &str| {
+            format!(
+                r#"{{"substrate": {{"mixerRelayTx": {{"chain": {}, "id": 5, "proof": "0x0102", "root": "0x{}", "nullifierHash": "0x{}", "recipient": "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY", "relayer": "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY", "fee": 0, "refund": 0}}}}}}"#,
+                chain,
+                "07".repeat(32),
+                "09".repeat(32),
+            )
+        };
+        for chain in &["1080", "\"1080\""] {
+            let cmd: Command = serde_json::from_str(&json(chain)).unwrap();
+            match &cmd {
+                Command::Substrate(SubstrateCommand::MixerRelayTx(tx)) => {
+                    assert_eq!(tx.chain, "1080")
+                }
+                _ => panic!("expected a mixer command"),
+            }
+            assert_eq!(round_trip(&cmd), cmd);
+        }
+        let by_name: Command = serde_json::from_str(&json("\"webb\"")).unwrap();
+        assert_eq!(round_trip(&by_name), by_name);
+    }
+
+    #[cfg(any(feature = "evm", feature = "substrate"))]
+    #[test]
+    fn proofs_could_be_hex_strings_or_arrays() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Proof {
+            #[serde(with = "hex_bytes")]
+            proof: Vec<u8>,
+            #[serde(with = "hex_array")]
+            root: [u8; 32],
+            #[serde(with = "hex_array")]
+            nullifier_hash: H256,
+        }
+        let hex = format!(
+            r#"{{"proof": "0x0102", "root": "0x{}", "nullifier_hash": "0x{}"}}"#,
+            "07".repeat(32),
+            "09".repeat(32),
+        );
+        let proof: Proof = serde_json::from_str(&hex).unwrap();
+        assert_eq!(proof.proof, vec![1, 2]);
+        assert_eq!(proof.root, [7u8; 32]);
+        assert_eq!(proof.nullifier_hash, H256::repeat_byte(9));
+        let arrays = format!(
+            r#"{{"proof": [1, 2], "root": {:?}, "nullifier_hash": {:?}}}"#,
+            [7u8; 32], [9u8; 32],
+        );
+        assert_eq!(serde_json::from_str::<Proof>(&arrays).unwrap(), proof);
+        assert_eq!(round_trip(&proof), proof);
+        // the binary encodings carry the raw bytes.
+        let bytes = rmp_serde::to_vec_named(&proof).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Proof>(&bytes).unwrap(), proof);
+        // the hex strings need their prefix, and the roots their length.
+        let unprefixed = hex.replacen("0x0102", "0102", 1);
+        assert!(serde_json::from_str::<Proof>(&unprefixed).is_err());
+        let short = hex.replacen(&"07".repeat(32), "07", 1);
+        assert!(serde_json::from_str::<Proof>(&short).is_err());
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = vec![
+            CommandResponse::Network(NetworkStatus::Failed {
+                reason: "timeout".into(),
+            }),
+            CommandResponse::Withdraw(WithdrawStatus::Unprofitable {
+                code: UNPROFITABLE_CODE,
+                fee: U256::from(1),
+                minimum_fee: U256::from(2),
+            }),
+            CommandResponse::Commitment(CommitmentStatus::Inserted {
+                commitment: H256::random(),
+                leaf_index: 42,
+            }),
+            CommandResponse::TxStatus(TxStatus::Mined {
+                tx_hash: H256::random(),
+                block_number: U64::from(7),
+                confirmations: 2,
+                success: true,
+            }),
+            CommandResponse::Pong(PongResponse {
+                version: "0.1.0".into(),
+                commit: "abcdef".into(),
+                chains: vec![ChainSyncStatus {
+                    name: "hermes".into(),
+                    chain_id: 5001,
+                    synced: true,
+                    degraded: vec![Address::random()],
+                    stopped: vec![],
+                }],
+                substrate_nodes: vec!["localnode".into()],
+            }),
+        ];
+        for response in responses {
+            assert_eq!(round_trip(&response), response);
+        }
+        let json = serde_json::to_value(CommandResponse::Withdraw(
+            WithdrawStatus::Submitted {
+                tx_hash: H256::zero(),
+            },
+        ))
+        .unwrap();
+        assert!(json["withdraw"]["submitted"]["txHash"].is_string());
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use ethereum_types::U256;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::providers::Middleware;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::signers::Signer;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::{
+    self, sp_core::crypto::AccountId32, sp_core::Pair,
+    sp_runtime::traits::Header,
+};
+#[cfg(feature = "substrate")]
+use webb::substrate::{dkg_runtime, protocol_substrate_runtime};
+
+use crate::config::HeartbeatConfig;
+#[cfg(feature = "evm")]
+use crate::config::{CommonContractConfig, Contract, EvmChainConfig};
+#[cfg(feature = "substrate")]
+use crate::config::{Pallet, SubstrateConfig, SubstrateRuntime};
+use crate::context::RelayerContext;
+#[cfg(feature = "substrate")]
+use crate::store::pallet_tree_ids;
+use crate::store::HistoryStore;
+
+/// Returns the contracts of that chain with an enabled events watcher.
+#[cfg(feature = "evm")]
+fn watched_contracts(
+    chain: &EvmChainConfig,
+) -> impl Iterator<Item = &CommonContractConfig> {
+    chain.contracts.iter().filter_map(|c| match c {
+        Contract::Tornado(c) if c.events_watcher.enabled => Some(&c.common),
+        Contract::Anchor(c) if c.events_watcher.enabled => Some(&c.common),
+        Contract::SignatureBridge(c) if c.events_watcher.enabled => {
+            Some(&c.common)
+        }
+        _ => None,
+    })
+}
+
+/// Returns the pallets of that node with an enabled events watcher, along with the
+/// tree ids their watchers key their history with.
+#[cfg(feature = "substrate")]
+fn watched_pallets(
+    node: &SubstrateConfig,
+) -> impl Iterator<Item = (&'static str, u32)> + '_ {
+    node.pallets
+        .iter()
+        .filter_map(move |p| match (&node.runtime, p) {
+            // the proposal handler watcher relays the proposals to the EVM chains.
+            #[cfg(feature = "evm")]
+            (SubstrateRuntime::Dkg, Pallet::DKGProposalHandler(c))
+                if c.events_watcher.enabled =>
+            {
+                Some((
+                    "DKG proposal handler",
+                    pallet_tree_ids::DKG_PROPOSAL_HANDLER,
+                ))
+            }
+            (SubstrateRuntime::WebbProtocol, Pallet::Bridge(c))
+                if c.events_watcher.enabled =>
+            {
+                Some(("bridge", pallet_tree_ids::BRIDGE))
+            }
+            _ => None,
+        })
+}
+
+/// Checks that an events watcher synced up to `max_lag` blocks behind the chain head.
+///
+/// Returns the problem, if it lags too much.
+fn check_lag(
+    watcher: &str,
+    chain_head: u64,
+    synced: u64,
+    max_lag: u64,
+) -> Option<String> {
+    let lag = chain_head.saturating_sub(synced);
+    if lag > max_lag {
+        Some(format!(
+            "{} is {} blocks behind (max {})",
+            watcher, lag, max_lag
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks the health of the relayer, on every enabled EVM chain and Substrate node.
+///
+/// Returns the found problems, the relayer is healthy if there is none.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - The store where the events watchers save their progress
+/// * `config` - The configuration of the heartbeat
+pub async fn check_health<S: HistoryStore>(
+    ctx: &RelayerContext,
+    store: &S,
+    config: &HeartbeatConfig,
+) -> anyhow::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    #[cfg(feature = "evm")]
+    problems.extend(check_evm_chains(ctx, store, config).await?);
+    #[cfg(feature = "substrate")]
+    problems.extend(check_substrate_nodes(ctx, store, config).await?);
+    Ok(problems)
+}
+
+/// Checks the wallets and the events watchers of every enabled EVM chain.
+#[cfg(feature = "evm")]
+async fn check_evm_chains<S: HistoryStore>(
+    ctx: &RelayerContext,
+    store: &S,
+    config: &HeartbeatConfig,
+) -> anyhow::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let chains = ctx.config.evm.iter().filter(|(_, c)| c.enabled);
+    for (chain_name, chain) in chains {
+        let provider = ctx.evm_provider(chain_name).await?;
+        let wallet = ctx.evm_wallet(chain_name).await?;
+        let balance = provider.get_balance(wallet.address(), None).await?;
+        if balance <= config.min_balance {
+            problems.push(format!(
+                "wallet {:?} on {} is not funded (balance {})",
+                wallet.address(),
+                chain_name,
+                balance
+            ));
+        }
+        let chain_head = provider.get_block_number().await?.as_u64();
+        let chain_id = U256::from(chain.chain_id);
+        for contract in watched_contracts(chain) {
+            let synced = store.get_last_block_number(
+                (chain_id, contract.address),
+                contract.deployed_at.into(),
+            )?;
+            let watcher = format!("{:?} on {}", contract.address, chain_name);
+            problems.extend(check_lag(
+                &watcher,
+                chain_head,
+                synced.as_u64(),
+                config.max_lag,
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// Checks the accounts and the pallet watchers of every enabled Substrate node.
+///
+/// The watchers follow the finalized blocks, so their lag is counted from the
+/// finalized head of the node.
+#[cfg(feature = "substrate")]
+async fn check_substrate_nodes<S: HistoryStore>(
+    ctx: &RelayerContext,
+    store: &S,
+    config: &HeartbeatConfig,
+) -> anyhow::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let nodes = ctx.config.substrate.iter().filter(|(_, c)| c.enabled);
+    for (node_name, node) in nodes {
+        let client = ctx
+            .substrate_provider::<subxt::DefaultConfig>(node_name)
+            .await?;
+        let account =
+            AccountId32::from(ctx.substrate_wallet(node_name).await?.public());
+        let balance = match node.runtime {
+            SubstrateRuntime::Dkg => {
+                let api = client
+                    .clone()
+                    .to_runtime_api::<dkg_runtime::api::RuntimeApi<
+                        subxt::DefaultConfig,
+                        subxt::DefaultExtra<subxt::DefaultConfig>,
+                    >>();
+                api.storage()
+                    .system()
+                    .account(account.clone(), None)
+                    .await?
+                    .data
+                    .free
+            }
+            SubstrateRuntime::WebbProtocol => {
+                let api = client.clone().to_runtime_api::<protocol_substrate_runtime::api::RuntimeApi<
+                    subxt::DefaultConfig,
+                    subxt::DefaultExtra<subxt::DefaultConfig>,
+                >>();
+                api.storage()
+                    .system()
+                    .account(account.clone(), None)
+                    .await?
+                    .data
+                    .free
+            }
+        };
+        if U256::from(balance) <= config.min_balance {
+            problems.push(format!(
+                "account {} on {} is not funded (balance {})",
+                account, node_name, balance
+            ));
+        }
+        let mut pallets = watched_pallets(node).peekable();
+        if pallets.peek().is_none() {
+            continue;
+        }
+        let rpc = client.rpc();
+        let finalized_head = rpc.finalized_head().await?;
+        let header =
+            rpc.header(Some(finalized_head)).await?.ok_or_else(|| {
+                anyhow::anyhow!("no finalized header on {}", node_name)
+            })?;
+        let chain_head = u64::from(*header.number());
+        let chain_id =
+            crate::utils::substrate_chain_id(&node.runtime, &client)?;
+        for (pallet, tree_id) in pallets {
+            let synced = store
+                .get_last_block_number((chain_id, tree_id), 1u64.into())?;
+            let watcher = format!("{} pallet on {}", pallet, node_name);
+            problems.extend(check_lag(
+                &watcher,
+                chain_head,
+                synced.as_u64(),
+                config.max_lag,
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// Pings the heartbeat URL, if the relayer is healthy.
+///
+/// Returns `true` if the heartbeat got sent.
+///
+/// # Arguments
+///
+/// * `http` - The client used for pinging the heartbeat URL
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - The store where the events watchers save their progress
+/// * `config` - The configuration of the heartbeat
+pub async fn beat<S: HistoryStore>(
+    http: &reqwest::Client,
+    ctx: &RelayerContext,
+    store: &S,
+    config: &HeartbeatConfig,
+) -> anyhow::Result<bool> {
+    let problems = check_health(ctx, store, config).await?;
+    if !problems.is_empty() {
+        for problem in &problems {
+            tracing::warn!("Skipping the heartbeat: {}", problem);
+        }
+        return Ok(false);
+    }
+    http.get(config.url.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lagging_watchers_are_reported() {
+        assert_eq!(check_lag("anchor", 1_000, 990, 50), None);
+        assert_eq!(check_lag("anchor", 1_000, 1_000, 0), None);
+        let problem = check_lag("anchor", 1_000, 900, 50).unwrap();
+        assert_eq!(problem, "anchor is 100 blocks behind (max 50)");
+        // the store could be ahead of the node we are connected to.
+        assert_eq!(check_lag("anchor", 900, 1_000, 50), None);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! # Relayer IPFS Module 🕸️
+//!
+//! A module for exporting the cached leaves to IPFS.
+//!
+//! ## Overview
+//!
+//! The exporter periodically takes a snapshot of the leaves of every watched contract,
+//! encodes it using the [`LeavesSnapshot`] format, and adds (and pins) it to the
+//! configured IPFS node. The CID of the latest snapshot is then saved in the store
+//! so it could be served by the API.
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Deserialize;
+use webb::evm::contract::protocol_solidity::FixedDepositAnchorContract;
+use webb::evm::contract::tornado::TornadoContract;
+use webb::evm::ethers::providers::Middleware;
+use webb::evm::ethers::types;
+
+use crate::config::{self, Contract};
+use crate::context::RelayerContext;
+use crate::snapshot::LeavesSnapshot;
+use crate::store::cache::RelayerStore;
+use crate::store::{
+    HistoryStore, LeafCacheStore, LeavesSnapshotInfo, LeavesSnapshotStore,
+};
+
+/// A minimal client for the IPFS HTTP API.
+#[derive(Debug, Clone)]
+pub struct IpfsClient {
+    endpoint: url::Url,
+    http: reqwest::Client,
+}
+
+impl IpfsClient {
+    /// Creates a new IpfsClient for the given IPFS HTTP API endpoint.
+    pub fn new(endpoint: url::Url) -> Self {
+        Self {
+            endpoint,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Adds and pins the given bytes to IPFS.
+    ///
+    /// Returns the CID of the added content.
+    pub async fn add(
+        &self,
+        name: String,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<String> {
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct AddResponse {
+            hash: String,
+        }
+        let url = self.endpoint.join("api/v0/add?pin=true")?;
+        let part = reqwest::multipart::Part::bytes(bytes).file_name(name);
+        let form = reqwest::multipart::Form::new().part("file", part);
+        let response = self
+            .http
+            .post(url)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AddResponse>()
+            .await?;
+        Ok(response.hash)
+    }
+}
+
+/// The LeavesSnapshotExporter periodically exports the leaves of every watched
+/// contract to IPFS.
+#[derive(Clone)]
+pub struct LeavesSnapshotExporter {
+    ctx: RelayerContext,
+    store: Arc<RelayerStore>,
+}
+
+impl LeavesSnapshotExporter {
+    /// Creates a new LeavesSnapshotExporter instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - RelayContext reference that holds the configuration
+    /// * `store` - [Sled](https://sled.rs)-based database store
+    pub fn new(ctx: RelayerContext, store: Arc<RelayerStore>) -> Self {
+        Self { ctx, store }
+    }
+
+    /// Starts the exporter service.
+    ///
+    /// Returns a future that never resolves unless the exporter is not configured.
+    #[tracing::instrument(skip_all)]
+    pub async fn run(self) -> anyhow::Result<()> {
+        let ipfs_config = self
+            .ctx
+            .config
+            .ipfs
+            .clone()
+            .context("IPFS is not configured")?;
+        let client = IpfsClient::new(ipfs_config.api_endpoint);
+        let interval = Duration::from_millis(ipfs_config.export_interval);
+        loop {
+            for (chain_name, chain_config) in &self.ctx.config.evm {
+                if !chain_config.enabled {
+                    continue;
+                }
+                for contract in &chain_config.contracts {
+                    let (common, use_tornado) = match contract {
+                        Contract::Tornado(c) if c.events_watcher.enabled => {
+                            (&c.common, true)
+                        }
+                        Contract::Anchor(c) if c.events_watcher.enabled => {
+                            (&c.common, false)
+                        }
+                        _ => continue,
+                    };
+                    let result = self
+                        .export(&client, chain_name, common, use_tornado)
+                        .await;
+                    if let Err(e) = result {
+                        tracing::error!(
+                            "Failed to export leaves snapshot of {} on {}: {}",
+                            common.address,
+                            chain_name,
+                            e
+                        );
+                    }
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Exports a snapshot of the leaves of a single contract, skipping it
+    /// if there are no new leaves since the last export.
+    async fn export(
+        &self,
+        client: &IpfsClient,
+        chain_name: &str,
+        common: &config::CommonContractConfig,
+        use_tornado: bool,
+    ) -> anyhow::Result<()> {
+        let provider = Arc::new(self.ctx.evm_provider(chain_name).await?);
+        let chain_id = provider.get_chainid().await?;
+        let key = (chain_id, common.address);
+        // a snapshot of a partial cache would be taken as the full set.
+        if let Some(synced_from) = self.store.get_synced_from(key)? {
+            tracing::debug!(
+                "Not exporting the leaves of {}, they are only synced from #{}",
+                common.address,
+                synced_from
+            );
+            return Ok(());
+        }
+        let leaves = self.store.get_leaves(key)?;
+        let leaf_count = leaves.len() as u32;
+        let latest = self.store.get_latest_leaves_snapshot(key)?;
+        if matches!(latest, Some(ref info) if info.leaf_count == leaf_count) {
+            tracing::trace!(
+                "No new leaves for {} since the last snapshot",
+                common.address
+            );
+            return Ok(());
+        }
+        let block_number = self
+            .store
+            .get_last_block_number(key, common.deployed_at.into())?;
+        let root = if use_tornado {
+            TornadoContract::new(common.address, provider)
+                .get_last_root()
+                .block(block_number)
+                .call()
+                .await?
+        } else {
+            FixedDepositAnchorContract::new(common.address, provider)
+                .get_last_root()
+                .block(block_number)
+                .call()
+                .await?
+        };
+        let snapshot = LeavesSnapshot {
+            chain_id: chain_id.as_u64(),
+            contract: common.address,
+            block_number: block_number.as_u64(),
+            root: types::H256::from(root),
+            leaves,
+        };
+        let name = format!(
+            "leaves-{}-{:?}-{}.bin",
+            snapshot.chain_id, snapshot.contract, snapshot.block_number
+        );
+        let cid = client.add(name, snapshot.to_bytes()).await?;
+        let exported_at =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        tracing::event!(
+            target: crate::probe::TARGET,
+            tracing::Level::DEBUG,
+            kind = %crate::probe::Kind::LeavesSnapshot,
+            chain_id = %chain_id,
+            contract = %common.address,
+            block_number = %block_number,
+            leaf_count = %leaf_count,
+            cid = %cid,
+        );
+        self.store.set_latest_leaves_snapshot(
+            key,
+            LeavesSnapshotInfo {
+                cid,
+                block_number,
+                leaf_count,
+                root: snapshot.root,
+                exported_at,
+            },
+        )?;
+        Ok(())
+    }
+}
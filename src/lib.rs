@@ -0,0 +1,38 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+#![deny(unsafe_code)]
+#![warn(missing_docs)]
+// the helpers shared by both stacks are left unused by a build with only one of them.
+#![cfg_attr(
+    not(all(feature = "evm", feature = "substrate")),
+    allow(dead_code, unused_imports)
+)]
+
+//! # Webb Relayer Types 🕸️
+//!
+//! The commands and the responses of the relayer, as they are sent over the wire, for
+//! the clients (and the tests) that want to build and parse exactly what the relayer
+//! speaks, by depending on this crate:
+//!
+//! ```toml
+//! webb-relayer = { git = "https://github.com/webb-tools/relayer", default-features = false, features = ["evm"] }
+//! ```
+//!
+//! The relayer itself is the binary of this crate, the library only holds the types, and
+//! the same `evm` and `substrate` features select the commands of each stack.
+
+/// The commands and the responses of the relayer, as they are sent over the wire.
+#[path = "handler/types.rs"]
+pub mod types;
@@ -14,6 +14,11 @@
 //
 #![deny(unsafe_code)]
 #![warn(missing_docs)]
+// the helpers shared by both stacks are left unused by a build with only one of them.
+#![cfg_attr(
+    not(all(feature = "evm", feature = "substrate")),
+    allow(dead_code, unused_imports, unused_mut, unused_variables)
+)]
 
 //! # Webb Relayer Crate 🕸️
 //!
@@ -80,11 +85,15 @@
 //! # Features
 //!
 //! There are several feature flags that control how much is available as part of the crate, both
-//! `evm-runtime`, `substrate-runtime` are enabled by default.
+//! `evm`, `substrate` are enabled by default.
 //!
-//! * `evm-runtime`: Enables the EVM runtime. By default, this is enabled.
-//! * `substrate-runtime`: Enables the substrate runtime. By default, this is enabled.
+//! * `evm`: Enables the EVM chains, their watchers and relays. By default, this is enabled.
+//! * `substrate`: Enables the Substrate nodes, their watchers and relays, and the DKG. By default, this is enabled.
+//! * `evm-runtime`, `substrate-runtime`: The former names of `evm` and `substrate`.
 //! * `integration-tests`: Enables integration tests. By default, this is disabled.
+//! * `integration`: Enables the end-to-end scenarios against dockerized chains, run with
+//!   `cargo test --features integration`. By default, this is disabled.
+//! * `arbitrary`: Implements the proptest `Arbitrary` for the commands and the responses. By default, this is disabled.
 
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -95,7 +104,7 @@ use futures::Future;
 use std::net::SocketAddr;
 use structopt::StructOpt;
 use tokio::signal::unix;
-use warp::Filter;
+use warp::{Filter, Reply};
 use warp_real_ip::real_ip;
 
 use crate::context::RelayerContext;
@@ -103,22 +112,57 @@ use crate::context::RelayerContext;
 mod config;
 /// A module for managing the context of the relayer.
 mod context;
+/// A module for running the relayer against local development nodes.
+#[cfg(feature = "evm")]
+mod dev;
+/// A module for diagnosing the connectivity to the configured endpoints.
+mod diagnostics;
 /// A module that listens for events on a given chain.
 mod events_watcher;
+/// A module for electing the leader among the instances of a deployment.
+#[cfg(feature = "ha")]
+mod ha;
 /// A module containing a collection of executable routines.
 mod handler;
+/// A module for reporting heartbeats to an external monitor.
+mod heartbeat;
+/// A module for exporting the cached leaves to IPFS.
+#[cfg(feature = "evm")]
+mod ipfs;
+/// A module for the labelled metrics of the relayer, in the Prometheus format.
+mod metrics;
+/// A module for the OpenAPI document of the HTTP API.
+mod openapi;
+/// A module for checking the configured chains at startup.
+mod preflight;
 /// A module used for debugging relayer lifecycle, sync state, or other relayer state.
 mod probe;
+/// A module for building and decoding the proposals.
+mod proposals;
+/// A module for enforcing the daily quotas of the API keys.
+mod quota;
+/// A module for rate limiting the requests sent to the RPC endpoints.
+#[cfg(feature = "evm")]
+mod rate_limit;
 /// A module for starting long-running tasks for event watching.
 mod service;
+/// A module for encoding the cached leaves into compact binary snapshots.
+mod snapshot;
 /// A module for managing the storage of the relayer.
 mod store;
+/// A module for running the relayer end-to-end against dockerized chains.
+#[cfg(all(test, feature = "integration"))]
+mod testing;
 /// A module for managing the transaction queue for the relayer.
+#[cfg(feature = "evm")]
 mod tx_queue;
 /// Transaction relaying handlers
 mod tx_relay;
 /// A module for common functionality.
 mod utils;
+/// A module for checking the cached leaves against the on-chain roots.
+#[cfg(feature = "evm")]
+mod verify;
 /// Package identifier, where the default configuration & database are defined.
 /// If the user does not start the relayer with the `--config-dir`
 /// it will default to read from the default location depending on the OS.
@@ -128,6 +172,14 @@ const PACKAGE_ID: [&str; 3] = ["tools", "webb", "webb-relayer"];
 /// Start the relayer from a config file:
 ///
 /// $ webb-relayer -vvv -c <CONFIG_FILE_PATH>
+///
+/// Check the cached leaves against the on-chain roots:
+///
+/// $ webb-relayer -c <CONFIG_FILE_PATH> verify-roots
+///
+/// Start the relayer against local development nodes, funding its accounts:
+///
+/// $ webb-relayer -vvv -c <CONFIG_FILE_PATH> --tmp --dev --dev-fund
 #[derive(StructOpt)]
 #[structopt(name = "Webb Relayer")]
 struct Opts {
@@ -146,6 +198,24 @@ struct Opts {
     /// and will be deleted when the process exits.
     #[structopt(long)]
     tmp: bool,
+    /// Run against local development nodes (Anvil, Hardhat or Ganache), the detected
+    /// ones get relaxed confirmations and gas settings.
+    #[structopt(long)]
+    dev: bool,
+    /// Fund the relayer's accounts on the local development nodes, in dev mode.
+    #[structopt(long, requires = "dev")]
+    dev_fund: bool,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+/// The commands of the relayer, if none is given the relayer is started.
+#[derive(StructOpt)]
+enum Command {
+    /// Recomputes the merkle roots from the cached leaves of every configured contract,
+    /// and compares them with the on-chain roots, then exits.
+    ///
+    /// Exits with an error if the cached leaves of any contract diverged.
+    VerifyRoots,
 }
 /// The main entry point for the relayer.
 ///
@@ -153,8 +223,7 @@ struct Opts {
 ///
 /// * `args` - The command line arguments.
 #[paw::main]
-#[tokio::main]
-async fn main(args: Opts) -> anyhow::Result<()> {
+fn main(args: Opts) -> anyhow::Result<()> {
     setup_logger(args.verbose)?;
     match dotenv::dotenv() {
         Ok(_) => {
@@ -167,24 +236,108 @@ async fn main(args: Opts) -> anyhow::Result<()> {
 
     // The configuration is validated and configured from the given directory
     let config = load_config(args.config_dir.clone())?;
-
+    // the runtime is built from the configuration, so it could be tuned for the deployment.
+    let runtime = build_runtime(&config.runtime)?;
+    runtime.block_on(run(args, config))
+}
+/// Runs the relayer (or the given command) until it gets a shutdown signal.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments.
+/// * `config` - The loaded configuration.
+async fn run(
+    args: Opts,
+    config: config::WebbRelayerConfig,
+) -> anyhow::Result<()> {
     // The RelayerContext takes a configuration, and populates objects that are needed
     // throughout the lifetime of the relayer. Items such as wallets and providers, as well
     // as a convenient place to access the configuration.
-    let ctx = RelayerContext::new(config);
+    let mut ctx = RelayerContext::new(config);
 
     // persistent storage for the relayer
-    let store = create_store(&args).await?;
+    let store = create_store(&args, &ctx.config.store).await?;
+    // the instances of a deployment share their transaction queues.
+    #[cfg(feature = "ha")]
+    let store = match ctx.config.ha {
+        Some(ref ha) => {
+            store.with_shared_queues(store::shared::SharedQueues::new(ha)?)
+        }
+        None => store,
+    };
+    // the keys rotated by the admin are kept across restarts.
+    #[cfg(feature = "evm")]
+    ctx.restore_key_rotations(&store)?;
+    if let Some(Command::VerifyRoots) = args.cmd {
+        #[cfg(feature = "evm")]
+        return verify_all_roots(&ctx, &store).await;
+        #[cfg(not(feature = "evm"))]
+        anyhow::bail!("The roots are only verified on the EVM chains, which this relayer is built without");
+    }
+    #[cfg(feature = "evm")]
+    if args.dev {
+        ctx = dev::setup(ctx, args.dev_fund).await?;
+        ctx.restore_key_rotations(&store)?;
+    }
+    #[cfg(not(feature = "evm"))]
+    if args.dev {
+        tracing::warn!("The dev mode only sets up the EVM chains, which this relayer is built without");
+    }
+    // check every chain before serving any traffic, so misconfigurations are found now
+    // instead of at the first relay.
+    if !ctx.config.preflight.skip {
+        let failed = preflight::run(&ctx).await?;
+        if !failed.is_empty() {
+            let mut config = ctx.config.clone();
+            for chain_name in &failed.evm {
+                if let Some(chain) = config.evm.get_mut(chain_name) {
+                    chain.enabled = false;
+                }
+            }
+            #[cfg(feature = "substrate")]
+            for node_name in &failed.substrate {
+                if let Some(node) = config.substrate.get_mut(node_name) {
+                    node.enabled = false;
+                }
+            }
+            ctx = RelayerContext::new(config);
+            #[cfg(feature = "evm")]
+            ctx.restore_key_rotations(&store)?;
+        }
+    }
+    #[cfg(not(feature = "ha"))]
+    if ctx.config.ha.is_some() {
+        anyhow::bail!("The high-availability mode is configured, but the relayer is built without the `ha` feature");
+    }
+    // the registry used for routing commands to the signature bridges.
+    let bridge_registry =
+        events_watcher::BridgeRegistry::new(Arc::new(store.clone()))?;
+    // the bridges removed from the config are forgotten, with their commands.
+    let mut configured_bridges = Vec::new();
+    for chain in ctx.config.evm.values() {
+        let chain_id =
+            crate::proposals::evm_typed_chain_id(chain.chain_id.into())?;
+        for contract in &chain.contracts {
+            if let config::Contract::SignatureBridge(c) = contract {
+                configured_bridges
+                    .push(store::BridgeKey::new(c.common.address, chain_id));
+            }
+        }
+    }
+    for key in bridge_registry.prune(&configured_bridges)? {
+        tracing::warn!(%key, "The bridge is not configured anymore, forgot it");
+    }
 
     // the build_relayer command sets up routing (endpoint queries / requests mapped to handled code)
     // so clients can interact with the relayer
-    let (addr, server) = build_relayer(ctx.clone(), store.clone())?;
+    let (addr, server) =
+        build_relayer(ctx.clone(), store.clone(), bridge_registry.clone())?;
     tracing::info!("Starting the server on {}", addr);
     // start the server.
     let server_handle = tokio::spawn(server);
     // start all background services.
     // this does not block, will fire the services on background tasks.
-    service::ignite(&ctx, Arc::new(store)).await?;
+    service::ignite(&ctx, Arc::new(store), bridge_registry).await?;
     tracing::event!(
         target: crate::probe::TARGET,
         tracing::Level::DEBUG,
@@ -226,6 +379,68 @@ async fn main(args: Opts) -> anyhow::Result<()> {
     }
     Ok(())
 }
+/// Checks the cached leaves of every configured contract against the on-chain roots,
+/// and prints a report for each one of them.
+///
+/// Returns `Err(anyhow::Error)` if the cached leaves of any contract diverged.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
+async fn verify_all_roots(
+    ctx: &RelayerContext,
+    store: &store::cache::RelayerStore,
+) -> anyhow::Result<()> {
+    let mut diverged = 0;
+    for (chain_name, chain_config) in &ctx.config.evm {
+        if !chain_config.enabled {
+            continue;
+        }
+        for contract in &chain_config.contracts {
+            if !matches!(
+                contract,
+                config::Contract::Anchor(_) | config::Contract::Tornado(_)
+            ) {
+                continue;
+            }
+            let report =
+                verify::verify_roots(ctx, store, chain_name, contract).await?;
+            if report.status == verify::RootsStatus::Diverged {
+                diverged += 1;
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+    anyhow::ensure!(
+        diverged == 0,
+        "The cached leaves of {} contract(s) diverged",
+        diverged
+    );
+    Ok(())
+}
+/// Builds the multi-threaded tokio runtime of the relayer.
+///
+/// Returns `Ok(Runtime)` on success, or `Err(anyhow::Error)` on failure.
+///
+/// # Arguments
+///
+/// * `config` - The runtime configuration, the tokio defaults are used for the missing values.
+fn build_runtime(
+    config: &config::RuntimeConfig,
+) -> anyhow::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all().thread_name("webb-relayer-worker");
+    if let Some(worker_threads) = config.worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+    if let Some(max_blocking_threads) = config.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads);
+    }
+    tracing::debug!(?config, "Starting the runtime");
+    Ok(builder.build()?)
+}
 /// Sets up the logger for the relayer, based on the verbosity level passed in.
 ///
 /// Returns `Ok(())` on success, or `Err(anyhow::Error)` on failure.
@@ -252,18 +467,52 @@ fn setup_logger(verbosity: i32) -> anyhow::Result<()> {
 
     let env_filter = tracing_subscriber::EnvFilter::from_default_env()
         .add_directive(format!("webb_relayer={}", log_level).parse()?);
-    let logger = tracing_subscriber::fmt()
+    #[cfg(not(feature = "tokio-console"))]
+    {
+        let logger = tracing_subscriber::fmt()
+            .with_target(true)
+            .with_max_level(log_level)
+            .with_env_filter(env_filter);
+        // if we are not compiling for integration tests, we should use pretty logs
+        #[cfg(not(feature = "integration-tests"))]
+        let logger = logger.pretty();
+        // otherwise, we should use json, which is easy to parse.
+        #[cfg(feature = "integration-tests")]
+        let logger = logger.json().flatten_event(true).with_current_span(false);
+
+        logger.init();
+    }
+    #[cfg(feature = "tokio-console")]
+    setup_console_logger(env_filter)?;
+    Ok(())
+}
+/// Sets up the logger along with the tokio-console server, which gets the instrumentation
+/// of the runtime, left out of the logs.
+///
+/// # Arguments
+///
+/// * `env_filter` - The filter of the logs.
+#[cfg(feature = "tokio-console")]
+fn setup_console_logger(
+    env_filter: tracing_subscriber::EnvFilter,
+) -> anyhow::Result<()> {
+    use tracing_subscriber::filter::filter_fn;
+    use tracing_subscriber::prelude::*;
+    let env_filter = env_filter
+        .add_directive("tokio=trace".parse()?)
+        .add_directive("runtime=trace".parse()?);
+    let logs = tracing_subscriber::fmt::layer()
         .with_target(true)
-        .with_max_level(log_level)
-        .with_env_filter(env_filter);
-    // if we are not compiling for integration tests, we should use pretty logs
-    #[cfg(not(feature = "integration-tests"))]
-    let logger = logger.pretty();
-    // otherwise, we should use json, which is easy to parse.
-    #[cfg(feature = "integration-tests")]
-    let logger = logger.json().flatten_event(true).with_current_span(false);
-
-    logger.init();
+        .pretty()
+        .with_filter(filter_fn(|metadata| {
+            let target = metadata.target();
+            !target.starts_with("tokio") && !target.starts_with("runtime")
+        }));
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(console_subscriber::spawn())
+        .with(logs)
+        .init();
     Ok(())
 }
 /// Loads the configuration from the given directory.
@@ -313,31 +562,65 @@ where
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
 /// * `store` - [Sled](https://sled.rs)-based database store
+/// * `bridge_registry` - The registry used for routing commands to the signature bridges
 ///
 /// # Examples
 ///
 /// ```
 /// let ctx = RelayerContext::new(config);
-/// let store = create_store(&args).await?;
-/// let (addr, server) = build_relayer(ctx.clone(), store.clone())?;
+/// let store = create_store(&args, &config.store).await?;
+/// let (addr, server) = build_relayer(ctx.clone(), store.clone(), bridge_registry.clone())?;
 /// ```
 fn build_relayer(
     ctx: RelayerContext,
-    store: store::sled::SledStore,
+    store: store::cache::RelayerStore,
+    bridge_registry: events_watcher::BridgeRegistry<store::cache::RelayerStore>,
 ) -> anyhow::Result<(SocketAddr, impl Future<Output = ()> + 'static)> {
     let port = ctx.config.port;
     let ctx_arc = Arc::new(ctx.clone());
     let ctx_filter = warp::any().map(move || Arc::clone(&ctx_arc)).boxed();
 
-    // the websocket server for users to submit relay transaction requests
+    let store = Arc::new(store);
+    let store_filter = warp::any().map(move || Arc::clone(&store)).boxed();
+
+    // the websocket server for users to submit relay transaction requests, the clients
+    // with an unknown API key are refused before the upgrade.
     let ws_filter = warp::path("ws")
         .and(warp::ws())
         .and(ctx_filter.clone())
-        .map(|ws: warp::ws::Ws, ctx: Arc<RelayerContext>| {
-            ws.on_upgrade(|socket| async move {
-                let _ = handler::accept_connection(ctx.as_ref(), socket).await;
-            })
-        })
+        .and(store_filter.clone())
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(warp::query::<handler::WsQuery>())
+        .map(
+            |ws: warp::ws::Ws,
+             ctx: Arc<RelayerContext>,
+             store: Arc<store::cache::RelayerStore>,
+             remote: Option<SocketAddr>,
+             api_key: Option<String>,
+             query: handler::WsQuery| {
+                let client_ip = remote.map(|v| v.ip());
+                let api_key = api_key.or(query.api_key);
+                let api_key = match quota::authenticate(
+                    &ctx.config.api_keys,
+                    api_key.as_deref(),
+                ) {
+                    Ok(v) => v,
+                    Err(e) => return handler::quota_error_reply(e),
+                };
+                ws.on_upgrade(move |socket| async move {
+                    let _ = handler::accept_connection(
+                        ctx.as_ref(),
+                        store,
+                        client_ip,
+                        api_key,
+                        socket,
+                    )
+                    .await;
+                })
+                .into_response()
+            },
+        )
         .boxed();
 
     // get the ip of the caller.
@@ -358,31 +641,309 @@ fn build_relayer(
     // Define the handling of a request for this relayer's information (supported networks)
     let info_filter = warp::path("info")
         .and(warp::get())
-        .and(ctx_filter)
+        .and(ctx_filter.clone())
         .and_then(handler::handle_relayer_info)
         .boxed();
 
     // Define the handling of a request for the leaves of a merkle tree. This is used by clients as a way to query
     // for information needed to generate zero-knowledge proofs (it is faster than querying the chain history)
-    let store = Arc::new(store);
-    let store_filter = warp::any().map(move || Arc::clone(&store)).boxed();
     let leaves_cache_filter = warp::path("leaves")
-        .and(store_filter)
+        .and(store_filter.clone())
         .and(warp::path::param())
         .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::header::optional::<String>("accept"))
+        .and(warp::query::<handler::LeavesQuery>())
+        .and(ctx_filter.clone())
+        .and(warp::header::optional::<String>("x-api-key"))
         .and_then(handler::handle_leaves_cache)
         .boxed();
 
+    // Define the handling of a request for the latest IPFS snapshot of the leaves of a merkle tree.
+    // Clients could use the returned CID to bulk-download the leaves and verify them against the root.
+    let leaves_snapshot_filter = warp::path("leaves")
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path("ipfs"))
+        .and(warp::path::end())
+        .and_then(handler::handle_leaves_snapshot_cid)
+        .boxed();
+
+    // Define the handling of a request for the leaves of a merkle tree in a compact binary encoding,
+    // several times smaller and faster to parse than the JSON leaves.
+    let leaves_binary_snapshot_filter = warp::path("leaves")
+        .and(warp::get())
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(ctx_filter.clone())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and_then(handler::handle_leaves_snapshot)
+        .boxed();
+
+    // Define the handling of a request for the index of a leaf by its commitment, so wallets can
+    // locate their deposit without downloading all the leaves.
+    let leaf_index_filter = warp::path("leaves")
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path("index"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(handler::handle_leaf_index)
+        .boxed();
+
+    // Define the handling of a request for the withdraw that spent a nullifier hash, for the
+    // support requests about missing withdraws.
+    let withdrawal_filter = warp::path("withdrawals")
+        .and(warp::get())
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(handler::handle_withdrawal)
+        .boxed();
+
+    // Define the handling of a request for checking the cached leaves of a merkle tree against
+    // the on-chain roots, for detecting a corrupted cache.
+    #[cfg(feature = "evm")]
+    let verify_roots_filter = warp::path("verify")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_verify_roots)
+        .boxed();
+
+    // Define the handling of a request for the fees earned by the relayer, per token and chain.
+    let earnings_filter = warp::path("earnings")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_earnings)
+        .boxed();
+
+    // Define the handling of a request for the gas spent against the fees earned, in daily buckets
+    // per chain and contract, so operators can check that their fees cover their costs.
+    let accounting_filter = warp::path("accounting")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::query::<handler::AccountingQuery>())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_accounting)
+        .boxed();
+
+    // Define the handling of a request for the number of relays, in hourly or daily buckets per
+    // chain and contract, so dashboards do not have to scan the relayed transactions.
+    let relay_stats_filter = warp::path("stats")
+        .and(warp::path("relays"))
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::query::<handler::RelayStatsQuery>())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_relay_stats)
+        .boxed();
+
+    // Define the handling of a request for the status of a signed proposal, so operators can
+    // follow it from the signing backend to its execution on the Signature Bridge.
+    let proposal_status_filter = warp::path("proposals")
+        .and(warp::get())
+        .and(store_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(handler::handle_proposal_status)
+        .boxed();
+
+    // Define the handling of a request for the current withdraw fee of a contract, which may
+    // follow the gas price of the chain, for the clients with an API key.
+    #[cfg(feature = "evm")]
+    let fee_quote_filter = warp::path("fee")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::query::<handler::FeeQuoteQuery>())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and_then(handler::handle_fee_quote)
+        .boxed();
+
+    // Define the handling of a request for the registered signature bridges, so operators can see
+    // whether the cross-chain command routing is alive.
+    let bridges_filter = warp::path("bridges")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(warp::any().map(move || bridge_registry.clone()))
+        .and(ctx_filter.clone())
+        .and_then(handler::handle_bridges_info)
+        .boxed();
+
+    // Define the handling of a request for the uptime of the relayer, and how long it kept
+    // up with every chain.
+    let uptime_filter = warp::path("uptime")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and_then(handler::handle_uptime)
+        .boxed();
+
+    // Define the handling of a request for the usage of the API key of the client, along with
+    // its daily quotas.
+    let api_usage_filter = warp::path("usage")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and_then(handler::handle_api_usage)
+        .boxed();
+
+    // Define the handling of a request for the OpenAPI document of the HTTP API, and for the
+    // Swagger UI page that browses it.
+    let openapi_filter = warp::path("openapi.json")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and_then(openapi::handle_openapi_spec)
+        .boxed();
+    let swagger_ui_filter = warp::path("docs")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and_then(openapi::handle_swagger_ui)
+        .boxed();
+
     // Code that will map the request handlers above to a defined http endpoint.
-    let routes = ip_filter.or(info_filter).or(leaves_cache_filter).boxed(); // will add more routes here.
+    let routes = ip_filter
+        .or(info_filter)
+        .or(leaves_cache_filter)
+        .or(leaves_snapshot_filter)
+        .or(leaves_binary_snapshot_filter)
+        .or(leaf_index_filter)
+        .or(withdrawal_filter)
+        .or(earnings_filter)
+        .or(accounting_filter)
+        .or(relay_stats_filter)
+        .or(proposal_status_filter)
+        .or(bridges_filter)
+        .or(uptime_filter)
+        .or(api_usage_filter)
+        .or(openapi_filter)
+        .or(swagger_ui_filter)
+        .boxed(); // will add more routes here.
+
+    // the routes that query the EVM chains.
+    #[cfg(feature = "evm")]
+    let routes = routes.or(verify_roots_filter).or(fee_quote_filter).boxed();
+    // the responses could be large (the leaves, the relayer info), so they are compressed
+    // for the clients that accept it.
+    let routes = with_compression(routes)
+        .with(warp::reply::with::header("vary", "accept-encoding"))
+        .boxed();
     let http_filter =
         warp::path("api").and(warp::path("v1")).and(routes).boxed();
 
+    // Admin endpoints, these are guarded by the admin token from the config.
+    // Pausing a chain acts as a circuit breaker during chain incidents, it stops accepting
+    // relay commands and broadcasting queued transactions for that chain.
+    let admin_chains = warp::path("admin").and(warp::path("chains"));
+    let admin_pause_filter = admin_chains
+        .and(warp::post())
+        .and(warp::path::param())
+        .and(warp::path("pause"))
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_admin_pause_chain)
+        .boxed();
+    let admin_resume_filter = admin_chains
+        .and(warp::post())
+        .and(warp::path::param())
+        .and(warp::path("resume"))
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_admin_resume_chain)
+        .boxed();
+    let admin_diagnostics_filter = warp::path("admin")
+        .and(warp::path("diagnostics"))
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_admin_diagnostics)
+        .boxed();
+    let admin_audit_filter = warp::path("admin")
+        .and(warp::path("audit"))
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::query::<handler::AuditLogQuery>())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and_then(handler::handle_admin_audit_log)
+        .boxed();
+    // Rotates the signing key of a chain, once its transactions in flight are done.
+    #[cfg(feature = "evm")]
+    let admin_rotate_key_filter = warp::path("admin")
+        .and(warp::path("keys"))
+        .and(warp::path("rotate"))
+        .and(warp::post())
+        .and(warp::path::end())
+        .and(ctx_filter.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional("authorization"))
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .and_then(handler::handle_admin_rotate_key)
+        .boxed();
+    let admin_filter = admin_pause_filter
+        .or(admin_resume_filter)
+        .or(admin_audit_filter)
+        .or(admin_diagnostics_filter)
+        .boxed();
+    #[cfg(feature = "evm")]
+    let admin_filter = admin_filter.or(admin_rotate_key_filter).boxed();
+
+    // the metrics of the relayer, in the Prometheus text format.
+    let metrics_filter = warp::path("metrics")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and_then(handler::handle_metrics)
+        .boxed();
+
     let cors = warp::cors().allow_any_origin();
     let service = http_filter
-        .or(ws_filter)
+        .or(admin_filter)
+        .or(metrics_filter)
+        .or(ws_filter);
+    let service = with_request_id(service)
         .with(cors)
-        .with(warp::trace::request());
+        .with(warp::trace(request_span));
     let mut shutdown_signal = ctx.shutdown_signal();
     let shutdown_signal = async move {
         shutdown_signal.recv().await;
@@ -391,26 +952,212 @@ fn build_relayer(
         .try_bind_with_graceful_shutdown(([0, 0, 0, 0], port), shutdown_signal)
         .map_err(Into::into)
 }
+/// The span of an HTTP request, its `request_id` gets recorded by [`with_request_id`].
+fn request_span(info: warp::trace::Info) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        method = %info.method(),
+        path = %info.path(),
+        request_id = tracing::field::Empty,
+    )
+}
+
+/// Tags every request to the `routes` with a new request id, it is recorded in the span of
+/// the request and echoed in the `x-request-id` header of the response.
+fn with_request_id<F, R>(
+    routes: F,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    R: warp::Reply + 'static,
+{
+    warp::any()
+        .map(|| {
+            let request_id = handler::new_request_id();
+            tracing::Span::current().record("request_id", &request_id.as_str());
+            request_id
+        })
+        .and(routes)
+        .map(|request_id: String, reply: R| {
+            warp::reply::with_header(reply, "x-request-id", request_id)
+        })
+}
+
+/// The content encodings the responses could be compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentEncoding {
+    Brotli,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// Negotiates the encoding with the `Accept-Encoding` header of a request, brotli is
+    /// preferred, `None` leaves the response uncompressed.
+    fn negotiate(accept_encoding: Option<&str>) -> Option<Self> {
+        let accepted = accept_encoding?;
+        if handler::accepts_encoding(accepted, "br") {
+            Some(Self::Brotli)
+        } else if handler::accepts_encoding(accepted, "gzip") {
+            Some(Self::Gzip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compresses the responses of the `routes` with brotli or gzip, negotiated with the
+/// `Accept-Encoding` header of the request, brotli is preferred.
+///
+/// The encoding is negotiated once, before the `routes`, so they only run once whatever
+/// the encoding, and their rejections (like a not found) are the ones sent back. Only the
+/// textual bodies are compressed, see [`compressible`].
+fn with_compression<F, R>(
+    routes: F,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    R: warp::Reply + 'static,
+{
+    warp::header::optional::<String>("accept-encoding")
+        .map(|accepted: Option<String>| {
+            ContentEncoding::negotiate(accepted.as_deref())
+        })
+        .and(routes)
+        .map(|encoding: Option<ContentEncoding>, reply: R| {
+            use warp::http::header::{HeaderValue, VARY};
+            use warp::http::StatusCode;
+
+            let mut response = reply.into_response();
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("accept-encoding"));
+            match encoding {
+                Some(encoding) if compressible(&response) => {
+                    compress(encoding, response)
+                }
+                // validates a compressed response, with its weak ETag.
+                Some(_) if response.status() == StatusCode::NOT_MODIFIED => {
+                    weaken_etag(response)
+                }
+                _ => response,
+            }
+        })
+}
+
+/// Returns true if the body of the response is worth compressing.
+///
+/// The responses without a body are left as they are, and so are the binary ones, like
+/// the leaves snapshots, which are compact already.
+fn compressible(response: &warp::reply::Response) -> bool {
+    use warp::http::header::CONTENT_TYPE;
+    use warp::http::StatusCode;
+    use warp::hyper::body::HttpBody;
+
+    let status = response.status();
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || response.body().size_hint().exact() == Some(0)
+    {
+        return false;
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    content_type.starts_with("text/")
+        || content_type.contains("json")
+        || content_type.contains("xml")
+        || content_type.contains("javascript")
+}
+
+/// Makes the ETag of the response weak, the compressed body is not byte for byte the
+/// one the (strong) ETag of the handler stands for.
+fn weaken_etag(mut response: warp::reply::Response) -> warp::reply::Response {
+    use warp::http::header::{HeaderValue, ETAG};
+
+    let weak = response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.starts_with("W/"))
+        .and_then(|v| HeaderValue::from_str(&format!("W/{}", v)).ok());
+    if let Some(weak) = weak {
+        response.headers_mut().insert(ETAG, weak);
+    }
+    response
+}
+
+/// Compresses the body of the response with that encoding.
+///
+/// The body is compressed as it is sent, so the streamed responses stay streamed.
+fn compress(
+    encoding: ContentEncoding,
+    response: warp::reply::Response,
+) -> warp::reply::Response {
+    use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder};
+    use futures::TryStreamExt;
+    use tokio_util::io::{ReaderStream, StreamReader};
+    use warp::http::header::{self, HeaderValue};
+    use warp::hyper::Body;
+
+    let (mut parts, body) = response.into_parts();
+    let body = StreamReader::new(body.map_err(std::io::Error::other));
+    let (name, body) = match encoding {
+        ContentEncoding::Brotli => (
+            "br",
+            Body::wrap_stream(ReaderStream::new(BrotliEncoder::new(body))),
+        ),
+        ContentEncoding::Gzip => (
+            "gzip",
+            Body::wrap_stream(ReaderStream::new(GzipEncoder::new(body))),
+        ),
+    };
+    // the length of the compressed body is not known up front.
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, HeaderValue::from_static(name));
+    weaken_etag(warp::reply::Response::from_parts(parts, body))
+}
+
 /// Creates a database store for the relayer based on the configuration passed in.
 ///
-/// Returns `Ok(store::sled::SledStore)` on success, or `Err(anyhow::Error)` on failure.
+/// Returns `Ok(store::cache::RelayerStore)` on success, or `Err(anyhow::Error)` on failure.
 ///
 /// # Arguments
 ///
 /// * `opts` - The configuration options for the database store.
+/// * `config` - The configuration of the store, with the size of its read cache.
 ///
 /// # Examples
 ///
 /// ```
 /// let args = Args::default();
-/// let store = create_store(&args).await?;
+/// let store = create_store(&args, &config.store).await?;
 /// ```
-async fn create_store(opts: &Opts) -> anyhow::Result<store::sled::SledStore> {
+async fn create_store(
+    opts: &Opts,
+    config: &config::StoreConfig,
+) -> anyhow::Result<store::cache::RelayerStore> {
     // check if we shall use the temp dir.
     if opts.tmp {
         tracing::debug!("Using temp dir for store");
         let store = store::sled::SledStore::temporary()?;
-        return Ok(store);
+        return Ok(store::cache::CachedStore::new(
+            store,
+            config.cache_capacity,
+        ));
     }
     let dirs = ProjectDirs::from(
         crate::PACKAGE_ID[0],
@@ -428,5 +1175,5 @@ async fn create_store(opts: &Opts) -> anyhow::Result<store::sled::SledStore> {
     };
 
     let store = store::sled::SledStore::open(db_path)?;
-    Ok(store)
+    Ok(store::cache::CachedStore::new(store, config.cache_capacity))
 }
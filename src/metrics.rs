@@ -0,0 +1,290 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use ethereum_types::U256;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use prometheus::{CounterVec, Encoder, GaugeVec, Opts, Registry, TextEncoder};
+
+/// The prefix of the names of all the metrics of the relayer.
+pub const PREFIX: &str = "webb_relayer";
+
+/// The metrics exported by the relayer.
+///
+/// Every metric carries the same labels (see [`Labels`]), so a single dashboard
+/// could cover all the chains and contracts of a deployment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Metric {
+    /// The number of events handled by the events watchers.
+    EventsHandled,
+    /// The number of relayed transactions that got finalized successfully.
+    RelayTxs,
+    /// The number of relayed transactions that failed, reverted or got rejected.
+    RelayTxsFailed,
+    /// The number of transactions waiting in the transaction queue.
+    TxQueueDepth,
+    /// The gas used by the transactions sent by the relayer.
+    GasUsed,
+    /// The native currency spent on the gas of the transactions sent by the relayer.
+    GasSpent,
+    /// The number of times a sender waited on a full channel.
+    ChannelBlocked,
+    /// The number of messages dropped, as their channel got closed.
+    ChannelDropped,
+    /// The number of leaves that conflicted with the cached ones, see [`LeafConflict`](crate::store::LeafConflict).
+    LeafConflicts,
+    /// Whether the root of the cached leaves diverged from the on-chain one for more
+    /// than one checkpoint, see [`RootCheckpoint`](crate::store::RootCheckpoint).
+    RootsDiverged,
+    /// The number of signed proposals that expired before getting executed.
+    ProposalsExpired,
+}
+
+impl Metric {
+    /// The name of the metric, with the [`PREFIX`].
+    pub fn name(&self) -> String {
+        let name = match self {
+            Self::EventsHandled => "events_handled_total",
+            Self::RelayTxs => "relay_txs_total",
+            Self::RelayTxsFailed => "relay_txs_failed_total",
+            Self::TxQueueDepth => "tx_queue_depth",
+            Self::GasUsed => "gas_used_total",
+            Self::GasSpent => "gas_spent_native_total",
+            Self::ChannelBlocked => "channel_blocked_total",
+            Self::ChannelDropped => "channel_dropped_total",
+            Self::LeafConflicts => "leaf_conflicts_total",
+            Self::RootsDiverged => "roots_diverged",
+            Self::ProposalsExpired => "proposals_expired_total",
+        };
+        format!("{}_{}", PREFIX, name)
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Self::EventsHandled => {
+                "The number of events handled by the events watchers"
+            }
+            Self::RelayTxs => {
+                "The number of relayed transactions that got finalized successfully"
+            }
+            Self::RelayTxsFailed => {
+                "The number of relayed transactions that failed, reverted or got rejected"
+            }
+            Self::TxQueueDepth => {
+                "The number of transactions waiting in the transaction queue"
+            }
+            Self::GasUsed => {
+                "The gas used by the transactions sent by the relayer"
+            }
+            Self::GasSpent => {
+                "The native currency spent on gas by the relayer, in whole units"
+            }
+            Self::ChannelBlocked => {
+                "The number of times a sender waited on a full channel"
+            }
+            Self::ChannelDropped => {
+                "The number of messages dropped, as their channel got closed"
+            }
+            Self::LeafConflicts => {
+                "The number of leaves that conflicted with the cached ones"
+            }
+            Self::RootsDiverged => {
+                "Whether the cached leaves diverged from the contract for more than one checkpoint"
+            }
+            Self::ProposalsExpired => {
+                "The number of signed proposals that expired before getting executed"
+            }
+        }
+    }
+
+    fn is_gauge(&self) -> bool {
+        matches!(self, Self::TxQueueDepth | Self::RootsDiverged)
+    }
+}
+
+/// The labels of a metric.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Labels {
+    /// The chain id of the chain, empty for the components that are not tied to a chain.
+    pub chain_id: String,
+    /// The address of the contract (or the tree id of the pallet), empty for
+    /// the metrics of a whole chain.
+    pub contract: String,
+    /// The kind of the contract or of the relayer's component.
+    pub kind: String,
+}
+
+impl Labels {
+    /// Creates the labels of a metric of that contract.
+    pub fn new(
+        chain_id: impl Display,
+        contract: impl Display,
+        kind: impl Display,
+    ) -> Self {
+        Self {
+            chain_id: chain_id.to_string(),
+            contract: contract.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+
+    /// Creates the labels of a metric of a whole chain.
+    pub fn chain(chain_id: impl Display, kind: impl Display) -> Self {
+        Self::new(chain_id, "", kind)
+    }
+
+    /// Creates the labels of a metric of a component that is not tied to a chain.
+    pub fn component(kind: impl Display) -> Self {
+        Self::new("", "", kind)
+    }
+
+    fn values(&self) -> [&str; 3] {
+        [&self.chain_id, &self.contract, &self.kind]
+    }
+}
+
+/// The names of the [`Labels`].
+const LABEL_NAMES: [&str; 3] = ["chain_id", "contract", "kind"];
+
+/// The collector of a metric, for all of its labels.
+#[derive(Clone)]
+enum Collector {
+    Counter(CounterVec),
+    Gauge(GaugeVec),
+}
+
+/// The Prometheus registry of the metrics, along with their collectors, which get
+/// registered on their first use.
+static REGISTRY: Lazy<(Registry, Mutex<HashMap<Metric, Collector>>)> =
+    Lazy::new(Default::default);
+
+fn collector(metric: Metric) -> Collector {
+    let (registry, collectors) = &*REGISTRY;
+    let mut collectors = collectors.lock();
+    let collector = collectors.entry(metric).or_insert_with(|| {
+        let opts = Opts::new(metric.name(), metric.help());
+        // the names and the labels of the metrics are all static and valid.
+        let collector = if metric.is_gauge() {
+            Collector::Gauge(
+                GaugeVec::new(opts, &LABEL_NAMES).expect("valid gauge"),
+            )
+        } else {
+            Collector::Counter(
+                CounterVec::new(opts, &LABEL_NAMES).expect("valid counter"),
+            )
+        };
+        let registered = match &collector {
+            Collector::Counter(v) => registry.register(Box::new(v.clone())),
+            Collector::Gauge(v) => registry.register(Box::new(v.clone())),
+        };
+        if let Err(e) = registered {
+            tracing::error!(
+                "Failed to register the metric {:?}: {}",
+                metric,
+                e
+            );
+        }
+        collector
+    });
+    collector.clone()
+}
+
+/// Adds `value` to a counter (or a gauge).
+pub fn add(metric: Metric, labels: Labels, value: f64) {
+    match collector(metric) {
+        Collector::Counter(v) => {
+            v.with_label_values(&labels.values()).inc_by(value)
+        }
+        Collector::Gauge(v) => v.with_label_values(&labels.values()).add(value),
+    }
+}
+
+/// Increments a counter by one.
+pub fn inc(metric: Metric, labels: Labels) {
+    add(metric, labels, 1.0);
+}
+
+/// Sets the value of a gauge.
+pub fn set(metric: Metric, labels: Labels, value: f64) {
+    match collector(metric) {
+        Collector::Gauge(v) => v.with_label_values(&labels.values()).set(value),
+        Collector::Counter(_) => {
+            tracing::warn!("The counter {:?} could not be set", metric)
+        }
+    }
+}
+
+/// Adds the gas used by a finalized transaction to [`Metric::GasUsed`], and its cost at
+/// that (effective) gas price, plus its L1 data fee on the rollups, to
+/// [`Metric::GasSpent`], in whole units of the native currency of the chain (18 decimals).
+pub fn add_gas(labels: Labels, gas_used: U256, gas_price: U256, l1_fee: U256) {
+    let spent = gas_used.saturating_mul(gas_price).saturating_add(l1_fee);
+    // precise enough for a metric, even past the 53 bits of the mantissa.
+    let spent = spent.to_string().parse::<f64>().unwrap_or_default() / 1e18;
+    add(Metric::GasUsed, labels.clone(), gas_used.low_u64() as f64);
+    add(Metric::GasSpent, labels, spent);
+}
+
+/// Renders all the metrics in the Prometheus text format.
+pub fn render() -> String {
+    let families = REGISTRY.0.gather();
+    let mut out = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&families, &mut out) {
+        tracing::error!("Failed to render the metrics: {}", e);
+    }
+    String::from_utf8(out).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_metric_carries_the_labels() {
+        let labels = Labels::new(4, "0xabcd", "anchor");
+        inc(Metric::RelayTxs, labels.clone());
+        inc(Metric::RelayTxs, labels);
+        set(Metric::TxQueueDepth, Labels::chain(4, "tx_queue"), 3.0);
+        let out = render();
+        assert!(out.contains("# TYPE webb_relayer_relay_txs_total counter"));
+        assert!(out.contains(
+            "webb_relayer_relay_txs_total{chain_id=\"4\",contract=\"0xabcd\",kind=\"anchor\"} 2"
+        ));
+        assert!(out.contains(
+            "webb_relayer_tx_queue_depth{chain_id=\"4\",contract=\"\",kind=\"tx_queue\"} 3"
+        ));
+    }
+
+    #[test]
+    fn gas_is_spent_in_whole_units() {
+        let labels = Labels::new(5, "0xgas", "tornado");
+        // the L1 data fee is part of the spent gas.
+        add_gas(
+            labels,
+            21_000.into(),
+            100_000_000_000_000u64.into(),
+            400_000_000_000_000_000u64.into(),
+        );
+        let out = render();
+        assert!(out.contains(
+            "webb_relayer_gas_used_total{chain_id=\"5\",contract=\"0xgas\",kind=\"tornado\"} 21000"
+        ));
+        assert!(out.contains(
+            "webb_relayer_gas_spent_native_total{chain_id=\"5\",contract=\"0xgas\",kind=\"tornado\"} 2.5"
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! # Relayer Verify Module 🕸️
+//!
+use std::convert::Infallible;
+
+use utoipa::OpenApi;
+
+use crate::handler;
+
+/// The OpenAPI document of the HTTP API of the relayer, derived from its handlers.
+///
+/// The routes of the EVM stack are documented by [`EvmApiDoc`], see [`api_doc`].
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Webb Relayer"),
+    paths(
+        handler::handle_ip_info,
+        handler::handle_relayer_info,
+        handler::handle_leaves_cache,
+        handler::handle_leaves_snapshot,
+        handler::handle_leaf_index,
+        handler::handle_withdrawal,
+        handler::handle_leaves_snapshot_cid,
+        handler::handle_earnings,
+        handler::handle_accounting,
+        handler::handle_relay_stats,
+        handler::handle_proposal_status,
+        handler::handle_bridges_info,
+        handler::handle_uptime,
+        handler::handle_api_usage,
+    ),
+    components(schemas(
+        handler::IpInformationResponse,
+        handler::ErrorResponse,
+        handler::LeavesCacheResponse,
+        handler::LeafIndexResponse,
+        handler::WithdrawalResponse,
+        handler::BridgeInfo,
+        handler::UptimeResponse,
+        handler::ChainUptime,
+        handler::QuotaErrorResponse,
+        handler::ApiUsageResponse,
+        crate::quota::Quota,
+        crate::quota::QuotaExceeded,
+        crate::store::ApiUsage,
+        crate::events_watcher::BridgeStatus,
+        crate::events_watcher::HandledCommand,
+        crate::store::LeavesSnapshotInfo,
+        crate::store::Earnings,
+        crate::store::AccountingEntry,
+        crate::store::RelayStats,
+        crate::store::StatsResolution,
+        crate::store::ProposalStatus,
+    ))
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document of the routes of the EVM stack.
+#[cfg(feature = "evm")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(handler::handle_verify_roots, handler::handle_fee_quote),
+    components(schemas(
+        crate::verify::RootsReport,
+        crate::verify::RootsStatus,
+        crate::tx_relay::evm::fees::FeeQuote,
+    ))
+)]
+pub struct EvmApiDoc;
+
+/// The OpenAPI document of the routes the relayer is built with.
+pub fn api_doc() -> utoipa::openapi::OpenApi {
+    #[allow(unused_mut)]
+    let mut doc = ApiDoc::openapi();
+    #[cfg(feature = "evm")]
+    doc.merge(EvmApiDoc::openapi());
+    doc
+}
+
+/// The Swagger UI page, it loads the UI from a CDN and points it at the OpenAPI document.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Webb Relayer API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Handles the OpenAPI document requests
+///
+/// Returns a Result with the OpenAPI document of the HTTP API, in JSON.
+pub async fn handle_openapi_spec() -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&api_doc()))
+}
+
+/// Handles the Swagger UI requests
+///
+/// Returns a Result with the Swagger UI page, for browsing the HTTP API.
+pub async fn handle_swagger_ui() -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::html(SWAGGER_UI))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_spec_documents_the_routes() {
+        let spec = serde_json::to_value(api_doc()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/v1/leaves/{chain_id}/{contract}"));
+        #[cfg(feature = "evm")]
+        assert!(paths.contains_key("/api/v1/fee/{chain_id}/{contract}"));
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        assert!(schemas.contains_key("LeavesCacheResponse"));
+        #[cfg(feature = "evm")]
+        assert!(schemas.contains_key("FeeQuote"));
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::time::Duration;
+
+use ethereum_types::U256;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::providers::Middleware;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::signers::Signer;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::{
+    self, sp_core::crypto::AccountId32, sp_core::Pair,
+};
+#[cfg(feature = "substrate")]
+use webb::substrate::{dkg_runtime, protocol_substrate_runtime};
+
+#[cfg(feature = "evm")]
+use crate::config::Contract;
+#[cfg(feature = "substrate")]
+use crate::config::SubstrateRuntime;
+use crate::config::{CommonContractConfig, PreflightFailureAction};
+use crate::context::RelayerContext;
+
+/// How long the RPC endpoint of a chain could take to answer, before the chain fails the checks.
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The chains and the nodes that failed the startup checks, and should be disabled.
+#[derive(Debug, Clone, Default)]
+pub struct Failed {
+    /// The names of the failed EVM chains.
+    pub evm: Vec<String>,
+    /// The names of the failed Substrate nodes.
+    pub substrate: Vec<String>,
+}
+
+impl Failed {
+    /// Whether nothing failed the checks.
+    pub fn is_empty(&self) -> bool {
+        self.evm.is_empty() && self.substrate.is_empty()
+    }
+
+    fn names(&self) -> String {
+        let names: Vec<&str> = self
+            .evm
+            .iter()
+            .chain(&self.substrate)
+            .map(String::as_str)
+            .collect();
+        names.join(", ")
+    }
+}
+
+/// Runs the startup checks of that EVM chain.
+///
+/// Returns the problems found, with what should be fixed in the configuration.
+/// An unreachable RPC endpoint stops the checks early, as nothing else could be checked.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_name` - The name of the chain
+#[cfg(feature = "evm")]
+pub async fn check_evm_chain(
+    ctx: &RelayerContext,
+    chain_name: &str,
+) -> anyhow::Result<Vec<String>> {
+    let chain = ctx.config.evm.get(chain_name).ok_or_else(|| {
+        anyhow::anyhow!("Chain {} not configured", chain_name)
+    })?;
+    let provider = ctx.evm_provider(chain_name).await?;
+    let wallet = ctx.evm_wallet(chain_name).await?;
+    let mut problems = Vec::new();
+    let head = tokio::time::timeout(RPC_TIMEOUT, provider.get_block_number())
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out"))
+        .and_then(|v| v.map_err(anyhow::Error::from));
+    let head = match head {
+        Ok(v) => v.as_u64(),
+        Err(e) => {
+            problems.push(format!(
+                "the RPC endpoint is not reachable ({}), check the `http-endpoint`",
+                e
+            ));
+            return Ok(problems);
+        }
+    };
+    match provider.get_chainid().await {
+        // compared as U256, a node could report a chain id that does not fit in a u64.
+        Ok(chain_id) if chain_id != U256::from(chain.chain_id) => {
+            problems.push(format!(
+                "the RPC endpoint reports the chain id {}, but the `chain-id` is {}",
+                chain_id, chain.chain_id
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("failed to fetch the chain id: {}", e)),
+    }
+    for contract in chain.contracts.iter().map(Contract::common) {
+        match provider.get_code(contract.address, None).await {
+            Ok(code) if code.as_ref().is_empty() => {
+                problems.push(format!(
+                    "there is no contract deployed at {:?}, check its `address`",
+                    contract.address
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!(
+                "failed to fetch the code of {:?}: {}",
+                contract.address, e
+            )),
+        }
+        if let Some(problem) = check_deployed_at(contract, head) {
+            problems.push(problem);
+        }
+    }
+    let min_balance = ctx.config.preflight.min_balance;
+    match provider.get_balance(wallet.address(), None).await {
+        Ok(balance) if balance < min_balance => {
+            problems.push(format!(
+                "the wallet {:?} has a balance of {} wei, it should be at least {} wei",
+                wallet.address(),
+                balance,
+                min_balance
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!(
+            "failed to fetch the balance of the wallet {:?}: {}",
+            wallet.address(),
+            e
+        )),
+    }
+    Ok(problems)
+}
+
+/// Runs the startup checks of that Substrate node.
+///
+/// Returns the problems found, with what should be fixed in the configuration.
+/// An unreachable node stops the checks early, as nothing else could be checked.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `node_name` - The name of the node
+#[cfg(feature = "substrate")]
+pub async fn check_substrate_node(
+    ctx: &RelayerContext,
+    node_name: &str,
+) -> anyhow::Result<Vec<String>> {
+    let node =
+        ctx.config.substrate.get(node_name).ok_or_else(|| {
+            anyhow::anyhow!("Node {} not configured", node_name)
+        })?;
+    let mut problems = Vec::new();
+    let connected = tokio::time::timeout(
+        RPC_TIMEOUT,
+        ctx.substrate_provider::<subxt::DefaultConfig>(node_name),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("timed out"))
+    .and_then(|v| v);
+    let client = match connected {
+        Ok(v) => v,
+        Err(e) => {
+            problems.push(format!(
+                "the node is not reachable ({}), check the `ws-endpoint`",
+                e
+            ));
+            return Ok(problems);
+        }
+    };
+    let account =
+        AccountId32::from(ctx.substrate_wallet(node_name).await?.public());
+    let balance = match node.runtime {
+        SubstrateRuntime::Dkg => {
+            let api = client.to_runtime_api::<dkg_runtime::api::RuntimeApi<
+                subxt::DefaultConfig,
+                subxt::DefaultExtra<subxt::DefaultConfig>,
+            >>();
+            api.storage()
+                .system()
+                .account(account.clone(), None)
+                .await
+                .map(|v| v.data.free)
+        }
+        SubstrateRuntime::WebbProtocol => {
+            let api = client
+                .to_runtime_api::<protocol_substrate_runtime::api::RuntimeApi<
+                    subxt::DefaultConfig,
+                    subxt::DefaultExtra<subxt::DefaultConfig>,
+                >>();
+            api.storage()
+                .system()
+                .account(account.clone(), None)
+                .await
+                .map(|v| v.data.free)
+        }
+    };
+    let min_balance = ctx.config.preflight.min_balance;
+    match balance {
+        Ok(balance) if U256::from(balance) < min_balance => {
+            problems.push(format!(
+                "the account {} has a free balance of {}, it should be at least {}",
+                account, balance, min_balance
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!(
+            "failed to fetch the balance of the account {}: {}",
+            account, e
+        )),
+    }
+    Ok(problems)
+}
+
+/// Checks that the contract got deployed before the current chain `head`.
+fn check_deployed_at(
+    contract: &CommonContractConfig,
+    head: u64,
+) -> Option<String> {
+    if contract.deployed_at > head {
+        Some(format!(
+            "the `deployed-at` of {:?} is #{}, after the chain head #{}",
+            contract.address, contract.deployed_at, head
+        ))
+    } else {
+        None
+    }
+}
+
+/// Logs the problems of a chain, returns whether it failed the checks.
+fn report(name: &str, problems: &[String]) -> bool {
+    if problems.is_empty() {
+        tracing::debug!("Chain {} passed the preflight checks", name);
+        return false;
+    }
+    for problem in problems {
+        tracing::error!("Chain {}: {}", name, problem);
+    }
+    true
+}
+
+/// Runs the startup checks of every enabled EVM chain and Substrate node.
+///
+/// Returns an error with all the problems if any chain failed the checks and the
+/// relayer should not start, otherwise the chains that should be disabled, none
+/// of them if the failures are only reported.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+pub async fn run(ctx: &RelayerContext) -> anyhow::Result<Failed> {
+    let mut failed = Failed::default();
+    #[cfg(feature = "evm")]
+    for (chain_name, _) in ctx.config.evm.iter().filter(|(_, c)| c.enabled) {
+        let problems = check_evm_chain(ctx, chain_name).await?;
+        if report(chain_name, &problems) {
+            failed.evm.push(chain_name.clone());
+        }
+    }
+    #[cfg(feature = "substrate")]
+    for (node_name, _) in ctx.config.substrate.iter().filter(|(_, c)| c.enabled)
+    {
+        let problems = check_substrate_node(ctx, node_name).await?;
+        if report(node_name, &problems) {
+            failed.substrate.push(node_name.clone());
+        }
+    }
+    if failed.is_empty() {
+        return Ok(failed);
+    }
+    match ctx.config.preflight.on_failure {
+        PreflightFailureAction::Warn => {
+            tracing::warn!(
+                "The chains {} failed the preflight checks, starting anyway",
+                failed.names()
+            );
+            Ok(Failed::default())
+        }
+        PreflightFailureAction::Abort => anyhow::bail!(
+            "The chains {} failed the preflight checks, fix them (see the errors above) \
+            or set `preflight.on-failure` to `disable-chain`",
+            failed.names()
+        ),
+        PreflightFailureAction::DisableChain => {
+            tracing::warn!(
+                "Disabling the chains {}, as they failed the preflight checks",
+                failed.names()
+            );
+            Ok(failed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contracts_deployed_after_the_head_are_reported() {
+        let contract = CommonContractConfig {
+            address: Default::default(),
+            deployed_at: 100,
+            sync_from: Default::default(),
+            force_resync: false,
+        };
+        assert_eq!(check_deployed_at(&contract, 100), None);
+        assert!(check_deployed_at(&contract, 99).is_some());
+    }
+}
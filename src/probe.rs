@@ -40,4 +40,25 @@ pub enum Kind {
     /// Relayer Leaves Store state on a specific chain/node.
     #[display(fmt = "leaves_store")]
     LeavesStore,
+    /// Relayer Leaves Snapshot exporter state on a specific chain/node.
+    #[display(fmt = "leaves_snapshot")]
+    LeavesSnapshot,
+    /// Garbage collection of the stale signed proposals.
+    #[display(fmt = "proposals_gc")]
+    ProposalsGc,
+    /// Garbage collection of the relay stats older than their retention.
+    #[display(fmt = "relay_stats_gc")]
+    RelayStatsGc,
+    /// Checking the cached leaves against the on-chain roots.
+    #[display(fmt = "verify_roots")]
+    VerifyRoots,
+    /// The periodic checkpoints of the roots of the cached leaves.
+    #[display(fmt = "root_checkpoints")]
+    RootCheckpoints,
+    /// Heartbeats reported to an external monitor.
+    #[display(fmt = "heartbeat")]
+    Heartbeat,
+    /// The leader election among the instances of a deployment, in the high-availability mode.
+    #[display(fmt = "leader_election")]
+    LeaderElection,
 }
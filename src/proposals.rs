@@ -0,0 +1,183 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+#![warn(missing_docs)]
+//! # Relayer Proposals Module 🕸️
+//!
+//! A module for building and decoding the proposals that the relayer deals with.
+//!
+//! ## Overview
+//!
+//! All the proposals start with a [`ProposalHeader`]:
+//! ```text
+//! ┌─────────────────────────────────────────────┬──────────────┬──────────┐
+//! │                ResourceId 32B               │              │          │
+//! ├──────────────────────────┬──────────────────┤ FuncSig 4B   │ Nonce 4B │
+//! │ TargetSystem 26B         │ TypedChainId 6B  │              │          │
+//! └──────────────────────────┴──────────────────┴──────────────┴──────────┘
+//! ```
+//! The types are the ones defined in `webb-proposals`, this module only adds the
+//! helpers used across the relayer, so the proposals are always built and decoded
+//! the same way, instead of concatenating bytes by hand.
+use ethereum_types as types;
+use std::convert::TryFrom;
+
+pub use webb_proposals::{
+    FunctionSignature, Nonce, ProposalHeader, ResourceId,
+    ResourceIdUpdateProposal, TargetSystem, TypedChainId,
+};
+
+/// The function signature of `updateEdge(uint256,bytes32,uint256)` on the `AnchorHandler`.
+pub const ANCHOR_UPDATE_FUNCTION_SIGNATURE: [u8; 4] = [68, 52, 123, 169];
+
+/// The function signature of `adminSetResourceWithSignature(bytes32,bytes4,uint32,bytes32,address,address,bytes)`
+/// on the `SignatureBridge`.
+pub const SET_RESOURCE_FUNCTION_SIGNATURE: [u8; 4] = [201, 68, 228, 8];
+
+/// Builds the proposal registering the resource of the `anchor` on the Signature Bridge
+/// at `bridge`, both deployed on the EVM chain `chain_id`.
+///
+/// The proposal is addressed to the resource of the bridge itself, and the anchor is both
+/// the execution context of its new resource, and the target of its `handler`.
+///
+/// # Arguments
+///
+/// * `bridge` - The address of the Signature Bridge
+/// * `chain_id` - The chain id of the chain of the bridge and the anchor
+/// * `nonce` - The next proposal nonce of the bridge
+/// * `anchor` - The address of the anchor
+/// * `handler` - The address of the `AnchorHandler` of the anchor
+pub fn resource_id_update_proposal(
+    bridge: types::Address,
+    chain_id: u32,
+    nonce: u32,
+    anchor: types::Address,
+    handler: types::Address,
+) -> ResourceIdUpdateProposal {
+    let header = ProposalHeader::new(
+        evm_resource_id(bridge, chain_id),
+        SET_RESOURCE_FUNCTION_SIGNATURE.into(),
+        Nonce::new(nonce),
+    );
+    ResourceIdUpdateProposal::new(
+        header,
+        evm_resource_id(anchor, chain_id),
+        handler.to_fixed_bytes(),
+        anchor.to_fixed_bytes(),
+    )
+}
+
+/// Returns the typed chain id of the EVM chain `chain_id`.
+///
+/// Returns an error if the chain id does not fit in the `u32` of a typed chain id.
+pub fn evm_typed_chain_id(
+    chain_id: types::U256,
+) -> anyhow::Result<TypedChainId> {
+    let chain_id = u32::try_from(chain_id).map_err(|_| {
+        anyhow::anyhow!(
+            "chain id {} does not fit in a typed chain id",
+            chain_id
+        )
+    })?;
+    Ok(TypedChainId::Evm(chain_id))
+}
+
+/// Derives the resource id of a contract deployed on an EVM chain.
+pub fn evm_resource_id(address: types::Address, chain_id: u32) -> ResourceId {
+    let target_system =
+        TargetSystem::new_contract_address(address.to_fixed_bytes());
+    ResourceId::new(target_system, TypedChainId::Evm(chain_id))
+}
+
+/// Derives the resource id of a tree of the anchor handler pallet of a Substrate node.
+pub fn substrate_resource_id(tree_id: u32, chain_id: u32) -> ResourceId {
+    let target_system = TargetSystem::new_tree_id(tree_id);
+    ResourceId::new(target_system, TypedChainId::Substrate(chain_id))
+}
+
+/// Decodes the [`ProposalHeader`] at the start of the proposal data.
+///
+/// Returns an error if the data is too short to contain a header.
+pub fn decode_header(data: &[u8]) -> anyhow::Result<ProposalHeader> {
+    const LENGTH: usize = ProposalHeader::LENGTH;
+    anyhow::ensure!(
+        data.len() >= LENGTH,
+        "proposal data is too short: got {} bytes, expected at least {}",
+        data.len(),
+        LENGTH
+    );
+    let mut header_bytes = [0u8; LENGTH];
+    header_bytes.copy_from_slice(&data[..LENGTH]);
+    Ok(ProposalHeader::from(header_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_id_roundtrip() {
+        let address = types::Address::random();
+        let resource_id = evm_resource_id(address, 5);
+        let bytes = resource_id.into_bytes();
+        // the address is padded to the right of the target system.
+        assert_eq!(&bytes[0..6], &[0u8; 6]);
+        assert_eq!(&bytes[6..26], address.as_bytes());
+        let decoded = ResourceId::from(bytes);
+        assert_eq!(decoded, resource_id);
+        assert_eq!(
+            decoded.target_system(),
+            TargetSystem::ContractAddress(address.to_fixed_bytes())
+        );
+        assert_eq!(decoded.typed_chain_id(), TypedChainId::Evm(5));
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let header = ProposalHeader::new(
+            evm_resource_id(types::Address::random(), 5),
+            ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
+            Nonce::new(7),
+        );
+        let mut data = header.to_bytes().to_vec();
+        data.extend_from_slice(&[0xff; 32]);
+        let decoded = decode_header(&data).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.nonce().to_u32(), 7);
+        assert_eq!(
+            decoded.function_signature().to_bytes(),
+            ANCHOR_UPDATE_FUNCTION_SIGNATURE
+        );
+        assert!(decode_header(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn resource_id_update_proposal_targets_the_bridge() {
+        let bridge = types::Address::random();
+        let anchor = types::Address::random();
+        let handler = types::Address::random();
+        let proposal =
+            resource_id_update_proposal(bridge, 5, 3, anchor, handler);
+        let data = proposal.to_bytes();
+        let header = decode_header(&data).unwrap();
+        assert_eq!(header.resource_id(), evm_resource_id(bridge, 5));
+        assert_eq!(
+            header.function_signature().to_bytes(),
+            SET_RESOURCE_FUNCTION_SIGNATURE
+        );
+        assert_eq!(header.nonce().to_u32(), 3);
+        assert_eq!(proposal.new_resource_id(), evm_resource_id(anchor, 5));
+        assert_eq!(ResourceIdUpdateProposal::from(data), proposal);
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! # Relayer Quota Module 🕸️
+//!
+//! A module for enforcing the daily quotas of the API keys.
+//!
+//! ## Overview
+//!
+//! The clients send their API key along with their requests, the relay commands and the
+//! leaves they get served are counted in the store under the name of the key, per (UTC)
+//! day. A request that would go over a quota is refused, with the quota, its limit and
+//! when it is reset, see [`QuotaExceeded`].
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use subtle::ConstantTimeEq;
+
+use crate::config::{ApiKeyConfig, ApiKeysConfig};
+#[doc(hidden)]
+pub use crate::handler::types::{Quota, QuotaExceeded};
+use crate::store::{ApiUsage, ApiUsageStore};
+
+/// The quotas are counted per day, since the unix epoch.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Every leaf counts for 32 bytes in the leaves quota, whatever the encoding of the response.
+pub const LEAF_BYTES: u64 = 32;
+
+/// The reasons a request gets refused by the quotas.
+#[derive(Debug, thiserror::Error)]
+pub enum QuotaError {
+    /// The API keys are required, but the client did not send one.
+    #[error("An API key is required")]
+    MissingApiKey,
+    /// The client sent a key that is not configured.
+    #[error("Unknown API key")]
+    UnknownApiKey,
+    /// The request would go over a quota of the API key.
+    #[error("The daily {} quota of the API key is exhausted", .0.quota)]
+    Exceeded(QuotaExceeded),
+    /// The usage could not be counted.
+    #[error(transparent)]
+    Store(#[from] anyhow::Error),
+}
+
+/// The API key of a client, with its quotas.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    /// The name the usage of the key is counted under.
+    pub name: String,
+    /// The configuration of the key.
+    pub config: ApiKeyConfig,
+}
+
+/// Resolves the API key sent by a client.
+///
+/// Returns `None` for the clients without a key, if the keys are not required. The keys
+/// are looked up by their hash, see [`ApiKeysConfig::index_keys`].
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the API keys
+/// * `key` - The key sent by the client, if any
+pub fn authenticate(
+    config: &ApiKeysConfig,
+    key: Option<&str>,
+) -> Result<Option<ApiKey>, QuotaError> {
+    let key = match key {
+        Some(key) => key,
+        None if config.required => return Err(QuotaError::MissingApiKey),
+        None => return Ok(None),
+    };
+    let hash = crate::utils::keccak256(key.as_bytes());
+    config
+        .names_by_hash
+        .get(&hash)
+        .and_then(|name| Some((name, config.keys.get(name)?)))
+        // the hash only finds the configured key, the keys are still compared, in
+        // constant time so the key could not be guessed from the timings.
+        .filter(|(_, c)| bool::from(c.key.as_bytes().ct_eq(key.as_bytes())))
+        .map(|(name, config)| {
+            Some(ApiKey {
+                name: name.clone(),
+                config: config.clone(),
+            })
+        })
+        .ok_or(QuotaError::UnknownApiKey)
+}
+
+/// Returns the day of that unix timestamp, the quotas are counted per day.
+pub fn day(timestamp: u64) -> u64 {
+    timestamp / SECONDS_PER_DAY
+}
+
+/// Returns the unix timestamp of when the quotas of that day are reset.
+pub fn resets_at(day: u64) -> u64 {
+    (day + 1) * SECONDS_PER_DAY
+}
+
+/// Returns the first quota of the key that `usage` goes over, with its limit and its usage.
+fn exceeded(
+    config: &ApiKeyConfig,
+    usage: &ApiUsage,
+) -> Option<(Quota, u64, u64)> {
+    let quotas = [
+        (Quota::Relays, config.relays_per_day, usage.relays),
+        (
+            Quota::LeavesBytes,
+            config.leaves_bytes_per_day,
+            usage.leaves_bytes,
+        ),
+    ];
+    quotas.iter().find_map(|&(quota, limit, used)| match limit {
+        Some(limit) if used > limit => Some((quota, limit, used)),
+        _ => None,
+    })
+}
+
+/// Counts `usage` against the quotas of the API key, only if it stays within them.
+///
+/// The clients without an API key are not counted.
+///
+/// # Arguments
+///
+/// * `store` - The store of the usage counters
+/// * `api_key` - The API key of the client, if any
+/// * `usage` - The usage of the request
+pub fn consume<S: ApiUsageStore>(
+    store: &S,
+    api_key: Option<&ApiKey>,
+    usage: ApiUsage,
+) -> Result<(), QuotaError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(anyhow::Error::from)?
+        .as_secs();
+    consume_at(store, api_key, usage, now)
+}
+
+fn consume_at<S: ApiUsageStore>(
+    store: &S,
+    api_key: Option<&ApiKey>,
+    usage: ApiUsage,
+    now: u64,
+) -> Result<(), QuotaError> {
+    let api_key = match api_key {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let day = day(now);
+    let (used, added) =
+        store.add_api_usage(&api_key.name, day, usage, |sum| {
+            exceeded(&api_key.config, sum).is_none()
+        })?;
+    if added {
+        return Ok(());
+    }
+    // report the quota the request would go over, with what is used of it so far.
+    let (quota, limit, _) =
+        exceeded(&api_key.config, &used.saturating_add(usage))
+            .expect("refused usage goes over a quota");
+    let used = match quota {
+        Quota::Relays => used.relays,
+        Quota::LeavesBytes => used.leaves_bytes,
+    };
+    tracing::debug!(
+        api_key = %api_key.name,
+        quota = %quota,
+        "The quota of the API key is exhausted"
+    );
+    Err(QuotaError::Exceeded(QuotaExceeded {
+        quota,
+        limit,
+        used,
+        resets_at: resets_at(day),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sled::SledStore;
+
+    fn api_key() -> ApiKey {
+        ApiKey {
+            name: String::from("partner"),
+            config: ApiKeyConfig {
+                key: String::from("secret"),
+                relays_per_day: Some(1),
+                leaves_bytes_per_day: Some(10 * LEAF_BYTES),
+            },
+        }
+    }
+
+    #[test]
+    fn only_the_configured_keys_are_accepted() {
+        let mut config = ApiKeysConfig::default();
+        config
+            .keys
+            .insert(String::from("partner"), api_key().config);
+        config.index_keys();
+        assert!(authenticate(&config, None).unwrap().is_none());
+        let key = authenticate(&config, Some("secret")).unwrap().unwrap();
+        assert_eq!(key.name, "partner");
+        assert!(matches!(
+            authenticate(&config, Some("partner")),
+            Err(QuotaError::UnknownApiKey)
+        ));
+        config.required = true;
+        assert!(matches!(
+            authenticate(&config, None),
+            Err(QuotaError::MissingApiKey)
+        ));
+    }
+
+    #[test]
+    fn the_quotas_are_reset_every_day() {
+        let store = SledStore::temporary().unwrap();
+        let api_key = api_key();
+        let relay = ApiUsage {
+            relays: 1,
+            leaves_bytes: 0,
+        };
+        let now = 1_650_000_000;
+        consume_at(&store, Some(&api_key), relay, now).unwrap();
+        let err = consume_at(&store, Some(&api_key), relay, now).unwrap_err();
+        match err {
+            QuotaError::Exceeded(e) => {
+                assert_eq!(e.quota, Quota::Relays);
+                assert_eq!(e.limit, 1);
+                assert_eq!(e.used, 1);
+                assert_eq!(e.resets_at, resets_at(day(now)));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        // the leaves are counted on their own.
+        let leaves = ApiUsage {
+            relays: 0,
+            leaves_bytes: 10 * LEAF_BYTES,
+        };
+        consume_at(&store, Some(&api_key), leaves, now).unwrap();
+        consume_at(&store, Some(&api_key), relay, resets_at(day(now))).unwrap();
+        // the clients without a key are not counted.
+        consume_at(&store, None, relay, now).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use webb::evm::ethers::providers::{self, JsonRpcClient};
+
+/// A provider over HTTP, sharing the rate limit of its endpoint.
+pub type HttpProvider = providers::Provider<RateLimitedHttp>;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// A token bucket, shared by everything calling the same RPC endpoint.
+///
+/// It allows `burst` requests at once, then `requests_per_second` requests every second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket.
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(requests_per_second.max(1)),
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, returning how long to wait before using it.
+    ///
+    /// The bucket could go below zero, so the callers waiting for a token are served in order.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock();
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        bucket.updated_at = now;
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        }
+    }
+
+    /// Waits until a request could be sent to the endpoint.
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tracing::trace!("Rate limited for {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// An HTTP transport that waits for the rate limiter of its endpoint before every request.
+#[derive(Debug, Clone)]
+pub struct RateLimitedHttp {
+    inner: providers::Http,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimitedHttp {
+    /// Creates the transport, without a rate limit if `limiter` is `None`.
+    pub fn new(
+        inner: providers::Http,
+        limiter: Option<Arc<RateLimiter>>,
+    ) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait::async_trait]
+impl JsonRpcClient for RateLimitedHttp {
+    type Error = providers::HttpClientError;
+
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_over_the_burst_wait_for_the_rate() {
+        let limiter = RateLimiter::new(10, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        // the next callers are queued, 100ms apart.
+        assert_eq!(limiter.reserve(now), Duration::from_millis(100));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(200));
+        // the bucket refills over time, up to the burst.
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert!(limiter.reserve(later) > Duration::ZERO);
+    }
+}
@@ -23,30 +23,42 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ethereum_types::U256;
-use webb::evm::ethers::providers;
-use webb::substrate::dkg_runtime::api::runtime_types::webb_proposals::header::TypedChainId;
-use webb::substrate::dkg_runtime::api::RuntimeApi as DkgRuntimeApi;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::providers::Middleware;
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt;
+#[cfg(all(feature = "evm", feature = "substrate"))]
 use webb::substrate::subxt::PairSigner;
 
 use crate::config::*;
 use crate::context::RelayerContext;
+#[cfg(feature = "evm")]
 use crate::events_watcher::proposal_signing_backend::*;
 use crate::events_watcher::*;
+#[cfg(feature = "ha")]
+use crate::ha::LeaderElection;
+use crate::heartbeat;
+#[cfg(feature = "evm")]
+use crate::ipfs::LeavesSnapshotExporter;
+#[cfg(feature = "evm")]
+use crate::store::BridgeKey;
+#[cfg(feature = "evm")]
+use crate::store::RelayStatsStore;
+#[cfg(feature = "evm")]
 use crate::tx_queue::TxQueue;
+#[cfg(feature = "evm")]
+use crate::verify;
 /// Type alias for providers
-type Client = providers::Provider<providers::Http>;
+#[cfg(feature = "evm")]
+type Client = crate::rate_limit::HttpProvider;
 /// Type alias for the DKG DefaultConfig
+#[cfg(feature = "substrate")]
 type DkgClient = subxt::Client<subxt::DefaultConfig>;
-/// Type alias for the DKG RuntimeApi
-type DkgRuntime = DkgRuntimeApi<
-    subxt::DefaultConfig,
-    subxt::DefaultExtra<subxt::DefaultConfig>,
->;
 /// Type alias for [Sled](https://sled.rs)-based database store
-type Store = crate::store::sled::SledStore;
+type Store = crate::store::cache::RelayerStore;
 /// Starts all background services for all chains configured in the config file.
 ///
 /// Returns a future that resolves when all services are started successfully.
@@ -55,17 +67,25 @@ type Store = crate::store::sled::SledStore;
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
 /// * `store` -[Sled](https://sled.rs)-based database store
+/// * `bridge_registry` - The registry used for routing commands to the signature bridges
 ///
 /// # Examples
 ///
 /// ```
-/// let _ = service::ignite(&ctx, Arc::new(store)).await?;
+/// let _ = service::ignite(&ctx, Arc::new(store), bridge_registry).await?;
 /// ```
 pub async fn ignite(
     ctx: &RelayerContext,
     store: Arc<Store>,
+    bridge_registry: BridgeRegistry<Store>,
 ) -> anyhow::Result<()> {
+    // compete for the leadership first, the standbys do not relay until they lead.
+    #[cfg(feature = "ha")]
+    if let Some(ref ha) = ctx.config.ha {
+        start_leader_election(ctx.clone(), ha)?;
+    }
     // now we go through each chain, in our configuration
+    #[cfg(feature = "evm")]
     for (chain_name, chain_config) in &ctx.config.evm {
         if !chain_config.enabled {
             continue;
@@ -78,6 +98,11 @@ pub async fn ignite(
         );
 
         for contract in &chain_config.contracts {
+            force_resync(
+                store.as_ref(),
+                chain_config.chain_id,
+                contract.common(),
+            )?;
             match contract {
                 Contract::Tornado(config) => {
                     start_tornado_events_watcher(
@@ -91,8 +116,10 @@ pub async fn ignite(
                     start_anchor_events_watcher(
                         ctx,
                         config,
+                        chain_config,
                         client.clone(),
                         store.clone(),
+                        bridge_registry.clone(),
                     )
                     .await?;
                 }
@@ -102,6 +129,7 @@ pub async fn ignite(
                         config,
                         client.clone(),
                         store.clone(),
+                        bridge_registry.clone(),
                     )
                     .await?;
                 }
@@ -112,6 +140,7 @@ pub async fn ignite(
         start_tx_queue(ctx.clone(), chain_name.clone(), store.clone())?;
     }
     // now, we start substrate service/tasks
+    #[cfg(feature = "substrate")]
     for (node_name, node_config) in &ctx.config.substrate {
         if !node_config.enabled {
             continue;
@@ -121,22 +150,13 @@ pub async fn ignite(
                 let client = ctx
                     .substrate_provider::<subxt::DefaultConfig>(node_name)
                     .await?;
-                let api = client.clone().to_runtime_api::<DkgRuntime>();
-                let chain_id =
-                    api.constants().dkg_proposals().chain_identifier()?;
-                let chain_id = match chain_id {
-                    TypedChainId::None => 0,
-                    TypedChainId::Evm(id)
-                    | TypedChainId::Substrate(id)
-                    | TypedChainId::PolkadotParachain(id)
-                    | TypedChainId::KusamaParachain(id)
-                    | TypedChainId::RococoParachain(id)
-                    | TypedChainId::Cosmos(id)
-                    | TypedChainId::Solana(id) => id,
-                };
-                let chain_id = U256::from(chain_id);
+                let chain_id = crate::utils::substrate_chain_id(
+                    &node_config.runtime,
+                    &client,
+                )?;
                 for pallet in &node_config.pallets {
                     match pallet {
+                        #[cfg(feature = "evm")]
                         Pallet::DKGProposalHandler(config) => {
                             start_dkg_proposal_handler(
                                 ctx,
@@ -145,19 +165,87 @@ pub async fn ignite(
                                 node_name.clone(),
                                 chain_id,
                                 store.clone(),
+                                bridge_registry.clone(),
                             )?;
                         }
+                        #[cfg(not(feature = "evm"))]
+                        Pallet::DKGProposalHandler(_) => {
+                            tracing::warn!(
+                                "The DKG proposal handler of ({}) relays the signed proposals to the EVM chains, which this relayer is built without, skipping",
+                                node_name,
+                            );
+                        }
                         Pallet::DKGProposals(_) => {
                             // TODO(@shekohex): start the dkg proposals service
                         }
+                        Pallet::Bridge(_) => {
+                            tracing::warn!(
+                                "The bridge pallet is not part of the DKG runtime of ({}), skipping",
+                                node_name,
+                            );
+                        }
                     }
                 }
             }
             SubstrateRuntime::WebbProtocol => {
-                // Handle Webb Protocol here
+                if node_config.pallets.is_empty() {
+                    continue;
+                }
+                let client = ctx
+                    .substrate_provider::<subxt::DefaultConfig>(node_name)
+                    .await?;
+                let chain_id = crate::utils::substrate_chain_id(
+                    &node_config.runtime,
+                    &client,
+                )?;
+                for pallet in &node_config.pallets {
+                    match pallet {
+                        Pallet::Bridge(config) => {
+                            start_substrate_bridge_watcher(
+                                ctx,
+                                config,
+                                client.clone(),
+                                node_name.clone(),
+                                chain_id,
+                                store.clone(),
+                            )?;
+                        }
+                        Pallet::DKGProposals(_)
+                        | Pallet::DKGProposalHandler(_) => {
+                            tracing::warn!(
+                                "The DKG pallets are not part of the Webb Protocol runtime of ({}), skipping",
+                                node_name,
+                            );
+                        }
+                    }
+                }
             }
         };
     }
+    // collect the signed proposals that never got executed.
+    #[cfg(feature = "evm")]
+    start_proposals_gc(ctx.clone(), store.clone())?;
+    // drop the relay stats older than their retention.
+    #[cfg(feature = "evm")]
+    start_relay_stats_gc(ctx.clone(), store.clone())?;
+    // report the heartbeats, if configured.
+    if let Some(ref heartbeat) = ctx.config.heartbeat {
+        start_heartbeat(ctx.clone(), heartbeat.clone(), store.clone())?;
+    }
+    // checkpoint the roots of the contracts, if configured.
+    #[cfg(feature = "evm")]
+    if let Some(ref root_checkpoints) = ctx.config.root_checkpoints {
+        start_root_checkpoints(
+            ctx.clone(),
+            root_checkpoints.clone(),
+            store.clone(),
+        )?;
+    }
+    // finally, start exporting the leaves snapshots if enabled.
+    #[cfg(feature = "evm")]
+    if matches!(ctx.config.ipfs, Some(ref ipfs) if ipfs.enabled) {
+        start_leaves_snapshot_exporter(ctx.clone(), store)?;
+    }
     Ok(())
 }
 /// Starts the event watcher for DKG proposal handler events.
@@ -172,6 +260,8 @@ pub async fn ignite(
 /// * `node_name` - Name of the node
 /// * `chain_id` - An U256 representing the chain id of the chain
 /// * `store` -[Sled](https://sled.rs)-based database store
+/// * `bridge_registry` - The registry used for routing the signed proposals to the bridges
+#[cfg(all(feature = "evm", feature = "substrate"))]
 fn start_dkg_proposal_handler(
     ctx: &RelayerContext,
     config: &DKGProposalHandlerPalletConfig,
@@ -179,6 +269,7 @@ fn start_dkg_proposal_handler(
     node_name: String,
     chain_id: U256,
     store: Arc<Store>,
+    bridge_registry: BridgeRegistry<Store>,
 ) -> anyhow::Result<()> {
     // check first if we should start the events watcher for this contract.
     if !config.events_watcher.enabled {
@@ -192,23 +283,112 @@ fn start_dkg_proposal_handler(
         "DKG Proposal Handler events watcher for ({}) Started.",
         node_name,
     );
-    let node_name2 = node_name.clone();
     let mut shutdown_signal = ctx.shutdown_signal();
-    let webb_config = ctx.config.clone();
+    let my_ctx = ctx.clone();
     let task = async move {
-        let proposal_handler = ProposalHandlerWatcher::new(webb_config);
-        let watcher = proposal_handler.run(node_name, chain_id, client, store);
+        let proposal_handler = ProposalHandlerWatcher::new(bridge_registry);
+        let watcher = async {
+            let mut client = client;
+            loop {
+                let result =
+                    proposal_handler.run(chain_id, client, store.clone()).await;
+                match result {
+                    // restart the watcher with a fresh client, after a runtime upgrade.
+                    Err(e) if e.is::<crate::utils::RuntimeUpgraded>() => {
+                        client = my_ctx
+                            .substrate_provider::<subxt::DefaultConfig>(
+                                &node_name,
+                            )
+                            .await?;
+                    }
+                    result => break result,
+                }
+            }
+        };
         tokio::select! {
             _ = watcher => {
                 tracing::warn!(
                     "DKG Proposal Handler events watcher stopped for ({})",
-                    node_name2,
+                    node_name,
                 );
             },
             _ = shutdown_signal.recv() => {
                 tracing::trace!(
                     "Stopping DKG Proposal Handler events watcher for ({})",
-                    node_name2,
+                    node_name,
+                );
+            },
+        }
+    };
+    // kick off the watcher.
+    tokio::task::spawn(task);
+    Ok(())
+}
+/// Starts the event watcher for the proposals of the Substrate bridge pallet.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `config` - Bridge pallet configuration
+/// * `client` - Webb Protocol client
+/// * `node_name` - Name of the node
+/// * `chain_id` - An U256 representing the chain id of the chain
+/// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "substrate")]
+fn start_substrate_bridge_watcher(
+    ctx: &RelayerContext,
+    config: &BridgePalletConfig,
+    client: subxt::Client<subxt::DefaultConfig>,
+    node_name: String,
+    chain_id: U256,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    if !config.events_watcher.enabled {
+        tracing::warn!(
+            "Substrate bridge events watcher is disabled for ({}).",
+            node_name,
+        );
+        return Ok(());
+    }
+    tracing::debug!(
+        "Substrate bridge events watcher for ({}) Started.",
+        node_name,
+    );
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let my_ctx = ctx.clone();
+    let task = async move {
+        let bridge_watcher = SubstrateBridgeWatcher::new(chain_id);
+        let watcher = async {
+            let mut client = client;
+            loop {
+                let result =
+                    bridge_watcher.run(chain_id, client, store.clone()).await;
+                match result {
+                    // restart the watcher with a fresh client, after a runtime upgrade.
+                    Err(e) if e.is::<crate::utils::RuntimeUpgraded>() => {
+                        client = my_ctx
+                            .substrate_provider::<subxt::DefaultConfig>(
+                                &node_name,
+                            )
+                            .await?;
+                    }
+                    result => break result,
+                }
+            }
+        };
+        tokio::select! {
+            _ = watcher => {
+                tracing::warn!(
+                    "Substrate bridge events watcher stopped for ({})",
+                    node_name,
+                );
+            },
+            _ = shutdown_signal.recv() => {
+                tracing::trace!(
+                    "Stopping Substrate bridge events watcher for ({})",
+                    node_name,
                 );
             },
         }
@@ -217,6 +397,54 @@ fn start_dkg_proposal_handler(
     tokio::task::spawn(task);
     Ok(())
 }
+/// Clears everything cached about that contract, along with the signed proposals
+/// addressed to it, so its events watcher re-indexes it from its `deployed-at` block.
+///
+/// The contract is only cleared once while its `force-resync` flag stays set, not on
+/// every restart, the flag is armed again once it is removed.
+///
+/// # Arguments
+///
+/// * `store` -[Sled](https://sled.rs)-based database store
+/// * `chain_id` - The chain id of the chain of the contract
+/// * `config` - The common configuration of the contract
+#[cfg(feature = "evm")]
+fn force_resync(
+    store: &Store,
+    chain_id: u64,
+    config: &CommonContractConfig,
+) -> anyhow::Result<()> {
+    use crate::store::LeafCacheStore;
+    let key = (U256::from(chain_id), config.address);
+    if !config.force_resync {
+        return store.set_contract_resynced(key, false);
+    }
+    if store.is_contract_resynced(key)? {
+        tracing::debug!(
+            address = %config.address,
+            %chain_id,
+            "The forced resync of the contract is done already",
+        );
+        return Ok(());
+    }
+    let typed_chain_id =
+        crate::proposals::evm_typed_chain_id(U256::from(chain_id))?;
+    store.clear_contract(key)?;
+    let resource_id = crate::proposals::evm_resource_id(
+        config.address,
+        typed_chain_id.underlying_chain_id(),
+    );
+    let removed = remove_proposals_of_resource(store, resource_id)?;
+    store.set_contract_resynced(key, true)?;
+    tracing::warn!(
+        address = %config.address,
+        %chain_id,
+        removed_proposals = removed,
+        deployed_at = config.deployed_at,
+        "Forced a resync of the contract, it gets re-indexed from its deployment",
+    );
+    Ok(())
+}
 /// Starts the event watcher for tornado events.
 ///
 /// Returns Ok(()) if successful, or an error if not.
@@ -226,6 +454,7 @@ fn start_dkg_proposal_handler(
 /// * `ctx` - RelayContext reference that holds the configuration
 /// * `config` - Tornado contract configuration
 /// * `client` - Tornado client * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
 fn start_tornado_events_watcher(
     ctx: &RelayerContext,
     config: &TornadoContractConfig,
@@ -245,10 +474,11 @@ fn start_tornado_events_watcher(
         "Tornado events watcher for ({}) Started.",
         config.common.address,
     );
-    let watcher = TornadoLeavesWatcher.run(client, store, wrapper);
     let mut shutdown_signal = ctx.shutdown_signal();
     let contract_address = config.common.address;
     let task = async move {
+        let leaves_watcher = TornadoLeavesWatcher::default();
+        let watcher = leaves_watcher.run(client, store, wrapper);
         tokio::select! {
             _ = watcher => {
                 tracing::warn!(
@@ -276,13 +506,18 @@ fn start_tornado_events_watcher(
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
 /// * `config` - Anchor contract configuration
+/// * `chain_config` - The configuration of the chain of the anchor
 /// * `client` - DKG client
 /// * `store` -[Sled](https://sled.rs)-based database store
+/// * `bridge_registry` - The registry used for routing the signed proposals to the bridges
+#[cfg(feature = "evm")]
 async fn start_anchor_events_watcher(
     ctx: &RelayerContext,
     config: &AnchorContractConfig,
+    chain_config: &EvmChainConfig,
     client: Arc<Client>,
     store: Arc<Store>,
+    bridge_registry: BridgeRegistry<Store>,
 ) -> anyhow::Result<()> {
     if !config.events_watcher.enabled {
         tracing::warn!(
@@ -294,12 +529,15 @@ async fn start_anchor_events_watcher(
     let wrapper = AnchorContractWrapper::new(
         config.clone(),
         ctx.config.clone(), // the original config to access all networks.
+        U256::from(chain_config.chain_id),
         client.clone(),
     );
     let mut shutdown_signal = ctx.shutdown_signal();
     let contract_address = config.common.address;
     let my_ctx = ctx.clone();
     let my_config = config.clone();
+    let chain_id = chain_config.chain_id;
+    let own_bridge = signature_bridge_address(chain_config);
     let proposal_signing_backend = config.proposal_signing_backend.clone();
     let task = async move {
         tracing::debug!(
@@ -311,6 +549,7 @@ async fn start_anchor_events_watcher(
             leaves_watcher.run(client.clone(), store.clone(), wrapper.clone());
         // we need to check/match on the proposal signing backend configured for this anchor.
         match proposal_signing_backend {
+            #[cfg(feature = "substrate")]
             ProposalSigningBackendConfig::DkgNode(c) => {
                 // if it is the dkg backend, we will need to connect to that node first,
                 // and then use the DkgProposalSigningBackend to sign the proposal.
@@ -319,8 +558,58 @@ async fn start_anchor_events_watcher(
                     .await?;
                 let pair = my_ctx.substrate_wallet(&c.node).await?;
                 let backend = DkgProposalSigningBackend::new(
+                    my_ctx.clone(),
+                    c.node.clone(),
                     dkg_client,
                     PairSigner::new(pair),
+                )
+                .await?;
+                let watcher = AnchorWatcher::new(backend);
+                let anchor_watcher_task = async {
+                    register_anchor_resource(
+                        &watcher,
+                        &my_config,
+                        own_bridge,
+                        client.clone(),
+                        &wrapper,
+                    )
+                    .await;
+                    watcher.run(client, store, wrapper).await
+                };
+                tokio::select! {
+                    _ = anchor_watcher_task => {
+                        tracing::warn!(
+                            "Anchor watcher task stopped for ({})",
+                            contract_address,
+                        );
+                    },
+                    _ = anchor_leaves_watcher => {
+                        tracing::warn!(
+                            "Anchor leaves watcher stopped for ({})",
+                            contract_address,
+                        );
+                    },
+                    _ = shutdown_signal.recv() => {
+                        tracing::trace!(
+                            "Stopping Anchor watcher for ({})",
+                            contract_address,
+                        );
+                    },
+                }
+            }
+            #[cfg(feature = "substrate")]
+            ProposalSigningBackendConfig::SubstrateBridge(c) => {
+                // the proposals are voted on the bridge pallet of that node,
+                // with the account of the relayer on it.
+                let node_client = my_ctx
+                    .substrate_provider::<subxt::DefaultConfig>(&c.node)
+                    .await?;
+                let pair = my_ctx.substrate_wallet(&c.node).await?;
+                let backend = SubstrateBridgeProposalSigningBackend::new(
+                    my_ctx.clone(),
+                    c.node.clone(),
+                    node_client,
+                    PairSigner::new(pair),
                 );
                 let watcher = AnchorWatcher::new(backend);
                 let anchor_watcher_task = watcher.run(client, store, wrapper);
@@ -345,6 +634,33 @@ async fn start_anchor_events_watcher(
                     },
                 }
             }
+            #[cfg(not(feature = "substrate"))]
+            ProposalSigningBackendConfig::DkgNode(
+                DkgNodeProposalSigningBackendConfig { node },
+            )
+            | ProposalSigningBackendConfig::SubstrateBridge(
+                SubstrateBridgeProposalSigningBackendConfig { node },
+            ) => {
+                tracing::warn!(
+                    "The node ({}) handling the proposals of ({}) needs the Substrate stack, which this relayer is built without, only its leaves are watched",
+                    node,
+                    contract_address,
+                );
+                tokio::select! {
+                    _ = anchor_leaves_watcher => {
+                        tracing::warn!(
+                            "Anchor leaves watcher stopped for ({})",
+                            contract_address,
+                        );
+                    },
+                    _ = shutdown_signal.recv() => {
+                        tracing::trace!(
+                            "Stopping Anchor watcher for ({})",
+                            contract_address,
+                        );
+                    },
+                }
+            }
             ProposalSigningBackendConfig::Mocked(_) => {
                 // if it is the mocked backend, we will use the MockedProposalSigningBackend to sign the proposal.
                 // which is a bit simpler than the DkgProposalSigningBackend.
@@ -360,7 +676,7 @@ async fn start_anchor_events_watcher(
                 // then will have to go through our configruation to retrieve the correct
                 // signature bridges that are configrued on the linked chains.
                 // Note: this assumes that every network will only have one signature bridge configured for it.
-                let signature_bridges = linked_chains
+                let mut signature_bridges = linked_chains
                     .flat_map(|(chain_config, address)| {
                         // find the first signature bridge configured on that chain.
                         let maybe_signature_bridge =
@@ -407,9 +723,10 @@ async fn start_anchor_events_watcher(
                                 _ => return None,
                             };
                         // then we just create the signature bridge metadata.
-                        let chain_id = webb_proposals::TypedChainId::Evm(
-                            chain_config.chain_id as u32,
-                        );
+                        let chain_id = crate::proposals::evm_typed_chain_id(
+                            U256::from(chain_config.chain_id),
+                        )
+                        .ok()?;
                         let metadata = SignatureBridgeMetadata {
                             address: bridge_config.common.address,
                             chain_id,
@@ -418,12 +735,41 @@ async fn start_anchor_events_watcher(
                         Some((chain_id, metadata))
                     })
                     .collect::<HashMap<_, _>>();
+                // the resource registration is signed for the bridge of the chain of this anchor.
+                if let (true, Some(address)) =
+                    (my_config.register_resource, own_bridge)
+                {
+                    if let ProposalSigningBackendConfig::Mocked(v) =
+                        &my_config.proposal_signing_backend
+                    {
+                        let chain_id = crate::proposals::evm_typed_chain_id(
+                            U256::from(chain_id),
+                        )?;
+                        let metadata = SignatureBridgeMetadata {
+                            address,
+                            chain_id,
+                            private_key: v.private_key.clone(),
+                        };
+                        signature_bridges.insert(chain_id, metadata);
+                    }
+                }
                 let backend = MockedProposalSigningBackend::builder()
-                    .store(store.clone())
+                    .bridge_registry(bridge_registry)
                     .signature_bridges(signature_bridges)
+                    .key_rotations(my_ctx.key_rotations())
                     .build();
                 let watcher = AnchorWatcher::new(backend);
-                let anchor_watcher_task = watcher.run(client, store, wrapper);
+                let anchor_watcher_task = async {
+                    register_anchor_resource(
+                        &watcher,
+                        &my_config,
+                        own_bridge,
+                        client.clone(),
+                        &wrapper,
+                    )
+                    .await;
+                    watcher.run(client, store, wrapper).await
+                };
                 tokio::select! {
                     _ = anchor_watcher_task => {
                         tracing::warn!(
@@ -455,12 +801,82 @@ async fn start_anchor_events_watcher(
     Ok(())
 }
 
+/// Returns the address of the Signature Bridge configured on that chain, if any.
+#[cfg(feature = "evm")]
+fn signature_bridge_address(
+    chain_config: &EvmChainConfig,
+) -> Option<ethereum_types::Address> {
+    chain_config
+        .contracts
+        .iter()
+        .find_map(|contract| match contract {
+            Contract::SignatureBridge(c) => Some(c.common.address),
+            _ => None,
+        })
+}
+
+/// Resolves the resource of the anchor on the Signature Bridge of its chain, then proposes to
+/// register it, if `register-resource` is enabled for it.
+///
+/// It runs before the anchor watcher starts, so the resource is cached for the anchor update
+/// proposals, and the bridge could execute them. Failures are only logged, the anchor still
+/// gets watched.
+#[cfg(feature = "evm")]
+async fn register_anchor_resource<B>(
+    watcher: &AnchorWatcher<B>,
+    config: &AnchorContractConfig,
+    bridge: Option<ethereum_types::Address>,
+    client: Arc<Client>,
+    wrapper: &AnchorContractWrapper<Client>,
+) where
+    B: ProposalSigningBackend<VersionedAnchorUpdateProposal>
+        + ProposalSigningBackend<crate::proposals::ResourceIdUpdateProposal>,
+{
+    let resource =
+        match resolve_anchor_resource(client.clone(), wrapper, bridge).await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!(
+                    anchor = %config.common.address,
+                    "Failed to resolve the resource of the anchor: {}",
+                    e
+                );
+                return;
+            }
+        };
+    if !config.register_resource {
+        return;
+    }
+    let bridge = match bridge {
+        Some(v) => v,
+        None => {
+            tracing::warn!(
+                anchor = %config.common.address,
+                "No Signature Bridge configured on the chain of the anchor, \
+                its resource could not be registered",
+            );
+            return;
+        }
+    };
+    if let Err(e) = watcher.register_resource(client, &resource, bridge).await {
+        tracing::error!(
+            anchor = %config.common.address,
+            "Failed to propose the registration of the resource of the anchor: {}",
+            e
+        );
+    }
+}
+
 /// Starts the event watcher for Signature Bridge contract.
+///
+/// The bridge is registered in the `bridge_registry` while its watcher is running.
+#[cfg(feature = "evm")]
 async fn start_signature_bridge_events_watcher(
     ctx: &RelayerContext,
     config: &SignatureBridgeContractConfig,
     client: Arc<Client>,
     store: Arc<Store>,
+    bridge_registry: BridgeRegistry<Store>,
 ) -> anyhow::Result<()> {
     if !config.events_watcher.enabled {
         tracing::warn!(
@@ -473,9 +889,24 @@ async fn start_signature_bridge_events_watcher(
     let contract_address = config.common.address;
     let wrapper =
         SignatureBridgeContractWrapper::new(config.clone(), client.clone());
+    let chain_id = client.get_chainid().await?;
+    let bridge_key = BridgeKey::new(
+        contract_address,
+        crate::proposals::evm_typed_chain_id(chain_id)?,
+    );
+    // the bridge could have been paused while the relayer was offline.
+    if ctx.set_bridge_paused(bridge_key, wrapper.is_paused().await?) {
+        tracing::warn!(
+            "The bridge ({}) is paused, its commands are held until it gets unpaused",
+            contract_address,
+        );
+    }
+    // from now on, the commands sent to this bridge will be handled.
+    bridge_registry.register(bridge_key)?;
+    let bridge_contract_watcher =
+        SignatureBridgeContractWatcher::new(ctx.clone());
     let task = async move {
         tracing::debug!("Bridge watcher for ({}) Started.", contract_address);
-        let bridge_contract_watcher = SignatureBridgeContractWatcher::default();
         let events_watcher_task = EventWatcher::run(
             &bridge_contract_watcher,
             client.clone(),
@@ -487,6 +918,7 @@ async fn start_signature_bridge_events_watcher(
             client,
             store,
             wrapper,
+            bridge_registry.clone(),
         );
         tokio::select! {
             _ = events_watcher_task => {
@@ -508,6 +940,7 @@ async fn start_signature_bridge_events_watcher(
                 );
             },
         }
+        bridge_registry.unregister(bridge_key);
     };
     // kick off the watcher.
     tokio::task::spawn(task);
@@ -523,6 +956,7 @@ async fn start_signature_bridge_events_watcher(
 /// * `ctx` - RelayContext reference that holds the configuration
 /// * `chain_name` - Name of the chain
 /// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
 fn start_tx_queue(
     ctx: RelayerContext,
     chain_name: String,
@@ -552,3 +986,312 @@ fn start_tx_queue(
     tokio::task::spawn(task);
     Ok(())
 }
+/// Starts the exporter of the leaves snapshots to IPFS.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
+fn start_leaves_snapshot_exporter(
+    ctx: RelayerContext,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let exporter = LeavesSnapshotExporter::new(ctx, store);
+
+    tracing::debug!("Leaves Snapshot Exporter Started.");
+    let task = async move {
+        tokio::select! {
+            result = exporter.run() => {
+                tracing::warn!(
+                    "Leaves Snapshot Exporter task stopped: {:?}",
+                    result,
+                );
+            },
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Leaves Snapshot Exporter");
+            },
+        }
+    };
+    // kick off the exporter.
+    tokio::task::spawn(task);
+    Ok(())
+}
+
+/// Starts the garbage collector of the stale signed proposals.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
+fn start_proposals_gc(
+    ctx: RelayerContext,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let max_age = Duration::from_secs(ctx.config.proposals.max_age);
+    let interval = Duration::from_millis(ctx.config.proposals.gc_interval);
+    tracing::debug!("Proposals Garbage Collector Started.");
+    let task = async move {
+        let gc = async {
+            loop {
+                match remove_stale_proposals(store.as_ref(), max_age) {
+                    Ok(expired) => {
+                        tracing::event!(
+                            target: crate::probe::TARGET,
+                            tracing::Level::DEBUG,
+                            kind = %crate::probe::Kind::ProposalsGc,
+                            expired = %expired,
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to remove the stale proposals: {}",
+                            e
+                        );
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        };
+        tokio::select! {
+            _ = gc => {},
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Proposals Garbage Collector");
+            },
+        }
+    };
+    // kick off the garbage collector.
+    tokio::task::spawn(task);
+    Ok(())
+}
+
+/// Starts the garbage collector of the relay stats older than `store.relay-stats-retention`,
+/// checked every hour.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
+fn start_relay_stats_gc(
+    ctx: RelayerContext,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let retention = ctx.config.store.relay_stats_retention;
+    let interval = Duration::from_secs(60 * 60);
+    tracing::debug!("Relay Stats Garbage Collector Started.");
+    let task = async move {
+        let gc = async {
+            loop {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                match store.prune_relay_stats(now.saturating_sub(retention)) {
+                    Ok(removed) => {
+                        tracing::event!(
+                            target: crate::probe::TARGET,
+                            tracing::Level::DEBUG,
+                            kind = %crate::probe::Kind::RelayStatsGc,
+                            removed = %removed,
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to remove the old relay stats: {}",
+                            e
+                        );
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        };
+        tokio::select! {
+            _ = gc => {},
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Relay Stats Garbage Collector");
+            },
+        }
+    };
+    // kick off the garbage collector.
+    tokio::task::spawn(task);
+    Ok(())
+}
+
+/// Starts the election of the leader among the instances of the deployment.
+///
+/// The lease is released on shutdown, so a standby takes over right away.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `config` - The configuration of the high-availability mode
+#[cfg(feature = "ha")]
+fn start_leader_election(
+    ctx: RelayerContext,
+    config: &HaConfig,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let election = LeaderElection::new(ctx, config)?;
+    tracing::debug!("Leader Election Started.");
+    let task = async move {
+        tokio::select! {
+            _ = election.run() => {},
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Leader Election");
+                if let Err(e) = election.release().await {
+                    tracing::warn!("Failed to release the leader lease: {}", e);
+                }
+            },
+        }
+    };
+    tokio::task::spawn(task);
+    Ok(())
+}
+
+/// Starts checkpointing the roots of the anchor and tornado contracts of every enabled
+/// EVM chain, alerting when they diverge for more than one interval.
+///
+/// Every interval, the contracts are checked concurrently, each check given up if it
+/// does not finish within the interval.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `config` - The configuration of the root checkpoints
+/// * `store` -[Sled](https://sled.rs)-based database store
+#[cfg(feature = "evm")]
+fn start_root_checkpoints(
+    ctx: RelayerContext,
+    config: RootCheckpointsConfig,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let interval = Duration::from_secs(config.interval);
+    let http = reqwest::Client::new();
+    tracing::debug!("Root Checkpoints Started.");
+    let task = async move {
+        let checkpoints = async {
+            loop {
+                tokio::time::sleep(interval).await;
+                let contracts = ctx
+                    .config
+                    .evm
+                    .iter()
+                    .filter(|(_, chain_config)| chain_config.enabled)
+                    .flat_map(|(chain_name, chain_config)| {
+                        chain_config
+                            .contracts
+                            .iter()
+                            .filter(|c| {
+                                matches!(
+                                    c,
+                                    Contract::Anchor(_) | Contract::Tornado(_)
+                                )
+                            })
+                            .map(move |c| (chain_name, c))
+                    });
+                // the contracts are checked concurrently, and a hanging endpoint
+                // only gives up the checks of its own chain.
+                let checks = contracts.map(|(chain_name, contract)| {
+                    let checkpoint = verify::checkpoint_roots(
+                        &ctx,
+                        store.as_ref(),
+                        &http,
+                        &config,
+                        chain_name,
+                        contract,
+                    );
+                    async move {
+                        let result =
+                            tokio::time::timeout(interval, checkpoint).await;
+                        let e = match result {
+                            Ok(Ok(_)) => return,
+                            Ok(Err(e)) => e,
+                            Err(_) => anyhow::anyhow!("timed out"),
+                        };
+                        tracing::error!(
+                            "Failed to checkpoint the roots of {} on {}: {}",
+                            contract.common().address,
+                            chain_name,
+                            e
+                        );
+                    }
+                });
+                futures::future::join_all(checks).await;
+            }
+        };
+        tokio::select! {
+            _ = checkpoints => {},
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Root Checkpoints");
+            },
+        }
+    };
+    tokio::task::spawn(task);
+    Ok(())
+}
+
+/// Starts reporting the heartbeats to the configured external monitor.
+///
+/// Returns Ok(()) if successful, or an error if not.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `config` - The configuration of the heartbeat
+/// * `store` -[Sled](https://sled.rs)-based database store
+fn start_heartbeat(
+    ctx: RelayerContext,
+    config: HeartbeatConfig,
+    store: Arc<Store>,
+) -> anyhow::Result<()> {
+    let mut shutdown_signal = ctx.shutdown_signal();
+    let interval = Duration::from_millis(config.interval);
+    let http = reqwest::Client::new();
+    tracing::debug!("Heartbeat Started.");
+    let task = async move {
+        let heartbeat = async {
+            loop {
+                match heartbeat::beat(&http, &ctx, store.as_ref(), &config)
+                    .await
+                {
+                    Ok(sent) => {
+                        tracing::event!(
+                            target: crate::probe::TARGET,
+                            tracing::Level::DEBUG,
+                            kind = %crate::probe::Kind::Heartbeat,
+                            sent = %sent,
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to send the heartbeat: {}", e);
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        };
+        tokio::select! {
+            _ = heartbeat => {},
+            _ = shutdown_signal.recv() => {
+                tracing::trace!("Stopping Heartbeat");
+            },
+        }
+    };
+    tokio::task::spawn(task);
+    Ok(())
+}
@@ -0,0 +1,230 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+#![warn(missing_docs)]
+//! # Relayer Leaves Snapshot Module 🕸️
+//!
+//! A module for encoding the cached leaves of a merkle tree into a compact binary snapshot.
+//!
+//! ## Overview
+//!
+//! A snapshot contains all the leaves of a contract at a specific block, along with
+//! the merkle root of the tree at that block, so anyone downloading it can verify
+//! it against the on-chain root before using it.
+//!
+//! The format of the snapshot is:
+//! ```text
+//! ┌──────────┬────────────┬────────────┬─────────────┬─────────────┬──────────┬──────────┬────────────────┐
+//! │          │            │            │             │             │          │          │                │
+//! │ Magic 4B │ Version 1B │ ChainId 8B │ Contract 20B│ BlockNum 8B │ Root 32B │ Count 4B │ Leaves 32B * n │
+//! │          │            │            │             │             │          │          │                │
+//! └──────────┴────────────┴────────────┴─────────────┴─────────────┴──────────┴──────────┴────────────────┘
+//! ```
+//! All numbers are encoded in big-endian.
+//!
+//! The leaves are also served as they are cached, without the root nor the header, for the
+//! wallets to download them quickly and verify them against the on-chain root themselves:
+//! ```text
+//! ┌──────────┬─────────────┬────────────────┐
+//! │          │             │                │
+//! │ Count 4B │ BlockNum 8B │ Leaves 32B * n │
+//! │          │             │                │
+//! └──────────┴─────────────┴────────────────┘
+//! ```
+use ethereum_types as types;
+
+/// The magic bytes at the start of every snapshot.
+const MAGIC: [u8; 4] = *b"WLSS";
+/// The current version of the snapshot encoding.
+const VERSION: u8 = 1;
+
+/// A compact binary snapshot of the leaves of a contract at a given block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeavesSnapshot {
+    /// The chain id where the contract is deployed.
+    pub chain_id: u64,
+    /// The address of the contract.
+    pub contract: types::Address,
+    /// The block number where the snapshot was taken at.
+    pub block_number: u64,
+    /// The merkle root of the tree at that block.
+    pub root: types::H256,
+    /// All the leaves of the tree, ordered by their leaf index.
+    pub leaves: Vec<types::H256>,
+}
+
+impl LeavesSnapshot {
+    /// Length of the snapshot header in bytes (everything before the leaves).
+    pub const HEADER_LENGTH: usize = 4 + 1 + 8 + 20 + 8 + 32 + 4;
+
+    /// Encodes the snapshot into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(Self::HEADER_LENGTH + self.leaves.len() * 32);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.chain_id.to_be_bytes());
+        bytes.extend_from_slice(self.contract.as_bytes());
+        bytes.extend_from_slice(&self.block_number.to_be_bytes());
+        bytes.extend_from_slice(self.root.as_bytes());
+        bytes.extend_from_slice(&(self.leaves.len() as u32).to_be_bytes());
+        for leaf in &self.leaves {
+            bytes.extend_from_slice(leaf.as_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a snapshot from bytes.
+    ///
+    /// Returns an error if the bytes are not a valid snapshot.
+    ///
+    /// The relayer itself never decodes snapshots, only the tests do, to check the
+    /// encoding.
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() >= Self::HEADER_LENGTH,
+            "snapshot is too short: got {} bytes",
+            bytes.len()
+        );
+        anyhow::ensure!(bytes[0..4] == MAGIC, "invalid snapshot magic bytes");
+        anyhow::ensure!(
+            bytes[4] == VERSION,
+            "unsupported snapshot version {}",
+            bytes[4]
+        );
+        let mut u64_buf = [0u8; 8];
+        u64_buf.copy_from_slice(&bytes[5..13]);
+        let chain_id = u64::from_be_bytes(u64_buf);
+        let contract = types::Address::from_slice(&bytes[13..33]);
+        u64_buf.copy_from_slice(&bytes[33..41]);
+        let block_number = u64::from_be_bytes(u64_buf);
+        let root = types::H256::from_slice(&bytes[41..73]);
+        let mut u32_buf = [0u8; 4];
+        u32_buf.copy_from_slice(&bytes[73..77]);
+        let count = u32::from_be_bytes(u32_buf) as usize;
+        let body = &bytes[Self::HEADER_LENGTH..];
+        anyhow::ensure!(
+            body.len() == count * 32,
+            "snapshot says it has {} leaves but got {} bytes of leaves",
+            count,
+            body.len()
+        );
+        let leaves =
+            body.chunks_exact(32).map(types::H256::from_slice).collect();
+        Ok(Self {
+            chain_id,
+            contract,
+            block_number,
+            root,
+            leaves,
+        })
+    }
+}
+
+/// The cached leaves of a contract, as they are served to the wallets, see the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactLeaves {
+    /// The last block number where the leaves were queried at.
+    pub block_number: u64,
+    /// All the leaves of the tree, ordered by their leaf index.
+    pub leaves: Vec<types::H256>,
+}
+
+impl CompactLeaves {
+    /// Length of the count and the block number in bytes (everything before the leaves).
+    pub const HEADER_LENGTH: usize = 4 + 8;
+
+    /// Encodes the leaves into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(Self::HEADER_LENGTH + self.leaves.len() * 32);
+        bytes.extend_from_slice(&(self.leaves.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.block_number.to_be_bytes());
+        for leaf in &self.leaves {
+            bytes.extend_from_slice(leaf.as_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes the leaves from bytes.
+    ///
+    /// Returns an error if the count does not match the leaves.
+    ///
+    /// The relayer itself never decodes them, only the tests do, to check the
+    /// encoding.
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() >= Self::HEADER_LENGTH,
+            "leaves are too short: got {} bytes",
+            bytes.len()
+        );
+        let mut u32_buf = [0u8; 4];
+        u32_buf.copy_from_slice(&bytes[0..4]);
+        let count = u32::from_be_bytes(u32_buf) as usize;
+        let mut u64_buf = [0u8; 8];
+        u64_buf.copy_from_slice(&bytes[4..12]);
+        let block_number = u64::from_be_bytes(u64_buf);
+        let body = &bytes[Self::HEADER_LENGTH..];
+        anyhow::ensure!(
+            body.len() == count * 32,
+            "got {} leaves but {} bytes of leaves",
+            count,
+            body.len()
+        );
+        let leaves =
+            body.chunks_exact(32).map(types::H256::from_slice).collect();
+        Ok(Self {
+            block_number,
+            leaves,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let snapshot = LeavesSnapshot {
+            chain_id: 5,
+            contract: types::Address::random(),
+            block_number: 6_543_210,
+            root: types::H256::random(),
+            leaves: (0..10).map(|_| types::H256::random()).collect(),
+        };
+        let bytes = snapshot.to_bytes();
+        assert_eq!(bytes.len(), LeavesSnapshot::HEADER_LENGTH + 10 * 32);
+        let decoded = LeavesSnapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, snapshot);
+        // truncated snapshots should be rejected.
+        assert!(LeavesSnapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn compact_leaves_roundtrip() {
+        let compact = CompactLeaves {
+            block_number: 6_543_210,
+            leaves: (0..10).map(|_| types::H256::random()).collect(),
+        };
+        let bytes = compact.to_bytes();
+        assert_eq!(bytes.len(), CompactLeaves::HEADER_LENGTH + 10 * 32);
+        assert_eq!(bytes[0..4], 10u32.to_be_bytes());
+        let decoded = CompactLeaves::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, compact);
+        assert!(CompactLeaves::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,888 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use ethereum_types as types;
+use parking_lot::Mutex;
+
+use crate::config::PrivateKey;
+use crate::proposals::TypedChainId;
+
+use super::codec::Record;
+#[cfg(feature = "ha")]
+use super::shared::SharedQueues;
+use super::sled::{SledQueueKey, SledStore};
+use super::{
+    AccountingEntry, AccountingStore, ApiUsage, ApiUsageStore, AuditEntry,
+    AuditLogStore, AuditRecord, BridgeKey, BridgeRegistryStore, Earnings,
+    EarningsStore, GovernorStore, HistoryStore, HistoryStoreKey,
+    KeyRotationStore, LeafCacheStore, LeafWatch, LeafWatchStore, LeavesIter,
+    LeavesSnapshotInfo, LeavesSnapshotStore, NeighborRootStore, NullifierStore,
+    ProposalNonceStore, ProposalStatus, ProposalStore, QueueStore, RelayStats,
+    RelayStatsStore, RelayedTx, RelayedTxStore, RootCheckpoint,
+    RootCheckpointStore, SpentNullifier, StatsResolution, SubstrateProposal,
+    SubstrateProposalStore,
+};
+
+/// The store used by the relayer, a [`SledStore`] behind the read cache.
+pub type RelayerStore = CachedStore<SledStore>;
+
+/// A least recently used map, holding at most `capacity` entries.
+#[derive(Debug)]
+struct Lru<V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<HistoryStoreKey, (V, u64)>,
+    // the keys by their last use, the oldest first.
+    uses: BTreeMap<u64, HistoryStoreKey>,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            uses: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &HistoryStoreKey) -> Option<V> {
+        let (value, used_at) = self.entries.get_mut(key)?;
+        self.uses.remove(used_at);
+        self.tick += 1;
+        *used_at = self.tick;
+        self.uses.insert(self.tick, key.clone());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: HistoryStoreKey, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            match self.uses.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        self.tick += 1;
+        self.uses.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn remove(&mut self, key: &HistoryStoreKey) {
+        if let Some((_, used_at)) = self.entries.remove(key) {
+            self.uses.remove(&used_at);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entries {
+    leaves: Lru<Arc<[types::H256]>>,
+    last_block_numbers: Lru<types::U64>,
+    last_deposit_block_numbers: Lru<types::U64>,
+    /// Bumped on every write, so a value read from the inner store before a write is
+    /// not cached after it.
+    generation: u64,
+}
+
+/// CachedStore keeps the hot entries of a store (the leaves, and the last block numbers)
+/// in memory, so polling them does not hit the disk every time.
+///
+/// Every write goes to the inner store first, then updates or invalidates the cached entry.
+/// The cache is shared between the clones of the store, so all the writers must go through
+/// (a clone of) the same `CachedStore`.
+#[derive(Clone)]
+pub struct CachedStore<S> {
+    inner: S,
+    entries: Arc<Mutex<Entries>>,
+    /// The transaction queues shared by the instances of a deployment, which replace
+    /// the ones of the inner store, in the high-availability mode.
+    #[cfg(feature = "ha")]
+    shared_queues: Option<SharedQueues>,
+}
+
+impl<S> std::fmt::Debug for CachedStore<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedStore").finish()
+    }
+}
+
+impl<S> CachedStore<S> {
+    /// Puts the store behind a cache of `capacity` entries, for each kind of entry.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        let entries = Entries {
+            leaves: Lru::new(capacity),
+            last_block_numbers: Lru::new(capacity),
+            last_deposit_block_numbers: Lru::new(capacity),
+            generation: 0,
+        };
+        Self {
+            inner,
+            entries: Arc::new(Mutex::new(entries)),
+            #[cfg(feature = "ha")]
+            shared_queues: None,
+        }
+    }
+
+    /// Uses the shared queues for the transaction queues, instead of the ones of the
+    /// inner store.
+    #[cfg(feature = "ha")]
+    pub fn with_shared_queues(mut self, shared_queues: SharedQueues) -> Self {
+        self.shared_queues = Some(shared_queues);
+        self
+    }
+
+    /// Returns the shared queues, if the queue of that key is shared.
+    #[cfg(feature = "ha")]
+    fn shared_queue(&self, key: &SledQueueKey) -> Option<&SharedQueues> {
+        self.shared_queues
+            .as_ref()
+            .filter(|_| SharedQueues::shares(key))
+    }
+
+    /// Caches a value read from the inner store, unless the entries got written to since
+    /// `generation`, then the value could be stale already.
+    fn fill(&self, generation: u64, fill: impl FnOnce(&mut Entries)) {
+        let mut entries = self.entries.lock();
+        if entries.generation == generation {
+            fill(&mut entries);
+        }
+    }
+
+    /// Runs `write` on the entries, once the inner store got written to.
+    fn write(&self, write: impl FnOnce(&mut Entries)) {
+        let mut entries = self.entries.lock();
+        entries.generation += 1;
+        write(&mut entries);
+    }
+}
+
+impl<S: HistoryStore> HistoryStore for CachedStore<S> {
+    fn set_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let key = key.into();
+        let old = self
+            .inner
+            .set_last_block_number(key.clone(), block_number)?;
+        self.write(|entries| {
+            entries.last_block_numbers.insert(key, block_number)
+        });
+        Ok(old)
+    }
+
+    fn get_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        default_block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let key = key.into();
+        let generation = {
+            let mut entries = self.entries.lock();
+            if let Some(v) = entries.last_block_numbers.get(&key) {
+                return Ok(v);
+            }
+            entries.generation
+        };
+        let block_number = self
+            .inner
+            .get_last_block_number(key.clone(), default_block_number)?;
+        // the default is not cached, it could differ between the callers.
+        if block_number != default_block_number {
+            self.fill(generation, |entries| {
+                entries.last_block_numbers.insert(key, block_number)
+            });
+        }
+        Ok(block_number)
+    }
+
+    fn set_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+        block_number: types::U64,
+    ) -> anyhow::Result<Option<types::U64>> {
+        self.inner
+            .set_last_event_block_number(key, event, block_number)
+    }
+
+    fn get_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+    ) -> anyhow::Result<Option<types::U64>> {
+        self.inner.get_last_event_block_number(key, event)
+    }
+
+    fn set_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+    ) -> anyhow::Result<()> {
+        self.inner.set_synced_from(key, from)
+    }
+
+    fn get_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::U64>> {
+        self.inner.get_synced_from(key)
+    }
+}
+
+impl<S> LeafCacheStore for CachedStore<S>
+where
+    S: LeafCacheStore<Output = Vec<types::H256>>,
+{
+    type Output = Vec<types::H256>;
+
+    fn get_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Self::Output> {
+        let key = key.into();
+        let generation = {
+            let mut entries = self.entries.lock();
+            if let Some(leaves) = entries.leaves.get(&key) {
+                return Ok(leaves.to_vec());
+            }
+            entries.generation
+        };
+        let leaves = self.inner.get_leaves(key.clone())?;
+        self.fill(generation, |entries| {
+            entries.leaves.insert(key, Arc::from(leaves.as_slice()))
+        });
+        Ok(leaves)
+    }
+
+    fn insert_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        leaves: &[(u32, types::H256)],
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+        let result = self.inner.insert_leaves(key.clone(), leaves);
+        // invalidated even if the write failed, as it could be partially applied.
+        self.write(|entries| entries.leaves.remove(&key));
+        result
+    }
+
+    fn get_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        self.inner.get_leaves_with_index(key)
+    }
+
+    fn iter_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<LeavesIter> {
+        self.inner.iter_leaves_with_index(key)
+    }
+
+    fn get_leaf_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<Option<u32>> {
+        self.inner.get_leaf_index(key, commitment)
+    }
+
+    fn get_leaves_count<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<usize> {
+        let key = key.into();
+        if let Some(leaves) = self.entries.lock().leaves.get(&key) {
+            return Ok(leaves.len());
+        }
+        self.inner.get_leaves_count(key)
+    }
+
+    fn roll_back_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from_index: u32,
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+        let result = self.inner.roll_back_leaves(key.clone(), from_index);
+        // invalidated even if the roll back failed, as it could be partially applied.
+        self.write(|entries| {
+            entries.leaves.remove(&key);
+            entries.last_block_numbers.remove(&key);
+            entries.last_deposit_block_numbers.remove(&key);
+        });
+        result
+    }
+
+    fn clear_contract<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+        let result = self.inner.clear_contract(key.clone());
+        // invalidated even if the clearing failed, as it could be partially applied.
+        self.write(|entries| {
+            entries.leaves.remove(&key);
+            entries.last_block_numbers.remove(&key);
+            entries.last_deposit_block_numbers.remove(&key);
+        });
+        result
+    }
+
+    fn is_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<bool> {
+        self.inner.is_contract_resynced(key)
+    }
+
+    fn set_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        resynced: bool,
+    ) -> anyhow::Result<()> {
+        self.inner.set_contract_resynced(key, resynced)
+    }
+
+    fn insert_leaves_block_numbers<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_numbers: &[(u32, types::U64)],
+    ) -> anyhow::Result<()> {
+        self.inner.insert_leaves_block_numbers(key, block_numbers)
+    }
+
+    fn get_leaves_in_block_range<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+        to: types::U64,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        self.inner.get_leaves_in_block_range(key, from, to)
+    }
+
+    fn get_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<types::U64> {
+        let key = key.into();
+        let generation = {
+            let mut entries = self.entries.lock();
+            if let Some(v) = entries.last_deposit_block_numbers.get(&key) {
+                return Ok(v);
+            }
+            entries.generation
+        };
+        let block_number =
+            self.inner.get_last_deposit_block_number(key.clone())?;
+        self.fill(generation, |entries| {
+            entries.last_deposit_block_numbers.insert(key, block_number)
+        });
+        Ok(block_number)
+    }
+
+    fn insert_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let key = key.into();
+        let old = self
+            .inner
+            .insert_last_deposit_block_number(key.clone(), block_number);
+        self.write(|entries| entries.last_deposit_block_numbers.remove(&key));
+        old
+    }
+}
+
+impl<S> LeafWatchStore for CachedStore<S>
+where
+    S: LeafWatchStore + LeafCacheStore<Output = Vec<types::H256>>,
+{
+    fn watch_leaf<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<LeafWatch> {
+        self.inner.watch_leaf(key, commitment)
+    }
+}
+
+impl<S: LeavesSnapshotStore> LeavesSnapshotStore for CachedStore<S> {
+    fn get_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<LeavesSnapshotInfo>> {
+        self.inner.get_latest_leaves_snapshot(key)
+    }
+
+    fn set_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        info: LeavesSnapshotInfo,
+    ) -> anyhow::Result<()> {
+        self.inner.set_latest_leaves_snapshot(key, info)
+    }
+}
+
+impl<S: RelayedTxStore> RelayedTxStore for CachedStore<S> {
+    fn insert_relayed_tx(&self, tx: RelayedTx) -> anyhow::Result<()> {
+        self.inner.insert_relayed_tx(tx)
+    }
+
+    fn get_relayed_tx(
+        &self,
+        chain_id: TypedChainId,
+        tx_hash: types::H256,
+    ) -> anyhow::Result<Option<RelayedTx>> {
+        self.inner.get_relayed_tx(chain_id, tx_hash)
+    }
+}
+
+impl<S: EarningsStore> EarningsStore for CachedStore<S> {
+    fn record_earning(
+        &self,
+        chain_id: types::U256,
+        token: types::Address,
+        identity: Option<&str>,
+        fee: types::U256,
+    ) -> anyhow::Result<()> {
+        self.inner.record_earning(chain_id, token, identity, fee)
+    }
+
+    fn earnings(&self) -> anyhow::Result<Vec<Earnings>> {
+        self.inner.earnings()
+    }
+}
+
+impl<S: AccountingStore> AccountingStore for CachedStore<S> {
+    fn record_relay_cost(
+        &self,
+        chain_id: types::U256,
+        contract: types::Address,
+        token: types::Address,
+        identity: Option<&str>,
+        timestamp: u64,
+        gas_used: types::U256,
+        gas_spent: types::U256,
+        fee: types::U256,
+    ) -> anyhow::Result<()> {
+        self.inner.record_relay_cost(
+            chain_id, contract, token, identity, timestamp, gas_used,
+            gas_spent, fee,
+        )
+    }
+
+    fn accounting(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<AccountingEntry>> {
+        self.inner.accounting(from, to)
+    }
+}
+
+impl<S: RelayStatsStore> RelayStatsStore for CachedStore<S> {
+    fn record_relay<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        timestamp: u64,
+        reverted: bool,
+    ) -> anyhow::Result<()> {
+        self.inner.record_relay(key, timestamp, reverted)
+    }
+
+    fn relay_stats(
+        &self,
+        resolution: StatsResolution,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<RelayStats>> {
+        self.inner.relay_stats(resolution, from, to)
+    }
+
+    fn prune_relay_stats(&self, before: u64) -> anyhow::Result<usize> {
+        self.inner.prune_relay_stats(before)
+    }
+}
+
+impl<S: NullifierStore> NullifierStore for CachedStore<S> {
+    fn insert_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+        spent: SpentNullifier,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .insert_spent_nullifier(key, nullifier_hash, spent)
+    }
+
+    fn get_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<Option<SpentNullifier>> {
+        self.inner.get_spent_nullifier(key, nullifier_hash)
+    }
+
+    fn is_nullifier_spent<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<bool> {
+        self.inner.is_nullifier_spent(key, nullifier_hash)
+    }
+}
+
+impl<S: GovernorStore> GovernorStore for CachedStore<S> {
+    fn set_governor<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        governor: types::Address,
+        block_number: u64,
+    ) -> anyhow::Result<bool> {
+        self.inner.set_governor(key, governor, block_number)
+    }
+
+    fn get_governor<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::Address>> {
+        self.inner.get_governor(key)
+    }
+}
+
+impl<S: KeyRotationStore> KeyRotationStore for CachedStore<S> {
+    fn insert_key_rotation(
+        &self,
+        chain_id: u64,
+        revoked: types::Address,
+        key: &PrivateKey,
+    ) -> anyhow::Result<()> {
+        self.inner.insert_key_rotation(chain_id, revoked, key)
+    }
+
+    fn key_rotations(
+        &self,
+    ) -> anyhow::Result<Vec<(u64, types::Address, PrivateKey)>> {
+        self.inner.key_rotations()
+    }
+}
+
+impl<S: NeighborRootStore> NeighborRootStore for CachedStore<S> {
+    fn insert_neighbor_root<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        neighbor_chain_id: types::U256,
+        root: types::H256,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .insert_neighbor_root(key, neighbor_chain_id, root)
+    }
+
+    fn neighbor_roots<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(types::U256, Vec<types::H256>)>> {
+        self.inner.neighbor_roots(key)
+    }
+}
+
+impl<S: RootCheckpointStore> RootCheckpointStore for CachedStore<S> {
+    fn insert_root_checkpoint<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        checkpoint: RootCheckpoint,
+    ) -> anyhow::Result<()> {
+        self.inner.insert_root_checkpoint(key, checkpoint)
+    }
+
+    fn root_checkpoints<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<RootCheckpoint>> {
+        self.inner.root_checkpoints(key)
+    }
+}
+
+impl<S: ApiUsageStore> ApiUsageStore for CachedStore<S> {
+    fn api_usage(&self, api_key: &str, day: u64) -> anyhow::Result<ApiUsage> {
+        self.inner.api_usage(api_key, day)
+    }
+
+    fn add_api_usage<F: Fn(&ApiUsage) -> bool>(
+        &self,
+        api_key: &str,
+        day: u64,
+        usage: ApiUsage,
+        admit: F,
+    ) -> anyhow::Result<(ApiUsage, bool)> {
+        self.inner.add_api_usage(api_key, day, usage, admit)
+    }
+}
+
+impl<S: AuditLogStore> AuditLogStore for CachedStore<S> {
+    fn append_audit_entry(
+        &self,
+        entry: AuditEntry,
+    ) -> anyhow::Result<AuditRecord> {
+        self.inner.append_audit_entry(entry)
+    }
+
+    fn audit_records(
+        &self,
+        from: u64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        self.inner.audit_records(from, limit)
+    }
+}
+
+impl<S: BridgeRegistryStore> BridgeRegistryStore for CachedStore<S> {
+    fn register_bridge(&self, key: BridgeKey) -> anyhow::Result<()> {
+        self.inner.register_bridge(key)
+    }
+
+    fn registered_bridges(&self) -> anyhow::Result<Vec<BridgeKey>> {
+        self.inner.registered_bridges()
+    }
+
+    fn unregister_bridge(&self, key: BridgeKey) -> anyhow::Result<()> {
+        self.inner.unregister_bridge(key)
+    }
+}
+
+impl<S: ProposalNonceStore> ProposalNonceStore for CachedStore<S> {
+    fn get_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+    ) -> anyhow::Result<Option<u32>> {
+        self.inner
+            .get_last_executed_nonce(resource_id, origin_chain)
+    }
+
+    fn set_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+        nonce: u32,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .set_last_executed_nonce(resource_id, origin_chain, nonce)
+    }
+}
+
+impl<S: ProposalStore> ProposalStore for CachedStore<S> {
+    type Proposal = S::Proposal;
+
+    fn insert_proposal(&self, proposal: Self::Proposal) -> anyhow::Result<()> {
+        self.inner.insert_proposal(proposal)
+    }
+
+    fn remove_proposal(
+        &self,
+        data_hash: &[u8],
+    ) -> anyhow::Result<Option<Self::Proposal>> {
+        self.inner.remove_proposal(data_hash)
+    }
+
+    fn proposals(&self) -> anyhow::Result<Vec<Self::Proposal>> {
+        self.inner.proposals()
+    }
+
+    fn proposals_by_nonce(
+        &self,
+        resource_id: crate::proposals::ResourceId,
+        nonce: u32,
+    ) -> anyhow::Result<Vec<Self::Proposal>> {
+        self.inner.proposals_by_nonce(resource_id, nonce)
+    }
+
+    fn set_proposal_status(
+        &self,
+        data_hash: &[u8],
+        status: ProposalStatus,
+    ) -> anyhow::Result<()> {
+        self.inner.set_proposal_status(data_hash, status)
+    }
+
+    fn proposal_status(
+        &self,
+        data_hash: &[u8],
+    ) -> anyhow::Result<Option<ProposalStatus>> {
+        self.inner.proposal_status(data_hash)
+    }
+
+    fn proposal_statuses(
+        &self,
+    ) -> anyhow::Result<Vec<(Vec<u8>, ProposalStatus)>> {
+        self.inner.proposal_statuses()
+    }
+
+    fn remove_proposal_status(&self, data_hash: &[u8]) -> anyhow::Result<()> {
+        self.inner.remove_proposal_status(data_hash)
+    }
+}
+
+impl<S: SubstrateProposalStore> SubstrateProposalStore for CachedStore<S> {
+    fn get_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>> {
+        self.inner
+            .get_substrate_proposal(chain_id, src_chain_id, deposit_nonce)
+    }
+
+    fn insert_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        proposal: SubstrateProposal,
+    ) -> anyhow::Result<()> {
+        self.inner.insert_substrate_proposal(chain_id, proposal)
+    }
+
+    fn remove_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>> {
+        self.inner.remove_substrate_proposal(
+            chain_id,
+            src_chain_id,
+            deposit_nonce,
+        )
+    }
+}
+
+impl<S, T> QueueStore<T> for CachedStore<S>
+where
+    S: QueueStore<T, Key = SledQueueKey>,
+    T: Record + Clone,
+{
+    type Key = SledQueueKey;
+
+    fn enqueue_item(&self, key: Self::Key, item: T) -> anyhow::Result<()> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return shared.enqueue_item(key, item);
+        }
+        self.inner.enqueue_item(key, item)
+    }
+
+    fn dequeue_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return shared.dequeue_item(key);
+        }
+        self.inner.dequeue_item(key)
+    }
+
+    fn peek_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return shared.peek_item(key);
+        }
+        self.inner.peek_item(key)
+    }
+
+    fn has_item(&self, key: Self::Key) -> anyhow::Result<bool> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return QueueStore::<T>::has_item(shared, key);
+        }
+        self.inner.has_item(key)
+    }
+
+    fn remove_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return shared.remove_item(key);
+        }
+        self.inner.remove_item(key)
+    }
+
+    fn count_items(&self, key: Self::Key) -> anyhow::Result<usize> {
+        #[cfg(feature = "ha")]
+        if let Some(shared) = self.shared_queue(&key) {
+            return QueueStore::<T>::count_items(shared, key);
+        }
+        self.inner.count_items(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_leaves_are_invalidated_on_write() {
+        let store = CachedStore::new(SledStore::temporary().unwrap(), 1);
+        let key = (types::U256::one(), types::Address::random());
+        let other = (types::U256::one(), types::Address::random());
+        store
+            .insert_leaves(key, &[(0, types::H256::random())])
+            .unwrap();
+        assert_eq!(store.get_leaves(key).unwrap().len(), 1);
+        store
+            .insert_leaves(key, &[(1, types::H256::random())])
+            .unwrap();
+        assert_eq!(store.get_leaves(key).unwrap().len(), 2);
+        assert_eq!(store.get_leaves_count(key).unwrap(), 2);
+        // caching the other contract evicts the first one.
+        store.get_leaves(other).unwrap();
+        let entries = store.entries.lock();
+        assert!(entries.leaves.entries.contains_key(&other.into()));
+        assert!(!entries.leaves.entries.contains_key(&key.into()));
+    }
+
+    #[test]
+    fn stale_leaves_are_not_cached_after_a_write() {
+        let store = CachedStore::new(SledStore::temporary().unwrap(), 1);
+        let key = (types::U256::one(), types::Address::random());
+        // a reader misses the cache, and reads the leaves of the inner store.
+        let generation = store.entries.lock().generation;
+        let stale = store.inner.get_leaves(key).unwrap();
+        // then a writer inserts a leaf, before the reader fills the cache.
+        store
+            .insert_leaves(key, &[(0, types::H256::random())])
+            .unwrap();
+        store.fill(generation, |entries| {
+            entries
+                .leaves
+                .insert(key.into(), Arc::from(stale.as_slice()))
+        });
+        assert!(!store
+            .entries
+            .lock()
+            .leaves
+            .entries
+            .contains_key(&key.into()));
+        assert_eq!(store.get_leaves(key).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+#[cfg(feature = "evm")]
+use webb::evm::ethers::types::transaction::eip2718::TypedTransaction;
+
+use super::{BridgeCommand, SignedProposal, SubstrateProposal};
+
+/// The tag byte of the values encoded in JSON.
+const JSON_TAG: u8 = 0x01;
+/// The tag byte of the values encoded with bincode.
+const BINCODE_TAG: u8 = 0x02;
+
+/// The format of an encoded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Self-describing, needed by the types relying on `deserialize_any`
+    /// (like internally tagged or untagged enums).
+    Json,
+    /// Compact binary format.
+    Bincode,
+}
+
+/// A value that could be saved in the stores.
+pub trait Record: Serialize + DeserializeOwned {
+    /// The format used for encoding the values of that type.
+    const ENCODING: Encoding = Encoding::Bincode;
+}
+
+// the transactions are internally tagged by their type, bincode could not decode them.
+#[cfg(feature = "evm")]
+impl Record for TypedTransaction {
+    const ENCODING: Encoding = Encoding::Json;
+}
+
+impl Record for BridgeCommand {}
+
+impl Record for SignedProposal {}
+
+impl Record for SubstrateProposal {}
+
+/// Encodes the value, prefixed with the tag byte of its format.
+pub fn encode<T: Record>(value: &T) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match T::ENCODING {
+        Encoding::Json => {
+            bytes.push(JSON_TAG);
+            serde_json::to_writer(&mut bytes, value)?;
+        }
+        Encoding::Bincode => {
+            bytes.push(BINCODE_TAG);
+            bincode::serialize_into(&mut bytes, value)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes a value encoded with [`encode`].
+///
+/// Values without a tag byte were saved as plain JSON, before the tags got introduced,
+/// a JSON document never starts with one of the tags.
+pub fn decode<T: Record>(bytes: &[u8]) -> anyhow::Result<T> {
+    match bytes.split_first() {
+        Some((&JSON_TAG, rest)) => Ok(serde_json::from_slice(rest)?),
+        Some((&BINCODE_TAG, rest)) => Ok(bincode::deserialize(rest)?),
+        _ => Ok(serde_json::from_slice(bytes)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types as types;
+
+    #[test]
+    fn values_are_decoded_from_every_format() {
+        let proposal = SignedProposal {
+            chain_id: types::U256::from(4),
+            data: vec![1; 104],
+            signature: vec![2; 65],
+            enqueued_at: 1_650_000_000,
+        };
+        let json = serde_json::to_vec(&proposal).unwrap();
+        let tagged = encode(&proposal).unwrap();
+        assert_eq!(tagged[0], BINCODE_TAG);
+        assert!(tagged.len() < json.len());
+        assert_eq!(decode::<SignedProposal>(&tagged).unwrap(), proposal);
+        // values saved before the tags.
+        assert_eq!(decode::<SignedProposal>(&json).unwrap(), proposal);
+    }
+
+    #[cfg(feature = "evm")]
+    #[test]
+    fn transactions_are_encoded_as_json() {
+        use webb::evm::ethers::types::TransactionRequest;
+        let tx = TypedTransaction::Legacy(
+            TransactionRequest::new().to(types::Address::random()),
+        );
+        let tagged = encode(&tx).unwrap();
+        assert_eq!(tagged[0], JSON_TAG);
+        assert_eq!(decode::<TypedTransaction>(&tagged).unwrap(), tx);
+    }
+}
@@ -12,21 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::sync::Arc;
 
+use ethereum_types as types;
 use parking_lot::RwLock;
-use webb::evm::ethers::types;
 
-use super::{HistoryStore, HistoryStoreKey, LeafCacheStore};
+use super::{
+    HistoryStore, HistoryStoreKey, LeafCacheStore, LeafConflict,
+    MissingLeafBlocks,
+};
 
 type MemStore = HashMap<HistoryStoreKey, Vec<(u32, types::H256)>>;
+type BlocksStore = HashMap<HistoryStoreKey, HashMap<u32, types::U64>>;
 /// InMemoryStore is a store that stores the history of events in memory.
 #[derive(Clone, Default)]
 pub struct InMemoryStore {
     store: Arc<RwLock<MemStore>>,
+    leaves_blocks: Arc<RwLock<BlocksStore>>,
     last_block_numbers: Arc<RwLock<HashMap<HistoryStoreKey, types::U64>>>,
+    last_event_block_numbers:
+        Arc<RwLock<HashMap<(HistoryStoreKey, String), types::U64>>>,
+    synced_from: Arc<RwLock<HashMap<HistoryStoreKey, types::U64>>>,
+    resynced: Arc<RwLock<HashSet<HistoryStoreKey>>>,
 }
 
 impl std::fmt::Debug for InMemoryStore {
@@ -62,6 +71,45 @@ impl HistoryStore for InMemoryStore {
         *val = block_number;
         Ok(old)
     }
+
+    #[tracing::instrument(skip(self))]
+    fn set_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+        block_number: types::U64,
+    ) -> anyhow::Result<Option<types::U64>> {
+        let mut guard = self.last_event_block_numbers.write();
+        Ok(guard.insert((key.into(), event.to_owned()), block_number))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+    ) -> anyhow::Result<Option<types::U64>> {
+        let guard = self.last_event_block_numbers.read();
+        Ok(guard.get(&(key.into(), event.to_owned())).cloned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+    ) -> anyhow::Result<()> {
+        self.synced_from.write().insert(key.into(), from);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::U64>> {
+        Ok(self.synced_from.read().get(&key.into()).cloned())
+    }
 }
 
 impl LeafCacheStore for InMemoryStore {
@@ -89,14 +137,186 @@ impl LeafCacheStore for InMemoryStore {
         key: K,
         leaves: &[(u32, types::H256)],
     ) -> anyhow::Result<()> {
+        let key = key.into();
         let mut guard = self.store.write();
+        let cached = guard.entry(key.clone()).or_default();
+        let mut new_leaves = Vec::with_capacity(leaves.len());
+        for (index, leaf) in leaves {
+            match cached.iter().find(|(i, _)| i == index) {
+                Some((_, v)) if v == leaf => {}
+                Some((_, v)) => {
+                    let conflict = LeafConflict {
+                        key,
+                        index: *index,
+                        cached: *v,
+                        leaf: *leaf,
+                    };
+                    conflict.report();
+                    return Err(conflict.into());
+                }
+                None => new_leaves.push((*index, *leaf)),
+            }
+        }
+        cached.extend(new_leaves);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        let guard = self.store.read();
+        let mut leaves = guard.get(&key.into()).cloned().unwrap_or_default();
+        leaves.sort_by_key(|(index, _)| *index);
+        Ok(leaves)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaf_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<Option<u32>> {
+        let guard = self.store.read();
+        let index = guard.get(&key.into()).and_then(|leaves| {
+            leaves
+                .iter()
+                .find(|(_, leaf)| *leaf == commitment)
+                .map(|(index, _)| *index)
+        });
+        Ok(index)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_count<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<usize> {
+        let guard = self.store.read();
+        Ok(guard.get(&key.into()).map(Vec::len).unwrap_or_default())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn roll_back_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from_index: u32,
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+        let block_number = self
+            .leaves_blocks
+            .read()
+            .get(&key)
+            .and_then(|blocks| blocks.get(&from_index).copied());
+        let block_number = match block_number {
+            Some(v) => v,
+            None => return self.clear_contract(key),
+        };
+        if let Some(leaves) = self.store.write().get_mut(&key) {
+            leaves.retain(|(index, _)| *index < from_index);
+        }
+        if let Some(blocks) = self.leaves_blocks.write().get_mut(&key) {
+            blocks.retain(|index, _| *index < from_index);
+        }
+        // the block of the leaf is handled again.
+        let before = block_number.saturating_sub(types::U64::one());
+        if let Some(v) = self.last_block_numbers.write().get_mut(&key) {
+            *v = (*v).min(before);
+        }
+        for ((k, _), v) in self.last_event_block_numbers.write().iter_mut() {
+            if *k == key {
+                *v = (*v).min(before);
+            }
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn clear_contract<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+        self.store.write().remove(&key);
+        self.leaves_blocks.write().remove(&key);
+        self.last_block_numbers.write().remove(&key);
+        self.synced_from.write().remove(&key);
+        self.last_event_block_numbers
+            .write()
+            .retain(|(k, _), _| *k != key);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn is_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<bool> {
+        Ok(self.resynced.read().contains(&key.into()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        resynced: bool,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.resynced.write();
+        if resynced {
+            guard.insert(key.into());
+        } else {
+            guard.remove(&key.into());
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, block_numbers))]
+    fn insert_leaves_block_numbers<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_numbers: &[(u32, types::U64)],
+    ) -> anyhow::Result<()> {
+        let mut guard = self.leaves_blocks.write();
         guard
             .entry(key.into())
-            .and_modify(|v| v.extend_from_slice(leaves))
-            .or_insert_with(|| leaves.to_vec());
+            .or_default()
+            .extend(block_numbers.iter().copied());
         Ok(())
     }
 
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_in_block_range<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+        to: types::U64,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        let key = key.into();
+        let leaves_count = self.get_leaves_count(key.clone())?;
+        let blocks = self.leaves_blocks.read();
+        let recorded = blocks.get(&key).map(HashMap::len).unwrap_or_default();
+        if recorded < leaves_count {
+            return Err(MissingLeafBlocks {
+                key,
+                missing: leaves_count - recorded,
+            }
+            .into());
+        }
+        let blocks = match blocks.get(&key) {
+            Some(v) => v,
+            None => return Ok(Vec::new()),
+        };
+        let leaves = self
+            .get_leaves_with_index(key)?
+            .into_iter()
+            .filter(|(index, _)| {
+                matches!(blocks.get(index), Some(b) if *b >= from && *b <= to)
+            })
+            .collect();
+        Ok(leaves)
+    }
+
     #[tracing::instrument(skip(self))]
     fn get_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
         &self,
@@ -21,18 +21,32 @@
 //! The relayer store module stores the history of events. Manages the setting
 //! and retrieving operations of events.
 //!
+use std::convert::TryFrom;
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
 
+use ethereum_types as types;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use webb::evm::ethers::types;
+use utoipa::ToSchema;
+
+use crate::config::PrivateKey;
+use crate::metrics::{self, Labels, Metric};
+use crate::proposals::TypedChainId;
+
+/// A module for caching the hot entries of a store in memory.
+pub mod cache;
+/// A module for encoding the values of the records saved in the stores.
+pub mod codec;
 /// A module for managing in-memory storage of the relayer.
 pub mod mem;
+/// A module for the queues shared by the instances of a deployment, in the high-availability mode.
+#[cfg(feature = "ha")]
+pub mod shared;
 /// A module for setting up and managing a [Sled](https://sled.rs)-based database.
 pub mod sled;
 /// HistoryStoreKey contains the keys used to store the history of events.
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum HistoryStoreKey {
     Evm {
         chain_id: types::U256,
@@ -40,22 +54,95 @@ pub enum HistoryStoreKey {
     },
     Substrate {
         chain_id: types::U256,
-        node_name: String,
+        /// The id of the tree (or the pallet) the events are coming from.
+        tree_id: u32,
     },
 }
 
+/// The tree ids of the Substrate watchers that follow a whole pallet, instead of a tree.
+///
+/// They are counted down from `u32::MAX`, so they never collide with the ids of the
+/// merkle trees, and every pallet watcher of a node keeps its own history.
+pub mod pallet_tree_ids {
+    /// The tree id of the DKG proposal handler pallet watcher.
+    pub const DKG_PROPOSAL_HANDLER: u32 = u32::MAX;
+    /// The tree id of the bridge pallet watcher.
+    pub const BRIDGE: u32 = u32::MAX - 1;
+}
+
 /// A Bridge Key is a unique key used for Sending and Receiving Commands to the Signature Bridge
 /// It is a combination of the Chain ID and the Address of the Bridge contract.
-#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
 pub struct BridgeKey {
     pub address: types::H160,
-    pub chain_id: types::U256,
+    #[serde(with = "typed_chain_id_serde")]
+    pub chain_id: TypedChainId,
 }
 
 impl BridgeKey {
-    pub fn new(address: types::Address, chain_id: types::U256) -> Self {
+    pub fn new(address: types::Address, chain_id: TypedChainId) -> Self {
         Self { address, chain_id }
     }
+
+    /// Returns the bytes of the key.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(self.address.as_bytes());
+        vec.extend_from_slice(&self.chain_id.to_bytes());
+        vec
+    }
+}
+
+/// (De)serializes a [`TypedChainId`] as its `u64` representation, as defined
+/// in the Webb spec, where the chain type is encoded in the upper bytes.
+///
+/// For backward compatibility, plain EVM chain ids (as `U256`) are accepted too.
+mod typed_chain_id_serde {
+    use ethereum_types as types;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::convert::TryFrom;
+
+    use super::TypedChainId;
+
+    pub fn serialize<S: Serializer>(
+        v: &TypedChainId,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(v.chain_id())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<TypedChainId, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Typed(u64),
+            Plain(types::U256),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Typed(v) => Ok(TypedChainId::from(v)),
+            Repr::Plain(v) => {
+                u32::try_from(v).map(TypedChainId::Evm).map_err(|_| {
+                    serde::de::Error::custom(format!(
+                        "chain id {} does not fit in a typed chain id",
+                        v
+                    ))
+                })
+            }
+        }
+    }
 }
 
 impl HistoryStoreKey {
@@ -66,38 +153,42 @@ impl HistoryStoreKey {
             HistoryStoreKey::Substrate { chain_id, .. } => *chain_id,
         }
     }
-    /// Returns the address of the chain this key is for.
-    pub fn address(&self) -> types::H160 {
-        match self {
-            HistoryStoreKey::Evm { address, .. } => *address,
-            HistoryStoreKey::Substrate { node_name, .. } => {
-                // a bit hacky, but we don't have a way to get the address from the node name
-                // so we just pretend it's the address of the node
-                let mut address_bytes = vec![];
-                address_bytes.extend_from_slice(node_name.as_bytes());
-                address_bytes.resize(20, 0);
-                types::H160::from_slice(&address_bytes)
+
+    /// Returns the typed chain id of the chain this key is for.
+    ///
+    /// Returns an error if the chain id does not fit in a typed chain id.
+    pub fn typed_chain_id(&self) -> anyhow::Result<TypedChainId> {
+        let chain_id = u32::try_from(self.chain_id()).map_err(|_| {
+            anyhow::anyhow!(
+                "chain id {} does not fit in a typed chain id",
+                self.chain_id()
+            )
+        })?;
+        let typed_chain_id = match self {
+            HistoryStoreKey::Evm { .. } => TypedChainId::Evm(chain_id),
+            HistoryStoreKey::Substrate { .. } => {
+                TypedChainId::Substrate(chain_id)
             }
-        }
+        };
+        Ok(typed_chain_id)
     }
 
     /// Returns the bytes of the key.
-    pub fn to_bytes(&self) -> Vec<u8> {
+    ///
+    /// The key is prefixed by the typed chain id, so EVM and Substrate chains
+    /// with the same chain id do not collide.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
         let mut vec = vec![];
+        vec.extend_from_slice(&self.typed_chain_id()?.to_bytes());
         match self {
-            Self::Evm { chain_id, address } => {
-                vec.extend_from_slice(&chain_id.as_u128().to_le_bytes());
+            Self::Evm { address, .. } => {
                 vec.extend_from_slice(address.as_bytes());
             }
-            Self::Substrate {
-                chain_id,
-                node_name,
-            } => {
-                vec.extend_from_slice(&chain_id.as_u128().to_le_bytes());
-                vec.extend_from_slice(node_name.as_bytes());
+            Self::Substrate { tree_id, .. } => {
+                vec.extend_from_slice(&tree_id.to_be_bytes());
             }
         }
-        vec
+        Ok(vec)
     }
 }
 
@@ -107,17 +198,16 @@ impl Display for HistoryStoreKey {
             Self::Evm { chain_id, address } => {
                 write!(f, "Evm({}, {})", chain_id, address)
             }
-            Self::Substrate {
-                chain_id,
-                node_name,
-            } => write!(f, "Substrate({}, {})", chain_id, node_name),
+            Self::Substrate { chain_id, tree_id } => {
+                write!(f, "Substrate({}, {})", chain_id, tree_id)
+            }
         }
     }
 }
 
 impl Display for BridgeKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Bridge({}, {})", self.chain_id, self.address)
+        write!(f, "Bridge({:?}, {})", self.chain_id, self.address)
     }
 }
 
@@ -133,21 +223,15 @@ impl From<(types::Address, types::U256)> for HistoryStoreKey {
     }
 }
 
-impl From<(types::U256, String)> for HistoryStoreKey {
-    fn from((chain_id, node_name): (types::U256, String)) -> Self {
-        Self::Substrate {
-            chain_id,
-            node_name,
-        }
+impl From<(types::U256, u32)> for HistoryStoreKey {
+    fn from((chain_id, tree_id): (types::U256, u32)) -> Self {
+        Self::Substrate { chain_id, tree_id }
     }
 }
 
-impl From<(String, types::U256)> for HistoryStoreKey {
-    fn from((node_name, chain_id): (String, types::U256)) -> Self {
-        Self::Substrate {
-            chain_id,
-            node_name,
-        }
+impl From<(u32, types::U256)> for HistoryStoreKey {
+    fn from((tree_id, chain_id): (u32, types::U256)) -> Self {
+        Self::Substrate { chain_id, tree_id }
     }
 }
 
@@ -168,6 +252,37 @@ pub trait HistoryStore: Clone + Send + Sync {
         default_block_number: types::U64,
     ) -> anyhow::Result<types::U64>;
 
+    /// Sets the last block handled for one kind of events of that contract,
+    /// and returns the old one, if any.
+    fn set_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+        block_number: types::U64,
+    ) -> anyhow::Result<Option<types::U64>>;
+
+    /// Get the last block handled for one kind of events of that contract, if any.
+    fn get_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+    ) -> anyhow::Result<Option<types::U64>>;
+
+    /// Records that the history of that contract is only synced from the block `from`,
+    /// its events before it got skipped, see [`SyncFrom::Latest`](crate::config::SyncFrom::Latest).
+    fn set_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the block the history of that contract is synced from, if its events
+    /// before it got skipped.
+    fn get_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::U64>>;
+
     /// an easy way to call the `get_last_block_number`
     /// where the default block number is `1`.
     fn get_last_block_number_or_default<K: Into<HistoryStoreKey> + Debug>(
@@ -178,6 +293,61 @@ pub trait HistoryStore: Clone + Send + Sync {
     }
 }
 
+/// A leaf that conflicts with the one cached at the same index.
+///
+/// The cached leaves are never overwritten: a conflict means the cache got out of sync
+/// with the contract (after a reorg, or a bug), and serving it would break the proofs
+/// of the clients. The events watchers roll the cache back from the conflicting leaf,
+/// see [`LeafCacheStore::roll_back_leaves`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "the leaf #{index} of {key} is cached as {cached:?}, refusing to overwrite it with {leaf:?}"
+)]
+pub struct LeafConflict {
+    /// The contract of the leaves.
+    pub key: HistoryStoreKey,
+    /// The index of the leaf.
+    pub index: u32,
+    /// The cached leaf.
+    pub cached: types::H256,
+    /// The leaf that was about to overwrite it.
+    pub leaf: types::H256,
+}
+
+impl LeafConflict {
+    /// Reports the conflict loudly, as an error and in the metrics.
+    pub fn report(&self) {
+        tracing::error!("Leaves cache inconsistency: {}", self);
+        let contract = match &self.key {
+            HistoryStoreKey::Evm { address, .. } => format!("{:?}", address),
+            HistoryStoreKey::Substrate { tree_id, .. } => tree_id.to_string(),
+        };
+        metrics::inc(
+            Metric::LeafConflicts,
+            Labels::new(self.key.chain_id(), contract, "leaves_store"),
+        );
+    }
+}
+
+/// Some of the cached leaves of a contract have no recorded deposit block, they got cached
+/// by an older version of the relayer.
+///
+/// The contract should be re-indexed (see `force-resync`) to serve its leaves by block range.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "{missing} of the cached leaves of {key} have no recorded deposit block"
+)]
+pub struct MissingLeafBlocks {
+    /// The contract of the leaves.
+    pub key: HistoryStoreKey,
+    /// The number of the leaves without a recorded block.
+    pub missing: usize,
+}
+
+/// A lazy iterator over the leaves of a contract, along with their indices.
+pub type LeavesIter =
+    Box<dyn Iterator<Item = anyhow::Result<(u32, types::H256)>> + Send>;
+
 /// A Leaf Cache Store is a simple trait that would help in
 /// getting the leaves and insert them with a simple API.
 pub trait LeafCacheStore: HistoryStore {
@@ -188,12 +358,104 @@ pub trait LeafCacheStore: HistoryStore {
         key: K,
     ) -> anyhow::Result<Self::Output>;
 
+    /// Caches the leaves of that contract, by their index.
+    ///
+    /// Writing the same leaf again is a no-op, but a different leaf at a cached index
+    /// fails with a [`LeafConflict`], and none of the leaves are written.
     fn insert_leaves<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
         leaves: &[(u32, types::H256)],
     ) -> anyhow::Result<()>;
 
+    /// Returns the cached leaves of that contract along with their indices,
+    /// sorted by the leaf index.
+    fn get_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>>;
+
+    /// Returns a lazy iterator over the cached leaves of that contract along with their
+    /// indices, sorted by the leaf index.
+    ///
+    /// By default, all the leaves are read at once, stores should override it to read
+    /// them one by one.
+    fn iter_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<LeavesIter> {
+        let leaves = self.get_leaves_with_index(key)?;
+        Ok(Box::new(leaves.into_iter().map(Ok)))
+    }
+
+    /// Returns the index of the leaf with that commitment, if it is cached.
+    fn get_leaf_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<Option<u32>>;
+
+    /// Returns the number of the cached leaves of that contract, without
+    /// reading all of them.
+    fn get_leaves_count<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<usize>;
+
+    /// Records the blocks the leaves of that contract got deposited at, by their index.
+    fn insert_leaves_block_numbers<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_numbers: &[(u32, types::U64)],
+    ) -> anyhow::Result<()>;
+
+    /// Returns the cached leaves of that contract deposited from the block `from` up to
+    /// the block `to` (inclusive) along with their indices, sorted by the leaf index.
+    ///
+    /// Fails with a [`MissingLeafBlocks`] if some of the cached leaves have no recorded
+    /// block, the leaves of the range could not be complete then.
+    fn get_leaves_in_block_range<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+        to: types::U64,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>>;
+
+    /// Rolls the leaves of that contract back, from the leaf `from_index`: the leaves from
+    /// it are forgotten, and the last blocks its events got handled up to are moved back
+    /// before the block of that leaf, so its events watcher handles them again.
+    ///
+    /// Without a recorded block for that leaf, the whole contract is cleared, see
+    /// [`Self::clear_contract`].
+    fn roll_back_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from_index: u32,
+    ) -> anyhow::Result<()>;
+
+    /// Forgets everything cached about that contract: its leaves, their deposit blocks, its
+    /// last deposit block, the last blocks its events got handled up to and the block its
+    /// history is synced from, so its events watcher re-indexes it from its deployment.
+    fn clear_contract<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<()>;
+
+    /// Whether the forced resync of that contract is done already, a contract is only
+    /// resynced once while its `force-resync` flag stays set.
+    fn is_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<bool>;
+
+    /// Records whether the forced resync of that contract is done, it is reset once its
+    /// `force-resync` flag is removed, so the next one is done again.
+    fn set_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        resynced: bool,
+    ) -> anyhow::Result<()>;
+
     // The last deposit info is sent to the client on leaf request
     // So they can verify when the last transaction was sent to maintain
     // their own state of mixers.
@@ -209,12 +471,498 @@ pub trait LeafCacheStore: HistoryStore {
     ) -> anyhow::Result<types::U64>;
 }
 
+/// A future resolving to the index of a watched leaf once it is cached, or to `None`
+/// if the store stopped watching it.
+pub type LeafWatch = futures::future::BoxFuture<'static, Option<u32>>;
+
+/// A Leaf Watch Store notifies the leaves of a contract as soon as they get cached,
+/// so the clients do not have to poll the leaves.
+pub trait LeafWatchStore: LeafCacheStore {
+    /// Watches the leaf with that commitment, resolving to its index once it is cached,
+    /// right away if it is cached already.
+    fn watch_leaf<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<LeafWatch>;
+}
+
+/// Metadata about the latest exported snapshot of a contract's leaves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesSnapshotInfo {
+    /// The IPFS Content Identifier of the snapshot.
+    pub cid: String,
+    /// The block number where the snapshot was taken at.
+    #[schema(value_type = String)]
+    pub block_number: types::U64,
+    /// The number of leaves included in the snapshot.
+    pub leaf_count: u32,
+    /// The merkle root of the tree at that block.
+    #[schema(value_type = String)]
+    pub root: types::H256,
+    /// Unix timestamp (in seconds) of when the snapshot got exported.
+    pub exported_at: u64,
+}
+
+/// A Leaves Snapshot Store is a simple trait for keeping track of
+/// the latest exported snapshot of the leaves of a contract.
+pub trait LeavesSnapshotStore {
+    /// Get the latest exported snapshot info for that contract, if any.
+    fn get_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<LeavesSnapshotInfo>>;
+    /// Sets the latest exported snapshot info for that contract.
+    fn set_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        info: LeavesSnapshotInfo,
+    ) -> anyhow::Result<()>;
+}
+
+/// A transaction that got relayed by the relayer, on behalf of a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayedTx {
+    /// The typed chain id of the chain the transaction got sent to, an EVM chain or a
+    /// Substrate node.
+    #[serde(with = "typed_chain_id_serde")]
+    pub chain_id: TypedChainId,
+    /// The hash of the transaction, or of the extrinsic.
+    pub tx_hash: types::H256,
+    /// Unix timestamp (in seconds) of when the transaction got sent.
+    pub relayed_at: u64,
+    /// The number of the block the transaction got finalized in, for the relays whose
+    /// outcome is recorded by the relayer, the Substrate ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Whether the transaction succeeded, recorded along with its `block_number`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// A Relayed Tx Store is a simple trait for keeping the history of the transactions
+/// relayed by the relayer, so clients could query their status later.
+pub trait RelayedTxStore {
+    /// Records a relayed transaction.
+    fn insert_relayed_tx(&self, tx: RelayedTx) -> anyhow::Result<()>;
+    /// Returns the relayed transaction with that hash on that chain, if any.
+    fn get_relayed_tx(
+        &self,
+        chain_id: TypedChainId,
+        tx_hash: types::H256,
+    ) -> anyhow::Result<Option<RelayedTx>>;
+}
+
+/// The fees earned by the relayer in one token, on one chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Earnings {
+    /// The chain id where the fees got earned.
+    #[schema(value_type = String)]
+    pub chain_id: types::U256,
+    /// The token the fees got paid in, the zero address is the native token of the chain.
+    #[schema(value_type = String)]
+    pub token: types::Address,
+    /// The sum of the earned fees.
+    #[schema(value_type = String)]
+    pub total: types::U256,
+    /// The number of the relayed withdraws.
+    pub withdrawals: u64,
+    /// The relayer identity that relayed the withdraws, none for the default accounts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+/// An Earnings Store is a simple trait for keeping track of the fees earned by the relayer.
+pub trait EarningsStore {
+    /// Adds the fee of a relayed withdraw to the earnings of that token on that chain,
+    /// kept apart for every relayer identity.
+    fn record_earning(
+        &self,
+        chain_id: types::U256,
+        token: types::Address,
+        identity: Option<&str>,
+        fee: types::U256,
+    ) -> anyhow::Result<()>;
+    /// Returns the earnings of every token, on every chain, for every identity.
+    fn earnings(&self) -> anyhow::Result<Vec<Earnings>>;
+}
+
+/// The length of the accounting buckets, one day in seconds.
+pub const ACCOUNTING_BUCKET: u64 = 24 * 60 * 60;
+
+/// The gas spent and the fees earned by the relayer on one contract, over one day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountingEntry {
+    /// Unix timestamp (in seconds) of the start of the day, in UTC.
+    pub day: u64,
+    /// The chain id of the chain of the contract.
+    #[schema(value_type = String)]
+    pub chain_id: types::U256,
+    /// The contract the withdraws got relayed to.
+    #[schema(value_type = String)]
+    pub contract: types::Address,
+    /// The token the fees got paid in, the zero address is the native token of the chain.
+    #[schema(value_type = String)]
+    pub token: types::Address,
+    /// The gas used by the relayed transactions, reverted ones included.
+    #[schema(value_type = String)]
+    pub gas_used: types::U256,
+    /// The cost of the gas used, along with the L1 data fees on the rollups, in wei of
+    /// the native token of the chain.
+    #[schema(value_type = String)]
+    pub gas_spent: types::U256,
+    /// The sum of the earned fees, in the token of the fees.
+    #[schema(value_type = String)]
+    pub fees_earned: types::U256,
+    /// The number of the relayed transactions.
+    pub transactions: u64,
+    /// The relayer identity that relayed the transactions, none for the default accounts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+/// An Accounting Store is a simple trait for keeping track of the gas spent by the relayer
+/// against the fees it earned, in daily buckets per chain, contract and relayer identity.
+pub trait AccountingStore {
+    /// Adds the cost and the earned fee of a relayed transaction to the bucket of its day.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_id` - The chain id of the chain of the contract
+    /// * `contract` - The contract the transaction got relayed to
+    /// * `token` - The token the fee got paid in
+    /// * `identity` - The relayer identity that relayed the transaction, if not the default one
+    /// * `timestamp` - Unix timestamp (in seconds) of when the transaction got finalized
+    /// * `gas_used` - The gas used by the transaction
+    /// * `gas_spent` - The cost of the gas used, and the L1 data fee on a rollup, in wei
+    /// * `fee` - The earned fee, zero if the transaction reverted
+    #[allow(clippy::too_many_arguments)]
+    fn record_relay_cost(
+        &self,
+        chain_id: types::U256,
+        contract: types::Address,
+        token: types::Address,
+        identity: Option<&str>,
+        timestamp: u64,
+        gas_used: types::U256,
+        gas_spent: types::U256,
+        fee: types::U256,
+    ) -> anyhow::Result<()>;
+    /// Returns the buckets of the days between the `from` and `to` timestamps (in seconds),
+    /// both included, ordered by day.
+    fn accounting(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<AccountingEntry>>;
+}
+
+/// The resolutions of the relay stats buckets.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum StatsResolution {
+    /// One bucket per hour.
+    #[default]
+    Hourly,
+    /// One bucket per day, in UTC.
+    Daily,
+}
+
+impl StatsResolution {
+    /// Both resolutions, every relay is counted in both.
+    pub const ALL: [StatsResolution; 2] = [Self::Hourly, Self::Daily];
+
+    /// The length of the buckets, in seconds.
+    pub const fn seconds(self) -> u64 {
+        match self {
+            Self::Hourly => 60 * 60,
+            Self::Daily => 24 * 60 * 60,
+        }
+    }
+
+    /// The start of the bucket of that timestamp (in seconds).
+    pub const fn bucket_of(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+}
+
+/// The relays to one contract (or one Substrate tree) over one hour or one day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayStats {
+    /// Unix timestamp (in seconds) of the start of the bucket, in UTC.
+    pub start: u64,
+    /// The length of the bucket.
+    pub resolution: StatsResolution,
+    /// The chain id of the chain of the contract.
+    #[schema(value_type = String)]
+    pub chain_id: types::U256,
+    /// The contract the withdraws got relayed to, on an EVM chain.
+    #[schema(value_type = Option<String>)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<types::Address>,
+    /// The tree the withdraws got relayed to, on a Substrate chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree_id: Option<u32>,
+    /// The number of the finalized relays, reverted ones included.
+    pub relays: u64,
+    /// The number of the reverted relays.
+    pub reverted: u64,
+}
+
+/// A Relay Stats Store is a simple trait for counting the relays per chain and contract (or tree),
+/// in hourly and daily buckets, so the stats of a time range are read without scanning
+/// the relayed transactions.
+pub trait RelayStatsStore {
+    /// Counts a finalized relay to the contract (or the Substrate tree) of that `key`, in
+    /// the hourly and the daily buckets of its `timestamp` (in seconds).
+    fn record_relay<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        timestamp: u64,
+        reverted: bool,
+    ) -> anyhow::Result<()>;
+    /// Returns the buckets of that resolution between the `from` and `to` timestamps
+    /// (in seconds), both included, ordered by their start.
+    fn relay_stats(
+        &self,
+        resolution: StatsResolution,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<RelayStats>>;
+    /// Removes the buckets that started before the `before` timestamp (in seconds),
+    /// returning how many got removed.
+    fn prune_relay_stats(&self, before: u64) -> anyhow::Result<usize>;
+}
+
+/// The withdraw that spent a nullifier, as far as the relayer knows it.
+///
+/// The nullifiers cached by older versions of the relayer carry none of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpentNullifier {
+    /// The hash of the withdraw transaction.
+    pub tx_hash: Option<types::H256>,
+    /// The block the withdraw transaction got included in.
+    pub block_number: Option<types::U64>,
+    /// Unix timestamp (in seconds) of the block the withdraw transaction got included in.
+    pub spent_at: Option<u64>,
+}
+
+/// A Nullifier Store is a simple trait for caching the nullifiers already spent
+/// on a contract, so the relayer could reject the withdraws spending them again.
+pub trait NullifierStore {
+    /// Records that nullifier hash as spent on that contract, by that withdraw.
+    fn insert_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+        spent: SpentNullifier,
+    ) -> anyhow::Result<()>;
+    /// Returns the withdraw that spent that nullifier hash on that contract, if it is
+    /// known to be spent.
+    fn get_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<Option<SpentNullifier>>;
+    /// Whether that nullifier hash is known to be spent on that contract.
+    fn is_nullifier_spent<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<bool>;
+}
+
+/// An operation of the relayer that gets recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum AuditEvent {
+    /// A command got accepted from a client.
+    #[serde(rename_all = "camelCase")]
+    CommandAccepted {
+        /// The name of the command.
+        command: String,
+        /// The chain the command is for, if any.
+        chain: Option<String>,
+    },
+    /// A transaction got queued for being sent by the transaction queue.
+    #[serde(rename_all = "camelCase")]
+    TxQueued {
+        /// The chain id of the chain the transaction is for.
+        chain_id: types::U256,
+        /// The hex encoded key of the transaction in the queue, if any.
+        item_key: Option<String>,
+    },
+    /// An admin action got performed.
+    #[serde(rename_all = "camelCase")]
+    AdminAction {
+        /// The name of the action.
+        action: String,
+        /// The chain id of the chain the action is for, if any.
+        chain_id: Option<u64>,
+    },
+    /// A request to the admin API got refused, for a missing or a wrong admin token.
+    #[serde(rename_all = "camelCase")]
+    AdminAuthFailed {
+        /// The name of the refused action.
+        action: String,
+    },
+}
+
+/// An entry to be appended to the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// The IP address of the client that triggered the operation, if any.
+    pub client_ip: Option<std::net::IpAddr>,
+    /// The id of the relay job (the session token) the operation belongs to, if any.
+    pub job_id: Option<String>,
+    /// The recorded operation.
+    pub event: AuditEvent,
+}
+
+impl AuditEntry {
+    /// Creates an entry that is not triggered by a client.
+    pub fn new(event: AuditEvent) -> Self {
+        Self {
+            client_ip: None,
+            job_id: None,
+            event,
+        }
+    }
+}
+
+/// A record of the audit log, chained to the previous one by its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// The sequence number of the record, starting at 1.
+    pub seq: u64,
+    /// Unix timestamp (in seconds) of when the record got appended.
+    pub timestamp: u64,
+    /// The recorded entry.
+    pub entry: AuditEntry,
+    /// The hash of the previous record, zero for the first one.
+    pub prev_hash: types::H256,
+    /// The hash of this record, see [`AuditRecord::compute_hash`].
+    pub hash: types::H256,
+}
+
+impl AuditRecord {
+    /// Creates the record following the one with `prev_hash`, and computes its hash.
+    pub fn new(
+        seq: u64,
+        timestamp: u64,
+        entry: AuditEntry,
+        prev_hash: types::H256,
+    ) -> serde_json::Result<Self> {
+        let mut record = Self {
+            seq,
+            timestamp,
+            entry,
+            prev_hash,
+            hash: types::H256::zero(),
+        };
+        record.hash = record.compute_hash()?;
+        Ok(record)
+    }
+
+    /// Returns the keccak256 hash of the record, covering every field but the hash itself.
+    pub fn compute_hash(&self) -> serde_json::Result<types::H256> {
+        let mut data = self.prev_hash.as_bytes().to_vec();
+        data.extend_from_slice(&self.seq.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&serde_json::to_vec(&self.entry)?);
+        Ok(crate::utils::keccak256(&data).into())
+    }
+}
+
+/// Checks that the records form an unbroken hash chain, in order, following the `prev`
+/// record (the last record before them), or starting the log if there is none.
+///
+/// Returns the sequence number of the first tampered (or missing) record, if any.
+pub fn verify_audit_chain(
+    prev: Option<&AuditRecord>,
+    records: &[AuditRecord],
+) -> anyhow::Result<Option<u64>> {
+    let (mut prev_seq, mut prev_hash) = prev
+        .map(|v| (v.seq, v.hash))
+        .unwrap_or((0, types::H256::zero()));
+    for record in records {
+        let expected_seq = prev_seq + 1;
+        if record.seq != expected_seq
+            || record.prev_hash != prev_hash
+            || record.hash != record.compute_hash()?
+        {
+            return Ok(Some(expected_seq));
+        }
+        prev_seq = record.seq;
+        prev_hash = record.hash;
+    }
+    Ok(None)
+}
+
+/// An Audit Log Store is a simple trait for keeping an append-only log of the operations
+/// of the relayer, for post-incident forensics.
+pub trait AuditLogStore {
+    /// Appends the entry to the audit log, returning the appended record.
+    fn append_audit_entry(
+        &self,
+        entry: AuditEntry,
+    ) -> anyhow::Result<AuditRecord>;
+    /// Returns at most `limit` records of the audit log, in order, starting at the
+    /// sequence number `from`.
+    fn audit_records(
+        &self,
+        from: u64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>>;
+}
+
+/// Appends the entry to the audit log of that store.
+///
+/// Failing to record an entry does not fail the audited operation, it is only logged.
+pub fn audit<S: AuditLogStore + ?Sized>(store: &S, entry: AuditEntry) {
+    if let Err(e) = store.append_audit_entry(entry) {
+        tracing::error!("Failed to append to the audit log: {}", e);
+    }
+}
+
+/// A Bridge Registry Store is a simple trait for persisting the Signature Bridges
+/// registered in the `BridgeRegistry`, so they are still known across restarts.
+pub trait BridgeRegistryStore {
+    /// Persists the bridge registration.
+    fn register_bridge(&self, key: BridgeKey) -> anyhow::Result<()>;
+    /// Returns all the bridges that got registered before.
+    fn registered_bridges(&self) -> anyhow::Result<Vec<BridgeKey>>;
+    /// Forgets the bridge registration, once the bridge is not configured anymore.
+    fn unregister_bridge(&self, key: BridgeKey) -> anyhow::Result<()>;
+}
+
 /// A Command sent to the Bridge to execute different actions.
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum BridgeCommand {
     ExecuteProposalWithSignature { data: Vec<u8>, signature: Vec<u8> },
 }
 
+impl BridgeCommand {
+    /// Returns the name of the command.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ExecuteProposalWithSignature { .. } => {
+                "ExecuteProposalWithSignature"
+            }
+        }
+    }
+}
+
 /// A trait for retrieving queue keys
 pub trait QueueKey {
     fn queue_name(&self) -> String;
@@ -240,6 +988,8 @@ where
     fn has_item(&self, key: Self::Key) -> anyhow::Result<bool>;
     /// Remove an item from the queue.
     fn remove_item(&self, key: Self::Key) -> anyhow::Result<Option<Item>>;
+    /// Returns the number of items in the queue, without reading them.
+    fn count_items(&self, key: Self::Key) -> anyhow::Result<usize>;
 }
 
 impl<S, T> QueueStore<T> for Arc<S>
@@ -268,8 +1018,258 @@ where
     fn remove_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
         S::remove_item(self, key)
     }
+
+    fn count_items(&self, key: Self::Key) -> anyhow::Result<usize> {
+        S::count_items(self, key)
+    }
+}
+/// A Proposal Nonce Store is a simple trait for keeping track of the highest executed
+/// proposal nonce, per resource id and origin chain.
+///
+/// It is used to refuse proposals that got replayed or duplicated, for example after a restart.
+pub trait ProposalNonceStore {
+    /// Get the highest executed nonce of that resource id and origin chain, if any.
+    fn get_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+    ) -> anyhow::Result<Option<u32>>;
+    /// Sets the executed nonce of that resource id and origin chain,
+    /// only if it is higher than the stored one.
+    fn set_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+        nonce: u32,
+    ) -> anyhow::Result<()>;
 }
+
+/// A Governor Store keeps the governor of every Signature Bridge, as it was last seen,
+/// so the signatures of the proposals could be verified without asking the bridge.
+pub trait GovernorStore {
+    /// Sets the governor of that bridge, seen at that block number.
+    ///
+    /// The governor is only updated if it is seen at a later block than the stored one,
+    /// so replaying older ownership transfers does not roll it back.
+    /// Returns whether the stored governor changed.
+    fn set_governor<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        governor: types::Address,
+        block_number: u64,
+    ) -> anyhow::Result<bool>;
+    /// Returns the governor of that bridge, if it is known.
+    fn get_governor<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::Address>>;
+}
+
+/// A Key Rotation Store keeps the signing keys rotated by the admin, so restarting the
+/// relayer does not bring the revoked keys back.
+pub trait KeyRotationStore {
+    /// Records that the key with that address is revoked on that chain, and replaced by
+    /// the new key.
+    fn insert_key_rotation(
+        &self,
+        chain_id: u64,
+        revoked: types::Address,
+        key: &PrivateKey,
+    ) -> anyhow::Result<()>;
+    /// Returns all the recorded key rotations, by chain id and the address of the revoked key.
+    fn key_rotations(
+        &self,
+    ) -> anyhow::Result<Vec<(u64, types::Address, PrivateKey)>>;
+}
+
+/// How many roots of every neighbor an anchor keeps in its history.
+pub const NEIGHBOR_ROOT_HISTORY_SIZE: usize = 30;
+
+/// A Neighbor Root Store keeps the latest roots of the neighbors of every anchor, as seen
+/// from its edges, so the roots of a withdraw could be checked without asking the anchor.
+pub trait NeighborRootStore {
+    /// Records the latest root of the edge of that neighbor chain, only the last
+    /// [`NEIGHBOR_ROOT_HISTORY_SIZE`] roots of every neighbor are kept.
+    ///
+    /// The neighbors are numbered in the order their first root is recorded, which is
+    /// the order of the edges of the anchor, as its edges are watched from its deployment.
+    fn insert_neighbor_root<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        neighbor_chain_id: types::U256,
+        root: types::H256,
+    ) -> anyhow::Result<()>;
+    /// Returns the known roots of every neighbor of that anchor, along with the chain id
+    /// of the neighbor, in the order of the edges of the anchor.
+    fn neighbor_roots<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(types::U256, Vec<types::H256>)>>;
+}
+
+/// How many root checkpoints of every contract are kept.
+pub const ROOT_CHECKPOINT_HISTORY_SIZE: usize = 100;
+
+/// A periodic checkpoint of the merkle root of a contract, the root computed from the
+/// cached leaves against the on-chain one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RootCheckpoint {
+    /// The block number where the on-chain root got read at.
+    #[schema(value_type = String)]
+    pub block_number: types::U64,
+    /// The latest root of the contract.
+    #[schema(value_type = String)]
+    pub onchain_root: types::H256,
+    /// The root computed from the cached leaves.
+    #[schema(value_type = String)]
+    pub local_root: types::H256,
+    /// The number of the cached leaves.
+    pub cached_leaves: u32,
+    /// The number of the leaves inserted in the contract.
+    pub contract_leaves: u32,
+    /// Whether the cached leaves diverged from the contract.
+    pub diverged: bool,
+    /// Whether the divergence got alerted, after lasting for more than one checkpoint.
+    pub alerted: bool,
+    /// Unix timestamp (in seconds) of when the roots got checked.
+    pub checked_at: u64,
+}
+
+/// A Root Checkpoint Store keeps the latest root checkpoints of every contract, so the
+/// divergences lasting for more than one checkpoint could be found.
+pub trait RootCheckpointStore {
+    /// Records a root checkpoint of that contract, only the last
+    /// [`ROOT_CHECKPOINT_HISTORY_SIZE`] checkpoints are kept.
+    fn insert_root_checkpoint<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        checkpoint: RootCheckpoint,
+    ) -> anyhow::Result<()>;
+    /// Returns the kept root checkpoints of that contract, the oldest first.
+    fn root_checkpoints<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<RootCheckpoint>>;
+}
+
+/// The usage of the relayer by an API key, over a day.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUsage {
+    /// The number of the accepted relay commands.
+    pub relays: u64,
+    /// The size of the served leaves, in bytes (32 per leaf).
+    pub leaves_bytes: u64,
+}
+
+impl ApiUsage {
+    /// Returns the sum of both usages.
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self {
+            relays: self.relays.saturating_add(other.relays),
+            leaves_bytes: self.leaves_bytes.saturating_add(other.leaves_bytes),
+        }
+    }
+}
+
+/// An API Usage Store keeps the daily usage counters of every API key, for enforcing
+/// their quotas.
+pub trait ApiUsageStore {
+    /// Returns the usage of that API key on that day (days since the unix epoch).
+    fn api_usage(&self, api_key: &str, day: u64) -> anyhow::Result<ApiUsage>;
+    /// Adds `usage` to the usage of that API key on that day, only if `admit` accepts
+    /// the sum, so concurrent requests could never go over a quota together.
+    ///
+    /// Returns the usage of the day, and whether `usage` got added to it.
+    fn add_api_usage<F: Fn(&ApiUsage) -> bool>(
+        &self,
+        api_key: &str,
+        day: u64,
+        usage: ApiUsage,
+        admit: F,
+    ) -> anyhow::Result<(ApiUsage, bool)>;
+}
+
+/// A proposal that got signed and enqueued for execution on a Signature Bridge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedProposal {
+    /// The chain id of the Signature Bridge that will execute the proposal.
+    pub chain_id: types::U256,
+    /// The proposal data (header + body).
+    pub data: Vec<u8>,
+    /// The signature of the governor over the proposal data.
+    pub signature: Vec<u8>,
+    /// Unix timestamp (in seconds) of when the proposal got enqueued.
+    pub enqueued_at: u64,
+}
+
+impl SignedProposal {
+    /// Returns the keccak256 hash of the proposal data.
+    pub fn data_hash(&self) -> [u8; 32] {
+        crate::utils::keccak256(&self.data)
+    }
+}
+
+/// The status of a signed proposal, on its way to the Signature Bridge.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum ProposalStatus {
+    /// The transaction executing the proposal is waiting in the tx queue.
+    #[serde(rename_all = "camelCase")]
+    Queued {
+        /// Unix timestamp (in seconds) of when the proposal got enqueued.
+        at: u64,
+    },
+    /// The transaction executing the proposal succeeded.
+    #[serde(rename_all = "camelCase")]
+    Executed {
+        /// Unix timestamp (in seconds) of when the execution got noticed.
+        at: u64,
+    },
+    /// The transaction executing the proposal reverted.
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        /// Unix timestamp (in seconds) of when the failure got noticed.
+        at: u64,
+    },
+    /// The proposal did not get executed in time, its transaction got cancelled.
+    #[serde(rename_all = "camelCase")]
+    Expired {
+        /// Unix timestamp (in seconds) of when the proposal expired.
+        at: u64,
+    },
+    /// The governor of the bridge changed before the proposal got executed, and
+    /// it is not signed by the new one, its transaction got cancelled.
+    #[serde(rename_all = "camelCase")]
+    Rejected {
+        /// Unix timestamp (in seconds) of when the proposal got rejected.
+        at: u64,
+    },
+}
+
+impl ProposalStatus {
+    /// Unix timestamp (in seconds) of when the proposal got that status.
+    pub fn at(&self) -> u64 {
+        match self {
+            Self::Queued { at }
+            | Self::Executed { at }
+            | Self::Failed { at }
+            | Self::Expired { at }
+            | Self::Rejected { at } => *at,
+        }
+    }
+}
+
 /// ProposalStore is a simple trait for inserting and removing proposals.
+///
+/// The status of a proposal is kept apart from the proposal itself, so it outlives the
+/// proposal once it got executed or expired.
 pub trait ProposalStore {
     type Proposal: Serialize + DeserializeOwned;
     fn insert_proposal(&self, proposal: Self::Proposal) -> anyhow::Result<()>;
@@ -277,4 +1277,90 @@ pub trait ProposalStore {
         &self,
         data_hash: &[u8],
     ) -> anyhow::Result<Option<Self::Proposal>>;
+    /// Returns all the proposals that are still in the store.
+    fn proposals(&self) -> anyhow::Result<Vec<Self::Proposal>>;
+    /// Returns the proposals still in the store with that resource id and nonce in
+    /// their header.
+    fn proposals_by_nonce(
+        &self,
+        resource_id: crate::proposals::ResourceId,
+        nonce: u32,
+    ) -> anyhow::Result<Vec<Self::Proposal>>;
+    /// Sets the status of the proposal with that data hash.
+    fn set_proposal_status(
+        &self,
+        data_hash: &[u8],
+        status: ProposalStatus,
+    ) -> anyhow::Result<()>;
+    /// Returns the status of the proposal with that data hash, if it is known.
+    fn proposal_status(
+        &self,
+        data_hash: &[u8],
+    ) -> anyhow::Result<Option<ProposalStatus>>;
+    /// Returns the data hashes and the statuses of every known proposal.
+    fn proposal_statuses(
+        &self,
+    ) -> anyhow::Result<Vec<(Vec<u8>, ProposalStatus)>>;
+    /// Forgets the status of the proposal with that data hash.
+    fn remove_proposal_status(&self, data_hash: &[u8]) -> anyhow::Result<()>;
+}
+
+/// The status of a proposal on a Substrate bridge pallet, as seen from its events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubstrateProposalStatus {
+    /// The relayers are still voting on the proposal.
+    Voting,
+    /// Enough relayers voted for the proposal, it is about to be executed.
+    Approved,
+    /// Enough relayers voted against the proposal.
+    Rejected,
+    /// The proposal got executed.
+    Succeeded,
+    /// The execution of the proposal failed.
+    Failed,
+}
+
+/// A proposal on a Substrate bridge pallet, identified by the source chain and the nonce
+/// of the deposit it is about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubstrateProposal {
+    /// The chain id of the source chain of the deposit.
+    pub src_chain_id: u64,
+    /// The nonce of the deposit on the source chain.
+    pub deposit_nonce: u64,
+    /// The last known status of the proposal.
+    pub status: SubstrateProposalStatus,
+    /// The number of relayers that voted for the proposal.
+    pub votes_for: u32,
+    /// The number of relayers that voted against the proposal.
+    pub votes_against: u32,
+    /// The block number of the last event of the proposal.
+    pub updated_at: u64,
+}
+
+/// SubstrateProposalStore keeps track of the proposals of the Substrate bridge pallets,
+/// keyed by the chain id of the bridge.
+pub trait SubstrateProposalStore {
+    /// Returns the proposal about that deposit, if it is tracked.
+    fn get_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>>;
+    /// Inserts the proposal, replacing the previous one about the same deposit.
+    fn insert_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        proposal: SubstrateProposal,
+    ) -> anyhow::Result<()>;
+    /// Stops tracking the proposal about that deposit.
+    fn remove_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>>;
 }
@@ -0,0 +1,270 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::config::HaConfig;
+
+use super::codec::{self, Record};
+use super::sled::SledQueueKey;
+use super::{QueueKey, QueueStore};
+
+/// How long a call to the Redis server could take, before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Queues an item, unless an item with the same key got queued already.
+///
+/// Returns `1` if the item got queued, `0` otherwise.
+const ENQUEUE_SCRIPT: &str = r#"
+if ARGV[2] ~= '' and redis.call('HSETNX', KEYS[2], ARGV[2], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('RPUSH', KEYS[1], ARGV[1])
+return 1
+"#;
+
+/// Removes the item queued under that key, returning it.
+const REMOVE_SCRIPT: &str = r#"
+local item = redis.call('HGET', KEYS[2], ARGV[1])
+if not item then
+    return false
+end
+redis.call('LREM', KEYS[1], 1, item)
+redis.call('HDEL', KEYS[2], ARGV[1])
+return item
+"#;
+
+/// The transaction queues shared by all the instances of a deployment, in the Redis
+/// server of the high-availability mode, see [`HaConfig`].
+///
+/// A transaction queued by the leader is still there for the next leader if it fails,
+/// and the transactions queued under the same key by the standbys, watching the same
+/// events, are only queued once.
+///
+/// Like the queues of the [`SledStore`](super::sled::SledStore), the key of a queued
+/// item is kept once it is dequeued, until the item is removed by its key.
+#[derive(Clone)]
+pub struct SharedQueues {
+    client: redis::Client,
+    /// The connection to the Redis server, opened on the first use, and opened
+    /// again after a failure.
+    conn: Arc<Mutex<Option<redis::Connection>>>,
+    prefix: String,
+}
+
+impl std::fmt::Debug for SharedQueues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedQueues")
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl SharedQueues {
+    /// Creates the shared queues, in the Redis server of the high-availability mode.
+    pub fn new(config: &HaConfig) -> anyhow::Result<Self> {
+        let client = redis::Client::open(config.redis_url.as_str())?;
+        Ok(Self {
+            client,
+            conn: Default::default(),
+            prefix: config.queue_prefix.clone(),
+        })
+    }
+
+    /// Whether the items of that queue are shared, only the transaction queues are.
+    pub fn shares(key: &SledQueueKey) -> bool {
+        matches!(key, SledQueueKey::EvmTx { .. })
+    }
+
+    fn list_key(&self, key: &SledQueueKey) -> String {
+        format!("{}:{}", self.prefix, key.queue_name())
+    }
+
+    fn keys_key(&self, key: &SledQueueKey) -> String {
+        format!("{}:{}:keys", self.prefix, key.queue_name())
+    }
+
+    /// Runs a call over the kept connection.
+    ///
+    /// The queues are used from the tasks of the (multi-threaded) runtime, the call runs
+    /// in place of the worker, whose other tasks are handed over to the other workers
+    /// meanwhile, so a slow Redis server does not stall them.
+    fn query<R>(
+        &self,
+        f: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<R>,
+    ) -> anyhow::Result<R> {
+        tokio::task::block_in_place(|| {
+            let mut conn = self.conn.lock();
+            let mut connected = match conn.take() {
+                Some(v) => v,
+                None => {
+                    let v = self.client.get_connection_with_timeout(TIMEOUT)?;
+                    v.set_read_timeout(Some(TIMEOUT))?;
+                    v.set_write_timeout(Some(TIMEOUT))?;
+                    v
+                }
+            };
+            let result = f(&mut connected)?;
+            // the connection is only kept if it did not fail.
+            *conn = Some(connected);
+            Ok(result)
+        })
+    }
+}
+
+impl<T> QueueStore<T> for SharedQueues
+where
+    T: Record + Clone,
+{
+    type Key = SledQueueKey;
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn enqueue_item(&self, key: Self::Key, item: T) -> anyhow::Result<()> {
+        let item_bytes = codec::encode(&item)?;
+        let item_key = key.item_key().map(hex::encode).unwrap_or_default();
+        let queued: i64 = self.query(|conn| {
+            redis::Script::new(ENQUEUE_SCRIPT)
+                .key(self.list_key(&key))
+                .key(self.keys_key(&key))
+                .arg(item_bytes)
+                .arg(item_key)
+                .invoke(conn)
+        })?;
+        if queued == 0 {
+            tracing::debug!("item with key {} is queued already", key);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn dequeue_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        let value: Option<Vec<u8>> = self.query(|conn| {
+            redis::cmd("LPOP").arg(self.list_key(&key)).query(conn)
+        })?;
+        value.map(|v| codec::decode(&v)).transpose()
+    }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn peek_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        let value: Option<Vec<u8>> = self.query(|conn| {
+            redis::cmd("LINDEX")
+                .arg(self.list_key(&key))
+                .arg(0)
+                .query(conn)
+        })?;
+        value.map(|v| codec::decode(&v)).transpose()
+    }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn has_item(&self, key: Self::Key) -> anyhow::Result<bool> {
+        let item_key = match key.item_key() {
+            Some(v) => hex::encode(v),
+            None => return Ok(false),
+        };
+        self.query(|conn| {
+            redis::cmd("HEXISTS")
+                .arg(self.keys_key(&key))
+                .arg(item_key)
+                .query(conn)
+        })
+    }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn remove_item(&self, key: Self::Key) -> anyhow::Result<Option<T>> {
+        let item_key = match key.item_key() {
+            Some(v) => hex::encode(v),
+            None => return Ok(None),
+        };
+        let value: Option<Vec<u8>> = self.query(|conn| {
+            redis::Script::new(REMOVE_SCRIPT)
+                .key(self.list_key(&key))
+                .key(self.keys_key(&key))
+                .arg(item_key)
+                .invoke(conn)
+        })?;
+        match value {
+            Some(v) => Ok(codec::decode(&v).ok()),
+            None => {
+                anyhow::bail!("item with key {} not found in queue", key);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn count_items(&self, key: Self::Key) -> anyhow::Result<usize> {
+        self.query(|conn| {
+            redis::cmd("LLEN").arg(self.list_key(&key)).query(conn)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::BridgeCommand;
+    use ethereum_types::U256;
+
+    fn shared_queues() -> SharedQueues {
+        let url = std::env::var("REDIS_URL")
+            .unwrap_or_else(|_| String::from("redis://127.0.0.1:6379"));
+        let config: HaConfig = serde_json::from_value(serde_json::json!({
+            "redis-url": url,
+            "queue-prefix": format!("webb-relayer-test:{}", hex::encode(rand::random::<[u8; 8]>())),
+        }))
+        .unwrap();
+        SharedQueues::new(&config).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server"]
+    fn items_queued_by_every_instance_are_queued_once() {
+        let leader = shared_queues();
+        // the standby shares the queues of the leader.
+        let standby = leader.clone();
+        let chain_id = U256::from(5001);
+        let tx_key = SledQueueKey::from_evm_with_custom_key(chain_id, [1; 64]);
+        let item = BridgeCommand::ExecuteProposalWithSignature {
+            data: vec![1],
+            signature: vec![2],
+        };
+        leader.enqueue_item(tx_key, item.clone()).unwrap();
+        standby.enqueue_item(tx_key, item.clone()).unwrap();
+        let queue = SledQueueKey::from_evm_chain_id(chain_id);
+        assert_eq!(
+            QueueStore::<BridgeCommand>::count_items(&leader, queue).unwrap(),
+            1
+        );
+        assert!(
+            QueueStore::<BridgeCommand>::has_item(&standby, tx_key).unwrap()
+        );
+        let dequeued: Option<BridgeCommand> =
+            standby.dequeue_item(queue).unwrap();
+        assert!(dequeued.is_some());
+        // once dequeued, it is not queued again.
+        leader.enqueue_item(tx_key, item).unwrap();
+        assert_eq!(
+            QueueStore::<BridgeCommand>::count_items(&leader, queue).unwrap(),
+            0
+        );
+        let removed: Option<BridgeCommand> =
+            leader.remove_item(tx_key).unwrap();
+        assert!(removed.is_some());
+        assert!(
+            !QueueStore::<BridgeCommand>::has_item(&standby, tx_key).unwrap()
+        );
+    }
+}
@@ -13,16 +13,45 @@
 // limitations under the License.
 //
 use core::fmt;
+use ethereum_types as types;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult,
+    TransactionError,
+};
+use sled::Transactional;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::path::Path;
-use webb::evm::ethers::types;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::config::PrivateKey;
+use crate::proposals::{evm_typed_chain_id, TypedChainId};
 use crate::store::{BridgeKey, QueueKey};
 
-use super::HistoryStoreKey;
-use super::{HistoryStore, LeafCacheStore, ProposalStore, QueueStore};
+use super::codec::{self, Record};
+use super::KeyRotationStore;
+use super::SignedProposal;
+use super::SpentNullifier;
+use super::{pallet_tree_ids, HistoryStoreKey};
+use super::{AccountingEntry, AccountingStore, ACCOUNTING_BUCKET};
+use super::{ApiUsage, ApiUsageStore};
+use super::{AuditEntry, AuditLogStore, AuditRecord};
+use super::{BridgeRegistryStore, LeavesSnapshotInfo, LeavesSnapshotStore};
+use super::{Earnings, EarningsStore, GovernorStore, NullifierStore};
+use super::{
+    HistoryStore, LeafCacheStore, LeafConflict, LeafWatch, LeafWatchStore,
+    LeavesIter, MissingLeafBlocks, ProposalStore, QueueStore,
+};
+use super::{NeighborRootStore, NEIGHBOR_ROOT_HISTORY_SIZE};
+use super::{ProposalNonceStore, ProposalStatus, RelayedTx, RelayedTxStore};
+use super::{RelayStats, RelayStatsStore, StatsResolution};
+use super::{
+    RootCheckpoint, RootCheckpointStore, ROOT_CHECKPOINT_HISTORY_SIZE,
+};
+use super::{SubstrateProposal, SubstrateProposalStore};
 /// SledStore is a store that stores the history of events in  a [Sled](https://sled.rs)-based database.
 #[derive(Clone)]
 pub struct SledStore {
@@ -44,120 +73,1937 @@ impl SledStore {
             .use_compression(true)
             .compression_factor(18)
             .open()?;
-        Ok(Self { db })
+        let store = Self { db };
+        store.migrate()?;
+        Ok(store)
     }
     /// Creates a temporary SledStore.
     pub fn temporary() -> anyhow::Result<Self> {
         let dir = tempfile::tempdir()?;
         Self::open(dir.path())
     }
+
+    /// Migrates the database to the current [`SCHEMA_VERSION`], if needed.
+    fn migrate(&self) -> anyhow::Result<()> {
+        let version = self
+            .db
+            .get(SCHEMA_VERSION_KEY)?
+            .and_then(|v| v.first().copied())
+            .unwrap_or(0);
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        type Migration = fn(&SledStore) -> anyhow::Result<()>;
+        let steps: [(u8, &str, Migration); 8] = [
+            (
+                1,
+                "Migrating the store to typed chain ids",
+                Self::migrate_to_typed_chain_ids,
+            ),
+            (
+                2,
+                "Migrating the store to substrate tree ids",
+                Self::migrate_to_substrate_tree_ids,
+            ),
+            (
+                3,
+                "Indexing the cached leaves by their commitments",
+                Self::index_leaves,
+            ),
+            (
+                4,
+                "Storing the cached leaves in segments",
+                Self::segment_leaves,
+            ),
+            (
+                5,
+                "Counting the cached leaves and the queued items",
+                Self::count_entries,
+            ),
+            (
+                6,
+                "Moving the history of the pallet watchers to their own tree ids",
+                Self::migrate_to_pallet_tree_ids,
+            ),
+            (
+                7,
+                "Indexing the signed proposals by their resource ids and nonces",
+                Self::index_proposals,
+            ),
+            (
+                8,
+                "Indexing the cached leaves by their deposit blocks",
+                Self::index_leaves_by_block,
+            ),
+        ];
+        // the version is recorded after every step, so an interrupted migration
+        // resumes from the step it stopped at.
+        for (step, description, migration) in steps {
+            if version >= step {
+                continue;
+            }
+            tracing::info!("{}", description);
+            migration(self)?;
+            self.db.insert(SCHEMA_VERSION_KEY, &[step])?;
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the keys and the trees that used plain chain ids to use typed chain ids.
+    ///
+    /// Every tree is rewritten in a transaction, which also records it in the
+    /// [`TYPED_CHAIN_IDS_TREE`], so the trees rewritten already are skipped when an
+    /// interrupted migration runs again. The renamed trees are copied before the old
+    /// ones are dropped, a name that is typed already is never renamed again.
+    fn migrate_to_typed_chain_ids(&self) -> anyhow::Result<()> {
+        let migrated = self.db.open_tree(TYPED_CHAIN_IDS_TREE)?;
+        // history keys used to be the chain id as u128 (LE) followed by the
+        // contract address (EVM) or the node name (Substrate).
+        for name in [
+            "last_block_numbers",
+            "last_deposit_block_number",
+            "leaves_snapshots",
+        ] {
+            if migrated.contains_key(name)? {
+                continue;
+            }
+            let tree = self.db.open_tree(name)?;
+            let mut batch = sled::Batch::default();
+            for entry in tree.iter() {
+                let (k, v) = entry?;
+                if k.len() < 16 {
+                    continue;
+                }
+                let mut chain_id = [0u8; 16];
+                chain_id.copy_from_slice(&k[..16]);
+                let chain_id = u32::try_from(u128::from_le_bytes(chain_id))
+                    .map_err(|_| {
+                        anyhow::anyhow!(
+                            "chain id of {:?} in {} does not fit in a typed chain id",
+                            k,
+                            name
+                        )
+                    })?;
+                let rest = &k[16..];
+                let typed_chain_id = if rest.len() == 20 {
+                    TypedChainId::Evm(chain_id)
+                } else {
+                    TypedChainId::Substrate(chain_id)
+                };
+                let mut new_key = typed_chain_id.to_bytes().to_vec();
+                new_key.extend_from_slice(rest);
+                batch.remove(k);
+                batch.insert(new_key, v);
+            }
+            apply_migration_batch(&tree, &migrated, name, &batch)?;
+        }
+        // registered bridges used to be keyed by the address and the chain id as U256 (BE).
+        if !migrated.contains_key("bridges")? {
+            let bridges = self.db.open_tree("bridges")?;
+            let mut batch = sled::Batch::default();
+            for entry in bridges.iter() {
+                let (k, v) = entry?;
+                let key: BridgeKey = serde_json::from_slice(&v)?;
+                batch.remove(k);
+                batch.insert(key.to_bytes(), serde_json::to_vec(&key)?);
+            }
+            apply_migration_batch(&bridges, &migrated, "bridges", &batch)?;
+        }
+        // the chain id is part of the name of the leaves and the bridge command trees.
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let new_name = if let Some(rest) = name.strip_prefix("leaves/") {
+                rest.split_once('/').and_then(|(chain_id, address)| {
+                    // the typed chain ids do not fit in a `u32`.
+                    let chain_id = types::U256::from_dec_str(chain_id).ok()?;
+                    let typed =
+                        TypedChainId::Evm(u32::try_from(chain_id).ok()?);
+                    Some(format!("leaves/{}/{}", typed.chain_id(), address))
+                })
+            } else if let Some(rest) = name.strip_prefix("queue_bridge_cmd_") {
+                rest.split_once('_').and_then(|(chain_id, address)| {
+                    let chain_id = types::U256::from_dec_str(chain_id).ok()?;
+                    let typed =
+                        TypedChainId::Evm(u32::try_from(chain_id).ok()?);
+                    Some(format!(
+                        "queue_bridge_cmd_{}_{}",
+                        typed.chain_id(),
+                        address
+                    ))
+                })
+            } else {
+                None
+            };
+            let new_name = match new_name {
+                Some(v) => v,
+                None => continue,
+            };
+            let old_tree = self.db.open_tree(&name)?;
+            let new_tree = self.db.open_tree(&new_name)?;
+            for entry in old_tree.iter() {
+                let (k, v) = entry?;
+                new_tree.insert(k, v)?;
+            }
+            self.db.drop_tree(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the commitment to leaf index lookup of all the cached leaves.
+    fn index_leaves(&self) -> anyhow::Result<()> {
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let rest = match name.strip_prefix("leaves/") {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let tree = self.db.open_tree(&name)?;
+            let index_tree =
+                self.db.open_tree(format!("leaves_index/{}", rest))?;
+            let mut batch = sled::Batch::default();
+            for entry in tree.iter() {
+                let (k, v) = entry?;
+                batch.insert(v, k);
+            }
+            index_tree.apply_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the resource id and nonce lookup of all the signed proposals.
+    fn index_proposals(&self) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(PROPOSALS_TREE)?;
+        let index_tree = self.db.open_tree(PROPOSALS_INDEX_TREE)?;
+        let mut batch = sled::Batch::default();
+        for entry in tree.iter() {
+            let (k, v) = entry?;
+            let proposal: SignedProposal = codec::decode(&v)?;
+            if let Some(index_key) = proposal_index_key(&proposal) {
+                batch.insert(index_key, k);
+            }
+        }
+        index_tree.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Indexes the cached leaves of every contract by their deposit blocks, so the leaves
+    /// of a block range are read without scanning all of them, and counts the leaves with
+    /// a recorded block.
+    ///
+    /// The index is rebuilt as a whole, so running it again is fine.
+    fn index_leaves_by_block(&self) -> anyhow::Result<()> {
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            if !name.starts_with("leaves_blocks/") {
+                continue;
+            }
+            let tree = self.db.open_tree(&name)?;
+            let by_block = self.db.open_tree(name.replacen(
+                "leaves_blocks/",
+                "leaves_by_block/",
+                1,
+            ))?;
+            let mut batch = sled::Batch::default();
+            let mut count = 0u64;
+            for entry in tree.iter() {
+                let (k, v) = entry?;
+                let mut index = [0u8; 4];
+                index.copy_from_slice(&k);
+                let block_number = types::U64::from_little_endian(&v);
+                batch.insert(
+                    leaf_block_key(block_number, u32::from_be_bytes(index)),
+                    Vec::new(),
+                );
+                count += 1;
+            }
+            by_block.apply_batch(batch)?;
+            counts.insert(name.as_bytes(), &count.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the cached leaves, that used to be keyed by their index (LE), into segments.
+    ///
+    /// Every contract is rewritten in a transaction, which also records it in the
+    /// [`SEGMENTED_LEAVES_TREE`], so the contracts rewritten already are skipped when
+    /// an interrupted migration runs again.
+    fn segment_leaves(&self) -> anyhow::Result<()> {
+        let segmented = self.db.open_tree(SEGMENTED_LEAVES_TREE)?;
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            if !name.starts_with("leaves/")
+                || segmented.contains_key(name.as_bytes())?
+            {
+                continue;
+            }
+            let tree = self.db.open_tree(&name)?;
+            let mut segments: BTreeMap<u32, LeavesSegment> = BTreeMap::new();
+            // the old keys are removed and the segments inserted in a single batch,
+            // the segment keys could be the same as the old ones.
+            let mut batch = sled::Batch::default();
+            for entry in tree.iter() {
+                let (k, v) = entry?;
+                anyhow::ensure!(
+                    k.len() == 4 && v.len() == 32,
+                    "unexpected leaf in {}, expected a 4 bytes index and a 32 bytes leaf",
+                    name,
+                );
+                let index = read_leaf_index(&k);
+                segments
+                    .entry(index / SEGMENT_SIZE)
+                    .or_default()
+                    .insert(index, types::H256::from_slice(&v));
+                batch.remove(k);
+            }
+            for (n, segment) in segments {
+                batch.insert(&n.to_be_bytes(), segment.encode());
+            }
+            apply_migration_batch(&tree, &segmented, &name, &batch)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the number of the cached leaves of every contract, and of the items
+    /// of every queue, so they are not counted one by one anymore.
+    fn count_entries(&self) -> anyhow::Result<()> {
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let tree = self.db.open_tree(&name)?;
+            if name.starts_with("leaves/") {
+                let count = tree
+                    .iter()
+                    .values()
+                    .map(|v| Ok(LeavesSegment::decode(&v?)?.len() as u64))
+                    .sum::<anyhow::Result<u64>>()?;
+                counts.insert(name.as_bytes(), &count.to_be_bytes())?;
+            } else if name.starts_with("queue_") {
+                let prefix =
+                    tree.get("key_prefix")?.unwrap_or_else(|| b"item".into());
+                let len = tree.scan_prefix(prefix).count() as u64;
+                tree.insert(QUEUE_LEN_KEY, &len.to_be_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the Substrate history keys, that used to be keyed by the node name,
+    /// to be keyed by the tree id.
+    ///
+    /// The node name can't be mapped to a tree id, so the entries are moved to the
+    /// tree `0`, which was the one used by the pallet watchers back then.
+    fn migrate_to_substrate_tree_ids(&self) -> anyhow::Result<()> {
+        const CHAIN_ID_LENGTH: usize = 6;
+        let substrate = TypedChainId::Substrate(0).to_bytes();
+        let is_substrate =
+            |k: &[u8]| k.len() > CHAIN_ID_LENGTH && k[..2] == substrate[..2];
+        let rewrite = |k: &[u8]| {
+            let mut new_key = k[..CHAIN_ID_LENGTH].to_vec();
+            new_key.extend_from_slice(&0u32.to_be_bytes());
+            new_key
+        };
+        for name in ["last_block_numbers", "last_deposit_block_number"] {
+            let tree = self.db.open_tree(name)?;
+            let entries = tree.iter().collect::<Result<Vec<_>, _>>()?;
+            for (k, v) in entries {
+                if !is_substrate(&k) {
+                    continue;
+                }
+                let new_key = rewrite(&k);
+                tree.remove(&k)?;
+                // if more than one node was watching the same chain, keep the
+                // lowest block number, so no events are missed.
+                let keep = match tree.get(&new_key)? {
+                    Some(existing) => {
+                        types::U64::from_little_endian(&existing)
+                            > types::U64::from_little_endian(&v)
+                    }
+                    None => true,
+                };
+                if keep {
+                    tree.insert(new_key, v)?;
+                }
+            }
+        }
+        let snapshots = self.db.open_tree("leaves_snapshots")?;
+        let entries = snapshots.iter().collect::<Result<Vec<_>, _>>()?;
+        for (k, v) in entries {
+            if is_substrate(&k) {
+                snapshots.remove(&k)?;
+                snapshots.insert(rewrite(&k), v)?;
+            }
+        }
+        for name in self.db.tree_names() {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let chain_id = match name
+                .strip_prefix("leaves/")
+                .and_then(|rest| rest.split_once('/'))
+                .and_then(|(chain_id, _)| chain_id.parse::<u64>().ok())
+            {
+                Some(v) => TypedChainId::from(v),
+                None => continue,
+            };
+            if !matches!(chain_id, TypedChainId::Substrate(_)) {
+                continue;
+            }
+            let new_name = format!("leaves/{}/0", chain_id.chain_id());
+            if new_name == name {
+                continue;
+            }
+            let old_tree = self.db.open_tree(&name)?;
+            let new_tree = self.db.open_tree(&new_name)?;
+            for entry in old_tree.iter() {
+                let (k, v) = entry?;
+                new_tree.insert(k, v)?;
+            }
+            self.db.drop_tree(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Gives every pallet watcher its own history, they all used to share the tree `0`.
+    ///
+    /// Both pallet watchers start from the block number of the shared history, so no
+    /// events are missed, if one was ahead of the other, it will see some events again.
+    fn migrate_to_pallet_tree_ids(&self) -> anyhow::Result<()> {
+        let substrate = TypedChainId::Substrate(0).to_bytes();
+        for name in ["last_block_numbers", "last_deposit_block_number"] {
+            let tree = self.db.open_tree(name)?;
+            let entries = tree.iter().collect::<Result<Vec<_>, _>>()?;
+            for (k, v) in entries {
+                let shared = k.len() == substrate.len() + 4
+                    && k[..2] == substrate[..2]
+                    && k[substrate.len()..] == 0u32.to_be_bytes();
+                if !shared {
+                    continue;
+                }
+                for tree_id in [
+                    pallet_tree_ids::DKG_PROPOSAL_HANDLER,
+                    pallet_tree_ids::BRIDGE,
+                ] {
+                    let mut new_key = k[..substrate.len()].to_vec();
+                    new_key.extend_from_slice(&tree_id.to_be_bytes());
+                    if tree.get(&new_key)?.is_none() {
+                        tree.insert(new_key, v.clone())?;
+                    }
+                }
+                tree.remove(&k)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Updates the JSON value at `key` in the `tree` atomically, `update` gets the current
+/// value, if any, and returns the new one.
+///
+/// The `update` could run more than once, if the value got updated concurrently. An
+/// undecodable value is left untouched, and the error returned.
+fn update_json<T, F>(
+    tree: &sled::Tree,
+    key: &[u8],
+    mut update: F,
+) -> anyhow::Result<()>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnMut(Option<T>) -> T,
+{
+    let mut error = None;
+    tree.update_and_fetch(key, |old| {
+        error = None;
+        let current = match old.map(serde_json::from_slice).transpose() {
+            Ok(v) => v,
+            Err(e) => {
+                error = Some(e);
+                return old.map(|v| v.to_vec());
+            }
+        };
+        match serde_json::to_vec(&update(current)) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error = Some(e);
+                old.map(|v| v.to_vec())
+            }
+        }
+    })?;
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Applies the `batch` of a migration to the `tree` named `name`, and records that name
+/// in the `migrated` tree, in a single transaction.
+fn apply_migration_batch(
+    tree: &sled::Tree,
+    migrated: &sled::Tree,
+    name: &str,
+    batch: &sled::Batch,
+) -> anyhow::Result<()> {
+    (tree, migrated)
+        .transaction(|(tree, migrated)| {
+            tree.apply_batch(batch)?;
+            migrated.insert(name.as_bytes(), sled::IVec::default())?;
+            Ok::<_, ConflictableTransactionError<()>>(())
+        })
+        .map_err(|e| match e {
+            TransactionError::Abort(()) => {
+                anyhow::anyhow!("migrating {} got aborted", name)
+            }
+            TransactionError::Storage(e) => e.into(),
+        })
+}
+
+/// The key of the schema version in the default tree.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// The current version of the database schema.
+///
+/// * `0` - plain chain ids.
+/// * `1` - typed chain ids, see [`TypedChainId`].
+/// * `2` - Substrate keys use the tree id instead of the node name.
+/// * `3` - the leaves are indexed by their commitments.
+/// * `4` - the leaves are stored in segments, see [`LeavesSegment`].
+/// * `5` - the cached leaves and the queued items are counted.
+/// * `6` - every pallet watcher has its own tree id, see [`pallet_tree_ids`].
+/// * `7` - the signed proposals are indexed by their resource ids and nonces.
+/// * `8` - the leaves are indexed by their deposit blocks.
+const SCHEMA_VERSION: u8 = 8;
+
+/// The tree holding the names of the trees rewritten to typed chain ids, see
+/// [`SledStore::migrate_to_typed_chain_ids`].
+const TYPED_CHAIN_IDS_TREE: &str = "typed_chain_ids";
+/// The tree holding the names of the leaves trees rewritten into segments, see
+/// [`SledStore::segment_leaves`].
+const SEGMENTED_LEAVES_TREE: &str = "segmented_leaves";
+/// The tree holding the number of cached leaves of every contract, by their leaves tree name,
+/// and the number of those with a recorded deposit block, by their blocks tree name.
+const LEAVES_COUNTS_TREE: &str = "leaves_counts";
+/// The key of the number of items in a queue tree.
+const QUEUE_LEN_KEY: &str = "len";
+
+/// The number of leaves stored together in one segment.
+const SEGMENT_SIZE: u32 = 1024;
+/// The size of the bitmap of the present leaves of a segment, in bytes.
+const SEGMENT_BITMAP_SIZE: usize = SEGMENT_SIZE as usize / 8;
+
+/// A segment of the leaves of a contract, the segment `n` holds the leaves with
+/// an index from `n * SEGMENT_SIZE` to `(n + 1) * SEGMENT_SIZE - 1`.
+///
+/// It is encoded as a bitmap of the present leaves, followed by the present leaves
+/// (32 bytes each) sorted by their index.
+#[derive(Debug, Clone, Default)]
+struct LeavesSegment {
+    /// The leaves, by their offset in the segment.
+    leaves: BTreeMap<u32, types::H256>,
+}
+
+impl LeavesSegment {
+    fn decode(bytes: &[u8]) -> std::io::Result<Self> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid leaves segment",
+            )
+        };
+        if bytes.len() < SEGMENT_BITMAP_SIZE {
+            return Err(invalid());
+        }
+        let (bitmap, mut rest) = bytes.split_at(SEGMENT_BITMAP_SIZE);
+        let mut leaves = BTreeMap::new();
+        for offset in 0..SEGMENT_SIZE {
+            let present = bitmap[offset as usize / 8] & (1 << (offset % 8));
+            if present == 0 {
+                continue;
+            }
+            if rest.len() < 32 {
+                return Err(invalid());
+            }
+            let (leaf, tail) = rest.split_at(32);
+            leaves.insert(offset, types::H256::from_slice(leaf));
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { leaves })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; SEGMENT_BITMAP_SIZE];
+        for (offset, leaf) in &self.leaves {
+            bytes[*offset as usize / 8] |= 1 << (offset % 8);
+            bytes.extend_from_slice(leaf.as_bytes());
+        }
+        bytes
+    }
+
+    /// Appends the leaves, with their indices in this segment, to the encoded segment
+    /// without decoding it, which is the case of the new deposits.
+    ///
+    /// Returns `None` unless they are sorted and all come after the last leaf of the
+    /// segment, or if the segment is malformed.
+    fn append(bytes: &[u8], leaves: &[(u32, types::H256)]) -> Option<Vec<u8>> {
+        if bytes.len() < SEGMENT_BITMAP_SIZE {
+            return None;
+        }
+        let (bitmap, rest) = bytes.split_at(SEGMENT_BITMAP_SIZE);
+        let present: usize =
+            bitmap.iter().map(|byte| byte.count_ones() as usize).sum();
+        if rest.len() != present * 32 {
+            return None;
+        }
+        let is_present = |offset: u32| {
+            bitmap[offset as usize / 8] & (1 << (offset % 8)) != 0
+        };
+        let mut next = (0..SEGMENT_SIZE)
+            .rev()
+            .find(|offset| is_present(*offset))
+            .map_or(0, |last| last + 1);
+        let mut appended = bytes.to_vec();
+        for (index, leaf) in leaves {
+            let offset = index % SEGMENT_SIZE;
+            if offset < next {
+                return None;
+            }
+            appended[offset as usize / 8] |= 1 << (offset % 8);
+            appended.extend_from_slice(leaf.as_bytes());
+            next = offset + 1;
+        }
+        Some(appended)
+    }
+
+    /// Inserts the leaf with that index, it should belong to this segment.
+    fn insert(&mut self, index: u32, leaf: types::H256) {
+        self.leaves.insert(index % SEGMENT_SIZE, leaf);
+    }
+
+    /// Returns the leaf with that index, if the segment has it.
+    fn get(&self, index: u32) -> Option<types::H256> {
+        self.leaves.get(&(index % SEGMENT_SIZE)).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Removes the leaves from the leaf `index` on, returning the removed ones along with
+    /// their indices, the segment is the segment `n`.
+    fn truncate(&mut self, n: u32, index: u32) -> Vec<(u32, types::H256)> {
+        let from = index.saturating_sub(n * SEGMENT_SIZE);
+        let removed = self.leaves.split_off(&from);
+        removed
+            .into_iter()
+            .map(|(offset, leaf)| (n * SEGMENT_SIZE + offset, leaf))
+            .collect()
+    }
+
+    /// Returns the leaves of the segment `n`, along with their indices.
+    fn into_leaves(self, n: u32) -> impl Iterator<Item = (u32, types::H256)> {
+        self.leaves
+            .into_iter()
+            .map(move |(offset, leaf)| (n * SEGMENT_SIZE + offset, leaf))
+    }
+}
+
+/// Returns the name of the tree holding the leaves of that contract.
+fn leaves_tree_name(key: &HistoryStoreKey) -> anyhow::Result<String> {
+    let chain_id = key.typed_chain_id()?.chain_id();
+    let name = match key {
+        HistoryStoreKey::Evm { address, .. } => {
+            format!("leaves/{}/{}", chain_id, address)
+        }
+        HistoryStoreKey::Substrate { tree_id, .. } => {
+            format!("leaves/{}/{}", chain_id, tree_id)
+        }
+    };
+    Ok(name)
+}
+
+/// Reads a count stored as a big endian `u64`, a missing one is zero.
+fn read_count(bytes: Option<sled::IVec>) -> u64 {
+    bytes
+        .and_then(|v| std::convert::TryFrom::try_from(v.as_ref()).ok())
+        .map(u64::from_be_bytes)
+        .unwrap_or(0)
+}
+
+/// Reads a leaf index of the commitment to leaf index lookup, stored as a little endian `u32`.
+fn read_leaf_index(bytes: &[u8]) -> u32 {
+    let mut index = [0u8; 4];
+    index.copy_from_slice(bytes);
+    u32::from_le_bytes(index)
+}
+
+/// Returns the name of the tree holding the commitment to leaf index lookup of that contract.
+fn leaves_index_tree_name(key: &HistoryStoreKey) -> anyhow::Result<String> {
+    Ok(leaves_tree_name(key)?.replacen("leaves/", "leaves_index/", 1))
+}
+
+/// Returns the name of the tree holding the leaf index to deposit block lookup of that contract.
+fn leaves_blocks_tree_name(key: &HistoryStoreKey) -> anyhow::Result<String> {
+    Ok(leaves_tree_name(key)?.replacen("leaves/", "leaves_blocks/", 1))
+}
+
+/// Returns the name of the tree holding the leaves of that contract by their deposit
+/// blocks, see [`leaf_block_key`].
+fn leaves_by_block_tree_name(key: &HistoryStoreKey) -> anyhow::Result<String> {
+    Ok(leaves_tree_name(key)?.replacen("leaves/", "leaves_by_block/", 1))
+}
+
+/// The key of a leaf in the tree of the leaves by block: its deposit block then its index,
+/// both in big endian, so the leaves of a block range are read in order.
+fn leaf_block_key(block_number: types::U64, index: u32) -> Vec<u8> {
+    let mut key = block_number.as_u64().to_be_bytes().to_vec();
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+impl HistoryStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn set_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let tree = self.db.open_tree("last_block_numbers")?;
+        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+        block_number.to_little_endian(&mut bytes);
+        let key: HistoryStoreKey = key.into();
+        let old = tree.insert(key.to_bytes()?, &bytes)?;
+        match old {
+            Some(v) => Ok(types::U64::from_little_endian(&v)),
+            None => Ok(block_number),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        default_block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let tree = self.db.open_tree("last_block_numbers")?;
+        let key: HistoryStoreKey = key.into();
+        let val = tree.get(key.to_bytes()?)?;
+        match val {
+            Some(v) => Ok(types::U64::from_little_endian(&v)),
+            None => Ok(default_block_number),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+        block_number: types::U64,
+    ) -> anyhow::Result<Option<types::U64>> {
+        let tree = self.db.open_tree("last_event_block_numbers")?;
+        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+        block_number.to_little_endian(&mut bytes);
+        let key = event_cursor_key(key.into(), event)?;
+        let old = tree.insert(key, &bytes)?;
+        Ok(old.map(|v| types::U64::from_little_endian(&v)))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_last_event_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        event: &str,
+    ) -> anyhow::Result<Option<types::U64>> {
+        let tree = self.db.open_tree("last_event_block_numbers")?;
+        let val = tree.get(event_cursor_key(key.into(), event)?)?;
+        Ok(val.map(|v| types::U64::from_little_endian(&v)))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("synced_from_block_numbers")?;
+        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+        from.to_little_endian(&mut bytes);
+        let key: HistoryStoreKey = key.into();
+        tree.insert(key.to_bytes()?, &bytes)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_synced_from<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<types::U64>> {
+        let tree = self.db.open_tree("synced_from_block_numbers")?;
+        let key: HistoryStoreKey = key.into();
+        let val = tree.get(key.to_bytes()?)?;
+        Ok(val.map(|v| types::U64::from_little_endian(&v)))
+    }
+}
+
+/// The key of the cursor of one kind of events of a contract.
+fn event_cursor_key(
+    key: HistoryStoreKey,
+    event: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = key.to_bytes()?;
+    bytes.extend_from_slice(event.as_bytes());
+    Ok(bytes)
+}
+
+impl LeafCacheStore for SledStore {
+    type Output = Vec<types::H256>;
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Self::Output> {
+        let leaves = self
+            .get_leaves_with_index(key)?
+            .into_iter()
+            .map(|(_, leaf)| leaf)
+            .collect();
+        Ok(leaves)
+    }
+
+    #[tracing::instrument(skip(self, leaves))]
+    fn insert_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        leaves: &[(u32, types::H256)],
+    ) -> anyhow::Result<()> {
+        let key: HistoryStoreKey = key.into();
+
+        let tree_name = leaves_tree_name(&key)?;
+        let tree = self.db.open_tree(&tree_name)?;
+        let index_tree = self.db.open_tree(leaves_index_tree_name(&key)?)?;
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        let mut segments: BTreeMap<u32, Vec<(u32, types::H256)>> =
+            BTreeMap::new();
+        let mut index_batch = sled::Batch::default();
+        for (k, v) in leaves {
+            segments.entry(k / SEGMENT_SIZE).or_default().push((*k, *v));
+            index_batch.insert(v.as_bytes(), &k.to_le_bytes());
+        }
+        // the segments, the index and the count are written in a single transaction,
+        // so a cached leaf always has an index and is counted.
+        let result = (&tree, &index_tree, &counts).transaction(
+            |(tree, index_tree, counts)| -> ConflictableTransactionResult<(), anyhow::Error> {
+                let mut added = 0;
+                for (n, leaves) in &segments {
+                    let segment_key = n.to_be_bytes();
+                    let cached = tree.get(segment_key)?;
+                    // the new leaves are appended, instead of rewriting the segment.
+                    let appended = cached
+                        .as_ref()
+                        .and_then(|v| LeavesSegment::append(v, leaves));
+                    if let Some(appended) = appended {
+                        tree.insert(&segment_key, appended)?;
+                        added += leaves.len() as u64;
+                        continue;
+                    }
+                    let mut segment = match cached {
+                        Some(v) => LeavesSegment::decode(&v).map_err(|e| {
+                            ConflictableTransactionError::Abort(e.into())
+                        })?,
+                        None => LeavesSegment::default(),
+                    };
+                    for (index, leaf) in leaves {
+                        match segment.get(*index) {
+                            Some(cached) if cached == *leaf => continue,
+                            // never overwrite a cached leaf, the whole write is aborted.
+                            Some(cached) => {
+                                let conflict = LeafConflict {
+                                    key: key.clone(),
+                                    index: *index,
+                                    cached,
+                                    leaf: *leaf,
+                                };
+                                return Err(ConflictableTransactionError::Abort(
+                                    conflict.into(),
+                                ));
+                            }
+                            None => {
+                                segment.insert(*index, *leaf);
+                                added += 1u64;
+                            }
+                        }
+                    }
+                    tree.insert(&segment_key, segment.encode())?;
+                }
+                index_tree.apply_batch(&index_batch)?;
+                let count = read_count(counts.get(tree_name.as_bytes())?);
+                counts.insert(tree_name.as_bytes(), &(count + added).to_be_bytes())?;
+                Ok(())
+            },
+        );
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(e)) => {
+                if let Some(conflict) = e.downcast_ref::<LeafConflict>() {
+                    conflict.report();
+                }
+                Err(e)
+            }
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        self.iter_leaves_with_index(key)?.collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn iter_leaves_with_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<LeavesIter> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree(leaves_tree_name(&key)?)?;
+        // the segments are keyed by their number in big endian, so the leaves
+        // are read in order, one segment at a time.
+        let leaves = tree.iter().flat_map(|entry| {
+            let segment =
+                entry.map_err(anyhow::Error::from).and_then(|(k, v)| {
+                    let mut n = [0u8; 4];
+                    n.copy_from_slice(&k);
+                    let segment = LeavesSegment::decode(&v)?;
+                    Ok(segment.into_leaves(u32::from_be_bytes(n)))
+                });
+            let (leaves, error) = match segment {
+                Ok(leaves) => (Some(leaves.map(Ok)), None),
+                Err(e) => (None, Some(Err(e))),
+            };
+            leaves.into_iter().flatten().chain(error)
+        });
+        Ok(Box::new(leaves))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaf_index<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<Option<u32>> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree(leaves_index_tree_name(&key)?)?;
+        let index = tree
+            .get(commitment.as_bytes())?
+            .map(|v| read_leaf_index(&v));
+        Ok(index)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_count<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<usize> {
+        let key: HistoryStoreKey = key.into();
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        let count = counts.get(leaves_tree_name(&key)?.as_bytes())?;
+        Ok(read_count(count) as usize)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn roll_back_leaves<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from_index: u32,
+    ) -> anyhow::Result<()> {
+        let key: HistoryStoreKey = key.into();
+        let tree_name = leaves_tree_name(&key)?;
+        let tree = self.db.open_tree(&tree_name)?;
+        let index_tree = self.db.open_tree(leaves_index_tree_name(&key)?)?;
+        let blocks_tree_name = leaves_blocks_tree_name(&key)?;
+        let blocks_tree = self.db.open_tree(&blocks_tree_name)?;
+        let by_block = self.db.open_tree(leaves_by_block_tree_name(&key)?)?;
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        let block_number = match blocks_tree.get(from_index.to_be_bytes())? {
+            Some(v) => types::U64::from_little_endian(&v),
+            None => {
+                tracing::warn!(
+                    "No block recorded for the leaf #{} of {}, clearing it",
+                    from_index,
+                    key
+                );
+                return self.clear_contract(key);
+            }
+        };
+        let first_segment = (from_index / SEGMENT_SIZE).to_be_bytes();
+        let segments = tree
+            .range(first_segment..)
+            .map(|entry| {
+                let (k, v) = entry?;
+                let mut n = [0u8; 4];
+                n.copy_from_slice(&k);
+                Ok((u32::from_be_bytes(n), LeavesSegment::decode(&v)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        (&tree, &index_tree, &blocks_tree, &by_block, &counts).transaction(
+            |(tree, index_tree, blocks_tree, by_block, counts)| -> ConflictableTransactionResult<(), anyhow::Error> {
+                let mut removed = 0;
+                let mut removed_blocks = 0u64;
+                for (n, segment) in &segments {
+                    let mut segment = segment.clone();
+                    let leaves = segment.truncate(*n, from_index);
+                    for (index, leaf) in &leaves {
+                        index_tree.remove(leaf.as_bytes())?;
+                        if let Some(old) = blocks_tree.remove(&index.to_be_bytes())? {
+                            let old = types::U64::from_little_endian(&old);
+                            by_block.remove(leaf_block_key(old, *index))?;
+                            removed_blocks += 1;
+                        }
+                    }
+                    removed += leaves.len() as u64;
+                    if segment.leaves.is_empty() {
+                        tree.remove(&n.to_be_bytes())?;
+                    } else {
+                        tree.insert(&n.to_be_bytes(), segment.encode())?;
+                    }
+                }
+                let count = read_count(counts.get(tree_name.as_bytes())?);
+                counts.insert(
+                    tree_name.as_bytes(),
+                    &count.saturating_sub(removed).to_be_bytes(),
+                )?;
+                let count = read_count(counts.get(blocks_tree_name.as_bytes())?);
+                counts.insert(
+                    blocks_tree_name.as_bytes(),
+                    &count.saturating_sub(removed_blocks).to_be_bytes(),
+                )?;
+                Ok(())
+            },
+        )
+        .map_err(|e| match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => e.into(),
+        })?;
+        // the block of the leaf is handled again.
+        let before = block_number.saturating_sub(types::U64::one());
+        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+        before.to_little_endian(&mut bytes);
+        let rewind = |tree: &sled::Tree, k: &[u8]| -> anyhow::Result<()> {
+            let v = tree.get(k)?;
+            if matches!(v, Some(v) if types::U64::from_little_endian(&v) > before)
+            {
+                tree.insert(k, &bytes)?;
+            }
+            Ok(())
+        };
+        for tree in ["last_deposit_block_number", "last_block_numbers"] {
+            rewind(&self.db.open_tree(tree)?, &key.to_bytes()?)?;
+        }
+        let tree = self.db.open_tree("last_event_block_numbers")?;
+        for entry in tree.scan_prefix(key.to_bytes()?) {
+            let (k, _) = entry?;
+            rewind(&tree, &k)?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn clear_contract<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<()> {
+        let key: HistoryStoreKey = key.into();
+        let tree_name = leaves_tree_name(&key)?;
+        self.db.drop_tree(&tree_name)?;
+        self.db.drop_tree(leaves_index_tree_name(&key)?)?;
+        let blocks_tree_name = leaves_blocks_tree_name(&key)?;
+        self.db.drop_tree(&blocks_tree_name)?;
+        self.db.drop_tree(leaves_by_block_tree_name(&key)?)?;
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        counts.remove(tree_name.as_bytes())?;
+        counts.remove(blocks_tree_name.as_bytes())?;
+        self.db
+            .open_tree("last_deposit_block_number")?
+            .remove(key.to_bytes()?)?;
+        self.db
+            .open_tree("last_block_numbers")?
+            .remove(key.to_bytes()?)?;
+        self.db
+            .open_tree("synced_from_block_numbers")?
+            .remove(key.to_bytes()?)?;
+        for tree in ["last_event_block_numbers", "neighbor_roots"] {
+            let tree = self.db.open_tree(tree)?;
+            for entry in tree.scan_prefix(key.to_bytes()?) {
+                let (k, _) = entry?;
+                tree.remove(k)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn is_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<bool> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("resynced_contracts")?;
+        Ok(tree.contains_key(key.to_bytes()?)?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_contract_resynced<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        resynced: bool,
+    ) -> anyhow::Result<()> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("resynced_contracts")?;
+        if resynced {
+            tree.insert(key.to_bytes()?, &[1])?;
+        } else {
+            tree.remove(key.to_bytes()?)?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, block_numbers))]
+    fn insert_leaves_block_numbers<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_numbers: &[(u32, types::U64)],
+    ) -> anyhow::Result<()> {
+        let key: HistoryStoreKey = key.into();
+        let tree_name = leaves_blocks_tree_name(&key)?;
+        let tree = self.db.open_tree(&tree_name)?;
+        let by_block = self.db.open_tree(leaves_by_block_tree_name(&key)?)?;
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        // the blocks, the index by block and the count are written in a single
+        // transaction, so a recorded block is always indexed and counted.
+        (&tree, &by_block, &counts)
+            .transaction(|(tree, by_block, counts)| -> ConflictableTransactionResult<(), anyhow::Error> {
+                let mut added = 0u64;
+                for (index, block_number) in block_numbers {
+                    let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+                    block_number.to_little_endian(&mut bytes);
+                    match tree.insert(&index.to_be_bytes(), &bytes)? {
+                        Some(old) => {
+                            let old = types::U64::from_little_endian(&old);
+                            by_block.remove(leaf_block_key(old, *index))?;
+                        }
+                        None => added += 1,
+                    }
+                    by_block.insert(
+                        leaf_block_key(*block_number, *index),
+                        Vec::new(),
+                    )?;
+                }
+                let count = read_count(counts.get(tree_name.as_bytes())?);
+                counts.insert(tree_name.as_bytes(), &(count + added).to_be_bytes())?;
+                Ok(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => e.into(),
+            })
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_leaves_in_block_range<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        from: types::U64,
+        to: types::U64,
+    ) -> anyhow::Result<Vec<(u32, types::H256)>> {
+        let key: HistoryStoreKey = key.into();
+        let counts = self.db.open_tree(LEAVES_COUNTS_TREE)?;
+        let leaves_count =
+            read_count(counts.get(leaves_tree_name(&key)?.as_bytes())?);
+        let recorded =
+            read_count(counts.get(leaves_blocks_tree_name(&key)?.as_bytes())?);
+        if recorded < leaves_count {
+            return Err(MissingLeafBlocks {
+                key,
+                missing: (leaves_count - recorded) as usize,
+            }
+            .into());
+        }
+        let by_block = self.db.open_tree(leaves_by_block_tree_name(&key)?)?;
+        let range = leaf_block_key(from, 0)..=leaf_block_key(to, u32::MAX);
+        let mut indices = by_block
+            .range(range)
+            .keys()
+            .map(|k| {
+                let k = k?;
+                let mut index = [0u8; 4];
+                index.copy_from_slice(&k[8..]);
+                Ok(u32::from_be_bytes(index))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        indices.sort_unstable();
+        // the leaves are read from their segments, every segment is read once.
+        let tree = self.db.open_tree(leaves_tree_name(&key)?)?;
+        let mut leaves = Vec::with_capacity(indices.len());
+        let mut segment: Option<(u32, LeavesSegment)> = None;
+        for index in indices {
+            let n = index / SEGMENT_SIZE;
+            if !matches!(segment, Some((m, _)) if m == n) {
+                let cached = match tree.get(n.to_be_bytes())? {
+                    Some(v) => LeavesSegment::decode(&v)?,
+                    None => LeavesSegment::default(),
+                };
+                segment = Some((n, cached));
+            }
+            if let Some(leaf) = segment.as_ref().and_then(|(_, s)| s.get(index))
+            {
+                leaves.push((index, leaf));
+            }
+        }
+        Ok(leaves)
+    }
+
+    fn get_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<types::U64> {
+        let tree = self.db.open_tree("last_deposit_block_number")?;
+        let key: HistoryStoreKey = key.into();
+        let val = tree.get(key.to_bytes()?)?;
+        match val {
+            Some(v) => Ok(types::U64::from_little_endian(&v)),
+            None => Ok(types::U64::from(0)),
+        }
+    }
+
+    fn insert_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        block_number: types::U64,
+    ) -> anyhow::Result<types::U64> {
+        let tree = self.db.open_tree("last_deposit_block_number")?;
+        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
+        block_number.to_little_endian(&mut bytes);
+        let key: HistoryStoreKey = key.into();
+        let old = tree.insert(key.to_bytes()?, &bytes)?;
+        match old {
+            Some(v) => Ok(types::U64::from_little_endian(&v)),
+            None => Ok(block_number),
+        }
+    }
+}
+impl LeafWatchStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn watch_leaf<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        commitment: types::H256,
+    ) -> anyhow::Result<LeafWatch> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree(leaves_index_tree_name(&key)?)?;
+        // subscribe before reading, so a leaf cached in between is not missed.
+        let mut subscriber = tree.watch_prefix(commitment.as_bytes());
+        if let Some(v) = tree.get(commitment.as_bytes())? {
+            let index = read_leaf_index(&v);
+            return Ok(Box::pin(futures::future::ready(Some(index))));
+        }
+        Ok(Box::pin(async move {
+            while let Some(event) = (&mut subscriber).await {
+                if let sled::Event::Insert { value, .. } = event {
+                    return Some(read_leaf_index(&value));
+                }
+            }
+            None
+        }))
+    }
+}
+
+impl LeavesSnapshotStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn get_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Option<LeavesSnapshotInfo>> {
+        let tree = self.db.open_tree("leaves_snapshots")?;
+        let key: HistoryStoreKey = key.into();
+        match tree.get(key.to_bytes()?)? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_latest_leaves_snapshot<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        info: LeavesSnapshotInfo,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("leaves_snapshots")?;
+        let key: HistoryStoreKey = key.into();
+        tree.insert(key.to_bytes()?, serde_json::to_vec(&info)?)?;
+        Ok(())
+    }
+}
+impl RelayedTxStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn insert_relayed_tx(&self, tx: RelayedTx) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("relayed_txs")?;
+        let key = relayed_tx_key(tx.chain_id, tx.tx_hash);
+        tree.insert(key, serde_json::to_vec(&tx)?)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_relayed_tx(
+        &self,
+        chain_id: TypedChainId,
+        tx_hash: types::H256,
+    ) -> anyhow::Result<Option<RelayedTx>> {
+        let tree = self.db.open_tree("relayed_txs")?;
+        match tree.get(relayed_tx_key(chain_id, tx_hash))? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The key of a relayed transaction, the typed chain id followed by the tx hash.
+fn relayed_tx_key(chain_id: TypedChainId, tx_hash: types::H256) -> Vec<u8> {
+    let mut key = chain_id.to_bytes().to_vec();
+    key.extend_from_slice(tx_hash.as_bytes());
+    key
+}
+
+impl EarningsStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn record_earning(
+        &self,
+        chain_id: types::U256,
+        token: types::Address,
+        identity: Option<&str>,
+        fee: types::U256,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("earnings")?;
+        let mut key = evm_typed_chain_id(chain_id)?.to_bytes().to_vec();
+        key.extend_from_slice(token.as_bytes());
+        // the earnings of the default accounts keep their key from before the identities.
+        if let Some(identity) = identity {
+            key.extend_from_slice(identity.as_bytes());
+        }
+        update_json(&tree, &key, |earnings: Option<Earnings>| {
+            let mut earnings = earnings.unwrap_or(Earnings {
+                chain_id,
+                token,
+                total: types::U256::zero(),
+                withdrawals: 0,
+                identity: identity.map(Into::into),
+            });
+            earnings.total = earnings.total.saturating_add(fee);
+            earnings.withdrawals += 1;
+            earnings
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn earnings(&self) -> anyhow::Result<Vec<Earnings>> {
+        let tree = self.db.open_tree("earnings")?;
+        tree.iter()
+            .values()
+            .map(|v| Ok(serde_json::from_slice(&v?)?))
+            .collect()
+    }
+}
+
+impl AccountingStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn record_relay_cost(
+        &self,
+        chain_id: types::U256,
+        contract: types::Address,
+        token: types::Address,
+        identity: Option<&str>,
+        timestamp: u64,
+        gas_used: types::U256,
+        gas_spent: types::U256,
+        fee: types::U256,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("accounting")?;
+        let day = timestamp - timestamp % ACCOUNTING_BUCKET;
+        // the day comes first, so the buckets of a time range are contiguous.
+        let mut key = day.to_be_bytes().to_vec();
+        key.extend_from_slice(&evm_typed_chain_id(chain_id)?.to_bytes());
+        key.extend_from_slice(contract.as_bytes());
+        if let Some(identity) = identity {
+            key.extend_from_slice(identity.as_bytes());
+        }
+        update_json(&tree, &key, |entry: Option<AccountingEntry>| {
+            let mut entry = entry.unwrap_or(AccountingEntry {
+                day,
+                chain_id,
+                contract,
+                token,
+                gas_used: types::U256::zero(),
+                gas_spent: types::U256::zero(),
+                fees_earned: types::U256::zero(),
+                transactions: 0,
+                identity: identity.map(Into::into),
+            });
+            entry.gas_used = entry.gas_used.saturating_add(gas_used);
+            entry.gas_spent = entry.gas_spent.saturating_add(gas_spent);
+            entry.fees_earned = entry.fees_earned.saturating_add(fee);
+            entry.transactions += 1;
+            entry
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn accounting(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<AccountingEntry>> {
+        let tree = self.db.open_tree("accounting")?;
+        let start = from - from % ACCOUNTING_BUCKET;
+        let end = to - to % ACCOUNTING_BUCKET + 1;
+        tree.range(start.to_be_bytes()..end.to_be_bytes())
+            .values()
+            .map(|v| Ok(serde_json::from_slice(&v?)?))
+            .collect()
+    }
+}
+
+/// The prefix of the relay stats buckets of that resolution, in the `relay_stats` tree.
+fn relay_stats_prefix(resolution: StatsResolution) -> u8 {
+    match resolution {
+        StatsResolution::Hourly => 0,
+        StatsResolution::Daily => 1,
+    }
 }
 
-impl HistoryStore for SledStore {
+impl RelayStatsStore for SledStore {
     #[tracing::instrument(skip(self))]
-    fn set_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+    fn record_relay<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-        block_number: types::U64,
-    ) -> anyhow::Result<types::U64> {
-        let tree = self.db.open_tree("last_block_numbers")?;
-        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
-        block_number.to_little_endian(&mut bytes);
+        timestamp: u64,
+        reverted: bool,
+    ) -> anyhow::Result<()> {
         let key: HistoryStoreKey = key.into();
-        let old = tree.insert(key.to_bytes(), &bytes)?;
-        match old {
-            Some(v) => Ok(types::U64::from_little_endian(&v)),
-            None => Ok(block_number),
+        let tree = self.db.open_tree("relay_stats")?;
+        let (contract, tree_id) = match key {
+            HistoryStoreKey::Evm { address, .. } => (Some(address), None),
+            HistoryStoreKey::Substrate { tree_id, .. } => (None, Some(tree_id)),
+        };
+        for resolution in StatsResolution::ALL {
+            let start = resolution.bucket_of(timestamp);
+            // the start comes right after the resolution, so the buckets of a time range
+            // are contiguous.
+            let mut stats_key = vec![relay_stats_prefix(resolution)];
+            stats_key.extend_from_slice(&start.to_be_bytes());
+            stats_key.extend_from_slice(&key.to_bytes()?);
+            update_json(&tree, &stats_key, |stats: Option<RelayStats>| {
+                let mut stats = stats.unwrap_or(RelayStats {
+                    start,
+                    resolution,
+                    chain_id: key.chain_id(),
+                    contract,
+                    tree_id,
+                    relays: 0,
+                    reverted: 0,
+                });
+                stats.relays += 1;
+                if reverted {
+                    stats.reverted += 1;
+                }
+                stats
+            })?;
         }
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
-    fn get_last_block_number<K: Into<HistoryStoreKey> + Debug>(
+    fn relay_stats(
+        &self,
+        resolution: StatsResolution,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<RelayStats>> {
+        let tree = self.db.open_tree("relay_stats")?;
+        let prefix = relay_stats_prefix(resolution);
+        let bound = |timestamp: u64| {
+            let mut key = vec![prefix];
+            key.extend_from_slice(&timestamp.to_be_bytes());
+            key
+        };
+        let start = bound(resolution.bucket_of(from));
+        let end = bound(resolution.bucket_of(to) + 1);
+        tree.range(start..end)
+            .values()
+            .map(|v| Ok(serde_json::from_slice(&v?)?))
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn prune_relay_stats(&self, before: u64) -> anyhow::Result<usize> {
+        let tree = self.db.open_tree("relay_stats")?;
+        let mut removed = 0;
+        for resolution in StatsResolution::ALL {
+            let prefix = relay_stats_prefix(resolution);
+            let mut end = vec![prefix];
+            end.extend_from_slice(&before.to_be_bytes());
+            for key in tree.range(vec![prefix]..end).keys() {
+                tree.remove(key?)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl NullifierStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn insert_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-        default_block_number: types::U64,
-    ) -> anyhow::Result<types::U64> {
-        let tree = self.db.open_tree("last_block_numbers")?;
+        nullifier_hash: types::H256,
+        spent: SpentNullifier,
+    ) -> anyhow::Result<()> {
         let key: HistoryStoreKey = key.into();
-        let val = tree.get(key.to_bytes())?;
-        match val {
-            Some(v) => Ok(types::U64::from_little_endian(&v)),
-            None => Ok(default_block_number),
+        let tree = self.db.open_tree("spent_nullifiers")?;
+        let mut k = key.to_bytes()?;
+        k.extend_from_slice(nullifier_hash.as_bytes());
+        tree.insert(k, serde_json::to_vec(&spent)?)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_spent_nullifier<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<Option<SpentNullifier>> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("spent_nullifiers")?;
+        let mut k = key.to_bytes()?;
+        k.extend_from_slice(nullifier_hash.as_bytes());
+        match tree.get(k)? {
+            // cached by an older version, without its withdraw.
+            Some(v) if v.is_empty() => Ok(Some(SpentNullifier::default())),
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
         }
     }
+
+    #[tracing::instrument(skip(self))]
+    fn is_nullifier_spent<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        nullifier_hash: types::H256,
+    ) -> anyhow::Result<bool> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("spent_nullifiers")?;
+        let mut k = key.to_bytes()?;
+        k.extend_from_slice(nullifier_hash.as_bytes());
+        Ok(tree.contains_key(k)?)
+    }
 }
 
-impl LeafCacheStore for SledStore {
-    type Output = Vec<types::H256>;
+impl GovernorStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn set_governor<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+        governor: types::Address,
+        block_number: u64,
+    ) -> anyhow::Result<bool> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("governors")?;
+        // the value is the block number followed by the governor address.
+        let mut value = block_number.to_be_bytes().to_vec();
+        value.extend_from_slice(governor.as_bytes());
+        let mut changed = false;
+        tree.fetch_and_update(key.to_bytes()?, |old| match old {
+            Some(old) if old[..8] > value[..8] => Some(old.to_vec()),
+            Some(old) => {
+                changed = old[8..] != value[8..];
+                Some(value.clone())
+            }
+            None => {
+                changed = true;
+                Some(value.clone())
+            }
+        })?;
+        Ok(changed)
+    }
 
     #[tracing::instrument(skip(self))]
-    fn get_leaves<K: Into<HistoryStoreKey> + Debug>(
+    fn get_governor<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-    ) -> anyhow::Result<Self::Output> {
+    ) -> anyhow::Result<Option<types::Address>> {
         let key: HistoryStoreKey = key.into();
-        let tree = self.db.open_tree(format!(
-            "leaves/{}/{}",
-            key.chain_id(),
-            key.address()
-        ))?;
-        let leaves = tree
-            .iter()
-            .values()
-            .flatten()
-            .map(|v| types::H256::from_slice(&v))
-            .collect();
-        Ok(leaves)
+        let tree = self.db.open_tree("governors")?;
+        let governor = tree
+            .get(key.to_bytes()?)?
+            .map(|v| types::Address::from_slice(&v[8..]));
+        Ok(governor)
+    }
+}
+
+impl KeyRotationStore for SledStore {
+    #[tracing::instrument(skip(self, key))]
+    fn insert_key_rotation(
+        &self,
+        chain_id: u64,
+        revoked: types::Address,
+        key: &PrivateKey,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("key_rotations")?;
+        let mut rotation_key = chain_id.to_be_bytes().to_vec();
+        rotation_key.extend_from_slice(revoked.as_bytes());
+        tree.insert(rotation_key, key.as_bytes())?;
+        tree.flush()?;
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
-    fn insert_leaves<K: Into<HistoryStoreKey> + Debug>(
+    fn key_rotations(
+        &self,
+    ) -> anyhow::Result<Vec<(u64, types::Address, PrivateKey)>> {
+        let tree = self.db.open_tree("key_rotations")?;
+        tree.iter()
+            .map(|entry| {
+                let (k, v) = entry?;
+                anyhow::ensure!(
+                    k.len() == 28 && v.len() == 32,
+                    "invalid key rotation"
+                );
+                let mut chain_id = [0u8; 8];
+                chain_id.copy_from_slice(&k[..8]);
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&v);
+                Ok((
+                    u64::from_be_bytes(chain_id),
+                    types::Address::from_slice(&k[8..]),
+                    PrivateKey::from_bytes(key),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl NeighborRootStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn insert_neighbor_root<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-        leaves: &[(u32, types::H256)],
+        neighbor_chain_id: types::U256,
+        root: types::H256,
     ) -> anyhow::Result<()> {
         let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("neighbor_roots")?;
+        let mut neighbor_key = key.to_bytes()?;
+        let mut chain_id = [0u8; 32];
+        neighbor_chain_id.to_big_endian(&mut chain_id);
+        neighbor_key.extend_from_slice(&chain_id);
+        // a new neighbor gets the next edge index, the neighbors are only scanned then.
+        let edge_index = if tree.contains_key(&neighbor_key)? {
+            0
+        } else {
+            tree.scan_prefix(key.to_bytes()?).count() as u32
+        };
+        // the value is the edge index of the neighbor, then its roots, the oldest first.
+        tree.fetch_and_update(neighbor_key, |old| {
+            let mut value = old
+                .map(|v| v.to_vec())
+                .unwrap_or_else(|| edge_index.to_be_bytes().to_vec());
+            // the neighbors recorded before their edges got numbered have no index.
+            let offset = value.len() % 32;
+            if value[offset..].ends_with(root.as_bytes()) {
+                return Some(value);
+            }
+            value.extend_from_slice(root.as_bytes());
+            let max_len = offset + NEIGHBOR_ROOT_HISTORY_SIZE * 32;
+            if value.len() > max_len {
+                let excess = value.len() - max_len;
+                value.drain(offset..offset + excess);
+            }
+            Some(value)
+        })?;
+        Ok(())
+    }
 
-        let tree = self.db.open_tree(format!(
-            "leaves/{}/{}",
-            key.chain_id(),
-            key.address()
-        ))?;
-        for (k, v) in leaves {
-            tree.insert(k.to_le_bytes(), v.as_bytes())?;
+    #[tracing::instrument(skip(self))]
+    fn neighbor_roots<K: Into<HistoryStoreKey> + Debug>(
+        &self,
+        key: K,
+    ) -> anyhow::Result<Vec<(types::U256, Vec<types::H256>)>> {
+        let key: HistoryStoreKey = key.into();
+        let tree = self.db.open_tree("neighbor_roots")?;
+        let mut neighbors = Vec::new();
+        for entry in tree.scan_prefix(key.to_bytes()?) {
+            let (k, v) = entry?;
+            if v.len() % 32 != 4 {
+                // recorded before the edges got numbered, their order is unknown
+                // until the contract is synced again.
+                return Ok(Vec::new());
+            }
+            let mut edge_index = [0u8; 4];
+            edge_index.copy_from_slice(&v[..4]);
+            let neighbor_chain_id =
+                types::U256::from_big_endian(&k[k.len() - 32..]);
+            let roots = v[4..]
+                .chunks_exact(32)
+                .map(types::H256::from_slice)
+                .collect();
+            neighbors.push((
+                u32::from_be_bytes(edge_index),
+                neighbor_chain_id,
+                roots,
+            ));
         }
-        Ok(())
+        neighbors.sort_by_key(|(edge_index, ..)| *edge_index);
+        Ok(neighbors
+            .into_iter()
+            .map(|(_, neighbor_chain_id, roots)| (neighbor_chain_id, roots))
+            .collect())
     }
+}
 
-    fn get_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+impl RootCheckpointStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn insert_root_checkpoint<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-    ) -> anyhow::Result<types::U64> {
-        let tree = self.db.open_tree("last_deposit_block_number")?;
+        checkpoint: RootCheckpoint,
+    ) -> anyhow::Result<()> {
         let key: HistoryStoreKey = key.into();
-        let val = tree.get(key.to_bytes())?;
-        match val {
-            Some(v) => Ok(types::U64::from_little_endian(&v)),
-            None => Ok(types::U64::from(0)),
+        let tree = self.db.open_tree("root_checkpoints")?;
+        let prefix = key.to_bytes()?;
+        // the checkpoints are ordered by the time they got checked at.
+        let mut checkpoint_key = prefix.clone();
+        checkpoint_key.extend_from_slice(&checkpoint.checked_at.to_be_bytes());
+        checkpoint_key
+            .extend_from_slice(&checkpoint.block_number.as_u64().to_be_bytes());
+        tree.insert(checkpoint_key, serde_json::to_vec(&checkpoint)?)?;
+        // scanned from the newest, only the kept checkpoints are skipped.
+        let expired = tree
+            .scan_prefix(&prefix)
+            .keys()
+            .rev()
+            .skip(ROOT_CHECKPOINT_HISTORY_SIZE);
+        for k in expired {
+            tree.remove(k?)?;
         }
+        Ok(())
     }
 
-    fn insert_last_deposit_block_number<K: Into<HistoryStoreKey> + Debug>(
+    #[tracing::instrument(skip(self))]
+    fn root_checkpoints<K: Into<HistoryStoreKey> + Debug>(
         &self,
         key: K,
-        block_number: types::U64,
-    ) -> anyhow::Result<types::U64> {
-        let tree = self.db.open_tree("last_deposit_block_number")?;
-        let mut bytes = [0u8; std::mem::size_of::<types::U64>()];
-        block_number.to_little_endian(&mut bytes);
+    ) -> anyhow::Result<Vec<RootCheckpoint>> {
         let key: HistoryStoreKey = key.into();
-        let old = tree.insert(key.to_bytes(), &bytes)?;
-        match old {
-            Some(v) => Ok(types::U64::from_little_endian(&v)),
-            None => Ok(block_number),
+        let tree = self.db.open_tree("root_checkpoints")?;
+        let mut checkpoints = Vec::new();
+        for entry in tree.scan_prefix(key.to_bytes()?) {
+            let (_, v) = entry?;
+            checkpoints.push(serde_json::from_slice(&v)?);
+        }
+        Ok(checkpoints)
+    }
+}
+
+/// The key of the usage of an API key on a day, the day comes last so all the days of
+/// an API key are next to each other.
+fn api_usage_key(api_key: &str, day: u64) -> Vec<u8> {
+    let mut key = api_key.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(&day.to_be_bytes());
+    key
+}
+
+impl ApiUsageStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn api_usage(&self, api_key: &str, day: u64) -> anyhow::Result<ApiUsage> {
+        let tree = self.db.open_tree("api_usage")?;
+        match tree.get(api_usage_key(api_key, day))? {
+            Some(v) => Ok(serde_json::from_slice(&v)?),
+            None => Ok(ApiUsage::default()),
+        }
+    }
+
+    #[tracing::instrument(skip(self, admit))]
+    fn add_api_usage<F: Fn(&ApiUsage) -> bool>(
+        &self,
+        api_key: &str,
+        day: u64,
+        usage: ApiUsage,
+        admit: F,
+    ) -> anyhow::Result<(ApiUsage, bool)> {
+        let tree = self.db.open_tree("api_usage")?;
+        let decode = |v: Option<&[u8]>| -> serde_json::Result<ApiUsage> {
+            v.map(serde_json::from_slice)
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        // the update could run more than once under contention, it only depends on
+        // the old value though, so it is decided the same way after the fact.
+        let old =
+            tree.fetch_and_update(api_usage_key(api_key, day), |old| {
+                // a counter that could not be decoded is left as is, and reported below,
+                // instead of being reset.
+                let sum = match decode(old) {
+                    Ok(v) => v.saturating_add(usage),
+                    Err(_) => return old.map(|v| v.to_vec()),
+                };
+                if !admit(&sum) {
+                    return old.map(|v| v.to_vec());
+                }
+                serde_json::to_vec(&sum).ok()
+            })?;
+        let old = decode(old.as_deref()).map_err(|e| {
+            anyhow::anyhow!(
+                "the usage of the API key {} on day {} is corrupted: {}",
+                api_key,
+                day,
+                e
+            )
+        })?;
+        let sum = old.saturating_add(usage);
+        if admit(&sum) {
+            Ok((sum, true))
+        } else {
+            Ok((old, false))
         }
     }
 }
+
+impl AuditLogStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn append_audit_entry(
+        &self,
+        entry: AuditEntry,
+    ) -> anyhow::Result<AuditRecord> {
+        let log = self.db.open_tree("audit_log")?;
+        let head = self.db.open_tree("audit_log_head")?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // the head (the last record) and the new record are written in a single transaction,
+        // so concurrent appends could never fork the hash chain.
+        let record = (&log, &head).transaction(|(log, head)| {
+            let (seq, prev_hash) = match head.get("last")? {
+                Some(v) => {
+                    let last: AuditRecord = serde_json::from_slice(&v)
+                        .map_err(ConflictableTransactionError::Abort)?;
+                    (last.seq + 1, last.hash)
+                }
+                None => (1, types::H256::zero()),
+            };
+            let record =
+                AuditRecord::new(seq, timestamp, entry.clone(), prev_hash)
+                    .map_err(ConflictableTransactionError::Abort)?;
+            let bytes = serde_json::to_vec(&record)
+                .map_err(ConflictableTransactionError::Abort)?;
+            log.insert(&seq.to_be_bytes(), bytes.as_slice())?;
+            head.insert("last", bytes)?;
+            Ok(record)
+        })?;
+        Ok(record)
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn audit_records(
+        &self,
+        from: u64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        let tree = self.db.open_tree("audit_log")?;
+        // the records are keyed by their big endian sequence number, so in order.
+        tree.range(from.to_be_bytes()..)
+            .values()
+            .take(limit)
+            .map(|v| Ok(serde_json::from_slice(&v?)?))
+            .collect()
+    }
+}
+
+impl BridgeRegistryStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn register_bridge(&self, key: BridgeKey) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("bridges")?;
+        tree.insert(key.to_bytes(), serde_json::to_vec(&key)?)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn registered_bridges(&self) -> anyhow::Result<Vec<BridgeKey>> {
+        let tree = self.db.open_tree("bridges")?;
+        tree.iter()
+            .values()
+            .map(|v| Ok(serde_json::from_slice(&v?)?))
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn unregister_bridge(&self, key: BridgeKey) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("bridges")?;
+        tree.remove(key.to_bytes())?;
+        Ok(())
+    }
+}
+impl ProposalNonceStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn get_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+    ) -> anyhow::Result<Option<u32>> {
+        let tree = self.db.open_tree("proposal_nonces")?;
+        let key = proposal_nonce_key(resource_id, origin_chain);
+        match tree.get(key)? {
+            Some(v) => {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&v);
+                Ok(Some(u32::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn set_last_executed_nonce(
+        &self,
+        resource_id: webb_proposals::ResourceId,
+        origin_chain: webb_proposals::TypedChainId,
+        nonce: u32,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("proposal_nonces")?;
+        let key = proposal_nonce_key(resource_id, origin_chain);
+        tree.fetch_and_update(key, |old| {
+            let old = old.map(|v| {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(v);
+                u32::from_be_bytes(bytes)
+            });
+            let new = old.map_or(nonce, |old| old.max(nonce));
+            Some(new.to_be_bytes().to_vec())
+        })?;
+        Ok(())
+    }
+}
+
+/// The key of a proposal nonce, which is the resource id followed by the origin chain.
+fn proposal_nonce_key(
+    resource_id: webb_proposals::ResourceId,
+    origin_chain: webb_proposals::TypedChainId,
+) -> [u8; 38] {
+    let mut key = [0u8; 38];
+    key[..32].copy_from_slice(&resource_id.to_bytes());
+    key[32..].copy_from_slice(&origin_chain.to_bytes());
+    key
+}
 /// SledQueueKey is a key for a queue in Sled.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SledQueueKey {
@@ -220,7 +2066,8 @@ impl QueueKey for SledQueueKey {
             Self::EvmTx { chain_id, .. } => format!("evm_tx_{}", chain_id),
             Self::BridgeCmd { bridge_key, .. } => format!(
                 "bridge_cmd_{}_{}",
-                bridge_key.chain_id, bridge_key.address
+                bridge_key.chain_id.chain_id(),
+                bridge_key.address
             ),
         }
     }
@@ -235,14 +2082,14 @@ impl QueueKey for SledQueueKey {
 
 impl<T> QueueStore<T> for SledStore
 where
-    T: Serialize + DeserializeOwned + Clone,
+    T: Record + Clone,
 {
     type Key = SledQueueKey;
 
     #[tracing::instrument(skip_all, fields(key = %key))]
     fn enqueue_item(&self, key: Self::Key, item: T) -> anyhow::Result<()> {
         let tree = self.db.open_tree(format!("queue_{}", key.queue_name()))?;
-        let item_bytes = serde_json::to_vec(&item)?;
+        let item_bytes = codec::encode(&item)?;
         // we do everything inside a single transaction
         // so everything happens atomically and if anything fails
         // we revert everything back to the old state.
@@ -268,6 +2115,8 @@ where
             item_key[4..].copy_from_slice(&idx_bytes);
             // then we save it.
             db.insert(&item_key, item_bytes.as_slice())?;
+            let len = read_count(db.get(QUEUE_LEN_KEY)?);
+            db.insert(QUEUE_LEN_KEY, &(len + 1).to_be_bytes())?;
             if let Some(k) = key.item_key() {
                 // also save the key where we can find it by special key.
                 db.insert(&k[..], &item_key)?;
@@ -295,9 +2144,9 @@ where
                 return Ok(None);
             }
         };
-        let item = serde_json::from_slice(&value)?;
+        let item = codec::decode(&value)?;
         // now it is safe to remove it from the queue.
-        tree.remove(key)?;
+        remove_queued(&tree, &key)?;
         // flush db
         self.db.flush()?;
         Ok(Some(item))
@@ -314,7 +2163,7 @@ where
             Some(Ok(v)) => v,
             _ => return Ok(None),
         };
-        let item = serde_json::from_slice(&value)?;
+        let item = codec::decode(&value)?;
         Ok(Some(item))
     }
 
@@ -337,9 +2186,9 @@ where
         };
         match tree.get(&inner_key[..])? {
             Some(k) => {
-                let exists = tree.remove(&k)?;
+                let exists = remove_queued(&tree, &k)?;
                 tree.remove(&inner_key)?;
-                let item = exists.and_then(|v| serde_json::from_slice(&v).ok());
+                let item = exists.and_then(|v| codec::decode(&v).ok());
                 tracing::trace!("removed item from the queue..");
                 self.db.flush()?;
                 Ok(item)
@@ -350,38 +2199,266 @@ where
             }
         }
     }
+
+    #[tracing::instrument(skip_all, fields(key = %key))]
+    fn count_items(&self, key: Self::Key) -> anyhow::Result<usize> {
+        let tree = self.db.open_tree(format!("queue_{}", key.queue_name()))?;
+        Ok(read_count(tree.get(QUEUE_LEN_KEY)?) as usize)
+    }
+}
+
+/// Removes a queued item, keeping the length of the queue up to date.
+fn remove_queued(
+    tree: &sled::Tree,
+    item_key: &[u8],
+) -> anyhow::Result<Option<sled::IVec>> {
+    let removed = tree.transaction::<_, _, std::io::Error>(|db| {
+        let removed = db.remove(item_key)?;
+        if removed.is_some() {
+            let len = read_count(db.get(QUEUE_LEN_KEY)?);
+            db.insert(QUEUE_LEN_KEY, &len.saturating_sub(1).to_be_bytes())?;
+        }
+        Ok(removed)
+    })?;
+    Ok(removed)
+}
+
+/// The tree of the signed proposals, by their data hashes.
+const PROPOSALS_TREE: &str = "proposal_store";
+/// The tree of the data hashes of the signed proposals, by their resource ids and
+/// nonces, see [`proposal_index_key`].
+const PROPOSALS_INDEX_TREE: &str = "proposals_by_nonce";
+
+/// The key of a signed proposal in the [`PROPOSALS_INDEX_TREE`], the resource id and
+/// the nonce (BE) of its header, then its data hash, since proposals from different
+/// origin chains could share a nonce.
+///
+/// Returns `None` if the proposal does not have a header.
+fn proposal_index_key(proposal: &SignedProposal) -> Option<Vec<u8>> {
+    let header = crate::proposals::decode_header(&proposal.data).ok()?;
+    let mut key =
+        proposal_index_prefix(header.resource_id(), header.nonce().to_u32());
+    key.extend_from_slice(&proposal.data_hash());
+    Some(key)
+}
+
+/// The prefix of the keys of the proposals with that resource id and nonce, in the
+/// [`PROPOSALS_INDEX_TREE`].
+fn proposal_index_prefix(
+    resource_id: crate::proposals::ResourceId,
+    nonce: u32,
+) -> Vec<u8> {
+    let mut key = Vec::with_capacity(32 + 4 + 32);
+    key.extend_from_slice(&resource_id.to_bytes());
+    key.extend_from_slice(&nonce.to_be_bytes());
+    key
 }
 
 impl ProposalStore for SledStore {
-    type Proposal = ();
+    type Proposal = SignedProposal;
 
-    #[tracing::instrument(skip_all)]
+    #[tracing::instrument(
+        skip_all,
+        fields(data_hash = %hex::encode(proposal.data_hash()))
+    )]
     fn insert_proposal(&self, proposal: Self::Proposal) -> anyhow::Result<()> {
-        let tree = self.db.open_tree("proposal_store")?;
-        tree.insert(&"TODO", serde_json::to_vec(&proposal)?.as_slice())?;
+        let tree = self.db.open_tree(PROPOSALS_TREE)?;
+        let index_tree = self.db.open_tree(PROPOSALS_INDEX_TREE)?;
+        let data_hash = proposal.data_hash();
+        let value = codec::encode(&proposal)?;
+        let index_key = proposal_index_key(&proposal);
+        // the proposal and its index are written in a single transaction.
+        (&tree, &index_tree)
+            .transaction(|(tree, index_tree)| {
+                tree.insert(&data_hash, value.as_slice())?;
+                if let Some(index_key) = &index_key {
+                    index_tree.insert(index_key.as_slice(), &data_hash)?;
+                }
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(()) => {
+                    anyhow::anyhow!("inserting the proposal got aborted")
+                }
+                TransactionError::Storage(e) => e.into(),
+            })
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(data_hash = %hex::encode(data_hash))
+    )]
+    fn remove_proposal(
+        &self,
+        data_hash: &[u8],
+    ) -> anyhow::Result<Option<Self::Proposal>> {
+        let tree = self.db.open_tree(PROPOSALS_TREE)?;
+        let index_tree = self.db.open_tree(PROPOSALS_INDEX_TREE)?;
+        // the proposal and its index are removed in a single transaction.
+        let result = (&tree, &index_tree).transaction(
+            |(tree, index_tree)| -> ConflictableTransactionResult<_, anyhow::Error> {
+                let bytes = match tree.remove(data_hash)? {
+                    Some(bytes) => bytes,
+                    None => return Ok(None),
+                };
+                let proposal: SignedProposal = codec::decode(&bytes)
+                    .map_err(ConflictableTransactionError::Abort)?;
+                if let Some(index_key) = proposal_index_key(&proposal) {
+                    index_tree.remove(index_key)?;
+                }
+                Ok(Some(proposal))
+            },
+        );
+        let removed = match result {
+            Ok(v) => v,
+            Err(TransactionError::Abort(e)) => return Err(e),
+            Err(TransactionError::Storage(e)) => return Err(e.into()),
+        };
+        match removed {
+            Some(proposal) => Ok(Some(proposal)),
+            None => {
+                tracing::warn!(
+                    "Proposal not seen yet; not found in the proposal storage."
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn proposals(&self) -> anyhow::Result<Vec<Self::Proposal>> {
+        let tree = self.db.open_tree(PROPOSALS_TREE)?;
+        tree.iter().values().map(|v| codec::decode(&v?)).collect()
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn proposals_by_nonce(
+        &self,
+        resource_id: crate::proposals::ResourceId,
+        nonce: u32,
+    ) -> anyhow::Result<Vec<Self::Proposal>> {
+        let tree = self.db.open_tree(PROPOSALS_TREE)?;
+        let index_tree = self.db.open_tree(PROPOSALS_INDEX_TREE)?;
+        let prefix = proposal_index_prefix(resource_id, nonce);
+        let mut proposals = Vec::new();
+        for data_hash in index_tree.scan_prefix(prefix).values() {
+            if let Some(v) = tree.get(data_hash?)? {
+                proposals.push(codec::decode(&v)?);
+            }
+        }
+        Ok(proposals)
+    }
+
+    #[tracing::instrument(
+        skip(self, data_hash),
+        fields(data_hash = %hex::encode(data_hash))
+    )]
+    fn set_proposal_status(
+        &self,
+        data_hash: &[u8],
+        status: ProposalStatus,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("proposal_statuses")?;
+        tree.insert(data_hash, serde_json::to_vec(&status)?)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(data_hash = %hex::encode(data_hash))
+    )]
+    fn proposal_status(
+        &self,
+        data_hash: &[u8],
+    ) -> anyhow::Result<Option<ProposalStatus>> {
+        let tree = self.db.open_tree("proposal_statuses")?;
+        match tree.get(data_hash)? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn proposal_statuses(
+        &self,
+    ) -> anyhow::Result<Vec<(Vec<u8>, ProposalStatus)>> {
+        let tree = self.db.open_tree("proposal_statuses")?;
+        tree.iter()
+            .map(|entry| {
+                let (k, v) = entry?;
+                Ok((k.to_vec(), serde_json::from_slice(&v)?))
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(data_hash = %hex::encode(data_hash))
+    )]
+    fn remove_proposal_status(&self, data_hash: &[u8]) -> anyhow::Result<()> {
+        let tree = self.db.open_tree("proposal_statuses")?;
+        tree.remove(data_hash)?;
+        Ok(())
+    }
+}
+
+/// The key of a Substrate proposal, the source chain id then the deposit nonce.
+fn substrate_proposal_key(src_chain_id: u64, deposit_nonce: u64) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&src_chain_id.to_be_bytes());
+    key[8..].copy_from_slice(&deposit_nonce.to_be_bytes());
+    key
+}
+
+impl SubstrateProposalStore for SledStore {
+    #[tracing::instrument(skip(self))]
+    fn get_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>> {
+        let tree = self
+            .db
+            .open_tree(format!("substrate_proposals_{}", chain_id))?;
+        let key = substrate_proposal_key(src_chain_id, deposit_nonce);
+        match tree.get(key)? {
+            Some(bytes) => Ok(Some(codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn insert_substrate_proposal(
+        &self,
+        chain_id: types::U256,
+        proposal: SubstrateProposal,
+    ) -> anyhow::Result<()> {
+        let tree = self
+            .db
+            .open_tree(format!("substrate_proposals_{}", chain_id))?;
+        let key = substrate_proposal_key(
+            proposal.src_chain_id,
+            proposal.deposit_nonce,
+        );
+        tree.insert(key, codec::encode(&proposal)?)?;
         Ok(())
     }
 
-    #[tracing::instrument(
-        skip_all,
-        fields(data_hash = %hex::encode(data_hash))
-    )]
-    fn remove_proposal(
+    #[tracing::instrument(skip(self))]
+    fn remove_substrate_proposal(
         &self,
-        data_hash: &[u8],
-    ) -> anyhow::Result<Option<Self::Proposal>> {
-        let tree = self.db.open_tree("proposal_store")?;
-        match tree.get(&data_hash)? {
-            Some(bytes) => {
-                let proposal: Self::Proposal = serde_json::from_slice(&bytes)?;
-                Ok(Some(proposal))
-            }
-            None => {
-                tracing::warn!(
-                    "Proposal not seen yet; not found in the proposal storage."
-                );
-                Ok(None)
-            }
+        chain_id: types::U256,
+        src_chain_id: u64,
+        deposit_nonce: u64,
+    ) -> anyhow::Result<Option<SubstrateProposal>> {
+        let tree = self
+            .db
+            .open_tree(format!("substrate_proposals_{}", chain_id))?;
+        let key = substrate_proposal_key(src_chain_id, deposit_nonce);
+        match tree.remove(key)? {
+            Some(bytes) => Ok(Some(codec::decode(&bytes)?)),
+            None => Ok(None),
         }
     }
 }
@@ -389,9 +2466,15 @@ impl ProposalStore for SledStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::{
+        verify_audit_chain, AuditEvent, SubstrateProposalStatus,
+    };
+    #[cfg(feature = "evm")]
     use webb::evm::ethers::core::types::transaction::eip2718::TypedTransaction;
+    #[cfg(feature = "evm")]
     use webb::evm::ethers::types::transaction::request::TransactionRequest;
 
+    #[cfg(feature = "evm")]
     impl SledQueueKey {
         pub fn from_evm_tx(
             chain_id: types::U256,
@@ -406,6 +2489,305 @@ mod tests {
             Self::from_evm_with_custom_key(chain_id, key)
         }
     }
+    #[test]
+    fn should_migrate_plain_chain_ids() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        let address = types::Address::random();
+        let mut old_key = 4u128.to_le_bytes().to_vec();
+        old_key.extend_from_slice(address.as_bytes());
+        let mut old_substrate_key = 5u128.to_le_bytes().to_vec();
+        old_substrate_key.extend_from_slice(b"dkg-node");
+        let tree = db.open_tree("last_block_numbers").unwrap();
+        tree.insert(old_key, &42u64.to_le_bytes()).unwrap();
+        tree.insert(old_substrate_key, &7u64.to_le_bytes()).unwrap();
+        db.open_tree(format!("leaves/4/{}", address))
+            .unwrap()
+            .insert(0u32.to_le_bytes(), &[1u8; 32])
+            .unwrap();
+        let store = SledStore { db };
+        store.migrate().unwrap();
+        let key = (types::U256::from(4), address);
+        let block_number =
+            store.get_last_block_number(key, 0u64.into()).unwrap();
+        assert_eq!(block_number, 42u64.into());
+        for tree_id in [
+            pallet_tree_ids::DKG_PROPOSAL_HANDLER,
+            pallet_tree_ids::BRIDGE,
+        ] {
+            let block_number = store
+                .get_last_block_number(
+                    (types::U256::from(5), tree_id),
+                    0u64.into(),
+                )
+                .unwrap();
+            assert_eq!(block_number, 7u64.into());
+        }
+        let tree = store.db.open_tree("last_block_numbers").unwrap();
+        assert_eq!(tree.len(), 3);
+        let leaves = store.get_leaves(key).unwrap();
+        assert_eq!(leaves, vec![types::H256::from([1u8; 32])]);
+        let index = store.get_leaf_index(key, [1u8; 32].into()).unwrap();
+        assert_eq!(index, Some(0));
+        // migrating again is a no-op.
+        store.migrate().unwrap();
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn interrupted_typed_chain_ids_migration_should_resume() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        let address = types::Address::random();
+        let mut old_key = 4u128.to_le_bytes().to_vec();
+        old_key.extend_from_slice(address.as_bytes());
+        let tree = db.open_tree("last_block_numbers").unwrap();
+        tree.insert(old_key, &42u64.to_le_bytes()).unwrap();
+        let store = SledStore { db };
+        store.migrate_to_typed_chain_ids().unwrap();
+        // interrupted before the version got recorded, the keys are kept.
+        store.migrate_to_typed_chain_ids().unwrap();
+        let key = (types::U256::from(4), address);
+        let block_number =
+            store.get_last_block_number(key, 0u64.into()).unwrap();
+        assert_eq!(block_number, 42u64.into());
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn chain_ids_out_of_range_should_be_rejected() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::from(u64::from(u32::MAX) + 1);
+        let key = (chain_id, types::Address::random());
+        assert!(store.set_last_block_number(key, 1u64.into()).is_err());
+        assert!(store.get_leaves(key).is_err());
+        let bridge = serde_json::json!({
+            "address": types::Address::random(),
+            "chain_id": chain_id,
+        });
+        assert!(serde_json::from_value::<BridgeKey>(bridge).is_err());
+    }
+
+    #[test]
+    fn key_rotations_should_be_kept() {
+        let tmp = tempfile::tempdir().unwrap();
+        let revoked = types::Address::random();
+        {
+            let store = SledStore::open(tmp.path()).unwrap();
+            store
+                .insert_key_rotation(
+                    5001,
+                    revoked,
+                    &PrivateKey::from_bytes([0xcd; 32]),
+                )
+                .unwrap();
+        }
+        let store = SledStore::open(tmp.path()).unwrap();
+        let rotations = store.key_rotations().unwrap();
+        assert_eq!(rotations.len(), 1);
+        let (chain_id, address, key) = &rotations[0];
+        assert_eq!((*chain_id, *address), (5001, revoked));
+        assert_eq!(key.as_bytes(), &[0xcd; 32]);
+    }
+
+    #[test]
+    fn trees_of_the_same_node_should_have_their_own_history() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::from(5);
+        let keys = [
+            (chain_id, 0u32),
+            (chain_id, 1u32),
+            (chain_id, pallet_tree_ids::DKG_PROPOSAL_HANDLER),
+            (chain_id, pallet_tree_ids::BRIDGE),
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            store
+                .set_last_block_number(*key, (10 * (i as u64 + 1)).into())
+                .unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            let block_number =
+                store.get_last_block_number(*key, 0u64.into()).unwrap();
+            assert_eq!(block_number, (10 * (i as u64 + 1)).into());
+        }
+    }
+
+    #[test]
+    fn pallet_watchers_should_get_their_own_history() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        db.insert(SCHEMA_VERSION_KEY, &[5]).unwrap();
+        let shared = HistoryStoreKey::from((types::U256::from(5), 0u32));
+        let tree = db.open_tree("last_block_numbers").unwrap();
+        tree.insert(shared.to_bytes().unwrap(), &7u64.to_le_bytes())
+            .unwrap();
+        let store = SledStore { db };
+        store.migrate().unwrap();
+        for tree_id in [
+            pallet_tree_ids::DKG_PROPOSAL_HANDLER,
+            pallet_tree_ids::BRIDGE,
+        ] {
+            let block_number = store
+                .get_last_block_number(
+                    (types::U256::from(5), tree_id),
+                    0u64.into(),
+                )
+                .unwrap();
+            assert_eq!(block_number, 7u64.into());
+        }
+        assert!(!tree.contains_key(shared.to_bytes().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn leaves_should_get_indexed_by_block() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        db.insert(SCHEMA_VERSION_KEY, &[7]).unwrap();
+        let store = SledStore { db };
+        let key = (types::U256::from(5), types::Address::random());
+        let leaves: Vec<_> = (0..3u32)
+            .map(|i| (i, types::H256::from_low_u64_be(i.into())))
+            .collect();
+        store.insert_leaves(key, &leaves).unwrap();
+        // the blocks recorded before the index by block.
+        let blocks = store
+            .db
+            .open_tree(leaves_blocks_tree_name(&key.into()).unwrap())
+            .unwrap();
+        for (index, block_number) in [(0u32, 10u64), (1, 20), (2, 30)] {
+            blocks
+                .insert(index.to_be_bytes(), &block_number.to_le_bytes())
+                .unwrap();
+        }
+        store.migrate().unwrap();
+        let in_range = store
+            .get_leaves_in_block_range(key, 15.into(), 30.into())
+            .unwrap();
+        assert_eq!(in_range, leaves[1..]);
+    }
+
+    #[test]
+    fn signed_proposals_should_get_indexed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        db.insert(SCHEMA_VERSION_KEY, &[6]).unwrap();
+        let resource_id = crate::proposals::ResourceId::from([1u8; 32]);
+        let header = crate::proposals::ProposalHeader::new(
+            resource_id,
+            crate::proposals::ANCHOR_UPDATE_FUNCTION_SIGNATURE.into(),
+            3.into(),
+        );
+        let mut data = header.to_bytes().to_vec();
+        data.extend_from_slice(&[0u8; 64]);
+        let proposal = SignedProposal {
+            chain_id: types::U256::from(5),
+            data,
+            signature: vec![0; 65],
+            enqueued_at: 0,
+        };
+        db.open_tree(PROPOSALS_TREE)
+            .unwrap()
+            .insert(proposal.data_hash(), codec::encode(&proposal).unwrap())
+            .unwrap();
+        let store = SledStore { db };
+        store.migrate().unwrap();
+        assert_eq!(
+            store.proposals_by_nonce(resource_id, 3).unwrap(),
+            vec![proposal.clone()]
+        );
+        assert!(store.proposals_by_nonce(resource_id, 4).unwrap().is_empty());
+        store.remove_proposal(&proposal.data_hash()).unwrap();
+        assert!(store.proposals_by_nonce(resource_id, 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn leaves_should_be_migrated_to_segments() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        db.insert(SCHEMA_VERSION_KEY, &[3]).unwrap();
+        let key = (types::U256::from(4), types::Address::random());
+        let tree = db
+            .open_tree(leaves_tree_name(&key.into()).unwrap())
+            .unwrap();
+        // more than one segment, with a hole.
+        let leaves: Vec<_> = (0..1500u32)
+            .filter(|i| *i != 1024)
+            .map(|i| (i, types::H256::random()))
+            .collect();
+        for (index, leaf) in &leaves {
+            tree.insert(index.to_le_bytes(), leaf.as_bytes()).unwrap();
+        }
+        let store = SledStore { db };
+        store.migrate().unwrap();
+        assert_eq!(store.get_leaves_with_index(key).unwrap(), leaves);
+        assert_eq!(store.get_leaves_count(key).unwrap(), leaves.len());
+        assert_eq!(tree.len(), 2);
+        // filling the hole updates the segment in place.
+        let leaf = types::H256::random();
+        store.insert_leaves(key, &[(1024, leaf)]).unwrap();
+        assert_eq!(store.get_leaves_count(key).unwrap(), leaves.len() + 1);
+        assert_eq!(store.get_leaves(key).unwrap()[1024], leaf);
+        assert_eq!(store.get_leaf_index(key, leaf).unwrap(), Some(1024));
+        // the new deposits are appended to the last segment.
+        let leaf = types::H256::random();
+        store.insert_leaves(key, &[(1500, leaf)]).unwrap();
+        assert_eq!(store.get_leaves_count(key).unwrap(), leaves.len() + 2);
+        assert_eq!(store.get_leaves(key).unwrap()[1500], leaf);
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn interrupted_segmentation_should_resume() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sled::Config::new().path(tmp.path()).open().unwrap();
+        db.insert(SCHEMA_VERSION_KEY, &[3]).unwrap();
+        let key = (types::U256::from(4), types::Address::random());
+        let tree = db
+            .open_tree(leaves_tree_name(&key.into()).unwrap())
+            .unwrap();
+        let leaves: Vec<_> =
+            (0..10u32).map(|i| (i, types::H256::random())).collect();
+        for (index, leaf) in &leaves {
+            tree.insert(index.to_le_bytes(), leaf.as_bytes()).unwrap();
+        }
+        let store = SledStore { db };
+        store.segment_leaves().unwrap();
+        // interrupted before the version got recorded, the segments are kept.
+        store.migrate().unwrap();
+        assert_eq!(store.get_leaves_with_index(key).unwrap(), leaves);
+        // a segmented contract that was not recorded is an error, not a panic.
+        store.db.drop_tree(SEGMENTED_LEAVES_TREE).unwrap();
+        assert!(store.segment_leaves().is_err());
+    }
+
+    #[test]
+    fn proposal_nonces_should_only_increase() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = SledStore::open(tmp.path()).unwrap();
+        let resource_id = webb_proposals::ResourceId::from([1u8; 32]);
+        let origin = webb_proposals::TypedChainId::Evm(4);
+        assert_eq!(
+            store.get_last_executed_nonce(resource_id, origin).unwrap(),
+            None
+        );
+        store
+            .set_last_executed_nonce(resource_id, origin, 5)
+            .unwrap();
+        store
+            .set_last_executed_nonce(resource_id, origin, 3)
+            .unwrap();
+        assert_eq!(
+            store.get_last_executed_nonce(resource_id, origin).unwrap(),
+            Some(5)
+        );
+        // other origin chains are tracked separately.
+        let other = webb_proposals::TypedChainId::Evm(5);
+        assert_eq!(
+            store.get_last_executed_nonce(resource_id, other).unwrap(),
+            None
+        );
+    }
+
     #[test]
     fn get_leaves_should_work() {
         let tmp = tempfile::tempdir().unwrap();
@@ -434,6 +2816,611 @@ mod tests {
         }
     }
 
+    #[test]
+    fn relay_costs_are_bucketed_by_day() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let contract = types::Address::random();
+        let token = types::Address::zero();
+        let day = 19_000 * ACCOUNTING_BUCKET;
+        let record = |timestamp, fee: u64| {
+            store
+                .record_relay_cost(
+                    chain_id,
+                    contract,
+                    token,
+                    None,
+                    timestamp,
+                    21_000.into(),
+                    42_000.into(),
+                    fee.into(),
+                )
+                .unwrap()
+        };
+        record(day + 10, 100);
+        record(day + ACCOUNTING_BUCKET - 1, 0);
+        record(day + ACCOUNTING_BUCKET, 50);
+        let entries = store.accounting(day + 5, day + 5).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].day, day);
+        assert_eq!(entries[0].gas_spent, 84_000.into());
+        assert_eq!(entries[0].fees_earned, 100.into());
+        assert_eq!(entries[0].transactions, 2);
+        let entries = store
+            .accounting(day, day + ACCOUNTING_BUCKET)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.day)
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![day, day + ACCOUNTING_BUCKET]);
+    }
+
+    #[test]
+    fn spent_nullifiers_keep_their_withdraw() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        let nullifier_hash = types::H256::random();
+        assert!(!store.is_nullifier_spent(key, nullifier_hash).unwrap());
+        assert_eq!(
+            store.get_spent_nullifier(key, nullifier_hash).unwrap(),
+            None
+        );
+        let spent = SpentNullifier {
+            tx_hash: Some(types::H256::random()),
+            block_number: Some(types::U64::from(42)),
+            spent_at: Some(1_650_000_000),
+        };
+        store
+            .insert_spent_nullifier(key, nullifier_hash, spent.clone())
+            .unwrap();
+        assert!(store.is_nullifier_spent(key, nullifier_hash).unwrap());
+        let cached = store.get_spent_nullifier(key, nullifier_hash).unwrap();
+        assert_eq!(cached, Some(spent));
+        // the nullifiers cached by older versions have no withdraw.
+        let legacy = types::H256::random();
+        let tree = store.db.open_tree("spent_nullifiers").unwrap();
+        let mut k = HistoryStoreKey::from(key).to_bytes();
+        k.extend_from_slice(legacy.as_bytes());
+        tree.insert(k, &[]).unwrap();
+        let cached = store.get_spent_nullifier(key, legacy).unwrap();
+        assert_eq!(cached, Some(SpentNullifier::default()));
+    }
+
+    #[test]
+    fn relays_are_counted_per_hour_and_day() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let contract = types::Address::random();
+        let day = 19_000 * StatsResolution::Daily.seconds();
+        let hour = StatsResolution::Hourly.seconds();
+        store
+            .record_relay((chain_id, contract), day + 10, false)
+            .unwrap();
+        store
+            .record_relay((chain_id, contract), day + 20, true)
+            .unwrap();
+        store
+            .record_relay((chain_id, contract), day + hour, false)
+            .unwrap();
+        let hourly = store
+            .relay_stats(StatsResolution::Hourly, day, day + hour)
+            .unwrap();
+        assert_eq!(hourly.len(), 2);
+        assert_eq!((hourly[0].relays, hourly[0].reverted), (2, 1));
+        assert_eq!(hourly[1].start, day + hour);
+        let daily = store
+            .relay_stats(StatsResolution::Daily, day + 5, day + 5)
+            .unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!((daily[0].relays, daily[0].reverted), (3, 1));
+        // only the buckets that started before the day + 1 hour are pruned.
+        assert_eq!(store.prune_relay_stats(day + hour).unwrap(), 2);
+        let hourly = store
+            .relay_stats(StatsResolution::Hourly, day, day + hour)
+            .unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert!(store
+            .relay_stats(StatsResolution::Daily, day, day)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn substrate_relays_are_counted_per_tree() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let day = 19_000 * StatsResolution::Daily.seconds();
+        store.record_relay((chain_id, 3u32), day, false).unwrap();
+        store.record_relay((chain_id, 3u32), day, true).unwrap();
+        // the same chain id on an EVM chain is counted apart.
+        let contract = types::Address::random();
+        store
+            .record_relay((chain_id, contract), day, false)
+            .unwrap();
+        let daily =
+            store.relay_stats(StatsResolution::Daily, day, day).unwrap();
+        assert_eq!(daily.len(), 2);
+        let tree = daily.iter().find(|v| v.tree_id == Some(3)).unwrap();
+        assert_eq!((tree.contract, tree.relays, tree.reverted), (None, 2, 1));
+        let evm = daily.iter().find(|v| v.contract.is_some()).unwrap();
+        assert_eq!((evm.tree_id, evm.relays), (None, 1));
+    }
+
+    #[test]
+    fn leaves_should_be_indexed_by_commitment() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        // more than 256 leaves, so the indices take more than one byte.
+        let leaves: Vec<_> =
+            (0..300u32).map(|i| (i, types::H256::random())).collect();
+        store.insert_leaves(key, &leaves).unwrap();
+        for (index, leaf) in [leaves[0], leaves[255], leaves[299]] {
+            assert_eq!(store.get_leaf_index(key, leaf).unwrap(), Some(index));
+        }
+        let unknown = store.get_leaf_index(key, types::H256::random());
+        assert_eq!(unknown.unwrap(), None);
+        assert_eq!(store.get_leaves_with_index(key).unwrap(), leaves);
+    }
+
+    #[tokio::test]
+    async fn watched_leaves_are_notified_once_cached() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        let cached = types::H256::random();
+        store.insert_leaves(key, &[(0, cached)]).unwrap();
+        let watch = store.watch_leaf(key, cached).unwrap();
+        assert_eq!(watch.await, Some(0));
+        // the leaf is cached after the watch started.
+        let deposited = types::H256::random();
+        let watch = store.watch_leaf(key, deposited).unwrap();
+        let other = store.watch_leaf(key, types::H256::random()).unwrap();
+        store.insert_leaves(key, &[(1, deposited)]).unwrap();
+        assert_eq!(watch.await, Some(1));
+        let timeout = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(timeout, other).await.is_err());
+    }
+
+    #[test]
+    fn cleared_contracts_are_forgotten() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        let other = (types::U256::one(), types::Address::random());
+        let leaves = [(0, types::H256::random())];
+        for key in [key, other] {
+            store.insert_leaves(key, &leaves).unwrap();
+            store
+                .insert_leaves_block_numbers(key, &[(0, 10.into())])
+                .unwrap();
+            store
+                .insert_last_deposit_block_number(key, 10.into())
+                .unwrap();
+            store.set_last_block_number(key, 20.into()).unwrap();
+            store
+                .set_last_event_block_number(key, "Deposit", 20.into())
+                .unwrap();
+            store.set_synced_from(key, 15.into()).unwrap();
+        }
+        store.clear_contract(key).unwrap();
+        assert_eq!(store.get_leaves_count(key).unwrap(), 0);
+        assert!(store.get_leaves_with_index(key).unwrap().is_empty());
+        assert_eq!(store.get_leaf_index(key, leaves[0].1).unwrap(), None);
+        assert!(store
+            .get_leaves_in_block_range(key, 0.into(), 100.into())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_last_deposit_block_number(key).unwrap(),
+            types::U64::zero()
+        );
+        assert_eq!(
+            store.get_last_block_number(key, 1.into()).unwrap(),
+            types::U64::one()
+        );
+        assert_eq!(
+            store.get_last_event_block_number(key, "Deposit").unwrap(),
+            None
+        );
+        assert_eq!(store.get_synced_from(key).unwrap(), None);
+        // the other contracts are left untouched.
+        assert_eq!(store.get_leaves_with_index(other).unwrap(), leaves);
+        assert_eq!(
+            store.get_synced_from(other).unwrap(),
+            Some(types::U64::from(15))
+        );
+        assert_eq!(
+            store.get_last_block_number(other, 1.into()).unwrap(),
+            types::U64::from(20)
+        );
+    }
+
+    #[test]
+    fn resynced_contracts_are_remembered() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        assert!(!store.is_contract_resynced(key).unwrap());
+        store.set_contract_resynced(key, true).unwrap();
+        // clearing the contract does not forget it got resynced.
+        store.clear_contract(key).unwrap();
+        assert!(store.is_contract_resynced(key).unwrap());
+        store.set_contract_resynced(key, false).unwrap();
+        assert!(!store.is_contract_resynced(key).unwrap());
+    }
+
+    #[test]
+    fn neighbor_roots_are_bounded() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        let roots: Vec<_> = (0..40).map(types::H256::from_low_u64_be).collect();
+        for root in &roots {
+            store.insert_neighbor_root(key, 2.into(), *root).unwrap();
+            // the same root seen again is not recorded twice.
+            store.insert_neighbor_root(key, 2.into(), *root).unwrap();
+        }
+        let other_root = types::H256::random();
+        store
+            .insert_neighbor_root(key, 3.into(), other_root)
+            .unwrap();
+        let known = store.neighbor_roots(key).unwrap();
+        assert_eq!(known.len(), 2);
+        assert_eq!(known[0], (2.into(), roots[10..].to_vec()));
+        assert_eq!(known[1], (3.into(), vec![other_root]));
+        let other = (types::U256::one(), types::Address::random());
+        assert!(store.neighbor_roots(other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn neighbor_roots_are_in_the_order_of_the_edges() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        // the neighbor with the higher chain id got its edge first.
+        for neighbor_chain_id in [9u64, 2, 9, 5] {
+            store
+                .insert_neighbor_root(
+                    key,
+                    neighbor_chain_id.into(),
+                    types::H256::random(),
+                )
+                .unwrap();
+        }
+        let known: Vec<_> = store
+            .neighbor_roots(key)
+            .unwrap()
+            .into_iter()
+            .map(|(neighbor_chain_id, _)| neighbor_chain_id.as_u64())
+            .collect();
+        assert_eq!(known, vec![9, 2, 5]);
+    }
+
+    #[test]
+    fn root_checkpoints_are_bounded() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::one(), types::Address::random());
+        let checkpoint = |i: u64| RootCheckpoint {
+            block_number: types::U64::from(i),
+            onchain_root: types::H256::random(),
+            local_root: types::H256::random(),
+            cached_leaves: 1,
+            contract_leaves: 1,
+            diverged: false,
+            alerted: false,
+            checked_at: 1_650_000_000 + i,
+        };
+        let total = ROOT_CHECKPOINT_HISTORY_SIZE as u64 + 10;
+        for i in 0..total {
+            store.insert_root_checkpoint(key, checkpoint(i)).unwrap();
+        }
+        let kept = store.root_checkpoints(key).unwrap();
+        assert_eq!(kept.len(), ROOT_CHECKPOINT_HISTORY_SIZE);
+        assert_eq!(kept[0].block_number, types::U64::from(10));
+        assert_eq!(
+            kept.last().unwrap().block_number,
+            types::U64::from(total - 1)
+        );
+        let other = (types::U256::one(), types::Address::random());
+        assert!(store.root_checkpoints(other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn api_usage_stays_within_the_admitted_limits() {
+        let store = SledStore::temporary().unwrap();
+        let relay = ApiUsage {
+            relays: 1,
+            leaves_bytes: 0,
+        };
+        let admit = |sum: &ApiUsage| sum.relays <= 2;
+        assert!(store.add_api_usage("partner", 1, relay, admit).unwrap().1);
+        assert!(store.add_api_usage("partner", 1, relay, admit).unwrap().1);
+        let (usage, added) =
+            store.add_api_usage("partner", 1, relay, admit).unwrap();
+        assert!(!added);
+        assert_eq!(usage.relays, 2);
+        assert_eq!(store.api_usage("partner", 1).unwrap().relays, 2);
+        // every day and every API key has its own counters.
+        assert_eq!(store.api_usage("partner", 2).unwrap(), ApiUsage::default());
+        assert_eq!(
+            store.api_usage("partners", 1).unwrap(),
+            ApiUsage::default()
+        );
+        // a corrupted counter is an error, it is not reset.
+        let tree = store.db.open_tree("api_usage").unwrap();
+        tree.insert(api_usage_key("partner", 3), &b"corrupted"[..])
+            .unwrap();
+        assert!(store.add_api_usage("partner", 3, relay, admit).is_err());
+        assert_eq!(
+            tree.get(api_usage_key("partner", 3)).unwrap().unwrap(),
+            &b"corrupted"[..]
+        );
+    }
+
+    #[test]
+    fn concurrent_relay_costs_are_all_recorded() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let contract = types::Address::random();
+        let day = 19_000 * ACCOUNTING_BUCKET;
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    store
+                        .record_relay_cost(
+                            chain_id,
+                            contract,
+                            types::Address::zero(),
+                            None,
+                            day + 10,
+                            10.into(),
+                            100.into(),
+                            1.into(),
+                        )
+                        .unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let entries = store.accounting(day, day).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].transactions, 8);
+        assert_eq!(entries[0].gas_spent, types::U256::from(800));
+        assert_eq!(entries[0].fees_earned, types::U256::from(8));
+    }
+
+    #[test]
+    fn earnings_are_summed_per_token() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let token = types::Address::random();
+        store
+            .record_earning(chain_id, token, None, types::U256::from(10))
+            .unwrap();
+        store
+            .record_earning(chain_id, token, None, types::U256::from(5))
+            .unwrap();
+        store
+            .record_earning(chain_id, types::Address::zero(), None, 1.into())
+            .unwrap();
+        let earnings = store.earnings().unwrap();
+        assert_eq!(earnings.len(), 2);
+        let earning = earnings.iter().find(|e| e.token == token).unwrap();
+        assert_eq!(earning.total, types::U256::from(15));
+        assert_eq!(earning.withdrawals, 2);
+    }
+
+    #[test]
+    fn accounting_is_kept_per_identity() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::one();
+        let contract = types::Address::random();
+        let token = types::Address::zero();
+        let day = 19_000 * ACCOUNTING_BUCKET;
+        for identity in [None, Some("dapp"), Some("dapp"), Some("other")] {
+            store
+                .record_relay_cost(
+                    chain_id,
+                    contract,
+                    token,
+                    identity,
+                    day + 10,
+                    10.into(),
+                    100.into(),
+                    1.into(),
+                )
+                .unwrap();
+            store
+                .record_earning(chain_id, token, identity, 1.into())
+                .unwrap();
+        }
+        let entries = store.accounting(day, day).unwrap();
+        assert_eq!(entries.len(), 3);
+        let dapp = entries
+            .iter()
+            .find(|e| e.identity.as_deref() == Some("dapp"))
+            .unwrap();
+        assert_eq!(dapp.transactions, 2);
+        assert_eq!(dapp.gas_spent, types::U256::from(200));
+        let default = entries.iter().find(|e| e.identity.is_none()).unwrap();
+        assert_eq!(default.transactions, 1);
+        let earnings = store.earnings().unwrap();
+        assert_eq!(earnings.len(), 3);
+        let other = earnings
+            .iter()
+            .find(|e| e.identity.as_deref() == Some("other"))
+            .unwrap();
+        assert_eq!(other.total, types::U256::one());
+        assert_eq!(other.withdrawals, 1);
+    }
+
+    #[test]
+    fn relayed_txs_are_keyed_by_chain() {
+        let store = SledStore::temporary().unwrap();
+        let tx = RelayedTx {
+            chain_id: TypedChainId::Evm(1),
+            tx_hash: types::H256::random(),
+            relayed_at: 1,
+            block_number: None,
+            success: None,
+        };
+        store.insert_relayed_tx(tx.clone()).unwrap();
+        let found = store.get_relayed_tx(tx.chain_id, tx.tx_hash).unwrap();
+        assert_eq!(found, Some(tx.clone()));
+        let other_chain =
+            store.get_relayed_tx(TypedChainId::Evm(2), tx.tx_hash);
+        assert_eq!(other_chain.unwrap(), None);
+        // a Substrate node with the same chain id is another chain.
+        let node = store.get_relayed_tx(TypedChainId::Substrate(1), tx.tx_hash);
+        assert_eq!(node.unwrap(), None);
+        let extrinsic = RelayedTx {
+            chain_id: TypedChainId::Substrate(1),
+            block_number: Some(12),
+            success: Some(true),
+            ..tx.clone()
+        };
+        store.insert_relayed_tx(extrinsic.clone()).unwrap();
+        let found = store.get_relayed_tx(extrinsic.chain_id, tx.tx_hash);
+        assert_eq!(found.unwrap(), Some(extrinsic));
+        // the records of the EVM chains are untouched.
+        let found = store.get_relayed_tx(tx.chain_id, tx.tx_hash).unwrap();
+        assert_eq!(found, Some(tx));
+    }
+
+    #[test]
+    fn relayed_txs_recorded_with_plain_chain_ids_are_read() {
+        let tx: RelayedTx = serde_json::from_str(
+            r#"{"chainId":"0x5","txHash":"0x0000000000000000000000000000000000000000000000000000000000000001","relayedAt":1}"#,
+        )
+        .unwrap();
+        assert_eq!(tx.chain_id, TypedChainId::Evm(5));
+        assert_eq!(tx.block_number, None);
+    }
+
+    #[test]
+    fn substrate_proposals_are_keyed_by_bridge_and_deposit() {
+        let store = SledStore::temporary().unwrap();
+        let chain_id = types::U256::from(1080);
+        let proposal = SubstrateProposal {
+            src_chain_id: 5,
+            deposit_nonce: 1,
+            status: SubstrateProposalStatus::Voting,
+            votes_for: 1,
+            votes_against: 0,
+            updated_at: 10,
+        };
+        store
+            .insert_substrate_proposal(chain_id, proposal.clone())
+            .unwrap();
+        let found = store.get_substrate_proposal(chain_id, 5, 1).unwrap();
+        assert_eq!(found, Some(proposal.clone()));
+        // neither the other deposits nor the other bridges see it.
+        assert_eq!(store.get_substrate_proposal(chain_id, 5, 2).unwrap(), None);
+        assert_eq!(
+            store
+                .get_substrate_proposal(types::U256::from(1081), 5, 1)
+                .unwrap(),
+            None
+        );
+        let approved = SubstrateProposal {
+            status: SubstrateProposalStatus::Approved,
+            ..proposal
+        };
+        store
+            .insert_substrate_proposal(chain_id, approved.clone())
+            .unwrap();
+        let removed = store.remove_substrate_proposal(chain_id, 5, 1).unwrap();
+        assert_eq!(removed, Some(approved));
+        assert_eq!(store.get_substrate_proposal(chain_id, 5, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn audit_log_is_hash_chained() {
+        let store = SledStore::temporary().unwrap();
+        for chain_id in [1u64, 4] {
+            let entry = AuditEntry::new(AuditEvent::AdminAction {
+                action: String::from("pauseChain"),
+                chain_id: Some(chain_id),
+            });
+            store.append_audit_entry(entry).unwrap();
+        }
+        let mut records = store.audit_records(1, 10).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].prev_hash, records[0].hash);
+        assert_eq!(verify_audit_chain(None, &records).unwrap(), None);
+        // a page is checked against the last record of the previous page.
+        let page = store.audit_records(2, 10).unwrap();
+        assert_eq!(page, records[1..]);
+        assert_eq!(verify_audit_chain(Some(&records[0]), &page).unwrap(), None);
+        assert_eq!(verify_audit_chain(None, &page).unwrap(), Some(1));
+        assert_eq!(store.audit_records(1, 1).unwrap(), records[..1]);
+        // tampering with a record breaks the chain.
+        records[0].entry.client_ip = Some([10, 0, 0, 1].into());
+        assert_eq!(verify_audit_chain(None, &records).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn conflicting_leaves_are_not_overwritten() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::from(4), types::Address::random());
+        let leaves = [(0, types::H256::random()), (1, types::H256::random())];
+        store.insert_leaves(key, &leaves).unwrap();
+        // writing the same leaves again is fine.
+        store.insert_leaves(key, &leaves[1..]).unwrap();
+        let new_leaf = types::H256::random();
+        let err = store
+            .insert_leaves(key, &[(2, new_leaf), (1, types::H256::random())])
+            .unwrap_err();
+        let conflict = err.downcast_ref::<LeafConflict>().unwrap();
+        assert_eq!((conflict.index, conflict.cached), leaves[1]);
+        // nothing got written.
+        assert_eq!(store.get_leaves_with_index(key).unwrap(), leaves);
+        assert_eq!(store.get_leaves_count(key).unwrap(), 2);
+        assert_eq!(store.get_leaf_index(key, new_leaf).unwrap(), None);
+    }
+
+    #[test]
+    fn leaves_are_rolled_back_before_their_block() {
+        let store = SledStore::temporary().unwrap();
+        let key = (types::U256::from(4), types::Address::random());
+        // two leaves per block, across two segments.
+        let leaves: Vec<_> = (0..300u32)
+            .map(|i| (i, types::H256::from_low_u64_be(i.into())))
+            .collect();
+        let blocks: Vec<_> = (0..300u32)
+            .map(|i| (i, types::U64::from(100 + i / 2)))
+            .collect();
+        store.insert_leaves(key, &leaves).unwrap();
+        store.insert_leaves_block_numbers(key, &blocks).unwrap();
+        store
+            .insert_last_deposit_block_number(key, 249.into())
+            .unwrap();
+        store.set_last_block_number(key, 260.into()).unwrap();
+        store
+            .set_last_event_block_number(key, "Deposit", 260.into())
+            .unwrap();
+        // the leaf #201 got deposited at the block 200.
+        store.roll_back_leaves(key, 201).unwrap();
+        assert_eq!(store.get_leaves_with_index(key).unwrap(), leaves[..201]);
+        assert_eq!(store.get_leaves_count(key).unwrap(), 201);
+        assert_eq!(store.get_leaf_index(key, leaves[201].1).unwrap(), None);
+        assert_eq!(
+            store.get_last_block_number(key, 1.into()).unwrap(),
+            types::U64::from(199)
+        );
+        assert_eq!(
+            store.get_last_event_block_number(key, "Deposit").unwrap(),
+            Some(types::U64::from(199))
+        );
+        assert_eq!(
+            store.get_last_deposit_block_number(key).unwrap(),
+            types::U64::from(199)
+        );
+        // the rolled back leaves could be cached again, as different ones.
+        let reorged = (201, types::H256::random());
+        store.insert_leaves(key, &[reorged]).unwrap();
+        assert_eq!(store.get_leaf_index(key, reorged.1).unwrap(), Some(201));
+    }
+
+    #[cfg(feature = "evm")]
     #[test]
     fn tx_queue_should_work() {
         let tmp = tempfile::tempdir().unwrap();
@@ -471,6 +3458,14 @@ mod tests {
                 tx2.clone(),
             )
             .unwrap();
+        let len = || {
+            QueueStore::<TypedTransaction>::count_items(
+                &store,
+                SledQueueKey::from_evm_chain_id(chain_id),
+            )
+            .unwrap()
+        };
+        assert_eq!(len(), 2);
 
         // now let's dequeue transactions.
         assert_eq!(
@@ -485,6 +3480,7 @@ mod tests {
                 .unwrap(),
             Some(tx2)
         );
+        assert_eq!(len(), 0);
 
         let tx3: TypedTransaction = TransactionRequest::pay(
             types::Address::random(),
@@ -509,9 +3505,11 @@ mod tests {
             SledQueueKey::from_evm_tx(chain_id, &tx3)
         )
         .unwrap());
+        assert_eq!(len(), 1);
         let _: Option<TypedTransaction> = store
             .remove_item(SledQueueKey::from_evm_tx(chain_id, &tx3))
             .unwrap();
+        assert_eq!(len(), 0);
         assert!(!QueueStore::<TypedTransaction>::has_item(
             &store,
             SledQueueKey::from_evm_tx(chain_id, &tx3)
@@ -0,0 +1,394 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! The fixture contracts, deployed from their Hardhat artifacts.
+//!
+//! The artifacts are looked up by contract name under the directory in
+//! `WEBB_EVM_ARTIFACTS` (the `artifacts` directory of a protocol-solidity build).
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context;
+use webb::evm::ethers::abi::{Abi, Tokenize};
+use webb::evm::ethers::prelude::*;
+use webb::substrate::protocol_substrate_runtime::api::runtime_types::{
+    pallet_anchor_handler, pallet_bridge, webb_standalone_runtime::Call,
+};
+use webb::substrate::subxt::sp_core::sr25519::Pair as Sr25519Pair;
+use webb::substrate::subxt::sp_core::Pair as _;
+use webb::substrate::{protocol_substrate_runtime, subxt};
+
+use super::{
+    DevClient, LocalEvmChain, LocalSubstrateNode, DEV_PRIVATE_KEY,
+    SUBSTRATE_CHAIN_ID,
+};
+use crate::proposals;
+
+/// The environment variable holding the directory of the Hardhat artifacts.
+pub const ARTIFACTS_ENV: &str = "WEBB_EVM_ARTIFACTS";
+/// The hasher of the anchors, the scenarios do not depend on the hash function.
+const HASHER: &str = "KeccakHasher";
+/// The token the anchors are deposited in.
+const TOKEN: &str = "ERC20PresetMinterPauser";
+/// The height of the merkle trees of the anchors.
+const MERKLE_TREE_HEIGHT: u32 = 30;
+/// The fixed deposit amount of the anchors, 1 token.
+const DENOMINATION: u64 = 1_000_000_000_000_000_000;
+
+/// The Hardhat artifacts of the fixture contracts.
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    dir: PathBuf,
+}
+
+impl Artifacts {
+    /// The artifacts under the directory in `WEBB_EVM_ARTIFACTS`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let dir = std::env::var(ARTIFACTS_ENV).with_context(|| {
+            format!(
+                "{} should be set to the Hardhat artifacts directory of protocol-solidity",
+                ARTIFACTS_ENV
+            )
+        })?;
+        Ok(Self { dir: dir.into() })
+    }
+
+    /// Loads the ABI and the bytecode of that contract.
+    pub fn load(&self, name: &str) -> anyhow::Result<(Abi, Bytes)> {
+        let pattern = format!("{}/**/{}.json", self.dir.display(), name);
+        let path = glob::glob(&pattern)?
+            .flatten()
+            .next()
+            .with_context(|| format!("no artifact found for {}", name))?;
+        let artifact: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path)?)?;
+        let abi = serde_json::from_value(artifact["abi"].clone())?;
+        let bytecode = artifact["bytecode"]
+            .as_str()
+            .with_context(|| format!("{} has no bytecode", name))?;
+        let bytecode = hex::decode(bytecode.trim_start_matches("0x"))?;
+        Ok((abi, bytecode.into()))
+    }
+
+    /// Deploys that contract, with the constructor arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the contract
+    /// * `client` - The client deploying the contract
+    /// * `args` - The constructor arguments
+    pub async fn deploy<T: Tokenize>(
+        &self,
+        name: &str,
+        client: Arc<DevClient>,
+        args: T,
+    ) -> anyhow::Result<Contract<DevClient>> {
+        let (abi, bytecode) = self.load(name)?;
+        let contract = ContractFactory::new(abi, bytecode, client)
+            .deploy(args)?
+            .send()
+            .await
+            .with_context(|| format!("failed to deploy {}", name))?;
+        tracing::debug!("Deployed {} at {:?}", name, contract.address());
+        Ok(contract)
+    }
+}
+
+/// A Signature Bridge, with an anchor behind its handler, deployed on a local chain.
+///
+/// The dev account is the governor of the bridge, the relayer signs the proposals with it.
+#[derive(Debug, Clone)]
+pub struct BridgeFixture {
+    /// The Signature Bridge.
+    pub bridge: Address,
+    /// The anchor handler of the bridge.
+    pub handler: Address,
+    /// The fixed deposit anchor.
+    pub anchor: Address,
+    /// The token of the anchor.
+    pub token: Address,
+    /// The block the fixtures got deployed at.
+    pub deployed_at: u64,
+    token_abi: Abi,
+}
+
+impl BridgeFixture {
+    /// Deploys the fixture contracts on that chain.
+    ///
+    /// The resource of the anchor is not registered on the bridge, the relayer registers it.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The chain to deploy on
+    /// * `artifacts` - The artifacts of the fixture contracts
+    pub async fn deploy(
+        chain: &LocalEvmChain<'_>,
+        artifacts: &Artifacts,
+    ) -> anyhow::Result<Self> {
+        let client = chain.client()?;
+        let deployed_at = client.get_block_number().await?.as_u64();
+        let governor = client.address();
+        let bridge = artifacts
+            .deploy("SignatureBridge", client.clone(), governor)
+            .await?;
+        let handler = artifacts
+            .deploy(
+                "AnchorHandler",
+                client.clone(),
+                (
+                    bridge.address(),
+                    Vec::<[u8; 32]>::new(),
+                    Vec::<Address>::new(),
+                ),
+            )
+            .await?;
+        let token = artifacts
+            .deploy(
+                TOKEN,
+                client.clone(),
+                ("Webb Test".to_string(), "WEBB".to_string()),
+            )
+            .await?;
+        let hasher = artifacts.deploy(HASHER, client.clone(), ()).await?;
+        // the scenarios never withdraw, so the verifier is never called.
+        let verifier = token.address();
+        let anchor = artifacts
+            .deploy(
+                "FixedDepositAnchor",
+                client.clone(),
+                (
+                    handler.address(),
+                    token.address(),
+                    verifier,
+                    hasher.address(),
+                    U256::from(DENOMINATION),
+                    MERKLE_TREE_HEIGHT,
+                    1u8,
+                ),
+            )
+            .await?;
+        Ok(Self {
+            bridge: bridge.address(),
+            handler: handler.address(),
+            anchor: anchor.address(),
+            token: token.address(),
+            deployed_at,
+            token_abi: token.abi().clone(),
+        })
+    }
+
+    /// Deposits that commitment in the anchor, from the dev account.
+    pub async fn deposit(
+        &self,
+        chain: &LocalEvmChain<'_>,
+        commitment: [u8; 32],
+    ) -> anyhow::Result<()> {
+        let client = chain.client()?;
+        let token =
+            Contract::new(self.token, self.token_abi.clone(), client.clone());
+        let amount = U256::from(DENOMINATION);
+        token
+            .method::<_, ()>("mint", (client.address(), amount))?
+            .send()
+            .await?
+            .await?;
+        token
+            .method::<_, bool>("approve", (self.anchor, amount))?
+            .send()
+            .await?
+            .await?;
+        let anchor = webb::evm::contract::protocol_solidity::FixedDepositAnchorContract::new(
+            self.anchor,
+            client,
+        );
+        anchor.deposit(commitment).send().await?.await?;
+        Ok(())
+    }
+
+    /// The relayer configuration of the chain of the fixtures, with the anchor linked to
+    /// the anchors of the other chains.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The chain of the fixtures
+    /// * `linked` - The other chains, with their fixtures
+    pub fn chain_config(
+        &self,
+        chain: &LocalEvmChain<'_>,
+        linked: &[(&LocalEvmChain<'_>, &BridgeFixture)],
+    ) -> serde_json::Value {
+        let events_watcher = serde_json::json!({
+            "enabled": true,
+            "polling-interval": 1000,
+            "print-progress-interval": 0,
+        });
+        let linked_anchors: Vec<_> = linked
+            .iter()
+            .map(|(chain, fixture)| {
+                serde_json::json!({
+                    "chain": chain.name,
+                    "address": fixture.anchor,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "enabled": true,
+            "http-endpoint": chain.http_endpoint(),
+            "ws-endpoint": chain.ws_endpoint(),
+            "chain-id": chain.chain_id,
+            "private-key": DEV_PRIVATE_KEY,
+            "contracts": [
+                {
+                    "contract": "Anchor",
+                    "address": self.anchor,
+                    "deployed-at": self.deployed_at,
+                    "size": 1,
+                    "events-watcher": events_watcher,
+                    "withdraw-fee-percentage": 0,
+                    "withdraw-gaslimit": "0x350000",
+                    "linked-anchors": linked_anchors,
+                    "proposal-signing-backend": {
+                        "type": "Mocked",
+                        "private-key": DEV_PRIVATE_KEY,
+                    },
+                    "register-resource": true,
+                },
+                {
+                    "contract": "SignatureBridge",
+                    "address": self.bridge,
+                    "deployed-at": self.deployed_at,
+                    "events-watcher": events_watcher,
+                },
+            ],
+        })
+    }
+}
+
+type WebbProtocolConfig = subxt::DefaultConfig;
+type WebbProtocolRuntimeApi = protocol_substrate_runtime::api::RuntimeApi<
+    WebbProtocolConfig,
+    subxt::DefaultExtra<WebbProtocolConfig>,
+>;
+
+/// An anchor of the anchor handler pallet, on a local Substrate node, linked to an anchor
+/// of an EVM chain through the bridge pallet.
+///
+/// The `//Alice` dev account, the sudo of the node, is the only relayer of the bridge,
+/// so its vote alone executes a proposal.
+#[derive(Debug, Clone)]
+pub struct SubstrateAnchorFixture {
+    /// The tree id of the anchor.
+    pub tree_id: u32,
+    /// The resource id of the anchor, on the bridge pallet.
+    pub resource_id: proposals::ResourceId,
+}
+
+impl SubstrateAnchorFixture {
+    /// Creates the anchor on the node, and sets up the bridge pallet for the proposals
+    /// from the anchor of that EVM chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The Substrate node
+    /// * `src_chain` - The EVM chain of the linked anchor
+    pub async fn create(
+        node: &LocalSubstrateNode<'_>,
+        src_chain: &LocalEvmChain<'_>,
+    ) -> anyhow::Result<Self> {
+        let api = node_api(node).await?;
+        let alice = subxt::PairSigner::<_, _, Sr25519Pair>::new(
+            Sr25519Pair::from_string("//Alice", None)
+                .map_err(|e| anyhow::anyhow!("{:?}", e))?,
+        );
+        let src_chain_id =
+            proposals::evm_typed_chain_id(src_chain.chain_id.into())?
+                .chain_id();
+        let tree_id =
+            api.storage().merkle_tree_bn254().next_tree_id(None).await?;
+        let resource_id =
+            proposals::substrate_resource_id(tree_id, SUBSTRATE_CHAIN_ID);
+        let r_id = resource_id.into_bytes();
+        let calls = vec![
+            Call::Bridge(pallet_bridge::pallet::Call::set_threshold {
+                threshold: 1,
+            }),
+            Call::Bridge(pallet_bridge::pallet::Call::add_relayer {
+                v: alice.account_id().clone(),
+            }),
+            Call::Bridge(pallet_bridge::pallet::Call::whitelist_chain {
+                id: src_chain_id,
+            }),
+            Call::Bridge(pallet_bridge::pallet::Call::set_resource {
+                id: r_id,
+                method: b"AnchorHandler.execute_anchor_update_proposal"
+                    .to_vec(),
+            }),
+            Call::AnchorHandler(
+                pallet_anchor_handler::pallet::Call::execute_anchor_create_proposal {
+                    src_chain_id,
+                    r_id,
+                    max_edges: 1,
+                    tree_depth: MERKLE_TREE_HEIGHT as u8,
+                    asset: 0,
+                },
+            ),
+        ];
+        for call in calls {
+            api.tx()
+                .sudo()
+                .sudo(call)
+                .sign_and_submit_then_watch(&alice)
+                .await?
+                .wait_for_finalized_success()
+                .await?;
+        }
+        tracing::debug!(
+            tree_id,
+            resource_id = %hex::encode(r_id),
+            "Created the anchor on the Substrate node",
+        );
+        Ok(Self {
+            tree_id,
+            resource_id,
+        })
+    }
+
+    /// Returns the root of the edge of the anchor from that EVM chain, if any.
+    pub async fn edge_root(
+        &self,
+        node: &LocalSubstrateNode<'_>,
+        src_chain: &LocalEvmChain<'_>,
+    ) -> anyhow::Result<Option<[u8; 32]>> {
+        let api = node_api(node).await?;
+        let src_chain_id =
+            proposals::evm_typed_chain_id(src_chain.chain_id.into())?
+                .chain_id();
+        let edge = api
+            .storage()
+            .linkable_tree_bn254()
+            .edge_list(self.tree_id, src_chain_id, None)
+            .await?;
+        Ok(edge.map(|edge| edge.root.0))
+    }
+}
+
+async fn node_api(
+    node: &LocalSubstrateNode<'_>,
+) -> anyhow::Result<WebbProtocolRuntimeApi> {
+    let client = subxt::ClientBuilder::new()
+        .set_url(format!("ws://127.0.0.1:{}", node.ws_port))
+        .build()
+        .await?;
+    Ok(client.to_runtime_api())
+}
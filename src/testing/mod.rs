@@ -0,0 +1,275 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! # Relayer Testing Harness 🕸️
+//!
+//! A harness for running the relayer end-to-end, against dockerized chains.
+//!
+//! The chains are started with [testcontainers](https://docs.rs/testcontainers), an
+//! [Anvil](https://github.com/foundry-rs/foundry) node per EVM chain and a standalone
+//! protocol-substrate node, then the fixture contracts are deployed on them and the relayer
+//! is started in-process, with a configuration pointing at them.
+//!
+//! The scenarios run with `cargo test --features integration`, they need a running docker
+//! daemon and the Hardhat artifacts of the fixture contracts, see [`fixtures`].
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Future;
+use testcontainers::clients::Cli;
+use testcontainers::core::WaitFor;
+use testcontainers::images::generic::GenericImage;
+use testcontainers::{Container, RunnableImage};
+use webb::evm::ethers::prelude::*;
+
+use crate::config;
+use crate::context::RelayerContext;
+use crate::events_watcher::BridgeRegistry;
+use crate::service;
+use crate::store::cache::{CachedStore, RelayerStore};
+use crate::store::sled::SledStore;
+
+/// A module for deploying the fixture contracts on the local chains.
+pub mod fixtures;
+/// A module for the end-to-end relay scenarios.
+#[cfg(test)]
+mod scenarios;
+
+/// The image of the Anvil nodes.
+///
+/// Pinned, so a new release of Anvil never changes the chains the scenarios run on.
+const ANVIL_IMAGE: (&str, &str) = ("ghcr.io/foundry-rs/foundry", "v1.0.0");
+/// The image of the Substrate nodes, the one the E2E tests use too.
+const SUBSTRATE_IMAGE: (&str, &str) = (
+    "ghcr.io/webb-tools/protocol-substrate-standalone-node",
+    "edge",
+);
+/// The chain id of the Substrate nodes, in their typed chain id.
+pub const SUBSTRATE_CHAIN_ID: u32 = 1080;
+/// The private key of the first dev account of Anvil, it deploys the fixtures and
+/// runs the relayer.
+pub const DEV_PRIVATE_KEY: &str =
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// The client of a local EVM chain, signing with the dev account.
+pub type DevClient = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+/// An Anvil node, running in a container.
+pub struct LocalEvmChain<'d> {
+    /// The name of the chain, in the relayer configuration.
+    pub name: String,
+    /// The chain id of the chain.
+    pub chain_id: u64,
+    /// The port of the node, on the host.
+    pub port: u16,
+    _container: Container<'d, GenericImage>,
+}
+
+impl<'d> LocalEvmChain<'d> {
+    /// Starts an Anvil node with that chain id, mining a block every second.
+    ///
+    /// # Arguments
+    ///
+    /// * `docker` - The docker client, the node is stopped once it is dropped
+    /// * `name` - The name of the chain, in the relayer configuration
+    /// * `chain_id` - The chain id of the chain
+    pub fn start(docker: &'d Cli, name: &str, chain_id: u64) -> Self {
+        let image = GenericImage::new(ANVIL_IMAGE.0, ANVIL_IMAGE.1)
+            .with_entrypoint("anvil")
+            .with_exposed_port(8545)
+            .with_wait_for(WaitFor::message_on_stdout("Listening on"));
+        let args = vec![
+            "--host".to_string(),
+            "0.0.0.0".to_string(),
+            "--chain-id".to_string(),
+            chain_id.to_string(),
+            "--block-time".to_string(),
+            "1".to_string(),
+        ];
+        let container = docker.run(RunnableImage::from((image, args)));
+        let port = container.get_host_port_ipv4(8545);
+        Self {
+            name: name.to_string(),
+            chain_id,
+            port,
+            _container: container,
+        }
+    }
+
+    /// The HTTP endpoint of the node.
+    pub fn http_endpoint(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// The websocket endpoint of the node.
+    pub fn ws_endpoint(&self) -> String {
+        format!("ws://127.0.0.1:{}", self.port)
+    }
+
+    /// A client of the node, signing with the dev account.
+    pub fn client(&self) -> anyhow::Result<Arc<DevClient>> {
+        let provider = Provider::<Http>::try_from(self.http_endpoint())?;
+        let wallet = DEV_PRIVATE_KEY
+            .parse::<LocalWallet>()?
+            .with_chain_id(self.chain_id);
+        Ok(Arc::new(SignerMiddleware::new(provider, wallet)))
+    }
+}
+
+/// A standalone protocol-substrate node, running in a container.
+pub struct LocalSubstrateNode<'d> {
+    /// The name of the node, in the relayer configuration.
+    pub name: String,
+    /// The port of the websocket endpoint, on the host.
+    pub ws_port: u16,
+    /// The port of the HTTP endpoint, on the host.
+    pub http_port: u16,
+    _container: Container<'d, GenericImage>,
+}
+
+impl<'d> LocalSubstrateNode<'d> {
+    /// Starts a development node.
+    ///
+    /// # Arguments
+    ///
+    /// * `docker` - The docker client, the node is stopped once it is dropped
+    /// * `name` - The name of the node, in the relayer configuration
+    pub fn start(docker: &'d Cli, name: &str) -> Self {
+        let image = GenericImage::new(SUBSTRATE_IMAGE.0, SUBSTRATE_IMAGE.1)
+            .with_exposed_port(9944)
+            .with_exposed_port(9933)
+            .with_wait_for(WaitFor::message_on_stderr(
+                "Running JSON-RPC WS server",
+            ));
+        let args = [
+            "--dev",
+            "--tmp",
+            "--ws-external",
+            "--rpc-external",
+            "--rpc-cors",
+            "all",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+        let container = docker.run(RunnableImage::from((image, args)));
+        Self {
+            name: name.to_string(),
+            ws_port: container.get_host_port_ipv4(9944),
+            http_port: container.get_host_port_ipv4(9933),
+            _container: container,
+        }
+    }
+
+    /// The configuration of the node, signing with the `//Alice` dev account, and
+    /// watching the proposals of its bridge pallet.
+    pub fn config(&self) -> serde_json::Value {
+        serde_json::json!({
+            "enabled": true,
+            "http-endpoint": format!("http://127.0.0.1:{}", self.http_port),
+            "ws-endpoint": format!("ws://127.0.0.1:{}", self.ws_port),
+            "runtime": "WebbProtocol",
+            "suri": "//Alice",
+            "chain-id": SUBSTRATE_CHAIN_ID,
+            "pallets": [
+                {
+                    "pallet": "Bridge",
+                    "events-watcher": {
+                        "enabled": true,
+                        "polling-interval": 1000,
+                        "print-progress-interval": 0,
+                    },
+                },
+            ],
+        })
+    }
+}
+
+/// A relayer running in-process, against a configuration written to a temporary directory.
+pub struct TestRelayer {
+    /// The context of the relayer.
+    pub ctx: RelayerContext,
+    /// The store of the relayer, a temporary one.
+    pub store: Arc<RelayerStore>,
+    _config_dir: tempfile::TempDir,
+}
+
+impl TestRelayer {
+    /// Writes the configuration, loads it the way the relayer does, then starts the
+    /// relayer services.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The relayer configuration, as JSON
+    pub async fn start(config: serde_json::Value) -> anyhow::Result<Self> {
+        let config_dir = tempfile::tempdir()?;
+        write_config(config_dir.path(), &config)?;
+        let config = config::load(config_dir.path())?;
+        let ctx = RelayerContext::new(config);
+        let store = Arc::new(CachedStore::new(
+            SledStore::temporary()?,
+            ctx.config.store.cache_capacity,
+        ));
+        let bridge_registry = BridgeRegistry::new(store.clone())?;
+        service::ignite(&ctx, store.clone(), bridge_registry).await?;
+        Ok(Self {
+            ctx,
+            store,
+            _config_dir: config_dir,
+        })
+    }
+}
+
+impl Drop for TestRelayer {
+    fn drop(&mut self) {
+        self.ctx.shutdown();
+    }
+}
+
+fn write_config(dir: &Path, config: &serde_json::Value) -> anyhow::Result<()> {
+    let path = dir.join("config.json");
+    std::fs::write(path, serde_json::to_vec_pretty(config)?)?;
+    Ok(())
+}
+
+/// Polls the condition every second until it holds, fails after the timeout.
+///
+/// # Arguments
+///
+/// * `timeout` - How long to wait for the condition
+/// * `condition` - The condition, errors are retried too
+pub async fn wait_until<F, Fut>(
+    timeout: Duration,
+    mut condition: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<bool>>,
+{
+    let started = std::time::Instant::now();
+    loop {
+        match condition().await {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) => tracing::debug!("The condition failed: {}", e),
+        }
+        anyhow::ensure!(
+            started.elapsed() < timeout,
+            "The condition did not hold after {:?}",
+            timeout
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
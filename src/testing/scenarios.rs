@@ -0,0 +1,129 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::time::Duration;
+
+use testcontainers::clients::Cli;
+use webb::evm::contract::protocol_solidity::FixedDepositAnchorContract;
+
+use super::fixtures::{Artifacts, BridgeFixture, SubstrateAnchorFixture};
+use super::*;
+use crate::handler::PongResponse;
+
+#[tokio::test]
+async fn deposits_are_relayed_as_anchor_updates() -> anyhow::Result<()> {
+    let artifacts = Artifacts::from_env()?;
+    let docker = Cli::default();
+    let hermes = LocalEvmChain::start(&docker, "hermes", 5001);
+    let athena = LocalEvmChain::start(&docker, "athena", 5002);
+    let hermes_fixture = BridgeFixture::deploy(&hermes, &artifacts).await?;
+    let athena_fixture = BridgeFixture::deploy(&athena, &artifacts).await?;
+    let relayer = TestRelayer::start(serde_json::json!({
+        "evm": {
+            "hermes": hermes_fixture.chain_config(&hermes, &[(&athena, &athena_fixture)]),
+            "athena": athena_fixture.chain_config(&athena, &[(&hermes, &hermes_fixture)]),
+        },
+    }))
+    .await?;
+
+    // deposit → the anchor update proposal → its execution on the bridge of the other chain.
+    hermes_fixture.deposit(&hermes, rand::random()).await?;
+    let hermes_anchor = FixedDepositAnchorContract::new(
+        hermes_fixture.anchor,
+        hermes.client()?,
+    );
+    let athena_anchor = FixedDepositAnchorContract::new(
+        athena_fixture.anchor,
+        athena.client()?,
+    );
+    let root = hermes_anchor.get_last_root().call().await?;
+    wait_until(Duration::from_secs(120), || {
+        let athena_anchor = &athena_anchor;
+        async move {
+            let roots =
+                athena_anchor.get_latest_neighbor_roots().call().await?;
+            Ok(roots.contains(&root))
+        }
+    })
+    .await?;
+    drop(relayer);
+    Ok(())
+}
+
+#[tokio::test]
+async fn deposits_are_voted_as_anchor_updates_on_the_substrate_bridge(
+) -> anyhow::Result<()> {
+    let artifacts = Artifacts::from_env()?;
+    let docker = Cli::default();
+    let hermes = LocalEvmChain::start(&docker, "hermes", 5001);
+    let node = LocalSubstrateNode::start(&docker, "localnode");
+    let hermes_fixture = BridgeFixture::deploy(&hermes, &artifacts).await?;
+    let node_fixture = SubstrateAnchorFixture::create(&node, &hermes).await?;
+    // the anchor of hermes is linked to the anchor of the node, and its updates are
+    // voted on the bridge pallet of the node.
+    let mut hermes_config = hermes_fixture.chain_config(&hermes, &[]);
+    let anchor_config = &mut hermes_config["contracts"][0];
+    anchor_config["linked-anchors"] = serde_json::json!([
+        { "chain": node.name, "tree-id": node_fixture.tree_id },
+    ]);
+    anchor_config["proposal-signing-backend"] = serde_json::json!({
+        "type": "SubstrateBridge",
+        "node": node.name,
+    });
+    anchor_config["register-resource"] = serde_json::json!(false);
+    let relayer = TestRelayer::start(serde_json::json!({
+        "evm": { "hermes": hermes_config },
+        "substrate": { "localnode": node.config() },
+    }))
+    .await?;
+
+    // deposit → the vote for the anchor update → its execution by the bridge pallet.
+    hermes_fixture.deposit(&hermes, rand::random()).await?;
+    let hermes_anchor = FixedDepositAnchorContract::new(
+        hermes_fixture.anchor,
+        hermes.client()?,
+    );
+    let root = hermes_anchor.get_last_root().call().await?;
+    wait_until(Duration::from_secs(120), || {
+        let (node, hermes, node_fixture) = (&node, &hermes, &node_fixture);
+        async move {
+            let edge_root = node_fixture.edge_root(node, hermes).await?;
+            Ok(edge_root == Some(root))
+        }
+    })
+    .await?;
+    drop(relayer);
+    Ok(())
+}
+
+#[tokio::test]
+async fn the_relayer_connects_to_the_substrate_node() -> anyhow::Result<()> {
+    let docker = Cli::default();
+    let node = LocalSubstrateNode::start(&docker, "localnode");
+    let relayer = TestRelayer::start(serde_json::json!({
+        "substrate": { "localnode": node.config() },
+    }))
+    .await?;
+    let pong = PongResponse::new(&relayer.ctx);
+    assert_eq!(pong.substrate_nodes, vec!["localnode".to_string()]);
+    let diagnostics =
+        crate::diagnostics::diagnose_substrate_node(&relayer.ctx, "localnode")
+            .await;
+    assert!(
+        diagnostics.latest_block.ok,
+        "{:?}",
+        diagnostics.latest_block
+    );
+    Ok(())
+}
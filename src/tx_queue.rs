@@ -16,16 +16,24 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
-use ethereum_types::H256;
+use ethereum_types::{H256, U256};
 use futures::TryFutureExt;
 use rand::Rng;
 use webb::evm::ethers::core::types::transaction::eip2718::TypedTransaction;
+use webb::evm::ethers::core::types::NameOrAddress;
 use webb::evm::ethers::middleware::SignerMiddleware;
 use webb::evm::ethers::providers::Middleware;
+use webb::evm::ethers::signers::Signer;
 
 use crate::context::RelayerContext;
+use crate::events_watcher::record_proposal_execution;
+use crate::metrics::{self, Labels, Metric};
+use crate::proposals::TypedChainId;
 use crate::store::sled::SledQueueKey;
-use crate::store::QueueStore;
+use crate::store::{
+    BridgeKey, ProposalNonceStore, ProposalStore, QueueStore, SignedProposal,
+};
+use crate::tx_relay::evm::fees;
 use crate::utils::ClickableLink;
 
 /// The TxQueue stores transaction requests so the relayer can process them later.
@@ -41,7 +49,9 @@ pub struct TxQueue<S: QueueStore<TypedTransaction>> {
 
 impl<S> TxQueue<S>
 where
-    S: QueueStore<TypedTransaction, Key = SledQueueKey>,
+    S: QueueStore<TypedTransaction, Key = SledQueueKey>
+        + ProposalStore<Proposal = SignedProposal>
+        + ProposalNonceStore,
 {
     /// Creates a new TxQueue instance.
     ///
@@ -98,7 +108,11 @@ where
             .get(&self.chain_name)
             .context("Chain not configured")?;
         let chain_id = client.get_chainid().await?;
+        let typed_chain_id = crate::proposals::evm_typed_chain_id(chain_id)?;
+        let rollup = chain_config.rollup_kind();
         let store = self.store;
+        let ctx = &self.ctx;
+        let chain_name = &self.chain_name;
         let backoff = backoff::ExponentialBackoff {
             max_elapsed_time: None,
             ..Default::default()
@@ -113,168 +127,367 @@ where
         );
 
         let task = || async {
-            loop {
+            let mut client = client.clone();
+            'queue: loop {
+                // while the chain is paused, we keep the txs in the queue
+                // and wait for it to be resumed, so do the standbys until they lead.
+                if ctx.is_chain_paused(typed_chain_id) || !ctx.is_leader() {
+                    tracing::trace!(
+                        "Chain is paused or relayer is a standby, skipping this round ..."
+                    );
+                    tokio::time::sleep(Duration::from_millis(
+                        chain_config.tx_queue.max_sleep_interval,
+                    ))
+                    .await;
+                    continue;
+                }
                 tracing::trace!("Checking for any txs in the queue ...");
-                let maybe_tx = store
-                    .dequeue_item(SledQueueKey::from_evm_chain_id(chain_id))?;
+                let maybe_tx = dequeue_sendable_tx(
+                    ctx,
+                    store.as_ref(),
+                    chain_id,
+                    typed_chain_id,
+                )?;
+                let depth = store
+                    .count_items(SledQueueKey::from_evm_chain_id(chain_id))?;
+                metrics::set(
+                    Metric::TxQueueDepth,
+                    Labels::chain(chain_id, crate::probe::Kind::TxQueue),
+                    depth as f64,
+                );
                 let maybe_explorer = &chain_config.explorer;
                 let mut tx_hash: H256;
-                if let Some(raw_tx) = maybe_tx {
-                    let my_tx_hash = raw_tx.sighash(chain_id.as_u64());
-                    tx_hash = my_tx_hash;
-                    let pending_tx = client
-                        .send_transaction(raw_tx.clone(), None)
-                        .map_err(anyhow::Error::from);
-                    let tx = match pending_tx.await {
-                        Ok(pending) => {
-                            tx_hash = *pending;
-                            tracing::event!(
-                                target: crate::probe::TARGET,
-                                tracing::Level::DEBUG,
-                                kind = %crate::probe::Kind::TxQueue,
-                                ty = "EVM",
-                                chain_id = %chain_id.as_u64(),
-                                pending = true,
-                                %tx_hash,
-                            );
-
-                            let tx_hash_string = format!("0x{:x}", tx_hash);
-                            if let Some(mut url) = maybe_explorer.clone() {
-                                url.set_path(&format!("tx/{}", tx_hash_string));
-                                let clickable_link = ClickableLink::new(
-                                    &tx_hash_string,
-                                    url.as_str(),
-                                );
-                                tracing::info!(
-                                    "Tx {} is submitted and pending!",
-                                    clickable_link,
-                                );
-                            } else {
-                                tracing::info!(
-                                    "Tx {} is submitted and pending!",
-                                    tx_hash_string,
-                                );
-                            }
-                            let result = pending
-                                .interval(Duration::from_millis(1000))
-                                .await;
-                            result
-                        }
-                        Err(e) => {
-                            let tx_hash_string = format!("0x{:x}", tx_hash);
-                            if let Some(mut url) = maybe_explorer.clone() {
-                                url.set_path(&format!("tx/{}", tx_hash_string));
-                                let clickable_link = ClickableLink::new(
-                                    &tx_hash_string,
-                                    url.as_str(),
-                                );
-                                tracing::error!(
-                                    "Error while sending tx {}, {}",
-                                    clickable_link,
-                                    e,
-                                );
-                            } else {
-                                tracing::error!(
-                                    "Error while sending tx {}, {}",
-                                    tx_hash_string,
+                if let Some(mut raw_tx) = maybe_tx {
+                    // the signing key is held until the tx is done, so a key rotation
+                    // waits for it, then the next txs are signed with the new key.
+                    let _signing = ctx.evm_signing_guard(chain_name).await;
+                    let wallet = ctx.evm_wallet(chain_name).await?;
+                    if wallet.address() != client.signer().address() {
+                        tracing::info!(
+                            old = %client.signer().address(),
+                            new = %wallet.address(),
+                            "Signing key rotated, switching to the new wallet",
+                        );
+                        client = Arc::new(SignerMiddleware::new(
+                            client.inner().clone(),
+                            wallet,
+                        ));
+                    }
+                    // the queued txs are built with mainnet-like gas assumptions,
+                    // that fail or overpay on the rollups.
+                    if chain_config.legacy_tx {
+                        raw_tx = fees::into_legacy_tx(raw_tx);
+                    }
+                    if let Some(rollup) = rollup {
+                        let prepared = fees::prepare_rollup_tx(
+                            client.as_ref(),
+                            rollup,
+                            client.signer().address(),
+                            raw_tx.clone(),
+                        )
+                        .await;
+                        match prepared {
+                            Ok(tx) => raw_tx = tx,
+                            Err(e) => {
+                                tracing::warn!(
+                                    ?rollup,
+                                    "Failed to prepare the tx for the rollup: {}",
                                     e
                                 );
                             }
-                            tracing::event!(
-                                target: crate::probe::TARGET,
-                                tracing::Level::DEBUG,
-                                kind = %crate::probe::Kind::TxQueue,
-                                ty = "EVM",
-                                chain_id = %chain_id.as_u64(),
-                                errored = true,
-                                %tx_hash,
-                                error = %e,
-                            );
-
-                            continue; // keep going.
                         }
-                    };
-                    match tx {
-                        Ok(Some(receipt)) => {
-                            let tx_hash_string =
-                                format!("0x{:x}", receipt.transaction_hash);
-                            if let Some(mut url) = maybe_explorer.clone() {
-                                url.set_path(&format!("tx/{}", tx_hash_string));
-                                let clickable_link = ClickableLink::new(
-                                    &tx_hash_string,
-                                    url.as_str(),
-                                );
-                                tracing::info!(
-                                    "Tx {} Finalized",
-                                    clickable_link
-                                );
-                            } else {
-                                tracing::info!(
-                                    "Tx {} Finalized",
-                                    tx_hash_string,
-                                );
-                            }
-                            tracing::event!(
-                                target: crate::probe::TARGET,
-                                tracing::Level::DEBUG,
-                                kind = %crate::probe::Kind::TxQueue,
-                                ty = "EVM",
-                                chain_id = %chain_id.as_u64(),
-                                finalized = true,
-                                %tx_hash,
+                    }
+                    // the queued tx is kept as is, it is enqueued again if it keeps
+                    // getting dropped.
+                    let queued_tx = raw_tx.clone();
+                    // the leader could have stepped down while the tx got prepared,
+                    // the tx is left to the next leader.
+                    if !ctx.is_leader() {
+                        tracing::warn!(
+                            "Stepped down to a standby, queueing the tx again"
+                        );
+                        store.enqueue_item(
+                            SledQueueKey::from_evm_chain_id(chain_id),
+                            queued_tx,
+                        )?;
+                        continue;
+                    }
+                    // the nonce is filled once, so a resubmission replaces the dropped tx.
+                    if let Err(e) =
+                        client.fill_transaction(&mut raw_tx, None).await
+                    {
+                        tracing::error!("Failed to fill the tx: {}", e);
+                        continue;
+                    }
+                    // on the rollups, the L1 data fee of the signed tx is part of its cost.
+                    let l1_fee = match rollup {
+                        Some(rollup) => fees::signed_l1_data_fee(
+                            client.as_ref(),
+                            rollup,
+                            chain_id.as_u64(),
+                            &raw_tx,
+                        )
+                        .await
+                        .unwrap_or_else(|e| {
+                            tracing::warn!(
+                                "Failed to estimate the L1 data fee: {}",
+                                e
                             );
-                        }
-                        Ok(None) => {
-                            // this should never happen
-                            // as we already know that is a bug in ethers
-                            // about timeing, so we already wait a bit
-                            // and increased the time interval for checking for
-                            // transaction status.
-                            let tx_hash_string = format!("0x{:x}", tx_hash);
+                            U256::zero()
+                        }),
+                        None => U256::zero(),
+                    };
+                    let mut resubmissions = 0;
+                    loop {
+                        // the nonce of a dropped tx is the next leader's once this
+                        // instance stepped down, the tx is left to it.
+                        if resubmissions > 0 && !ctx.is_leader() {
                             tracing::warn!(
-                                "Tx {} Dropped from Mempool!!",
-                                tx_hash_string
+                                "Stepped down to a standby, queueing the dropped tx again"
                             );
-                            // enquing the tx again
                             store.enqueue_item(
                                 SledQueueKey::from_evm_chain_id(chain_id),
-                                raw_tx,
+                                queued_tx,
                             )?;
+                            break;
                         }
-                        Err(e) => {
-                            let reason = e.to_string();
-                            let tx_hash_string = format!("0x{:x}", tx_hash);
-                            if let Some(mut url) = maybe_explorer.clone() {
-                                url.set_path(&format!("tx/{}", tx_hash_string));
-                                let clickable_link = ClickableLink::new(
-                                    &tx_hash_string,
-                                    url.as_str(),
+                        let my_tx_hash = raw_tx.sighash(chain_id.as_u64());
+                        tx_hash = my_tx_hash;
+                        let pending_tx = client
+                            .send_transaction(raw_tx.clone(), None)
+                            .map_err(anyhow::Error::from);
+                        let tx = match pending_tx.await {
+                            Ok(pending) => {
+                                tx_hash = *pending;
+                                tracing::event!(
+                                    target: crate::probe::TARGET,
+                                    tracing::Level::DEBUG,
+                                    kind = %crate::probe::Kind::TxQueue,
+                                    ty = "EVM",
+                                    chain_id = %chain_id.as_u64(),
+                                    pending = true,
+                                    %tx_hash,
                                 );
-                                tracing::error!(
-                                    "Tx {} Errored: {}",
-                                    clickable_link,
-                                    reason,
+
+                                let tx_hash_string = format!("0x{:x}", tx_hash);
+                                if let Some(mut url) = maybe_explorer.clone() {
+                                    url.set_path(&format!(
+                                        "tx/{}",
+                                        tx_hash_string
+                                    ));
+                                    let clickable_link = ClickableLink::new(
+                                        &tx_hash_string,
+                                        url.as_str(),
+                                    );
+                                    tracing::info!(
+                                        "Tx {} is submitted and pending!",
+                                        clickable_link,
+                                    );
+                                } else {
+                                    tracing::info!(
+                                        "Tx {} is submitted and pending!",
+                                        tx_hash_string,
+                                    );
+                                }
+                                let result = pending
+                                    .interval(Duration::from_millis(
+                                        chain_config
+                                            .profile
+                                            .tx_polling_interval(),
+                                    ))
+                                    .await;
+                                result
+                            }
+                            Err(e) => {
+                                let tx_hash_string = format!("0x{:x}", tx_hash);
+                                if let Some(mut url) = maybe_explorer.clone() {
+                                    url.set_path(&format!(
+                                        "tx/{}",
+                                        tx_hash_string
+                                    ));
+                                    let clickable_link = ClickableLink::new(
+                                        &tx_hash_string,
+                                        url.as_str(),
+                                    );
+                                    tracing::error!(
+                                        "Error while sending tx {}, {}",
+                                        clickable_link,
+                                        e,
+                                    );
+                                } else {
+                                    tracing::error!(
+                                        "Error while sending tx {}, {}",
+                                        tx_hash_string,
+                                        e
+                                    );
+                                }
+                                tracing::event!(
+                                    target: crate::probe::TARGET,
+                                    tracing::Level::DEBUG,
+                                    kind = %crate::probe::Kind::TxQueue,
+                                    ty = "EVM",
+                                    chain_id = %chain_id.as_u64(),
+                                    errored = true,
+                                    %tx_hash,
+                                    error = %e,
                                 );
-                            } else {
-                                tracing::error!(
-                                    "Tx {} Errored: {}",
-                                    tx_hash_string,
-                                    reason,
+
+                                continue 'queue; // keep going.
+                            }
+                        };
+                        match tx {
+                            Ok(Some(mut receipt)) => {
+                                // the nodes before london do not return the price paid, it is the one sent.
+                                if receipt.effective_gas_price.is_none() {
+                                    receipt.effective_gas_price =
+                                        raw_tx.gas_price();
+                                }
+                                let tx_hash_string =
+                                    format!("0x{:x}", receipt.transaction_hash);
+                                if let Some(mut url) = maybe_explorer.clone() {
+                                    url.set_path(&format!(
+                                        "tx/{}",
+                                        tx_hash_string
+                                    ));
+                                    let clickable_link = ClickableLink::new(
+                                        &tx_hash_string,
+                                        url.as_str(),
+                                    );
+                                    tracing::info!(
+                                        "Tx {} Finalized",
+                                        clickable_link
+                                    );
+                                } else {
+                                    tracing::info!(
+                                        "Tx {} Finalized",
+                                        tx_hash_string,
+                                    );
+                                }
+                                tracing::event!(
+                                    target: crate::probe::TARGET,
+                                    tracing::Level::DEBUG,
+                                    kind = %crate::probe::Kind::TxQueue,
+                                    ty = "EVM",
+                                    chain_id = %chain_id.as_u64(),
+                                    finalized = true,
+                                    %tx_hash,
+                                );
+                                // the proposals are only executed once their tx succeeded.
+                                let succeeded =
+                                    receipt.status != Some(0u64.into());
+                                let calldata = raw_tx
+                                    .data()
+                                    .map(|v| v.as_ref())
+                                    .unwrap_or_default();
+                                if let Err(e) = record_proposal_execution(
+                                    store.as_ref(),
+                                    chain_id,
+                                    calldata,
+                                    succeeded,
+                                ) {
+                                    tracing::warn!(
+                                        "Failed to record the execution of the proposal: {}",
+                                        e
+                                    );
+                                }
+                                if let Some(gas_used) = receipt.gas_used {
+                                    let contract = match raw_tx.to() {
+                                        Some(NameOrAddress::Address(v)) => {
+                                            format!("{:#x}", v)
+                                        }
+                                        Some(NameOrAddress::Name(v)) => {
+                                            v.clone()
+                                        }
+                                        None => String::new(),
+                                    };
+                                    metrics::add_gas(
+                                        Labels::new(
+                                            chain_id,
+                                            contract,
+                                            crate::probe::Kind::TxQueue,
+                                        ),
+                                        gas_used,
+                                        receipt
+                                            .effective_gas_price
+                                            .unwrap_or_default(),
+                                        l1_fee,
+                                    );
+                                }
+                            }
+                            Ok(None) => {
+                                let tx_hash_string = format!("0x{:x}", tx_hash);
+                                if resubmissions
+                                    < chain_config.max_resubmissions
+                                {
+                                    resubmissions += 1;
+                                    tracing::warn!(
+                                    attempt = resubmissions,
+                                    "Tx {} Dropped from Mempool, resubmitting it",
+                                    tx_hash_string
                                 );
+                                    let repriced = fees::reprice_dropped_tx(
+                                        client.as_ref(),
+                                        &mut raw_tx,
+                                    )
+                                    .await;
+                                    match repriced {
+                                        Ok(()) => continue,
+                                        Err(e) => tracing::error!(
+                                        "Failed to reprice the dropped tx: {}",
+                                        e
+                                    ),
+                                    }
+                                } else {
+                                    tracing::warn!(
+                                        "Tx {} Dropped from Mempool!!",
+                                        tx_hash_string
+                                    );
+                                }
+                                // enquing the tx again, it gets a new nonce.
+                                store.enqueue_item(
+                                    SledQueueKey::from_evm_chain_id(chain_id),
+                                    queued_tx,
+                                )?;
                             }
+                            Err(e) => {
+                                let reason = e.to_string();
+                                let tx_hash_string = format!("0x{:x}", tx_hash);
+                                if let Some(mut url) = maybe_explorer.clone() {
+                                    url.set_path(&format!(
+                                        "tx/{}",
+                                        tx_hash_string
+                                    ));
+                                    let clickable_link = ClickableLink::new(
+                                        &tx_hash_string,
+                                        url.as_str(),
+                                    );
+                                    tracing::error!(
+                                        "Tx {} Errored: {}",
+                                        clickable_link,
+                                        reason,
+                                    );
+                                } else {
+                                    tracing::error!(
+                                        "Tx {} Errored: {}",
+                                        tx_hash_string,
+                                        reason,
+                                    );
+                                }
 
-                            tracing::event!(
-                                target: crate::probe::TARGET,
-                                tracing::Level::DEBUG,
-                                kind = %crate::probe::Kind::TxQueue,
-                                ty = "EVM",
-                                chain_id = %chain_id.as_u64(),
-                                errored = true,
-                                %tx_hash,
-                                error = %e,
-                            );
-                        }
-                    };
+                                tracing::event!(
+                                    target: crate::probe::TARGET,
+                                    tracing::Level::DEBUG,
+                                    kind = %crate::probe::Kind::TxQueue,
+                                    ty = "EVM",
+                                    chain_id = %chain_id.as_u64(),
+                                    errored = true,
+                                    %tx_hash,
+                                    error = %e,
+                                );
+                            }
+                        };
+                        break;
+                    }
                 }
                 // sleep for a random amount of time.
                 let max_sleep_interval =
@@ -289,3 +502,89 @@ where
         Ok(())
     }
 }
+
+/// Dequeues the next tx of the chain that is not sent to a paused bridge.
+///
+/// A tx sent to a paused bridge would revert, so it is held, queued again behind the
+/// others, until the bridge gets unpaused, while the txs of the other contracts go on.
+///
+/// Returns `None` once every queued tx got held.
+fn dequeue_sendable_tx<S>(
+    ctx: &RelayerContext,
+    store: &S,
+    chain_id: U256,
+    typed_chain_id: TypedChainId,
+) -> anyhow::Result<Option<TypedTransaction>>
+where
+    S: QueueStore<TypedTransaction, Key = SledQueueKey>,
+{
+    let queue = SledQueueKey::from_evm_chain_id(chain_id);
+    let depth = store.count_items(queue)?;
+    for _ in 0..depth {
+        let tx = match store.dequeue_item(queue)? {
+            Some(v) => v,
+            None => break,
+        };
+        let paused_bridge = tx
+            .to()
+            .and_then(|to| match to {
+                NameOrAddress::Address(address) => Some(*address),
+                NameOrAddress::Name(_) => None,
+            })
+            .map(|address| BridgeKey::new(address, typed_chain_id))
+            .filter(|key| ctx.is_bridge_paused(*key));
+        match paused_bridge {
+            Some(key) => {
+                tracing::trace!(
+                    bridge = %key.address,
+                    "The tx is sent to a paused bridge, holding it ..."
+                );
+                store.enqueue_item(queue, tx)?;
+            }
+            None => return Ok(Some(tx)),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sled::SledStore;
+    use ethereum_types::Address;
+    use webb::evm::ethers::types::TransactionRequest;
+
+    #[test]
+    fn txs_of_a_paused_bridge_do_not_hold_the_others() {
+        let ctx = RelayerContext::new(Default::default());
+        let store = SledStore::temporary().unwrap();
+        let chain_id = U256::from(5001);
+        let typed_chain_id =
+            crate::proposals::evm_typed_chain_id(chain_id).unwrap();
+        let (paused, active) =
+            (Address::repeat_byte(1), Address::repeat_byte(2));
+        ctx.set_bridge_paused(BridgeKey::new(paused, typed_chain_id), true);
+        let queue = SledQueueKey::from_evm_chain_id(chain_id);
+        let tx = |to: Address| -> TypedTransaction {
+            TransactionRequest::new().to(to).into()
+        };
+        store.enqueue_item(queue, tx(paused)).unwrap();
+        store.enqueue_item(queue, tx(active)).unwrap();
+        let next = dequeue_sendable_tx(&ctx, &store, chain_id, typed_chain_id)
+            .unwrap();
+        assert_eq!(next.unwrap().to(), Some(&NameOrAddress::Address(active)));
+        // the tx of the paused bridge is held in the queue.
+        let next = dequeue_sendable_tx(&ctx, &store, chain_id, typed_chain_id)
+            .unwrap();
+        assert!(next.is_none());
+        assert_eq!(
+            QueueStore::<TypedTransaction>::count_items(&store, queue).unwrap(),
+            1
+        );
+        // and sent once its bridge is unpaused.
+        ctx.set_bridge_paused(BridgeKey::new(paused, typed_chain_id), false);
+        let next = dequeue_sendable_tx(&ctx, &store, chain_id, typed_chain_id)
+            .unwrap();
+        assert_eq!(next.unwrap().to(), Some(&NameOrAddress::Address(paused)));
+    }
+}
@@ -1,18 +1,34 @@
-use ethereum_types::U256;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use anyhow::Context;
+use ethereum_types::{Address, H256, U256};
+use std::sync::Arc;
 use webb::evm::{
     contract::protocol_solidity::{
-        fixed_deposit_anchor::{ExtData, Proof},
+        fixed_deposit_anchor::{
+            ExtData, Proof, WithdrawCall, WithdrawalFilter,
+        },
         FixedDepositAnchorContract,
     },
-    ethers::prelude::{Signer, SignerMiddleware},
+    ethers::abi::{AbiDecode, AbiEncode, RawLog},
+    ethers::contract::EthLogDecode,
+    ethers::prelude::{Middleware, SignerMiddleware},
+    ethers::types::{Bytes, TransactionReceipt},
 };
 
 use crate::{
+    config::SyncFrom,
     context::RelayerContext,
+    events_watcher::is_contract_synced,
     handler::{
         calculate_fee, into_withdraw_error, AnchorRelayTransaction,
         CommandResponse, CommandStream, NetworkStatus, WithdrawStatus,
+        UNPROFITABLE_CODE,
+    },
+    metrics::{self, Labels, Metric},
+    store::cache::RelayerStore,
+    store::{EarningsStore, NeighborRootStore},
+    tx_relay::evm::{
+        anchor_contract_config, claim_withdrawal, fees, record_relay_cost,
+        record_spent_nullifier, resolve_identity, send_relay_tx,
     },
 };
 
@@ -21,10 +37,12 @@ use crate::{
 /// # Arguments
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, where the relayed txs are recorded
 /// * `cmd` - The command to execute
 /// * `stream` - The stream to write the response to
 pub async fn handle_anchor_relay_tx<'a>(
     ctx: RelayerContext,
+    store: Arc<RelayerStore>,
     cmd: AnchorRelayTransaction,
     stream: CommandStream,
 ) {
@@ -38,18 +56,18 @@ pub async fn handle_anchor_relay_tx<'a>(
             return;
         }
     };
-    let supported_contracts: HashMap<_, _> = chain
-        .contracts
-        .iter()
-        .cloned()
-        .filter_map(|c| match c {
-            crate::config::Contract::Anchor(c) => Some(c),
-            _ => None,
-        })
-        .map(|c| (c.common.address, c))
-        .collect();
+    if ctx.is_evm_chain_paused(U256::from(chain.chain_id)) {
+        tracing::warn!("Chain {} is paused", requested_chain);
+        let _ = stream.send(Network(NetworkStatus::Paused)).await;
+        return;
+    }
+    if !ctx.is_leader() {
+        tracing::warn!("Standby relayer, only the leader relays");
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
     // get the contract configuration
-    let contract_config = match supported_contracts.get(&cmd.contract) {
+    let contract_config = match anchor_contract_config(chain, cmd.contract) {
         Some(config) => config,
         None => {
             tracing::warn!("Unsupported Contract: {:?}", cmd.contract);
@@ -60,22 +78,50 @@ pub async fn handle_anchor_relay_tx<'a>(
         }
     };
 
-    let wallet = match ctx.evm_wallet(&cmd.chain).await {
+    // reject the withdraws spending a nullifier that is already spent or being relayed,
+    // the claim is held until the relay job ends.
+    let _claim = match claim_withdrawal(
+        &ctx,
+        &store,
+        chain,
+        cmd.contract,
+        cmd.nullifier_hash,
+    ) {
         Ok(v) => v,
-        Err(e) => {
-            tracing::error!("Misconfigured Network: {}", e);
+        Err(status) => {
+            tracing::warn!(nullifier_hash = %cmd.nullifier_hash, "Rejected withdraw: {:?}", status);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
+        }
+    };
+
+    // the signing key of the chain is held until the relay job ends, a key rotation
+    // waits for the withdraws signed with the old key.
+    let _signing = ctx.evm_signing_guard(&requested_chain).await;
+    let identity = match resolve_identity(
+        &ctx,
+        &requested_chain,
+        cmd.contract,
+        cmd.identity.as_deref(),
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(NetworkStatus::Misconfigured) => {
             let _ = stream
                 .send(Error(format!("Misconfigured Network: {:?}", cmd.chain)))
                 .await;
             return;
         }
+        Err(status) => {
+            let _ = stream.send(Network(status)).await;
+            return;
+        }
     };
+    let wallet = identity.wallet;
     // validate the relayer address first before trying
     // send the transaction.
-    let reward_address = match chain.beneficiary {
-        Some(account) => account,
-        None => wallet.address(),
-    };
+    let reward_address = identity.beneficiary;
 
     if cmd.relayer != reward_address {
         let _ = stream
@@ -92,6 +138,17 @@ pub async fn handle_anchor_relay_tx<'a>(
             .await;
         return;
     }
+    // reject the invalid proofs locally, instead of paying for a dry run.
+    #[cfg(feature = "zk-verifier")]
+    if let Some(path) = &contract_config.verifying_key {
+        if let Err(status) =
+            verify_anchor_proof(&ctx, path, chain, &cmd, &roots).await
+        {
+            tracing::warn!(nullifier_hash = %cmd.nullifier_hash, "Rejected withdraw: {:?}", status);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
+        }
+    }
 
     tracing::debug!(
         "Connecting to chain {:?} .. at {}",
@@ -116,6 +173,32 @@ pub async fn handle_anchor_relay_tx<'a>(
     let client = SignerMiddleware::new(provider, wallet);
     let client = Arc::new(client);
     let contract = FixedDepositAnchorContract::new(cmd.contract, client);
+    // reject the proofs against unknown roots, before spending anything on them.
+    let chain_id = U256::from(chain.chain_id);
+    let edges_synced = contract_config.common.sync_from()
+        == SyncFrom::DeployedAt
+        && is_contract_synced((chain_id, cmd.contract).into());
+    match unknown_root(
+        store.as_ref(),
+        &contract,
+        chain_id,
+        edges_synced,
+        &roots,
+    )
+    .await
+    {
+        Ok(None) => {}
+        Ok(Some(root)) => {
+            tracing::warn!(%root, "Rejected withdraw against an unknown root");
+            let _ = stream
+                .send(Withdraw(WithdrawStatus::InvalidMerkleRoots))
+                .await;
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to check the roots: {}", e);
+        }
+    }
     let denomination = match contract.denomination().call().await {
         Ok(v) => v,
         Err(e) => {
@@ -129,11 +212,54 @@ pub async fn handle_anchor_relay_tx<'a>(
             return;
         }
     };
+    // the fee is paid in the token of the anchor, not in the native token.
+    let token = match contract.token().call().await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Misconfigured Contract Token: {}", e);
+            let _ = stream
+                .send(Error(format!(
+                    "Misconfigured Contract: {:?}",
+                    cmd.contract
+                )))
+                .await;
+            return;
+        }
+    };
+    // wait for a turn on the chain, the relays estimating and submitting their
+    // transaction together are bounded by `max-concurrent-relays`.
+    let permit = ctx.evm_relay_permit(chain).await;
     // check the fee
-    let expected_fee = calculate_fee(
-        contract_config.withdraw_config.withdraw_fee_percentage,
-        denomination,
+    let base_fee_percentage = fees::base_fee_percentage(
+        &contract_config.withdraw_config,
+        identity.withdraw_fee_percentage,
+    );
+    let gas = fees::estimate_gas(
+        &chain.gas_models,
+        fees::Withdrawal::Anchor,
+        contract_config.withdraw_config.withdraw_gaslimit,
     );
+    let fee_percentage = match fees::withdraw_fee_percentage(
+        contract.client(),
+        chain,
+        cmd.contract,
+        fees::Withdrawal::Anchor,
+        gas,
+        base_fee_percentage,
+        denomination,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Failed to compute the fee: {}", e);
+            let _ = stream
+                .send(Error(format!("Failed to compute the fee: {}", e)))
+                .await;
+            return;
+        }
+    };
+    let expected_fee = calculate_fee(fee_percentage, denomination);
     let (_, unacceptable_fee) = U256::overflowing_sub(cmd.fee, expected_fee);
     if unacceptable_fee {
         tracing::error!("Received a fee lower than configuration");
@@ -160,6 +286,20 @@ pub async fn handle_anchor_relay_tx<'a>(
     };
     tracing::trace!(?proof, ?ext_data, "Client Proof");
     let call = contract.withdraw(proof, ext_data);
+    // some chains reject the typed transactions.
+    let call = if chain.legacy_tx { call.legacy() } else { call };
+    // make sure the withdraw pays the fee to the relayer, before relaying it.
+    if let Err(e) =
+        check_fee_recipient(call.calldata(), reward_address, cmd.fee)
+    {
+        tracing::error!("Invalid withdraw fee: {}", e);
+        let _ = stream
+            .send(Error(format!("Invalid withdraw fee: {}", e)))
+            .await;
+        return;
+    }
+    let labels =
+        Labels::new(chain.chain_id, format!("{:?}", cmd.contract), "anchor");
     // Make a dry call, to make sure the transaction will go through successfully
     // to avoid wasting fees on invalid calls.
     match call.call().await {
@@ -170,32 +310,124 @@ pub async fn handle_anchor_relay_tx<'a>(
         Err(e) => {
             tracing::error!("Error Client sent an invalid proof: {}", e);
             let err = into_withdraw_error(e);
+            metrics::inc(Metric::RelayTxsFailed, labels);
             let _ = stream.send(Withdraw(err)).await;
             return;
         }
     };
-    tracing::trace!("About to send Tx to {:?} Chain", cmd.chain);
-    let tx = match call.send().await {
-        Ok(pending) => {
-            let _ = stream.send(Withdraw(WithdrawStatus::Sent)).await;
-            let tx_hash = *pending;
-            tracing::debug!(%tx_hash, "Tx is submitted and pending!");
-            let result = pending.interval(Duration::from_millis(1000)).await;
-            let _ = stream
-                .send(Withdraw(WithdrawStatus::Submitted { tx_hash }))
-                .await;
-            result
+    // make sure the fee covers the gas cost, before sending the transaction.
+    let unprofitable = fees::check_profitability(
+        contract.client(),
+        chain,
+        &call.tx,
+        gas,
+        cmd.fee,
+        token,
+        contract_config.withdraw_config.price_feed,
+    )
+    .await;
+    match unprofitable {
+        Ok(None) => {}
+        Ok(Some(minimum_fee)) => {
+            tracing::warn!(fee = %cmd.fee, %minimum_fee, "Unprofitable withdraw");
+            let status = WithdrawStatus::Unprofitable {
+                code: UNPROFITABLE_CODE,
+                fee: cmd.fee,
+                minimum_fee,
+            };
+            metrics::inc(Metric::RelayTxsFailed, labels);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
         }
         Err(e) => {
-            tracing::error!("Error while sending Tx: {}", e);
-            let err = into_withdraw_error(e);
-            let _ = stream.send(Withdraw(err)).await;
+            tracing::error!("Failed to check the profitability: {}", e);
+            let _ = stream
+                .send(Error(format!(
+                    "Failed to check the profitability: {}",
+                    e
+                )))
+                .await;
             return;
         }
-    };
+    }
+    // the leader could have stepped down while the tx got prepared, then
+    // another instance could be relaying already.
+    if !ctx.is_leader() {
+        tracing::warn!("Stepped down to a standby, not sending the Tx");
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
+    if !stream.cancellation().broadcast() {
+        tracing::debug!("The relay job got cancelled, not sending the Tx");
+        let _ = stream.send(Withdraw(WithdrawStatus::Cancelled)).await;
+        return;
+    }
+    let mut relay_tx = call.tx.clone();
+    fees::apply_withdraw_gas_limit(
+        contract.client(),
+        &mut relay_tx,
+        contract_config.withdraw_config.fixed_gas_limit,
+    )
+    .await;
+    tracing::trace!("About to send Tx to {:?} Chain", cmd.chain);
+    let tx = send_relay_tx(
+        contract.client(),
+        chain,
+        &store,
+        &stream,
+        relay_tx,
+        permit,
+    )
+    .await;
     match tx {
-        Ok(Some(receipt)) => {
+        Ok(Some((receipt, l1_fee))) => {
             tracing::debug!("Finalized Tx #{}", receipt.transaction_hash);
+            if let Some(gas_used) = receipt.gas_used {
+                metrics::add_gas(
+                    labels.clone(),
+                    gas_used,
+                    receipt.effective_gas_price.unwrap_or_default(),
+                    l1_fee,
+                );
+            }
+            if receipt.status == Some(1.into()) {
+                metrics::inc(Metric::RelayTxs, labels);
+                let chain_id = U256::from(chain.chain_id);
+                record_spent_nullifier(
+                    contract.client(),
+                    &store,
+                    chain_id,
+                    cmd.contract,
+                    cmd.nullifier_hash,
+                    &receipt,
+                )
+                .await;
+            } else {
+                // the reverted transactions are mined, but relayed nothing.
+                metrics::inc(Metric::RelayTxsFailed, labels);
+            }
+            let earned = earned_fee(&receipt, cmd.contract, reward_address);
+            record_relay_cost(
+                &store,
+                U256::from(chain.chain_id),
+                cmd.contract,
+                token,
+                identity.name.as_deref(),
+                &receipt,
+                l1_fee,
+                earned,
+            );
+            if !earned.is_zero() {
+                let chain_id = U256::from(chain.chain_id);
+                if let Err(e) = store.record_earning(
+                    chain_id,
+                    token,
+                    identity.name.as_deref(),
+                    earned,
+                ) {
+                    tracing::error!("Failed to record the earned fee: {}", e);
+                }
+            }
             let _ = stream
                 .send(Withdraw(WithdrawStatus::Finalized {
                     tx_hash: receipt.transaction_hash,
@@ -203,17 +435,261 @@ pub async fn handle_anchor_relay_tx<'a>(
                 .await;
         }
         Ok(None) => {
-            tracing::warn!("Transaction Dropped from Mempool!!");
+            tracing::warn!("Transaction Dropped from Mempool, giving up on it");
+            metrics::inc(Metric::RelayTxsFailed, labels);
             let _ = stream
                 .send(Withdraw(WithdrawStatus::DroppedFromMemPool))
                 .await;
         }
+        Err(status) => {
+            metrics::inc(Metric::RelayTxsFailed, labels);
+            let _ = stream.send(Withdraw(status)).await;
+        }
+    };
+}
+
+/// Returns the first root of the withdraw that the anchor does not know, if any.
+///
+/// The first root is the root of the anchor itself, the others are the roots of its
+/// neighbors, in the order of its edges. A neighbor root is looked up in the root history
+/// of its own edge, as cached by the events watcher, and only the roots missing there,
+/// like the ones newer than the cache, are checked against the anchor. The zero roots
+/// stand for the neighbors without an edge yet, and are not checked. Until the edges are
+/// cached (`edges_synced`), their order is not known and the neighbor roots are left to
+/// the dry run.
+async fn unknown_root<S, M>(
+    store: &S,
+    contract: &FixedDepositAnchorContract<M>,
+    chain_id: U256,
+    edges_synced: bool,
+    roots: &[u8],
+) -> anyhow::Result<Option<H256>>
+where
+    S: NeighborRootStore,
+    M: Middleware + 'static,
+{
+    let mut roots = roots.chunks_exact(32).map(H256::from_slice);
+    let local_root = match roots.next() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if !contract
+        .is_known_root(local_root.to_fixed_bytes())
+        .call()
+        .await?
+    {
+        return Ok(Some(local_root));
+    }
+    if !edges_synced {
+        return Ok(None);
+    }
+    let neighbors = store.neighbor_roots((chain_id, contract.address()))?;
+    for (neighbor_chain_id, root) in uncached_neighbor_roots(&neighbors, roots)
+    {
+        let known = contract
+            .is_known_neighbor_root(neighbor_chain_id, root.to_fixed_bytes())
+            .call()
+            .await?;
+        if !known {
+            return Ok(Some(root));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the neighbor roots that are not in the cached root history of their own edge,
+/// along with the chain id of that neighbor.
+///
+/// The roots are matched with the `neighbors` by position, the roots past the known edges
+/// are not returned.
+fn uncached_neighbor_roots(
+    neighbors: &[(U256, Vec<H256>)],
+    roots: impl Iterator<Item = H256>,
+) -> Vec<(U256, H256)> {
+    roots
+        .zip(neighbors)
+        .filter(|(root, (_, known))| !root.is_zero() && !known.contains(root))
+        .map(|(root, (neighbor_chain_id, _))| (*neighbor_chain_id, root))
+        .collect()
+}
+
+/// Verifies the proof of the anchor withdraw against the verifying key at `path`.
+///
+/// See [`verify_withdraw_proof`](crate::tx_relay::evm::verify_withdraw_proof).
+#[cfg(feature = "zk-verifier")]
+async fn verify_anchor_proof(
+    ctx: &RelayerContext,
+    path: &std::path::Path,
+    chain: &crate::config::EvmChainConfig,
+    cmd: &AnchorRelayTransaction,
+    roots: &[u8],
+) -> Result<(), WithdrawStatus> {
+    use crate::tx_relay::evm::{verifier, verify_withdraw_proof};
+
+    let typed_chain_id = match crate::proposals::evm_typed_chain_id(U256::from(
+        chain.chain_id,
+    )) {
+        Ok(v) => v.chain_id(),
         Err(e) => {
-            let reason = e.to_string();
-            tracing::error!("Transaction Errored: {}", reason);
-            let _ = stream
-                .send(Withdraw(WithdrawStatus::Errored { reason, code: 4 }))
-                .await;
+            tracing::error!("Failed to verify the proof: {}", e);
+            return Ok(());
         }
     };
+    let inputs = verifier::anchor_public_inputs(
+        cmd.nullifier_hash,
+        cmd.ext_data_hash,
+        typed_chain_id,
+        roots,
+    );
+    verify_withdraw_proof(ctx, path, cmd.proof.to_vec(), inputs).await
+}
+
+/// The order of the scalar field of BN254, the ext data hash of a proof is reduced to it.
+const SNARK_SCALAR_FIELD: U256 = U256([
+    0x43e1_f593_f000_0001,
+    0x2833_e848_79b9_7091,
+    0xb850_45b6_8181_585d,
+    0x3064_4e72_e131_a029,
+]);
+
+/// Checks that the withdraw pays the `fee` to the `relayer`, by decoding its calldata.
+///
+/// The anchor checks the ext data of the call against the `ext_data_hash` the proof is
+/// made for, so the ext data paying the relayer must be the one the proof commits to,
+/// otherwise the withdraw would revert.
+fn check_fee_recipient(
+    calldata: Option<Bytes>,
+    relayer: Address,
+    fee: U256,
+) -> anyhow::Result<()> {
+    let calldata = calldata.context("the withdraw call has no calldata")?;
+    let call = WithdrawCall::decode(calldata)?;
+    anyhow::ensure!(
+        call.ext_data.relayer == relayer,
+        "the fee is paid to {:?} instead of {:?}",
+        call.ext_data.relayer,
+        relayer,
+    );
+    anyhow::ensure!(
+        call.ext_data.fee == fee,
+        "the fee is {} instead of {}",
+        call.ext_data.fee,
+        fee,
+    );
+    let ext_data_hash =
+        U256::from(crate::utils::keccak256(&call.ext_data.clone().encode()))
+            % SNARK_SCALAR_FIELD;
+    anyhow::ensure!(
+        ext_data_hash == U256::from(call.proof.ext_data_hash),
+        "the proof is made for another recipient, relayer or fee",
+    );
+    Ok(())
+}
+
+/// Returns the fee paid to the `relayer` by the `Withdrawal` events of the anchor in the receipt.
+fn earned_fee(
+    receipt: &TransactionReceipt,
+    anchor: Address,
+    relayer: Address,
+) -> U256 {
+    receipt
+        .logs
+        .iter()
+        .filter(|log| log.address == anchor)
+        .filter_map(|log| {
+            let raw = RawLog {
+                topics: log.topics.clone(),
+                data: log.data.to_vec(),
+            };
+            WithdrawalFilter::decode_log(&raw).ok()
+        })
+        .filter(|event| event.relayer == relayer)
+        .fold(U256::zero(), |total, event| total.saturating_add(event.fee))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_recipient_is_checked_in_calldata() {
+        let relayer = Address::random();
+        let ext_data = |relayer: Address, fee: u64| ExtData {
+            recipient: Address::repeat_byte(1),
+            relayer,
+            fee: U256::from(fee),
+            ..Default::default()
+        };
+        // the ext data hash the client made its proof for.
+        let withdraw = |ext_data: ExtData, proven: &ExtData| {
+            let hash =
+                U256::from(crate::utils::keccak256(&proven.clone().encode()))
+                    % SNARK_SCALAR_FIELD;
+            let mut ext_data_hash = [0u8; 32];
+            hash.to_big_endian(&mut ext_data_hash);
+            let call = WithdrawCall {
+                proof: Proof {
+                    ext_data_hash,
+                    ..Default::default()
+                },
+                ext_data,
+            };
+            Some(Bytes::from(call.encode()))
+        };
+        let proven = ext_data(relayer, 10);
+        let calldata = withdraw(proven.clone(), &proven);
+        assert!(
+            check_fee_recipient(calldata.clone(), relayer, 10.into()).is_ok()
+        );
+        let other = Address::random();
+        assert!(
+            check_fee_recipient(calldata.clone(), other, 10.into()).is_err()
+        );
+        assert!(check_fee_recipient(calldata, relayer, 20.into()).is_err());
+        // a proof made for another relayer is rejected, even if the ext data pays us.
+        let proven = ext_data(other, 10);
+        let calldata = withdraw(ext_data(relayer, 10), &proven);
+        assert!(check_fee_recipient(calldata, relayer, 10.into()).is_err());
+        // so is a proof made for a lower fee.
+        let proven = ext_data(relayer, 5);
+        let calldata = withdraw(ext_data(relayer, 10), &proven);
+        assert!(check_fee_recipient(calldata, relayer, 10.into()).is_err());
+    }
+
+    #[test]
+    fn neighbor_roots_are_matched_with_their_own_edge() {
+        let fresh = H256::random();
+        let other_fresh = H256::random();
+        let newer = H256::random();
+        let neighbors = vec![
+            (U256::from(5), vec![fresh]),
+            (U256::from(7), vec![other_fresh]),
+        ];
+        let roots = |roots: &[H256]| roots.to_vec().into_iter();
+        // every root is in the history of its own edge.
+        let uncached =
+            uncached_neighbor_roots(&neighbors, roots(&[fresh, other_fresh]));
+        assert!(uncached.is_empty());
+        // the zero roots stand for the neighbors without an edge yet.
+        let uncached =
+            uncached_neighbor_roots(&neighbors, roots(&[fresh, H256::zero()]));
+        assert!(uncached.is_empty());
+        // a root of another neighbor is not in the history of that edge.
+        let uncached =
+            uncached_neighbor_roots(&neighbors, roots(&[other_fresh, fresh]));
+        assert_eq!(
+            uncached,
+            vec![(U256::from(5), other_fresh), (U256::from(7), fresh)]
+        );
+        // a root newer than the cache is left to the anchor, along with its edge.
+        let uncached =
+            uncached_neighbor_roots(&neighbors, roots(&[fresh, newer]));
+        assert_eq!(uncached, vec![(U256::from(7), newer)]);
+        // the roots past the known edges are not checked.
+        let uncached = uncached_neighbor_roots(
+            &neighbors,
+            roots(&[fresh, other_fresh, newer]),
+        );
+        assert!(uncached.is_empty());
+    }
 }
@@ -0,0 +1,979 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ethereum_types::{Address, U256};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use utoipa::ToSchema;
+use webb::evm::contract::protocol_solidity::FixedDepositAnchorContract;
+use webb::evm::contract::tornado::TornadoContract;
+use webb::evm::ethers::abi::{self, ParamType, Token};
+use webb::evm::ethers::core::types::transaction::eip2718::TypedTransaction;
+use webb::evm::ethers::middleware::SignerMiddleware;
+use webb::evm::ethers::providers::Middleware;
+use webb::evm::ethers::signers::Signer;
+use webb::evm::ethers::types::{NameOrAddress, Signature, TransactionRequest};
+use webb::evm::ethers::utils::id;
+
+use crate::config::{
+    AnchorWithdrawConfig, Contract, DynamicFeeConfig, EvmChainConfig,
+    FeePolicyConfig, GasModelsConfig, ProfitabilityGuardConfig,
+    RelayerIdentityConfig, RollupKind,
+};
+use crate::context::RelayerContext;
+use crate::handler::calculate_fee;
+
+/// A quote of the fee the relayer expects for relaying a withdraw.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeQuote {
+    /// The chain id of the contract.
+    #[schema(value_type = String)]
+    pub chain_id: U256,
+    /// The address of the contract.
+    #[schema(value_type = String)]
+    pub contract: Address,
+    /// The current fee percentage of the contract.
+    pub fee_percentage: f64,
+    /// The denomination of the contract.
+    #[schema(value_type = String)]
+    pub denomination: U256,
+    /// The fee expected for a withdraw, at the current fee percentage.
+    #[schema(value_type = String)]
+    pub expected_fee: U256,
+}
+
+/// The kinds of withdraws, each with its own gas model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Withdrawal {
+    /// A withdraw on a Tornado contract.
+    Tornado,
+    /// A withdraw on an Anchor contract.
+    Anchor,
+}
+
+impl Withdrawal {
+    /// An upper bound of the calldata size of the withdraw, with a 256 bytes proof and
+    /// two roots, used for estimating its L1 data fee on rollups.
+    pub fn calldata_size(&self) -> usize {
+        match self {
+            Self::Tornado => 516,
+            Self::Anchor => 708,
+        }
+    }
+}
+
+/// The address of the `GasPriceOracle` predeploy of the Optimism chains.
+const OPTIMISM_GAS_PRICE_ORACLE: [u8; 20] = [
+    0x42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x0f,
+];
+/// The address of the `NodeInterface` precompile of the Arbitrum chains.
+const ARBITRUM_NODE_INTERFACE: [u8; 20] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc8,
+];
+
+/// Estimates the L1 data fee of a transaction on a rollup, in wei.
+///
+/// This is the cost of posting the transaction's calldata to the L1, which
+/// is not covered by its L2 gas price times its `gas` on Optimism, nor by the static
+/// gas models on Arbitrum (its nodes include it in their gas estimations).
+///
+/// # Arguments
+///
+/// * `client` - The client used for calling the fee precompiles of the rollup
+/// * `rollup` - The kind of rollup of the chain
+/// * `chain_id` - The chain id of the rollup
+/// * `tx` - The transaction, its calldata is what gets posted to the L1
+/// * `signature` - The signature of the transaction, Optimism charges the signed transaction,
+///   without it the fee is a little underestimated
+pub async fn l1_data_fee<M: Middleware>(
+    client: &M,
+    rollup: RollupKind,
+    chain_id: u64,
+    tx: &TypedTransaction,
+    signature: Option<&Signature>,
+) -> anyhow::Result<U256>
+where
+    M::Error: 'static,
+{
+    match rollup {
+        RollupKind::Optimism => {
+            let rlp = match signature {
+                Some(sig) => tx.rlp_signed(chain_id, sig),
+                None => tx.rlp(chain_id),
+            };
+            let mut data = id("getL1Fee(bytes)").to_vec();
+            data.extend(abi::encode(&[Token::Bytes(rlp.to_vec())]));
+            let call = TransactionRequest::new()
+                .to(Address::from(OPTIMISM_GAS_PRICE_ORACLE))
+                .data(data);
+            let output = client.call(&call.into(), None).await?;
+            let tokens = abi::decode(&[ParamType::Uint(256)], output.as_ref())?;
+            match tokens.as_slice() {
+                [Token::Uint(fee)] => Ok(*fee),
+                _ => anyhow::bail!("Unexpected output of getL1Fee"),
+            }
+        }
+        RollupKind::Arbitrum => {
+            let (to, creation) = match tx.to() {
+                Some(NameOrAddress::Address(v)) => (*v, false),
+                Some(NameOrAddress::Name(_)) => {
+                    anyhow::bail!("ENS names are not supported on Arbitrum")
+                }
+                None => (Address::zero(), true),
+            };
+            let calldata = tx.data().map(|v| v.to_vec()).unwrap_or_default();
+            let mut data =
+                id("gasEstimateL1Component(address,bool,bytes)").to_vec();
+            data.extend(abi::encode(&[
+                Token::Address(to),
+                Token::Bool(creation),
+                Token::Bytes(calldata),
+            ]));
+            let call = TransactionRequest::new()
+                .to(Address::from(ARBITRUM_NODE_INTERFACE))
+                .data(data);
+            let output = client.call(&call.into(), None).await?;
+            let tokens = abi::decode(
+                &[
+                    ParamType::Uint(64),
+                    ParamType::Uint(256),
+                    ParamType::Uint(256),
+                ],
+                output.as_ref(),
+            )?;
+            match tokens.as_slice() {
+                [Token::Uint(l1_gas), Token::Uint(base_fee), _] => {
+                    Ok(l1_gas.saturating_mul(*base_fee))
+                }
+                _ => {
+                    anyhow::bail!("Unexpected output of gasEstimateL1Component")
+                }
+            }
+        }
+    }
+}
+
+/// Estimates the L1 data fee of a filled transaction, as it is posted to the L1 once it
+/// is signed by the wallet of the client, see [`l1_data_fee`].
+pub async fn signed_l1_data_fee<M, S>(
+    client: &SignerMiddleware<M, S>,
+    rollup: RollupKind,
+    chain_id: u64,
+    tx: &TypedTransaction,
+) -> anyhow::Result<U256>
+where
+    M: Middleware + 'static,
+    S: Signer + 'static,
+    S::Error: 'static,
+{
+    let signature = client.signer().sign_transaction(tx).await?;
+    l1_data_fee(client, rollup, chain_id, tx, Some(&signature)).await
+}
+
+/// Estimates the L1 data fee of a withdraw on that chain, and zero if it is not a rollup.
+///
+/// The calldata of the withdraw is not built yet, an upper bound of it is used, see
+/// [`Withdrawal::calldata_size`].
+///
+/// # Arguments
+///
+/// * `client` - The client used for calling the fee precompiles of the rollup
+/// * `chain` - The configuration of the chain
+/// * `contract` - The address of the contract
+/// * `withdrawal` - The kind of the withdraw
+pub async fn withdraw_l1_data_fee<M: Middleware>(
+    client: &M,
+    chain: &EvmChainConfig,
+    contract: Address,
+    withdrawal: Withdrawal,
+) -> anyhow::Result<U256>
+where
+    M::Error: 'static,
+{
+    let rollup = match chain.rollup_kind() {
+        Some(v) => v,
+        None => return Ok(U256::zero()),
+    };
+    let tx = TransactionRequest::new().to(contract).data(vec![
+        0xff;
+        withdrawal
+            .calldata_size(
+            )
+    ]);
+    let l1_fee =
+        l1_data_fee(client, rollup, chain.chain_id, &tx.into(), None).await?;
+    tracing::trace!(?rollup, %l1_fee, "L1 data fee of the withdraw");
+    Ok(l1_fee)
+}
+
+/// Converts a typed transaction into a legacy one, for the chains that reject the typed
+/// transactions or lack `eth_feeHistory`.
+///
+/// Its gas price is the one of the typed transaction if any, otherwise the signer
+/// fills it with `eth_gasPrice`.
+pub fn into_legacy_tx(tx: TypedTransaction) -> TypedTransaction {
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            let legacy: TransactionRequest = inner.into();
+            TypedTransaction::Legacy(legacy)
+        }
+        TypedTransaction::Eip2930(inner) => TypedTransaction::Legacy(inner.tx),
+        legacy => legacy,
+    }
+}
+
+/// The gas price of a resubmitted transaction, at least `9/8` (12.5% more) of the `dropped` one,
+/// since the nodes only replace a pending transaction with one paying at least 10% more.
+fn resubmission_gas_price(dropped: U256, current: U256) -> U256 {
+    current.max(dropped * 9 / 8)
+}
+
+/// Reprices a transaction that got dropped from the mempool, before it gets resubmitted.
+///
+/// It pays the current gas price of the chain, but never less than 12.5% over the dropped one,
+/// so it replaces the dropped one on the nodes that still have it (it keeps the same nonce).
+///
+/// # Arguments
+///
+/// * `client` - The client used for fetching the current gas price
+/// * `tx` - The dropped transaction, with its gas price and nonce filled
+pub async fn reprice_dropped_tx<M: Middleware>(
+    client: &M,
+    tx: &mut TypedTransaction,
+) -> anyhow::Result<()>
+where
+    M::Error: 'static,
+{
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            let (max_fee, priority_fee) =
+                client.estimate_eip1559_fees(None).await?;
+            inner.max_fee_per_gas = Some(resubmission_gas_price(
+                inner.max_fee_per_gas.unwrap_or_default(),
+                max_fee,
+            ));
+            inner.max_priority_fee_per_gas = Some(resubmission_gas_price(
+                inner.max_priority_fee_per_gas.unwrap_or_default(),
+                priority_fee,
+            ));
+        }
+        _ => {
+            let current = client.get_gas_price().await?;
+            let dropped = tx.gas_price().unwrap_or_default();
+            tx.set_gas_price(resubmission_gas_price(dropped, current));
+        }
+    }
+    tracing::debug!(gas_price = ?tx.gas_price(), "Repriced the dropped tx");
+    Ok(())
+}
+
+/// Prepares a transaction of the tx queue for a rollup, before it gets signed and sent.
+///
+/// The rollups do not price their gas like the mainnet: Arbitrum ignores the priority fee and
+/// its gas limits include the L1 calldata cost, while the Optimism nodes lack `eth_feeHistory`.
+/// So the transaction is downgraded to a legacy one at the current gas price of the rollup, and
+/// its gas limit is estimated again by its node.
+///
+/// # Arguments
+///
+/// * `client` - The client used for estimating the gas of the transaction
+/// * `rollup` - The kind of rollup of the chain
+/// * `from` - The address of the relayer's wallet, which sends the transaction
+/// * `tx` - The queued transaction
+pub async fn prepare_rollup_tx<M: Middleware>(
+    client: &M,
+    rollup: RollupKind,
+    from: Address,
+    tx: TypedTransaction,
+) -> anyhow::Result<TypedTransaction>
+where
+    M::Error: 'static,
+{
+    let mut legacy = match into_legacy_tx(tx) {
+        TypedTransaction::Legacy(inner) => inner,
+        _ => unreachable!("the tx got converted to a legacy one"),
+    };
+    legacy.from = Some(from);
+    // the gas limit of the queued tx would cap the estimation.
+    legacy.gas = None;
+    legacy.gas_price = Some(client.get_gas_price().await?);
+    let mut tx = TypedTransaction::Legacy(legacy);
+    let gas = client.estimate_gas(&tx).await?;
+    tx.set_gas(gas);
+    tracing::trace!(?rollup, %gas, "Prepared the tx for the rollup");
+    Ok(tx)
+}
+
+/// Estimates the gas used by a withdraw, using the gas model of its contract type.
+///
+/// The `gas_limit` configured on the contract, if any, takes precedence over the model.
+pub fn estimate_gas(
+    models: &GasModelsConfig,
+    withdrawal: Withdrawal,
+    gas_limit: Option<U256>,
+) -> U256 {
+    if let Some(v) = gas_limit {
+        return v;
+    }
+    match withdrawal {
+        Withdrawal::Tornado => models.tornado_withdraw,
+        Withdrawal::Anchor => models.anchor_withdraw,
+    }
+}
+
+/// Returns the fee policy of that kind of withdraw on the chain.
+pub fn fee_policy(
+    chain: &EvmChainConfig,
+    withdrawal: Withdrawal,
+) -> &FeePolicyConfig {
+    match withdrawal {
+        Withdrawal::Tornado => &chain.fee_policies.tornado_withdraw,
+        Withdrawal::Anchor => &chain.fee_policies.anchor_withdraw,
+    }
+}
+
+/// Sets the `fixed-gas-limit` configured on the contract as the gas limit of a withdraw
+/// transaction, instead of letting the node estimate it, as some chains mis-estimate the
+/// proof verification.
+///
+/// The node still estimates the transaction, the estimate is logged along the configured
+/// limit, so a drift between them gets noticed. Without a configured limit, the transaction
+/// is left untouched and gets estimated when it is filled.
+///
+/// # Arguments
+///
+/// * `client` - The client used for estimating the gas of the transaction
+/// * `tx` - The withdraw transaction
+/// * `gas_limit` - The `fixed-gas-limit` of the contract, if any
+pub async fn apply_withdraw_gas_limit<M: Middleware>(
+    client: &M,
+    tx: &mut TypedTransaction,
+    gas_limit: Option<U256>,
+) {
+    let gas_limit = match gas_limit {
+        Some(v) => v,
+        None => return,
+    };
+    match client.estimate_gas(tx).await {
+        Ok(estimate) if estimate > gas_limit => {
+            tracing::warn!(
+                %estimate,
+                %gas_limit,
+                "The node estimates the withdraw above the configured gas limit"
+            );
+        }
+        Ok(estimate) => {
+            tracing::debug!(
+                %estimate,
+                %gas_limit,
+                "Using the configured gas limit of the withdraw"
+            );
+        }
+        Err(e) => {
+            tracing::warn!(
+                %gas_limit,
+                "Failed to estimate the withdraw, using the configured gas limit: {}",
+                e
+            );
+        }
+    }
+    tx.set_gas(gas_limit);
+}
+
+/// Computes the fee percentage that covers the gas cost of a withdraw.
+///
+/// The result is never lower than `base_percentage` (the configured fee percentage),
+/// nor higher than the maximum of the policy, unless the configured one is higher.
+/// On rollups, the gas cost includes the `l1_fee`, see [`l1_data_fee`].
+pub fn dynamic_fee_percentage(
+    policy: &DynamicFeeConfig,
+    base_percentage: f64,
+    gas_price: U256,
+    gas_limit: U256,
+    l1_fee: U256,
+    denomination: U256,
+) -> f64 {
+    if denomination.is_zero() {
+        return base_percentage;
+    }
+    let margin = U256::from((policy.gas_price_margin * 1_000.0) as u64);
+    let gas_cost = gas_price
+        .saturating_mul(gas_limit)
+        .saturating_add(l1_fee)
+        .saturating_mul(margin)
+        / 1_000;
+    // in millionths, the same precision used by `calculate_fee`.
+    let mill_fee =
+        gas_cost.saturating_mul(U256::from(1_000_000)) / denomination;
+    let gas_percentage = if mill_fee > U256::from(u32::MAX) {
+        f64::MAX
+    } else {
+        mill_fee.as_u64() as f64 / 1_000_000.0
+    };
+    gas_percentage
+        .min(policy.max_fee_percentage)
+        .max(base_percentage)
+}
+
+/// Resolves the current withdraw fee percentage of a contract.
+///
+/// Without a dynamic fee policy for that kind of withdraw, its own or the chain's one,
+/// this is the `base_percentage`.
+///
+/// # Arguments
+///
+/// * `client` - The client used for reading the gas price of the chain
+/// * `chain` - The configuration of the chain
+/// * `contract` - The address of the contract
+/// * `withdrawal` - The kind of the withdraw
+/// * `gas` - The gas used by the withdraw, see [`estimate_gas`]
+/// * `base_percentage` - The configured fee percentage, of the contract or the identity
+/// * `denomination` - The denomination of the contract
+pub async fn withdraw_fee_percentage<M: Middleware>(
+    client: &M,
+    chain: &EvmChainConfig,
+    contract: Address,
+    withdrawal: Withdrawal,
+    gas: U256,
+    base_percentage: f64,
+    denomination: U256,
+) -> anyhow::Result<f64>
+where
+    M::Error: 'static,
+{
+    let policy = fee_policy(chain, withdrawal).dynamic_fee.as_ref();
+    let policy = match policy.or(chain.dynamic_fee.as_ref()) {
+        Some(v) => v,
+        None => return Ok(base_percentage),
+    };
+    let gas_price = client.get_gas_price().await?;
+    // the quotes do not know the calldata, so the same upper bound
+    // is used for them and for checking the fee of the withdraws.
+    let l1_fee =
+        withdraw_l1_data_fee(client, chain, contract, withdrawal).await?;
+    let fee_percentage = dynamic_fee_percentage(
+        policy,
+        base_percentage,
+        gas_price,
+        gas,
+        l1_fee,
+        denomination,
+    );
+    tracing::trace!(%gas_price, fee_percentage, "Dynamic fee percentage");
+    Ok(fee_percentage)
+}
+
+/// The price of a token in the native token of its chain, as reported by its price feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenPrice {
+    /// The price, with `decimals` decimals.
+    pub answer: U256,
+    /// The decimals of the price.
+    pub decimals: u8,
+}
+
+/// Reads the latest price of a Chainlink-compatible price feed.
+///
+/// Returns an error if the feed reports a price that is not positive.
+///
+/// # Arguments
+///
+/// * `client` - The client used for calling the price feed
+/// * `feed` - The address of the price feed (an `AggregatorV3Interface`)
+pub async fn token_price<M: Middleware>(
+    client: &M,
+    feed: Address,
+) -> anyhow::Result<TokenPrice>
+where
+    M::Error: 'static,
+{
+    let call = TransactionRequest::new()
+        .to(feed)
+        .data(id("latestRoundData()").to_vec());
+    let output = client.call(&call.into(), None).await?;
+    let answer = decode_latest_round_data(output.as_ref())?;
+    let decimals = call_decimals(client, feed).await?;
+    tracing::trace!(%feed, %answer, decimals, "Token price");
+    Ok(TokenPrice { answer, decimals })
+}
+
+/// The decimals of the native token of the EVM chains.
+const NATIVE_DECIMALS: u8 = 18;
+
+/// The decimals of the ERC20 tokens read so far, by chain id and token address.
+static TOKEN_DECIMALS: Lazy<RwLock<HashMap<(u64, Address), u8>>> =
+    Lazy::new(Default::default);
+
+/// Returns the decimals of a token, read from its ERC20 `decimals()` the first time.
+///
+/// # Arguments
+///
+/// * `client` - The client used for calling the token
+/// * `chain_id` - The chain id of the token
+/// * `token` - The address of the token, the zero address for the native token
+pub async fn token_decimals<M: Middleware>(
+    client: &M,
+    chain_id: u64,
+    token: Address,
+) -> anyhow::Result<u8>
+where
+    M::Error: 'static,
+{
+    if token.is_zero() {
+        return Ok(NATIVE_DECIMALS);
+    }
+    if let Some(decimals) = TOKEN_DECIMALS.read().get(&(chain_id, token)) {
+        return Ok(*decimals);
+    }
+    let decimals = call_decimals(client, token).await?;
+    TOKEN_DECIMALS.write().insert((chain_id, token), decimals);
+    Ok(decimals)
+}
+
+/// Calls the `decimals()` of a contract, a token or a price feed.
+async fn call_decimals<M: Middleware>(
+    client: &M,
+    contract: Address,
+) -> anyhow::Result<u8>
+where
+    M::Error: 'static,
+{
+    let call = TransactionRequest::new()
+        .to(contract)
+        .data(id("decimals()").to_vec());
+    let output = client.call(&call.into(), None).await?;
+    let tokens = abi::decode(&[ParamType::Uint(8)], output.as_ref())?;
+    match tokens.as_slice() {
+        [Token::Uint(v)] if *v <= U256::from(u8::MAX) => Ok(v.as_u32() as u8),
+        _ => anyhow::bail!("Unexpected output of decimals"),
+    }
+}
+
+/// Decodes the answer of the `latestRoundData` of a price feed.
+fn decode_latest_round_data(output: &[u8]) -> anyhow::Result<U256> {
+    let tokens = abi::decode(
+        &[
+            ParamType::Uint(80),
+            ParamType::Int(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(80),
+        ],
+        output,
+    )?;
+    match tokens.as_slice() {
+        // the answer is a two's complement int256, a set top bit is a negative price.
+        [_, Token::Int(answer), ..]
+            if !answer.is_zero() && !answer.bit(255) =>
+        {
+            Ok(*answer)
+        }
+        [_, Token::Int(_), ..] => {
+            anyhow::bail!(
+                "The price feed reported a price that is not positive"
+            )
+        }
+        _ => anyhow::bail!("Unexpected output of latestRoundData"),
+    }
+}
+
+/// Computes the minimum fee that covers the gas cost of a withdraw, with the margin of the guard.
+///
+/// The gas cost is converted to the contract's token at its `price`, if it is not the
+/// native token, then scaled from the decimals of the native token to the `token_decimals`.
+pub fn minimum_fee(
+    guard: &ProfitabilityGuardConfig,
+    gas_cost: U256,
+    price: Option<TokenPrice>,
+    token_decimals: u8,
+) -> U256 {
+    let margin = U256::from((guard.gas_cost_margin * 1_000.0) as u64);
+    let gas_cost = gas_cost.saturating_mul(margin) / 1_000;
+    match price {
+        Some(price) => gas_cost
+            .saturating_mul(U256::exp10(usize::from(price.decimals)))
+            .saturating_mul(U256::exp10(usize::from(token_decimals)))
+            .checked_div(
+                price
+                    .answer
+                    .saturating_mul(U256::exp10(usize::from(NATIVE_DECIMALS))),
+            )
+            .unwrap_or(U256::MAX),
+        None => gas_cost,
+    }
+}
+
+/// Checks that the fee of a withdraw covers its gas cost, if the chain has a profitability guard.
+///
+/// Returns the minimum fee if the withdraw should be rejected, altruistic operators
+/// relay the unprofitable withdraws anyway.
+///
+/// # Arguments
+///
+/// * `client` - The client used for reading the gas price of the chain
+/// * `chain` - The configuration of the chain
+/// * `tx` - The withdraw transaction, for estimating its L1 data fee on rollups
+/// * `gas` - The gas used by the withdraw, see [`estimate_gas`]
+/// * `fee` - The fee embedded in the withdraw
+/// * `token` - The token the fee is paid in, the zero address for the native token
+/// * `price_feed` - The price feed of the contract's token in the native token, if it is not the native one
+pub async fn check_profitability<M: Middleware>(
+    client: &M,
+    chain: &EvmChainConfig,
+    tx: &TypedTransaction,
+    gas: U256,
+    fee: U256,
+    token: Address,
+    price_feed: Option<Address>,
+) -> anyhow::Result<Option<U256>>
+where
+    M::Error: 'static,
+{
+    let guard = match &chain.profitability_guard {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let gas_price = client.get_gas_price().await?;
+    let l1_fee = match chain.rollup_kind() {
+        Some(rollup) => {
+            l1_data_fee(client, rollup, chain.chain_id, tx, None).await?
+        }
+        None => U256::zero(),
+    };
+    let (price, decimals) = match price_feed {
+        Some(feed) => (
+            Some(token_price(client, feed).await?),
+            token_decimals(client, chain.chain_id, token).await?,
+        ),
+        None => (None, NATIVE_DECIMALS),
+    };
+    let gas_cost = gas_price.saturating_mul(gas).saturating_add(l1_fee);
+    let minimum_fee = minimum_fee(guard, gas_cost, price, decimals);
+    if fee >= minimum_fee {
+        return Ok(None);
+    }
+    if guard.altruistic {
+        tracing::warn!(
+            %fee,
+            %minimum_fee,
+            "Relaying an unprofitable withdraw (altruistic mode)"
+        );
+        return Ok(None);
+    }
+    Ok(Some(minimum_fee))
+}
+
+/// Returns the fee percentage of a withdraw on a contract, before it follows the gas price.
+///
+/// The percentage of the relayer identity overrides the one of the contract, both the
+/// quotes and the relays resolve it here, so a quote is what the relay expects.
+pub fn base_fee_percentage(
+    withdraw_config: &AnchorWithdrawConfig,
+    identity_fee_percentage: Option<f64>,
+) -> f64 {
+    identity_fee_percentage
+        .unwrap_or_else(|| withdraw_config.withdraw_fee_percentage())
+}
+
+/// Quotes the fee the relayer currently expects for relaying a withdraw on that contract.
+///
+/// Returns an error if the contract does not support withdraws.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_name` - The name of the chain the contract is deployed on
+/// * `contract` - The configuration of the contract
+/// * `identity` - The relayer identity the withdraw would be relayed with, if any
+pub async fn quote_fee(
+    ctx: &RelayerContext,
+    chain_name: &str,
+    contract: &Contract,
+    identity: Option<&RelayerIdentityConfig>,
+) -> anyhow::Result<FeeQuote> {
+    let chain = ctx.config.evm.get(chain_name).ok_or_else(|| {
+        anyhow::anyhow!("Chain {} not configured", chain_name)
+    })?;
+    let provider = Arc::new(ctx.evm_provider(chain_name).await?);
+    let (address, withdrawal, withdraw_config, denomination) = match contract {
+        Contract::Anchor(c) => {
+            let address = c.common.address;
+            let contract =
+                FixedDepositAnchorContract::new(address, provider.clone());
+            let denomination = contract.denomination().call().await?;
+            (
+                address,
+                Withdrawal::Anchor,
+                &c.withdraw_config,
+                denomination,
+            )
+        }
+        Contract::Tornado(c) => {
+            let address = c.common.address;
+            let contract = TornadoContract::new(address, provider.clone());
+            let denomination = contract.denomination().call().await?;
+            (
+                address,
+                Withdrawal::Tornado,
+                &c.withdraw_config,
+                denomination,
+            )
+        }
+        _ => anyhow::bail!("Contract does not support withdraws"),
+    };
+    let gas = estimate_gas(
+        &chain.gas_models,
+        withdrawal,
+        withdraw_config.withdraw_gaslimit,
+    );
+    let fee_percentage = withdraw_fee_percentage(
+        provider.as_ref(),
+        chain,
+        address,
+        withdrawal,
+        gas,
+        base_fee_percentage(
+            withdraw_config,
+            identity.and_then(|v| v.withdraw_fee_percentage),
+        ),
+        denomination,
+    )
+    .await?;
+    Ok(FeeQuote {
+        chain_id: U256::from(chain.chain_id),
+        contract: address,
+        fee_percentage,
+        denomination,
+        expected_fee: calculate_fee(fee_percentage, denomination),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use webb::evm::ethers::providers::Provider;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn configured_gas_limits_replace_the_estimate() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U256::from(900_000)).unwrap();
+        let mut tx = TypedTransaction::Legacy(TransactionRequest::new());
+        let gas_limit = U256::from(1_200_000);
+        apply_withdraw_gas_limit(&provider, &mut tx, Some(gas_limit)).await;
+        assert_eq!(tx.gas(), Some(&gas_limit));
+        // without a configured limit, the node estimates it when the tx gets filled.
+        let mut tx = TypedTransaction::Legacy(TransactionRequest::new());
+        apply_withdraw_gas_limit(&provider, &mut tx, None).await;
+        assert_eq!(tx.gas(), None);
+    }
+
+    #[test]
+    fn resubmissions_outbid_the_dropped_tx() {
+        let gwei = U256::from(1_000_000_000u64);
+        // the gas price went down, the dropped tx is still outbid.
+        let price = resubmission_gas_price(gwei * 80, gwei * 50);
+        assert_eq!(price, gwei * 90);
+        // the gas price went up, the current one is paid.
+        let price = resubmission_gas_price(gwei * 80, gwei * 120);
+        assert_eq!(price, gwei * 120);
+    }
+
+    #[test]
+    fn dynamic_fee_tracks_the_gas_price() {
+        let policy = DynamicFeeConfig {
+            gas_price_margin: 1.0,
+            max_fee_percentage: 0.1,
+        };
+        let gas_limit = U256::from(500_000);
+        let denomination = U256::exp10(18);
+        // 10 gwei: the gas cost is 0.5% of the denomination, lower than the configured fee.
+        let cheap = U256::exp10(10);
+        let fee = dynamic_fee_percentage(
+            &policy,
+            0.01,
+            cheap,
+            gas_limit,
+            U256::zero(),
+            denomination,
+        );
+        assert_eq!(fee, 0.01);
+        // 40 gwei: the gas cost is 2% of the denomination.
+        let fee = dynamic_fee_percentage(
+            &policy,
+            0.01,
+            cheap * 4,
+            gas_limit,
+            U256::zero(),
+            denomination,
+        );
+        assert_eq!(fee, 0.02);
+        // a huge spike is capped.
+        let fee = dynamic_fee_percentage(
+            &policy,
+            0.01,
+            U256::exp10(20),
+            gas_limit,
+            U256::zero(),
+            denomination,
+        );
+        assert_eq!(fee, 0.1);
+    }
+
+    #[tokio::test]
+    async fn withdraws_follow_the_fee_policy_of_their_type() {
+        let mut chain: EvmChainConfig =
+            serde_json::from_value(serde_json::json!({
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 5,
+                "private-key": format!("0x{}", "ab".repeat(32)),
+            }))
+            .unwrap();
+        chain.fee_policies.anchor_withdraw.dynamic_fee =
+            Some(DynamicFeeConfig {
+                gas_price_margin: 1.0,
+                max_fee_percentage: 0.1,
+            });
+        let (provider, mock) = Provider::mocked();
+        // 40 gwei: the gas cost is 2% of the denomination.
+        mock.push(U256::exp10(10) * 4).unwrap();
+        let gas = U256::from(500_000);
+        let denomination = U256::exp10(18);
+        let contract = Address::zero();
+        let fee = withdraw_fee_percentage(
+            &provider,
+            &chain,
+            contract,
+            Withdrawal::Anchor,
+            gas,
+            0.01,
+            denomination,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fee, 0.02);
+        // the tornado withdraws have no dynamic fee policy, nor does the chain.
+        let fee = withdraw_fee_percentage(
+            &provider,
+            &chain,
+            contract,
+            Withdrawal::Tornado,
+            gas,
+            0.01,
+            denomination,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fee, 0.01);
+    }
+
+    #[test]
+    fn l1_data_fee_is_part_of_the_gas_cost() {
+        let policy = DynamicFeeConfig {
+            gas_price_margin: 1.0,
+            max_fee_percentage: 0.1,
+        };
+        let denomination = U256::exp10(18);
+        // a cheap L2 gas (0.5% of the denomination), but the L1 data fee is 1.5% of it.
+        let fee = dynamic_fee_percentage(
+            &policy,
+            0.01,
+            U256::exp10(10),
+            U256::from(500_000),
+            U256::exp10(16) + U256::exp10(15) * 5,
+            denomination,
+        );
+        assert_eq!(fee, 0.02);
+    }
+
+    #[test]
+    fn typed_txs_are_converted_to_legacy_ones() {
+        use webb::evm::ethers::types::Eip1559TransactionRequest;
+        let typed = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .data(vec![1, 2, 3])
+            .gas(21_000);
+        let tx = into_legacy_tx(typed.into());
+        match tx {
+            TypedTransaction::Legacy(inner) => {
+                assert_eq!(inner.to, Some(Address::repeat_byte(1).into()));
+                assert_eq!(inner.data.map(|v| v.to_vec()), Some(vec![1, 2, 3]));
+                assert_eq!(inner.gas, Some(U256::from(21_000)));
+            }
+            _ => panic!("expected a legacy tx"),
+        }
+    }
+
+    #[test]
+    fn minimum_fee_is_converted_to_the_token() {
+        let guard = ProfitabilityGuardConfig {
+            gas_cost_margin: 1.5,
+            altruistic: false,
+        };
+        let gas_cost = U256::from(1_000_000);
+        assert_eq!(
+            minimum_fee(&guard, gas_cost, None, NATIVE_DECIMALS),
+            U256::from(1_500_000)
+        );
+        // the token is worth half of the native token.
+        let price = TokenPrice {
+            answer: U256::from(50_000_000),
+            decimals: 8,
+        };
+        let fee = minimum_fee(&guard, gas_cost, Some(price), NATIVE_DECIMALS);
+        assert_eq!(fee, U256::from(3_000_000));
+    }
+
+    #[test]
+    fn minimum_fee_follows_the_decimals_of_the_token() {
+        let guard = ProfitabilityGuardConfig {
+            gas_cost_margin: 1.0,
+            altruistic: false,
+        };
+        // 0.001 of the native token, with a token of 6 decimals worth 1/2000 of it.
+        let gas_cost = U256::exp10(15);
+        let price = TokenPrice {
+            answer: U256::from(50_000),
+            decimals: 8,
+        };
+        let fee = minimum_fee(&guard, gas_cost, Some(price), 6);
+        // 2 tokens.
+        assert_eq!(fee, U256::from(2_000_000));
+    }
+
+    #[test]
+    fn price_feeds_must_report_a_positive_price() {
+        let round = |answer: U256| {
+            abi::encode(&[
+                Token::Uint(U256::from(7)),
+                Token::Int(answer),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::from(7)),
+            ])
+        };
+        let answer = U256::from(50_000_000);
+        assert_eq!(decode_latest_round_data(&round(answer)).unwrap(), answer);
+        assert!(decode_latest_round_data(&round(U256::zero())).is_err());
+        // -1 in two's complement.
+        assert!(decode_latest_round_data(&round(U256::MAX)).is_err());
+        assert!(decode_latest_round_data(&[0; 32]).is_err());
+    }
+}
@@ -1,3 +1,572 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethereum_types::{Address, H256, U256};
+use tokio::sync::OwnedSemaphorePermit;
+use webb::evm::ethers::contract::ContractError;
+use webb::evm::ethers::core::types::transaction::eip2718::TypedTransaction;
+use webb::evm::ethers::middleware::SignerMiddleware;
+use webb::evm::ethers::prelude::{LocalWallet, Signer};
+use webb::evm::ethers::providers::Middleware;
+use webb::evm::ethers::types::TransactionReceipt;
+
+use crate::config::{
+    AnchorContractConfig, Contract, EvmChainConfig, TornadoContractConfig,
+};
+use crate::context::{QueuedWithdrawal, RelayerContext};
+use crate::handler::{
+    into_withdraw_error, CommandResponse, CommandStream, NetworkStatus,
+    WithdrawStatus,
+};
+use crate::store::cache::RelayerStore;
+use crate::store::{
+    AccountingStore, NullifierStore, RelayStatsStore, RelayedTx,
+    RelayedTxStore, SpentNullifier,
+};
+
 pub mod anchor;
+pub mod fees;
 pub mod tornado;
 pub mod vanchor;
+#[cfg(feature = "zk-verifier")]
+pub mod verifier;
+
+/// The relayer identity used for relaying a transaction on an EVM chain.
+pub struct EvmRelayerIdentity {
+    /// The name of the identity, none for the default account of the chain.
+    pub name: Option<String>,
+    /// The wallet used for signing the transaction.
+    pub wallet: LocalWallet,
+    /// The account that should receive the relaying fees.
+    pub beneficiary: Address,
+    /// The withdraw fee percentage of this identity, if it overrides the contract's one.
+    pub withdraw_fee_percentage: Option<f64>,
+}
+
+/// Finds the configuration of the Anchor `contract` on that chain.
+///
+/// The relayer only relays transactions to the contracts configured on the chain, with the
+/// type expected by the command, so a wrong address never makes it call another contract.
+pub fn anchor_contract_config(
+    chain: &EvmChainConfig,
+    contract: Address,
+) -> Option<&AnchorContractConfig> {
+    chain.contracts.iter().find_map(|c| match c {
+        Contract::Anchor(c) if c.common.address == contract => Some(c),
+        _ => None,
+    })
+}
+
+/// Finds the configuration of the Tornado `contract` on that chain.
+///
+/// See [`anchor_contract_config`].
+pub fn tornado_contract_config(
+    chain: &EvmChainConfig,
+    contract: Address,
+) -> Option<&TornadoContractConfig> {
+    chain.contracts.iter().find_map(|c| match c {
+        Contract::Tornado(c) if c.common.address == contract => Some(c),
+        _ => None,
+    })
+}
+
+/// Resolves the relayer identity that should be used for relaying a transaction
+/// to the `contract` on the `chain_name` chain.
+///
+/// If no identity is requested, the default account of that chain is used, paying
+/// the fees to the beneficiary of the contract, which defaults to the chain's one.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `chain_name` - The (lowercased) name of the chain.
+/// * `contract` - The target contract.
+/// * `identity` - The name of the requested identity, if any.
+pub async fn resolve_identity(
+    ctx: &RelayerContext,
+    chain_name: &str,
+    contract: Address,
+    identity: Option<&str>,
+) -> Result<EvmRelayerIdentity, NetworkStatus> {
+    let chain = ctx
+        .config
+        .evm
+        .get(chain_name)
+        .ok_or(NetworkStatus::UnsupportedChain)?;
+    let identity_name = match identity {
+        Some(v) => v,
+        None => {
+            let wallet = ctx.evm_wallet(chain_name).await.map_err(|e| {
+                tracing::error!("Misconfigured Network: {}", e);
+                NetworkStatus::Misconfigured
+            })?;
+            let beneficiary = anchor_contract_config(chain, contract)
+                .map(|c| &c.withdraw_config)
+                .or_else(|| {
+                    tornado_contract_config(chain, contract)
+                        .map(|c| &c.withdraw_config)
+                })
+                .and_then(|c| c.beneficiary)
+                .or(chain.beneficiary);
+            return Ok(EvmRelayerIdentity {
+                name: None,
+                beneficiary: beneficiary.unwrap_or_else(|| wallet.address()),
+                wallet,
+                withdraw_fee_percentage: None,
+            });
+        }
+    };
+    let identity = ctx
+        .config
+        .identities
+        .get(identity_name)
+        .ok_or(NetworkStatus::UnsupportedIdentity)?;
+    if !identity.allowed_contracts.is_empty()
+        && !identity.allowed_contracts.contains(&contract)
+    {
+        tracing::warn!(
+            "Contract {:?} is not allowed for identity {}",
+            contract,
+            identity_name
+        );
+        return Err(NetworkStatus::UnsupportedContract);
+    }
+    let account = identity
+        .evm
+        .get(chain_name)
+        .ok_or(NetworkStatus::UnsupportedChain)?;
+    let wallet = ctx
+        .evm_identity_wallet(identity_name, chain_name)
+        .await
+        .map_err(|e| {
+            tracing::error!("Misconfigured Identity: {}", e);
+            NetworkStatus::Misconfigured
+        })?;
+    Ok(EvmRelayerIdentity {
+        name: Some(identity_name.to_owned()),
+        beneficiary: account.beneficiary.unwrap_or_else(|| wallet.address()),
+        wallet,
+        withdraw_fee_percentage: identity.withdraw_fee_percentage,
+    })
+}
+
+/// Records a transaction that got submitted by the relayer in the tx history,
+/// so that clients can query its status later.
+///
+/// Failing to record it is only logged, since the transaction is already submitted.
+pub fn record_relayed_tx(store: &RelayerStore, chain_id: U256, tx_hash: H256) {
+    let chain_id = match crate::proposals::evm_typed_chain_id(chain_id) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!(%tx_hash, "Failed to record the relayed tx: {}", e);
+            return;
+        }
+    };
+    let relayed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let tx = RelayedTx {
+        chain_id,
+        tx_hash,
+        relayed_at,
+        block_number: None,
+        success: None,
+    };
+    if let Err(e) = store.insert_relayed_tx(tx) {
+        tracing::error!(%tx_hash, "Failed to record the relayed tx: {}", e);
+    }
+}
+
+/// Records the gas spent by a finalized relay transaction, and the fee it earned, in the
+/// accounting of the `contract`, and counts it in the relay stats of the `contract`.
+///
+/// Failing to record it is only logged, since the transaction is already finalized.
+///
+/// # Arguments
+///
+/// * `store` - [Sled](https://sled.rs)-based database store, where the accounting is kept
+/// * `chain_id` - The chain id of the chain
+/// * `contract` - The contract the transaction got relayed to
+/// * `token` - The token the fee got paid in, the zero address for the native token
+/// * `identity` - The relayer identity that relayed the transaction, if not the default one
+/// * `receipt` - The receipt of the transaction
+/// * `l1_fee` - The L1 data fee of the transaction on a rollup, zero otherwise
+/// * `fee` - The earned fee, zero if the transaction reverted
+pub fn record_relay_cost(
+    store: &RelayerStore,
+    chain_id: U256,
+    contract: Address,
+    token: Address,
+    identity: Option<&str>,
+    receipt: &TransactionReceipt,
+    l1_fee: U256,
+    fee: U256,
+) {
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    let gas_price = receipt.effective_gas_price.unwrap_or_default();
+    let finalized_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let recorded = store.record_relay_cost(
+        chain_id,
+        contract,
+        token,
+        identity,
+        finalized_at,
+        gas_used,
+        gas_used.saturating_mul(gas_price).saturating_add(l1_fee),
+        fee,
+    );
+    if let Err(e) = recorded {
+        tracing::error!(
+            tx_hash = %receipt.transaction_hash,
+            "Failed to record the relay cost: {}",
+            e
+        );
+    }
+    let reverted = receipt.status == Some(0u64.into());
+    let counted =
+        store.record_relay((chain_id, contract), finalized_at, reverted);
+    if let Err(e) = counted {
+        tracing::error!(
+            tx_hash = %receipt.transaction_hash,
+            "Failed to count the relay: {}",
+            e
+        );
+    }
+}
+
+/// Claims the withdraw spending `nullifier_hash` on the `contract`, before relaying it.
+///
+/// A withdraw whose nullifier is already known to be spent, or that is already being
+/// relayed, is rejected right away, instead of letting its transaction revert on chain.
+/// The claim is held until the returned guard is dropped.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the queued withdraws
+/// * `store` - [Sled](https://sled.rs)-based database store, where the spent nullifiers are cached
+/// * `chain` - The configuration of the chain
+/// * `contract` - The address of the contract
+/// * `nullifier_hash` - The nullifier hash spent by the withdraw
+pub fn claim_withdrawal(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    chain: &EvmChainConfig,
+    contract: Address,
+    nullifier_hash: H256,
+) -> Result<QueuedWithdrawal, WithdrawStatus> {
+    let key = (U256::from(chain.chain_id), contract);
+    match store.is_nullifier_spent(key, nullifier_hash) {
+        Ok(true) => return Err(WithdrawStatus::NullifierAlreadySpent),
+        Ok(false) => {}
+        // the cache is only an early check, the dry call still catches a spent nullifier.
+        Err(e) => tracing::warn!("Failed to check the nullifier cache: {}", e),
+    }
+    ctx.claim_withdrawal(chain.chain_id, contract, nullifier_hash)
+        .ok_or(WithdrawStatus::AlreadyQueued)
+}
+
+/// Verifies the proof of a withdraw against the verifying key at `path`, on the blocking
+/// threads.
+///
+/// Only the proofs that are certainly invalid get rejected: when the key cannot be loaded,
+/// or does not match the contract, the error is logged and the withdraw is left to the
+/// dry run.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the loaded verifying keys
+/// * `path` - The path to the verifying key of the withdraw circuit
+/// * `proof` - The proof, encoded as the Solidity verifiers expect it
+/// * `inputs` - The public inputs of the withdraw, an error if they are not field elements
+#[cfg(feature = "zk-verifier")]
+pub async fn verify_withdraw_proof(
+    ctx: &RelayerContext,
+    path: &std::path::Path,
+    proof: Vec<u8>,
+    inputs: anyhow::Result<Vec<ark_bn254::Fr>>,
+) -> Result<(), WithdrawStatus> {
+    let inputs = inputs.map_err(|e| {
+        tracing::debug!("Invalid public inputs: {}", e);
+        WithdrawStatus::InvalidProof
+    })?;
+    let key = match ctx.verifying_keys().get(path).await {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("Failed to load the verifying key: {:#}", e);
+            return Ok(());
+        }
+    };
+    // the pairings take a few milliseconds, that the executor should not wait for.
+    let verified = tokio::task::spawn_blocking(move || {
+        let proof = match verifier::decode_proof(&proof) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::debug!("Malformed proof: {}", e);
+                return Ok(false);
+            }
+        };
+        verifier::verify_proof(&key, &proof, &inputs)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|v| v);
+    match verified {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(WithdrawStatus::InvalidProof),
+        Err(e) => {
+            tracing::error!("Failed to verify the proof: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Caches the nullifier spent by a finalized withdraw, along with its transaction and the
+/// timestamp of its block.
+///
+/// Failing to cache it is only logged, since the withdraw is already finalized.
+pub async fn record_spent_nullifier<M: Middleware>(
+    client: &M,
+    store: &RelayerStore,
+    chain_id: U256,
+    contract: Address,
+    nullifier_hash: H256,
+    receipt: &TransactionReceipt,
+) {
+    let spent_at = match receipt.block_hash {
+        Some(block_hash) => match client.get_block(block_hash).await {
+            Ok(block) => block.map(|b| b.timestamp.as_u64()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch the block of the withdraw: {}",
+                    e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let spent = SpentNullifier {
+        tx_hash: Some(receipt.transaction_hash),
+        block_number: receipt.block_number,
+        spent_at,
+    };
+    if let Err(e) = store.insert_spent_nullifier(
+        (chain_id, contract),
+        nullifier_hash,
+        spent,
+    ) {
+        tracing::error!(%nullifier_hash, "Failed to cache the spent nullifier: {}", e);
+    }
+}
+
+/// Sends a relay transaction, and waits for its receipt.
+///
+/// The client gets the `Sent` and `Submitted` updates of every submission. A transaction
+/// dropped from the mempool is repriced (see [`fees::reprice_dropped_tx`]) and resubmitted
+/// with the same nonce, up to `max-resubmissions` times, then `Ok(None)` is returned.
+///
+/// On the rollups, the transaction is prepared like the ones of the tx queue (see
+/// [`fees::prepare_rollup_tx`]), and its L1 data fee is returned with its receipt, zero
+/// on the other chains. The effective gas price of the receipt is always set, to the
+/// gas price of the transaction on the nodes that do not return it.
+///
+/// Returns the status of the withdraw if the transaction failed.
+///
+/// # Arguments
+///
+/// * `client` - The client signing and sending the transaction
+/// * `chain` - The configuration of the chain
+/// * `store` - [Sled](https://sled.rs)-based database store, where the relayed txs are recorded
+/// * `stream` - The stream to write the updates to
+/// * `tx` - The transaction
+/// * `permit` - The turn of the relay on the chain, released once the transaction is submitted
+pub async fn send_relay_tx<M, S>(
+    client: &SignerMiddleware<M, S>,
+    chain: &EvmChainConfig,
+    store: &RelayerStore,
+    stream: &CommandStream,
+    mut tx: TypedTransaction,
+    permit: OwnedSemaphorePermit,
+) -> Result<Option<(TransactionReceipt, U256)>, WithdrawStatus>
+where
+    M: Middleware + 'static,
+    S: Signer + 'static,
+    S::Error: 'static,
+{
+    use CommandResponse::Withdraw;
+    let send_error = |e: <SignerMiddleware<M, S> as Middleware>::Error| {
+        tracing::error!("Error while sending Tx: {}", e);
+        into_withdraw_error(
+            ContractError::<SignerMiddleware<M, S>>::MiddlewareError(e),
+        )
+    };
+    let rollup = chain.rollup_kind();
+    if let Some(rollup) = rollup {
+        let prepared = fees::prepare_rollup_tx(
+            client,
+            rollup,
+            client.signer().address(),
+            tx.clone(),
+        )
+        .await;
+        match prepared {
+            Ok(v) => tx = v,
+            Err(e) => tracing::warn!(
+                ?rollup,
+                "Failed to prepare the tx for the rollup: {}",
+                e
+            ),
+        }
+    }
+    // the nonce is filled once, so a resubmission replaces the dropped transaction.
+    client
+        .fill_transaction(&mut tx, None)
+        .await
+        .map_err(send_error)?;
+    // on the rollups, the L1 data fee of the signed tx is part of its cost.
+    let l1_fee = match rollup {
+        Some(rollup) => {
+            fees::signed_l1_data_fee(client, rollup, chain.chain_id, &tx)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to estimate the L1 data fee: {}", e);
+                    U256::zero()
+                })
+        }
+        None => U256::zero(),
+    };
+    let mut permit = Some(permit);
+    let interval = Duration::from_millis(chain.profile.tx_polling_interval());
+    let mut attempt = 0;
+    loop {
+        let sent = client.send_transaction(tx.clone(), None).await;
+        // the transaction is submitted (or failed), the next relay could take its turn.
+        drop(permit.take());
+        let pending = sent.map_err(send_error)?;
+        let _ = stream.send(Withdraw(WithdrawStatus::Sent)).await;
+        let tx_hash = *pending;
+        tracing::debug!(%tx_hash, "Tx is submitted and pending!");
+        record_relayed_tx(store, U256::from(chain.chain_id), tx_hash);
+        let result = pending.interval(interval).await;
+        let _ = stream
+            .send(Withdraw(WithdrawStatus::Submitted { tx_hash }))
+            .await;
+        match result {
+            Ok(Some(mut receipt)) => {
+                // the nodes before london do not return the price paid, it is the one sent.
+                if receipt.effective_gas_price.is_none() {
+                    receipt.effective_gas_price = tx.gas_price();
+                }
+                return Ok(Some((receipt, l1_fee)));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let reason = e.to_string();
+                tracing::error!("Transaction Errored: {}", reason);
+                return Err(WithdrawStatus::Errored { reason, code: 4 });
+            }
+        }
+        if attempt >= chain.max_resubmissions {
+            return Ok(None);
+        }
+        attempt += 1;
+        tracing::warn!(
+            %tx_hash,
+            attempt,
+            "Transaction Dropped from Mempool, resubmitting it"
+        );
+        if let Err(e) = fees::reprice_dropped_tx(client, &mut tx).await {
+            tracing::error!("Failed to reprice the dropped Tx: {}", e);
+            return Ok(None);
+        }
+        let _ = stream
+            .send(Withdraw(WithdrawStatus::Resubmitting { attempt }))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebbRelayerConfig;
+
+    fn context_with_identity(allowed_contracts: &[Address]) -> RelayerContext {
+        let chain = format!(
+            r#"{{
+                "http-endpoint": "http://localhost:8545",
+                "ws-endpoint": "ws://localhost:8545",
+                "chain-id": 5001,
+                "private-key": "0x{}"
+            }}"#,
+            "ab".repeat(32)
+        );
+        let identity = serde_json::json!({
+            "evm": {
+                "hermes": {
+                    "private-key": format!("0x{}", "cd".repeat(32)),
+                    "beneficiary": format!("0x{}", "11".repeat(20)),
+                },
+            },
+            "withdraw-fee-percentage": 0.5,
+            "allowed-contracts": allowed_contracts,
+        });
+        let mut config = WebbRelayerConfig::default();
+        config
+            .evm
+            .insert("hermes".into(), serde_json::from_str(&chain).unwrap());
+        config
+            .identities
+            .insert("dapp".into(), serde_json::from_value(identity).unwrap());
+        RelayerContext::new(config)
+    }
+
+    #[tokio::test]
+    async fn identities_relay_with_their_own_account() {
+        let ctx = context_with_identity(&[]);
+        let contract = Address::random();
+        let default = resolve_identity(&ctx, "hermes", contract, None)
+            .await
+            .unwrap();
+        assert_eq!(default.name, None);
+        assert_eq!(default.beneficiary, default.wallet.address());
+        assert_eq!(default.withdraw_fee_percentage, None);
+        let dapp = resolve_identity(&ctx, "hermes", contract, Some("dapp"))
+            .await
+            .unwrap();
+        assert_eq!(dapp.name.as_deref(), Some("dapp"));
+        assert_ne!(dapp.wallet.address(), default.wallet.address());
+        assert_eq!(dapp.wallet.chain_id(), 5001);
+        assert_eq!(dapp.beneficiary, Address::repeat_byte(0x11));
+        assert_eq!(dapp.withdraw_fee_percentage, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn identities_only_relay_to_their_allowed_contracts() {
+        let allowed = Address::random();
+        let ctx = context_with_identity(&[allowed]);
+        let resolved =
+            resolve_identity(&ctx, "hermes", allowed, Some("dapp")).await;
+        assert!(resolved.is_ok());
+        let resolved =
+            resolve_identity(&ctx, "hermes", Address::random(), Some("dapp"))
+                .await;
+        assert!(matches!(resolved, Err(NetworkStatus::UnsupportedContract)));
+    }
+
+    #[tokio::test]
+    async fn unknown_identities_and_chains_are_unsupported() {
+        let ctx = context_with_identity(&[]);
+        let contract = Address::random();
+        let resolved =
+            resolve_identity(&ctx, "hermes", contract, Some("other")).await;
+        assert!(matches!(resolved, Err(NetworkStatus::UnsupportedIdentity)));
+        let resolved =
+            resolve_identity(&ctx, "athena", contract, Some("dapp")).await;
+        assert!(matches!(resolved, Err(NetworkStatus::UnsupportedChain)));
+        assert!(ctx.evm_identity_wallet("other", "hermes").await.is_err());
+        assert!(ctx.evm_identity_wallet("dapp", "athena").await.is_err());
+    }
+}
@@ -1,19 +1,24 @@
 use std::sync::Arc;
-use std::{collections::HashMap, time::Duration};
 
-use ethereum_types::U256;
+use ethereum_types::{Address, U256};
 use webb::evm::{
-    contract::tornado::TornadoContract,
-    ethers::prelude::{Signer, SignerMiddleware},
+    contract::tornado::TornadoContract, ethers::prelude::SignerMiddleware,
 };
 
 use crate::{
     context::RelayerContext,
     handler::{
         calculate_fee, into_withdraw_error, NetworkStatus,
-        TornadoRelayTransaction, WithdrawStatus,
+        TornadoRelayTransaction, WithdrawStatus, UNPROFITABLE_CODE,
     },
     handler::{CommandResponse, CommandStream},
+    metrics::{self, Labels, Metric},
+    store::cache::RelayerStore,
+    store::EarningsStore,
+    tx_relay::evm::{
+        claim_withdrawal, fees, record_relay_cost, record_spent_nullifier,
+        resolve_identity, send_relay_tx, tornado_contract_config,
+    },
 };
 
 /// Handler for tornado mixer commands
@@ -21,10 +26,12 @@ use crate::{
 /// # Arguments
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, where the relayed txs are recorded
 /// * `cmd` - The command to execute
 /// * `stream` - The stream to write the response to
 pub async fn handle_tornado_relay_tx<'a>(
     ctx: RelayerContext,
+    store: Arc<RelayerStore>,
     cmd: TornadoRelayTransaction,
     stream: CommandStream,
 ) {
@@ -43,18 +50,28 @@ pub async fn handle_tornado_relay_tx<'a>(
             return;
         }
     };
-    let supported_contracts: HashMap<_, _> = chain
-        .contracts
-        .iter()
-        .cloned()
-        .filter_map(|c| match c {
-            crate::config::Contract::Tornado(c) => Some(c),
-            _ => None,
-        })
-        .map(|c| (c.common.address, c))
-        .collect();
+    if ctx.is_evm_chain_paused(U256::from(chain.chain_id)) {
+        tracing::event!(
+            target: crate::probe::TARGET,
+            tracing::Level::DEBUG,
+            kind = %crate::probe::Kind::RelayTx,
+            network = ?NetworkStatus::Paused,
+        );
+        let _ = stream.send(Network(NetworkStatus::Paused)).await;
+        return;
+    }
+    if !ctx.is_leader() {
+        tracing::event!(
+            target: crate::probe::TARGET,
+            tracing::Level::DEBUG,
+            kind = %crate::probe::Kind::RelayTx,
+            network = ?NetworkStatus::Standby,
+        );
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
     // get the contract configuration
-    let contract_config = match supported_contracts.get(&cmd.contract) {
+    let contract_config = match tornado_contract_config(chain, cmd.contract) {
         Some(config) => config,
         None => {
             tracing::event!(
@@ -70,10 +87,36 @@ pub async fn handle_tornado_relay_tx<'a>(
         }
     };
 
-    let wallet = match ctx.evm_wallet(&cmd.chain).await {
+    // reject the withdraws spending a nullifier that is already spent or being relayed,
+    // the claim is held until the relay job ends.
+    let _claim = match claim_withdrawal(
+        &ctx,
+        &store,
+        chain,
+        cmd.contract,
+        cmd.nullifier_hash,
+    ) {
         Ok(v) => v,
-        Err(e) => {
-            tracing::error!("Misconfigured Network: {}", e);
+        Err(status) => {
+            tracing::warn!(nullifier_hash = %cmd.nullifier_hash, "Rejected withdraw: {:?}", status);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
+        }
+    };
+
+    // the signing key of the chain is held until the relay job ends, a key rotation
+    // waits for the withdraws signed with the old key.
+    let _signing = ctx.evm_signing_guard(&requested_chain).await;
+    let identity = match resolve_identity(
+        &ctx,
+        &requested_chain,
+        cmd.contract,
+        cmd.identity.as_deref(),
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(NetworkStatus::Misconfigured) => {
             tracing::event!(
                 target: crate::probe::TARGET,
                 tracing::Level::DEBUG,
@@ -85,13 +128,21 @@ pub async fn handle_tornado_relay_tx<'a>(
                 .await;
             return;
         }
+        Err(status) => {
+            tracing::event!(
+                target: crate::probe::TARGET,
+                tracing::Level::DEBUG,
+                kind = %crate::probe::Kind::RelayTx,
+                network = ?status,
+            );
+            let _ = stream.send(Network(status)).await;
+            return;
+        }
     };
+    let wallet = identity.wallet;
     // validate the relayer address first before trying
     // send the transaction.
-    let reward_address = match chain.beneficiary {
-        Some(account) => account,
-        None => wallet.address(),
-    };
+    let reward_address = identity.beneficiary;
 
     if cmd.relayer != reward_address {
         tracing::event!(
@@ -105,6 +156,30 @@ pub async fn handle_tornado_relay_tx<'a>(
             .await;
         return;
     }
+    // reject the invalid proofs locally, instead of paying for a dry run.
+    #[cfg(feature = "zk-verifier")]
+    if let Some(path) = &contract_config.verifying_key {
+        let inputs = crate::tx_relay::evm::verifier::tornado_public_inputs(
+            cmd.root,
+            cmd.nullifier_hash,
+            cmd.recipient,
+            cmd.relayer,
+            cmd.fee,
+            cmd.refund,
+        );
+        let verified = crate::tx_relay::evm::verify_withdraw_proof(
+            &ctx,
+            path,
+            cmd.proof.to_vec(),
+            inputs,
+        )
+        .await;
+        if let Err(status) = verified {
+            tracing::warn!(nullifier_hash = %cmd.nullifier_hash, "Rejected withdraw: {:?}", status);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
+        }
+    }
 
     tracing::debug!(
         "Connecting to chain {:?} .. at {}",
@@ -174,11 +249,40 @@ pub async fn handle_tornado_relay_tx<'a>(
             return;
         }
     };
+    // wait for a turn on the chain, the relays estimating and submitting their
+    // transaction together are bounded by `max-concurrent-relays`.
+    let permit = ctx.evm_relay_permit(chain).await;
     // check the fee
-    let expected_fee = calculate_fee(
-        contract_config.withdraw_config.withdraw_fee_percentage,
-        denomination,
+    let base_fee_percentage = fees::base_fee_percentage(
+        &contract_config.withdraw_config,
+        identity.withdraw_fee_percentage,
     );
+    let gas = fees::estimate_gas(
+        &chain.gas_models,
+        fees::Withdrawal::Tornado,
+        contract_config.withdraw_config.withdraw_gaslimit,
+    );
+    let fee_percentage = match fees::withdraw_fee_percentage(
+        contract.client(),
+        chain,
+        cmd.contract,
+        fees::Withdrawal::Tornado,
+        gas,
+        base_fee_percentage,
+        denomination,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Failed to compute the fee: {}", e);
+            let _ = stream
+                .send(Error(format!("Failed to compute the fee: {}", e)))
+                .await;
+            return;
+        }
+    };
+    let expected_fee = calculate_fee(fee_percentage, denomination);
     let (_, unacceptable_fee) = U256::overflowing_sub(cmd.fee, expected_fee);
     if unacceptable_fee {
         tracing::error!("Received a fee lower than configuration");
@@ -199,6 +303,10 @@ pub async fn handle_tornado_relay_tx<'a>(
         cmd.fee,
         cmd.refund,
     );
+    // some chains reject the typed transactions.
+    let call = if chain.legacy_tx { call.legacy() } else { call };
+    let labels =
+        Labels::new(chain.chain_id, format!("{:?}", cmd.contract), "tornado");
     // Make a dry call, to make sure the transaction will go through successfully
     // to avoid wasting fees on invalid calls.
     match call.call().await {
@@ -209,32 +317,127 @@ pub async fn handle_tornado_relay_tx<'a>(
         Err(e) => {
             tracing::error!("Error Client sent an invalid proof: {}", e);
             let err = into_withdraw_error(e);
+            metrics::inc(Metric::RelayTxsFailed, labels);
             let _ = stream.send(Withdraw(err)).await;
             return;
         }
     };
-    tracing::trace!("About to send Tx to {:?} Chain", cmd.chain);
-    let tx = match call.send().await {
-        Ok(pending) => {
-            let _ = stream.send(Withdraw(WithdrawStatus::Sent)).await;
-            let tx_hash = *pending;
-            tracing::debug!("Tx is submitted and pending! {}", tx_hash);
-            let result = pending.interval(Duration::from_millis(1000)).await;
-            let _ = stream
-                .send(Withdraw(WithdrawStatus::Submitted { tx_hash }))
-                .await;
-            result
+    // make sure the fee covers the gas cost, before sending the transaction.
+    let unprofitable = fees::check_profitability(
+        contract.client(),
+        chain,
+        &call.tx,
+        gas,
+        cmd.fee,
+        Address::zero(),
+        contract_config.withdraw_config.price_feed,
+    )
+    .await;
+    match unprofitable {
+        Ok(None) => {}
+        Ok(Some(minimum_fee)) => {
+            tracing::warn!(fee = %cmd.fee, %minimum_fee, "Unprofitable withdraw");
+            let status = WithdrawStatus::Unprofitable {
+                code: UNPROFITABLE_CODE,
+                fee: cmd.fee,
+                minimum_fee,
+            };
+            metrics::inc(Metric::RelayTxsFailed, labels);
+            let _ = stream.send(Withdraw(status)).await;
+            return;
         }
         Err(e) => {
-            tracing::error!("Error while sending Tx: {}", e);
-            let err = into_withdraw_error(e);
-            let _ = stream.send(Withdraw(err)).await;
+            tracing::error!("Failed to check the profitability: {}", e);
+            let _ = stream
+                .send(Error(format!(
+                    "Failed to check the profitability: {}",
+                    e
+                )))
+                .await;
             return;
         }
-    };
+    }
+    // the leader could have stepped down while the tx got prepared, then
+    // another instance could be relaying already.
+    if !ctx.is_leader() {
+        tracing::warn!("Stepped down to a standby, not sending the Tx");
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
+    if !stream.cancellation().broadcast() {
+        tracing::debug!("The relay job got cancelled, not sending the Tx");
+        let _ = stream.send(Withdraw(WithdrawStatus::Cancelled)).await;
+        return;
+    }
+    let mut relay_tx = call.tx.clone();
+    fees::apply_withdraw_gas_limit(
+        contract.client(),
+        &mut relay_tx,
+        contract_config.withdraw_config.fixed_gas_limit,
+    )
+    .await;
+    tracing::trace!("About to send Tx to {:?} Chain", cmd.chain);
+    let tx = send_relay_tx(
+        contract.client(),
+        chain,
+        &store,
+        &stream,
+        relay_tx,
+        permit,
+    )
+    .await;
     match tx {
-        Ok(Some(receipt)) => {
+        Ok(Some((receipt, l1_fee))) => {
             tracing::debug!("Finalized Tx #{}", receipt.transaction_hash);
+            if let Some(gas_used) = receipt.gas_used {
+                metrics::add_gas(
+                    labels.clone(),
+                    gas_used,
+                    receipt.effective_gas_price.unwrap_or_default(),
+                    l1_fee,
+                );
+            }
+            if receipt.status == Some(1.into()) {
+                metrics::inc(Metric::RelayTxs, labels);
+                let chain_id = U256::from(chain.chain_id);
+                record_spent_nullifier(
+                    contract.client(),
+                    &store,
+                    chain_id,
+                    cmd.contract,
+                    cmd.nullifier_hash,
+                    &receipt,
+                )
+                .await;
+            } else {
+                // the reverted transactions are mined, but relayed nothing.
+                metrics::inc(Metric::RelayTxsFailed, labels);
+            }
+            // tornado contracts pay the fee in the native token of the chain.
+            let chain_id = U256::from(chain.chain_id);
+            let native = Address::zero();
+            let succeeded = receipt.status == Some(1.into());
+            let earned = if succeeded { cmd.fee } else { U256::zero() };
+            record_relay_cost(
+                &store,
+                chain_id,
+                cmd.contract,
+                native,
+                identity.name.as_deref(),
+                &receipt,
+                l1_fee,
+                earned,
+            );
+            if !earned.is_zero() {
+                if let Err(e) = store.record_earning(
+                    chain_id,
+                    native,
+                    identity.name.as_deref(),
+                    earned,
+                ) {
+                    tracing::error!("Failed to record the earned fee: {}", e);
+                }
+            }
             let _ = stream
                 .send(Withdraw(WithdrawStatus::Finalized {
                     tx_hash: receipt.transaction_hash,
@@ -242,17 +445,15 @@ pub async fn handle_tornado_relay_tx<'a>(
                 .await;
         }
         Ok(None) => {
-            tracing::warn!("Transaction Dropped from Mempool!!");
+            tracing::warn!("Transaction Dropped from Mempool, giving up on it");
+            metrics::inc(Metric::RelayTxsFailed, labels);
             let _ = stream
                 .send(Withdraw(WithdrawStatus::DroppedFromMemPool))
                 .await;
         }
-        Err(e) => {
-            let reason = e.to_string();
-            tracing::error!("Transaction Errored: {}", reason);
-            let _ = stream
-                .send(Withdraw(WithdrawStatus::Errored { reason, code: 4 }))
-                .await;
+        Err(status) => {
+            metrics::inc(Metric::RelayTxsFailed, labels);
+            let _ = stream.send(Withdraw(status)).await;
         }
     };
 }
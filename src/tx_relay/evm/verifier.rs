@@ -0,0 +1,367 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Verifies the withdraw proofs locally, before spending any gas on them.
+//!
+//! The proofs are Groth16 proofs over BN254, encoded the way the Solidity verifiers
+//! of the anchors expect them, and checked against the verifying key of the circuit.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{PreparedVerifyingKey, Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use ethereum_types::{Address, H256, U256};
+use parking_lot::RwLock;
+
+/// The verifying keys loaded so far, by their path.
+///
+/// The clones share the loaded keys, it is kept in the
+/// [`RelayerContext`](crate::context::RelayerContext).
+#[derive(Clone, Default)]
+pub struct VerifyingKeys {
+    keys: Arc<RwLock<HashMap<PathBuf, Arc<PreparedVerifyingKey<Bn254>>>>>,
+}
+
+impl VerifyingKeys {
+    /// Returns the verifying key at that path, loaded the first time it is needed.
+    ///
+    /// The key is expected in the uncompressed arkworks serialization, it is read and
+    /// prepared on the blocking threads.
+    pub async fn get(
+        &self,
+        path: &Path,
+    ) -> anyhow::Result<Arc<PreparedVerifyingKey<Bn254>>> {
+        if let Some(key) = self.keys.read().get(path) {
+            return Ok(key.clone());
+        }
+        let owned = path.to_owned();
+        let key =
+            tokio::task::spawn_blocking(move || load_verifying_key(&owned))
+                .await??;
+        let key = self
+            .keys
+            .write()
+            .entry(path.to_owned())
+            .or_insert_with(|| Arc::new(key))
+            .clone();
+        Ok(key)
+    }
+}
+
+fn load_verifying_key(
+    path: &Path,
+) -> anyhow::Result<PreparedVerifyingKey<Bn254>> {
+    let bytes = std::fs::read(path).with_context(|| {
+        format!("failed to read the verifying key at {}", path.display())
+    })?;
+    let key = VerifyingKey::<Bn254>::deserialize_uncompressed(&bytes[..])
+        .with_context(|| {
+            format!("invalid verifying key at {}", path.display())
+        })?;
+    Ok(ark_groth16::prepare_verifying_key(&key))
+}
+
+/// Decodes a field element from its 32 bytes big-endian encoding.
+///
+/// The Solidity verifiers reject the values that are not below the modulus, instead of
+/// reducing them, so these are rejected here too.
+fn field_element<F: PrimeField>(bytes: &[u8]) -> anyhow::Result<F> {
+    let v = F::from_be_bytes_mod_order(bytes);
+    anyhow::ensure!(
+        v.into_repr().to_bytes_be() == bytes,
+        "0x{} is not below the modulus of the field",
+        hex::encode(bytes)
+    );
+    Ok(v)
+}
+
+/// Decodes a proof encoded as the Solidity verifiers expect it, a `uint256[8]` holding
+/// `a`, `b` and `c`, where the coordinates of `b` are in the order of the pairing
+/// precompile: the imaginary part first.
+pub fn decode_proof(bytes: &[u8]) -> anyhow::Result<Proof<Bn254>> {
+    anyhow::ensure!(
+        bytes.len() == 8 * 32,
+        "the proof is {} bytes instead of {}",
+        bytes.len(),
+        8 * 32
+    );
+    let w = bytes
+        .chunks_exact(32)
+        .map(field_element::<Fq>)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let a = G1Affine::new(w[0], w[1], false);
+    let b = G2Affine::new(Fq2::new(w[3], w[2]), Fq2::new(w[5], w[4]), false);
+    let c = G1Affine::new(w[6], w[7], false);
+    anyhow::ensure!(
+        a.is_on_curve()
+            && c.is_on_curve()
+            && b.is_on_curve()
+            && b.is_in_correct_subgroup_assuming_on_curve(),
+        "the points of the proof are not on the curve"
+    );
+    Ok(Proof { a, b, c })
+}
+
+/// The public inputs of an anchor withdraw, in the order of the anchor: the nullifier hash,
+/// the hash of the external data, the typed chain id of the anchor, then its roots.
+///
+/// Returns an error if one of them is not a field element, the anchor would reject it.
+pub fn anchor_public_inputs(
+    nullifier_hash: H256,
+    ext_data_hash: H256,
+    typed_chain_id: u64,
+    roots: &[u8],
+) -> anyhow::Result<Vec<Fr>> {
+    let mut inputs = vec![
+        field_element(nullifier_hash.as_bytes())?,
+        field_element(ext_data_hash.as_bytes())?,
+        Fr::from(typed_chain_id),
+    ];
+    for root in roots.chunks_exact(32) {
+        inputs.push(field_element(root)?);
+    }
+    Ok(inputs)
+}
+
+/// The public inputs of a Tornado withdraw, in the order of the Tornado contract: the root,
+/// the nullifier hash, the recipient, the relayer, the fee and the refund.
+///
+/// Returns an error if one of them is not a field element, the contract would reject it.
+pub fn tornado_public_inputs(
+    root: H256,
+    nullifier_hash: H256,
+    recipient: Address,
+    relayer: Address,
+    fee: U256,
+    refund: U256,
+) -> anyhow::Result<Vec<Fr>> {
+    let uint = |v: U256| {
+        let mut bytes = [0u8; 32];
+        v.to_big_endian(&mut bytes);
+        field_element(&bytes)
+    };
+    Ok(vec![
+        field_element(root.as_bytes())?,
+        field_element(nullifier_hash.as_bytes())?,
+        field_element(H256::from(recipient).as_bytes())?,
+        field_element(H256::from(relayer).as_bytes())?,
+        uint(fee)?,
+        uint(refund)?,
+    ])
+}
+
+/// Verifies the proof against the public inputs.
+///
+/// Returns an error if the verifying key does not expect that many inputs, which is a
+/// misconfiguration rather than an invalid proof.
+pub fn verify_proof(
+    key: &PreparedVerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    inputs: &[Fr],
+) -> anyhow::Result<bool> {
+    let expected = key.vk.gamma_abc_g1.len().saturating_sub(1);
+    anyhow::ensure!(
+        inputs.len() == expected,
+        "the verifying key expects {} public inputs, got {}",
+        expected,
+        inputs.len()
+    );
+    Ok(ark_groth16::verify_proof(key, proof, inputs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ff::Field;
+    use ark_relations::lc;
+    use ark_relations::r1cs::{
+        ConstraintSynthesizer, ConstraintSystemRef, SynthesisError,
+    };
+    use ark_serialize::CanonicalSerialize;
+
+    /// Proves the knowledge of a square root of the first public input, the other
+    /// inputs are only bound to the proof.
+    struct SquareRootCircuit {
+        secret: Fr,
+        inputs: Vec<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for SquareRootCircuit {
+        fn generate_constraints(
+            self,
+            cs: ConstraintSystemRef<Fr>,
+        ) -> Result<(), SynthesisError> {
+            let secret = cs.new_witness_variable(|| Ok(self.secret))?;
+            let inputs = self
+                .inputs
+                .iter()
+                .map(|v| cs.new_input_variable(|| Ok(*v)))
+                .collect::<Result<Vec<_>, _>>()?;
+            cs.enforce_constraint(
+                lc!() + secret,
+                lc!() + secret,
+                lc!() + inputs[0],
+            )
+        }
+    }
+
+    fn fq_bytes(v: Fq) -> Vec<u8> {
+        v.into_repr().to_bytes_be()
+    }
+
+    /// Encodes the proof as the Solidity verifiers expect it.
+    fn encode_proof(proof: &Proof<Bn254>) -> Vec<u8> {
+        [
+            proof.a.x,
+            proof.a.y,
+            proof.b.x.c1,
+            proof.b.x.c0,
+            proof.b.y.c1,
+            proof.b.y.c0,
+            proof.c.x,
+            proof.c.y,
+        ]
+        .iter()
+        .flat_map(|v| fq_bytes(*v))
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn anchor_proofs_are_verified_against_the_verifying_key() {
+        let rng = &mut ark_std::test_rng();
+        let secret = Fr::from(7u64);
+        let nullifier_hash =
+            H256::from_slice(&secret.square().into_repr().to_bytes_be());
+        let ext_data_hash = H256::from_low_u64_be(2);
+        let roots: Vec<u8> = [3u64, 4]
+            .iter()
+            .flat_map(|v| H256::from_low_u64_be(*v).to_fixed_bytes())
+            .collect();
+        let inputs = anchor_public_inputs(
+            nullifier_hash,
+            ext_data_hash,
+            0x0100_0000_0005,
+            &roots,
+        )
+        .unwrap();
+        let setup = SquareRootCircuit {
+            secret: Fr::default(),
+            inputs: vec![Fr::default(); inputs.len()],
+        };
+        let params =
+            ark_groth16::generate_random_parameters::<Bn254, _, _>(setup, rng)
+                .unwrap();
+        let circuit = SquareRootCircuit {
+            secret,
+            inputs: inputs.clone(),
+        };
+        let proof =
+            ark_groth16::create_random_proof(circuit, &params, rng).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("verifying_key.bin");
+        let mut bytes = Vec::new();
+        params.vk.serialize_uncompressed(&mut bytes).unwrap();
+        std::fs::write(&path, bytes).unwrap();
+        let keys = VerifyingKeys::default();
+        let key = keys.get(&path).await.unwrap();
+        // the clones share the loaded keys.
+        assert!(Arc::ptr_eq(&key, &keys.clone().get(&path).await.unwrap()));
+
+        let proof = decode_proof(&encode_proof(&proof)).unwrap();
+        assert!(verify_proof(&key, &proof, &inputs).unwrap());
+        // the same proof, for another external data.
+        let other = anchor_public_inputs(
+            nullifier_hash,
+            H256::from_low_u64_be(5),
+            0x0100_0000_0005,
+            &roots,
+        )
+        .unwrap();
+        assert!(!verify_proof(&key, &proof, &other).unwrap());
+        // a key for other inputs is a misconfiguration, not an invalid proof.
+        assert!(verify_proof(&key, &proof, &inputs[..4]).is_err());
+    }
+
+    #[test]
+    fn malformed_proofs_are_rejected() {
+        assert!(decode_proof(&[0u8; 255]).is_err());
+        // (1, 1) is not on the curve.
+        let mut bytes = [0u8; 256];
+        bytes[31] = 1;
+        bytes[63] = 1;
+        assert!(decode_proof(&bytes).is_err());
+    }
+
+    #[test]
+    fn coordinates_beyond_the_modulus_are_rejected() {
+        let mut modulus = [0u64; 4];
+        modulus.copy_from_slice(Fq::characteristic());
+        let mut bytes = [0u8; 32];
+        U256(modulus).to_big_endian(&mut bytes);
+        assert!(field_element::<Fq>(&bytes).is_err());
+        // a coordinate congruent to zero is not reduced either.
+        let mut proof = [0u8; 256];
+        proof[..32].copy_from_slice(&bytes);
+        assert!(decode_proof(&proof).is_err());
+        assert!(anchor_public_inputs(
+            H256::repeat_byte(0xff),
+            H256::zero(),
+            1,
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn anchor_inputs_are_in_the_anchor_order() {
+        let nullifier_hash = H256::from_low_u64_be(1);
+        let ext_data_hash = H256::from_low_u64_be(2);
+        let roots: Vec<u8> = [3u64, 4]
+            .iter()
+            .flat_map(|v| H256::from_low_u64_be(*v).to_fixed_bytes())
+            .collect();
+        let inputs = anchor_public_inputs(
+            nullifier_hash,
+            ext_data_hash,
+            0x0100_0000_0005,
+            &roots,
+        )
+        .unwrap();
+        let expected: Vec<Fr> = [1u64, 2, 0x0100_0000_0005, 3, 4]
+            .iter()
+            .map(|v| Fr::from(*v))
+            .collect();
+        assert_eq!(inputs, expected);
+    }
+
+    #[test]
+    fn tornado_inputs_are_in_the_tornado_order() {
+        let inputs = tornado_public_inputs(
+            H256::from_low_u64_be(1),
+            H256::from_low_u64_be(2),
+            Address::from_low_u64_be(3),
+            Address::from_low_u64_be(4),
+            U256::from(5),
+            U256::from(6),
+        )
+        .unwrap();
+        let expected: Vec<Fr> = (1u64..=6).map(Fr::from).collect();
+        assert_eq!(inputs, expected);
+    }
+}
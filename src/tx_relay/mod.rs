@@ -1,2 +1,6 @@
+#[cfg(feature = "evm")]
 pub mod evm;
+pub mod session;
+#[cfg(feature = "substrate")]
 pub mod substrate;
+pub mod validation;
@@ -0,0 +1,248 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::prelude::*;
+use rand::Rng;
+use tokio::sync::mpsc;
+use warp::ws::Message;
+
+use crate::handler::{
+    CommandResponse, CommandStream, MessageEncoding, TaggedResponse,
+};
+
+/// How long a session is kept around after its relay job is done, so that a client
+/// that got disconnected can still resume it and receive the last updates.
+pub const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+const PENDING: u8 = 0;
+const CANCELLED: u8 = 1;
+const BROADCAST: u8 = 2;
+
+/// Whether a relay job got cancelled by its client, before its transaction got broadcast.
+///
+/// Only one of cancelling the job and broadcasting its transaction succeeds.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicU8>);
+
+impl Cancellation {
+    /// Cancels the job, returns `false` if its transaction got broadcast already.
+    pub fn cancel(&self) -> bool {
+        self.transition(CANCELLED)
+    }
+
+    /// Marks the transaction of the job as broadcast, returns `false` if the job
+    /// got cancelled, then the transaction should not be sent.
+    pub fn broadcast(&self) -> bool {
+        self.transition(BROADCAST)
+    }
+
+    fn transition(&self, to: u8) -> bool {
+        match self.0.compare_exchange(
+            PENDING,
+            to,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => true,
+            Err(current) => current == to,
+        }
+    }
+}
+
+/// The updates of a relay job that are not delivered to the client yet.
+struct SessionState {
+    rx: mpsc::Receiver<CommandResponse>,
+    /// An update that failed to be sent over a dropped connection.
+    pending: Option<CommandResponse>,
+}
+
+/// A relay job that a client can attach to, using the session token.
+#[derive(Clone)]
+pub struct Session {
+    token: String,
+    /// The id of the request that started the relay job.
+    request_id: String,
+    state: Arc<tokio::sync::Mutex<SessionState>>,
+    cancellation: Cancellation,
+}
+
+impl Session {
+    /// The token the client uses to resume this session.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The id of the request that started the relay job, its updates are tagged with it.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Forwards the updates of the relay job to the client, until the job is done.
+    ///
+    /// The updates are tagged with the token of the session, so the client gets it
+    /// along with the first one.
+    ///
+    /// If the connection drops, the update that failed to be sent is kept, so that
+    /// it gets delivered first once the client resumes the session.
+    /// Only one connection can be attached at a time, others wait for it to be detached.
+    pub async fn forward<TX>(
+        &self,
+        tx: &mut TX,
+        encoding: MessageEncoding,
+    ) -> anyhow::Result<()>
+    where
+        TX: Sink<Message> + Unpin,
+        TX::Error: Error + Send + Sync + 'static,
+    {
+        let mut state = self.state.lock().await;
+        loop {
+            let update = match state.pending.take() {
+                Some(v) => v,
+                None => match state.rx.recv().await {
+                    Some(v) => v,
+                    None => return Ok(()),
+                },
+            };
+            tracing::trace!("Sending: {:?}", update);
+            let value = TaggedResponse::new(&update, &self.request_id)
+                .with_session_token(&self.token)
+                .to_message(encoding)?;
+            if let Err(e) = tx.send(value).await {
+                state.pending = Some(update);
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// Keeps track of the running relay jobs, so that clients can re-attach to them
+/// after their connection dropped.
+#[derive(Clone)]
+pub struct RelaySessions {
+    sessions: Arc<parking_lot::Mutex<HashMap<String, Session>>>,
+    /// The number of updates buffered for each session.
+    capacity: usize,
+}
+
+impl std::fmt::Debug for RelaySessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RelaySessions")
+            .field("sessions", &self.sessions.lock().len())
+            .finish()
+    }
+}
+
+impl RelaySessions {
+    /// Creates the sessions registry, each session buffers up to `capacity` updates.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sessions: Default::default(),
+            capacity,
+        }
+    }
+
+    /// Starts a new session for the relay job of the request `request_id`, returning it
+    /// along with the stream the relay job should send its updates to.
+    pub fn start(&self, request_id: String) -> (Session, CommandStream) {
+        let (tx, rx) = CommandStream::channel(self.capacity, "session");
+        let token = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        let session = Session {
+            token: token.clone(),
+            request_id,
+            state: Arc::new(tokio::sync::Mutex::new(SessionState {
+                rx,
+                pending: None,
+            })),
+            cancellation: tx.cancellation().clone(),
+        };
+        self.sessions.lock().insert(token, session.clone());
+        (session, tx)
+    }
+
+    /// Returns the session with that token, if it is not expired.
+    pub fn get(&self, token: &str) -> Option<Session> {
+        self.sessions.lock().get(token).cloned()
+    }
+
+    /// Cancels the relay job of the session with that token.
+    ///
+    /// Returns `false` if there is no such session, or if the transaction of the job
+    /// got broadcast already.
+    pub fn cancel(&self, token: &str) -> bool {
+        self.get(token)
+            .map(|s| s.cancellation.cancel())
+            .unwrap_or(false)
+    }
+
+    /// Removes the session with that token, it can't be resumed anymore.
+    pub fn remove(&self, token: &str) {
+        self.sessions.lock().remove(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::NetworkStatus;
+
+    #[tokio::test]
+    async fn resumed_session_gets_the_remaining_updates() {
+        let sessions = RelaySessions::new(50);
+        let (session, stream) = sessions.start(String::from("request"));
+        let connecting = CommandResponse::Network(NetworkStatus::Connecting);
+        let connected = CommandResponse::Network(NetworkStatus::Connected);
+        stream.send(connecting).await.unwrap();
+        stream.send(connected).await.unwrap();
+        drop(stream);
+        // the first connection is dropped, before getting any update.
+        let (mut dropped, _) = futures::channel::mpsc::unbounded::<Message>();
+        assert!(session
+            .forward(&mut dropped, MessageEncoding::Json)
+            .await
+            .is_err());
+        // the client reconnects, and resumes the session.
+        let resumed = sessions.get(session.token()).unwrap();
+        let (mut tx, rx) = futures::channel::mpsc::unbounded::<Message>();
+        resumed
+            .forward(&mut tx, MessageEncoding::Json)
+            .await
+            .unwrap();
+        drop(tx);
+        let updates = rx.collect::<Vec<_>>().await;
+        assert_eq!(updates.len(), 2);
+        // the updates are tagged with the request that started the job.
+        assert!(updates[0]
+            .to_str()
+            .unwrap()
+            .contains("\"requestId\":\"request\""));
+    }
+
+    #[test]
+    fn broadcast_jobs_can_not_be_cancelled() {
+        let sessions = RelaySessions::new(50);
+        let (cancelled, stream) = sessions.start(String::from("request"));
+        assert!(sessions.cancel(cancelled.token()));
+        assert!(!stream.cancellation().broadcast());
+        let (broadcast, stream) = sessions.start(String::from("request"));
+        assert!(stream.cancellation().broadcast());
+        assert!(!sessions.cancel(broadcast.token()));
+        assert!(!sessions.cancel("unknown"));
+    }
+}
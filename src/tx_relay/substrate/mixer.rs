@@ -1,16 +1,27 @@
-use ethereum_types::H256;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ethereum_types::{H256, U256};
 use tokio_stream::StreamExt;
 use webb::substrate::{
     protocol_substrate_runtime::api::{
-        runtime_types::webb_standalone_runtime::Element, RuntimeApi,
+        runtime_types::webb_standalone_runtime::Element, DispatchError,
+        RuntimeApi,
+    },
+    subxt::sp_core::Pair,
+    subxt::sp_runtime::traits::{BlakeTwo256, Hash},
+    subxt::{
+        self, DefaultConfig, PairSigner, TransactionProgress, TransactionStatus,
     },
-    subxt::{self, DefaultConfig, PairSigner, TransactionStatus},
 };
 
 use crate::{
     context::RelayerContext,
-    handler::{CommandResponse, CommandStream},
+    handler::{CommandResponse, CommandStream, NetworkStatus},
     handler::{MixerRelayTransaction, WithdrawStatus},
+    proposals::TypedChainId,
+    store::{cache::RelayerStore, RelayStatsStore, RelayedTx, RelayedTxStore},
+    utils,
 };
 
 /// Handler for Substrate Mixer commands
@@ -18,18 +29,51 @@ use crate::{
 /// # Arguments
 ///
 /// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store, where the relays are counted
 /// * `cmd` - The command to execute
 /// * `stream` - The stream to write the response to
 pub async fn handle_substrate_mixer_relay_tx<'a>(
     ctx: RelayerContext,
+    store: Arc<RelayerStore>,
     cmd: MixerRelayTransaction,
     stream: CommandStream,
 ) {
     use CommandResponse::*;
 
+    if ctx.is_substrate_node_paused(&cmd.chain.to_lowercase()) {
+        tracing::warn!("Node {} is paused", cmd.chain);
+        let _ = stream.send(Network(NetworkStatus::Paused)).await;
+        return;
+    }
+    if !ctx.is_leader() {
+        tracing::warn!("Standby relayer, only the leader relays");
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
+
     let root_element = Element(cmd.root);
     let nullifier_hash_element = Element(cmd.nullifier_hash);
 
+    let pair = match ctx.substrate_wallet(&cmd.chain).await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Misconfigured Network: {}", e);
+            let _ = stream
+                .send(Error(format!("Misconfigured Network: {:?}", cmd.chain)))
+                .await;
+            return;
+        }
+    };
+
+    // validate the relayer address first before connecting to the chain.
+    let relayer = subxt::sp_core::crypto::AccountId32::from(pair.public());
+    if cmd.relayer != relayer {
+        let _ = stream
+            .send(Network(NetworkStatus::InvalidRelayerAddress))
+            .await;
+        return;
+    }
+
     let requested_chain = cmd.chain.to_lowercase();
     let maybe_client = ctx
         .substrate_provider::<DefaultConfig>(&requested_chain)
@@ -44,52 +88,87 @@ pub async fn handle_substrate_mixer_relay_tx<'a>(
     };
     let api = client.to_runtime_api::<RuntimeApi<DefaultConfig, subxt::DefaultExtra<DefaultConfig>>>();
 
-    let pair = match ctx.substrate_wallet(&cmd.chain).await {
+    let signer = PairSigner::new(pair);
+
+    // the leader could have stepped down while the tx got prepared, then
+    // another instance could be relaying already.
+    if !ctx.is_leader() {
+        tracing::warn!("Stepped down to a standby, not sending the Tx");
+        let _ = stream.send(Network(NetworkStatus::Standby)).await;
+        return;
+    }
+    if !stream.cancellation().broadcast() {
+        tracing::debug!("The relay job got cancelled, not sending the Tx");
+        let _ = stream.send(Withdraw(WithdrawStatus::Cancelled)).await;
+        return;
+    }
+    let withdraw = api.tx().mixer_bn254().withdraw(
+        cmd.id,
+        cmd.proof,
+        root_element,
+        nullifier_hash_element,
+        cmd.recipient,
+        cmd.relayer,
+        cmd.fee,
+        cmd.refund,
+    );
+    // the extrinsic is signed here, so its hash is known before submitting it, in case
+    // the connection drops while watching it.
+    let extrinsic = match withdraw.create_signed(&signer, ()).await {
         Ok(v) => v,
         Err(e) => {
-            tracing::error!("Misconfigured Network: {}", e);
+            tracing::error!("Error while signing Tx: {}", e);
+            let _ = stream.send(Error(format!("{}", e))).await;
+            return;
+        }
+    };
+    let ext_hash = BlakeTwo256::hash_of(&extrinsic);
+    // the recovery of the extrinsic starts at the best block, so without it the Tx
+    // is not sent at all.
+    let from_block = match api.client.rpc().header(None).await {
+        Ok(Some(header)) => header.number,
+        Ok(None) => {
+            tracing::error!("The node has no best block");
             let _ = stream
-                .send(Error(format!("Misconfigured Network: {:?}", cmd.chain)))
+                .send(Error("The node has no best block".to_string()))
                 .await;
             return;
         }
-    };
-
-    let signer = PairSigner::new(pair);
-
-    let withdraw_tx = api
-        .tx()
-        .mixer_bn254()
-        .withdraw(
-            cmd.id,
-            cmd.proof,
-            root_element,
-            nullifier_hash_element,
-            cmd.recipient,
-            cmd.relayer,
-            cmd.fee,
-            cmd.refund,
-        )
-        .sign_and_submit_then_watch(&signer)
-        .await;
-    let mut event_stream = match withdraw_tx {
-        Ok(s) => s,
         Err(e) => {
-            tracing::error!("Error while sending Tx: {}", e);
+            tracing::error!("Error while getting the best block: {}", e);
             let _ = stream.send(Error(format!("{}", e))).await;
             return;
         }
     };
+    let mut event_stream =
+        match api.client.rpc().watch_extrinsic(extrinsic).await {
+            Ok(sub) => {
+                TransactionProgress::<DefaultConfig, DispatchError>::new(
+                    sub,
+                    &api.client,
+                    ext_hash,
+                )
+            }
+            Err(e) => {
+                tracing::error!("Error while sending Tx: {}", e);
+                let _ = stream.send(Error(format!("{}", e))).await;
+                return;
+            }
+        };
+    if let Some(chain_id) = node_chain_id(&ctx, &requested_chain) {
+        let tx_hash = H256::from_slice(ext_hash.as_bytes());
+        record_relayed_extrinsic(&store, chain_id, tx_hash, None);
+    }
 
     // Listen to the withdraw transaction, and send information back to the client
+    let mut done = false;
     loop {
         let maybe_event = event_stream.next().await;
         let event = match maybe_event {
             Some(Ok(v)) => v,
             Some(Err(e)) => {
-                tracing::error!("Error while watching Tx: {}", e);
-                let _ = stream.send(Error(format!("{}", e))).await;
-                return;
+                tracing::warn!("Error while watching Tx: {}", e);
+                break;
             }
             None => break,
         };
@@ -117,7 +196,7 @@ pub async fn handle_substrate_mixer_relay_tx<'a>(
                     info.extrinsic_hash(),
                     info.block_hash()
                 );
-                let _has_event = match info.wait_for_success().await {
+                let succeeded = match info.wait_for_success().await {
                     Ok(_) => {
                         // TODO: check if the event is actually a withdraw event
                         true
@@ -128,6 +207,15 @@ pub async fn handle_substrate_mixer_relay_tx<'a>(
                         false
                     }
                 };
+                record_relay(
+                    &ctx,
+                    &store,
+                    &requested_chain,
+                    cmd.id,
+                    (info.extrinsic_hash(), info.block_hash()),
+                    !succeeded,
+                )
+                .await;
                 let _ = stream
                     .send(Withdraw(WithdrawStatus::Finalized {
                         tx_hash: H256::from_slice(
@@ -135,14 +223,17 @@ pub async fn handle_substrate_mixer_relay_tx<'a>(
                         ),
                     }))
                     .await;
+                done = true;
             }
             TransactionStatus::Dropped => {
                 tracing::warn!("Transaction dropped from the pool");
                 let _ = stream
                     .send(Withdraw(WithdrawStatus::DroppedFromMemPool))
                     .await;
+                done = true;
             }
             TransactionStatus::Invalid => {
+                done = true;
                 let _ = stream
                     .send(Withdraw(WithdrawStatus::Errored {
                         reason: "Invalid".to_string(),
@@ -153,4 +244,230 @@ pub async fn handle_substrate_mixer_relay_tx<'a>(
             _ => continue,
         }
     }
+    if done {
+        return;
+    }
+    // the connection dropped before the final status of the extrinsic, so look it up
+    // in the finalized blocks once reconnected.
+    tracing::warn!("Lost track of Tx {:?}, recovering its status", ext_hash);
+    let tx_hash = H256::from_slice(ext_hash.as_bytes());
+    let recovered =
+        recover_extrinsic(&ctx, &requested_chain, ext_hash, from_block).await;
+    match recovered {
+        Some((block_hash, succeeded)) => {
+            tracing::debug!(
+                "Transaction {:?} finalized in block {:?}",
+                ext_hash,
+                block_hash
+            );
+            record_relay(
+                &ctx,
+                &store,
+                &requested_chain,
+                cmd.id,
+                (ext_hash, block_hash),
+                !succeeded,
+            )
+            .await;
+            if !succeeded {
+                tracing::error!("Transaction {:?} failed", ext_hash);
+                let _ = stream
+                    .send(Error(format!("The Tx {:?} failed", tx_hash)))
+                    .await;
+            }
+            let _ = stream
+                .send(Withdraw(WithdrawStatus::Finalized { tx_hash }))
+                .await;
+        }
+        None => {
+            let _ = stream
+                .send(Error(format!(
+                    "Lost track of the Tx {:?}, it did not get finalized in time",
+                    tx_hash
+                )))
+                .await;
+        }
+    }
+}
+
+/// Returns the chain id of that node, if it is configured.
+fn node_chain_id(ctx: &RelayerContext, node_name: &str) -> Option<u32> {
+    ctx.config.substrate.get(node_name)?.chain_id
+}
+
+/// Counts a finalized relay to the tree `tree_id` of that node in the relay stats, and
+/// records its outcome in the tx history, when the chain id of the node is configured.
+///
+/// Failing to record it is only logged, since the extrinsic is already finalized.
+async fn record_relay(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    node_name: &str,
+    tree_id: u32,
+    (ext_hash, block_hash): (subxt::sp_core::H256, subxt::sp_core::H256),
+    reverted: bool,
+) {
+    let chain_id = match node_chain_id(ctx, node_name) {
+        Some(v) => v,
+        None => return,
+    };
+    let finalized_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    if let Err(e) = store.record_relay(
+        (U256::from(chain_id), tree_id),
+        finalized_at,
+        reverted,
+    ) {
+        tracing::error!("Failed to count the relay: {}", e);
+    }
+    let header = async {
+        let client = ctx.substrate_provider::<DefaultConfig>(node_name).await?;
+        let header = client.rpc().header(Some(block_hash)).await?;
+        Result::<_, anyhow::Error>::Ok(header)
+    }
+    .await;
+    let block_number = match header {
+        Ok(Some(header)) => u64::from(header.number),
+        Ok(None) => {
+            tracing::error!("The block {:?} of the Tx is unknown", block_hash);
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to get the block of the Tx: {}", e);
+            return;
+        }
+    };
+    let tx_hash = H256::from_slice(ext_hash.as_bytes());
+    record_relayed_extrinsic(
+        store,
+        chain_id,
+        tx_hash,
+        Some((block_number, !reverted)),
+    );
+}
+
+/// Records the extrinsic in the tx history, so clients can query its status later, along
+/// with the block it got finalized in and whether it succeeded, once finalized.
+///
+/// Failing to record it is only logged, since the extrinsic is already submitted.
+fn record_relayed_extrinsic(
+    store: &RelayerStore,
+    chain_id: u32,
+    tx_hash: H256,
+    finalized: Option<(u64, bool)>,
+) {
+    let chain_id = TypedChainId::Substrate(chain_id);
+    // the extrinsic is recorded again once finalized, when it got sent is kept.
+    let relayed_at = match store.get_relayed_tx(chain_id, tx_hash) {
+        Ok(Some(tx)) => tx.relayed_at,
+        _ => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    };
+    let tx = RelayedTx {
+        chain_id,
+        tx_hash,
+        relayed_at,
+        block_number: finalized.map(|(block_number, _)| block_number),
+        success: finalized.map(|(_, success)| success),
+    };
+    if let Err(e) = store.insert_relayed_tx(tx) {
+        tracing::error!(%tx_hash, "Failed to record the relayed Tx: {}", e);
+    }
+}
+
+/// How long the status of an extrinsic is looked up for, after losing track of it.
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How long to wait between two lookups of the status of an extrinsic.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Looks up the extrinsic in the finalized blocks of that node, starting at `from_block`,
+/// reconnecting to the node as needed.
+///
+/// Returns the hash of the block that included the extrinsic and whether it succeeded, or
+/// `None` if it did not get finalized before the [`RECOVERY_TIMEOUT`].
+async fn recover_extrinsic(
+    ctx: &RelayerContext,
+    node_name: &str,
+    ext_hash: subxt::sp_core::H256,
+    from_block: u32,
+) -> Option<(subxt::sp_core::H256, bool)> {
+    let started = Instant::now();
+    let mut next_block = from_block;
+    loop {
+        match find_extrinsic(ctx, node_name, ext_hash, &mut next_block).await {
+            Ok(Some(found)) => return Some(found),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(
+                    "Error while recovering Tx {:?}: {}",
+                    ext_hash,
+                    e
+                )
+            }
+        }
+        if started.elapsed() > RECOVERY_TIMEOUT {
+            return None;
+        }
+        tokio::time::sleep(RECOVERY_INTERVAL).await;
+    }
+}
+
+/// Scans the finalized blocks from `next_block` for the extrinsic, `next_block` is
+/// advanced past the scanned blocks.
+///
+/// Returns the hash of the block that included the extrinsic, and whether it succeeded
+/// going by the events of that block.
+async fn find_extrinsic(
+    ctx: &RelayerContext,
+    node_name: &str,
+    ext_hash: subxt::sp_core::H256,
+    next_block: &mut u32,
+) -> anyhow::Result<Option<(subxt::sp_core::H256, bool)>> {
+    let client = ctx.substrate_provider::<DefaultConfig>(node_name).await?;
+    let finalized_head = client.rpc().finalized_head().await?;
+    let finalized = match client.rpc().header(Some(finalized_head)).await? {
+        Some(header) => header.number,
+        None => return Ok(None),
+    };
+    while *next_block <= finalized {
+        let block_hash =
+            client.rpc().block_hash(Some((*next_block).into())).await?;
+        let block = match block_hash {
+            Some(hash) => client.rpc().block(Some(hash)).await?,
+            None => None,
+        };
+        if let (Some(hash), Some(block)) = (block_hash, block) {
+            let index = block
+                .block
+                .extrinsics
+                .iter()
+                .position(|x| BlakeTwo256::hash_of(x) == ext_hash);
+            if let Some(index) = index {
+                let events = client
+                    .rpc()
+                    .storage(&utils::system_events_key(), Some(hash))
+                    .await?
+                    .map(|data| {
+                        client.events_decoder().decode_events(&mut &data.0[..])
+                    })
+                    .transpose()?
+                    .unwrap_or_default();
+                let succeeded =
+                    utils::extrinsic_succeeded(&events, index as u32)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "no outcome for the extrinsic in block {:?}",
+                                hash
+                            )
+                        })?;
+                return Ok(Some((hash, succeeded)));
+            }
+        }
+        *next_block += 1;
+    }
+    Ok(None)
 }
@@ -0,0 +1,370 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::config::WebbRelayerConfig;
+use crate::handler::Command;
+#[doc(hidden)]
+pub use crate::handler::ValidationError;
+#[cfg(feature = "evm")]
+use crate::handler::{
+    AnchorRelayTransaction, EvmCommand, TornadoRelayTransaction,
+};
+#[cfg(feature = "substrate")]
+use crate::handler::{MixerRelayTransaction, SubstrateCommand};
+
+/// The maximum size of a command message, in bytes.
+pub const MAX_COMMAND_SIZE: usize = 16 * 1024;
+/// The maximum size of a zero-knowledge proof, in bytes.
+pub const MAX_PROOF_SIZE: usize = 1024;
+/// The maximum number of merkle roots of an anchor withdraw.
+pub const MAX_ROOTS: usize = 32;
+/// The maximum length of the names (chains and identities), in bytes.
+pub const MAX_NAME_SIZE: usize = 64;
+
+impl ValidationError {
+    fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Validates the sizes of a command message, before deserializing it.
+pub fn validate_message(message: &[u8]) -> Result<(), ValidationError> {
+    if message.len() > MAX_COMMAND_SIZE {
+        return Err(ValidationError::new(
+            "command",
+            format!(
+                "the command is {} bytes, the maximum is {}",
+                message.len(),
+                MAX_COMMAND_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves the `chain` of the commands, sent by name, alias or chain id, to the
+/// canonical name of the chain, as wallets usually know the chain id, not the operator's
+/// naming, see [`WebbRelayerConfig::canonical_evm_chain`]. The Substrate nodes are
+/// resolved by their `chain-id`, for the mixer relays and the tx status queries.
+///
+/// The unknown chains are left for [`validate_command`] to reject.
+pub fn resolve_chain(config: &WebbRelayerConfig, cmd: &mut Command) {
+    // the chain of the command, and whether it is an EVM chain, a Substrate node or both.
+    let (chain, evm, substrate) = match cmd {
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::TornadoRelayTx(cmd)) => {
+            (&mut cmd.chain, true, false)
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::AnchorRelayTx(cmd)) => {
+            (&mut cmd.chain, true, false)
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::GetLeaves { chain, .. }) => {
+            (chain, true, false)
+        }
+        #[cfg(feature = "substrate")]
+        Command::Substrate(SubstrateCommand::MixerRelayTx(cmd)) => {
+            (&mut cmd.chain, false, true)
+        }
+        Command::TxStatus { chain, .. } => (chain, true, true),
+        Command::WatchCommitment { chain, .. } => (chain, true, false),
+        _ => return,
+    };
+    let mut resolved = None;
+    if evm {
+        resolved = config.canonical_evm_chain(chain);
+    }
+    #[cfg(feature = "substrate")]
+    if resolved.is_none() && substrate {
+        resolved = config.canonical_substrate_chain(chain);
+    }
+    if let Some(name) = resolved {
+        if name != chain.as_str() {
+            tracing::trace!(%chain, %name, "Resolved the chain of the command");
+            *chain = name.to_owned();
+        }
+    }
+}
+
+/// Runs the sanity checks of a relay command, that do not need any RPC call.
+///
+/// The relayer address is checked by the relay handlers, once the identity is resolved.
+pub fn validate_command(
+    config: &WebbRelayerConfig,
+    cmd: &Command,
+) -> Result<(), ValidationError> {
+    match cmd {
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::TornadoRelayTx(cmd)) => {
+            validate_tornado(config, cmd)
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::AnchorRelayTx(cmd)) => {
+            validate_anchor(config, cmd)
+        }
+        #[cfg(feature = "evm")]
+        Command::Evm(EvmCommand::GetLeaves { chain, .. }) => {
+            validate_evm_chain(config, chain)
+        }
+        #[cfg(feature = "substrate")]
+        Command::Substrate(SubstrateCommand::MixerRelayTx(cmd)) => {
+            validate_mixer(config, cmd)
+        }
+        Command::TxStatus { chain, .. } => validate_tx_chain(config, chain),
+        Command::WatchCommitment { chain, .. } => {
+            validate_evm_chain(config, chain)
+        }
+        Command::Ping()
+        | Command::Resume { .. }
+        | Command::CancelJob { .. } => Ok(()),
+    }
+}
+
+#[cfg(feature = "evm")]
+fn validate_tornado(
+    config: &WebbRelayerConfig,
+    cmd: &TornadoRelayTransaction,
+) -> Result<(), ValidationError> {
+    validate_evm_chain(config, &cmd.chain)?;
+    validate_identity(config, cmd.identity.as_deref())?;
+    validate_proof(cmd.proof.as_ref())
+}
+
+#[cfg(feature = "evm")]
+fn validate_anchor(
+    config: &WebbRelayerConfig,
+    cmd: &AnchorRelayTransaction,
+) -> Result<(), ValidationError> {
+    validate_evm_chain(config, &cmd.chain)?;
+    validate_identity(config, cmd.identity.as_deref())?;
+    validate_proof(cmd.proof.as_ref())?;
+    let roots: &[u8] = cmd.roots.as_ref();
+    if roots.is_empty() || !roots.len().is_multiple_of(32) {
+        return Err(ValidationError::new(
+            "roots",
+            "the roots should be a non empty list of 32 bytes roots",
+        ));
+    }
+    if roots.len() / 32 > MAX_ROOTS {
+        return Err(ValidationError::new(
+            "roots",
+            format!("there are more than {} roots", MAX_ROOTS),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "substrate")]
+fn validate_mixer(
+    config: &WebbRelayerConfig,
+    cmd: &MixerRelayTransaction,
+) -> Result<(), ValidationError> {
+    validate_name("chain", &cmd.chain)?;
+    if !config.substrate.contains_key(&cmd.chain.to_lowercase()) {
+        return Err(ValidationError::new(
+            "chain",
+            format!("chain {} is not configured", cmd.chain),
+        ));
+    }
+    validate_proof(&cmd.proof)
+}
+
+fn validate_evm_chain(
+    config: &WebbRelayerConfig,
+    chain: &str,
+) -> Result<(), ValidationError> {
+    validate_name("chain", chain)?;
+    if !config.evm.contains_key(&chain.to_lowercase()) {
+        return Err(ValidationError::new(
+            "chain",
+            format!("chain {} is not configured", chain),
+        ));
+    }
+    Ok(())
+}
+
+/// Validates the chain of a tx status query, an EVM chain or an enabled Substrate node.
+fn validate_tx_chain(
+    config: &WebbRelayerConfig,
+    chain: &str,
+) -> Result<(), ValidationError> {
+    validate_name("chain", chain)?;
+    #[cfg(feature = "substrate")]
+    if config
+        .substrate
+        .get(&chain.to_lowercase())
+        .map_or(false, |node| node.enabled)
+    {
+        return Ok(());
+    }
+    validate_evm_chain(config, chain)
+}
+
+fn validate_identity(
+    config: &WebbRelayerConfig,
+    identity: Option<&str>,
+) -> Result<(), ValidationError> {
+    let identity = match identity {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    validate_name("identity", identity)?;
+    if !config.identities.contains_key(identity) {
+        return Err(ValidationError::new(
+            "identity",
+            format!("identity {} is not configured", identity),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_proof(proof: &[u8]) -> Result<(), ValidationError> {
+    if proof.is_empty() {
+        return Err(ValidationError::new("proof", "the proof is empty"));
+    }
+    if proof.len() > MAX_PROOF_SIZE {
+        return Err(ValidationError::new(
+            "proof",
+            format!(
+                "the proof is {} bytes, the maximum is {}",
+                proof.len(),
+                MAX_PROOF_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_name(
+    field: &'static str,
+    name: &str,
+) -> Result<(), ValidationError> {
+    if name.is_empty() || name.len() > MAX_NAME_SIZE {
+        return Err(ValidationError::new(
+            field,
+            format!("should be between 1 and {} bytes", MAX_NAME_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_proofs_are_rejected() {
+        assert!(validate_proof(&[1; 256]).is_ok());
+        let err = validate_proof(&[1; MAX_PROOF_SIZE + 1]).unwrap_err();
+        assert_eq!(err.field, "proof");
+        assert!(validate_proof(&[]).is_err());
+    }
+
+    #[test]
+    fn chain_ids_resolve_to_the_configured_chains() {
+        let mut config = WebbRelayerConfig::default();
+        let hermes = serde_json::from_value(serde_json::json!({
+            "http-endpoint": "http://localhost:5001",
+            "ws-endpoint": "ws://localhost:5001",
+            "chain-id": 5001,
+            "private-key": format!("0x{}", "ab".repeat(32)),
+        }))
+        .unwrap();
+        config.evm.insert("hermes".into(), hermes);
+        config
+            .evm_chain_names
+            .insert("5001".into(), "hermes".into());
+        let tx_status = |chain: &str| Command::TxStatus {
+            chain: chain.into(),
+            tx_hash: Default::default(),
+        };
+        let resolved_chain = |chain: &str| {
+            let mut cmd = tx_status(chain);
+            resolve_chain(&config, &mut cmd);
+            match cmd {
+                Command::TxStatus { chain, .. } => chain,
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(resolved_chain("5001"), "hermes");
+        assert_eq!(resolved_chain("Hermes"), "hermes");
+        // the unknown chain ids are left for the validation to reject.
+        assert_eq!(resolved_chain("5002"), "5002");
+        assert!(validate_command(&config, &tx_status("5002")).is_err());
+    }
+
+    #[test]
+    fn unknown_chains_are_rejected() {
+        let config = WebbRelayerConfig::default();
+        let err = validate_evm_chain(&config, "ropsten").unwrap_err();
+        assert_eq!(err.field, "chain");
+        let name = "a".repeat(MAX_NAME_SIZE + 1);
+        assert!(validate_evm_chain(&config, &name).is_err());
+    }
+
+    #[cfg(feature = "substrate")]
+    #[test]
+    fn chain_ids_resolve_to_the_configured_substrate_nodes() {
+        let mut config = WebbRelayerConfig::default();
+        let node = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "http-endpoint": "http://localhost:9933",
+            "ws-endpoint": "ws://localhost:9944",
+            "runtime": "WebbProtocol",
+            "suri": "//Alice",
+            "chain-id": 1080,
+        }))
+        .unwrap();
+        config.substrate.insert("webb".into(), node);
+        assert_eq!(config.canonical_substrate_chain("1080"), Some("webb"));
+        assert_eq!(config.canonical_substrate_chain("Webb"), Some("webb"));
+        assert_eq!(config.canonical_substrate_chain("1081"), None);
+        let mut cmd = Command::TxStatus {
+            chain: "1080".into(),
+            tx_hash: Default::default(),
+        };
+        resolve_chain(&config, &mut cmd);
+        assert!(
+            matches!(cmd, Command::TxStatus { chain, .. } if chain == "webb")
+        );
+    }
+
+    #[cfg(feature = "substrate")]
+    #[test]
+    fn tx_status_of_enabled_substrate_nodes_is_accepted() {
+        let mut config = WebbRelayerConfig::default();
+        let mut node: crate::config::SubstrateConfig =
+            serde_json::from_value(serde_json::json!({
+                "http-endpoint": "http://localhost:9933",
+                "ws-endpoint": "ws://localhost:9944",
+                "runtime": "WebbProtocol",
+                "suri": "//Alice",
+                "chain-id": 1080,
+            }))
+            .unwrap();
+        config.substrate.insert("webb".into(), node.clone());
+        let tx_status = Command::TxStatus {
+            chain: "webb".into(),
+            tx_hash: Default::default(),
+        };
+        assert!(validate_command(&config, &tx_status).is_err());
+        node.enabled = true;
+        config.substrate.insert("webb".into(), node);
+        assert!(validate_command(&config, &tx_status).is_ok());
+    }
+}
@@ -14,8 +14,58 @@
 //
 use std::fmt;
 
+#[cfg(feature = "substrate")]
 use webb::substrate::subxt;
-use webb::substrate::subxt::sp_core::storage::StorageChangeSet;
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::sp_core::storage::{StorageChangeSet, StorageKey};
+#[cfg(feature = "substrate")]
+use webb::substrate::subxt::sp_core::twox_128;
+#[cfg(feature = "substrate")]
+use webb::substrate::{dkg_runtime, protocol_substrate_runtime};
+
+#[cfg(feature = "substrate")]
+use crate::config::SubstrateRuntime;
+
+/// The runtime of a Substrate node got upgraded, so the metadata of the clients connected
+/// to it is stale, and they should be re-created before submitting any extrinsic.
+#[cfg(feature = "substrate")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the runtime got upgraded from spec version {from} to {to}")]
+pub struct RuntimeUpgraded {
+    /// The spec version the client got created with.
+    pub from: u32,
+    /// The spec version the node is running now.
+    pub to: u32,
+}
+
+/// Returns the spec version of the runtime the node is running.
+#[cfg(feature = "substrate")]
+pub async fn spec_version<C: subxt::Config>(
+    client: &subxt::Client<C>,
+) -> anyhow::Result<u32> {
+    let version = client.rpc().runtime_version(None).await?;
+    Ok(version.spec_version)
+}
+
+/// Checks that the node is still running the runtime of that spec version.
+///
+/// Returns a [`RuntimeUpgraded`] error otherwise.
+#[cfg(feature = "substrate")]
+pub async fn ensure_spec_version<C: subxt::Config>(
+    client: &subxt::Client<C>,
+    expected: u32,
+) -> anyhow::Result<()> {
+    let current = spec_version(client).await?;
+    if current != expected {
+        return Err(RuntimeUpgraded {
+            from: expected,
+            to: current,
+        }
+        .into());
+    }
+    Ok(())
+}
+
 /// Represents a clickable link containing text and url
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct ClickableLink<'a> {
@@ -39,8 +89,110 @@ impl fmt::Display for ClickableLink<'_> {
         )
     }
 }
+
+/// Returns the keccak256 hash of the bytes.
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    use tiny_keccak::{Hasher, Keccak};
+    let mut output = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(bytes);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// An event that could be decoded from the raw events of a block.
+///
+/// Every [`subxt::Event`] is one, enums over several events of a pallet could implement
+/// it too, so a single watcher could follow all of them.
+#[cfg(feature = "substrate")]
+pub trait DecodeEvent: Sized {
+    /// Decodes the event, returns `None` if the raw event is another one.
+    fn decode_event(raw: &subxt::RawEvent) -> Option<Self>;
+}
+
+#[cfg(feature = "substrate")]
+impl<E: subxt::Event> DecodeEvent for E {
+    fn decode_event(raw: &subxt::RawEvent) -> Option<Self> {
+        raw.as_event::<E>().ok().flatten()
+    }
+}
+
+/// The storage key of the events of the `System` pallet, where all the events of a
+/// block are stored.
+#[cfg(feature = "substrate")]
+pub fn system_events_key() -> StorageKey {
+    let mut storage_key = twox_128(b"System").to_vec();
+    storage_key.extend(twox_128(b"Events").to_vec());
+    StorageKey(storage_key)
+}
+
+/// Returns the chain id of a Substrate node, as read from the pallets of its runtime.
+///
+/// The watchers of the node key their history with it.
+#[cfg(feature = "substrate")]
+pub fn substrate_chain_id(
+    runtime: &SubstrateRuntime,
+    client: &subxt::Client<subxt::DefaultConfig>,
+) -> anyhow::Result<ethereum_types::U256> {
+    use dkg_runtime::api::runtime_types::webb_proposals::header::TypedChainId;
+    let chain_id = match runtime {
+        SubstrateRuntime::Dkg => {
+            let api = client
+                .clone()
+                .to_runtime_api::<dkg_runtime::api::RuntimeApi<
+                    subxt::DefaultConfig,
+                    subxt::DefaultExtra<subxt::DefaultConfig>,
+                >>();
+            match api.constants().dkg_proposals().chain_identifier()? {
+                TypedChainId::None => 0,
+                TypedChainId::Evm(id)
+                | TypedChainId::Substrate(id)
+                | TypedChainId::PolkadotParachain(id)
+                | TypedChainId::KusamaParachain(id)
+                | TypedChainId::RococoParachain(id)
+                | TypedChainId::Cosmos(id)
+                | TypedChainId::Solana(id) => id,
+            }
+        }
+        SubstrateRuntime::WebbProtocol => {
+            let api = client
+                .clone()
+                .to_runtime_api::<protocol_substrate_runtime::api::RuntimeApi<
+                    subxt::DefaultConfig,
+                    subxt::DefaultExtra<subxt::DefaultConfig>,
+                >>();
+            api.constants().bridge().chain_identifier()?
+        }
+    };
+    Ok(chain_id.into())
+}
+
+/// Whether the extrinsic at `index` in its block succeeded, going by the
+/// `ExtrinsicSuccess` or `ExtrinsicFailed` event of the `System` pallet.
+///
+/// Returns `None` if neither event got emitted for that extrinsic.
+#[cfg(feature = "substrate")]
+pub fn extrinsic_succeeded(
+    events: &[(subxt::Phase, subxt::RawEvent)],
+    index: u32,
+) -> Option<bool> {
+    events
+        .iter()
+        .filter(|(phase, _)| {
+            matches!(phase, subxt::Phase::ApplyExtrinsic(i) if *i == index)
+        })
+        .find_map(|(_, raw)| {
+            match (raw.pallet.as_str(), raw.variant.as_str()) {
+                ("System", "ExtrinsicSuccess") => Some(true),
+                ("System", "ExtrinsicFailed") => Some(false),
+                _ => None,
+            }
+        })
+}
+
 /// change_set_to_events converts a StorageChangeSet to a Vec of events.
-pub fn change_set_to_events<C: subxt::Config, E: subxt::Event>(
+#[cfg(feature = "substrate")]
+pub fn change_set_to_events<C: subxt::Config, E: DecodeEvent>(
     change_set: StorageChangeSet<C::Hash>,
     decoder: &subxt::EventsDecoder<C>,
 ) -> Vec<(C::Hash, E)> {
@@ -72,9 +224,51 @@ pub fn change_set_to_events<C: subxt::Config, E: subxt::Event>(
                 None
             }
         })
-        .filter_map(|(block, raw)| match raw.as_event::<E>() {
-            Ok(event) => event.map(|event| (block, event)),
-            Err(_) => None,
+        .filter_map(|(block, raw)| {
+            E::decode_event(&raw).map(|event| (block, event))
         })
         .collect()
 }
+
+#[cfg(all(test, feature = "substrate"))]
+mod tests {
+    use super::*;
+    use webb::substrate::subxt::sp_core::Bytes;
+
+    fn event(
+        index: u32,
+        pallet: &str,
+        variant: &str,
+    ) -> (subxt::Phase, subxt::RawEvent) {
+        let raw = subxt::RawEvent {
+            pallet: pallet.to_string(),
+            pallet_index: 0,
+            variant: variant.to_string(),
+            variant_index: 0,
+            data: Bytes(Vec::new()),
+        };
+        (subxt::Phase::ApplyExtrinsic(index), raw)
+    }
+
+    #[test]
+    fn outcome_of_the_extrinsic_is_read_from_its_events() {
+        let events = vec![
+            event(0, "System", "ExtrinsicSuccess"),
+            event(1, "MixerBn254", "Withdraw"),
+            event(1, "System", "ExtrinsicFailed"),
+            event(2, "System", "ExtrinsicSuccess"),
+        ];
+        assert_eq!(extrinsic_succeeded(&events, 0), Some(true));
+        assert_eq!(extrinsic_succeeded(&events, 1), Some(false));
+        assert_eq!(extrinsic_succeeded(&events, 2), Some(true));
+        // no events for that extrinsic.
+        assert_eq!(extrinsic_succeeded(&events, 3), None);
+    }
+
+    #[test]
+    fn events_of_other_phases_are_ignored() {
+        let mut events = vec![event(0, "System", "ExtrinsicFailed")];
+        events[0].0 = subxt::Phase::Finalization;
+        assert_eq!(extrinsic_succeeded(&events, 0), None);
+    }
+}
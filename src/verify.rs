@@ -0,0 +1,523 @@
+// Copyright 2022 Webb Technologies Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! # Relayer Verify Module 🕸️
+//!
+//! A module for checking the cached leaves against the on-chain merkle roots.
+//!
+//! ## Overview
+//!
+//! The merkle root is recomputed from the cached leaves, hashing every level of the tree
+//! using the contract's own `hashLeftRight`, so the relayer does not need to know which
+//! hash function the contract uses. The computed root is then compared with the latest
+//! root of the contract, or with its root history if the relayer did not cache all the
+//! leaves yet.
+//!
+//! If the roots do not match, the cached leaves are checked against the commitments of
+//! the contract, to find the first leaf that got corrupted.
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future;
+use serde::Serialize;
+use utoipa::ToSchema;
+use webb::evm::contract::protocol_solidity::FixedDepositAnchorContract;
+use webb::evm::contract::tornado::TornadoContract;
+use webb::evm::ethers::providers::Middleware;
+use webb::evm::ethers::types;
+
+use crate::config::{Contract, RootCheckpointsConfig, WebbRelayerConfig};
+use crate::context::RelayerContext;
+use crate::metrics::{self, Labels, Metric};
+use crate::store::cache::RelayerStore;
+use crate::store::{LeafCacheStore, RootCheckpoint, RootCheckpointStore};
+
+type HttpProvider = crate::rate_limit::HttpProvider;
+
+/// How many contract calls are sent at once.
+const CONCURRENT_CALLS: usize = 32;
+
+/// The result of checking the cached leaves of a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RootsStatus {
+    /// The cached leaves match the latest root of the contract.
+    Consistent,
+    /// The relayer did not cache all the leaves yet, and the cached ones
+    /// do not show any sign of corruption.
+    Behind,
+    /// The cached leaves do not match the leaves of the contract.
+    Diverged,
+}
+
+/// A report about the cached leaves of a contract.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RootsReport {
+    /// The chain id of the contract.
+    #[schema(value_type = String)]
+    pub chain_id: types::U256,
+    /// The address of the contract.
+    #[schema(value_type = String)]
+    pub contract: types::Address,
+    /// The block number where the contract got checked at.
+    #[schema(value_type = String)]
+    pub block_number: types::U64,
+    /// The result of the check.
+    pub status: RootsStatus,
+    /// The number of the cached leaves.
+    pub cached_leaves: u32,
+    /// The number of the leaves inserted in the contract.
+    pub contract_leaves: u32,
+    /// The root computed from the cached leaves.
+    #[schema(value_type = String)]
+    pub computed_root: types::H256,
+    /// The latest root of the contract.
+    #[schema(value_type = String)]
+    pub latest_root: types::H256,
+    /// The index of the first cached leaf that does not match the contract, if found.
+    pub first_divergent_leaf: Option<u32>,
+}
+
+/// The contracts that have a merkle tree.
+enum MerkleTreeContract {
+    Anchor(FixedDepositAnchorContract<HttpProvider>),
+    Tornado(TornadoContract<HttpProvider>),
+}
+
+/// Calls the same method on any of the [`MerkleTreeContract`]s, at the given block.
+macro_rules! call {
+    ($tree:expr, $block:expr, $c:ident => $call:expr) => {
+        match $tree {
+            MerkleTreeContract::Anchor($c) => {
+                $call.block($block).call().await?
+            }
+            MerkleTreeContract::Tornado($c) => {
+                $call.block($block).call().await?
+            }
+        }
+    };
+}
+
+/// A merkle tree contract, where all the calls are made at the same block.
+struct MerkleTree {
+    contract: MerkleTreeContract,
+    block: types::U64,
+}
+
+impl MerkleTree {
+    fn address(&self) -> types::Address {
+        match &self.contract {
+            MerkleTreeContract::Anchor(c) => c.address(),
+            MerkleTreeContract::Tornado(c) => c.address(),
+        }
+    }
+
+    async fn levels(&self) -> anyhow::Result<u32> {
+        Ok(call!(&self.contract, self.block, c => c.levels()))
+    }
+
+    async fn next_index(&self) -> anyhow::Result<u32> {
+        Ok(call!(&self.contract, self.block, c => c.next_index()))
+    }
+
+    async fn hasher(&self) -> anyhow::Result<types::Address> {
+        Ok(call!(&self.contract, self.block, c => c.hasher()))
+    }
+
+    async fn last_root(&self) -> anyhow::Result<[u8; 32]> {
+        Ok(call!(&self.contract, self.block, c => c.get_last_root()))
+    }
+
+    async fn is_known_root(&self, root: [u8; 32]) -> anyhow::Result<bool> {
+        Ok(call!(&self.contract, self.block, c => c.is_known_root(root)))
+    }
+
+    async fn is_commitment(&self, leaf: [u8; 32]) -> anyhow::Result<bool> {
+        Ok(call!(&self.contract, self.block, c => c.commitments(leaf)))
+    }
+
+    async fn zero(&self, level: u32) -> anyhow::Result<[u8; 32]> {
+        Ok(call!(&self.contract, self.block, c => c.zeros(level.into())))
+    }
+
+    async fn hash(
+        &self,
+        hasher: types::Address,
+        left: [u8; 32],
+        right: [u8; 32],
+    ) -> anyhow::Result<[u8; 32]> {
+        Ok(call!(
+            &self.contract,
+            self.block,
+            c => c.hash_left_right(hasher, left, right)
+        ))
+    }
+}
+
+/// Finds the chain name and the configuration of a contract by its chain id and address.
+pub fn find_contract(
+    config: &WebbRelayerConfig,
+    chain_id: types::U256,
+    address: types::Address,
+) -> Option<(&str, &Contract)> {
+    config
+        .evm
+        .iter()
+        .filter(|(_, chain)| types::U256::from(chain.chain_id) == chain_id)
+        .find_map(|(name, chain)| {
+            chain
+                .contracts
+                .iter()
+                .find(|c| match c {
+                    Contract::Anchor(c) => c.common.address == address,
+                    Contract::Tornado(c) => c.common.address == address,
+                    _ => false,
+                })
+                .map(|c| (name.as_str(), c))
+        })
+}
+
+/// Checks the cached leaves of the contract against its on-chain roots.
+///
+/// Returns an error if the contract does not have a merkle tree.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - [Sled](https://sled.rs)-based database store
+/// * `chain_name` - The name of the chain the contract is deployed on
+/// * `contract` - The configuration of the contract
+#[tracing::instrument(skip_all, fields(chain = %chain_name))]
+pub async fn verify_roots(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    chain_name: &str,
+    contract: &Contract,
+) -> anyhow::Result<RootsReport> {
+    let provider = Arc::new(ctx.evm_provider(chain_name).await?);
+    let chain_id = provider.get_chainid().await?;
+    let block = provider.get_block_number().await?;
+    let contract = match contract {
+        Contract::Anchor(c) => MerkleTreeContract::Anchor(
+            FixedDepositAnchorContract::new(c.common.address, provider),
+        ),
+        Contract::Tornado(c) => MerkleTreeContract::Tornado(
+            TornadoContract::new(c.common.address, provider),
+        ),
+        _ => anyhow::bail!("Only anchors and tornado contracts have leaves"),
+    };
+    let tree = MerkleTree { contract, block };
+    let address = tree.address();
+    let leaves = store.get_leaves_with_index((chain_id, address))?;
+    let cached_leaves = leaves.len() as u32;
+    let contract_leaves = tree.next_index().await?;
+    let latest_root = tree.last_root().await?;
+    // the leaves should be contiguous, starting from the first one.
+    let gap = leaves
+        .iter()
+        .zip(0u32..)
+        .find(|((index, _), expected)| index != expected)
+        .map(|(_, expected)| expected);
+    let mut first_divergent_leaf = gap.or_else(|| {
+        (cached_leaves > contract_leaves).then_some(contract_leaves)
+    });
+    let count = first_divergent_leaf.unwrap_or(cached_leaves) as usize;
+    let leaves: Vec<_> = leaves[..count]
+        .iter()
+        .map(|(_, v)| v.to_fixed_bytes())
+        .collect();
+
+    let levels = tree.levels().await?;
+    let hasher = tree.hasher().await?;
+    let zeros =
+        future::try_join_all((0..=levels).map(|i| tree.zero(i))).await?;
+    let computed_root =
+        compute_root(leaves.clone(), &zeros, |l, r| tree.hash(hasher, l, r))
+            .await?;
+
+    let status = if first_divergent_leaf.is_some() {
+        RootsStatus::Diverged
+    } else if count == contract_leaves as usize && computed_root == latest_root
+    {
+        RootsStatus::Consistent
+    } else if tree.is_known_root(computed_root).await? {
+        RootsStatus::Behind
+    } else {
+        first_divergent_leaf = first_unknown_commitment(&tree, &leaves).await?;
+        // if we are too far behind, the root could be out of the root history.
+        if first_divergent_leaf.is_none() && count < contract_leaves as usize {
+            RootsStatus::Behind
+        } else {
+            RootsStatus::Diverged
+        }
+    };
+    tracing::event!(
+        target: crate::probe::TARGET,
+        tracing::Level::DEBUG,
+        kind = %crate::probe::Kind::VerifyRoots,
+        chain_id = %chain_id,
+        contract = %address,
+        status = ?status,
+        first_divergent_leaf = ?first_divergent_leaf,
+    );
+    Ok(RootsReport {
+        chain_id,
+        contract: address,
+        block_number: block,
+        status,
+        cached_leaves,
+        contract_leaves,
+        computed_root: computed_root.into(),
+        latest_root: latest_root.into(),
+        first_divergent_leaf,
+    })
+}
+
+/// Computes the merkle root of the leaves, where `zeros[i]` is the root of an empty
+/// subtree at level `i`, and `zeros[levels]` is the root of the empty tree.
+async fn compute_root<H, F>(
+    mut nodes: Vec<[u8; 32]>,
+    zeros: &[[u8; 32]],
+    hash: H,
+) -> anyhow::Result<[u8; 32]>
+where
+    H: Fn([u8; 32], [u8; 32]) -> F,
+    F: Future<Output = anyhow::Result<[u8; 32]>>,
+{
+    let levels = zeros.len() - 1;
+    anyhow::ensure!(
+        nodes.len() <= 1 << levels,
+        "Too many leaves for a tree of {} levels",
+        levels
+    );
+    for zero in &zeros[..levels] {
+        if nodes.is_empty() {
+            break;
+        }
+        if nodes.len() % 2 == 1 {
+            nodes.push(*zero);
+        }
+        let mut parents = Vec::with_capacity(nodes.len() / 2);
+        for chunk in nodes.chunks(CONCURRENT_CALLS * 2) {
+            let calls = chunk.chunks(2).map(|pair| hash(pair[0], pair[1]));
+            parents.extend(future::try_join_all(calls).await?);
+        }
+        nodes = parents;
+    }
+    Ok(nodes.first().copied().unwrap_or(zeros[levels]))
+}
+
+/// Returns the index of the first leaf that is not a commitment of the contract.
+async fn first_unknown_commitment(
+    tree: &MerkleTree,
+    leaves: &[[u8; 32]],
+) -> anyhow::Result<Option<u32>> {
+    for (i, chunk) in leaves.chunks(CONCURRENT_CALLS).enumerate() {
+        let calls = chunk.iter().map(|leaf| tree.is_commitment(*leaf));
+        let known = future::try_join_all(calls).await?;
+        if let Some(pos) = known.iter().position(|known| !known) {
+            return Ok(Some((i * CONCURRENT_CALLS + pos) as u32));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns true if the cached leaves of a contract diverged from it, given the status of
+/// the latest check and the previous checkpoint.
+///
+/// A relayer that stays behind the contract is diverged too, once its cache did not even
+/// catch up with the leaves the contract had at the previous checkpoint, as the watcher
+/// must have missed some events.
+fn is_divergent(
+    status: RootsStatus,
+    cached_leaves: u32,
+    previous: Option<&RootCheckpoint>,
+) -> bool {
+    match status {
+        RootsStatus::Consistent => false,
+        RootsStatus::Diverged => true,
+        RootsStatus::Behind => {
+            matches!(previous, Some(p) if cached_leaves < p.contract_leaves)
+        }
+    }
+}
+
+/// The payload POSTed to the webhook when the roots of a contract diverge.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DivergenceAlert<'a> {
+    chain_id: types::U256,
+    contract: types::Address,
+    checkpoint: &'a RootCheckpoint,
+    previous: &'a RootCheckpoint,
+}
+
+/// Checkpoints the roots of a contract, recording the on-chain root along with the root
+/// of the cached leaves.
+///
+/// A divergence lasting for more than one checkpoint sets [`Metric::RootsDiverged`],
+/// and is sent to the webhook once, if configured. The checkpoint is only marked as
+/// alerted once the webhook accepted the alert, so a failed one is sent again.
+///
+/// # Arguments
+///
+/// * `ctx` - RelayContext reference that holds the configuration
+/// * `store` - The store of the cached leaves and of the checkpoints
+/// * `http` - The client used for sending the alerts
+/// * `config` - The configuration of the root checkpoints
+/// * `chain_name` - The name of the chain of the contract
+/// * `contract` - The configuration of the contract
+pub async fn checkpoint_roots(
+    ctx: &RelayerContext,
+    store: &RelayerStore,
+    http: &reqwest::Client,
+    config: &RootCheckpointsConfig,
+    chain_name: &str,
+    contract: &Contract,
+) -> anyhow::Result<RootCheckpoint> {
+    let report = verify_roots(ctx, store, chain_name, contract).await?;
+    let key = (report.chain_id, report.contract);
+    let previous = store.root_checkpoints(key)?.pop();
+    let diverged =
+        is_divergent(report.status, report.cached_leaves, previous.as_ref());
+    let lasting = diverged && matches!(previous, Some(ref p) if p.diverged);
+    let mut checkpoint = RootCheckpoint {
+        block_number: report.block_number,
+        onchain_root: report.latest_root,
+        local_root: report.computed_root,
+        cached_leaves: report.cached_leaves,
+        contract_leaves: report.contract_leaves,
+        diverged,
+        alerted: false,
+        checked_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+    };
+    metrics::set(
+        Metric::RootsDiverged,
+        Labels::new(
+            report.chain_id,
+            format!("{:#x}", report.contract),
+            crate::probe::Kind::RootCheckpoints,
+        ),
+        if lasting { 1.0 } else { 0.0 },
+    );
+    checkpoint.alerted = match previous {
+        // a divergence is alerted once, until the alert gets through.
+        Some(ref previous) if lasting && previous.alerted => true,
+        Some(ref previous) if lasting => {
+            tracing::error!(
+                "The cached leaves of {} on chain {} diverged from the contract at block {}",
+                report.contract,
+                report.chain_id,
+                report.block_number,
+            );
+            match config.webhook {
+                Some(ref webhook) => {
+                    let alert = DivergenceAlert {
+                        chain_id: report.chain_id,
+                        contract: report.contract,
+                        checkpoint: &checkpoint,
+                        previous,
+                    };
+                    let sent = http
+                        .post(webhook.clone())
+                        .json(&alert)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status());
+                    match sent {
+                        Ok(_) => true,
+                        Err(e) => {
+                            tracing::error!(
+                                "Failed to send the divergence alert: {}",
+                                e
+                            );
+                            false
+                        }
+                    }
+                }
+                None => true,
+            }
+        }
+        _ => false,
+    };
+    store.insert_root_checkpoint(key, checkpoint.clone())?;
+    tracing::event!(
+        target: crate::probe::TARGET,
+        tracing::Level::DEBUG,
+        kind = %crate::probe::Kind::RootCheckpoints,
+        chain_id = %report.chain_id,
+        contract = %report.contract,
+        diverged = %diverged,
+        alerted = %checkpoint.alerted,
+    );
+    Ok(checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A toy hash function, that is good enough for checking the shape of the tree.
+    async fn hash(l: [u8; 32], r: [u8; 32]) -> anyhow::Result<[u8; 32]> {
+        let mut out = [0u8; 32];
+        for i in 0..32 {
+            out[i] = l[i].wrapping_mul(3).wrapping_add(r[i]).wrapping_add(1);
+        }
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn compute_root_pads_with_zeros() {
+        let zero = [0u8; 32];
+        let z1 = hash(zero, zero).await.unwrap();
+        let z2 = hash(z1, z1).await.unwrap();
+        let zeros = [zero, z1, z2];
+        // empty tree.
+        let root = compute_root(vec![], &zeros, hash).await.unwrap();
+        assert_eq!(root, z2);
+        // three leaves, the last one is paired with a zero.
+        let leaves = vec![[1u8; 32], [2u8; 32], [3u8; 32]];
+        let root = compute_root(leaves, &zeros, hash).await.unwrap();
+        let left = hash([1u8; 32], [2u8; 32]).await.unwrap();
+        let right = hash([3u8; 32], zero).await.unwrap();
+        assert_eq!(root, hash(left, right).await.unwrap());
+        // a tree of two levels can't hold five leaves.
+        let leaves = vec![[1u8; 32]; 5];
+        assert!(compute_root(leaves, &zeros, hash).await.is_err());
+    }
+
+    #[test]
+    fn staying_behind_the_previous_checkpoint_is_divergent() {
+        let previous = RootCheckpoint {
+            block_number: 100.into(),
+            onchain_root: Default::default(),
+            local_root: Default::default(),
+            cached_leaves: 8,
+            contract_leaves: 10,
+            diverged: false,
+            alerted: false,
+            checked_at: 0,
+        };
+        assert!(!is_divergent(RootsStatus::Consistent, 10, Some(&previous)));
+        assert!(is_divergent(RootsStatus::Diverged, 10, Some(&previous)));
+        // still catching up with the new leaves.
+        assert!(!is_divergent(RootsStatus::Behind, 10, Some(&previous)));
+        assert!(!is_divergent(RootsStatus::Behind, 8, None));
+        // never got the leaves the contract had at the previous checkpoint.
+        assert!(is_divergent(RootsStatus::Behind, 9, Some(&previous)));
+    }
+}
@@ -169,6 +169,30 @@ export class WebbRelayer {
     });
   }
 
+  public async getLeavesOverWs(
+    chain: string,
+    contract: string,
+    start = 0
+  ): Promise<LeavesMessage['leaves']> {
+    const wsEndpoint = `ws://127.0.0.1:${this.opts.port}/ws`;
+    const ws = new WebSocket(wsEndpoint);
+    await new Promise((resolve) => ws.once('open', resolve));
+    return new Promise(async (resolve, reject) => {
+      ws.on('error', reject);
+      ws.on('message', (data) => {
+        const o = JSON.parse(data.toString());
+        const msg = parseRelayTxMessage(o);
+        ws.close();
+        if (msg.kind === 'leaves') {
+          resolve(msg.leaves);
+        } else {
+          reject(new Error(`Unexpected message: ${msg.kind}: ${data}`));
+        }
+      });
+      ws.send(JSON.stringify({ evm: { getLeaves: { chain, contract, start } } }));
+    });
+  }
+
   public async anchorWithdraw(
     chainName: string,
     anchorAddress: string,
@@ -268,6 +292,8 @@ async function txHashOrReject(
         const isError =
           msg.withdraw === 'invalidMerkleRoots' ||
           msg.withdraw === 'droppedFromMemPool' ||
+          msg.withdraw === 'nullifierAlreadySpent' ||
+          msg.withdraw === 'alreadyQueued' ||
           (msg.withdraw as { errored: any }).errored;
         const success = msg.withdraw as {
           finalized: { txHash: `0x${string}` };
@@ -343,6 +369,8 @@ async function substrateTxHashOrReject(
         const isError =
           msg.withdraw === 'invalidMerkleRoots' ||
           msg.withdraw === 'droppedFromMemPool' ||
+          msg.withdraw === 'nullifierAlreadySpent' ||
+          msg.withdraw === 'alreadyQueued' ||
           (msg.withdraw as { errored: any }).errored;
         const success = msg.withdraw as {
           finalized: { txHash: `0x${string}` };
@@ -487,6 +515,25 @@ export type ProposalSigningBackend =
 
 type PongMessage = {
   kind: 'pong';
+} & {
+  pong: {
+    version: string;
+    commit: string;
+    chains: {
+      name: string;
+      chainId: number;
+      synced: boolean;
+      degraded: string[];
+      stopped: string[];
+    }[];
+    substrateNodes: string[];
+  };
+};
+
+type LeavesMessage = {
+  kind: 'leaves';
+} & {
+  leaves: { start: number; leaves: string[]; lastQueriedBlock: string };
 };
 
 type NetworkMessage = {
@@ -512,6 +559,10 @@ type WithdrawMessage = {
     | 'valid'
     | 'invalidMerkleRoots'
     | 'droppedFromMemPool'
+    | { resubmitting: { attempt: number } }
+    | 'nullifierAlreadySpent'
+    | 'alreadyQueued'
+    | 'cancelled'
     | { errored: { code: number; reason: string } };
 };
 
@@ -525,6 +576,7 @@ type UnimplementedMessage = {
 
 type ParsedRelayerMessage =
   | PongMessage
+  | LeavesMessage
   | NetworkMessage
   | WithdrawMessage
   | ErrorMessage
@@ -533,7 +585,9 @@ type ParsedRelayerMessage =
 
 function parseRelayTxMessage(o: any): ParsedRelayerMessage {
   if (o.pong) {
-    return { kind: 'pong' };
+    return { kind: 'pong', pong: o.pong };
+  } else if (o.leaves) {
+    return { kind: 'leaves', leaves: o.leaves };
   } else if (o.network) {
     return {
       kind: 'network',